@@ -0,0 +1,53 @@
+//! The hunks of a `GIT binary patch`: deflated data in base85, one line of at most 52 bytes
+//! at a time.
+
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::objects;
+use crate::utils;
+
+/// A hunk of a binary patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryHunk {
+    /// The whole new contents.
+    Literal(Vec<u8>),
+    /// A delta against the old contents, as stored in packs.
+    Delta(Vec<u8>),
+}
+
+impl BinaryHunk {
+    /// Returns the contents made by the hunk from `old`, or [None] if a delta does not apply.
+    pub(super) fn apply(&self, old: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            BinaryHunk::Literal(data) => Some(data.clone()),
+            BinaryHunk::Delta(delta) => objects::apply_delta(old, delta),
+        }
+    }
+}
+
+/// Decodes a line of a binary hunk: its length as a letter, `A` to `Z` for 1 to 26 bytes and
+/// `a` to `z` for 27 to 52, then the bytes in base85. Returns false if the line is corrupt.
+pub(super) fn decode_line(line: &[u8], out: &mut Vec<u8>) -> bool {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let len = match line.first() {
+        Some(&ch @ b'A'..=b'Z') => (ch - b'A') as usize + 1,
+        Some(&ch @ b'a'..=b'z') => (ch - b'a') as usize + 27,
+        _ => return false,
+    };
+    match utils::decode_base85(&line[1..], len) {
+        Some(bytes) => {
+            out.extend(bytes);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Inflates the data of a hunk. Returns [None] unless it inflates to exactly `size` bytes.
+pub(super) fn inflate(deflated: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(size);
+    ZlibDecoder::new(deflated).read_to_end(&mut data).ok()?;
+    Some(data).filter(|data| data.len() == size)
+}
@@ -0,0 +1,292 @@
+//! Merging two files made from the same base line by line, with conflict markers where both
+//! change the same lines.
+//!
+//! Changes that overlap or touch conflict unless they are the same. Each conflict is narrowed
+//! down to the lines the two sides do not have in common, and conflicts separated by at most
+//! three lines are joined.
+
+use crate::diff::{diff_lines, DiffOptions};
+
+/// The size of the conflict markers.
+const MARKER_SIZE: usize = 7;
+
+/// The lines of a base changed into lines of another file.
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    base: usize,
+    base_len: usize,
+    side: usize,
+    side_len: usize,
+}
+
+/// Whether a region of the result is a conflict or comes from one side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Conflict,
+    Ours,
+    Theirs,
+    /// Both sides made the same change.
+    Both,
+}
+
+/// A region of the result, as ranges of lines of ours and theirs.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    mode: Mode,
+    ours: usize,
+    ours_len: usize,
+    theirs: usize,
+    theirs_len: usize,
+}
+
+/// Merges the changes from `base` to `ours` and to `theirs`. Returns the result and true if
+/// it has conflicts, marked with `ours_label` and `theirs_label`.
+pub(super) fn merge(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    ours_label: &str,
+    theirs_label: &str,
+) -> (Vec<u8>, bool) {
+    let base_lines = split_lines(base);
+    let our_lines = split_lines(ours);
+    let their_lines = split_lines(theirs);
+    let our_changes = changes(base, ours);
+    let their_changes = changes(base, theirs);
+
+    let mut regions: Vec<Region> = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    while x < our_changes.len() && y < their_changes.len() {
+        let (a, b) = (our_changes[x], their_changes[y]);
+        if a.base + a.base_len < b.base {
+            let theirs = b.side + a.base - b.base;
+            append(
+                &mut regions,
+                Mode::Ours,
+                a.side,
+                a.side_len,
+                theirs,
+                a.base_len,
+            );
+            x += 1;
+            continue;
+        }
+        if b.base + b.base_len < a.base {
+            let ours = a.side + b.base - a.base;
+            append(
+                &mut regions,
+                Mode::Theirs,
+                ours,
+                b.base_len,
+                b.side,
+                b.side_len,
+            );
+            y += 1;
+            continue;
+        }
+        let same = a.base == b.base
+            && a.base_len == b.base_len
+            && our_lines[a.side..a.side + a.side_len] == their_lines[b.side..b.side + b.side_len];
+        if !same {
+            // Both sides cover the base lines of both changes.
+            let begin = a.base.min(b.base);
+            let end = (a.base + a.base_len).max(b.base + b.base_len);
+            let ours = a.side + begin - a.base;
+            let theirs = b.side + begin - b.base;
+            let ours_len = a.side + a.side_len + end - (a.base + a.base_len) - ours;
+            let theirs_len = b.side + b.side_len + end - (b.base + b.base_len) - theirs;
+            append(
+                &mut regions,
+                Mode::Conflict,
+                ours,
+                ours_len,
+                theirs,
+                theirs_len,
+            );
+        }
+        let (a_end, b_end) = (a.base + a.base_len, b.base + b.base_len);
+        if a_end >= b_end {
+            y += 1;
+        }
+        if b_end >= a_end {
+            x += 1;
+        }
+    }
+    for a in &our_changes[x..] {
+        let theirs = a.base + their_lines.len() - base_lines.len();
+        append(
+            &mut regions,
+            Mode::Ours,
+            a.side,
+            a.side_len,
+            theirs,
+            a.base_len,
+        );
+    }
+    for b in &their_changes[y..] {
+        let ours = b.base + our_lines.len() - base_lines.len();
+        append(
+            &mut regions,
+            Mode::Theirs,
+            ours,
+            b.base_len,
+            b.side,
+            b.side_len,
+        );
+    }
+
+    let regions = simplify(refine(regions, &our_lines, &their_lines));
+    let conflicts = regions.iter().any(|region| region.mode == Mode::Conflict);
+
+    let mut result = Vec::new();
+    let mut current = 0;
+    for region in &regions {
+        match region.mode {
+            Mode::Both => continue,
+            Mode::Ours => {
+                result.extend(our_lines[current..region.ours + region.ours_len].concat());
+            }
+            Mode::Theirs => {
+                result.extend(our_lines[current..region.ours].concat());
+                result
+                    .extend(their_lines[region.theirs..region.theirs + region.theirs_len].concat());
+            }
+            Mode::Conflict => {
+                result.extend(our_lines[current..region.ours].concat());
+                marker(&mut result, b'<', ours_label);
+                copy_lines(
+                    &mut result,
+                    &our_lines[region.ours..region.ours + region.ours_len],
+                );
+                marker(&mut result, b'=', "");
+                copy_lines(
+                    &mut result,
+                    &their_lines[region.theirs..region.theirs + region.theirs_len],
+                );
+                marker(&mut result, b'>', theirs_label);
+            }
+        }
+        current = region.ours + region.ours_len;
+    }
+    result.extend(our_lines[current..].concat());
+    (result, conflicts)
+}
+
+/// Adds a region after the others, joining it with the last one if they overlap or touch.
+fn append(
+    regions: &mut Vec<Region>,
+    mode: Mode,
+    ours: usize,
+    ours_len: usize,
+    theirs: usize,
+    theirs_len: usize,
+) {
+    if let Some(last) = regions.last_mut() {
+        if ours <= last.ours + last.ours_len || theirs <= last.theirs + last.theirs_len {
+            if mode != last.mode {
+                last.mode = Mode::Conflict;
+            }
+            last.ours_len = ours + ours_len - last.ours;
+            last.theirs_len = theirs + theirs_len - last.theirs;
+            return;
+        }
+    }
+    regions.push(Region {
+        mode,
+        ours,
+        ours_len,
+        theirs,
+        theirs_len,
+    });
+}
+
+/// Narrows each conflict down to the lines that differ between the two sides, which may split
+/// it into several conflicts. Conflicts where one side is empty are left alone.
+fn refine(regions: Vec<Region>, ours: &[&[u8]], theirs: &[&[u8]]) -> Vec<Region> {
+    let mut refined = Vec::new();
+    for region in regions {
+        if region.mode != Mode::Conflict || region.ours_len == 0 || region.theirs_len == 0 {
+            refined.push(region);
+            continue;
+        }
+        let our_text = ours[region.ours..region.ours + region.ours_len].concat();
+        let their_text = theirs[region.theirs..region.theirs + region.theirs_len].concat();
+        let changes = changes(&our_text, &their_text);
+        if changes.is_empty() {
+            refined.push(Region {
+                mode: Mode::Both,
+                ..region
+            });
+            continue;
+        }
+        refined.extend(changes.iter().map(|change| Region {
+            mode: Mode::Conflict,
+            ours: region.ours + change.base,
+            ours_len: change.base_len,
+            theirs: region.theirs + change.side,
+            theirs_len: change.side_len,
+        }));
+    }
+    refined
+}
+
+/// Joins conflicts separated by at most three lines, which take up no more room inside one
+/// conflict than between two.
+fn simplify(regions: Vec<Region>) -> Vec<Region> {
+    let mut simplified: Vec<Region> = Vec::new();
+    for region in regions {
+        if let Some(last) = simplified.last_mut() {
+            if last.mode == Mode::Conflict
+                && region.mode == Mode::Conflict
+                && region.ours - (last.ours + last.ours_len) <= 3
+            {
+                last.ours_len = region.ours + region.ours_len - last.ours;
+                last.theirs_len = region.theirs + region.theirs_len - last.theirs;
+                continue;
+            }
+        }
+        simplified.push(region);
+    }
+    simplified
+}
+
+/// Returns the changes from `base` to `side`, without context.
+fn changes(base: &[u8], side: &[u8]) -> Vec<Change> {
+    let options = DiffOptions {
+        context: 0,
+        indent_heuristic: false,
+        ..Default::default()
+    };
+    // A side without lines starts after the line before it.
+    let start = |start: usize, len: usize| if len == 0 { start } else { start - 1 };
+    diff_lines(base, side, &options)
+        .into_iter()
+        .map(|hunk| Change {
+            base: start(hunk.old_start, hunk.old_lines),
+            base_len: hunk.old_lines,
+            side: start(hunk.new_start, hunk.new_lines),
+            side_len: hunk.new_lines,
+        })
+        .collect()
+}
+
+fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&ch| ch == b'\n').collect()
+}
+
+/// Appends the lines of one side of a conflict, ending the last one with a newline.
+fn copy_lines(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    out.extend(lines.concat());
+    if !lines.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+fn marker(out: &mut Vec<u8>, ch: u8, label: &str) {
+    out.extend(std::iter::repeat_n(ch, MARKER_SIZE));
+    if !label.is_empty() {
+        out.push(b' ');
+        out.extend(label.as_bytes());
+    }
+    out.push(b'\n');
+}
@@ -0,0 +1,986 @@
+//! Applying patches to the worktree and the index, like `git apply`.
+//!
+//! All patches are checked before any file is written, so a patch that does not apply leaves
+//! everything untouched. Progress and the reasons patches do not apply are returned in an
+//! [ApplyReport], for the caller to print on stderr like git.
+
+mod binary;
+mod merge;
+mod parse;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+pub use self::binary::BinaryHunk;
+pub use self::parse::{parse_patch, BinaryPatch, FilePatch};
+
+use crate::config;
+use crate::diff::{Hunk, HunkLine, LineKind};
+use crate::index::{GitIndex, IndexEntry};
+use crate::objects::{self, is_full_sha, ObjectType};
+use crate::utils;
+use crate::{
+    self as gitrs, is_binary, GitConverter, GitError, GitFileMode, GitOdb, GitRepo, GitResult,
+};
+
+/// The object name of a deleted file in the `index` line of a binary patch.
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// How patches are applied, like the options of `git apply`.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// Only check that the patches apply, like `--check`.
+    pub check: bool,
+    /// Apply to the index as well as the worktree, whose files must match the index, like
+    /// `--index`.
+    pub index: bool,
+    /// Apply to the index only, like `--cached`.
+    pub cached: bool,
+    /// Undo the patches, like `--reverse`.
+    pub reverse: bool,
+    /// Merge the changes into the files with the blobs the patches were made from, leaving
+    /// conflicts in the index, like `--3way`. Implies [ApplyOptions::index] unless
+    /// [ApplyOptions::cached] is set.
+    pub three_way: bool,
+    /// The number of unchanged lines around each change that must match, like `-C<n>`. All
+    /// of them must match by default.
+    pub min_context: Option<usize>,
+    /// Report each patch and where its hunks apply, like `--verbose`.
+    pub verbose: bool,
+}
+
+/// What a path of the patches stands for while they are checked.
+#[derive(Debug, Clone, Copy)]
+enum PathState {
+    /// The path is deleted or renamed by a later patch.
+    ToBeDeleted,
+    /// The path was deleted or renamed by an earlier patch.
+    WasDeleted,
+    /// The path has the result of an earlier patch, by its position.
+    Patched(usize),
+}
+
+/// The result of a patch that applies.
+#[derive(Debug)]
+struct Applied {
+    patch: FilePatch,
+    data: Vec<u8>,
+    mode: GitFileMode,
+    /// The blobs of the base, ours and theirs of a three-way merge with conflicts.
+    conflict: Option<[Option<String>; 3]>,
+}
+
+/// What [GitApply::apply] did with a series of patches.
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    /// Whether every patch applies. Nothing is written otherwise.
+    pub applied: bool,
+    /// The paths that three-way merges left with conflicts, sorted.
+    pub conflicts: Vec<String>,
+    /// The progress, warnings and reasons patches do not apply, in the order `git apply`
+    /// prints them.
+    pub messages: Vec<String>,
+}
+
+impl ApplyReport {
+    /// Returns true if every patch applied without conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.applied && self.conflicts.is_empty()
+    }
+}
+
+/// Applies patches to the worktree or the index of a repository.
+#[derive(Debug)]
+pub struct GitApply {
+    worktree: Option<PathBuf>,
+    prefix: String,
+    odb: GitOdb,
+    index: GitIndex,
+    converter: GitConverter,
+    trust_filemode: bool,
+    symlinks: bool,
+    options: ApplyOptions,
+    /// The messages of the patches being applied.
+    messages: Vec<String>,
+}
+
+impl GitApply {
+    /// Prepares to apply patches to `repo` as set by `options`. The paths of the patches are
+    /// taken to be relative to the directory the repository was discovered from.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ApplyError]: The patches are applied to the worktree of a bare repository
+    /// * [GitError::IndexError]: The index is corrupt
+    /// * Errors obtained when reading the object database, config or attributes
+    pub fn new(repo: &GitRepo, options: ApplyOptions) -> GitResult<Self> {
+        let mut options = options;
+        if options.three_way && !options.cached {
+            options.index = true;
+        }
+        let worktree = repo.worktree().map(Path::to_path_buf);
+        if worktree.is_none() && !options.cached {
+            return Err(GitError::ApplyError {
+                message: "fatal: this operation must be run in a work tree".to_string(),
+            });
+        }
+        let prefix = repo.prefix().to_string_lossy().into_owned();
+        Ok(Self {
+            worktree,
+            prefix,
+            odb: GitOdb::open(repo)?,
+            index: GitIndex::read(repo)?,
+            converter: GitConverter::new(repo)?,
+            trust_filemode: config::get_bool(repo, "core.filemode", true)?,
+            symlinks: config::get_bool(repo, "core.symlinks", true)?,
+            options,
+            messages: Vec::new(),
+        })
+    }
+
+    /// The object database the blobs of three-way merges are read from.
+    pub fn odb(&self) -> &GitOdb {
+        &self.odb
+    }
+
+    /// The index as updated so far.
+    pub fn index(&self) -> &GitIndex {
+        &self.index
+    }
+
+    /// Applies `patches` in order, so later patches can change the files of earlier ones.
+    /// Nothing is written if a patch does not apply, or if only checking. Conflicts of
+    /// three-way merges are left in the index and marked in the files.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ApplyError]: There are no patches, or a path is outside the repository
+    /// * Errors obtained when reading or writing files, objects or the index
+    pub fn apply(&mut self, patches: &[FilePatch]) -> GitResult<ApplyReport> {
+        self.messages.clear();
+        if patches.is_empty() {
+            return Err(GitError::ApplyError {
+                message: "error: No valid patches in input (allow with \"--allow-empty\")"
+                    .to_string(),
+            });
+        }
+        // Like git, reversed patches are also applied in reverse order.
+        let mut patches: Vec<FilePatch> = match self.options.reverse {
+            true => patches.iter().rev().map(FilePatch::reverse).collect(),
+            false => patches.to_vec(),
+        };
+        for patch in &mut patches {
+            for path in patch.old_path.iter_mut().chain(patch.new_path.iter_mut()) {
+                if !self.prefix.is_empty() && !patch.toplevel_relative {
+                    *path = format!("{}/{}", self.prefix, path);
+                }
+                check_path(path)?;
+            }
+        }
+
+        let mut paths = HashMap::new();
+        for patch in &patches {
+            if let (Some(old_path), true) = (&patch.old_path, patch.is_delete() || patch.is_rename)
+            {
+                paths.insert(old_path.clone(), PathState::ToBeDeleted);
+            }
+        }
+        let mut results: Vec<Applied> = Vec::new();
+        let mut failed = false;
+        for patch in patches {
+            if self.options.verbose {
+                self.report(format!("Checking patch {}...", patch_name(&patch)));
+            }
+            let applied = match self.check_patch(patch, &paths, &results)? {
+                Some(applied) => applied,
+                None => {
+                    failed = true;
+                    continue;
+                }
+            };
+            if let Some(new_path) = &applied.patch.new_path {
+                paths.insert(new_path.clone(), PathState::Patched(results.len()));
+            }
+            if let (Some(old_path), true) = (
+                &applied.patch.old_path,
+                applied.patch.is_delete() || applied.patch.is_rename,
+            ) {
+                paths.insert(old_path.clone(), PathState::WasDeleted);
+            }
+            results.push(applied);
+        }
+        if failed || self.options.check {
+            return Ok(ApplyReport {
+                applied: !failed,
+                conflicts: Vec::new(),
+                messages: std::mem::take(&mut self.messages),
+            });
+        }
+
+        // Old files go first, so that files can be renamed onto each other.
+        for applied in &results {
+            let patch = &applied.patch;
+            if let Some(old_path) = &patch.old_path {
+                if !patch.is_copy && (patch.is_delete() || patch.new_path.is_some()) {
+                    self.remove_file(old_path, patch.is_delete() || patch.is_rename)?;
+                }
+            }
+        }
+        let mut conflicts = Vec::new();
+        for applied in &results {
+            if let Some(new_path) = &applied.patch.new_path {
+                self.create_file(new_path, applied)?;
+                if applied.conflict.is_some() {
+                    conflicts.push(new_path.clone());
+                }
+            }
+            if self.options.verbose {
+                self.report(format!(
+                    "Applied patch {} cleanly.",
+                    patch_name(&applied.patch)
+                ));
+            }
+        }
+        if self.options.index || self.options.cached {
+            self.index.write()?;
+        }
+        conflicts.sort();
+        Ok(ApplyReport {
+            applied: true,
+            conflicts,
+            messages: std::mem::take(&mut self.messages),
+        })
+    }
+
+    /// Adds a message to the report of the patches being applied.
+    fn report(&mut self, message: String) {
+        self.messages.push(message);
+    }
+
+    /// Reports why a patch does not apply.
+    fn error(&mut self, message: &str) {
+        self.report(format!("error: {}", message));
+    }
+
+    /// Checks that `patch` applies and returns its result, or [None] after reporting why it
+    /// does not apply.
+    fn check_patch(
+        &mut self,
+        patch: FilePatch,
+        paths: &HashMap<String, PathState>,
+        results: &[Applied],
+    ) -> GitResult<Option<Applied>> {
+        let name = patch
+            .old_path
+            .clone()
+            .or_else(|| patch.new_path.clone())
+            .unwrap_or_default();
+        let update_index = self.options.index || self.options.cached;
+
+        // The contents and mode the patch applies to.
+        let mut preimage = Vec::new();
+        let mut old_mode = None;
+        if let Some(old_path) = &patch.old_path {
+            match paths.get(old_path) {
+                Some(PathState::WasDeleted) => {
+                    self.error(&format!("path {} has been renamed/deleted", old_path));
+                    return Ok(None);
+                }
+                Some(PathState::Patched(previous)) => {
+                    preimage = results[*previous].data.clone();
+                    old_mode = Some(results[*previous].mode);
+                }
+                _ if update_index => {
+                    let entry = match self.index.entry(old_path) {
+                        Some(entry) => entry.clone(),
+                        None => {
+                            self.error(&format!("{}: does not exist in index", old_path));
+                            return Ok(None);
+                        }
+                    };
+                    if !self.options.cached && !self.matches_index(&entry)? {
+                        self.error(&format!("{}: does not match index", old_path));
+                        return Ok(None);
+                    }
+                    preimage = self.read_blob(entry.sha())?;
+                    old_mode = Some(entry.mode());
+                }
+                _ => match self.read_worktree(old_path)? {
+                    Some((data, mode)) => {
+                        preimage = data;
+                        old_mode = Some(mode);
+                    }
+                    None => {
+                        self.error(&format!("{}: No such file or directory", old_path));
+                        return Ok(None);
+                    }
+                },
+            }
+        }
+        if let (Some(found), Some(expected)) = (old_mode, patch.old_mode) {
+            if found.file_type() != expected.file_type() {
+                self.error(&format!("{}: wrong type", name));
+                return Ok(None);
+            }
+            if found != expected {
+                self.report(format!(
+                    "warning: {} has type {}, expected {}",
+                    name, found, expected
+                ));
+            }
+        }
+        let old_mode = patch.old_mode.or(old_mode);
+
+        // A path deleted or renamed by another patch may be taken over.
+        let mut direct_to_three_way = false;
+        let mut new_mode = patch.new_mode;
+        if let Some(new_path) = &patch.new_path {
+            if patch.is_new() || patch.is_rename || patch.is_copy {
+                let ok_if_exists = matches!(
+                    paths.get(new_path),
+                    Some(PathState::ToBeDeleted | PathState::WasDeleted)
+                );
+                match self.check_to_create(new_path, ok_if_exists)? {
+                    Some(_) if self.options.three_way => direct_to_three_way = true,
+                    Some(problem) => {
+                        self.error(&format!("{}: {}", new_path, problem));
+                        return Ok(None);
+                    }
+                    None => {}
+                }
+                if new_mode.is_none() && patch.is_new() {
+                    new_mode = Some(GitFileMode::regular());
+                }
+            }
+            new_mode = new_mode.or(old_mode);
+        }
+        if let (Some(old_path), Some(new_path), Some(old), Some(new)) =
+            (&patch.old_path, &patch.new_path, old_mode, new_mode)
+        {
+            if old.file_type() != new.file_type() {
+                let message = match old_path == new_path {
+                    true => format!(
+                        "new mode ({}) of {} does not match old mode ({})",
+                        new, new_path, old
+                    ),
+                    false => format!(
+                        "new mode ({}) of {} does not match old mode ({}) of {}",
+                        new, new_path, old, old_path
+                    ),
+                };
+                self.error(&message);
+                return Ok(None);
+            }
+        }
+
+        let mut result = None;
+        if self.options.three_way {
+            result = self.try_three_way(&patch, &preimage, direct_to_three_way)?;
+            if result.is_none() && !direct_to_three_way {
+                self.report("Falling back to direct application...".to_string());
+            }
+        }
+        let (data, conflict) = match result {
+            Some(result) => result,
+            None if direct_to_three_way => {
+                self.error(&format!("{}: patch does not apply", name));
+                return Ok(None);
+            }
+            None => match self.apply_fragments(&patch, &preimage)? {
+                Some(data) => (data, None),
+                None => {
+                    self.error(&format!("{}: patch does not apply", name));
+                    return Ok(None);
+                }
+            },
+        };
+        if patch.is_delete() && !data.is_empty() {
+            self.error("removal patch leaves file contents");
+            self.error(&format!("{}: patch does not apply", name));
+            return Ok(None);
+        }
+        Ok(Some(Applied {
+            mode: new_mode.unwrap_or_else(GitFileMode::regular),
+            patch,
+            data,
+            conflict,
+        }))
+    }
+
+    /// Returns why the new file `path` can not be created, if it already exists.
+    fn check_to_create(&self, path: &str, ok_if_exists: bool) -> GitResult<Option<&str>> {
+        let check_index = self.options.index || self.options.cached;
+        if check_index && !ok_if_exists && self.index.entry(path).is_some() {
+            return Ok(Some("already exists in index"));
+        }
+        if self.options.cached {
+            return Ok(None);
+        }
+        match fs::symlink_metadata(self.worktree_path(path)) {
+            Ok(metadata) if !metadata.is_dir() && !ok_if_exists => {
+                Ok(Some("already exists in working directory"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Applies the hunks or the binary hunk of `patch` to `image`, or returns [None] after
+    /// reporting why they do not apply.
+    fn apply_fragments(&mut self, patch: &FilePatch, image: &[u8]) -> GitResult<Option<Vec<u8>>> {
+        let name = patch
+            .old_path
+            .as_deref()
+            .or(patch.new_path.as_deref())
+            .unwrap_or_default();
+        if let Some(binary) = &patch.binary {
+            return self.apply_binary(patch, binary, name, image);
+        }
+
+        let mut image = Image {
+            lines: image
+                .split_inclusive(|&ch| ch == b'\n')
+                .map(<[u8]>::to_vec)
+                .collect(),
+            patched: Vec::new(),
+        };
+        image.patched = vec![false; image.lines.len()];
+        for (i, hunk) in patch.hunks.iter().enumerate() {
+            if !self.apply_hunk(&mut image, hunk, i + 1) {
+                self.error(&format!("patch failed: {}:{}", name, hunk.old_start));
+                return Ok(None);
+            }
+        }
+        Ok(Some(image.lines.concat()))
+    }
+
+    /// Applies `hunk`, the `nth` of its patch, where its preimage matches `image`, first with
+    /// all its context and then, if allowed, with less. Returns false if it does not apply.
+    fn apply_hunk(&mut self, image: &mut Image, hunk: &Hunk, nth: usize) -> bool {
+        let mut preimage = Vec::new();
+        let mut postimage = Vec::new();
+        for line in &hunk.lines {
+            if line.kind != LineKind::Added {
+                preimage.push(line.text.as_slice());
+            }
+            if line.kind != LineKind::Removed {
+                postimage.push(line.text.as_slice());
+            }
+        }
+        let is_context = |line: &&HunkLine| line.kind == LineKind::Context;
+        let (old_leading, old_trailing) = (
+            hunk.lines.iter().take_while(is_context).count(),
+            hunk.lines.iter().rev().take_while(is_context).count(),
+        );
+        let (mut leading, mut trailing) = (old_leading, old_trailing);
+        let min_context = self.options.min_context.unwrap_or(usize::MAX);
+
+        // A hunk at the start of the file, or without context after its changes at the end,
+        // must be applied there.
+        let mut match_beginning = hunk.old_start <= 1;
+        let mut match_end = trailing == 0;
+        let mut pos = hunk.new_start as isize - 1;
+        if hunk.new_start == 0 {
+            pos = 0;
+        }
+        let found = loop {
+            if let Some(found) = image.find(&preimage, pos, match_beginning, match_end) {
+                break Some(found);
+            }
+            if leading <= min_context && trailing <= min_context {
+                break None;
+            }
+            if match_beginning || match_end {
+                match_beginning = false;
+                match_end = false;
+                continue;
+            }
+            // Drop a line of context from the side with more of it, or from both sides.
+            if leading >= trailing {
+                preimage.remove(0);
+                postimage.remove(0);
+                pos -= 1;
+                leading -= 1;
+            }
+            if trailing > leading {
+                preimage.pop();
+                postimage.pop();
+                trailing -= 1;
+            }
+        };
+
+        let found = match found {
+            Some(found) => found,
+            None => {
+                if self.options.verbose {
+                    let searched: Vec<u8> = hunk
+                        .lines
+                        .iter()
+                        .filter(|line| line.kind != LineKind::Added)
+                        .flat_map(|line| line.text.iter().copied())
+                        .collect();
+                    self.error(&format!(
+                        "while searching for:\n{}",
+                        String::from_utf8_lossy(&searched)
+                    ));
+                }
+                return false;
+            }
+        };
+        if self.options.verbose && found as isize != pos {
+            let mut offset = found as isize - pos;
+            if self.options.reverse {
+                offset = -offset;
+            }
+            self.report(format!(
+                "Hunk #{} succeeded at {} (offset {} line{}).",
+                nth,
+                found + 1,
+                offset,
+                if offset == 1 { "" } else { "s" }
+            ));
+        }
+        if leading != old_leading || trailing != old_trailing {
+            self.report(format!(
+                "Context reduced to ({}/{}) to apply fragment at {}",
+                leading,
+                trailing,
+                found + 1
+            ));
+        }
+        image.lines.splice(
+            found..found + preimage.len(),
+            postimage.iter().map(|line| line.to_vec()),
+        );
+        image.patched.splice(
+            found..found + preimage.len(),
+            postimage.iter().map(|_| true),
+        );
+        true
+    }
+
+    /// Applies a binary patch, which must name the full object names of the old and new
+    /// contents, to `image`.
+    fn apply_binary(
+        &mut self,
+        patch: &FilePatch,
+        binary: &BinaryPatch,
+        name: &str,
+        image: &[u8],
+    ) -> GitResult<Option<Vec<u8>>> {
+        let (old_id, new_id) = match (&patch.old_id, &patch.new_id) {
+            (Some(old_id), Some(new_id)) if is_full_sha(old_id) && is_full_sha(new_id) => {
+                (old_id, new_id)
+            }
+            _ => {
+                self.error(&format!(
+                    "cannot apply binary patch to '{}' without full index line",
+                    name
+                ));
+                return Ok(None);
+            }
+        };
+        if patch.old_path.is_some() {
+            let sha = objects::hash_object(ObjectType::Blob, image);
+            if sha != *old_id {
+                self.error(&format!(
+                    "the patch applies to '{}' ({}), which does not match the current contents.",
+                    name, sha
+                ));
+                return Ok(None);
+            }
+        } else if !image.is_empty() {
+            self.error(&format!(
+                "the patch applies to an empty '{}' but it is not empty",
+                name
+            ));
+            return Ok(None);
+        }
+
+        if new_id == NULL_SHA {
+            return Ok(Some(Vec::new()));
+        }
+        if self.odb.contains(new_id) {
+            return Ok(Some(self.read_blob(new_id)?));
+        }
+        let hunk = match (&binary.forward, &binary.reverse) {
+            (Some(hunk), _) => hunk,
+            (None, Some(_)) => {
+                self.error(&format!(
+                    "cannot reverse-apply a binary patch without the reverse hunk to '{}'",
+                    name
+                ));
+                return Ok(None);
+            }
+            (None, None) => {
+                self.error(&format!("missing binary patch data for '{}'", name));
+                return Ok(None);
+            }
+        };
+        let data = match hunk.apply(image) {
+            Some(data) => data,
+            None => {
+                self.error(&format!("binary patch does not apply to '{}'", name));
+                return Ok(None);
+            }
+        };
+        let sha = objects::hash_object(ObjectType::Blob, &data);
+        if sha != *new_id {
+            self.error(&format!(
+                "binary patch to '{}' creates incorrect result (expecting {}, got {})",
+                name, new_id, sha
+            ));
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+
+    /// Applies `patch` to the blob it was made from and merges the result with `current`, the
+    /// contents it is applied to. Returns [None] if there is no such blob or the patch does not
+    /// apply to it, and otherwise the merged contents and the blobs of the conflict stages if
+    /// they have conflicts.
+    #[allow(clippy::type_complexity)]
+    fn try_three_way(
+        &mut self,
+        patch: &FilePatch,
+        current: &[u8],
+        is_new_over_existing: bool,
+    ) -> GitResult<Option<(Vec<u8>, Option<[Option<String>; 3]>)>> {
+        let gitlink = |mode: Option<GitFileMode>| mode.is_some_and(|mode| mode.is_gitlink());
+        if patch.is_delete()
+            || gitlink(patch.old_mode)
+            || gitlink(patch.new_mode)
+            || (patch.is_new() && !is_new_over_existing)
+            || (patch.is_rename && patch.changed_lines() == 0 && patch.binary.is_none())
+        {
+            return Ok(None);
+        }
+
+        let (base_sha, base) = if patch.is_new() {
+            (self.odb.write(ObjectType::Blob, b"")?, Vec::new())
+        } else {
+            match self.resolve_blob(patch.old_id.as_deref())? {
+                Some(sha) => {
+                    let data = self.read_blob(&sha)?;
+                    (sha, data)
+                }
+                None => {
+                    self.error("repository lacks the necessary blob to perform 3-way merge.");
+                    return Ok(None);
+                }
+            }
+        };
+        if is_new_over_existing {
+            self.report("Performing three-way merge...".to_string());
+        }
+        let theirs = match self.apply_fragments(patch, &base)? {
+            Some(theirs) => theirs,
+            None => return Ok(None),
+        };
+        let theirs_sha = self.odb.write(ObjectType::Blob, &theirs)?;
+
+        // A new file merges with the file already at its path.
+        let ours = if is_new_over_existing {
+            let path = patch.new_path.as_deref().unwrap_or_default();
+            match self.index.entry(path) {
+                Some(entry) => self.read_blob(entry.sha())?,
+                None => self
+                    .read_worktree(path)?
+                    .map(|(data, _)| data)
+                    .unwrap_or_default(),
+            }
+        } else {
+            current.to_vec()
+        };
+        let ours_sha = self.odb.write(ObjectType::Blob, &ours)?;
+
+        let name = patch.new_path.as_deref().unwrap_or_default();
+        let (merged, conflicts) = if base_sha == ours_sha {
+            (theirs, false)
+        } else if base_sha == theirs_sha || ours_sha == theirs_sha {
+            (ours, false)
+        } else if is_binary(&base) || is_binary(&ours) || is_binary(&theirs) {
+            self.report(format!(
+                "warning: Cannot merge binary files: {} (ours vs. theirs)",
+                name
+            ));
+            (ours, true)
+        } else {
+            merge::merge(&base, &ours, &theirs, "ours", "theirs")
+        };
+        if conflicts {
+            self.report(format!("Applied patch to '{}' with conflicts.", name));
+            let base_sha = Some(base_sha).filter(|_| !patch.is_new());
+            let stages = [base_sha, Some(ours_sha), Some(theirs_sha)];
+            Ok(Some((merged, Some(stages))))
+        } else {
+            self.report(format!("Applied patch to '{}' cleanly.", name));
+            Ok(Some((merged, None)))
+        }
+    }
+
+    /// Returns the full name of the blob named by the possibly abbreviated `id`, if the
+    /// object database has exactly one.
+    fn resolve_blob(&self, id: Option<&str>) -> GitResult<Option<String>> {
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let found = match is_full_sha(id) {
+            true => Some(id.to_string()).filter(|id| self.odb.contains(id)),
+            false => match self.odb.find_prefix(id)?.as_slice() {
+                [sha] => Some(sha.clone()),
+                _ => None,
+            },
+        };
+        match found {
+            Some(sha) if self.odb.read_type(&sha)? == ObjectType::Blob => Ok(Some(sha)),
+            _ => Ok(None),
+        }
+    }
+
+    fn read_blob(&self, sha: &str) -> GitResult<Vec<u8>> {
+        Ok(self.odb.read(sha)?.1)
+    }
+
+    fn worktree_path(&self, path: &str) -> PathBuf {
+        self.worktree.as_deref().unwrap_or(Path::new("")).join(path)
+    }
+
+    /// Reads the worktree file at `path` as it would be stored, and its mode. Returns [None]
+    /// if it does not exist.
+    fn read_worktree(&mut self, path: &str) -> GitResult<Option<(Vec<u8>, GitFileMode)>> {
+        let full = self.worktree_path(path);
+        let metadata = match fs::symlink_metadata(&full) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+        if metadata.file_type().is_symlink() {
+            let target = gitrs::to_git_result(fs::read_link(&full), &full)?;
+            let target = target.to_string_lossy().into_owned().into_bytes();
+            return Ok(Some((target, GitFileMode::symlink())));
+        }
+        let data = utils::read_bytes(&full)?;
+        let mode = match self.index.entry(path) {
+            Some(entry) if !self.trust_filemode || entry.mode().is_symlink() => entry.mode(),
+            _ => GitFileMode::from_metadata(&metadata, self.trust_filemode),
+        };
+        if mode.is_symlink() {
+            return Ok(Some((data, mode)));
+        }
+        let data = self.converter.to_git(path, &data)?;
+        self.messages.extend(self.converter.take_warnings());
+        Ok(Some((data, mode)))
+    }
+
+    /// Returns true if the worktree file of `entry` has the contents and mode of the entry.
+    fn matches_index(&mut self, entry: &IndexEntry) -> GitResult<bool> {
+        let full = self.worktree_path(entry.path());
+        match fs::symlink_metadata(&full) {
+            Ok(metadata) if entry.is_stat_clean(&metadata) && !self.index.is_racy(entry) => {
+                return Ok(true)
+            }
+            Ok(_) => {}
+            Err(_) => return Ok(false),
+        }
+        Ok(match self.read_worktree(entry.path())? {
+            Some((data, mode)) => {
+                mode == entry.mode() && objects::hash_object(ObjectType::Blob, &data) == entry.sha()
+            }
+            None => false,
+        })
+    }
+
+    /// Removes `path` from the index and the worktree, and its leading directories if
+    /// `remove_dirs` is set and they become empty.
+    fn remove_file(&mut self, path: &str, remove_dirs: bool) -> GitResult<()> {
+        if self.options.index || self.options.cached {
+            self.index.remove(path);
+        }
+        if self.options.cached {
+            return Ok(());
+        }
+        let full = self.worktree_path(path);
+        if fs::symlink_metadata(&full).is_ok() {
+            gitrs::to_git_result(fs::remove_file(&full), &full)?;
+        }
+        if remove_dirs {
+            let worktree = self.worktree_path("");
+            let mut dir = full.parent();
+            while let Some(current) = dir {
+                if current == worktree || fs::remove_dir(current).is_err() {
+                    break;
+                }
+                dir = current.parent();
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the result of a patch to `path` in the worktree and the index.
+    fn create_file(&mut self, path: &str, applied: &Applied) -> GitResult<()> {
+        let mode = applied.mode;
+        if !self.options.cached {
+            let full = self.worktree_path(path);
+            utils::create_dir_all_if_new(full.parent().unwrap())?;
+            if fs::symlink_metadata(&full).is_ok() {
+                gitrs::to_git_result(fs::remove_file(&full), &full)?;
+            }
+            if mode.is_symlink() && self.symlinks {
+                let target = String::from_utf8_lossy(&applied.data).into_owned();
+                gitrs::to_git_result(symlink(target, &full), &full)?;
+            } else {
+                let data = match mode.is_symlink() {
+                    true => applied.data.clone(),
+                    false => self.converter.to_worktree(path, &applied.data)?,
+                };
+                self.messages.extend(self.converter.take_warnings());
+                let result = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(mode.permissions())
+                    .open(&full)
+                    .and_then(|mut file| file.write_all(&data));
+                gitrs::to_git_result(result, &full)?;
+            }
+        }
+        if !(self.options.index || self.options.cached) {
+            return Ok(());
+        }
+
+        if let Some(stages) = &applied.conflict {
+            self.index.remove(path);
+            for (stage, sha) in stages.iter().enumerate() {
+                if let Some(sha) = sha {
+                    let mut entry = IndexEntry::new(path, mode, sha);
+                    entry.set_stage(stage as u8 + 1);
+                    self.index.add(entry);
+                }
+            }
+            return Ok(());
+        }
+        let sha = self.odb.write(ObjectType::Blob, &applied.data)?;
+        let entry = if self.options.cached {
+            IndexEntry::new(path, mode, &sha)
+        } else {
+            let full = self.worktree_path(path);
+            let metadata = gitrs::to_git_result(fs::symlink_metadata(&full), &full)?;
+            IndexEntry::from_metadata(path, mode, &sha, &metadata)
+        };
+        self.index.add(entry);
+        Ok(())
+    }
+}
+
+/// The lines of a file being patched, with the lines added by hunks marked so that later
+/// hunks do not apply to them.
+struct Image {
+    lines: Vec<Vec<u8>>,
+    patched: Vec<bool>,
+}
+
+impl Image {
+    /// Returns the line where `preimage` matches, looking first at `pos`, then alternately
+    /// after and before it, further and further away.
+    fn find(
+        &self,
+        preimage: &[&[u8]],
+        pos: isize,
+        match_beginning: bool,
+        match_end: bool,
+    ) -> Option<usize> {
+        let len = self.lines.len() as isize;
+        let mut line = if match_beginning {
+            0
+        } else if match_end {
+            len - preimage.len() as isize
+        } else {
+            pos
+        };
+        if line < 0 || line > len {
+            line = len;
+        }
+
+        let (mut backwards, mut forwards, mut current) = (line, line, line);
+        let mut i = 0;
+        loop {
+            if self.matches(preimage, current as usize, match_beginning, match_end) {
+                return Some(current as usize);
+            }
+            loop {
+                if backwards == 0 && forwards == len {
+                    return None;
+                }
+                if i % 2 == 1 {
+                    if backwards == 0 {
+                        i += 1;
+                        continue;
+                    }
+                    backwards -= 1;
+                    current = backwards;
+                } else {
+                    if forwards == len {
+                        i += 1;
+                        continue;
+                    }
+                    forwards += 1;
+                    current = forwards;
+                }
+                break;
+            }
+            i += 1;
+        }
+    }
+
+    fn matches(
+        &self,
+        preimage: &[&[u8]],
+        at: usize,
+        match_beginning: bool,
+        match_end: bool,
+    ) -> bool {
+        let end = at + preimage.len();
+        if end > self.lines.len() || (match_end && end != self.lines.len()) {
+            return false;
+        }
+        if match_beginning && at != 0 {
+            return false;
+        }
+        (at..end).all(|i| !self.patched[i] && self.lines[i] == preimage[i - at])
+    }
+}
+
+/// Returns the path of `patch`, or both paths if it renames or copies its file.
+fn patch_name(patch: &FilePatch) -> String {
+    match (&patch.old_path, &patch.new_path) {
+        (Some(old_path), Some(new_path)) if old_path != new_path => format!(
+            "{} => {}",
+            utils::quote_c_style(old_path),
+            utils::quote_c_style(new_path)
+        ),
+        (old_path, new_path) => {
+            utils::quote_c_style(new_path.as_deref().or(old_path.as_deref()).unwrap_or(""))
+        }
+    }
+}
+
+/// Refuses paths leading outside the worktree or into the repository.
+fn check_path(path: &str) -> GitResult<()> {
+    let invalid = path.starts_with('/')
+        || path.split('/').any(|component| {
+            component.is_empty()
+                || component == "."
+                || component == ".."
+                || component.eq_ignore_ascii_case(".git")
+        });
+    if invalid {
+        return Err(GitError::ApplyError {
+            message: format!("error: invalid path '{}'", path),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,518 @@
+//! Parsing unified diffs, with the extended headers of `git diff`, into the changes of each
+//! file.
+
+use super::binary::{self, BinaryHunk};
+use crate::diff::{Hunk, HunkLine, LineKind};
+use crate::utils;
+use crate::{GitError, GitFileMode, GitResult};
+
+/// The forward and reverse hunks of a `GIT binary patch`. Both are missing when the patch
+/// only says that the binary files differ.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BinaryPatch {
+    pub forward: Option<BinaryHunk>,
+    pub reverse: Option<BinaryHunk>,
+}
+
+/// The changes of one file in a patch.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FilePatch {
+    /// The path before the change, or [None] if the file is created.
+    pub old_path: Option<String>,
+    /// The path after the change, or [None] if the file is deleted.
+    pub new_path: Option<String>,
+    pub old_mode: Option<GitFileMode>,
+    pub new_mode: Option<GitFileMode>,
+    pub is_rename: bool,
+    pub is_copy: bool,
+    /// The object names of the old and new contents from the `index` line, possibly
+    /// abbreviated.
+    pub old_id: Option<String>,
+    pub new_id: Option<String>,
+    pub hunks: Vec<Hunk>,
+    /// The binary changes, if the patch is for a binary file.
+    pub binary: Option<BinaryPatch>,
+    /// True for `diff --git` patches, whose paths are relative to the top of the worktree
+    /// rather than to the current directory.
+    pub toplevel_relative: bool,
+}
+
+impl FilePatch {
+    /// Returns true if the patch creates its file.
+    pub fn is_new(&self) -> bool {
+        self.old_path.is_none()
+    }
+
+    /// Returns true if the patch deletes its file.
+    pub fn is_delete(&self) -> bool {
+        self.new_path.is_none()
+    }
+
+    /// Returns the patch undoing `self`.
+    pub fn reverse(&self) -> FilePatch {
+        let hunks = self
+            .hunks
+            .iter()
+            .map(|hunk| Hunk {
+                old_start: hunk.new_start,
+                old_lines: hunk.new_lines,
+                new_start: hunk.old_start,
+                new_lines: hunk.old_lines,
+                function: hunk.function.clone(),
+                lines: hunk
+                    .lines
+                    .iter()
+                    .map(|line| HunkLine {
+                        kind: match line.kind {
+                            LineKind::Context => LineKind::Context,
+                            LineKind::Removed => LineKind::Added,
+                            LineKind::Added => LineKind::Removed,
+                        },
+                        text: line.text.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        FilePatch {
+            old_path: self.new_path.clone(),
+            new_path: self.old_path.clone(),
+            old_mode: self.new_mode,
+            new_mode: self.old_mode,
+            is_rename: self.is_rename,
+            is_copy: self.is_copy,
+            old_id: self.new_id.clone(),
+            new_id: self.old_id.clone(),
+            hunks,
+            binary: self.binary.as_ref().map(|binary| BinaryPatch {
+                forward: binary.reverse.clone(),
+                reverse: binary.forward.clone(),
+            }),
+            toplevel_relative: self.toplevel_relative,
+        }
+    }
+
+    /// Returns the number of added and removed lines.
+    pub(super) fn changed_lines(&self) -> usize {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|line| line.kind != LineKind::Context)
+            .count()
+    }
+}
+
+/// Parses the patches in `data`, skipping the text around them like the message of a mail.
+/// `strip` leading components are removed from the paths in the headers, like `-p<n>`.
+///
+/// # Errors
+///
+/// * [GitError::ApplyError]: A patch is corrupt, or its header has no paths
+pub fn parse_patch(data: &[u8], strip: usize) -> GitResult<Vec<FilePatch>> {
+    let mut parser = Parser {
+        lines: data.split_inclusive(|&ch| ch == b'\n').collect(),
+        pos: 0,
+        strip,
+    };
+    let mut patches = Vec::new();
+    while parser.pos < parser.lines.len() {
+        let line = parser.lines[parser.pos];
+        if line.starts_with(b"diff --git ") {
+            patches.push(parser.git_patch()?);
+        } else if line.starts_with(b"--- ")
+            && parser.line_at(1).starts_with(b"+++ ")
+            && parser.line_at(2).starts_with(b"@@ -")
+        {
+            patches.push(parser.traditional_patch()?);
+        } else {
+            parser.pos += 1;
+        }
+    }
+    Ok(patches)
+}
+
+struct Parser<'a> {
+    lines: Vec<&'a [u8]>,
+    pos: usize,
+    strip: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the line `offset` lines after the current one, or an empty line at the end.
+    fn line_at(&self, offset: usize) -> &'a [u8] {
+        self.lines.get(self.pos + offset).copied().unwrap_or(b"")
+    }
+
+    fn corrupt(&self) -> GitError {
+        GitError::ApplyError {
+            message: format!("error: corrupt patch at line {}", self.pos + 1),
+        }
+    }
+
+    fn lacks_filename(&self) -> GitError {
+        let message = if self.strip > 0 {
+            format!(
+                "error: git diff header lacks filename information when removing {} leading \
+                 pathname component{} (line {})",
+                self.strip,
+                if self.strip == 1 { "" } else { "s" },
+                self.pos + 1
+            )
+        } else {
+            format!(
+                "error: git diff header lacks filename information (line {})",
+                self.pos + 1
+            )
+        };
+        GitError::ApplyError { message }
+    }
+
+    /// Parses a patch starting with a `diff --git` line and its extended headers.
+    fn git_patch(&mut self) -> GitResult<FilePatch> {
+        let first = text(self.lines[self.pos]);
+        let default_name = header_name(&first["diff --git ".len()..], self.strip);
+        self.pos += 1;
+
+        let mut patch = FilePatch {
+            toplevel_relative: true,
+            ..Default::default()
+        };
+        let (mut old_path, mut new_path) = (None, None);
+        let (mut is_new, mut is_delete) = (false, false);
+        while self.pos < self.lines.len() {
+            let line = text(self.lines[self.pos]);
+            let line = line.trim_end_matches('\n');
+            let value = |prefix: &str| line.strip_prefix(prefix).map(str::to_string);
+            if let Some(name) = value("--- ") {
+                old_path = Some(self.name(&name)?);
+            } else if let Some(name) = value("+++ ") {
+                new_path = Some(self.name(&name)?);
+            } else if let Some(mode) = value("old mode ") {
+                patch.old_mode = Some(self.mode(&mode)?);
+            } else if let Some(mode) = value("new mode ") {
+                patch.new_mode = Some(self.mode(&mode)?);
+            } else if let Some(mode) = value("deleted file mode ") {
+                patch.old_mode = Some(self.mode(&mode)?);
+                is_delete = true;
+            } else if let Some(mode) = value("new file mode ") {
+                patch.new_mode = Some(self.mode(&mode)?);
+                is_new = true;
+            } else if let Some(name) = value("copy from ").or_else(|| value("rename from ")) {
+                patch.is_copy = line.starts_with("copy");
+                patch.is_rename = !patch.is_copy;
+                old_path = Some(self.rename_name(&name)?);
+            } else if let Some(name) = value("copy to ").or_else(|| value("rename to ")) {
+                new_path = Some(self.rename_name(&name)?);
+            } else if let Some(name) = value("rename old ") {
+                patch.is_rename = true;
+                old_path = Some(self.rename_name(&name)?);
+            } else if let Some(name) = value("rename new ") {
+                new_path = Some(self.rename_name(&name)?);
+            } else if let Some(index) = value("index ") {
+                let (ids, mode) = match index.split_once(' ') {
+                    Some((ids, mode)) => (ids.to_string(), Some(mode.to_string())),
+                    None => (index, None),
+                };
+                let (old_id, new_id) = ids.split_once("..").ok_or_else(|| self.corrupt())?;
+                patch.old_id = Some(old_id.to_string());
+                patch.new_id = Some(new_id.to_string());
+                if let Some(mode) = mode {
+                    patch.old_mode = Some(self.mode(&mode)?);
+                }
+            } else if !line.starts_with("similarity index ")
+                && !line.starts_with("dissimilarity index ")
+            {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        // The paths of `---` and `+++` lines are /dev/null for created and deleted files.
+        let mut old_path = old_path.unwrap_or_else(|| default_name.clone());
+        let mut new_path = new_path.unwrap_or_else(|| default_name.clone());
+        if is_new {
+            old_path = None;
+        }
+        if is_delete {
+            new_path = None;
+        }
+        if (old_path.is_none() && !is_new) || (new_path.is_none() && !is_delete) {
+            return Err(self.lacks_filename());
+        }
+        patch.old_path = old_path;
+        patch.new_path = new_path;
+
+        if self.line_at(0).starts_with(b"GIT binary patch") {
+            self.pos += 1;
+            patch.binary = Some(self.binary_patch()?);
+        } else if self.line_at(0).starts_with(b"Binary files ") {
+            self.pos += 1;
+            patch.binary = Some(BinaryPatch::default());
+        } else {
+            patch.hunks = self.hunks()?;
+        }
+        Ok(patch)
+    }
+
+    /// Parses a patch starting with `---` and `+++` lines, as made by `diff -u`.
+    fn traditional_patch(&mut self) -> GitResult<FilePatch> {
+        let old = traditional_name(&text(self.lines[self.pos])[4..]);
+        let new = traditional_name(&text(self.lines[self.pos + 1])[4..]);
+        let mut old_path = self.name(&old)?;
+        let mut new_path = self.name(&new)?;
+        if old_path.is_none() && new_path.is_none() {
+            return Err(self.lacks_filename());
+        }
+        // Traditional patches never rename: like git, take the shorter name if the other one
+        // extends it, as in `--- a` and `+++ a.new`, and the new name otherwise.
+        if let (Some(old), Some(new)) = (&old_path, &new_path) {
+            let name = match new.starts_with(old.as_str()) {
+                true => old.clone(),
+                false => new.clone(),
+            };
+            old_path = Some(name.clone());
+            new_path = Some(name);
+        }
+        self.pos += 2;
+        Ok(FilePatch {
+            old_path,
+            new_path,
+            hunks: self.hunks()?,
+            ..Default::default()
+        })
+    }
+
+    /// Returns the path of a `---` or `+++` line, or [None] for /dev/null.
+    fn name(&self, name: &str) -> GitResult<Option<String>> {
+        let name = traditional_name(name);
+        if name == "/dev/null" {
+            return Ok(None);
+        }
+        match strip_components(&unquote(&name), self.strip) {
+            Some(name) => Ok(Some(name)),
+            None => Err(self.lacks_filename()),
+        }
+    }
+
+    /// Returns the path of a rename or copy header, which has no `a/` or `b/` to strip.
+    fn rename_name(&self, name: &str) -> GitResult<Option<String>> {
+        match strip_components(&unquote(name), self.strip.saturating_sub(1)) {
+            Some(name) => Ok(Some(name)),
+            None => Err(self.lacks_filename()),
+        }
+    }
+
+    fn mode(&self, mode: &str) -> GitResult<GitFileMode> {
+        u32::from_str_radix(mode.trim(), 8)
+            .ok()
+            .and_then(|mode| GitFileMode::from_octal(mode).ok())
+            .ok_or_else(|| self.corrupt())
+    }
+
+    /// Parses the hunks at the current line.
+    fn hunks(&mut self) -> GitResult<Vec<Hunk>> {
+        let mut hunks = Vec::new();
+        while self.line_at(0).starts_with(b"@@ -") {
+            hunks.push(self.hunk()?);
+        }
+        Ok(hunks)
+    }
+
+    fn hunk(&mut self) -> GitResult<Hunk> {
+        let header = text(self.lines[self.pos]);
+        let (ranges, function) = header[3..]
+            .split_once(" @@")
+            .ok_or_else(|| self.corrupt())?;
+        let (old, new) = ranges
+            .strip_prefix('-')
+            .and_then(|ranges| ranges.split_once(" +"))
+            .ok_or_else(|| self.corrupt())?;
+        let range = |range: &str| -> Option<(usize, usize)> {
+            match range.split_once(',') {
+                Some((start, lines)) => Some((start.parse().ok()?, lines.parse().ok()?)),
+                None => Some((range.parse().ok()?, 1)),
+            }
+        };
+        let ((old_start, old_lines), (new_start, new_lines)) =
+            range(old).zip(range(new)).ok_or_else(|| self.corrupt())?;
+        let mut hunk = Hunk {
+            old_start,
+            old_lines,
+            new_start,
+            new_lines,
+            function: function
+                .trim_start()
+                .trim_end_matches('\n')
+                .as_bytes()
+                .to_vec(),
+            lines: Vec::new(),
+        };
+        self.pos += 1;
+
+        let (mut old_left, mut new_left) = (old_lines, new_lines);
+        while old_left > 0 || new_left > 0 {
+            let line = self.line_at(0);
+            if !line.ends_with(b"\n") {
+                return Err(self.corrupt());
+            }
+            let kind = match line[0] {
+                // Some versions of diff drop the space of empty unchanged lines.
+                b' ' | b'\n' => LineKind::Context,
+                b'-' => LineKind::Removed,
+                b'+' => LineKind::Added,
+                b'\\' if line.len() >= 12 && line.starts_with(b"\\ ") => {
+                    self.no_newline(&mut hunk);
+                    self.pos += 1;
+                    continue;
+                }
+                _ => return Err(self.corrupt()),
+            };
+            let fits = match kind {
+                LineKind::Context => old_left > 0 && new_left > 0,
+                LineKind::Removed => old_left > 0,
+                LineKind::Added => new_left > 0,
+            };
+            if !fits {
+                return Err(self.corrupt());
+            }
+            if kind != LineKind::Added {
+                old_left -= 1;
+            }
+            if kind != LineKind::Removed {
+                new_left -= 1;
+            }
+            let text = if line[0] == b'\n' { line } else { &line[1..] };
+            hunk.lines.push(HunkLine {
+                kind,
+                text: text.to_vec(),
+            });
+            self.pos += 1;
+        }
+        if hunk.lines.iter().all(|line| line.kind == LineKind::Context) {
+            return Err(self.corrupt());
+        }
+        let line = self.line_at(0);
+        if line.len() >= 12 && line.starts_with(b"\\ ") {
+            self.no_newline(&mut hunk);
+            self.pos += 1;
+        }
+        Ok(hunk)
+    }
+
+    /// Drops the newline of the last line of `hunk`, for a `\ No newline at end of file` line.
+    fn no_newline(&self, hunk: &mut Hunk) {
+        if let Some(last) = hunk.lines.last_mut() {
+            if last.text.ends_with(b"\n") {
+                last.text.pop();
+            }
+        }
+    }
+
+    /// Parses the forward and the optional reverse hunk of a `GIT binary patch`.
+    fn binary_patch(&mut self) -> GitResult<BinaryPatch> {
+        let forward = self.binary_hunk()?.ok_or_else(|| self.corrupt())?;
+        let reverse = self.binary_hunk()?;
+        Ok(BinaryPatch {
+            forward: Some(forward),
+            reverse,
+        })
+    }
+
+    /// Parses a `literal <size>` or `delta <size>` hunk and the empty line ending it.
+    fn binary_hunk(&mut self) -> GitResult<Option<BinaryHunk>> {
+        let header = text(self.line_at(0));
+        let (is_delta, size) = if let Some(size) = header.strip_prefix("literal ") {
+            (false, size)
+        } else if let Some(size) = header.strip_prefix("delta ") {
+            (true, size)
+        } else {
+            return Ok(None);
+        };
+        let size: usize = size.trim().parse().map_err(|_| self.corrupt())?;
+        let start = self.pos;
+        self.pos += 1;
+
+        let mut deflated = Vec::new();
+        loop {
+            let line = self.line_at(0);
+            if line == b"\n" || line.is_empty() {
+                self.pos += 1;
+                break;
+            }
+            if !binary::decode_line(line, &mut deflated) {
+                return Err(self.corrupt_binary(line));
+            }
+            self.pos += 1;
+        }
+        match binary::inflate(&deflated, size) {
+            Some(data) if is_delta => Ok(Some(BinaryHunk::Delta(data))),
+            Some(data) => Ok(Some(BinaryHunk::Literal(data))),
+            None => {
+                self.pos = start;
+                Err(self.corrupt_binary(self.lines[start]))
+            }
+        }
+    }
+
+    fn corrupt_binary(&self, line: &[u8]) -> GitError {
+        GitError::ApplyError {
+            message: format!(
+                "error: corrupt binary patch at line {}: {}",
+                self.pos + 1,
+                text(line).trim_end_matches('\n')
+            ),
+        }
+    }
+}
+
+fn text(line: &[u8]) -> String {
+    String::from_utf8_lossy(line).into_owned()
+}
+
+/// Returns `name` without the newline and the timestamp after a tab that `diff -u` adds.
+fn traditional_name(name: &str) -> String {
+    let name = name.trim_end_matches('\n');
+    let name = name.split('\t').next().unwrap_or(name);
+    name.trim_end().to_string()
+}
+
+/// Unquotes `name` if it is a C-style quoted string.
+fn unquote(name: &str) -> String {
+    let name = name.trim_end_matches('\n');
+    match utils::unquote_c_style(name) {
+        Some((unquoted, _)) => unquoted,
+        None => name.to_string(),
+    }
+}
+
+/// Removes `strip` leading components from `name`. Returns [None] if it has too few.
+fn strip_components(name: &str, strip: usize) -> Option<String> {
+    let mut rest = name;
+    for _ in 0..strip {
+        let slash = rest.find('/')?;
+        rest = rest[slash + 1..].trim_start_matches('/');
+    }
+    if rest.is_empty() {
+        None
+    } else {
+        Some(rest.to_string())
+    }
+}
+
+/// Returns the path named by the `a/<path> b/<path>` of a `diff --git` line if both names are
+/// the same. Renames and copies name their paths in later headers.
+fn header_name(names: &str, strip: usize) -> Option<String> {
+    let names = names.trim_end_matches('\n');
+    let split = |first: &str, second: &str| {
+        let second = unquote(second.trim_start());
+        let first = strip_components(first, strip)?;
+        let second = strip_components(&second, strip)?;
+        Some(first).filter(|first| *first == second)
+    };
+    if names.starts_with('"') {
+        let (first, len) = utils::unquote_c_style(names)?;
+        return split(&first, &names[len..]);
+    }
+    names
+        .match_indices(' ')
+        .find_map(|(i, _)| split(&names[..i], &names[i + 1..]))
+}
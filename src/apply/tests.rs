@@ -0,0 +1,237 @@
+use super::*;
+use crate::test_utils;
+use std::error::Error;
+
+/// Writes `contents` to `path` in the worktree and the index, and returns its blob name.
+fn add(repo: &GitRepo, path: &str, contents: &str) -> Result<String, Box<dyn Error>> {
+    let file = repo.worktree().unwrap().join(path);
+    fs::write(&file, contents)?;
+    let odb = GitOdb::open(repo)?;
+    let sha = odb.write(ObjectType::Blob, contents.as_bytes())?;
+    let mut index = GitIndex::read(repo)?;
+    let metadata = fs::metadata(&file)?;
+    index.add(IndexEntry::from_metadata(
+        path,
+        GitFileMode::regular(),
+        &sha,
+        &metadata,
+    ));
+    index.write()?;
+    Ok(sha)
+}
+
+fn read(repo: &GitRepo, path: &str) -> Result<String, Box<dyn Error>> {
+    Ok(fs::read_to_string(repo.worktree().unwrap().join(path))?)
+}
+
+const PATCH: &str = "\
+diff --git a/a.txt b/a.txt
+index 1234567..89abcde 100644
+--- a/a.txt
++++ b/a.txt
+@@ -2,3 +2,3 @@
+ 2
+-3
++three
+ 4
+";
+
+#[test]
+fn test_parse_patch() -> Result<(), Box<dyn Error>> {
+    let patches = parse_patch(PATCH.as_bytes(), 1)?;
+    assert_eq!(patches.len(), 1);
+    let patch = &patches[0];
+    assert_eq!(patch.old_path.as_deref(), Some("a.txt"));
+    assert_eq!(patch.new_path.as_deref(), Some("a.txt"));
+    assert_eq!(patch.old_id.as_deref(), Some("1234567"));
+    assert_eq!(patch.old_mode, Some(GitFileMode::regular()));
+    assert_eq!(patch.hunks.len(), 1);
+    assert_eq!(patch.hunks[0].lines.len(), 4);
+    assert!(patch.toplevel_relative);
+
+    let rename = "\
+diff --git a/dir/old b/dir/new
+similarity index 100%
+rename from dir/old
+rename to dir/new
+";
+    let patch = &parse_patch(rename.as_bytes(), 1)?[0];
+    assert!(patch.is_rename);
+    assert_eq!(patch.old_path.as_deref(), Some("dir/old"));
+    assert_eq!(patch.new_path.as_deref(), Some("dir/new"));
+
+    // A traditional patch keeps the shorter name, and is relative to the current directory.
+    let traditional = "--- a.txt\t2020-01-01\n+++ a.txt.new\t2020-01-01\n@@ -1 +1 @@\n-1\n+one\n";
+    let patch = &parse_patch(traditional.as_bytes(), 0)?[0];
+    assert_eq!(patch.old_path.as_deref(), Some("a.txt"));
+    assert_eq!(patch.new_path.as_deref(), Some("a.txt"));
+    assert!(!patch.toplevel_relative);
+
+    let corrupt = "diff --git a/a b/a\n--- a/a\n+++ b/a\n@@ -1,2 +1,2 @@\n-1\n";
+    match parse_patch(corrupt.as_bytes(), 1) {
+        Err(GitError::ApplyError { message }) => {
+            assert_eq!(message, "error: corrupt patch at line 6")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let bad_mode = "diff --git a/n b/n\nold mode 100644\nnew mode 1100755\n";
+    match parse_patch(bad_mode.as_bytes(), 1) {
+        Err(GitError::ApplyError { message }) => {
+            assert_eq!(message, "error: corrupt patch at line 3")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_binary_literal() -> Result<(), Box<dyn Error>> {
+    // Made by `git diff --binary` for a new file holding "bin\0ary\n".
+    let patch = "\
+diff --git a/bin b/bin
+new file mode 100644
+index 0000000000000000000000000000000000000000..7989678ab4a6aa63e80faf3ec63f094f48d780f7
+GIT binary patch
+literal 8
+PcmYew%wtF_s^kIy3}FJ0
+
+literal 0
+HcmV?d00001
+
+";
+    let patch = &parse_patch(patch.as_bytes(), 1)?[0];
+    assert!(patch.is_new());
+    let binary = patch.binary.as_ref().unwrap();
+    assert_eq!(
+        binary.forward,
+        Some(BinaryHunk::Literal(b"bin\0ary\n".to_vec()))
+    );
+    assert_eq!(binary.reverse, Some(BinaryHunk::Literal(Vec::new())));
+    Ok(())
+}
+
+#[test]
+fn test_merge() {
+    let base = b"1\n2\n3\n4\n5\n6\n7\n8\n";
+    let ours = b"one\n2\n3\n4\n5\n6\n7\n8\n";
+    let theirs = b"1\n2\n3\n4\n5\n6\n7\neight\n";
+    let (result, conflicts) = merge::merge(base, ours, theirs, "ours", "theirs");
+    assert!(!conflicts);
+    assert_eq!(result, b"one\n2\n3\n4\n5\n6\n7\neight\n");
+
+    let base = b"1\n2\n3\n4\n";
+    let ours = b"1\nTWO\ndrei\n4\n";
+    let theirs = b"1\n2\nthree\n4\n";
+    let (result, conflicts) = merge::merge(base, ours, theirs, "ours", "theirs");
+    assert!(conflicts);
+    assert_eq!(
+        String::from_utf8(result).unwrap(),
+        "1\n<<<<<<< ours\nTWO\ndrei\n=======\n2\nthree\n>>>>>>> theirs\n4\n"
+    );
+}
+
+#[test]
+fn test_apply() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_apply")?;
+    add(&repo, "a.txt", "0\n1\n2\n3\n4\n")?;
+    add(&repo, "b.txt", "b\n")?;
+
+    // The hunk applies one line later than it says.
+    let mut apply = GitApply::new(&repo, ApplyOptions::default())?;
+    assert!(apply.apply(&parse_patch(PATCH.as_bytes(), 1)?)?.is_clean());
+    assert_eq!(read(&repo, "a.txt")?, "0\n1\n2\nthree\n4\n");
+
+    // Nothing is written unless every patch applies.
+    let patch = format!(
+        "{}{}",
+        "diff --git a/b.txt b/b.txt\n--- a/b.txt\n+++ b/b.txt\n@@ -1 +1 @@\n-b\n+B\n", PATCH
+    );
+    let options = ApplyOptions {
+        verbose: true,
+        ..Default::default()
+    };
+    let mut apply = GitApply::new(&repo, options)?;
+    let report = apply.apply(&parse_patch(patch.as_bytes(), 1)?)?;
+    assert!(!report.applied);
+    assert_eq!(
+        report.messages,
+        [
+            "Checking patch b.txt...",
+            "Checking patch a.txt...",
+            "error: while searching for:\n2\n3\n4\n",
+            "error: patch failed: a.txt:2",
+            "error: a.txt: patch does not apply",
+        ]
+    );
+    assert_eq!(read(&repo, "b.txt")?, "b\n");
+
+    let options = ApplyOptions {
+        reverse: true,
+        ..Default::default()
+    };
+    let mut apply = GitApply::new(&repo, options)?;
+    assert!(apply.apply(&parse_patch(PATCH.as_bytes(), 1)?)?.is_clean());
+    assert_eq!(read(&repo, "a.txt")?, "0\n1\n2\n3\n4\n");
+
+    // --cached only changes the index.
+    let options = ApplyOptions {
+        cached: true,
+        ..Default::default()
+    };
+    let mut apply = GitApply::new(&repo, options)?;
+    assert!(apply.apply(&parse_patch(PATCH.as_bytes(), 1)?)?.is_clean());
+    assert_eq!(read(&repo, "a.txt")?, "0\n1\n2\n3\n4\n");
+    let sha = apply.index().entry("a.txt").unwrap().sha().to_string();
+    let (_, data) = apply.odb().read(&sha)?;
+    assert_eq!(data, b"0\n1\n2\nthree\n4\n");
+    Ok(())
+}
+
+#[test]
+fn test_three_way() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_apply_three_way")?;
+    let odb = GitOdb::open(&repo)?;
+    let base = odb.write(ObjectType::Blob, b"1\n2\n3\n4\n")?;
+    let theirs = odb.write(ObjectType::Blob, b"1\n2\nthree\n4\n")?;
+    let patch = format!(
+        "diff --git a/a.txt b/a.txt\nindex {}..{} 100644\n--- a/a.txt\n+++ b/a.txt\n\
+         @@ -1,4 +1,4 @@\n 1\n 2\n-3\n+three\n 4\n",
+        base, theirs
+    );
+    let patches = parse_patch(patch.as_bytes(), 1)?;
+    let options = ApplyOptions {
+        three_way: true,
+        ..Default::default()
+    };
+
+    // The context does not match, but the changes do not overlap.
+    add(&repo, "a.txt", "one\n2\n3\n4\n")?;
+    let mut apply = GitApply::new(&repo, options.clone())?;
+    let report = apply.apply(&patches)?;
+    assert!(report.is_clean());
+    assert_eq!(report.messages, ["Applied patch to 'a.txt' cleanly."]);
+    assert_eq!(read(&repo, "a.txt")?, "one\n2\nthree\n4\n");
+    assert_eq!(apply.index().entry("a.txt").unwrap().stage(), 0);
+
+    let ours = add(&repo, "a.txt", "1\nTWO\ndrei\n4\n")?;
+    let mut apply = GitApply::new(&repo, options)?;
+    let report = apply.apply(&patches)?;
+    assert!(report.applied);
+    assert_eq!(report.conflicts, ["a.txt"]);
+    assert_eq!(
+        read(&repo, "a.txt")?,
+        "1\n<<<<<<< ours\nTWO\ndrei\n=======\n2\nthree\n>>>>>>> theirs\n4\n"
+    );
+    let stages: Vec<(u8, &str)> = apply
+        .index()
+        .entries()
+        .iter()
+        .map(|entry| (entry.stage(), entry.sha()))
+        .collect();
+    assert_eq!(
+        stages,
+        [(1, base.as_str()), (2, ours.as_str()), (3, theirs.as_str())]
+    );
+    Ok(())
+}
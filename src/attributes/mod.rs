@@ -0,0 +1,377 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use crate::config::GitConfig;
+use crate::utils;
+use crate::{GitRepo, GitResult, IgnorePattern};
+
+/// The state of an attribute for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttrValue {
+    /// The attribute is set (`text`).
+    Set,
+    /// The attribute is unset (`-text`).
+    Unset,
+    /// The attribute is set to a value (`eol=lf`).
+    Value(String),
+    /// The attribute is not specified for the path (`!text` or no matching line).
+    Unspecified,
+}
+
+impl AttrValue {
+    /// Returns the value if the attribute is set to one.
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            AttrValue::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AttrValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AttrValue::Set => write!(f, "set"),
+            AttrValue::Unset => write!(f, "unset"),
+            AttrValue::Value(value) => write!(f, "{}", value),
+            AttrValue::Unspecified => write!(f, "unspecified"),
+        }
+    }
+}
+
+/// A line of an attributes file: a pattern (or macro name) and the attributes it assigns.
+#[derive(Debug, Clone)]
+struct AttrRule {
+    pattern: IgnorePattern,
+    attrs: Vec<(String, AttrValue)>,
+}
+
+/// Parses a single attribute assignment like `text`, `-text`, `!text` or `eol=lf`.
+fn parse_attr(token: &str) -> Option<(String, AttrValue)> {
+    let (name, value) = if let Some(name) = token.strip_prefix('-') {
+        (name, AttrValue::Unset)
+    } else if let Some(name) = token.strip_prefix('!') {
+        (name, AttrValue::Unspecified)
+    } else if let Some(i) = token.find('=') {
+        (&token[..i], AttrValue::Value(token[i + 1..].to_string()))
+    } else {
+        (token, AttrValue::Set)
+    };
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|ch| ch.is_ascii_alphanumeric() || b"-_.".contains(&ch));
+    if valid {
+        Some((name.to_string(), value))
+    } else {
+        None
+    }
+}
+
+/// The contents of one attributes file.
+#[derive(Debug, Default, Clone)]
+struct AttrFile {
+    rules: Vec<AttrRule>,
+    macros: Vec<(String, Vec<(String, AttrValue)>)>,
+    /// The macro and attribute names in the order they appear.
+    names: Vec<String>,
+    /// Why lines were ignored, as printed by git.
+    warnings: Vec<String>,
+}
+
+impl AttrFile {
+    fn parse(data: &str, base: &str, source: Option<PathBuf>, allow_macros: bool) -> Self {
+        let mut file = Self::default();
+        for (i, line) in data.lines().enumerate() {
+            let line = line.trim_start_matches([' ', '\t']).trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (pattern, rest) = if line.starts_with('"') {
+                match utils::unquote_c_style(line) {
+                    Some((pattern, len)) => (pattern, &line[len..]),
+                    None => continue,
+                }
+            } else {
+                let end = line.find([' ', '\t']).unwrap_or(line.len());
+                (line[..end].to_string(), &line[end..])
+            };
+            let attrs: Vec<_> = rest.split_whitespace().filter_map(parse_attr).collect();
+
+            if let Some(name) = pattern.strip_prefix("[attr]") {
+                if allow_macros {
+                    file.names.push(name.to_string());
+                    file.names
+                        .extend(attrs.iter().map(|(name, _)| name.clone()));
+                    file.macros.push((name.to_string(), attrs));
+                } else {
+                    // Like git, this message has no "warning: " prefix.
+                    file.warnings.push(format!(
+                        "{} not allowed: {}:{}",
+                        line,
+                        source
+                            .as_ref()
+                            .map_or(String::new(), |s| s.display().to_string()),
+                        i + 1
+                    ));
+                }
+                continue;
+            }
+            if pattern.starts_with('!') {
+                file.warnings.push(
+                    "warning: Negative patterns are ignored in git attributes\n\
+                     Use '\\!' for literal leading exclamation."
+                        .to_string(),
+                );
+                continue;
+            }
+            if let Some(pattern) = IgnorePattern::parse(&pattern, base, source.clone(), i + 1) {
+                file.names
+                    .extend(attrs.iter().map(|(name, _)| name.clone()));
+                file.rules.push(AttrRule { pattern, attrs });
+            }
+        }
+        file
+    }
+
+    fn read(path: &Path, base: &str, source: PathBuf, allow_macros: bool) -> GitResult<Self> {
+        if path.is_file() {
+            let data = utils::read_file(path)?;
+            Ok(Self::parse(&data, base, Some(source), allow_macros))
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+/// Looks up gitattributes for worktree paths.
+///
+/// Attributes are read, from highest to lowest precedence, from `$GIT_DIR/info/attributes`, the
+/// `.gitattributes` files from the deepest directory of a path up to the worktree root,
+/// `core.attributesFile` and `/etc/gitattributes`. Within a file, later lines override earlier
+/// ones. Macros (`[attr]name ...`) may only be defined at the top level, i.e. outside of
+/// subdirectories.
+#[derive(Debug)]
+pub struct GitAttributes {
+    worktree: PathBuf,
+    ignore_case: bool,
+    macros: HashMap<String, Vec<(String, AttrValue)>>,
+    info: AttrFile,
+    global: AttrFile,
+    system: AttrFile,
+    per_dir: HashMap<String, AttrFile>,
+    /// The position of every name in the order git registers them: the built-in macro, then
+    /// the files in the order they are read.
+    order: HashMap<String, usize>,
+    warnings: Vec<String>,
+}
+
+impl GitAttributes {
+    /// Creates a lookup for the worktree of `repo`.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading the config or the attributes files.
+    pub fn new(repo: &GitRepo) -> GitResult<Self> {
+        let worktree = repo
+            .worktree()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf();
+        let config = GitConfig::load(Some(repo))?;
+        let ignore_case = config.get_bool("core.ignorecase", false)?;
+
+        let mut system = if env::var_os("GIT_ATTR_NOSYSTEM").is_some() {
+            AttrFile::default()
+        } else {
+            let path = PathBuf::from("/etc/gitattributes");
+            AttrFile::read(&path, "", path.clone(), true)?
+        };
+        let global_path = config
+            .get_path("core.attributesfile")?
+            .or_else(|| utils::xdg_config_path("attributes"));
+        let mut global = match global_path {
+            Some(path) => AttrFile::read(&path, "", path.clone(), true)?,
+            None => AttrFile::default(),
+        };
+        let info_path = repo.gitpath().join("info").join("attributes");
+        let source = info_path
+            .strip_prefix(&worktree)
+            .unwrap_or(&info_path)
+            .to_path_buf();
+        let mut info = AttrFile::read(&info_path, "", source, true)?;
+
+        let mut warnings = Vec::new();
+        for file in [&mut system, &mut global, &mut info] {
+            warnings.append(&mut file.warnings);
+        }
+        let mut attributes = Self {
+            worktree,
+            ignore_case,
+            macros: HashMap::new(),
+            info,
+            global,
+            system,
+            per_dir: HashMap::new(),
+            order: HashMap::new(),
+            warnings,
+        };
+        let binary = vec![
+            ("diff".to_string(), AttrValue::Unset),
+            ("merge".to_string(), AttrValue::Unset),
+            ("text".to_string(), AttrValue::Unset),
+        ];
+        register(
+            &mut attributes.order,
+            &["binary", "diff", "merge", "text"].map(String::from),
+        );
+        attributes.macros.insert("binary".to_string(), binary);
+        // Names are registered in the order git reads the files, which puts the info file
+        // after the top level .gitattributes.
+        register(&mut attributes.order, &attributes.system.names);
+        register(&mut attributes.order, &attributes.global.names);
+        // Lowest precedence first so that later definitions win.
+        let root = attributes.dir_file("")?.macros.clone();
+        register(&mut attributes.order, &attributes.info.names);
+        let files = [
+            attributes.system.macros.clone(),
+            attributes.global.macros.clone(),
+            root,
+            attributes.info.macros.clone(),
+        ];
+        for macros in files.iter() {
+            for (name, attrs) in macros {
+                attributes.macros.insert(name.clone(), attrs.clone());
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Returns the warnings about ignored lines of the attributes files read since the last
+    /// call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Returns the state of each attribute in `names` for `path`.
+    ///
+    /// `path` is relative to the worktree and uses '/' as a separator.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitattributes` files.
+    pub fn check(&mut self, path: &str, names: &[&str]) -> GitResult<Vec<(String, AttrValue)>> {
+        let all = self.collect(path)?;
+        Ok(names
+            .iter()
+            .map(|&name| {
+                let value = all
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map_or(AttrValue::Unspecified, |(_, value)| value.clone());
+                (name.to_string(), value)
+            })
+            .collect())
+    }
+
+    /// Returns the state of a single attribute for `path`.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitattributes` files.
+    pub fn get(&mut self, path: &str, name: &str) -> GitResult<AttrValue> {
+        Ok(self.check(path, &[name])?.remove(0).1)
+    }
+
+    /// Returns every attribute that is specified for `path`, in the order git lists them: the
+    /// order their names first appear in the attributes files read so far.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitattributes` files.
+    pub fn check_all(&mut self, path: &str) -> GitResult<Vec<(String, AttrValue)>> {
+        let mut all = self.collect(path)?;
+        all.retain(|(_, value)| *value != AttrValue::Unspecified);
+        all.sort_by_key(|(name, _)| self.order.get(name).copied().unwrap_or(usize::MAX));
+        Ok(all)
+    }
+
+    /// Resolves every attribute touched by a rule matching `path`, including explicit
+    /// `Unspecified` ones.
+    fn collect(&mut self, path: &str) -> GitResult<Vec<(String, AttrValue)>> {
+        let path = path.trim_matches('/');
+        let mut dirs = Vec::new();
+        let mut dir = path;
+        while let Some(i) = dir.rfind('/') {
+            dir = &dir[..i];
+            dirs.push(dir.to_string());
+        }
+        dirs.push(String::new());
+        for dir in &dirs {
+            self.dir_file(dir)?;
+        }
+
+        let mut stack = vec![&self.info];
+        stack.extend(dirs.iter().map(|dir| &self.per_dir[dir]));
+        stack.push(&self.global);
+        stack.push(&self.system);
+
+        let mut result = Vec::new();
+        for file in stack {
+            for rule in file.rules.iter().rev() {
+                if rule.pattern.matches(path, false, self.ignore_case) {
+                    self.fill(&rule.attrs, &mut result, 0);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Assigns `attrs` (last one first) to every attribute that has not been assigned yet, and
+    /// expands macros that end up set.
+    fn fill(
+        &self,
+        attrs: &[(String, AttrValue)],
+        result: &mut Vec<(String, AttrValue)>,
+        depth: usize,
+    ) {
+        for (name, value) in attrs.iter().rev() {
+            if result.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            result.push((name.clone(), value.clone()));
+            if *value == AttrValue::Set && depth < 64 {
+                if let Some(expansion) = self.macros.get(name) {
+                    self.fill(expansion, result, depth + 1);
+                }
+            }
+        }
+    }
+
+    fn dir_file(&mut self, dir: &str) -> GitResult<&AttrFile> {
+        if !self.per_dir.contains_key(dir) {
+            let path = self.worktree.join(dir).join(".gitattributes");
+            let source = PathBuf::from(dir).join(".gitattributes");
+            let mut file = AttrFile::read(&path, dir, source, dir.is_empty())?;
+            self.warnings.append(&mut file.warnings);
+            register(&mut self.order, &file.names);
+            self.per_dir.insert(dir.to_string(), file);
+        }
+        Ok(&self.per_dir[dir])
+    }
+}
+
+/// Gives the names in `names` not seen yet the next positions in `order`.
+fn register(order: &mut HashMap<String, usize>, names: &[String]) {
+    for name in names {
+        let next = order.len();
+        order.entry(name.clone()).or_insert(next);
+    }
+}
@@ -0,0 +1,119 @@
+use super::*;
+use crate::test_utils;
+use std::error::Error;
+use std::fs;
+
+#[test]
+fn test_parse_attr() {
+    assert_eq!(
+        parse_attr("text"),
+        Some(("text".to_string(), AttrValue::Set))
+    );
+    assert_eq!(
+        parse_attr("-diff"),
+        Some(("diff".to_string(), AttrValue::Unset))
+    );
+    assert_eq!(
+        parse_attr("!eol"),
+        Some(("eol".to_string(), AttrValue::Unspecified))
+    );
+    assert_eq!(
+        parse_attr("eol=crlf"),
+        Some(("eol".to_string(), AttrValue::Value("crlf".to_string())))
+    );
+    assert_eq!(parse_attr("=x"), None);
+}
+
+#[test]
+fn test_git_attributes() -> Result<(), Box<dyn Error>> {
+    let worktree = env::temp_dir().join("gitrs_test_git_attributes");
+    let gitpath = worktree.join(".git");
+    fs::create_dir_all(gitpath.join("info"))?;
+    fs::create_dir_all(worktree.join("sub"))?;
+    fs::write(gitpath.join("config"), "[core]\n")?;
+    fs::write(gitpath.join("info").join("attributes"), "*.bat eol=crlf\n")?;
+    fs::write(
+        worktree.join(".gitattributes"),
+        "[attr]docs text diff=markdown\n* text=auto\n*.png binary\n*.md docs\n*.bat eol=lf\n",
+    )?;
+    fs::write(
+        worktree.join("sub").join(".gitattributes"),
+        "[attr]nested text\n!*.txt text\n*.md -text\n",
+    )?;
+
+    let repo = GitRepo::new(worktree.clone(), gitpath);
+    let mut attributes = GitAttributes::new(&repo)?;
+
+    assert_eq!(
+        attributes.get("a.txt", "text")?,
+        AttrValue::Value("auto".to_string())
+    );
+    // Built-in binary macro.
+    assert_eq!(attributes.get("img.png", "text")?, AttrValue::Unset);
+    assert_eq!(attributes.get("img.png", "diff")?, AttrValue::Unset);
+    assert_eq!(attributes.get("img.png", "eol")?, AttrValue::Unspecified);
+    // User defined macro.
+    assert_eq!(attributes.get("README.md", "text")?, AttrValue::Set);
+    assert_eq!(
+        attributes.get("README.md", "diff")?,
+        AttrValue::Value("markdown".to_string())
+    );
+    assert!(attributes.take_warnings().is_empty());
+    // Deeper directories take precedence.
+    assert_eq!(attributes.get("sub/README.md", "text")?, AttrValue::Unset);
+    // Their macros and negative patterns are ignored with a warning when they are read.
+    assert_eq!(
+        attributes.take_warnings(),
+        [
+            "[attr]nested text not allowed: sub/.gitattributes:1",
+            "warning: Negative patterns are ignored in git attributes\n\
+             Use '\\!' for literal leading exclamation."
+        ]
+    );
+    assert_eq!(attributes.get("sub/a.md", "text")?, AttrValue::Unset);
+    assert!(attributes.take_warnings().is_empty());
+    // info/attributes takes precedence over everything.
+    assert_eq!(
+        attributes.get("run.bat", "eol")?,
+        AttrValue::Value("crlf".to_string())
+    );
+
+    let all = attributes.check_all("img.png")?;
+    assert_eq!(all.len(), 4);
+    assert!(all.contains(&("binary".to_string(), AttrValue::Set)));
+
+    fs::remove_dir_all(worktree)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_check_all_order() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_check_all_order")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    fs::create_dir_all(repo.gitpath().join("info"))?;
+    fs::write(repo.gitpath().join("info/attributes"), "*.m info\n")?;
+    fs::write(
+        worktree.join(".gitattributes"),
+        "*.txt text\n[attr]mymacro text eol=crlf\n*.m mymacro\n*.m foo -diff\n",
+    )?;
+    fs::create_dir_all(worktree.join("d"))?;
+    fs::write(worktree.join("d/.gitattributes"), "*.m deep\n")?;
+    let mut attributes = GitAttributes::new(&repo)?;
+
+    // Attributes are listed in the order git registers their names, not as assigned.
+    let names = |all: Vec<(String, AttrValue)>| -> Vec<String> {
+        all.into_iter().map(|(name, _)| name).collect()
+    };
+    assert_eq!(
+        names(attributes.check_all("a.m")?),
+        ["diff", "text", "mymacro", "eol", "foo", "info"]
+    );
+    assert_eq!(
+        names(attributes.check_all("d/a.m")?),
+        ["diff", "text", "mymacro", "eol", "foo", "info", "deep"]
+    );
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
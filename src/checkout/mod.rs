@@ -0,0 +1,688 @@
+//! Updating the index and the worktree to the contents of a tree.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::index::{GitIndex, IndexEntry};
+use crate::objects::{self, ObjectType};
+use crate::pathspec::Pathspec;
+use crate::utils;
+use crate::{
+    self as gitrs, GitBlob, GitConverter, GitError, GitFileMode, GitIgnore, GitObject, GitOdb,
+    GitRepo, GitResult, GitTree, GitTreeEntry,
+};
+
+/// The state of a worktree file compared to the blob it is supposed to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileState {
+    Missing,
+    Clean,
+    Modified,
+}
+
+/// Moves the index and worktree of a repository between trees without losing local changes.
+#[derive(Debug)]
+pub struct GitCheckout {
+    worktree: PathBuf,
+    odb: GitOdb,
+    index: GitIndex,
+    converter: GitConverter,
+    ignore: GitIgnore,
+    trust_filemode: bool,
+    symlinks: bool,
+    force: bool,
+}
+
+impl GitCheckout {
+    /// Prepares a checkout in the worktree of `repo`, reading its index and settings.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::CheckoutError]: The repository has no worktree
+    /// * [GitError::IndexError]: The index is corrupt
+    /// * Errors obtained when reading the object database, config or attributes
+    pub fn new(repo: &GitRepo) -> GitResult<Self> {
+        let worktree = match repo.worktree() {
+            Some(worktree) => worktree.to_path_buf(),
+            None => {
+                return Err(GitError::CheckoutError {
+                    message: "fatal: this operation must be run in a work tree".to_string(),
+                })
+            }
+        };
+        Ok(Self {
+            worktree,
+            odb: GitOdb::open(repo)?,
+            index: GitIndex::read(repo)?,
+            converter: GitConverter::new(repo)?,
+            ignore: GitIgnore::new(repo)?,
+            trust_filemode: config::get_bool(repo, "core.filemode", true)?,
+            symlinks: config::get_bool(repo, "core.symlinks", true)?,
+            force: false,
+        })
+    }
+
+    /// Discards local changes instead of refusing to overwrite them.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// The object database the contents are read from.
+    pub fn odb(&self) -> &GitOdb {
+        &self.odb
+    }
+
+    /// The index as updated so far.
+    pub fn index(&self) -> &GitIndex {
+        &self.index
+    }
+
+    /// Returns what the conversion of files to and from the worktree warned about since the last
+    /// call. See [GitConverter::take_warnings].
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        self.converter.take_warnings()
+    }
+
+    /// Writes the updated index.
+    ///
+    /// # Errors
+    ///
+    /// See [GitIndex::write].
+    pub fn write_index(&mut self) -> GitResult<()> {
+        self.index.write()
+    }
+
+    /// Moves the index and worktree from the tree `old` (the tree of `HEAD`, or [None] for an
+    /// unborn branch) to the tree `new`.
+    ///
+    /// Paths that are the same in both trees keep their local changes. Paths that differ are
+    /// updated, unless that would overwrite changes in the index or worktree, or an untracked
+    /// file; then nothing is changed and a [GitError::CheckoutError] listing the paths is
+    /// returned. With [GitCheckout::set_force] local changes are discarded instead.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::CheckoutError]: Local changes or untracked files would be overwritten, or the
+    ///   index has unresolved conflicts
+    /// * Errors obtained when reading objects or writing the worktree
+    pub fn switch_trees(&mut self, old: Option<&str>, new: &str) -> GitResult<()> {
+        let old_entries = match old {
+            Some(old) => GitTree::flatten(&self.odb, old)?,
+            None => BTreeMap::new(),
+        };
+        let new_entries = GitTree::flatten(&self.odb, new)?;
+
+        if !self.force && self.index.has_conflicts() {
+            let mut message = String::new();
+            let mut unmerged: Vec<&str> = self
+                .index
+                .entries()
+                .iter()
+                .filter(|entry| entry.stage() != 0)
+                .map(IndexEntry::path)
+                .collect();
+            unmerged.dedup();
+            for path in unmerged {
+                message.push_str(&format!("{}: needs merge\n", path));
+            }
+            message.push_str("error: you need to resolve your current index first");
+            return Err(GitError::CheckoutError { message });
+        }
+
+        let mut paths: BTreeSet<String> = old_entries.keys().cloned().collect();
+        paths.extend(new_entries.keys().cloned());
+        paths.extend(self.index.entries().iter().map(|e| e.path().to_string()));
+
+        let mut local_changes = Vec::new();
+        let mut untracked = Vec::new();
+        let mut removals = Vec::new();
+        let mut writes = Vec::new();
+        for path in &paths {
+            let old = old_entries.get(path);
+            let new = new_entries.get(path);
+            let index = self.index.entry(path).cloned();
+
+            if self.force {
+                match new {
+                    Some(new) => {
+                        let unchanged = index.as_ref().is_some_and(|index| same(new, index))
+                            && self.file_state(path, new.mode(), new.sha(), index.as_ref())?
+                                == FileState::Clean;
+                        if !unchanged {
+                            writes.push(new.clone());
+                        }
+                    }
+                    None => removals.push(path.clone()),
+                }
+                continue;
+            }
+
+            if old == new {
+                continue;
+            }
+            match (&index, new) {
+                (Some(index), Some(new)) if same(new, index) => continue,
+                _ => {}
+            }
+            let index_matches_old = match (&index, old) {
+                (Some(index), Some(old)) => same(old, index),
+                (None, None) => true,
+                _ => false,
+            };
+            if !index_matches_old {
+                local_changes.push(path.clone());
+                continue;
+            }
+            match &index {
+                Some(index) => {
+                    if self.file_state(path, index.mode(), index.sha(), Some(index))?
+                        == FileState::Modified
+                    {
+                        local_changes.push(path.clone());
+                        continue;
+                    }
+                }
+                None => {
+                    if self.is_untracked_file(path)? {
+                        untracked.push(path.clone());
+                        continue;
+                    }
+                }
+            }
+
+            match new {
+                Some(new) => writes.push(new.clone()),
+                None => removals.push(path.clone()),
+            }
+        }
+
+        // Files replacing a directory, or placed below a path that is a file now.
+        if !self.force {
+            for entry in &writes {
+                let path = entry.name();
+                if !untracked.iter().any(|p| p == path)
+                    && self.blocked_by_untracked(path, &removals)?
+                {
+                    untracked.push(path.to_string());
+                }
+            }
+        }
+
+        if !local_changes.is_empty() || !untracked.is_empty() {
+            return Err(overwrite_error(&local_changes, &untracked));
+        }
+
+        for path in &removals {
+            self.remove_file(path)?;
+            self.index.remove(path);
+        }
+        self.write_entries(&writes)
+    }
+
+    /// Checks out the paths matching `pathspec` from the tree `source` (updating the index too),
+    /// or from the index if `source` is [None]. Paths not in the source are left alone.
+    ///
+    /// Returns the number of paths that were checked out.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::CheckoutError]: An item of the pathspec matched nothing, or a path is
+    ///   unmerged
+    /// * Errors obtained when reading objects or writing the worktree
+    pub fn checkout_paths(
+        &mut self,
+        source: Option<&str>,
+        pathspec: &Pathspec,
+    ) -> GitResult<usize> {
+        let entries: Vec<GitTreeEntry> = match source {
+            Some(tree) => GitTree::flatten(&self.odb, tree)?
+                .into_values()
+                .filter(|entry| pathspec.matches(entry.name()))
+                .collect(),
+            None => {
+                let mut unmerged: Vec<String> = self
+                    .index
+                    .entries()
+                    .iter()
+                    .filter(|entry| entry.stage() != 0 && pathspec.matches(entry.path()))
+                    .map(|entry| format!("error: path '{}' is unmerged", entry.path()))
+                    .collect();
+                unmerged.dedup();
+                if !unmerged.is_empty() {
+                    return Err(GitError::CheckoutError {
+                        message: unmerged.join("\n"),
+                    });
+                }
+                self.index
+                    .entries()
+                    .iter()
+                    .filter(|entry| pathspec.matches(entry.path()))
+                    .map(|entry| GitTreeEntry::new(entry.mode(), entry.path(), entry.sha()))
+                    .collect()
+            }
+        };
+        check_pathspec(pathspec, entries.iter().map(GitTreeEntry::name))?;
+
+        let mut writes = Vec::new();
+        for entry in &entries {
+            let index = self.index.entry(entry.name()).cloned();
+            let state = self.file_state(entry.name(), entry.mode(), entry.sha(), index.as_ref())?;
+            if state != FileState::Clean {
+                writes.push(entry.clone());
+            } else if source.is_some() && !index.is_some_and(|index| same(entry, &index)) {
+                self.index
+                    .add(IndexEntry::new(entry.name(), entry.mode(), entry.sha()));
+            }
+        }
+        self.write_entries(&writes)?;
+        Ok(entries.len())
+    }
+
+    /// Restores the paths matching `pathspec` from the tree `source`, or from the index if
+    /// `source` is [None] (only valid for the worktree).
+    ///
+    /// With `staged` the index entries are replaced, with `worktree` the files. Unlike
+    /// [GitCheckout::checkout_paths], tracked paths missing from the source are removed.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::CheckoutError]: An item of the pathspec matched nothing, or a path is
+    ///   unmerged
+    /// * Errors obtained when reading objects or writing the worktree
+    pub fn restore(
+        &mut self,
+        source: Option<&str>,
+        pathspec: &Pathspec,
+        staged: bool,
+        worktree: bool,
+    ) -> GitResult<()> {
+        let source_entries: BTreeMap<String, GitTreeEntry> = match source {
+            Some(tree) => GitTree::flatten(&self.odb, tree)?
+                .into_iter()
+                .filter(|(path, _)| pathspec.matches(path))
+                .collect(),
+            None => self
+                .index
+                .entries()
+                .iter()
+                .filter(|entry| entry.stage() == 0 && pathspec.matches(entry.path()))
+                .map(|entry| {
+                    let tree_entry = GitTreeEntry::new(entry.mode(), entry.path(), entry.sha());
+                    (entry.path().to_string(), tree_entry)
+                })
+                .collect(),
+        };
+        let tracked: BTreeSet<String> = self
+            .index
+            .entries()
+            .iter()
+            .map(|entry| entry.path().to_string())
+            .filter(|path| pathspec.matches(path))
+            .collect();
+        check_pathspec(
+            pathspec,
+            source_entries
+                .keys()
+                .chain(tracked.iter())
+                .map(String::as_str),
+        )?;
+
+        if source.is_none() {
+            let unmerged: Vec<String> = tracked
+                .iter()
+                .filter(|path| self.index.entry(path).is_none())
+                .map(|path| format!("error: path '{}' is unmerged", path))
+                .collect();
+            if !unmerged.is_empty() {
+                return Err(GitError::CheckoutError {
+                    message: unmerged.join("\n"),
+                });
+            }
+        }
+
+        if staged {
+            for path in &tracked {
+                if !source_entries.contains_key(path) {
+                    self.index.remove(path);
+                }
+            }
+            for entry in source_entries.values() {
+                let current = self.index.entry(entry.name());
+                if !current.is_some_and(|current| same(entry, current)) {
+                    self.index
+                        .add(IndexEntry::new(entry.name(), entry.mode(), entry.sha()));
+                }
+            }
+        }
+
+        if worktree {
+            for path in &tracked {
+                if !source_entries.contains_key(path) {
+                    self.remove_file(path)?;
+                }
+            }
+            let mut writes = Vec::new();
+            for entry in source_entries.values() {
+                let index = self.index.entry(entry.name()).cloned();
+                if self.file_state(entry.name(), entry.mode(), entry.sha(), index.as_ref())?
+                    != FileState::Clean
+                {
+                    writes.push(entry.clone());
+                }
+            }
+            // Only the files change; the index keeps its entries unless it was restored too.
+            let saved: Vec<Option<IndexEntry>> = writes
+                .iter()
+                .map(|entry| self.index.entry(entry.name()).cloned())
+                .collect();
+            self.write_entries(&writes)?;
+            for (entry, saved) in writes.iter().zip(saved) {
+                match saved {
+                    Some(saved) if !same(entry, &saved) => self.index.add(saved),
+                    None if !staged => {
+                        self.index.remove(entry.name());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the worktree file at `path` with the blob `sha` of mode `mode`.
+    ///
+    /// `index` is the index entry of the path, whose stat data avoids reading unchanged files.
+    fn file_state(
+        &mut self,
+        path: &str,
+        mode: GitFileMode,
+        sha: &str,
+        index: Option<&IndexEntry>,
+    ) -> GitResult<FileState> {
+        let full = self.worktree.join(path);
+        let metadata = match fs::symlink_metadata(&full) {
+            Ok(metadata) => metadata,
+            Err(error)
+                if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) =>
+            {
+                return Ok(FileState::Missing)
+            }
+            Err(error) => return Err(GitError::IOError { error, path: full }),
+        };
+
+        if mode.is_gitlink() {
+            return Ok(if metadata.is_dir() {
+                FileState::Clean
+            } else {
+                FileState::Modified
+            });
+        }
+        let file_type = metadata.file_type();
+        let found_mode = if file_type.is_symlink() || (!self.symlinks && mode.is_symlink()) {
+            GitFileMode::symlink()
+        } else if file_type.is_file() {
+            GitFileMode::from_metadata(&metadata, self.trust_filemode)
+        } else {
+            return Ok(FileState::Modified);
+        };
+        let expected_mode = if !self.trust_filemode && mode.is_file() {
+            GitFileMode::regular()
+        } else {
+            mode
+        };
+        if found_mode != expected_mode {
+            return Ok(FileState::Modified);
+        }
+
+        if let Some(index) = index {
+            if index.sha() == sha && index.is_stat_clean(&metadata) && !self.index.is_racy(index) {
+                return Ok(FileState::Clean);
+            }
+        }
+
+        let data = if file_type.is_symlink() {
+            let target = gitrs::to_git_result(fs::read_link(&full), &full)?;
+            target.to_string_lossy().into_owned().into_bytes()
+        } else {
+            let data = utils::read_bytes(&full)?;
+            if mode.is_symlink() {
+                data
+            } else {
+                self.converter.to_git(path, &data)?
+            }
+        };
+        if objects::hash_object(ObjectType::Blob, &data) == sha {
+            Ok(FileState::Clean)
+        } else {
+            Ok(FileState::Modified)
+        }
+    }
+
+    /// Returns true if an untracked, not ignored file exists at the new path `path`.
+    fn is_untracked_file(&mut self, path: &str) -> GitResult<bool> {
+        match fs::symlink_metadata(self.worktree.join(path)) {
+            Ok(metadata) if !metadata.is_dir() => Ok(!self.ignore.is_ignored(path, false)?),
+            _ => Ok(false),
+        }
+    }
+
+    /// Returns true if checking out `path` requires removing untracked files: either a file in
+    /// place of one of its leading directories, or files in a directory at `path`.
+    fn blocked_by_untracked(&mut self, path: &str, removals: &[String]) -> GitResult<bool> {
+        for (i, _) in path.match_indices('/') {
+            let parent = &path[..i];
+            match fs::symlink_metadata(self.worktree.join(parent)) {
+                Ok(metadata) if metadata.is_dir() => continue,
+                Ok(_) => {
+                    let removed = removals.iter().any(|r| r == parent);
+                    return Ok(!removed && !self.ignore.is_ignored(parent, false)?);
+                }
+                Err(_) => return Ok(false),
+            }
+        }
+
+        let full = self.worktree.join(path);
+        if fs::symlink_metadata(&full).is_ok_and(|metadata| metadata.is_dir()) {
+            let mut files = Vec::new();
+            collect_files(&self.worktree, &full, &mut files)?;
+            for file in files {
+                let removed = removals.contains(&file);
+                if !removed && !self.ignore.is_ignored(&file, false)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes a file from the worktree, along with directories that become empty.
+    fn remove_file(&self, path: &str) -> GitResult<()> {
+        let full = self.worktree.join(path);
+        match fs::symlink_metadata(&full) {
+            Ok(metadata) if metadata.is_dir() => {
+                // Gitlinks are checked out as directories; only remove them if empty.
+                let _ = fs::remove_dir(&full);
+            }
+            Ok(_) => gitrs::to_git_result(fs::remove_file(&full), &full)?,
+            Err(_) => {}
+        }
+        let mut dir = full.parent();
+        while let Some(current) = dir {
+            if current == self.worktree || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+        Ok(())
+    }
+
+    /// Writes `entries` to the worktree and the index, waiting for delayed filters at the end.
+    fn write_entries(&mut self, entries: &[GitTreeEntry]) -> GitResult<()> {
+        let mut delayed = Vec::new();
+        for entry in entries {
+            let full = self.worktree.join(entry.name());
+            self.prepare_path(&full)?;
+
+            let mode = entry.mode();
+            if mode.is_gitlink() {
+                utils::create_dir_all_if_new(&full)?;
+                self.index
+                    .add(IndexEntry::new(entry.name(), mode, entry.sha()));
+                continue;
+            }
+
+            let blob = self.odb.read_as::<GitBlob>(entry.sha())?;
+            let data = blob.data();
+            if mode.is_symlink() && self.symlinks {
+                let target = String::from_utf8_lossy(data).into_owned();
+                gitrs::to_git_result(symlink(target, &full), &full)?;
+            } else if mode.is_symlink() {
+                write_file(&full, data, mode)?;
+            } else {
+                match self.converter.to_worktree_delayed(entry.name(), data)? {
+                    Some(data) => write_file(&full, &data, mode)?,
+                    None => {
+                        delayed.push(entry.clone());
+                        continue;
+                    }
+                }
+            }
+            self.add_to_index(entry)?;
+        }
+
+        for (path, data) in self.converter.finish_delayed()? {
+            if let Some(entry) = delayed.iter().find(|entry| entry.name() == path) {
+                write_file(&self.worktree.join(&path), &data, entry.mode())?;
+                self.add_to_index(&entry.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_to_index(&mut self, entry: &GitTreeEntry) -> GitResult<()> {
+        let full = self.worktree.join(entry.name());
+        let metadata = gitrs::to_git_result(fs::symlink_metadata(&full), &full)?;
+        self.index.add(IndexEntry::from_metadata(
+            entry.name(),
+            entry.mode(),
+            entry.sha(),
+            &metadata,
+        ));
+        Ok(())
+    }
+
+    /// Clears the way for a new file at `full`: removes whatever is there and replaces leading
+    /// files by directories.
+    fn prepare_path(&self, full: &Path) -> GitResult<()> {
+        let parent = full.parent().unwrap();
+        let mut current = PathBuf::new();
+        for component in parent.strip_prefix(&self.worktree).unwrap_or(parent).iter() {
+            current.push(component);
+            let path = self.worktree.join(&current);
+            if let Ok(metadata) = fs::symlink_metadata(&path) {
+                if !metadata.is_dir() {
+                    gitrs::to_git_result(fs::remove_file(&path), &path)?;
+                }
+            }
+        }
+        utils::create_dir_all_if_new(parent)?;
+
+        match fs::symlink_metadata(full) {
+            Ok(metadata) if metadata.is_dir() => {
+                gitrs::to_git_result(fs::remove_dir_all(full), full)
+            }
+            Ok(_) => gitrs::to_git_result(fs::remove_file(full), full),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Returns true if the index entry has the same mode and object as the tree entry.
+fn same(entry: &GitTreeEntry, index: &IndexEntry) -> bool {
+    entry.mode() == index.mode() && entry.sha() == index.sha()
+}
+
+/// Creates a file with the permission bits of `mode`; the umask still applies.
+fn write_file(path: &Path, data: &[u8], mode: GitFileMode) -> GitResult<()> {
+    let result = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode.permissions())
+        .open(path)
+        .and_then(|mut file| file.write_all(data));
+    gitrs::to_git_result(result, path)
+}
+
+/// Collects the paths (relative to `worktree`) of all files below `dir`.
+fn collect_files(worktree: &Path, dir: &Path, files: &mut Vec<String>) -> GitResult<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries {
+        let entry = gitrs::to_git_result(entry, dir)?;
+        let path = entry.path();
+        if path.file_name().is_some_and(|name| name == ".git") {
+            continue;
+        }
+        let file_type = gitrs::to_git_result(entry.file_type(), &path)?;
+        if file_type.is_dir() {
+            collect_files(worktree, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(worktree) {
+            files.push(relative.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Returns an error for the first pathspec item that matches none of `paths`.
+fn check_pathspec<'a, I: Iterator<Item = &'a str>>(pathspec: &Pathspec, paths: I) -> GitResult<()> {
+    let paths: Vec<&str> = paths.collect();
+    let unmatched: Vec<String> = (0..pathspec.items().len())
+        .filter(|&i| !paths.iter().any(|path| pathspec.item_matches(i, path)))
+        .map(|i| {
+            format!(
+                "error: pathspec '{}' did not match any file(s) known to git",
+                pathspec.items()[i]
+            )
+        })
+        .collect();
+    if unmatched.is_empty() {
+        Ok(())
+    } else {
+        Err(GitError::CheckoutError {
+            message: unmatched.join("\n"),
+        })
+    }
+}
+
+fn overwrite_error(local_changes: &[String], untracked: &[String]) -> GitError {
+    let mut message = String::new();
+    if !local_changes.is_empty() {
+        message.push_str(
+            "error: Your local changes to the following files would be overwritten by checkout:\n",
+        );
+        for path in local_changes {
+            message.push_str(&format!("\t{}\n", path));
+        }
+        message.push_str("Please commit your changes or stash them before you switch branches.\n");
+    }
+    if !untracked.is_empty() {
+        message.push_str(
+            "error: The following untracked working tree files would be overwritten by checkout:\n",
+        );
+        for path in untracked {
+            message.push_str(&format!("\t{}\n", path));
+        }
+        message.push_str("Please move or remove them before you switch branches.\n");
+    }
+    message.push_str("Aborting");
+    GitError::CheckoutError { message }
+}
@@ -0,0 +1,143 @@
+use super::*;
+use crate::test_utils::{self, tree};
+use std::error::Error;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn test_switch_trees() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_switch_trees")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    let odb = GitOdb::open(&repo)?;
+    let one = tree(
+        &odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "a\n"),
+            ("dir/run.sh", GitFileMode::executable(), "#!/bin/sh\n"),
+            ("gone.txt", GitFileMode::regular(), "gone\n"),
+        ],
+    )?;
+    let two = tree(
+        &odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "a\n"),
+            (
+                "dir/run.sh",
+                GitFileMode::executable(),
+                "#!/bin/sh\nexit 0\n",
+            ),
+            ("link", GitFileMode::symlink(), "a.txt"),
+        ],
+    )?;
+
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(None, &one)?;
+    checkout.write_index()?;
+    let mode = fs::metadata(worktree.join("dir/run.sh"))?
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o111, 0o111);
+    assert_eq!(fs::read_to_string(worktree.join("gone.txt"))?, "gone\n");
+
+    // A local change to a path that is the same in both trees is carried over.
+    fs::write(worktree.join("a.txt"), "changed\n")?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(Some(&one), &two)?;
+    checkout.write_index()?;
+    assert_eq!(fs::read_to_string(worktree.join("a.txt"))?, "changed\n");
+    assert_eq!(
+        fs::read_to_string(worktree.join("dir/run.sh"))?,
+        "#!/bin/sh\nexit 0\n"
+    );
+    assert!(!worktree.join("gone.txt").exists());
+    assert_eq!(fs::read_link(worktree.join("link"))?, Path::new("a.txt"));
+    let index = GitIndex::read(&repo)?;
+    let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+    assert_eq!(paths, ["a.txt", "dir/run.sh", "link"]);
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
+
+#[test]
+fn test_switch_refuses_local_changes() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_switch_refuses")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    let odb = GitOdb::open(&repo)?;
+    let one = tree(&odb, &[("a.txt", GitFileMode::regular(), "one\n")])?;
+    let two = tree(
+        &odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "two\n"),
+            ("new.txt", GitFileMode::regular(), "new\n"),
+        ],
+    )?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(None, &one)?;
+    checkout.write_index()?;
+
+    fs::write(worktree.join("a.txt"), "local\n")?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    let error = checkout.switch_trees(Some(&one), &two).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("overwritten by checkout:\n\ta.txt\n"));
+    assert_eq!(fs::read_to_string(worktree.join("a.txt"))?, "local\n");
+    assert!(!worktree.join("new.txt").exists());
+
+    // Untracked files are protected too.
+    fs::write(worktree.join("a.txt"), "one\n")?;
+    fs::write(worktree.join("new.txt"), "untracked\n")?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    let error = checkout.switch_trees(Some(&one), &two).unwrap_err();
+    assert!(error.to_string().contains("untracked working tree files"));
+
+    // Unless the checkout is forced.
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.set_force(true);
+    checkout.switch_trees(Some(&one), &two)?;
+    assert_eq!(fs::read_to_string(worktree.join("new.txt"))?, "new\n");
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
+
+#[test]
+fn test_restore() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_restore")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    let odb = GitOdb::open(&repo)?;
+    let one = tree(
+        &odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "one\n"),
+            ("b.txt", GitFileMode::regular(), "b\n"),
+        ],
+    )?;
+    let two = tree(&odb, &[("a.txt", GitFileMode::regular(), "two\n")])?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(None, &one)?;
+    checkout.write_index()?;
+
+    fs::write(worktree.join("a.txt"), "local\n")?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.restore(None, &Pathspec::new(&["a.txt"]), false, true)?;
+    assert_eq!(fs::read_to_string(worktree.join("a.txt"))?, "one\n");
+
+    // Restoring the index leaves the worktree alone; paths missing from the source go away.
+    checkout.restore(Some(&two), &Pathspec::new(&["."]), true, false)?;
+    assert_eq!(checkout.index().entries().len(), 1);
+    assert_eq!(fs::read_to_string(worktree.join("a.txt"))?, "one\n");
+    assert!(worktree.join("b.txt").exists());
+
+    checkout.restore(Some(&one), &Pathspec::new(&["."]), true, false)?;
+    checkout.restore(Some(&two), &Pathspec::new(&["."]), true, true)?;
+    assert_eq!(fs::read_to_string(worktree.join("a.txt"))?, "two\n");
+    assert!(!worktree.join("b.txt").exists());
+
+    assert!(checkout
+        .restore(None, &Pathspec::new(&["missing"]), false, true)
+        .is_err());
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
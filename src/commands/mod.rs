@@ -0,0 +1,1463 @@
+//! The command line parsing of the subcommands, which hands over to [gitrs::subcommands].
+
+#[cfg(test)]
+mod tests;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use regex::bytes::Regex as BytesRegex;
+use regex::{Regex, RegexBuilder};
+
+use gitrs::subcommands::branch_helper::{self, ListOptions};
+use gitrs::subcommands::checkout_helper::{self, Command, SwitchOptions};
+pub use gitrs::subcommands::config_helper::ConfigStatus;
+use gitrs::subcommands::config_helper::{self, ConfigFailure, ConfigOptions, ConfigResult};
+use gitrs::subcommands::plumbing::{self, GraphSource, MergeBaseMode};
+use gitrs::subcommands::{diff_helper, log_helper, mail_helper};
+use gitrs::{
+    AmOptions, ApplyOptions, ColorMoved, CommitGraphOptions, ConfigScope, DateFormat, DiffFormat,
+    DiffOptions, FormatPatchOptions, GitAm, GitApply, GitConfig, GitDiff, GitRepo, GitResult,
+    InitOptions, LogOptions, Pathspec, PrettyFormat, RenameOptions, RevSort, SharedMode,
+    SplitStrategy, TreeRecursion, ValueType, WalkOptions, WordDiff, DEFAULT_ABBREV,
+};
+
+/// Looks up an environment variable.
+type Env<'a> = dyn Fn(&str) -> Option<OsString> + 'a;
+
+/// Rewrites the options clap can not parse before `--`: `-<n>` after `log` or `rev-list` to
+/// `--max-count=<n>`, and `-M<n>` and `-C<n>` after `diff` and the `diff-*` commands to
+/// `--find-renames=<n>` and `--find-copies=<n>`.
+///
+/// The subcommand is the first argument after the program name that is not a global option
+/// or the value of one, so `gitrs -C log diff -M50` is a `diff` in the directory `log`.
+pub fn expand_short_values<I: Iterator<Item = String>>(mut args: I) -> Vec<String> {
+    let mut expanded: Vec<String> = args.next().into_iter().collect();
+    while let Some(arg) = args.next() {
+        let takes_value = matches!(arg.as_str(), "-C" | "-c" | "--git-dir" | "--work-tree");
+        let is_option = arg.starts_with('-') && arg != "-" && arg != "--";
+        expanded.push(arg);
+        if takes_value {
+            expanded.extend(args.next());
+        } else if !is_option {
+            break;
+        }
+    }
+    let subcommand = match expanded.last().map(String::as_str) {
+        Some("log" | "rev-list" | "format-patch") => "log",
+        Some("diff" | "diff-tree" | "diff-index" | "diff-files") => "diff",
+        _ => "",
+    };
+
+    let mut separated = false;
+    expanded.extend(args.map(|arg| {
+        separated |= arg == "--";
+        let is_count = arg.len() > 1
+            && arg.starts_with('-')
+            && arg[1..].bytes().all(|byte| byte.is_ascii_digit());
+        let is_score = arg.len() > 2
+            && (arg.starts_with("-M") || arg.starts_with("-C"))
+            && (arg.as_bytes()[2].is_ascii_digit() || arg.as_bytes()[2] == b'.');
+        match subcommand {
+            _ if separated => arg,
+            "log" if is_count => format!("--max-count={}", &arg[1..]),
+            "diff" if is_score => {
+                let option = if arg.starts_with("-M") {
+                    "--find-renames"
+                } else {
+                    "--find-copies"
+                };
+                format!("{}={}", option, &arg[2..])
+            }
+            _ => arg,
+        }
+    }));
+    expanded
+}
+
+/// Applies the options given before the subcommand: changes to the `-C` directories and
+/// passes `--git-dir`, `--work-tree`, `--bare` and `-c` on through the environment, like git
+/// does.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RepositoryError]: A `-C` directory can not be entered
+/// * [gitrs::GitError::ConfigError]: A `-c` parameter is invalid
+pub fn setup_environment(matches: &ArgMatches) -> GitResult<()> {
+    let cwd = gitrs::to_git_result(env::current_dir(), ".")?;
+    let setup = resolve_environment(matches, &cwd, &|name| env::var_os(name))?;
+    if let Some((arg, dir)) = setup.dir {
+        if let Err(error) = env::set_current_dir(&dir) {
+            return Err(change_dir_error(&arg, &error));
+        }
+    }
+    for (name, value) in setup.vars {
+        env::set_var(name, value);
+    }
+    Ok(())
+}
+
+/// The changes to the process that the options given before the subcommand ask for.
+#[derive(Debug, Default, PartialEq)]
+struct Environment {
+    /// The last `-C` argument and the directory it leads to, if any.
+    dir: Option<(String, PathBuf)>,
+    /// The environment variables to set, in order.
+    vars: Vec<(&'static str, OsString)>,
+}
+
+/// Works out what [setup_environment] changes when started in `cwd` with the variables in
+/// `env`, without changing anything.
+fn resolve_environment(matches: &ArgMatches, cwd: &Path, env: &Env) -> GitResult<Environment> {
+    let mut setup = Environment::default();
+    let mut current = cwd.to_path_buf();
+
+    // Empty paths are ignored, and each path is relative to the previous one.
+    for dir in matches.values_of("C").into_iter().flatten() {
+        if dir.is_empty() {
+            continue;
+        }
+        current = current.join(dir);
+        match fs::metadata(&current) {
+            Ok(metadata) if metadata.is_dir() => (),
+            Ok(_) => {
+                let error = io::Error::other("Not a directory");
+                return Err(change_dir_error(dir, &error));
+            }
+            Err(error) => return Err(change_dir_error(dir, &error)),
+        }
+        setup.dir = Some((dir.to_string(), current.clone()));
+    }
+    if let Some(gitpath) = matches.value_of("git-dir") {
+        setup.vars.push(("GIT_DIR", OsString::from(gitpath)));
+    }
+    if let Some(worktree) = matches.value_of("work-tree") {
+        setup.vars.push(("GIT_WORK_TREE", OsString::from(worktree)));
+    }
+    // A bare repository is the current directory unless told otherwise.
+    if matches.is_present("bare")
+        && matches.value_of("git-dir").is_none()
+        && env("GIT_DIR").is_none()
+    {
+        setup.vars.push(("GIT_DIR", current.into_os_string()));
+    }
+    if let Some(values) = matches.values_of("config") {
+        let mut parameters = env("GIT_CONFIG_PARAMETERS")
+            .and_then(|value| value.into_string().ok())
+            .unwrap_or_default();
+        for parameter in values {
+            parameters = gitrs::add_config_parameter(parameters, parameter)?;
+        }
+        setup
+            .vars
+            .push(("GIT_CONFIG_PARAMETERS", OsString::from(parameters)));
+    }
+    Ok(setup)
+}
+
+fn change_dir_error(dir: &str, error: &io::Error) -> gitrs::GitError {
+    let reason = match error.kind() {
+        ErrorKind::NotFound => "No such file or directory".to_string(),
+        ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        _ => error.to_string(),
+    };
+    gitrs::GitError::RepositoryError {
+        path: PathBuf::from(dir),
+        message: format!("cannot change to '{}': {}", dir, reason),
+    }
+}
+
+/// What `init` advises when a repository starts on `master` because no branch is configured.
+const DEFAULT_BRANCH_ADVICE: &str = concat!(
+    "hint: Using 'master' as the name for the initial branch. This default branch name\n",
+    "hint: is subject to change. To configure the initial branch name to use in all\n",
+    "hint: of your new repositories, which will suppress this warning, call:\n",
+    "hint: \n",
+    "hint: \tgit config --global init.defaultBranch <name>\n",
+    "hint: \n",
+    "hint: Names commonly chosen instead of 'master' are 'main', 'trunk' and\n",
+    "hint: 'development'. The just-created branch can be renamed via this command:\n",
+    "hint: \n",
+    "hint: \tgit branch -m <name>",
+);
+
+/// Initializes a git repository, or reinitializes an existing one.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RepositoryError]: The initial branch name is invalid
+/// * [gitrs::GitError::ConfigError]: The `--shared` value is invalid, or a config file can not
+///   be parsed
+/// * [gitrs::GitError::IOError]: A directory or file can not be created, copied or moved
+#[inline]
+pub fn init(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("init").unwrap();
+    let shared = if sub_m.is_present("shared") {
+        Some(SharedMode::parse(sub_m.value_of("shared"))?)
+    } else {
+        None
+    };
+    let options = InitOptions {
+        directory: sub_m.value_of("directory").map(PathBuf::from),
+        bare: sub_m.is_present("bare") || matches.is_present("bare"),
+        git_dir: env::var_os("GIT_DIR").map(PathBuf::from),
+        work_tree: env::var_os("GIT_WORK_TREE")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from),
+        template: sub_m
+            .value_of("template")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("GIT_TEMPLATE_DIR").map(PathBuf::from)),
+        initial_branch: sub_m.value_of("initial-branch").map(String::from),
+        shared,
+        separate_git_dir: sub_m.value_of("separate-git-dir").map(PathBuf::from),
+    };
+    let report = GitRepo::init_with_report(&options)?;
+    for warning in &report.warnings {
+        eprintln!("{}", warning);
+    }
+    if sub_m.is_present("quiet") {
+        return Ok(());
+    }
+    if report.default_branch {
+        eprintln!("{}", DEFAULT_BRANCH_ADVICE);
+    }
+    let gitpath = report.repo.gitpath();
+    let abs = gitrs::to_git_result(gitpath.canonicalize(), gitpath)?;
+    let shared = match options.shared {
+        Some(shared) if shared != SharedMode::Umask => "shared ",
+        _ => "",
+    };
+    if report.reinitialized {
+        println!(
+            "Reinitialized existing {}Git repository in {}/",
+            shared,
+            abs.display()
+        );
+    } else {
+        println!(
+            "Initialized empty {}Git repository in {}/",
+            shared,
+            abs.display()
+        );
+    }
+    Ok(())
+}
+
+/// Prints which of the paths from command line args are ignored.
+///
+/// Returns true if at least one path is ignored.
+pub fn check_ignore(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("check-ignore").unwrap();
+    let mut paths: Vec<String> = sub_m
+        .values_of("pathname")
+        .map_or(Vec::new(), |values| values.map(String::from).collect());
+    if sub_m.is_present("stdin") {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = gitrs::to_git_result(line, "<stdin>")?;
+            paths.push(line);
+        }
+    }
+
+    plumbing::check_ignore(
+        &open_repo(matches)?,
+        &paths,
+        sub_m.is_present("verbose"),
+        sub_m.is_present("non-matching"),
+        sub_m.is_present("quiet"),
+    )
+}
+
+/// Prints the gitattributes of the paths from command line args.
+pub fn check_attr(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("check-attr").unwrap();
+    let all = sub_m.is_present("all");
+    let mut attrs: Vec<String> = sub_m
+        .values_of("attr")
+        .map_or(Vec::new(), |values| values.map(String::from).collect());
+    let mut paths: Vec<String> = sub_m
+        .values_of("pathname")
+        .map_or(Vec::new(), |values| values.map(String::from).collect());
+
+    // Without '--' only the first argument names an attribute.
+    if !sub_m.is_present("pathname") {
+        paths = if all {
+            std::mem::take(&mut attrs)
+        } else {
+            attrs.drain(1.min(attrs.len())..).collect()
+        };
+    } else if all {
+        paths.splice(0..0, attrs.drain(..));
+    }
+    if sub_m.is_present("stdin") {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            paths.push(gitrs::to_git_result(line, "<stdin>")?);
+        }
+    }
+
+    plumbing::check_attr(&open_repo(matches)?, &attrs, &paths, all)
+}
+
+/// Writes or verifies the commit-graph file.
+///
+/// Returns false if `verify` found a problem.
+pub fn commit_graph(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("commit-graph").unwrap();
+    let repo = open_repo(matches)?;
+    match sub_m.subcommand() {
+        ("write", Some(write_m)) => {
+            let source = if write_m.is_present("reachable") {
+                GraphSource::Reachable
+            } else if write_m.is_present("stdin-commits") {
+                let mut shas = Vec::new();
+                for line in io::stdin().lock().lines() {
+                    let line = gitrs::to_git_result(line, "<stdin>")?;
+                    if !line.trim().is_empty() {
+                        shas.push(line.trim().to_string());
+                    }
+                }
+                GraphSource::Commits(shas)
+            } else {
+                GraphSource::Packs
+            };
+            let split = if write_m.is_present("split") {
+                Some(match write_m.value_of("split") {
+                    None => SplitStrategy::Merge,
+                    Some("no-merge") => SplitStrategy::NoMerge,
+                    Some("replace") => SplitStrategy::Replace,
+                    Some(strategy) => {
+                        return Err(gitrs::GitError::CommitGraphError {
+                            path: PathBuf::new(),
+                            message: format!("unrecognized --split argument, {}", strategy),
+                        })
+                    }
+                })
+            } else {
+                None
+            };
+            let changed_paths = if write_m.is_present("no-changed-paths") {
+                Some(false)
+            } else if write_m.is_present("changed-paths") {
+                Some(true)
+            } else {
+                None
+            };
+            let options = CommitGraphOptions {
+                split,
+                changed_paths,
+                append: write_m.is_present("append"),
+            };
+            plumbing::commit_graph_write(&repo, source, &options)?;
+            Ok(true)
+        }
+        ("verify", Some(verify_m)) => {
+            plumbing::commit_graph_verify(&repo, verify_m.is_present("shallow"))
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Finds the best common ancestors of commits for a merge.
+///
+/// Returns false if there are none, or for `--is-ancestor` and `--fork-point` if the answer is
+/// no.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: A commit is invalid or missing
+/// * [gitrs::GitError::RefError]: The `--fork-point` ref does not exist
+/// * Errors obtained when reading refs, reflogs or objects
+pub fn merge_base(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("merge-base").unwrap();
+    let mode = if sub_m.is_present("octopus") {
+        MergeBaseMode::Octopus
+    } else if sub_m.is_present("independent") {
+        MergeBaseMode::Independent
+    } else if sub_m.is_present("is-ancestor") {
+        MergeBaseMode::IsAncestor
+    } else if sub_m.is_present("fork-point") {
+        MergeBaseMode::ForkPoint
+    } else {
+        MergeBaseMode::Bases
+    };
+    plumbing::merge_base(
+        &open_repo(matches)?,
+        mode,
+        &values(sub_m, "commits"),
+        sub_m.is_present("all"),
+    )
+}
+
+/// Switches branches or restores worktree files, depending on the command line args.
+///
+/// Without `--`, the first argument is a branch or commit if it resolves to one and paths
+/// otherwise.
+pub fn checkout(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("checkout").unwrap();
+    let repo = open_repo(matches)?;
+    let mut args: Vec<String> = values(sub_m, "args");
+    let quiet = sub_m.is_present("quiet");
+
+    let new_branch = match (sub_m.value_of("b"), sub_m.value_of("B")) {
+        (Some(name), _) => Some((name.to_string(), false)),
+        (_, Some(name)) => Some((name.to_string(), true)),
+        _ => None,
+    };
+    let paths = if sub_m.is_present("paths") {
+        Some(values(sub_m, "paths"))
+    } else if args.is_empty() {
+        None
+    } else if checkout_helper::is_revision(&repo, &args[0])? {
+        if args.len() > 1 {
+            Some(args.split_off(1))
+        } else {
+            None
+        }
+    } else if new_branch.is_some() {
+        None
+    } else {
+        Some(std::mem::take(&mut args))
+    };
+
+    match paths {
+        Some(paths) if new_branch.is_none() && !sub_m.is_present("detach") => {
+            let paths = worktree_paths(&repo, &paths)?;
+            let source = args.first().map(String::as_str);
+            checkout_helper::checkout_paths(&repo, source, &paths, quiet)
+        }
+        Some(_) => Err(gitrs::GitError::CheckoutError {
+            message: "fatal: updating paths is incompatible with switching branches.".to_string(),
+        }),
+        None => {
+            let options = SwitchOptions {
+                force: sub_m.is_present("force"),
+                quiet,
+                detach: sub_m.is_present("detach"),
+                new_branch,
+            };
+            let target = args.first().map(String::as_str);
+            checkout_helper::switch(&repo, Command::Checkout, target, &options)
+        }
+    }
+}
+
+/// Switches to a branch or, with `--detach`, to a commit from command line args.
+pub fn switch(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("switch").unwrap();
+    let new_branch = match (sub_m.value_of("create"), sub_m.value_of("force-create")) {
+        (Some(name), _) => Some((name.to_string(), false)),
+        (_, Some(name)) => Some((name.to_string(), true)),
+        _ => None,
+    };
+    let options = SwitchOptions {
+        force: sub_m.is_present("discard-changes"),
+        quiet: sub_m.is_present("quiet"),
+        detach: sub_m.is_present("detach"),
+        new_branch,
+    };
+    checkout_helper::switch(
+        &open_repo(matches)?,
+        Command::Switch,
+        sub_m.value_of("branch"),
+        &options,
+    )
+}
+
+/// Restores worktree files or index entries from command line args.
+pub fn restore(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("restore").unwrap();
+    let repo = open_repo(matches)?;
+    checkout_helper::restore(
+        &repo,
+        sub_m.value_of("source"),
+        &worktree_paths(&repo, &values(sub_m, "pathspec"))?,
+        sub_m.is_present("staged"),
+        sub_m.is_present("worktree"),
+    )
+}
+
+/// Lists, creates, deletes or renames branches, or configures their upstream, from command
+/// line args.
+///
+/// Returns false if a branch could not be deleted.
+pub fn branch(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("branch").unwrap();
+    let repo = open_repo(matches)?;
+    let args = values(sub_m, "args");
+    let arg = |i: usize| args.get(i).map(String::as_str);
+    let quiet = sub_m.is_present("quiet");
+    let force = sub_m.is_present("force");
+    let remotes = sub_m.is_present("remotes");
+    // Filters without a commit default to HEAD.
+    let filter = |name: &str| {
+        if sub_m.is_present(name) {
+            Some(sub_m.value_of(name).unwrap_or("HEAD").to_string())
+        } else {
+            None
+        }
+    };
+
+    if sub_m.is_present("delete") || sub_m.is_present("D") {
+        let force = force || sub_m.is_present("D");
+        return branch_helper::delete(&repo, &args, force, remotes, quiet);
+    }
+    if sub_m.is_present("move") || sub_m.is_present("M") {
+        let force = force || sub_m.is_present("M");
+        match args.len() {
+            1 => branch_helper::rename(&repo, None, &args[0], force)?,
+            2 => branch_helper::rename(&repo, arg(0), &args[1], force)?,
+            _ => {
+                return Err(gitrs::GitError::BranchError {
+                    name: String::new(),
+                    message: "too many arguments for a rename operation".to_string(),
+                })
+            }
+        }
+        return Ok(true);
+    }
+    if let Some(upstream) = sub_m.value_of("set-upstream-to") {
+        branch_helper::set_upstream_to(&repo, upstream, arg(0), quiet)?;
+        return Ok(true);
+    }
+    if sub_m.is_present("unset-upstream") {
+        branch_helper::unset_upstream(&repo, arg(0))?;
+        return Ok(true);
+    }
+    if sub_m.is_present("show-current") {
+        branch_helper::show_current(&repo)?;
+        return Ok(true);
+    }
+
+    let options = ListOptions {
+        patterns: args.clone(),
+        local: !remotes || sub_m.is_present("all"),
+        remotes: remotes || sub_m.is_present("all"),
+        verbose: sub_m.occurrences_of("verbose"),
+        contains: filter("contains"),
+        merged: filter("merged"),
+        no_merged: filter("no-merged"),
+    };
+    let listing = args.is_empty()
+        || sub_m.is_present("list")
+        || options.verbose > 0
+        || sub_m.is_present("all")
+        || remotes
+        || options.contains.is_some()
+        || options.merged.is_some()
+        || options.no_merged.is_some();
+    if listing {
+        branch_helper::list(&repo, &options)?;
+    } else if args.len() > 2 {
+        return Err(gitrs::GitError::BranchError {
+            name: String::new(),
+            message: "too many arguments".to_string(),
+        });
+    } else {
+        branch_helper::create(&repo, &args[0], arg(1), force, quiet)?;
+    }
+    Ok(true)
+}
+
+/// Gets and sets repository or global options, returning how the command ends, such as
+/// [ConfigStatus::NotFound] if a value to get does not exist.
+///
+/// # Errors
+///
+/// Errors come with the status git ends with, such as [ConfigStatus::InvalidPattern]:
+///
+/// * [gitrs::GitError::ConfigError]: The arguments, a name, value or pattern are invalid, or a
+///   config file can not be parsed
+/// * Errors obtained when reading or writing the config files
+pub fn config(matches: &ArgMatches) -> ConfigResult<ConfigStatus> {
+    let sub_m = matches.subcommand_matches("config").unwrap();
+    let repo = open_repo_gently(matches)?;
+    let repo = repo.as_ref();
+    let args = values(sub_m, "args");
+    let arg = |i: usize| args.get(i).map(String::as_str);
+
+    let scope = [
+        ("system", ConfigScope::System),
+        ("global", ConfigScope::Global),
+        ("local", ConfigScope::Local),
+        ("worktree", ConfigScope::Worktree),
+    ]
+    .iter()
+    .find(|(flag, _)| sub_m.is_present(flag))
+    .map(|(_, scope)| *scope);
+    let source = match (sub_m.value_of("file"), scope) {
+        (Some(file), _) => Some((file.into(), ConfigScope::Command)),
+        (None, Some(scope)) => Some((config_helper::scope_path(repo, scope)?, scope)),
+        (None, None) => None,
+    };
+    let value_type = match sub_m.value_of("type") {
+        Some(value_type) => Some(value_type.parse::<ValueType>()?),
+        None => ["bool", "int", "path"]
+            .iter()
+            .find(|flag| sub_m.is_present(flag))
+            .map(|flag| flag.parse::<ValueType>().unwrap()),
+    };
+    let options = ConfigOptions {
+        includes: sub_m.is_present("includes")
+            || (source.is_none() && !sub_m.is_present("no-includes")),
+        source,
+        value_type,
+        fixed_value: sub_m.is_present("fixed-value"),
+        show_origin: sub_m.is_present("show-origin"),
+        show_scope: sub_m.is_present("show-scope"),
+    };
+
+    let check_args = |min: usize, max: usize| {
+        if (min..=max).contains(&args.len()) {
+            return Ok(());
+        }
+        let expected = if min == max {
+            min.to_string()
+        } else {
+            format!("from {} to {}", min, max)
+        };
+        Err(ConfigFailure {
+            status: ConfigStatus::Usage,
+            error: gitrs::GitError::ConfigError {
+                path: Default::default(),
+                message: format!("error: wrong number of arguments, should be {}", expected),
+            },
+        })
+    };
+
+    if sub_m.is_present("list") {
+        check_args(0, 0)?;
+        config_helper::list(repo, &options)?;
+    } else if sub_m.is_present("get") || sub_m.is_present("get-all") {
+        check_args(1, 2)?;
+        let all = sub_m.is_present("get-all");
+        return config_helper::get(repo, &options, &args[0], arg(1), all);
+    } else if sub_m.is_present("get-regexp") {
+        check_args(1, 2)?;
+        return config_helper::get_regexp(repo, &options, &args[0], arg(1));
+    } else if sub_m.is_present("add") {
+        check_args(2, 2)?;
+        config_helper::add(repo, &options, &args[0], &args[1])?;
+    } else if sub_m.is_present("replace-all") {
+        check_args(2, 3)?;
+        config_helper::replace_all(repo, &options, &args[0], &args[1], arg(2))?;
+    } else if sub_m.is_present("unset") || sub_m.is_present("unset-all") {
+        check_args(1, 2)?;
+        let all = sub_m.is_present("unset-all");
+        return config_helper::unset(repo, &options, &args[0], arg(1), all);
+    } else if sub_m.is_present("rename-section") {
+        check_args(2, 2)?;
+        config_helper::rename_section(repo, &options, &args[0], arg(1))?;
+    } else if sub_m.is_present("remove-section") {
+        check_args(1, 1)?;
+        config_helper::rename_section(repo, &options, &args[0], None)?;
+    } else if args.len() == 1 {
+        return config_helper::get(repo, &options, &args[0], None, false);
+    } else {
+        check_args(1, 3)?;
+        config_helper::set(repo, &options, &args[0], &args[1], arg(2))?;
+    }
+    Ok(ConfigStatus::Done)
+}
+
+/// Shows the commit history from command line args.
+///
+/// Without `--`, arguments are revisions up to the first one that is not, and paths from
+/// there on.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::LogError]: An option is invalid, or the branch has no commits yet
+/// * [gitrs::GitError::RevisionError]: A revision is invalid or ambiguous
+/// * Errors obtained when reading refs, objects or the config
+pub fn log(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("log").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let args = values(sub_m, "args");
+    // clap only records `--` when paths follow it.
+    let separated = sub_m.is_present("paths") || env::args().any(|arg| arg == "--");
+    let (revisions, paths) = if separated {
+        (args, values(sub_m, "paths"))
+    } else {
+        log_helper::split_args(&repo, &args)?
+    };
+
+    let format = if sub_m.is_present("oneline") {
+        PrettyFormat::Oneline
+    } else if let Some(format) = sub_m.value_of("format") {
+        // An empty format shows nothing rather than the default.
+        if format.is_empty() {
+            "tformat:".parse()?
+        } else {
+            format.parse()?
+        }
+    } else if sub_m.is_present("pretty") {
+        sub_m.value_of("pretty").unwrap_or("medium").parse()?
+    } else {
+        PrettyFormat::Medium
+    };
+    let date = match sub_m.value_of("date") {
+        Some(date) => date.parse()?,
+        None => match config.get("log.date")? {
+            Some(date) => date.parse()?,
+            None => DateFormat::Default,
+        },
+    };
+    let decorate = if sub_m.is_present("decorate") {
+        Some(sub_m.value_of("decorate").unwrap_or("short"))
+    } else {
+        None
+    };
+    let graph = sub_m.is_present("graph");
+    let options = LogOptions {
+        format,
+        abbrev_commit: sub_m.is_present("abbrev-commit")
+            || sub_m.is_present("oneline")
+            || (!sub_m.is_present("no-abbrev-commit")
+                && config.get_bool("log.abbrevCommit", false)?),
+        date,
+        decorate: log_helper::decorate_style(&repo, decorate, sub_m.is_present("no-decorate"))?,
+        graph,
+        first_parent: sub_m.is_present("first-parent"),
+    };
+
+    if graph && sub_m.is_present("reverse") {
+        return Err(gitrs::GitError::LogError {
+            message: "options '--reverse' and '--graph' cannot be used together".to_string(),
+        });
+    }
+    let walk_options = WalkOptions {
+        rewrite_parents: graph,
+        ..walk_options(&repo, sub_m, &paths, graph)?
+    };
+    let all = sub_m.is_present("all");
+    log_helper::log(&repo, &revisions, all, separated, walk_options, &options)
+}
+
+/// Shows the changes between the worktree, the index and commits, like `git diff`.
+///
+/// Returns false with `--exit-code` or `--quiet` if there are changes.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: A revision is invalid
+/// * [gitrs::GitError::DiffError]: An option has an invalid value
+/// * Errors obtained when reading objects, the index or worktree files
+pub fn diff(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("diff").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let (revisions, paths) = diff_args(&repo, sub_m)?;
+    let options = diff_options(&config, sub_m)?;
+    let format = diff_format(&config, sub_m, false)?;
+    let same = diff_helper::diff(
+        &repo,
+        &revisions,
+        &paths,
+        sub_m.is_present("cached"),
+        rename_options(&config, sub_m, false)?,
+        &options,
+        &format,
+    )?;
+    Ok(same || !(sub_m.is_present("quiet") || sub_m.is_present("exit-code")))
+}
+
+/// Compares two trees, or a commit with its parent, like `git diff-tree`.
+///
+/// Returns false if there are changes and `--exit-code` or `--quiet` was given.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: A revision is invalid
+/// * [gitrs::GitError::DiffError]: An option is invalid, or there are not one or two revisions
+/// * Errors obtained when reading objects or the config
+pub fn diff_tree(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("diff-tree").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let (revisions, paths) = diff_args(&repo, sub_m)?;
+    let options = diff_options(&config, sub_m)?;
+    let format = diff_format(&config, sub_m, true)?;
+
+    let mut diff = GitDiff::new(&repo)?;
+    diff.set_renames(rename_options(&config, sub_m, true)?);
+    // Only lists of names look at subtrees without -r.
+    let recursion = if sub_m.is_present("trees") {
+        TreeRecursion::Trees
+    } else if sub_m.is_present("recursive")
+        || format.patch
+        || format.stat
+        || format.numstat
+        || format.shortstat
+    {
+        TreeRecursion::Files
+    } else {
+        TreeRecursion::Shallow
+    };
+    diff.set_recursion(recursion);
+    let same = diff_helper::diff_tree(
+        &repo,
+        &mut diff,
+        &revisions,
+        &paths,
+        sub_m.is_present("root"),
+        !sub_m.is_present("no-commit-id"),
+        &options,
+        &format,
+    )?;
+    Ok(same || !(sub_m.is_present("quiet") || sub_m.is_present("exit-code")))
+}
+
+/// Compares a tree with the worktree, or with the index with `--cached`, like
+/// `git diff-index`.
+///
+/// Returns false if there are changes and `--exit-code` or `--quiet` was given.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: The tree is invalid
+/// * [gitrs::GitError::DiffError]: An option is invalid, or there is not one tree
+/// * Errors obtained when reading objects, the index, the worktree or the config
+pub fn diff_index(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("diff-index").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let (revisions, paths) = diff_args(&repo, sub_m)?;
+    let mut diff = GitDiff::new(&repo)?;
+    diff.set_renames(rename_options(&config, sub_m, true)?);
+    diff.set_refresh(false);
+    let same = diff_helper::diff_index(
+        &repo,
+        &mut diff,
+        &revisions,
+        &paths,
+        sub_m.is_present("cached"),
+        &diff_options(&config, sub_m)?,
+        &diff_format(&config, sub_m, true)?,
+    )?;
+    Ok(same || !(sub_m.is_present("quiet") || sub_m.is_present("exit-code")))
+}
+
+/// Compares the index with the worktree, like `git diff-files`.
+///
+/// Returns false if there are changes and `--exit-code` or `--quiet` was given.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::DiffError]: An option is invalid, or the repository is bare
+/// * Errors obtained when reading the index, the worktree or the config
+pub fn diff_files(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("diff-files").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let paths = worktree_paths(&repo, &values(sub_m, "paths"))?;
+    let mut diff = GitDiff::new(&repo)?;
+    diff.set_renames(rename_options(&config, sub_m, true)?);
+    diff.set_refresh(false);
+    let same = diff_helper::diff_files(
+        &mut diff,
+        &paths,
+        sub_m.is_present("no-deleted"),
+        &diff_options(&config, sub_m)?,
+        &diff_format(&config, sub_m, true)?,
+    )?;
+    Ok(same || !(sub_m.is_present("quiet") || sub_m.is_present("exit-code")))
+}
+
+/// Applies patches read from files, or from stdin, to the worktree or the index, like
+/// `git apply`.
+///
+/// Returns false if a patch does not apply, or if a three-way merge has conflicts.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::ApplyError]: An option is invalid, a patch can not be read or is
+///   corrupt, or a file has no patches
+/// * Errors obtained when reading or writing files, objects or the index
+pub fn apply(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("apply").unwrap();
+    let repo = open_repo(matches)?;
+    let number = |name: &str| -> GitResult<Option<usize>> {
+        match sub_m.value_of(name) {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(gitrs::GitError::ApplyError {
+                    message: format!("error: switch `{}' expects a numerical value", name),
+                }),
+            },
+            None => Ok(None),
+        }
+    };
+    let strip = number("p")?.unwrap_or(1);
+    let options = ApplyOptions {
+        check: sub_m.is_present("check"),
+        index: sub_m.is_present("index"),
+        cached: sub_m.is_present("cached"),
+        reverse: sub_m.is_present("reverse"),
+        three_way: sub_m.is_present("3way"),
+        min_context: number("C")?,
+        verbose: sub_m.is_present("verbose"),
+    };
+    let mut apply = GitApply::new(&repo, options)?;
+
+    let mut files = values(sub_m, "patches");
+    if files.is_empty() {
+        files.push("-".to_string());
+    }
+    let mut clean = true;
+    for file in files {
+        let data = if file == "-" {
+            let mut data = Vec::new();
+            gitrs::to_git_result(io::stdin().lock().read_to_end(&mut data), "<stdin>")?;
+            data
+        } else {
+            match std::fs::read(&file) {
+                Ok(data) => data,
+                Err(error) => {
+                    let reason = match error.kind() {
+                        ErrorKind::NotFound => "No such file or directory".to_string(),
+                        _ => error.to_string(),
+                    };
+                    return Err(gitrs::GitError::ApplyError {
+                        message: format!("error: can't open patch '{}': {}", file, reason),
+                    });
+                }
+            }
+        };
+        let patches = gitrs::parse_patch(&data, strip)?;
+        let report = apply.apply(&patches)?;
+        for message in &report.messages {
+            eprintln!("{}", message);
+        }
+        for path in &report.conflicts {
+            eprintln!("U {}", path);
+        }
+        clean &= report.is_clean();
+    }
+    Ok(clean)
+}
+
+/// Prepares each commit of a range as a mail holding its message and patch, like
+/// `git format-patch`. The mails are written to numbered files, or printed with `--stdout`.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: A revision is invalid
+/// * [gitrs::GitError::MailError]: A number is invalid
+/// * [gitrs::GitError::IdentityError]: The committer is unknown with `--signoff` or
+///   `--cover-letter`
+/// * Errors obtained when reading objects or the config, or writing the files
+pub fn format_patch(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("format-patch").unwrap();
+    let repo = open_repo(matches)?;
+    let config = repo.config()?;
+    let number = |name: &str| -> GitResult<Option<usize>> {
+        match sub_m.value_of(name) {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(gitrs::GitError::MailError {
+                    message: format!("error: option `{}' expects a numerical value", name),
+                }),
+            },
+            None => Ok(None),
+        }
+    };
+    let numbered = if sub_m.is_present("no-numbered") {
+        Some(false)
+    } else if sub_m.is_present("numbered") {
+        Some(true)
+    } else {
+        None
+    };
+    let options = FormatPatchOptions {
+        numbered,
+        cover_letter: sub_m.is_present("cover-letter"),
+        signoff: sub_m.is_present("signoff"),
+        subject_prefix: sub_m
+            .value_of("subject-prefix")
+            .unwrap_or("PATCH")
+            .to_string(),
+        start_number: number("start-number")?.unwrap_or(1),
+        renames: rename_options(&config, sub_m, false)?,
+    };
+    let commits = mail_helper::patch_commits(
+        &repo,
+        &values(sub_m, "revisions"),
+        number("max-count")?,
+        sub_m.is_present("root"),
+    )?;
+    let mails = gitrs::format_patches(&repo, &commits, &options)?;
+    mail_helper::write_mails(
+        &mails,
+        sub_m.value_of("output-directory"),
+        sub_m.is_present("stdout"),
+        sub_m.is_present("quiet"),
+    )
+}
+
+/// Commits the patches of the mails in mailboxes one after the other, like `git am`, or
+/// resumes, skips or aborts a series that stopped at a patch that did not apply.
+///
+/// Returns false if a patch does not apply, and the series stopped.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::MailError]: A mailbox can not be read, or a series is in progress
+///   already or not at all
+/// * Errors obtained when applying patches, writing commits or updating refs
+pub fn am(matches: &ArgMatches) -> GitResult<bool> {
+    let sub_m = matches.subcommand_matches("am").unwrap();
+    let repo = open_repo(matches)?;
+    let am = GitAm::new(&repo);
+    if sub_m.is_present("show-current-patch") {
+        let diff = sub_m.value_of("show-current-patch") == Some("diff");
+        let patch = am.current_patch(diff)?;
+        let stdout = io::stdout();
+        let result = stdout.lock().write_all(&patch);
+        return gitrs::to_git_result(result, "<stdout>").map(|_| true);
+    }
+    if sub_m.is_present("continue") {
+        return Ok(mail_helper::print_am_report(&am.resolve()?));
+    }
+    if sub_m.is_present("skip") {
+        return Ok(mail_helper::print_am_report(&am.skip()?));
+    }
+    if sub_m.is_present("abort") {
+        if !am.abort()? {
+            eprintln!(
+                "error: You seem to have moved HEAD since the last 'am' failure.\n\
+                 Not rewinding to ORIG_HEAD"
+            );
+        }
+        return Ok(true);
+    }
+
+    let options = AmOptions {
+        three_way: sub_m.is_present("3way"),
+        signoff: sub_m.is_present("signoff"),
+        quiet: sub_m.is_present("quiet"),
+    };
+    let mails: Vec<Vec<u8>> = mail_helper::read_mailboxes(&values(sub_m, "mbox"))?
+        .iter()
+        .flat_map(|mailbox| gitrs::split_mbox(mailbox))
+        .collect();
+    Ok(mail_helper::print_am_report(&am.start(&mails, &options)?))
+}
+
+/// Splits the arguments of `diff`, `diff-tree` or `diff-index` into revisions and worktree
+/// paths. The paths follow the revisions, with or without `--`.
+fn diff_args(repo: &GitRepo, sub_m: &ArgMatches) -> GitResult<(Vec<String>, Vec<String>)> {
+    let args = values(sub_m, "args");
+    let separated = sub_m.is_present("paths") || env::args().any(|arg| arg == "--");
+    let (revisions, paths) = if separated {
+        (args, values(sub_m, "paths"))
+    } else {
+        log_helper::split_args(repo, &args)?
+    };
+    Ok((revisions, worktree_paths(repo, &paths)?))
+}
+
+/// Reads how the lines of files are compared from the options of `diff` and the `diff-*`
+/// commands, or from `config`.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::DiffError]: The algorithm or a number of lines is invalid
+/// * [gitrs::GitError::ConfigError]: A config value is invalid
+fn diff_options(config: &GitConfig, sub_m: &ArgMatches) -> GitResult<DiffOptions> {
+    let algorithm = if sub_m.is_present("minimal") {
+        "minimal".to_string()
+    } else if sub_m.is_present("patience") {
+        "patience".to_string()
+    } else if sub_m.is_present("histogram") {
+        "histogram".to_string()
+    } else if let Some(algorithm) = sub_m.value_of("diff-algorithm") {
+        algorithm.to_string()
+    } else {
+        config
+            .get("diff.algorithm")?
+            .unwrap_or_else(|| "myers".to_string())
+    };
+    Ok(DiffOptions {
+        algorithm: algorithm.parse()?,
+        context: diff_helper::count_option(
+            config,
+            sub_m.value_of("unified"),
+            "-U",
+            "diff.context",
+            3,
+        )?,
+        inter_hunk_context: diff_helper::count_option(
+            config,
+            sub_m.value_of("inter-hunk-context"),
+            "--inter-hunk-context",
+            "diff.interHunkContext",
+            0,
+        )?,
+        ignore_all_space: sub_m.is_present("ignore-all-space"),
+        ignore_space_change: sub_m.is_present("ignore-space-change"),
+        ignore_space_at_eol: sub_m.is_present("ignore-space-at-eol"),
+        ignore_cr_at_eol: sub_m.is_present("ignore-cr-at-eol"),
+        ignore_blank_lines: sub_m.is_present("ignore-blank-lines"),
+        indent_heuristic: if sub_m.is_present("no-indent-heuristic") {
+            false
+        } else {
+            sub_m.is_present("indent-heuristic") || config.get_bool("diff.indentHeuristic", true)?
+        },
+    })
+}
+
+/// Reads what `diff`, or with `plumbing` a `diff-*` command, shows. Plumbing commands show
+/// raw output with full object names by default, and only color it when asked to.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::DiffError]: A width, `--abbrev` or the color is invalid
+/// * [gitrs::GitError::ConfigError]: The color config is invalid
+fn diff_format(config: &GitConfig, sub_m: &ArgMatches, plumbing: bool) -> GitResult<DiffFormat> {
+    if sub_m.is_present("quiet") {
+        return Ok(DiffFormat::default());
+    }
+    let word_diff = match sub_m.value_of("word-diff") {
+        Some("none") => None,
+        Some(mode) => Some(mode.parse::<WordDiff>()?),
+        None if sub_m.is_present("word-diff") => Some(WordDiff::Plain),
+        None if sub_m.is_present("color-words") => Some(WordDiff::Color),
+        None if sub_m.is_present("word-diff-regex") => Some(WordDiff::Plain),
+        None => None,
+    };
+    let word_regex = match sub_m
+        .value_of("word-diff-regex")
+        .or_else(|| sub_m.value_of("color-words"))
+    {
+        Some(regex) => Some(regex.to_string()),
+        None if word_diff.is_some() => config.get("diff.wordRegex")?,
+        None => None,
+    };
+    let word_regex = match word_regex {
+        Some(regex) => Some(
+            BytesRegex::new(&regex).map_err(|_| gitrs::GitError::DiffError {
+                message: format!("invalid regular expression: {}", regex),
+            })?,
+        ),
+        None => None,
+    };
+    let color = if word_diff == Some(WordDiff::Color) {
+        true
+    } else if plumbing && !sub_m.is_present("color") {
+        false
+    } else {
+        diff_helper::use_color(
+            config,
+            sub_m
+                .value_of("color")
+                .or(sub_m.is_present("color").then_some("always")),
+            sub_m.is_present("no-color"),
+        )?
+    };
+    let abbrev = match sub_m.value_of("abbrev") {
+        _ if sub_m.is_present("no-abbrev") => None,
+        Some(abbrev) => Some(
+            abbrev
+                .parse::<usize>()
+                .map_err(|_| gitrs::GitError::DiffError {
+                    message: format!("--abbrev expects a numerical value, got '{}'", abbrev),
+                })?
+                .max(4),
+        ),
+        None if sub_m.is_present("abbrev") || !plumbing => Some(DEFAULT_ABBREV),
+        None => None,
+    };
+    let color_moved = match sub_m.value_of("color-moved") {
+        _ if sub_m.is_present("no-color-moved") => None,
+        Some(mode) => color_moved(mode)?,
+        None if sub_m.is_present("color-moved") => Some(ColorMoved::default()),
+        None if plumbing => None,
+        None => match config.get("diff.colorMoved")? {
+            Some(mode) => color_moved(&mode)?,
+            None => None,
+        },
+    };
+    let mut format = DiffFormat {
+        stat: sub_m.is_present("stat"),
+        numstat: sub_m.is_present("numstat"),
+        shortstat: sub_m.is_present("shortstat"),
+        summary: sub_m.is_present("summary"),
+        name_only: sub_m.is_present("name-only"),
+        name_status: sub_m.is_present("name-status"),
+        raw: sub_m.is_present("raw"),
+        abbrev,
+        null_terminated: sub_m.is_present("z"),
+        color,
+        word_diff,
+        word_regex,
+        color_moved,
+        binary: sub_m.is_present("binary"),
+        ..DiffFormat::default()
+    };
+    if let Some(widths) = sub_m.value_of("stat") {
+        diff_helper::stat_widths(&mut format, widths)?;
+    }
+    let listed = format.stat || format.numstat || format.shortstat || format.summary || format.raw;
+    // The names replace any other output.
+    if format.name_only || format.name_status {
+        format.stat = false;
+        format.numstat = false;
+        format.shortstat = false;
+        format.summary = false;
+        format.raw = false;
+    } else {
+        format.patch = sub_m.is_present("patch") || format.binary || (!listed && !plumbing);
+        format.raw |= plumbing && !listed && !format.patch;
+    }
+    Ok(format)
+}
+
+/// Reads the mode of `--color-moved` or `diff.colorMoved`, where `no` and false turn it off
+/// and true picks the default mode.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::DiffError]: The mode is invalid
+fn color_moved(mode: &str) -> GitResult<Option<ColorMoved>> {
+    match gitrs::parse_bool(Some(mode)) {
+        Some(true) => Ok(Some(ColorMoved::default())),
+        Some(false) => Ok(None),
+        None => mode.parse().map(Some),
+    }
+}
+
+/// Lists the commits reachable from the given revisions, like `git rev-list`.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::RevisionError]: A revision is invalid
+/// * [gitrs::GitError::LogError]: An option has an invalid value
+/// * Errors obtained when reading refs or objects
+pub fn rev_list(matches: &ArgMatches) -> GitResult<()> {
+    let sub_m = matches.subcommand_matches("rev-list").unwrap();
+    let repo = open_repo(matches)?;
+    let args = values(sub_m, "args");
+    let separated = sub_m.is_present("paths") || env::args().any(|arg| arg == "--");
+    let (revisions, paths) = if separated {
+        (args, values(sub_m, "paths"))
+    } else {
+        log_helper::split_args(&repo, &args)?
+    };
+    let walk_options = walk_options(&repo, sub_m, &paths, false)?;
+    log_helper::rev_list(
+        &repo,
+        &revisions,
+        sub_m.is_present("all"),
+        separated,
+        walk_options,
+        sub_m.is_present("count"),
+        sub_m.is_present("objects"),
+    )
+}
+
+/// Reads how renamed and copied files are found from `-M`, `-C`, `--find-copies-harder`,
+/// `--no-renames`, `-l` or the config, where `diff.renames` defaults to finding renames. With
+/// `plumbing`, renames are only found when asked for.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::DiffError]: A similarity or the limit is invalid
+/// * [gitrs::GitError::ConfigError]: `diff.renames` or `diff.renameLimit` is invalid
+fn rename_options(
+    config: &GitConfig,
+    sub_m: &ArgMatches,
+    plumbing: bool,
+) -> GitResult<Option<RenameOptions>> {
+    if sub_m.is_present("no-renames") {
+        return Ok(None);
+    }
+    let find_copies = sub_m.occurrences_of("find-copies");
+    let find_copies_harder = find_copies > 1 || sub_m.is_present("find-copies-harder");
+    let score = if find_copies > 0 {
+        sub_m.values_of("find-copies").and_then(Iterator::last)
+    } else {
+        sub_m.value_of("find-renames")
+    };
+    let copies = if find_copies > 0 || find_copies_harder {
+        true
+    } else if sub_m.is_present("find-renames") {
+        false
+    } else if plumbing {
+        return Ok(None);
+    } else {
+        match config.get("diff.renames")? {
+            Some(value)
+                if value.eq_ignore_ascii_case("copies") || value.eq_ignore_ascii_case("copy") =>
+            {
+                true
+            }
+            _ if config.get_bool("diff.renames", true)? => false,
+            _ => return Ok(None),
+        }
+    };
+    let limit = diff_helper::count_option(
+        config,
+        sub_m.value_of("rename-limit"),
+        "-l",
+        "diff.renameLimit",
+        1000,
+    )?;
+    Ok(Some(RenameOptions {
+        copies,
+        find_copies_harder,
+        min_score: RenameOptions::parse_score(score.unwrap_or(""))?,
+        limit,
+    }))
+}
+
+/// Reads the options limiting and ordering the history shared by `log` and `rev-list`.
+///
+/// # Errors
+///
+/// * [gitrs::GitError::LogError]: The count or a pattern is invalid
+fn walk_options(
+    repo: &GitRepo,
+    sub_m: &ArgMatches,
+    paths: &[String],
+    graph: bool,
+) -> GitResult<WalkOptions> {
+    let max_count = match sub_m.value_of("max-count") {
+        Some(count) => Some(
+            count
+                .parse::<usize>()
+                .map_err(|_| gitrs::GitError::LogError {
+                    message: format!("'{}': not an integer", count),
+                })?,
+        ),
+        None => None,
+    };
+    let sort = if sub_m.is_present("date-order") {
+        RevSort::Date
+    } else if sub_m.is_present("topo-order") || graph {
+        RevSort::Topo
+    } else {
+        RevSort::Default
+    };
+    let now = gitrs::now();
+    let ignore_case = sub_m.is_present("regexp-ignore-case");
+    let patterns = |name: &str| -> GitResult<Vec<Regex>> {
+        values(sub_m, name)
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(ignore_case)
+                    .multi_line(true)
+                    .build()
+                    .map_err(|_| gitrs::GitError::LogError {
+                        message: format!("command line, '{}': invalid regular expression", pattern),
+                    })
+            })
+            .collect()
+    };
+    Ok(WalkOptions {
+        sort,
+        pathspec: Pathspec::new(&worktree_paths(repo, paths)?),
+        max_count,
+        reverse: sub_m.is_present("reverse"),
+        first_parent: sub_m.is_present("first-parent"),
+        ancestry_path: sub_m.is_present("ancestry-path"),
+        since: sub_m
+            .value_of("since")
+            .map(|date| gitrs::approxidate(date, now)),
+        until: sub_m
+            .value_of("until")
+            .map(|date| gitrs::approxidate(date, now)),
+        authors: patterns("author")?,
+        grep: patterns("grep")?,
+        ..WalkOptions::default()
+    })
+}
+
+fn values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map_or(Vec::new(), |values| values.map(String::from).collect())
+}
+
+/// Returns the repository the command runs in, found from the current directory.
+///
+/// # Errors
+///
+/// See [GitRepo::discover].
+fn open_repo(matches: &ArgMatches) -> GitResult<GitRepo> {
+    let mut repo = GitRepo::discover(Path::new("."))?;
+    if matches.is_present("bare") {
+        repo.set_bare();
+    }
+    Ok(repo)
+}
+
+/// Returns the repository the command runs in, or [None] outside of a repository.
+///
+/// # Errors
+///
+/// See [GitRepo::discover].
+fn open_repo_gently(matches: &ArgMatches) -> GitResult<Option<GitRepo>> {
+    let mut repo = GitRepo::discover_gently(Path::new("."))?;
+    if let Some(repo) = repo.as_mut().filter(|_| matches.is_present("bare")) {
+        repo.set_bare();
+    }
+    Ok(repo)
+}
+
+/// Converts paths relative to the current directory to paths relative to the worktree.
+fn worktree_paths(repo: &GitRepo, paths: &[String]) -> GitResult<Vec<String>> {
+    paths.iter().map(|path| repo.worktree_path(path)).collect()
+}
+
+/// Computes and prints the Sha1 hash of an input from command line args.
+pub fn hash_object(matches: &ArgMatches) -> GitResult<()> {
+    let hash = hash_object_utils::from_args(matches)?;
+    println!("{}", hash);
+    Ok(())
+}
+
+mod hash_object_utils {
+    use clap::ArgMatches;
+
+    use super::open_repo_gently;
+    use gitrs::subcommands::plumbing::hash_object as hash_object_helper;
+    use gitrs::{GitConverter, GitResult, SafeCrlf};
+    use std::fs;
+
+    pub fn from_args(matches: &ArgMatches) -> GitResult<String> {
+        let sub_m = matches.subcommand_matches("hash-object").unwrap();
+        let file = sub_m.value_of("file").unwrap();
+        let data = gitrs::to_git_result(fs::read(file), file)?;
+        if sub_m.is_present("no-filters") {
+            return Ok(from_data(&data));
+        }
+
+        // Outside of a repository there are no filters to apply.
+        let repo = match open_repo_gently(matches)? {
+            Some(repo) => repo,
+            None => return Ok(from_data(&data)),
+        };
+        // Hashing without writing never warns about irreversible conversions.
+        let path = repo.worktree_path(sub_m.value_of("path").unwrap_or(file))?;
+        let mut converter = GitConverter::new(&repo)?;
+        converter.set_safe_crlf(SafeCrlf::False);
+        let data = converter.to_git(&path, &data);
+        for warning in converter.take_warnings() {
+            eprintln!("{}", warning);
+        }
+        Ok(from_data(&data?))
+    }
+
+    #[inline]
+    fn from_data(data: &[u8]) -> String {
+        hash_object_helper(data)
+    }
+}
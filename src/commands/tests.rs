@@ -0,0 +1,119 @@
+use super::*;
+use clap::{App, Arg};
+use std::error::Error;
+
+/// Parses the options given before the subcommand, as the `gitrs` binary declares them.
+fn global_options(args: &[&str]) -> ArgMatches<'static> {
+    App::new("gitrs")
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("C")
+                .short("C")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .empty_values(true),
+        )
+        .arg(Arg::with_name("git-dir").long("git-dir").takes_value(true))
+        .arg(
+            Arg::with_name("work-tree")
+                .long("work-tree")
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("bare").long("bare"))
+        .get_matches_from(std::iter::once("gitrs").chain(args.iter().copied()))
+}
+
+fn no_env(_: &str) -> Option<OsString> {
+    None
+}
+
+#[test]
+fn test_resolve_environment() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_resolve_environment");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("a/b"))?;
+
+    // Each -C is relative to the one before, and empty ones are ignored.
+    let matches = global_options(&["-C", "a", "-C", "", "-C", "b"]);
+    let setup = resolve_environment(&matches, &dir, &no_env)?;
+    assert_eq!(setup.dir, Some(("b".to_string(), dir.join("a/b"))));
+    assert!(setup.vars.is_empty());
+
+    let matches = global_options(&["-C", ""]);
+    let setup = resolve_environment(&matches, &dir, &no_env)?;
+    assert_eq!(setup.dir, None);
+
+    let matches = global_options(&["-C", "a", "-C", "a"]);
+    match resolve_environment(&matches, &dir, &no_env) {
+        Err(gitrs::GitError::RepositoryError { message, .. }) => {
+            assert_eq!(message, "cannot change to 'a': No such file or directory")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // --bare without --git-dir uses the directory the -C options lead to.
+    let matches = global_options(&["-C", "a", "--bare"]);
+    let setup = resolve_environment(&matches, &dir, &no_env)?;
+    assert_eq!(setup.vars, [("GIT_DIR", dir.join("a").into_os_string())]);
+
+    let git_dir = |name: &str| match name {
+        "GIT_DIR" => Some(OsString::from("repo.git")),
+        _ => None,
+    };
+    let setup = resolve_environment(&matches, &dir, &git_dir)?;
+    assert!(setup.vars.is_empty());
+
+    let matches = global_options(&["--bare", "--git-dir", "repo.git"]);
+    let setup = resolve_environment(&matches, &dir, &no_env)?;
+    assert_eq!(setup.vars, [("GIT_DIR", OsString::from("repo.git"))]);
+
+    let matches = global_options(&["-c", "a.b=c", "-c", "d.e"]);
+    let setup = resolve_environment(&matches, &dir, &no_env)?;
+    assert_eq!(
+        setup.vars,
+        [("GIT_CONFIG_PARAMETERS", OsString::from("'a.b'='c' 'd.e'"))]
+    );
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_expand_short_values() {
+    let expand = |args: &[&str]| {
+        let args = std::iter::once("gitrs").chain(args.iter().copied());
+        expand_short_values(args.map(String::from))[1..].join(" ")
+    };
+
+    assert_eq!(
+        expand(&["log", "-3", "--", "-4"]),
+        "log --max-count=3 -- -4"
+    );
+    assert_eq!(expand(&["diff", "-M50", "-C"]), "diff --find-renames=50 -C");
+    assert_eq!(expand(&["diff-tree", "-C.5"]), "diff-tree --find-copies=.5");
+    assert_eq!(expand(&["branch", "-3", "-M5"]), "branch -3 -M5");
+
+    // Global options and their values come before the subcommand.
+    assert_eq!(
+        expand(&["-C", "log", "diff", "-M50"]),
+        "-C log diff --find-renames=50"
+    );
+    assert_eq!(
+        expand(&["-c", "diff", "--git-dir", "log", "--bare", "log", "-2"]),
+        "-c diff --git-dir log --bare log --max-count=2"
+    );
+    assert_eq!(
+        expand(&["-Clog", "--work-tree=diff", "rev-list", "-5"]),
+        "-Clog --work-tree=diff rev-list --max-count=5"
+    );
+    // Only the first argument that is not an option is the subcommand.
+    assert_eq!(expand(&["am", "log", "-3"]), "am log -3");
+    assert_eq!(expand(&["-C", "log"]), "-C log");
+}
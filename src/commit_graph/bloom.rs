@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{GitOdb, GitResult, GitTree, GitTreeEntry};
+
+/// The number of hashes set for each path.
+pub const NUM_HASHES: u32 = 7;
+/// The number of bits a filter uses per changed path.
+pub const BITS_PER_ENTRY: u32 = 10;
+/// Commits changing more files than this get a filter that matches every path.
+pub const MAX_CHANGED_PATHS: usize = 512;
+
+const SEED_0: u32 = 0x293a_e76f;
+const SEED_1: u32 = 0x7e64_6e2c;
+
+/// A changed-path Bloom filter: tells for certain that a commit does not change a path, or
+/// that it may change it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    data: Vec<u8>,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Wraps the filter `data` read from a commit-graph using `num_hashes` hashes per path.
+    pub fn from_bytes(data: &[u8], num_hashes: u32) -> Self {
+        Self {
+            data: data.to_vec(),
+            num_hashes,
+        }
+    }
+
+    /// Creates the filter for a commit changing `paths`: the changed files and their leading
+    /// directories.
+    pub fn new<S: AsRef<str>>(paths: &[S]) -> Self {
+        if paths.len() > MAX_CHANGED_PATHS {
+            return Self::from_bytes(&[0xff], NUM_HASHES);
+        }
+        let mut keys = BTreeSet::new();
+        for path in paths {
+            let path = path.as_ref();
+            keys.insert(path);
+            for (i, _) in path.match_indices('/') {
+                keys.insert(&path[..i]);
+            }
+        }
+
+        let len = (keys.len() * BITS_PER_ENTRY as usize).div_ceil(8).max(1);
+        let mut filter = Self::from_bytes(&vec![0; len], NUM_HASHES);
+        for key in keys {
+            for bit in filter.bits(key) {
+                filter.data[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    /// Returns false if the commit certainly does not change `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        self.data.is_empty()
+            || self
+                .bits(path)
+                .into_iter()
+                .all(|bit| self.data[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// The filter as stored in a commit-graph.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the positions of the bits set for `path`.
+    fn bits(&self, path: &str) -> Vec<usize> {
+        let hash0 = murmur3(SEED_0, path.as_bytes());
+        let hash1 = murmur3(SEED_1, path.as_bytes());
+        let len = self.data.len() as u64 * 8;
+        (0..self.num_hashes)
+            .map(|i| {
+                let hash = hash0.wrapping_add(i.wrapping_mul(hash1));
+                (hash as u64 % len) as usize
+            })
+            .collect()
+    }
+}
+
+/// Returns the paths of the files that differ between the trees `old` and `new`, where `None`
+/// is the empty tree.
+///
+/// # Errors
+///
+/// * [GitError::ObjectError]: A tree is missing or corrupt
+///
+/// [GitError::ObjectError]: crate::GitError::ObjectError
+pub fn changed_paths(odb: &GitOdb, old: Option<&str>, new: Option<&str>) -> GitResult<Vec<String>> {
+    let mut paths = Vec::new();
+    diff_trees(odb, old, new, "", &mut paths)?;
+    Ok(paths)
+}
+
+fn diff_trees(
+    odb: &GitOdb,
+    old: Option<&str>,
+    new: Option<&str>,
+    prefix: &str,
+    paths: &mut Vec<String>,
+) -> GitResult<()> {
+    if old == new {
+        return Ok(());
+    }
+    let entries = |sha: Option<&str>| -> GitResult<BTreeMap<String, GitTreeEntry>> {
+        Ok(match sha {
+            Some(sha) => odb
+                .read_as::<GitTree>(sha)?
+                .entries()
+                .iter()
+                .map(|entry| (entry.name().to_string(), entry.clone()))
+                .collect(),
+            None => BTreeMap::new(),
+        })
+    };
+    let mut old = entries(old)?;
+    let new = entries(new)?;
+
+    for (name, new_entry) in new {
+        let old_entry = old.remove(&name);
+        if old_entry.as_ref() == Some(&new_entry) {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        let old_tree = old_entry
+            .as_ref()
+            .filter(|entry| entry.mode().is_tree())
+            .map(|entry| entry.sha());
+        let new_tree = Some(new_entry.sha()).filter(|_| new_entry.mode().is_tree());
+        if old_tree.is_some() || new_tree.is_some() {
+            diff_trees(odb, old_tree, new_tree, &format!("{}/", path), paths)?;
+        }
+        // A file replaced by a directory or the other way around changes both.
+        if !new_entry.mode().is_tree() || old_entry.is_some_and(|entry| !entry.mode().is_tree()) {
+            paths.push(path);
+        }
+    }
+    for (name, old_entry) in old {
+        let path = format!("{}{}", prefix, name);
+        if old_entry.mode().is_tree() {
+            diff_trees(
+                odb,
+                Some(old_entry.sha()),
+                None,
+                &format!("{}/", path),
+                paths,
+            )?;
+        } else {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Version 1 of git's murmur3, which treats bytes as signed chars.
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let byte = |b: u8| b as i8 as i32 as u32;
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k =
+            byte(chunk[0]) | byte(chunk[1]) << 8 | byte(chunk[2]) << 16 | byte(chunk[3]) << 24;
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, &b) in tail.iter().enumerate().rev() {
+            k ^= byte(b) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash
+}
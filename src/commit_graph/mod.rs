@@ -0,0 +1,1028 @@
+//! The commit-graph file in `objects/info`, which stores the parents, trees, dates and
+//! generation numbers of commits so that walking the history does not need to inflate them.
+
+mod bloom;
+#[cfg(test)]
+mod tests;
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+pub use self::bloom::BloomFilter;
+use crate::objects::is_full_sha;
+use crate::{self as gitrs, utils, GitCommit, GitError, GitOdb, GitResult};
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const CHUNK_ENTRY_LEN: usize = 12;
+const HASH_LEN: usize = 20;
+const COMMIT_DATA_LEN: usize = HASH_LEN + 16;
+
+const OID_FANOUT: u32 = 0x4f49_4446; // "OIDF"
+const OID_LOOKUP: u32 = 0x4f49_444c; // "OIDL"
+const COMMIT_DATA: u32 = 0x4344_4154; // "CDAT"
+const GENERATION_DATA: u32 = 0x4744_4132; // "GDA2"
+const GENERATION_OVERFLOW: u32 = 0x4744_4f32; // "GDO2"
+const EXTRA_EDGES: u32 = 0x4544_4745; // "EDGE"
+const BLOOM_INDEXES: u32 = 0x4249_4458; // "BIDX"
+const BLOOM_DATA: u32 = 0x4244_4154; // "BDAT"
+const BASE_GRAPHS: u32 = 0x4241_5345; // "BASE"
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const EDGES_NEEDED: u32 = 0x8000_0000;
+const LAST_EDGE: u32 = 0x8000_0000;
+const LEVEL_MAX: u32 = 0x3fff_ffff;
+const OFFSET_OVERFLOW: u32 = 0x8000_0000;
+const BLOOM_HEADER_LEN: usize = 12;
+
+const OVERFLOW_MISSING: &str = "fatal: commit-graph requires overflow generation data but has none";
+
+/// The commit-graph of an object database: a single file, or a chain of layers where each
+/// layer adds the commits missing from the layers below it.
+#[derive(Debug)]
+pub struct GitCommitGraph {
+    layers: Vec<GraphFile>,
+    /// False if a layer lacks corrected commit dates, so topological levels are used instead.
+    generation_data: bool,
+}
+
+/// A commit as stored in a commit-graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    tree: String,
+    parents: Vec<String>,
+    time: i64,
+    level: u32,
+    generation: u64,
+}
+
+impl GraphCommit {
+    /// The Sha1 hash of the root tree.
+    pub fn tree(&self) -> &str {
+        &self.tree
+    }
+
+    /// The Sha1 hashes of the parents.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    /// The commit date in seconds since the unix epoch.
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// The topological level: one more than the highest level of the parents.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// The generation number: the corrected commit date if every layer stores it, otherwise
+    /// the topological level. A commit's generation is always greater than its parents'.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// How `--split` combines the new commits with the layers of an existing chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SplitStrategy {
+    /// Merge the top layers into the new one while they hold at most twice as many commits.
+    #[default]
+    Merge,
+    /// Always add a new layer.
+    NoMerge,
+    /// Replace the chain with a single layer holding only the written commits.
+    Replace,
+}
+
+/// What [write_commit_graph] writes.
+#[derive(Debug, Clone, Default)]
+pub struct CommitGraphOptions {
+    /// Write a new layer of a commit-graph chain instead of a single file.
+    pub split: Option<SplitStrategy>,
+    /// Whether to store changed-path Bloom filters; by default they are kept if the existing
+    /// commit-graph has them.
+    pub changed_paths: Option<bool>,
+    /// Also write the commits of the existing commit-graph.
+    pub append: bool,
+}
+
+impl GitCommitGraph {
+    /// Reads the commit-graph of the object database in `objects_dir`: `info/commit-graph`,
+    /// or else the chain listed in `info/commit-graphs/commit-graph-chain`. Returns `None` if
+    /// there is neither.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::CommitGraphError]: A graph file is corrupt or the chain is inconsistent
+    /// * [GitError::IOError]: A graph file can not be read
+    pub fn open<P: AsRef<Path>>(objects_dir: P) -> GitResult<Option<Self>> {
+        let info_dir = objects_dir.as_ref().join("info");
+        let single = info_dir.join("commit-graph");
+        if single.is_file() {
+            return Ok(Some(Self::from_layers(vec![GraphFile::open(&single)?])));
+        }
+
+        let graphs_dir = info_dir.join("commit-graphs");
+        let chain = graphs_dir.join("commit-graph-chain");
+        if !chain.is_file() {
+            return Ok(None);
+        }
+        let mut layers: Vec<GraphFile> = Vec::new();
+        for line in utils::read_file(&chain)?.lines() {
+            if !is_full_sha(line) {
+                return Err(graph_error(
+                    &chain,
+                    format!("invalid commit-graph chain: line '{}' not a hash", line),
+                ));
+            }
+            let layer = GraphFile::open(&graphs_dir.join(format!("graph-{}.graph", line)))?;
+            let bases: Vec<String> = layers.iter().map(GraphFile::hash).collect();
+            if layer.base_hashes() != bases {
+                return Err(graph_error(
+                    &layer.path,
+                    "commit-graph base graphs do not match the chain".to_string(),
+                ));
+            }
+            layers.push(layer);
+        }
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self::from_layers(layers)))
+    }
+
+    fn from_layers(layers: Vec<GraphFile>) -> Self {
+        let generation_data = layers
+            .iter()
+            .all(|layer| layer.chunk(GENERATION_DATA).is_some());
+        Self {
+            layers,
+            generation_data,
+        }
+    }
+
+    /// The number of commits in all layers.
+    pub fn num_commits(&self) -> usize {
+        self.layers.iter().map(|layer| layer.num_commits).sum()
+    }
+
+    /// The Sha1 hashes of all commits, layer by layer from the base, each layer sorted.
+    pub fn commits(&self) -> Vec<String> {
+        self.layers
+            .iter()
+            .flat_map(|layer| (0..layer.num_commits).map(move |i| hex::encode(layer.oid(i))))
+            .collect()
+    }
+
+    /// Returns true if the commit `sha` is in the commit-graph.
+    pub fn contains(&self, sha: &str) -> bool {
+        self.position(sha).is_some()
+    }
+
+    /// Returns the commit `sha`, or `None` if it is not in the commit-graph.
+    pub fn lookup(&self, sha: &str) -> Option<GraphCommit> {
+        self.commit_at(self.position(sha)?)
+    }
+
+    /// Returns true if the generation numbers are corrected commit dates rather than
+    /// topological levels.
+    pub fn has_generation_data(&self) -> bool {
+        self.generation_data
+    }
+
+    /// Returns true if any layer stores changed-path Bloom filters.
+    pub fn has_changed_paths(&self) -> bool {
+        self.layers.iter().any(|layer| layer.bloom_hashes.is_some())
+    }
+
+    /// Returns the Bloom filter of the paths the commit `sha` changes compared to its first
+    /// parent, if its layer stores one.
+    pub fn changed_paths(&self, sha: &str) -> Option<BloomFilter> {
+        let (layer, i) = self.locate(self.position(sha)?)?;
+        layer.bloom_filter(i)
+    }
+
+    /// Checks the commit-graph against the object database like `git commit-graph verify`,
+    /// returning a message for each problem found. With `shallow`, only the top layer of a
+    /// chain is checked.
+    ///
+    /// A problem that makes the rest of the commit-graph unreadable ends the check; its
+    /// message is the last one and starts with `fatal: `.
+    pub fn verify(&self, odb: &GitOdb, shallow: bool) -> Vec<String> {
+        let mut errors = Vec::new();
+        for layer in self.layers.iter().rev() {
+            if !self.verify_layer(odb, layer, &mut errors) || shallow {
+                break;
+            }
+        }
+        errors
+    }
+
+    /// Checks one layer, returning false if the check had to stop.
+    fn verify_layer(&self, odb: &GitOdb, layer: &GraphFile, errors: &mut Vec<String>) -> bool {
+        let len = layer.data.len();
+        if Sha1::digest(&layer.data[..len - HASH_LEN]).as_slice() != &layer.data[len - HASH_LEN..] {
+            errors.push(
+                "the commit-graph file has incorrect checksum and is likely corrupt".to_string(),
+            );
+        }
+
+        let checksum_errors = errors.len();
+        let fanout = layer.chunk(OID_FANOUT).unwrap();
+        let mut fanout_pos = 0;
+        for i in 0..layer.num_commits {
+            let oid = layer.oid(i);
+            if i > 0 && layer.oid(i - 1) >= oid {
+                errors.push(format!(
+                    "commit-graph has incorrect OID order: {} then {}",
+                    hex::encode(layer.oid(i - 1)),
+                    hex::encode(oid)
+                ));
+            }
+            while oid[0] as usize > fanout_pos {
+                check_fanout(fanout, fanout_pos, i, errors);
+                fanout_pos += 1;
+            }
+        }
+        while fanout_pos < 256 {
+            check_fanout(fanout, fanout_pos, layer.num_commits, errors);
+            fanout_pos += 1;
+        }
+        if errors.len() > checksum_errors {
+            return true;
+        }
+
+        let mut zero_generations = None;
+        for i in 0..layer.num_commits {
+            let sha = hex::encode(layer.oid(i));
+            let commit = match odb.read_as::<GitCommit>(&sha) {
+                Ok(commit) => commit,
+                Err(_) => {
+                    errors.push(format!("error: Could not read {}", sha));
+                    errors.push(format!(
+                        "failed to parse commit {} from object database for commit-graph",
+                        sha
+                    ));
+                    continue;
+                }
+            };
+            let tree = hex::encode(&layer.commit_data(i)[..HASH_LEN]);
+            if tree != commit.tree() {
+                errors.push(format!(
+                    "root tree OID for commit {} in commit-graph is {} != {}",
+                    sha,
+                    tree,
+                    commit.tree()
+                ));
+            }
+
+            let positions = match layer.parent_positions(i) {
+                Some(positions) => positions,
+                None => {
+                    errors.push("fatal: commit-graph extra-edges list is too short".to_string());
+                    return false;
+                }
+            };
+            let mut max_generation = 0;
+            let mut odb_parents = commit.parents().iter();
+            for &position in &positions {
+                let odb_parent = match odb_parents.next() {
+                    Some(parent) => parent,
+                    None => {
+                        errors.push(format!(
+                            "commit-graph parent list for commit {} is too long",
+                            sha
+                        ));
+                        break;
+                    }
+                };
+                let (parent_layer, j) = match self.locate(position as usize) {
+                    Some(found) => found,
+                    None => {
+                        errors.push(format!("fatal: invalid parent position {}", position));
+                        return false;
+                    }
+                };
+                let parent = hex::encode(parent_layer.oid(j));
+                if &parent != odb_parent {
+                    errors.push(format!(
+                        "commit-graph parent for {} is {} != {}",
+                        sha, parent, odb_parent
+                    ));
+                }
+                match self.generation(parent_layer, j) {
+                    Some(generation) => max_generation = max_generation.max(generation),
+                    None => {
+                        errors.push(OVERFLOW_MISSING.to_string());
+                        return false;
+                    }
+                }
+            }
+            if positions.len() < commit.parents().len() {
+                errors.push(format!(
+                    "commit-graph parent list for commit {} terminates early",
+                    sha
+                ));
+            }
+
+            let generation = match self.generation(layer, i) {
+                Some(generation) => generation,
+                None => {
+                    errors.push(OVERFLOW_MISSING.to_string());
+                    return false;
+                }
+            };
+            if generation == 0 {
+                if zero_generations == Some(false) {
+                    errors.push(format!(
+                        "commit-graph has generation number zero for commit {}, but non-zero \
+                         elsewhere",
+                        sha
+                    ));
+                }
+                zero_generations = Some(true);
+            } else {
+                if zero_generations == Some(true) {
+                    errors.push(format!(
+                        "commit-graph has non-zero generation number for commit {}, but zero \
+                         elsewhere",
+                        sha
+                    ));
+                }
+                zero_generations = Some(false);
+            }
+            if zero_generations == Some(true) {
+                continue;
+            }
+            // A level at the maximum stays there, so its children are not one higher.
+            if !self.generation_data && max_generation == LEVEL_MAX as u64 {
+                max_generation -= 1;
+            }
+            if generation < max_generation + 1 {
+                errors.push(format!(
+                    "commit-graph generation for commit {} is {} < {}",
+                    sha,
+                    generation,
+                    max_generation + 1
+                ));
+            }
+            let time = layer.time(i);
+            if time != commit.committer().time() {
+                errors.push(format!(
+                    "commit date for commit {} in commit-graph is {} != {}",
+                    sha,
+                    time,
+                    commit.committer().time()
+                ));
+            }
+        }
+        true
+    }
+
+    /// Returns the position of `sha` among the commits of all layers.
+    fn position(&self, sha: &str) -> Option<usize> {
+        let mut raw = [0u8; HASH_LEN];
+        hex::decode_to_slice(sha, &mut raw).ok()?;
+        let mut base = 0;
+        for layer in &self.layers {
+            if let Some(i) = layer.find(&raw) {
+                return Some(base + i);
+            }
+            base += layer.num_commits;
+        }
+        None
+    }
+
+    /// Returns the layer holding the commit at `position`, and its index in the layer.
+    fn locate(&self, mut position: usize) -> Option<(&GraphFile, usize)> {
+        for layer in &self.layers {
+            if position < layer.num_commits {
+                return Some((layer, position));
+            }
+            position -= layer.num_commits;
+        }
+        None
+    }
+
+    fn sha_at(&self, position: usize) -> Option<String> {
+        let (layer, i) = self.locate(position)?;
+        Some(hex::encode(layer.oid(i)))
+    }
+
+    fn commit_at(&self, position: usize) -> Option<GraphCommit> {
+        let (layer, i) = self.locate(position)?;
+        let parents = layer
+            .parent_positions(i)?
+            .into_iter()
+            .map(|position| self.sha_at(position as usize))
+            .collect::<Option<Vec<String>>>()?;
+        Some(GraphCommit {
+            tree: hex::encode(&layer.commit_data(i)[..HASH_LEN]),
+            parents,
+            time: layer.time(i),
+            level: layer.level(i),
+            generation: self.generation(layer, i)?,
+        })
+    }
+
+    /// Returns the generation number of the `i`th commit of `layer`, or `None` if its corrected
+    /// commit date is missing.
+    fn generation(&self, layer: &GraphFile, i: usize) -> Option<u64> {
+        if !self.generation_data {
+            return Some(layer.level(i) as u64);
+        }
+        let offset = layer.date_offset(i)?;
+        Some((layer.time(i) as u64).wrapping_add(offset))
+    }
+}
+
+fn check_fanout(fanout: &[u8], pos: usize, expected: usize, errors: &mut Vec<String>) {
+    let value = be32(&fanout[pos * 4..]);
+    if value as usize != expected {
+        errors.push(format!(
+            "commit-graph has incorrect fanout value: fanout[{}] = {} != {}",
+            pos, value, expected
+        ));
+    }
+}
+
+/// A single commit-graph file: the whole graph, or one layer of a chain.
+#[derive(Debug)]
+struct GraphFile {
+    path: PathBuf,
+    data: Vec<u8>,
+    num_commits: usize,
+    /// The start and end of each chunk in `data`.
+    chunks: HashMap<u32, (usize, usize)>,
+    /// The number of hashes per path of the Bloom filters, if the file stores them.
+    bloom_hashes: Option<u32>,
+}
+
+impl GraphFile {
+    fn open(path: &Path) -> GitResult<Self> {
+        let data = utils::read_bytes(path)?;
+        let corrupt = |message: String| Err(graph_error(path, message));
+        if data.len() < HEADER_LEN + CHUNK_ENTRY_LEN + HASH_LEN {
+            return corrupt("commit-graph file is too small".to_string());
+        }
+        if &data[..4] != SIGNATURE {
+            return corrupt(format!(
+                "commit-graph signature {:X} does not match signature {:X}",
+                be32(&data),
+                be32(SIGNATURE)
+            ));
+        }
+        if data[4] != 1 {
+            return corrupt(format!(
+                "commit-graph version {:X} does not match version 1",
+                data[4]
+            ));
+        }
+        if data[5] != 1 {
+            return corrupt(format!(
+                "commit-graph hash version {:X} does not match version 1",
+                data[5]
+            ));
+        }
+
+        let num_chunks = data[6] as usize;
+        let table_end = HEADER_LEN + (num_chunks + 1) * CHUNK_ENTRY_LEN;
+        if data.len() < table_end + HASH_LEN {
+            return corrupt("commit-graph file is too small to hold its chunks".to_string());
+        }
+        let entry = |i: usize| {
+            let start = HEADER_LEN + i * CHUNK_ENTRY_LEN;
+            let offset = u64::from_be_bytes(data[start + 4..start + 12].try_into().unwrap());
+            (be32(&data[start..]), offset as usize)
+        };
+        let mut chunks = HashMap::new();
+        for i in 0..num_chunks {
+            let (id, start) = entry(i);
+            let (_, end) = entry(i + 1);
+            if start < table_end || start > end || end > data.len() - HASH_LEN {
+                return corrupt(format!(
+                    "improper chunk offset(s) {:x} and {:x}",
+                    start, end
+                ));
+            }
+            chunks.insert(id, (start, end));
+        }
+
+        let chunk_len = |id: u32| chunks.get(&id).map(|(start, end)| end - start);
+        if chunk_len(OID_FANOUT) != Some(256 * 4) {
+            return corrupt("commit-graph required OID fanout chunk missing or corrupted".into());
+        }
+        let (fanout, _) = chunks[&OID_FANOUT];
+        let num_commits = be32(&data[fanout + 255 * 4..]) as usize;
+        if chunk_len(OID_LOOKUP).is_none_or(|len| len < num_commits * HASH_LEN) {
+            return corrupt("commit-graph required OID lookup chunk missing or corrupted".into());
+        }
+        if chunk_len(COMMIT_DATA).is_none_or(|len| len < num_commits * COMMIT_DATA_LEN) {
+            return corrupt("commit-graph required commit data chunk missing or corrupted".into());
+        }
+        if chunk_len(GENERATION_DATA).is_some_and(|len| len < num_commits * 4) {
+            return corrupt("commit-graph generations chunk is too small".to_string());
+        }
+        if chunk_len(BASE_GRAPHS).unwrap_or(0) != data[7] as usize * HASH_LEN {
+            return corrupt("commit-graph base graphs chunk is too small".to_string());
+        }
+
+        let bloom_hashes = match (chunk_len(BLOOM_INDEXES), chunks.get(&BLOOM_DATA)) {
+            (Some(len), Some(&(start, end)))
+                if len >= num_commits * 4 && end - start >= BLOOM_HEADER_LEN =>
+            {
+                Some(be32(&data[start + 4..]))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            data,
+            num_commits,
+            chunks,
+            bloom_hashes,
+        })
+    }
+
+    fn chunk(&self, id: u32) -> Option<&[u8]> {
+        self.chunks
+            .get(&id)
+            .map(|&(start, end)| &self.data[start..end])
+    }
+
+    /// The checksum at the end of the file, which names it in a chain.
+    fn hash(&self) -> String {
+        hex::encode(&self.data[self.data.len() - HASH_LEN..])
+    }
+
+    fn base_hashes(&self) -> Vec<String> {
+        self.chunk(BASE_GRAPHS)
+            .unwrap_or_default()
+            .chunks(HASH_LEN)
+            .map(hex::encode)
+            .collect()
+    }
+
+    fn oid(&self, i: usize) -> &[u8] {
+        &self.chunk(OID_LOOKUP).unwrap()[i * HASH_LEN..(i + 1) * HASH_LEN]
+    }
+
+    fn commit_data(&self, i: usize) -> &[u8] {
+        &self.chunk(COMMIT_DATA).unwrap()[i * COMMIT_DATA_LEN..(i + 1) * COMMIT_DATA_LEN]
+    }
+
+    fn level(&self, i: usize) -> u32 {
+        be32(&self.commit_data(i)[HASH_LEN + 8..]) >> 2
+    }
+
+    fn time(&self, i: usize) -> i64 {
+        let data = self.commit_data(i);
+        let high = (be32(&data[HASH_LEN + 8..]) & 0x3) as i64;
+        high << 32 | be32(&data[HASH_LEN + 12..]) as i64
+    }
+
+    /// The offset of the corrected commit date from the commit date.
+    fn date_offset(&self, i: usize) -> Option<u64> {
+        let offset = be32(&self.chunk(GENERATION_DATA)?[i * 4..]);
+        if offset & OFFSET_OVERFLOW == 0 {
+            return Some(offset as u64);
+        }
+        let index = (offset ^ OFFSET_OVERFLOW) as usize * 8;
+        let overflow = self.chunk(GENERATION_OVERFLOW)?.get(index..index + 8)?;
+        Some(u64::from_be_bytes(overflow.try_into().unwrap()))
+    }
+
+    /// Returns the positions of the parents of the `i`th commit in the whole chain, or `None`
+    /// if the extra edges of an octopus merge are missing.
+    fn parent_positions(&self, i: usize) -> Option<Vec<u32>> {
+        let data = self.commit_data(i);
+        let (first, second) = (be32(&data[HASH_LEN..]), be32(&data[HASH_LEN + 4..]));
+        let mut parents = Vec::new();
+        if first == PARENT_NONE {
+            return Some(parents);
+        }
+        parents.push(first);
+        if second == PARENT_NONE {
+            return Some(parents);
+        }
+        if second & EDGES_NEEDED == 0 {
+            parents.push(second);
+            return Some(parents);
+        }
+        let edges = self.chunk(EXTRA_EDGES)?;
+        let mut start = (second ^ EDGES_NEEDED) as usize * 4;
+        loop {
+            let edge = be32(edges.get(start..start + 4)?);
+            parents.push(edge & !LAST_EDGE);
+            if edge & LAST_EDGE != 0 {
+                return Some(parents);
+            }
+            start += 4;
+        }
+    }
+
+    fn bloom_filter(&self, i: usize) -> Option<BloomFilter> {
+        let num_hashes = self.bloom_hashes?;
+        let indexes = self.chunk(BLOOM_INDEXES)?;
+        let filters = &self.chunk(BLOOM_DATA)?[BLOOM_HEADER_LEN..];
+        let start = if i == 0 {
+            0
+        } else {
+            be32(&indexes[(i - 1) * 4..]) as usize
+        };
+        let end = be32(&indexes[i * 4..]) as usize;
+        Some(BloomFilter::from_bytes(
+            filters.get(start..end)?,
+            num_hashes,
+        ))
+    }
+
+    /// Returns the index of the commit `raw` in this file.
+    fn find(&self, raw: &[u8]) -> Option<usize> {
+        let fanout = self.chunk(OID_FANOUT)?;
+        let first = raw[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            be32(&fanout[(first - 1) * 4..]) as usize
+        };
+        let end = (be32(&fanout[first * 4..]) as usize).min(self.num_commits);
+        if start > end {
+            return None;
+        }
+        let lookup = self.chunk(OID_LOOKUP)?;
+        let shas: Vec<&[u8]> = lookup[start * HASH_LEN..end * HASH_LEN]
+            .chunks(HASH_LEN)
+            .collect();
+        shas.binary_search(&raw).ok().map(|i| start + i)
+    }
+}
+
+/// A commit to be written, with the values computed for it.
+struct NewCommit {
+    tree: String,
+    parents: Vec<String>,
+    time: i64,
+}
+
+/// Writes a commit-graph holding `commits` and all of their ancestors to the object database,
+/// like `git commit-graph write`.
+///
+/// Without a split strategy, the whole graph is written to `info/commit-graph` and any chain
+/// is removed. With one, a layer of the commits missing from the chain is added to
+/// `info/commit-graphs`, merging it with the layers above the ones it keeps. Nothing is written
+/// if there are no commits to add.
+///
+/// # Errors
+///
+/// * [GitError::ObjectError]: A commit or tree is missing or corrupt
+/// * [GitError::CommitGraphError]: A commit to be kept is missing from the existing chain
+/// * [GitError::LockError]: The commit-graph or the chain is being written by another process
+/// * [GitError::IOError]: A graph file can not be written
+pub fn write_commit_graph(
+    odb: &GitOdb,
+    commits: &[String],
+    options: &CommitGraphOptions,
+) -> GitResult<()> {
+    // A corrupt commit-graph is replaced instead of being built upon.
+    let existing = GitCommitGraph::open(odb.objects_dir()).unwrap_or(None);
+    let layers = existing
+        .as_ref()
+        .map_or(&[][..], |graph| graph.layers.as_slice());
+    let changed_paths = options.changed_paths.unwrap_or_else(|| {
+        existing
+            .as_ref()
+            .is_some_and(|graph| graph.has_changed_paths())
+    });
+
+    // A new layer only holds the commits missing from the existing ones, unless it replaces
+    // them.
+    let in_graph = |sha: &str| {
+        matches!(
+            options.split,
+            Some(SplitStrategy::Merge) | Some(SplitStrategy::NoMerge)
+        ) && existing.as_ref().is_some_and(|graph| graph.contains(sha))
+    };
+    let mut new_commits: HashMap<String, NewCommit> = HashMap::new();
+    let mut stack = commits.to_vec();
+    if options.append {
+        stack.extend(existing.iter().flat_map(GitCommitGraph::commits));
+    }
+    while let Some(sha) = stack.pop() {
+        if new_commits.contains_key(&sha) || in_graph(&sha) {
+            continue;
+        }
+        let commit = read_commit(odb, existing.as_ref(), &sha)?;
+        stack.extend(commit.parents.iter().cloned());
+        new_commits.insert(sha, commit);
+    }
+
+    let keep = match options.split {
+        None | Some(SplitStrategy::Replace) => 0,
+        Some(SplitStrategy::NoMerge) => layers.len(),
+        Some(SplitStrategy::Merge) => {
+            let mut keep = layers.len();
+            let mut count = new_commits.len();
+            while keep > 0 && layers[keep - 1].num_commits <= 2 * count {
+                count += layers[keep - 1].num_commits;
+                keep -= 1;
+            }
+            keep
+        }
+    };
+    if matches!(
+        options.split,
+        Some(SplitStrategy::Merge) | Some(SplitStrategy::NoMerge)
+    ) {
+        for layer in &layers[keep..] {
+            for i in 0..layer.num_commits {
+                let sha = hex::encode(layer.oid(i));
+                let commit = read_commit(odb, existing.as_ref(), &sha)?;
+                new_commits.insert(sha, commit);
+            }
+        }
+    }
+    if new_commits.is_empty() && options.split != Some(SplitStrategy::Replace) {
+        return Ok(());
+    }
+
+    let bases = &layers[..keep];
+    let base_count: usize = bases.iter().map(|layer| layer.num_commits).sum();
+    let base_graph = existing.as_ref().filter(|_| keep > 0);
+    let base_commit = |sha: &str| -> GitResult<(u32, GraphCommit)> {
+        base_graph
+            .and_then(|graph| Some((graph.position(sha)? as u32, graph.lookup(sha)?)))
+            .filter(|(position, _)| (*position as usize) < base_count)
+            .ok_or_else(|| {
+                graph_error(
+                    odb.objects_dir(),
+                    format!("commit {} is missing from the commit-graph", sha),
+                )
+            })
+    };
+
+    let mut shas: Vec<&String> = new_commits.keys().collect();
+    shas.sort();
+    let positions: HashMap<&str, u32> = shas
+        .iter()
+        .enumerate()
+        .map(|(i, sha)| (sha.as_str(), (base_count + i) as u32))
+        .collect();
+    let position = |sha: &str| match positions.get(sha) {
+        Some(&position) => Ok(position),
+        None => base_commit(sha).map(|(position, _)| position),
+    };
+
+    // Generation numbers need those of the parents first, which may be deep in the history.
+    let mut generations: HashMap<&str, (u32, u64)> = HashMap::new();
+    for &sha in &shas {
+        let mut pending = vec![sha.as_str()];
+        while let Some(&current) = pending.last() {
+            if generations.contains_key(current) {
+                pending.pop();
+                continue;
+            }
+            let commit = &new_commits[current];
+            let (mut max_level, mut max_date) = (0, 0);
+            let mut ready = true;
+            for parent in &commit.parents {
+                let (level, date) = match generations.get(parent.as_str()) {
+                    Some(&generation) => generation,
+                    None if new_commits.contains_key(parent) => {
+                        pending.push(parent);
+                        ready = false;
+                        continue;
+                    }
+                    None => {
+                        let (_, parent) = base_commit(parent)?;
+                        (parent.level(), parent.generation())
+                    }
+                };
+                max_level = max_level.max(level);
+                max_date = max_date.max(date);
+            }
+            if ready {
+                if commit.time != 0 && commit.time as u64 > max_date {
+                    max_date = commit.time as u64 - 1;
+                }
+                generations.insert(current, (max_level.min(LEVEL_MAX - 1) + 1, max_date + 1));
+                pending.pop();
+            }
+        }
+    }
+
+    let mut fanout = vec![0u8; 256 * 4];
+    let mut lookup = Vec::with_capacity(shas.len() * HASH_LEN);
+    let mut commit_data = Vec::with_capacity(shas.len() * COMMIT_DATA_LEN);
+    let mut generation_data = Vec::with_capacity(shas.len() * 4);
+    let mut generation_overflow = Vec::new();
+    let mut extra_edges = Vec::new();
+    let mut bloom_indexes = Vec::new();
+    let mut bloom_data = Vec::new();
+    for &sha in &shas {
+        let raw = hex::decode(sha).unwrap();
+        lookup.extend_from_slice(&raw);
+        let commit = &new_commits[sha];
+        commit_data.extend_from_slice(&hex::decode(&commit.tree).unwrap());
+
+        let parents = commit
+            .parents
+            .iter()
+            .map(|parent| position(parent))
+            .collect::<GitResult<Vec<u32>>>()?;
+        let (first, second) = match parents.as_slice() {
+            [] => (PARENT_NONE, PARENT_NONE),
+            [first] => (*first, PARENT_NONE),
+            [first, second] => (*first, *second),
+            [first, rest @ ..] => {
+                let second = EDGES_NEEDED | (extra_edges.len() / 4) as u32;
+                for (i, parent) in rest.iter().enumerate() {
+                    let last = if i == rest.len() - 1 { LAST_EDGE } else { 0 };
+                    extra_edges.extend_from_slice(&(parent | last).to_be_bytes());
+                }
+                (*first, second)
+            }
+        };
+        commit_data.extend_from_slice(&first.to_be_bytes());
+        commit_data.extend_from_slice(&second.to_be_bytes());
+        let (level, corrected_date) = generations[sha.as_str()];
+        let time = commit.time as u64;
+        commit_data.extend_from_slice(&((level << 2) | (time >> 32 & 0x3) as u32).to_be_bytes());
+        commit_data.extend_from_slice(&(time as u32).to_be_bytes());
+
+        let offset = corrected_date.wrapping_sub(time);
+        if offset > i32::MAX as u64 {
+            let index = (generation_overflow.len() / 8) as u32;
+            generation_data.extend_from_slice(&(OFFSET_OVERFLOW | index).to_be_bytes());
+            generation_overflow.extend_from_slice(&offset.to_be_bytes());
+        } else {
+            generation_data.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+
+        if changed_paths {
+            let parent_tree = match commit.parents.first() {
+                Some(parent) => Some(match new_commits.get(parent) {
+                    Some(parent) => parent.tree.clone(),
+                    None => base_commit(parent)?.1.tree,
+                }),
+                None => None,
+            };
+            let paths = bloom::changed_paths(odb, parent_tree.as_deref(), Some(&commit.tree))?;
+            bloom_data.extend_from_slice(BloomFilter::new(&paths).as_bytes());
+            bloom_indexes.extend_from_slice(&(bloom_data.len() as u32).to_be_bytes());
+        }
+    }
+    let mut count = 0;
+    for (byte, chunk) in fanout.chunks_mut(4).enumerate() {
+        while count < shas.len() && usize::from_str_radix(&shas[count][..2], 16).unwrap() <= byte {
+            count += 1;
+        }
+        chunk.copy_from_slice(&(count as u32).to_be_bytes());
+    }
+
+    let mut chunks = vec![
+        (OID_FANOUT, fanout),
+        (OID_LOOKUP, lookup),
+        (COMMIT_DATA, commit_data),
+    ];
+    // Corrected dates can only be used if the layers below store them too.
+    if bases
+        .iter()
+        .all(|layer| layer.chunk(GENERATION_DATA).is_some())
+    {
+        chunks.push((GENERATION_DATA, generation_data));
+        if !generation_overflow.is_empty() {
+            chunks.push((GENERATION_OVERFLOW, generation_overflow));
+        }
+    }
+    if !extra_edges.is_empty() {
+        chunks.push((EXTRA_EDGES, extra_edges));
+    }
+    if changed_paths {
+        let mut data = Vec::with_capacity(BLOOM_HEADER_LEN + bloom_data.len());
+        for value in &[1, bloom::NUM_HASHES, bloom::BITS_PER_ENTRY] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&bloom_data);
+        chunks.push((BLOOM_INDEXES, bloom_indexes));
+        chunks.push((BLOOM_DATA, data));
+    }
+    if keep > 0 {
+        let hashes = bases
+            .iter()
+            .flat_map(|layer| hex::decode(layer.hash()).unwrap())
+            .collect();
+        chunks.push((BASE_GRAPHS, hashes));
+    }
+
+    let mut data = SIGNATURE.to_vec();
+    data.extend_from_slice(&[1, 1, chunks.len() as u8, keep as u8]);
+    let mut offset = (HEADER_LEN + (chunks.len() + 1) * CHUNK_ENTRY_LEN) as u64;
+    for (id, chunk) in &chunks {
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    data.extend_from_slice(&0u32.to_be_bytes());
+    data.extend_from_slice(&offset.to_be_bytes());
+    for (_, chunk) in &chunks {
+        data.extend_from_slice(chunk);
+    }
+    let checksum = Sha1::digest(&data);
+    data.extend_from_slice(&checksum);
+
+    let info_dir = odb.objects_dir().join("info");
+    let graphs_dir = info_dir.join("commit-graphs");
+    let single = info_dir.join("commit-graph");
+    utils::create_dir_all_if_new(&info_dir)?;
+    let mut chain: Vec<String> = bases.iter().map(GraphFile::hash).collect();
+    if options.split.is_some() {
+        utils::create_dir_all_if_new(&graphs_dir)?;
+        let hash = hex::encode(checksum);
+        write_graph_file(&graphs_dir.join(format!("graph-{}.graph", hash)), &data)?;
+        // A single commit-graph file that is kept becomes the base of the chain.
+        if let Some(base) = bases.iter().find(|layer| layer.path == single) {
+            let path = graphs_dir.join(format!("graph-{}.graph", base.hash()));
+            gitrs::to_git_result(fs::rename(&single, &path), &path)?;
+        }
+        chain.push(hash);
+        let mut list = chain.join("\n");
+        list.push('\n');
+        utils::write_locked(graphs_dir.join("commit-graph-chain"), list.as_bytes())?;
+        if single.is_file() {
+            gitrs::to_git_result(fs::remove_file(&single), &single)?;
+        }
+    } else {
+        write_graph_file(&single, &data)?;
+        let chain_path = graphs_dir.join("commit-graph-chain");
+        if chain_path.is_file() {
+            gitrs::to_git_result(fs::remove_file(&chain_path), &chain_path)?;
+        }
+    }
+    expire_graph_files(&graphs_dir, &chain)
+}
+
+/// Reads the parts of the commit `sha` stored in a commit-graph, preferring the existing one.
+fn read_commit(odb: &GitOdb, existing: Option<&GitCommitGraph>, sha: &str) -> GitResult<NewCommit> {
+    if let Some(commit) = existing.and_then(|graph| graph.lookup(sha)) {
+        return Ok(NewCommit {
+            tree: commit.tree,
+            parents: commit.parents,
+            time: commit.time,
+        });
+    }
+    let commit = odb.read_as::<GitCommit>(sha)?;
+    Ok(NewCommit {
+        tree: commit.tree().to_string(),
+        parents: commit.parents().to_vec(),
+        time: commit.committer().time(),
+    })
+}
+
+/// Writes a graph file through a lock file and makes it read-only, like git does.
+fn write_graph_file(path: &Path, data: &[u8]) -> GitResult<()> {
+    utils::write_locked(path, data)?;
+    gitrs::to_git_result(
+        fs::set_permissions(path, Permissions::from_mode(0o444)),
+        path,
+    )
+}
+
+/// Removes the layers in `graphs_dir` that are not part of the chain anymore.
+fn expire_graph_files(graphs_dir: &Path, chain: &[String]) -> GitResult<()> {
+    if !graphs_dir.is_dir() {
+        return Ok(());
+    }
+    let keep: HashSet<String> = chain
+        .iter()
+        .map(|hash| format!("graph-{}.graph", hash))
+        .collect();
+    for entry in gitrs::to_git_result(fs::read_dir(graphs_dir), graphs_dir)? {
+        let entry = gitrs::to_git_result(entry, graphs_dir)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("graph-") && name.ends_with(".graph") && !keep.contains(&name) {
+            let path = entry.path();
+            gitrs::to_git_result(fs::remove_file(&path), &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn graph_error(path: &Path, message: String) -> GitError {
+    GitError::CommitGraphError {
+        path: path.to_path_buf(),
+        message,
+    }
+}
+
+fn be32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
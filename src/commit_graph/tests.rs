@@ -0,0 +1,177 @@
+use super::*;
+use crate::test_utils::{self, tree};
+use crate::{GitFileMode, GitSignature};
+use std::error::Error;
+
+/// Writes a commit made at `time` whose tree holds `files`, each in `dir`.
+fn commit(odb: &GitOdb, files: &[&str], time: i64, parents: &[&str]) -> GitResult<String> {
+    let paths: Vec<String> = files.iter().map(|file| format!("dir/{}", file)).collect();
+    let files: Vec<(&str, GitFileMode, &str)> = paths
+        .iter()
+        .zip(files)
+        .map(|(path, file)| (path.as_str(), GitFileMode::regular(), *file))
+        .collect();
+    let tree = tree(odb, &files)?;
+    let signature = GitSignature::new("A U Thor", "author@example.com", time, 0);
+    test_utils::commit(
+        odb,
+        &tree,
+        parents,
+        signature.clone(),
+        signature,
+        "commit\n",
+    )
+}
+
+#[test]
+fn test_write_commit_graph() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_write_commit_graph")?;
+    let objects_dir = repo.gitpath().join("objects");
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, &["a"], 1000, &[])?;
+    let second = commit(&odb, &["a", "b"], 2000, &[&first])?;
+    let third = commit(&odb, &["a", "c"], 3000, &[&first])?;
+    // Made before its parents, so its corrected date is bumped above theirs.
+    let fourth = commit(&odb, &["d"], 500, &[&first])?;
+    let octopus = commit(
+        &odb,
+        &["a", "b", "c", "d"],
+        4000,
+        &[&second, &third, &fourth],
+    )?;
+
+    assert!(GitCommitGraph::open(&objects_dir)?.is_none());
+    let options = CommitGraphOptions {
+        changed_paths: Some(true),
+        ..CommitGraphOptions::default()
+    };
+    write_commit_graph(&odb, std::slice::from_ref(&octopus), &options)?;
+    let graph = GitCommitGraph::open(&objects_dir)?.unwrap();
+    assert_eq!(graph.num_commits(), 5);
+    assert!(graph.contains(&second));
+    assert!(!graph.contains(&"0".repeat(40)));
+    assert!(graph.verify(&odb, false).is_empty());
+
+    let merge = graph.lookup(&octopus).unwrap();
+    assert_eq!(
+        merge.parents(),
+        [second.clone(), third.clone(), fourth.clone()]
+    );
+    assert_eq!(merge.tree(), odb.read_as::<GitCommit>(&octopus)?.tree());
+    assert_eq!(merge.time(), 4000);
+    assert_eq!(merge.level(), 3);
+    assert_eq!(graph.lookup(&first).unwrap().generation(), 1000);
+    assert_eq!(graph.lookup(&fourth).unwrap().generation(), 1001);
+    assert_eq!(merge.generation(), 4000);
+
+    assert!(graph.has_changed_paths());
+    let filter = graph.changed_paths(&third).unwrap();
+    assert!(filter.contains("dir"));
+    assert!(filter.contains("dir/c"));
+    assert_eq!(
+        graph.changed_paths(&first).unwrap(),
+        BloomFilter::new(&["dir/a"])
+    );
+
+    let path = objects_dir.join("info/commit-graph");
+    let mut data = fs::read(&path)?;
+    let len = data.len();
+    data[len - 1] ^= 1;
+    fs::set_permissions(&path, Permissions::from_mode(0o644))?;
+    fs::write(&path, data)?;
+    let graph = GitCommitGraph::open(&objects_dir)?.unwrap();
+    assert_eq!(
+        graph.verify(&odb, false),
+        ["the commit-graph file has incorrect checksum and is likely corrupt"]
+    );
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_write_split_commit_graph() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_write_split_commit_graph")?;
+    let objects_dir = repo.gitpath().join("objects");
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, &["a"], 1000, &[])?;
+    let second = commit(&odb, &["b"], 2000, &[&first])?;
+    let third = commit(&odb, &["c"], 3000, &[&second])?;
+    let fourth = commit(&odb, &["d"], 4000, &[&third])?;
+    let split = |strategy| CommitGraphOptions {
+        split: Some(strategy),
+        ..CommitGraphOptions::default()
+    };
+    let graphs_dir = objects_dir.join("info/commit-graphs");
+    let num_files = || fs::read_dir(&graphs_dir).map_or(0, |dir| dir.count());
+
+    write_commit_graph(&odb, &[second], &split(SplitStrategy::NoMerge))?;
+    write_commit_graph(
+        &odb,
+        std::slice::from_ref(&third),
+        &split(SplitStrategy::NoMerge),
+    )?;
+    let graph = GitCommitGraph::open(&objects_dir)?.unwrap();
+    assert_eq!(graph.layers.len(), 2);
+    assert_eq!(graph.num_commits(), 3);
+    assert_eq!(graph.lookup(&third).unwrap().level(), 3);
+    assert!(graph.verify(&odb, false).is_empty());
+    // The chain file and a file for each layer.
+    assert_eq!(num_files(), 3);
+
+    // Nothing is written without new commits.
+    write_commit_graph(&odb, &[third], &split(SplitStrategy::NoMerge))?;
+    assert_eq!(GitCommitGraph::open(&objects_dir)?.unwrap().layers.len(), 2);
+
+    write_commit_graph(
+        &odb,
+        std::slice::from_ref(&fourth),
+        &split(SplitStrategy::Merge),
+    )?;
+    let graph = GitCommitGraph::open(&objects_dir)?.unwrap();
+    assert_eq!(graph.layers.len(), 1);
+    assert_eq!(graph.num_commits(), 4);
+    assert_eq!(num_files(), 2);
+
+    write_commit_graph(
+        &odb,
+        std::slice::from_ref(&fourth),
+        &CommitGraphOptions::default(),
+    )?;
+    assert!(objects_dir.join("info/commit-graph").is_file());
+    assert!(!graphs_dir.join("commit-graph-chain").exists());
+    assert_eq!(num_files(), 0);
+
+    write_commit_graph(&odb, &[fourth], &split(SplitStrategy::Replace))?;
+    assert!(!objects_dir.join("info/commit-graph").exists());
+    let graph = GitCommitGraph::open(&objects_dir)?.unwrap();
+    assert_eq!(graph.layers.len(), 1);
+    assert_eq!(graph.num_commits(), 4);
+    assert!(graph.verify(&odb, false).is_empty());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_bloom_filter() {
+    let filter = BloomFilter::new(&["src/objects/mod.rs", "README.md"]);
+    // Four keys of 10 bits each.
+    assert_eq!(filter.as_bytes().len(), 5);
+    for path in &["src", "src/objects", "src/objects/mod.rs", "README.md"] {
+        assert!(filter.contains(path));
+    }
+    assert_eq!(
+        BloomFilter::from_bytes(filter.as_bytes(), bloom::NUM_HASHES),
+        filter
+    );
+
+    let empty: [&str; 0] = [];
+    assert_eq!(BloomFilter::new(&empty).as_bytes(), [0]);
+    let many: Vec<String> = (0..=bloom::MAX_CHANGED_PATHS)
+        .map(|i| i.to_string())
+        .collect();
+    let full = BloomFilter::new(&many);
+    assert_eq!(full.as_bytes(), [0xff]);
+    assert!(full.contains("anything"));
+}
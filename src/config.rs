@@ -1,9 +0,0 @@
-pub fn initial_config() -> &'static str {
-    "[core]
-    repositoryformatversion = 0
-    filemode = true
-    bare = false
-    logallrefupdates = true
-    ignorecase = true
-    precomposeunicode = true\n"
-}
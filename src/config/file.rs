@@ -0,0 +1,845 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::utils::{self, LockFile};
+use crate::{GitError, GitResult};
+
+/// A single line of a config file, kept verbatim so edits preserve comments and formatting.
+///
+/// `raw` includes the line terminator, and for entries any continuation lines. A section
+/// header followed by an entry on the same line is stored as two lines, the first without
+/// a terminator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// Blank lines and comments.
+    Other { raw: String },
+    Section {
+        raw: String,
+        name: String,
+        subsection: Option<String>,
+    },
+    Entry {
+        raw: String,
+        /// The canonical name, e.g. `branch.Main.remote`.
+        name: String,
+        value: Option<String>,
+    },
+}
+
+impl Line {
+    fn raw(&self) -> &str {
+        match self {
+            Line::Other { raw } | Line::Section { raw, .. } | Line::Entry { raw, .. } => raw,
+        }
+    }
+}
+
+/// A variable of a config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    name: String,
+    value: Option<String>,
+}
+
+impl ConfigEntry {
+    /// The canonical name, with the section and key in lower case, e.g. `branch.Main.remote`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value, or [None] for a key without `=`, which means true.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+}
+
+/// A config file in git's INI dialect, which can be queried and edited without losing comments
+/// or formatting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<Line>,
+}
+
+impl ConfigFile {
+    /// Reads the config file at `path`. A missing file is empty.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The file has a syntax error
+    /// * [GitError::IOError]: The file can not be read
+    pub fn open<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                lines: Vec::new(),
+            });
+        }
+        Self::parse(&utils::read_file(path)?, path)
+    }
+
+    /// Parses `data` as the contents of the config file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The data has a syntax error
+    pub fn parse<P: AsRef<Path>>(data: &str, path: P) -> GitResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lines = Parser::new(data, &path).parse()?;
+        Ok(Self { path, lines })
+    }
+
+    /// The path the file is read from and written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns every variable in the order of the file.
+    pub fn entries(&self) -> Vec<ConfigEntry> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry { name, value, .. } => Some(ConfigEntry {
+                    name: name.clone(),
+                    value: value.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns all values of `name`, in the order of the file.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn get_all(&self, name: &str) -> GitResult<Vec<Option<String>>> {
+        let name = canonical_name(name)?;
+        Ok(self
+            .entries()
+            .into_iter()
+            .filter(|entry| entry.name == name)
+            .map(|entry| entry.value)
+            .collect())
+    }
+
+    /// Returns the last value of `name`, where an inner [None] is a key without `=`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn get(&self, name: &str) -> GitResult<Option<Option<String>>> {
+        Ok(self.get_all(name)?.pop())
+    }
+
+    /// Sets `name` to `value`, replacing its only value or adding it to the last matching
+    /// section (creating the section if needed).
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key, or it has multiple values
+    pub fn set(&mut self, name: &str, value: &str) -> GitResult<()> {
+        let canonical = canonical_name(name)?;
+        let count = self.matching(&canonical, None).len();
+        if count > 1 {
+            return Err(GitError::ConfigError {
+                path: self.path.clone(),
+                message: format!(
+                    "warning: {} has multiple values\n\
+                     error: cannot overwrite multiple values with a single value\n       \
+                     Use a regexp, --add or --replace-all to change {}.",
+                    name, name
+                ),
+            });
+        }
+        self.replace_all(name, value, None)
+    }
+
+    /// Adds another value for `name` without touching existing ones.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn add(&mut self, name: &str, value: &str) -> GitResult<()> {
+        let (section, subsection, key) = split_name(name)?;
+        let line = Line::Entry {
+            raw: format!("\t{} = {}\n", key, quote_value(value)),
+            name: canonical_name(name)?,
+            value: Some(value.to_string()),
+        };
+        match self.section_end(&section, subsection.as_deref()) {
+            Some(end) => self.insert(end, line),
+            None => {
+                self.ensure_newline(self.lines.len());
+                self.lines.push(Line::Section {
+                    raw: format!("{}\n", section_header(&section, subsection.as_deref())),
+                    name: section.to_ascii_lowercase(),
+                    subsection,
+                });
+                self.lines.push(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces all values of `name` matching `pattern` (or all values) with a single `value`,
+    /// written where the last of them was.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn replace_all(
+        &mut self,
+        name: &str,
+        value: &str,
+        pattern: Option<&ValuePattern>,
+    ) -> GitResult<()> {
+        let canonical = canonical_name(name)?;
+        let (_, _, key) = split_name(name)?;
+        let matching = self.matching(&canonical, pattern);
+        let last = match matching.last() {
+            Some(&last) => last,
+            None => return self.add(name, value),
+        };
+
+        // Keep the indentation of the entry being replaced.
+        let indent: String = self.lines[last]
+            .raw()
+            .chars()
+            .take_while(|ch| *ch == ' ' || *ch == '\t')
+            .collect();
+        let indent = if indent.is_empty() { "\t" } else { &indent };
+        let newline = if self.lines[last].raw().ends_with('\n') {
+            "\n"
+        } else {
+            ""
+        };
+        self.lines[last] = Line::Entry {
+            raw: format!("{}{} = {}{}", indent, key, quote_value(value), newline),
+            name: canonical,
+            value: Some(value.to_string()),
+        };
+        self.remove_lines(&matching[..matching.len() - 1]);
+        Ok(())
+    }
+
+    /// Removes the values of `name` matching `pattern` (or all values) and returns how many
+    /// were removed. Sections left empty are removed too.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn unset_all(&mut self, name: &str, pattern: Option<&ValuePattern>) -> GitResult<usize> {
+        let canonical = canonical_name(name)?;
+        let matching = self.matching(&canonical, pattern);
+        self.remove_lines(&matching);
+        Ok(matching.len())
+    }
+
+    /// Removes the only value of `name` matching `pattern` (or its only value), returning false
+    /// if there is none.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key, or it has multiple values
+    pub fn unset(&mut self, name: &str, pattern: Option<&ValuePattern>) -> GitResult<bool> {
+        let canonical = canonical_name(name)?;
+        if self.matching(&canonical, pattern).len() > 1 {
+            return Err(GitError::ConfigError {
+                path: self.path.clone(),
+                message: format!("warning: {} has multiple values", name),
+            });
+        }
+        Ok(self.unset_all(name, pattern)? == 1)
+    }
+
+    /// Renames every section `old` (e.g. `branch.main`) to `new`, or removes it with all its
+    /// variables if `new` is [None]. Returns false if there is no such section.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: A section name is invalid
+    pub fn rename_section(&mut self, old: &str, new: Option<&str>) -> GitResult<bool> {
+        let (old_name, old_sub) = split_section(old)?;
+        let new = match new {
+            Some(new) => Some(split_section(new)?),
+            None => None,
+        };
+
+        let mut found = false;
+        let mut removing = false;
+        let mut section_key = String::new();
+        let mut lines = Vec::new();
+        for line in std::mem::take(&mut self.lines) {
+            match line {
+                Line::Section {
+                    name, subsection, ..
+                } if name == old_name.to_ascii_lowercase() && subsection == old_sub => {
+                    found = true;
+                    removing = new.is_none();
+                    if let Some((new_name, new_sub)) = &new {
+                        section_key = match new_sub {
+                            Some(sub) => format!("{}.{}", new_name.to_ascii_lowercase(), sub),
+                            None => new_name.to_ascii_lowercase(),
+                        };
+                        lines.push(Line::Section {
+                            raw: format!("{}\n", section_header(new_name, new_sub.as_deref())),
+                            name: new_name.to_ascii_lowercase(),
+                            subsection: new_sub.clone(),
+                        });
+                    }
+                }
+                Line::Section { .. } => {
+                    removing = false;
+                    section_key.clear();
+                    lines.push(line);
+                }
+                _ if removing => {}
+                Line::Entry { raw, name, value } if !section_key.is_empty() => {
+                    let key = &name[name.rfind('.').unwrap() + 1..];
+                    lines.push(Line::Entry {
+                        name: format!("{}.{}", section_key, key),
+                        raw,
+                        value,
+                    });
+                }
+                line => lines.push(line),
+            }
+        }
+        self.lines = lines;
+        Ok(found)
+    }
+
+    /// Writes the file atomically through a lock file.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::LockError]: The file is locked by another process
+    /// * [GitError::IOError]: The file can not be written
+    pub fn write(&self) -> GitResult<()> {
+        let mut lock = LockFile::acquire(&self.path)?;
+        lock.write_all(self.to_string().as_bytes())?;
+        lock.commit()
+    }
+
+    /// Returns the indices of the entries named `name` whose value matches `pattern`.
+    fn matching(&self, name: &str, pattern: Option<&ValuePattern>) -> Vec<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| match line {
+                Line::Entry {
+                    name: entry, value, ..
+                } if entry == name
+                    && pattern.is_none_or(|pattern| pattern.matches(value.as_deref())) =>
+                {
+                    Some(i)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the index after the last line of the last section called `section`.
+    fn section_end(&self, section: &str, subsection: Option<&str>) -> Option<usize> {
+        let mut end = None;
+        let mut inside = false;
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Section {
+                    name,
+                    subsection: sub,
+                    ..
+                } => {
+                    inside = name.eq_ignore_ascii_case(section) && sub.as_deref() == subsection;
+                    if inside {
+                        end = Some(i + 1);
+                    }
+                }
+                Line::Entry { .. } if inside => end = Some(i + 1),
+                _ => {}
+            }
+        }
+        end
+    }
+
+    fn insert(&mut self, i: usize, line: Line) {
+        self.ensure_newline(i);
+        self.lines.insert(i, line);
+    }
+
+    /// Makes sure the line before index `i` ends with a newline.
+    fn ensure_newline(&mut self, i: usize) {
+        if let Some(i) = i.checked_sub(1) {
+            push_newline(&mut self.lines[i]);
+        }
+    }
+
+    /// Removes the lines at `indices`, and the headers of sections this leaves without any
+    /// entries or comments.
+    fn remove_lines(&mut self, indices: &[usize]) {
+        let header_of = |i: usize| {
+            (0..i)
+                .rev()
+                .find(|&j| matches!(self.lines[j], Line::Section { .. }))
+        };
+        let touched: HashSet<usize> = indices.iter().filter_map(|&i| header_of(i)).collect();
+
+        // Pairs of original index and line.
+        let mut kept: Vec<(usize, Line)> = Vec::new();
+        for (i, line) in std::mem::take(&mut self.lines).into_iter().enumerate() {
+            if indices.contains(&i) {
+                // The line may have followed its section header on the same line.
+                if let Some((_, previous)) = kept.last_mut() {
+                    push_newline(previous);
+                }
+                continue;
+            }
+            kept.push((i, line));
+        }
+
+        let mut lines = Vec::with_capacity(kept.len());
+        for (k, (i, line)) in kept.iter().enumerate() {
+            if touched.contains(i) {
+                let empty = kept[k + 1..]
+                    .iter()
+                    .take_while(|(_, line)| !matches!(line, Line::Section { .. }))
+                    .all(|(_, line)| line.raw().trim().is_empty());
+                if empty {
+                    continue;
+                }
+            }
+            lines.push(line.clone());
+        }
+        self.lines = lines;
+    }
+}
+
+fn push_newline(line: &mut Line) {
+    match line {
+        Line::Other { raw } | Line::Section { raw, .. } | Line::Entry { raw, .. } => {
+            if !raw.ends_with('\n') {
+                raw.push('\n');
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            f.write_str(line.raw())?;
+        }
+        Ok(())
+    }
+}
+
+/// A regular expression selecting values, negated by a leading `!` like in git.
+#[derive(Debug, Clone)]
+pub struct ValuePattern {
+    regex: Regex,
+    negate: bool,
+}
+
+impl ValuePattern {
+    /// Compiles `pattern`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The pattern is not a valid regular expression
+    pub fn new(pattern: &str) -> GitResult<Self> {
+        let (pattern, negate) = match pattern.strip_prefix('!') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let regex = Regex::new(pattern).map_err(|_| GitError::ConfigError {
+            path: PathBuf::new(),
+            message: format!("error: invalid pattern: {}", pattern),
+        })?;
+        Ok(Self { regex, negate })
+    }
+
+    /// Compiles a pattern matching exactly `value`, like `--fixed-value`.
+    pub fn fixed(value: &str) -> Self {
+        Self {
+            regex: Regex::new(&format!("^{}$", regex::escape(value))).unwrap(),
+            negate: false,
+        }
+    }
+
+    /// Returns true if `value` is selected. A key without `=` has no value and only matches
+    /// negated patterns.
+    pub fn matches(&self, value: Option<&str>) -> bool {
+        match value {
+            Some(value) => self.regex.is_match(value) != self.negate,
+            None => self.negate,
+        }
+    }
+}
+
+/// Splits a variable name like `branch.main.remote` into its section, subsection and key, and
+/// checks that they are valid.
+///
+/// # Errors
+///
+/// * [GitError::ConfigError]: The name has no section or the key is invalid
+pub fn split_name(name: &str) -> GitResult<(String, Option<String>, String)> {
+    let invalid = |message: String| GitError::ConfigError {
+        path: PathBuf::new(),
+        message,
+    };
+    let last = name
+        .rfind('.')
+        .ok_or_else(|| invalid(format!("error: key does not contain a section: {}", name)))?;
+    let key = &name[last + 1..];
+    let (section, subsection) = match name[..last].find('.') {
+        Some(first) => (&name[..first], Some(&name[first + 1..last])),
+        None => (&name[..last], None),
+    };
+    let valid_key = key
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-');
+    let valid_section = !section.is_empty()
+        && section
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-');
+    if key.is_empty() {
+        return Err(invalid(format!(
+            "error: key does not contain variable name: {}",
+            name
+        )));
+    }
+    if !valid_key || !valid_section || subsection.is_some_and(|sub| sub.contains('\n')) {
+        return Err(invalid(format!("error: invalid key: {}", name)));
+    }
+    Ok((
+        section.to_string(),
+        subsection.map(String::from),
+        key.to_string(),
+    ))
+}
+
+/// Returns the canonical form of a variable name, with the section and key in lower case.
+///
+/// # Errors
+///
+/// See [split_name].
+pub fn canonical_name(name: &str) -> GitResult<String> {
+    let (section, subsection, key) = split_name(name)?;
+    Ok(match subsection {
+        Some(sub) => format!(
+            "{}.{}.{}",
+            section.to_ascii_lowercase(),
+            sub,
+            key.to_ascii_lowercase()
+        ),
+        None => format!(
+            "{}.{}",
+            section.to_ascii_lowercase(),
+            key.to_ascii_lowercase()
+        ),
+    })
+}
+
+/// Splits a section name like `branch.main` into the section and subsection.
+fn split_section(name: &str) -> GitResult<(String, Option<String>)> {
+    let (section, subsection, _) = split_name(&format!("{}.key", name))?;
+    Ok((section, subsection))
+}
+
+fn section_header(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(sub) => format!(
+            "[{} \"{}\"]",
+            section,
+            sub.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{}]", section),
+    }
+}
+
+/// Quotes and escapes `value` so it reads back unchanged.
+fn quote_value(value: &str) -> String {
+    let quote = value.starts_with(' ')
+        || value.ends_with(' ')
+        || value.starts_with('\t')
+        || value.ends_with('\t')
+        || value.contains([';', '#']);
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch => escaped.push(ch),
+        }
+    }
+    if quote {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Splits config data into [Line]s.
+struct Parser<'a> {
+    data: &'a str,
+    path: &'a Path,
+    pos: usize,
+    line: usize,
+    section: Option<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a str, path: &'a Path) -> Self {
+        Self {
+            data,
+            path,
+            pos: 0,
+            line: 1,
+            section: None,
+        }
+    }
+
+    fn parse(mut self) -> GitResult<Vec<Line>> {
+        let mut lines = Vec::new();
+        if self.data.starts_with('\u{feff}') {
+            self.pos = '\u{feff}'.len_utf8();
+        }
+        let mut start = 0;
+        while self.pos < self.data.len() {
+            self.skip_whitespace();
+            match self.peek() {
+                None => {}
+                Some('\n') | Some('\r') | Some('#') | Some(';') => self.skip_line(),
+                Some('[') => {
+                    let (name, subsection) = self.parse_header()?;
+                    self.section = Some(match &subsection {
+                        Some(sub) => format!("{}.{}", name, sub),
+                        None => name.clone(),
+                    });
+                    self.skip_whitespace();
+                    if matches!(self.peek(), None | Some('\n' | '\r' | '#' | ';')) {
+                        self.skip_line();
+                    }
+                    lines.push(Line::Section {
+                        raw: self.data[start..self.pos].to_string(),
+                        name,
+                        subsection,
+                    });
+                    start = self.pos;
+                    continue;
+                }
+                Some(ch) if ch.is_ascii_alphabetic() => {
+                    let (name, value) = self.parse_entry()?;
+                    lines.push(Line::Entry {
+                        raw: self.data[start..self.pos].to_string(),
+                        name,
+                        value,
+                    });
+                    start = self.pos;
+                    continue;
+                }
+                Some(_) => return Err(self.error()),
+            }
+            lines.push(Line::Other {
+                raw: self.data[start..self.pos].to_string(),
+            });
+            start = self.pos;
+        }
+        Ok(lines)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.data[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+        }
+        Some(ch)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    /// Skips to after the end of the current line.
+    fn skip_line(&mut self) {
+        while let Some(ch) = self.next() {
+            if ch == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn parse_header(&mut self) -> GitResult<(String, Option<String>)> {
+        self.next();
+        let mut name = String::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == '-' || ch == '.' {
+                name.push(ch.to_ascii_lowercase());
+                self.next();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() {
+            return Err(self.error());
+        }
+
+        match self.next() {
+            Some(']') => {
+                // The deprecated `[section.subsection]` form is case insensitive.
+                Ok(match name.find('.') {
+                    Some(dot) => (name[..dot].to_string(), Some(name[dot + 1..].to_string())),
+                    None => (name, None),
+                })
+            }
+            Some(' ' | '\t') if !name.contains('.') => {
+                self.skip_whitespace();
+                match self.next() {
+                    Some('"') => (),
+                    ch => return Err(self.header_error(ch)),
+                }
+                let mut subsection = String::new();
+                loop {
+                    match self.next() {
+                        Some('"') => break,
+                        Some('\\') => match self.next() {
+                            Some(ch) if ch != '\n' => subsection.push(ch),
+                            ch => return Err(self.header_error(ch)),
+                        },
+                        ch @ (Some('\n') | None) => return Err(self.header_error(ch)),
+                        Some(ch) => subsection.push(ch),
+                    }
+                }
+                if self.next() != Some(']') {
+                    return Err(self.error());
+                }
+                Ok((name, Some(subsection)))
+            }
+            ch => Err(self.header_error(ch)),
+        }
+    }
+
+    /// Returns the error for a section header that ends at `ch`. Like git, a header cut short
+    /// by the end of its line is reported on that line, not the next one.
+    fn header_error(&self, ch: Option<char>) -> GitError {
+        match ch {
+            Some('\n') => self.error_at(self.line - 1),
+            _ => self.error(),
+        }
+    }
+
+    fn parse_entry(&mut self) -> GitResult<(String, Option<String>)> {
+        let section = match &self.section {
+            Some(section) => section.clone(),
+            None => return Err(self.error()),
+        };
+        let mut key = String::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == '-' {
+                key.push(ch.to_ascii_lowercase());
+                self.next();
+            } else {
+                break;
+            }
+        }
+        let name = format!("{}.{}", section, key);
+
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok((name, None)),
+            Some('\n' | '\r' | '#' | ';') => {
+                self.skip_line();
+                Ok((name, None))
+            }
+            Some('=') => {
+                self.next();
+                Ok((name, Some(self.parse_value()?)))
+            }
+            Some(_) => Err(self.error()),
+        }
+    }
+
+    fn parse_value(&mut self) -> GitResult<String> {
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut spaces = 0;
+        self.skip_whitespace();
+        loop {
+            let ch = match self.next() {
+                Some('\r') if self.peek() == Some('\n') => continue,
+                Some(ch) => ch,
+                None if quoted => return Err(self.error()),
+                None => break,
+            };
+            if ch == '\n' {
+                if quoted {
+                    return Err(self.error_at(self.line - 1));
+                }
+                break;
+            }
+            if !quoted && (ch == '#' || ch == ';') {
+                self.skip_line();
+                break;
+            }
+            // Whitespace outside quotes is kept only between other characters, as spaces.
+            if !quoted && (ch == ' ' || ch == '\t') {
+                if !value.is_empty() {
+                    spaces += 1;
+                }
+                continue;
+            }
+            for _ in 0..spaces {
+                value.push(' ');
+            }
+            spaces = 0;
+            match ch {
+                '\\' => match self.next() {
+                    Some('\n') => {}
+                    Some('\r') if self.peek() == Some('\n') => {
+                        self.next();
+                    }
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => value.push('\u{8}'),
+                    Some(ch @ ('\\' | '"')) => value.push(ch),
+                    _ => return Err(self.error()),
+                },
+                '"' => quoted = !quoted,
+                ch => value.push(ch),
+            }
+        }
+        Ok(value)
+    }
+
+    fn error(&self) -> GitError {
+        self.error_at(self.line)
+    }
+
+    fn error_at(&self, line: usize) -> GitError {
+        GitError::ConfigError {
+            path: self.path.to_path_buf(),
+            message: format!(
+                "fatal: bad config line {} in file {}",
+                line,
+                self.path.display()
+            ),
+        }
+    }
+}
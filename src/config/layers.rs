@@ -0,0 +1,599 @@
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::file::{canonical_name, ConfigFile};
+use super::{parse_bool, parse_int, ValueType};
+use crate::utils::{self, WM_CASEFOLD, WM_PATHNAME};
+use crate::{GitError, GitRepo, GitResult};
+
+/// Included config files are followed at most this deep.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// The level a config value comes from, from lowest to highest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigScope {
+    System,
+    Global,
+    Local,
+    Worktree,
+    Command,
+}
+
+impl Display for ConfigScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigScope::System => "system",
+            ConfigScope::Global => "global",
+            ConfigScope::Local => "local",
+            ConfigScope::Worktree => "worktree",
+            ConfigScope::Command => "command",
+        })
+    }
+}
+
+/// Where a config value was read from, as shown by `--show-origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    File(PathBuf),
+    CommandLine,
+}
+
+impl Display for ConfigOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigOrigin::File(path) => {
+                write!(f, "file:{}", utils::quote_c_style(&path.to_string_lossy()))
+            }
+            ConfigOrigin::CommandLine => write!(f, "command line:"),
+        }
+    }
+}
+
+/// A config variable with the scope and file it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigValue {
+    name: String,
+    value: Option<String>,
+    scope: ConfigScope,
+    origin: ConfigOrigin,
+}
+
+impl ConfigValue {
+    /// The canonical name, with the section and key in lower case.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The value, or [None] for a key without `=`, which means true.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    pub fn scope(&self) -> ConfigScope {
+        self.scope
+    }
+
+    pub fn origin(&self) -> &ConfigOrigin {
+        &self.origin
+    }
+
+    /// Returns the value in the canonical form of `value_type`, like `git config --type`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The value is not valid for the type
+    pub fn typed(&self, value_type: ValueType) -> GitResult<String> {
+        value_type
+            .normalize(self.value())
+            .ok_or_else(|| bad_value(value_type, self))
+    }
+}
+
+/// The config of a repository, merged from the system, global, local and worktree config
+/// files, the `GIT_CONFIG_*` environment variables and `-c` options, following includes.
+///
+/// Values are kept in order of priority: later values override earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct GitConfig {
+    values: Vec<ConfigValue>,
+}
+
+/// What conditional includes are matched against.
+struct IncludeContext {
+    gitdir: Option<PathBuf>,
+    branch: Option<String>,
+}
+
+impl GitConfig {
+    /// Reads every config layer for `repo`, or only the system and global config and the
+    /// command line without a repository.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: A config file or the environment is malformed, or includes
+    ///   nest too deeply
+    /// * [GitError::IOError]: A config file can not be read
+    pub fn load(repo: Option<&GitRepo>) -> GitResult<Self> {
+        Self::load_with_env(repo, &process_env)
+    }
+
+    /// Reads every config layer like [GitConfig::load], taking the environment variables that
+    /// locate the system and global config and hold the command line from `env`.
+    pub(crate) fn load_with_env(repo: Option<&GitRepo>, env: &Env) -> GitResult<Self> {
+        let context = IncludeContext::new(repo);
+        let mut config = Self::default();
+
+        let nosystem =
+            env("GIT_CONFIG_NOSYSTEM").is_some_and(|value| parse_bool(Some(&value)) == Some(true));
+        if !nosystem {
+            config.read_file(&system_path_in(env), ConfigScope::System, &context, 0)?;
+        }
+        for path in global_paths_in(env) {
+            config.read_file(&path, ConfigScope::Global, &context, 0)?;
+        }
+        if let Some(repo) = repo {
+            let local = repo.gitpath().join("config");
+            config.read_file(&local, ConfigScope::Local, &context, 0)?;
+            let worktree_config = ConfigFile::open(&local)?
+                .get("extensions.worktreeConfig")?
+                .is_some_and(|value| parse_bool(value.as_deref()) == Some(true));
+            if worktree_config {
+                let path = repo.gitpath().join("config.worktree");
+                config.read_file(&path, ConfigScope::Worktree, &context, 0)?;
+            }
+        }
+        config.read_command_line(&context, env)?;
+        Ok(config)
+    }
+
+    /// Reads the single file at `path` (with `scope`), following includes if `includes` is
+    /// set, like `git config --file`.
+    ///
+    /// # Errors
+    ///
+    /// See [GitConfig::load].
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        scope: ConfigScope,
+        repo: Option<&GitRepo>,
+        includes: bool,
+    ) -> GitResult<Self> {
+        let mut config = Self::default();
+        let context = IncludeContext::new(repo);
+        let depth = if includes { 0 } else { MAX_INCLUDE_DEPTH };
+        config.read_file_with(path.as_ref(), scope, &context, depth, includes)?;
+        Ok(config)
+    }
+
+    /// Returns every value, lowest priority first.
+    pub fn values(&self) -> &[ConfigValue] {
+        &self.values
+    }
+
+    /// Returns all values of `name`, lowest priority first.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn get_all(&self, name: &str) -> GitResult<Vec<&ConfigValue>> {
+        let name = canonical_name(name)?;
+        Ok(self
+            .values
+            .iter()
+            .filter(|value| value.name == name)
+            .collect())
+    }
+
+    /// Returns the value of `name` with the highest priority.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn get_entry(&self, name: &str) -> GitResult<Option<&ConfigValue>> {
+        Ok(self.get_all(name)?.pop())
+    }
+
+    /// Returns the value of `name`, where a key without a value reads as `true`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is not a valid key
+    pub fn get(&self, name: &str) -> GitResult<Option<String>> {
+        Ok(self
+            .get_entry(name)?
+            .map(|value| value.value().unwrap_or("true").to_string()))
+    }
+
+    /// Returns `name` as a boolean, or `default` if it is not set.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is invalid or the value is not a boolean
+    pub fn get_bool(&self, name: &str, default: bool) -> GitResult<bool> {
+        match self.get_entry(name)? {
+            Some(value) => {
+                parse_bool(value.value()).ok_or_else(|| bad_value(ValueType::Bool, value))
+            }
+            None => Ok(default),
+        }
+    }
+
+    /// Returns `name` as an integer with an optional unit suffix.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is invalid or the value is not a number
+    pub fn get_int(&self, name: &str) -> GitResult<Option<i64>> {
+        match self.get_entry(name)? {
+            Some(value) => match value.value().and_then(parse_int) {
+                Some(n) => Ok(Some(n)),
+                None => Err(bad_value(ValueType::Int, value)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `name` as a path, expanding a leading `~/`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The name is invalid
+    pub fn get_path(&self, name: &str) -> GitResult<Option<PathBuf>> {
+        Ok(self.get(name)?.map(|path| utils::expand_home(&path)))
+    }
+
+    fn read_file(
+        &mut self,
+        path: &Path,
+        scope: ConfigScope,
+        context: &IncludeContext,
+        depth: usize,
+    ) -> GitResult<()> {
+        self.read_file_with(path, scope, context, depth, true)
+    }
+
+    fn read_file_with(
+        &mut self,
+        path: &Path,
+        scope: ConfigScope,
+        context: &IncludeContext,
+        depth: usize,
+        includes: bool,
+    ) -> GitResult<()> {
+        if !path.is_file() {
+            return Ok(());
+        }
+        let file = ConfigFile::open(path)?;
+        for entry in file.entries() {
+            self.values.push(ConfigValue {
+                name: entry.name().to_string(),
+                value: entry.value().map(String::from),
+                scope,
+                origin: ConfigOrigin::File(path.to_path_buf()),
+            });
+            if !includes {
+                continue;
+            }
+            if let Some(include) = included_path(entry.name(), entry.value(), path, context)? {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(GitError::ConfigError {
+                        path: path.to_path_buf(),
+                        message: format!(
+                            "fatal: exceeded maximum include depth ({}) while including\n\t{}\n\
+                             from\n\t{}\n\
+                             This might be due to circular includes.",
+                            MAX_INCLUDE_DEPTH,
+                            include.display(),
+                            path.display()
+                        ),
+                    });
+                }
+                self.read_file(&include, scope, context, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads `GIT_CONFIG_COUNT`/`GIT_CONFIG_KEY_<n>`/`GIT_CONFIG_VALUE_<n>` and then
+    /// `GIT_CONFIG_PARAMETERS`, which holds the `-c` options.
+    fn read_command_line(&mut self, context: &IncludeContext, env: &Env) -> GitResult<()> {
+        let mut pairs = Vec::new();
+        if let Some(count) = env("GIT_CONFIG_COUNT") {
+            let count = count
+                .parse::<usize>()
+                .map_err(|_| env_error("bogus count in GIT_CONFIG_COUNT"))?;
+            for i in 0..count {
+                let key = env(&format!("GIT_CONFIG_KEY_{}", i)).ok_or_else(|| {
+                    env_error(&format!("missing config key GIT_CONFIG_KEY_{}", i))
+                })?;
+                let value = env(&format!("GIT_CONFIG_VALUE_{}", i)).ok_or_else(|| {
+                    env_error(&format!("missing config value GIT_CONFIG_VALUE_{}", i))
+                })?;
+                pairs.push((key, Some(value)));
+            }
+        }
+        if let Some(parameters) = env("GIT_CONFIG_PARAMETERS") {
+            pairs.extend(parse_parameters(&parameters)?);
+        }
+
+        for (key, value) in pairs {
+            let name = canonical_name(&key)
+                .map_err(|_| env_error(&format!("error: invalid key: {}", key)))?;
+            if included_path(&name, value.as_deref(), Path::new(""), context)?.is_some() {
+                return Err(env_error("relative config includes must come from files"));
+            }
+            self.values.push(ConfigValue {
+                name,
+                value,
+                scope: ConfigScope::Command,
+                origin: ConfigOrigin::CommandLine,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl IncludeContext {
+    fn new(repo: Option<&GitRepo>) -> Self {
+        let gitdir = repo.map(|repo| {
+            fs::canonicalize(repo.gitpath()).unwrap_or_else(|_| repo.gitpath().to_path_buf())
+        });
+        let branch = repo.and_then(|repo| {
+            let head = fs::read_to_string(repo.gitpath().join("HEAD")).ok()?;
+            let branch = head.trim().strip_prefix("ref: refs/heads/")?;
+            Some(branch.to_string())
+        });
+        Self { gitdir, branch }
+    }
+}
+
+/// Returns `parameters`, the value of `GIT_CONFIG_PARAMETERS`, with the `-c` option
+/// `parameter` added. A parameter without `=` sets the variable to true.
+///
+/// Setting the result as `GIT_CONFIG_PARAMETERS` makes it seen by [GitConfig::load] and by
+/// any git processes started later.
+///
+/// # Errors
+///
+/// * [GitError::ConfigError]: The name is not a valid key
+pub fn add_config_parameter(mut parameters: String, parameter: &str) -> GitResult<String> {
+    let (key, value) = match parameter.find('=') {
+        Some(eq) => (&parameter[..eq], Some(&parameter[eq + 1..])),
+        None => (parameter, None),
+    };
+    if let Err(error) = canonical_name(key) {
+        return Err(GitError::ConfigError {
+            path: PathBuf::new(),
+            message: format!("{}\nfatal: unable to parse command-line config", error),
+        });
+    }
+
+    if !parameters.is_empty() {
+        parameters.push(' ');
+    }
+    parameters.push_str(&sq_quote(key));
+    if let Some(value) = value {
+        parameters.push('=');
+        parameters.push_str(&sq_quote(value));
+    }
+    Ok(parameters)
+}
+
+/// Returns the file included by the variable `name`, if it is `include.path` or a matching
+/// `includeIf.<condition>.path`. Relative paths are relative to the including `file`.
+fn included_path(
+    name: &str,
+    value: Option<&str>,
+    file: &Path,
+    context: &IncludeContext,
+) -> GitResult<Option<PathBuf>> {
+    let value = match value {
+        Some(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+    let included = if name == "include.path" {
+        true
+    } else if let Some(condition) = name
+        .strip_prefix("includeif.")
+        .and_then(|rest| rest.strip_suffix(".path"))
+    {
+        condition_matches(condition, file, context)?
+    } else {
+        false
+    };
+    if !included {
+        return Ok(None);
+    }
+
+    let path = utils::expand_home(value);
+    if path.is_absolute() {
+        return Ok(Some(path));
+    }
+    match file.parent() {
+        Some(dir) if !file.as_os_str().is_empty() => Ok(Some(dir.join(path))),
+        _ => Err(env_error("relative config includes must come from files")),
+    }
+}
+
+/// Evaluates an `includeIf` condition: `gitdir:`, `gitdir/i:` or `onbranch:`.
+fn condition_matches(condition: &str, file: &Path, context: &IncludeContext) -> GitResult<bool> {
+    let (pattern, flags, text) = if let Some(pattern) = condition.strip_prefix("gitdir:") {
+        (pattern, WM_PATHNAME, &context.gitdir)
+    } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+        (pattern, WM_PATHNAME | WM_CASEFOLD, &context.gitdir)
+    } else if let Some(pattern) = condition.strip_prefix("onbranch:") {
+        let text = context.branch.as_ref().map(PathBuf::from);
+        let pattern = if pattern.ends_with('/') {
+            format!("{}**", pattern)
+        } else {
+            pattern.to_string()
+        };
+        return Ok(text.is_some_and(|branch| {
+            utils::wildmatch(&pattern, &branch.to_string_lossy(), WM_PATHNAME)
+        }));
+    } else {
+        return Ok(false);
+    };
+    let text = match text {
+        Some(text) => text.to_string_lossy(),
+        None => return Ok(false),
+    };
+
+    let mut pattern = if let Some(rest) = pattern.strip_prefix("./") {
+        match file.parent() {
+            Some(dir) if !file.as_os_str().is_empty() => {
+                let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+                format!("{}/{}", dir.display(), rest)
+            }
+            _ => {
+                return Err(env_error(
+                    "relative config include conditionals must come from files",
+                ))
+            }
+        }
+    } else {
+        utils::expand_home(pattern).to_string_lossy().into_owned()
+    };
+    if !pattern.starts_with('/') {
+        pattern = format!("**/{}", pattern);
+    }
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    Ok(utils::wildmatch(&pattern, &text, flags))
+}
+
+/// Parses `GIT_CONFIG_PARAMETERS`: shell-quoted `'name'='value'` or `'name=value'` words.
+fn parse_parameters(data: &str) -> GitResult<Vec<(String, Option<String>)>> {
+    let bogus = || env_error("bogus format in GIT_CONFIG_PARAMETERS");
+    let mut chars = data.chars().peekable();
+    let mut pairs = Vec::new();
+    loop {
+        while chars.peek().is_some_and(|ch| ch.is_ascii_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let key = sq_dequote(&mut chars).ok_or_else(bogus)?;
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            let value = if chars.peek() == Some(&'\'') {
+                Some(sq_dequote(&mut chars).ok_or_else(bogus)?)
+            } else {
+                None
+            };
+            pairs.push((key, value));
+        } else {
+            // The old format quotes `name=value` as one word.
+            pairs.push(match key.find('=') {
+                Some(eq) => (key[..eq].to_string(), Some(key[eq + 1..].to_string())),
+                None => (key, None),
+            });
+        }
+        if chars.peek().is_some_and(|ch| !ch.is_ascii_whitespace()) {
+            return Err(bogus());
+        }
+    }
+    Ok(pairs)
+}
+
+/// Reads a single-quoted word, where `'\''` stands for a quote.
+fn sq_dequote(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut word = String::new();
+    if chars.next()? != '\'' {
+        return None;
+    }
+    loop {
+        match chars.next()? {
+            '\'' => {
+                if chars.peek() != Some(&'\\') {
+                    return Some(word);
+                }
+                chars.next();
+                let quoted = chars.next()?;
+                if !matches!(quoted, '\'' | '!') || chars.next()? != '\'' {
+                    return None;
+                }
+                word.push(quoted);
+            }
+            ch => word.push(ch),
+        }
+    }
+}
+
+fn sq_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// The system config, `$GIT_CONFIG_SYSTEM` or `/etc/gitconfig`.
+pub fn system_path() -> PathBuf {
+    system_path_in(&process_env)
+}
+
+fn system_path_in(env: &Env) -> PathBuf {
+    env("GIT_CONFIG_SYSTEM")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/etc/gitconfig"))
+}
+
+/// The global config files from lowest to highest priority: `$GIT_CONFIG_GLOBAL`, or the XDG
+/// config and `~/.gitconfig`.
+fn global_paths_in(env: &Env) -> Vec<PathBuf> {
+    if let Some(path) = env("GIT_CONFIG_GLOBAL") {
+        return vec![PathBuf::from(path)];
+    }
+    let home = env("HOME").map(PathBuf::from);
+    let xdg = match env("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("git/config")),
+        _ => home.as_ref().map(|home| home.join(".config/git/config")),
+    };
+    xdg.into_iter()
+        .chain(home.map(|home| home.join(".gitconfig")))
+        .collect()
+}
+
+/// The global config file read and written by `git config --global`: `$GIT_CONFIG_GLOBAL`,
+/// or `~/.gitconfig` unless only the XDG config exists.
+///
+/// Returns [None] if `$HOME` is not set.
+pub fn global_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("GIT_CONFIG_GLOBAL") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("HOME")?;
+    let home = utils::expand_home("~/.gitconfig");
+    match utils::xdg_config_path("config") {
+        Some(xdg) if !home.exists() && xdg.exists() => Some(xdg),
+        _ => Some(home),
+    }
+}
+
+/// Looks up an environment variable, like [process_env].
+pub(crate) type Env<'a> = dyn Fn(&str) -> Option<String> + 'a;
+
+/// Reads an environment variable of this process, which is missing if it is not valid UTF-8.
+fn process_env(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+fn env_error(message: &str) -> GitError {
+    GitError::ConfigError {
+        path: PathBuf::new(),
+        message: if message.starts_with("error: ") {
+            message.to_string()
+        } else {
+            format!("fatal: {}", message)
+        },
+    }
+}
+
+fn bad_value(value_type: ValueType, value: &ConfigValue) -> GitError {
+    let location = match &value.origin {
+        ConfigOrigin::File(path) => format!(" in file {}", path.display()),
+        ConfigOrigin::CommandLine => " in command line".to_string(),
+    };
+    value_type.invalid(&value.name, value.value(), &location)
+}
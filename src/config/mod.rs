@@ -0,0 +1,189 @@
+//! Reading and writing git config files.
+
+mod file;
+mod layers;
+#[cfg(test)]
+mod tests;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub(crate) use self::file::canonical_name;
+pub use self::file::{ConfigEntry, ConfigFile, ValuePattern};
+pub use self::layers::{
+    add_config_parameter, global_path, system_path, ConfigOrigin, ConfigScope, ConfigValue,
+    GitConfig,
+};
+
+use crate::{utils, GitError, GitRepo, GitResult};
+
+/// Returns the last value of `name` (for example `core.excludesFile`) in the config file at
+/// `path`.
+///
+/// Returns [None] if the file or the key does not exist. A key without a value reads as
+/// `true`.
+///
+/// # Errors
+///
+/// See [ConfigFile::open] and [ConfigFile::get].
+pub fn read_value<P: AsRef<Path>>(path: P, name: &str) -> GitResult<Option<String>> {
+    let value = ConfigFile::open(path)?.get(name)?;
+    Ok(value.map(|value| value.unwrap_or_else(|| "true".to_string())))
+}
+
+/// Returns the value of `name` from the config of `repo`, with every layer and include
+/// applied. See [GitConfig::load].
+pub fn get(repo: &GitRepo, name: &str) -> GitResult<Option<String>> {
+    GitConfig::load(Some(repo))?.get(name)
+}
+
+/// Returns `name` from the config like [get], interpreted as a boolean.
+pub fn get_bool(repo: &GitRepo, name: &str, default: bool) -> GitResult<bool> {
+    GitConfig::load(Some(repo))?.get_bool(name, default)
+}
+
+/// Returns true if `value` is one of git's spellings of a true boolean.
+pub fn is_true(value: &str) -> bool {
+    parse_bool(Some(value)) == Some(true)
+}
+
+/// Parses a boolean like git: a key without a value, `true`, `yes`, `on` and non-zero numbers
+/// are true; the empty string, `false`, `no`, `off` and zero are false.
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let value = match value {
+        Some(value) => value,
+        None => return Some(true),
+    };
+    if ["true", "yes", "on"]
+        .iter()
+        .any(|t| value.eq_ignore_ascii_case(t))
+    {
+        return Some(true);
+    }
+    if value.is_empty()
+        || ["false", "no", "off"]
+            .iter()
+            .any(|f| value.eq_ignore_ascii_case(f))
+    {
+        return Some(false);
+    }
+    parse_int(value).map(|n| n != 0)
+}
+
+/// Parses an integer with an optional `k`, `m` or `g` suffix (powers of 1024) like git.
+///
+/// Returns [None] if the value is not a number or does not fit into an [i64].
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    number.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+/// A type values can be read and written as, like `git config --type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Path,
+    Color,
+}
+
+impl ValueType {
+    /// Returns the canonical form of `value`: `true` or `false` for booleans, a plain number
+    /// for integers, an expanded path or an ANSI escape sequence for colors.
+    ///
+    /// Returns [None] if the value is not valid for the type.
+    pub fn normalize(self, value: Option<&str>) -> Option<String> {
+        match self {
+            ValueType::Bool => parse_bool(value).map(|value| value.to_string()),
+            ValueType::Int => value.and_then(parse_int).map(|value| value.to_string()),
+            ValueType::Path => value.map(|path| utils::expand_home(path).display().to_string()),
+            ValueType::Color => value.and_then(utils::parse_color),
+        }
+    }
+
+    /// Returns the error for an invalid `value` of `name`, where `location` says where the
+    /// value was read from (e.g. ` in file .git/config`).
+    pub fn invalid(self, name: &str, value: Option<&str>, location: &str) -> GitError {
+        let message = match (self, value) {
+            (ValueType::Bool, _) => format!(
+                "fatal: bad boolean config value '{}' for '{}'{}",
+                value.unwrap_or(""),
+                name,
+                location
+            ),
+            (_, None) => format!("error: missing value for '{}'", name),
+            (ValueType::Int, Some(value)) => format!(
+                "fatal: bad numeric config value '{}' for '{}'{}: invalid unit",
+                value, name, location
+            ),
+            (_, Some(value)) => format!("error: invalid color value: {}", value),
+        };
+        GitError::ConfigError {
+            path: PathBuf::new(),
+            message,
+        }
+    }
+}
+
+impl FromStr for ValueType {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        match s {
+            "bool" => Ok(ValueType::Bool),
+            "int" => Ok(ValueType::Int),
+            "path" => Ok(ValueType::Path),
+            "color" => Ok(ValueType::Color),
+            _ => Err(GitError::ConfigError {
+                path: PathBuf::new(),
+                message: format!("error: unrecognized --type argument, {}", s),
+            }),
+        }
+    }
+}
+
+/// Sets `name` to `value` in the config file at `path`, or removes all its values if `value`
+/// is [None]. The file is created if it does not exist.
+///
+/// # Errors
+///
+/// * [crate::GitError::ConfigError]: The file can not be parsed, the name is invalid, or it
+///   has multiple values
+/// * [crate::GitError::LockError]: The config file is locked by another process
+/// * [crate::GitError::IOError]: The config file can not be read or written
+pub fn set_value<P: AsRef<Path>>(path: P, name: &str, value: Option<&str>) -> GitResult<()> {
+    let mut config = ConfigFile::open(path)?;
+    match value {
+        Some(value) => config.set(name, value)?,
+        None => {
+            config.unset_all(name, None)?;
+        }
+    }
+    config.write()
+}
+
+/// Renames the section `old` (for example `branch.main`) to `new` in the config file at `path`,
+/// or removes it with all its values if `new` is [None].
+///
+/// # Errors
+///
+/// * [crate::GitError::ConfigError]: The file can not be parsed
+/// * [crate::GitError::LockError]: The config file is locked by another process
+/// * [crate::GitError::IOError]: The config file can not be read or written
+pub fn rename_section<P: AsRef<Path>>(path: P, old: &str, new: Option<&str>) -> GitResult<()> {
+    let path = path.as_ref();
+    if !path.is_file() {
+        return Ok(());
+    }
+    let mut config = ConfigFile::open(path)?;
+    if config.rename_section(old, new)? {
+        config.write()?;
+    }
+    Ok(())
+}
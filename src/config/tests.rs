@@ -0,0 +1,306 @@
+use super::*;
+use crate::test_utils;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+const SAMPLE: &str = "# leading comment
+[core]
+\tbare = false ; trailing comment
+\tlogAllRefUpdates
+[Remote \"origin\"]
+\turl = \"https://example.com/repo.git\"
+\tfetch = +refs/heads/*:refs/remotes/origin/*
+[alias]
+\tlg = log --oneline \\
+\t     --graph
+\tq = \"a # not a comment\" # a comment
+\tesc = tab\\there\\nnewline \"quoted\\\\\"
+[branch.Main]
+\tspaced =   several   words  
+";
+
+#[test]
+fn test_parse() -> Result<(), Box<dyn Error>> {
+    let config = ConfigFile::parse(SAMPLE, "config")?;
+
+    assert_eq!(config.get("core.bare")?, Some(Some("false".to_string())));
+    assert_eq!(config.get("Core.LogAllRefUpdates")?, Some(None));
+    assert_eq!(
+        config.get("remote.origin.url")?,
+        Some(Some("https://example.com/repo.git".to_string()))
+    );
+    // Subsections are case sensitive.
+    assert_eq!(config.get("remote.ORIGIN.url")?, None);
+    assert_eq!(
+        config.get("alias.lg")?,
+        Some(Some("log --oneline       --graph".to_string()))
+    );
+    assert_eq!(
+        config.get("alias.q")?,
+        Some(Some("a # not a comment".to_string()))
+    );
+    assert_eq!(
+        config.get("alias.esc")?,
+        Some(Some("tab\there\nnewline quoted\\".to_string()))
+    );
+    // The deprecated `[section.subsection]` form is lower cased.
+    assert_eq!(
+        config.get("branch.main.spaced")?,
+        Some(Some("several   words".to_string()))
+    );
+    assert_eq!(config.to_string(), SAMPLE);
+    Ok(())
+}
+
+#[test]
+fn test_parse_errors() {
+    for data in &[
+        "key = value\n",
+        "[core\nbare = true\n",
+        "[core]\n\tbare = \"unterminated\n",
+        "[core]\n\tbad\\escape = 1\n",
+        "[core]\n\tvalue = bad\\escape\n",
+        "[section \"sub]\n",
+    ] {
+        let error = ConfigFile::parse(data, "config").unwrap_err();
+        assert!(
+            error.to_string().starts_with("fatal: bad config line"),
+            "{:?}",
+            data
+        );
+    }
+    for (data, line) in &[
+        ("[a]\nx = 1\n!\n", 3),
+        ("[a]\nb\n[c\n", 3),
+        ("[a]\nb\n[c \"d\n", 3),
+        ("[a]\nb\n[c \"d\"\n", 4),
+    ] {
+        let error = ConfigFile::parse(data, "config").unwrap_err();
+        let expected = format!("fatal: bad config line {} in file config", line);
+        assert_eq!(error.to_string(), expected, "{:?}", data);
+    }
+}
+
+#[test]
+fn test_edit() -> Result<(), Box<dyn Error>> {
+    let mut config = ConfigFile::parse(
+        "[a]\n\tx = 1 # keep\n\n# about b\n[b] y = 2\n[c]\n\tz = 1\n\tz = 2",
+        "config",
+    )?;
+
+    config.set("a.w", "new")?;
+    config.set("A.X", "one")?;
+    config.add("c.z", "3")?;
+    config.set("d.sub.key", " needs;quotes\t")?;
+    assert_eq!(
+        config.to_string(),
+        "[a]\n\tX = one\n\tw = new\n\n# about b\n[b] y = 2\n[c]\n\tz = 1\n\tz = 2\n\tz = 3\n\
+         [d \"sub\"]\n\tkey = \" needs;quotes\\t\"\n"
+    );
+    assert!(config.set("c.z", "4").is_err());
+    assert_eq!(
+        config.get("d.sub.key")?,
+        Some(Some(" needs;quotes\t".to_string()))
+    );
+
+    let pattern = ValuePattern::new("^[12]$")?;
+    config.replace_all("c.z", "12", Some(&pattern))?;
+    assert_eq!(
+        config.get_all("c.z")?,
+        [Some("12".to_string()), Some("3".to_string())]
+    );
+    assert_eq!(config.unset_all("c.z", None)?, 2);
+    // Sections left empty go away.
+    assert!(config.unset("b.y", None)?);
+    assert!(!config.unset("b.y", None)?);
+    assert!(config.rename_section("d.sub", Some("e"))?);
+    assert_eq!(
+        config.get("e.key")?,
+        Some(Some(" needs;quotes\t".to_string()))
+    );
+    // Comments up to the next section belong to the removed one.
+    assert!(config.rename_section("a", None)?);
+    assert_eq!(config.to_string(), "[e]\n\tkey = \" needs;quotes\\t\"\n");
+
+    assert!(config.set("novalue", "1").is_err());
+    assert!(config.set("a.1x", "1").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_values() {
+    assert_eq!(parse_bool(None), Some(true));
+    assert_eq!(parse_bool(Some("Yes")), Some(true));
+    assert_eq!(parse_bool(Some("")), Some(false));
+    assert_eq!(parse_bool(Some("off")), Some(false));
+    assert_eq!(parse_bool(Some("2")), Some(true));
+    assert_eq!(parse_bool(Some("maybe")), None);
+    assert_eq!(parse_int("42"), Some(42));
+    assert_eq!(parse_int("-3k"), Some(-3072));
+    assert_eq!(parse_int("1M"), Some(1 << 20));
+    assert_eq!(parse_int("2g"), Some(2 << 30));
+    assert_eq!(parse_int("1t"), None);
+    assert_eq!(parse_int("99999999999g"), None);
+}
+
+#[test]
+fn test_write_locked() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_config_write");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("config");
+
+    set_value(&path, "user.name", Some("A U Thor"))?;
+    set_value(&path, "branch.main.remote", Some("origin"))?;
+    assert_eq!(read_value(&path, "user.name")?.as_deref(), Some("A U Thor"));
+    rename_section(&path, "branch.main", Some("branch.trunk"))?;
+    assert_eq!(
+        fs::read_to_string(&path)?,
+        "[user]\n\tname = A U Thor\n[branch \"trunk\"]\n\tremote = origin\n"
+    );
+
+    fs::write(dir.join("config.lock"), "")?;
+    assert!(set_value(&path, "user.name", Some("Other")).is_err());
+    assert_eq!(read_value(&path, "user.name")?.as_deref(), Some("A U Thor"));
+
+    fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+fn setup_repo(name: &str, config: &str) -> Result<GitRepo, Box<dyn Error>> {
+    let repo = test_utils::setup_with_config(name, config)?;
+    fs::write(repo.gitpath().join("HEAD"), "ref: refs/heads/feature/x\n")?;
+    Ok(repo)
+}
+
+#[test]
+fn test_includes() -> Result<(), Box<dyn Error>> {
+    let repo = setup_repo(
+        "gitrs_test_config_includes",
+        "[user]\n\tname = Local\n[include]\n\tpath = ../shared.inc\n\
+         [includeIf \"gitdir:gitrs_test_config_includes/\"]\n\tpath = ../gitdir.inc\n\
+         [includeIf \"gitdir:elsewhere/\"]\n\tpath = ../never.inc\n\
+         [includeIf \"onbranch:feature/\"]\n\tpath = ../branch.inc\n",
+    )?;
+    let worktree = repo.worktree().unwrap();
+    fs::write(
+        worktree.join("shared.inc"),
+        "[user]\n\temail = a@b.c\n\tname = Shared\n",
+    )?;
+    fs::write(worktree.join("gitdir.inc"), "[test]\n\tgitdir = yes\n")?;
+    fs::write(worktree.join("never.inc"), "[test]\n\tnever = yes\n")?;
+    fs::write(worktree.join("branch.inc"), "[test]\n\tbranch = yes\n")?;
+
+    let path = repo.gitpath().join("config");
+    let config = GitConfig::from_file(&path, ConfigScope::Local, Some(&repo), true)?;
+    // Included values apply where the include is, so the local name is overridden.
+    assert_eq!(config.get("user.name")?, Some("Shared".to_string()));
+    let email = config.get_entry("user.email")?.unwrap();
+    assert_eq!(email.scope(), ConfigScope::Local);
+    assert_eq!(
+        email.origin(),
+        &ConfigOrigin::File(repo.gitpath().join("../shared.inc"))
+    );
+    assert_eq!(config.get("test.gitdir")?, Some("yes".to_string()));
+    assert_eq!(config.get("test.never")?, None);
+    assert_eq!(config.get("test.branch")?, Some("yes".to_string()));
+
+    let config = GitConfig::from_file(&path, ConfigScope::Local, Some(&repo), false)?;
+    assert_eq!(config.get("user.name")?, Some("Local".to_string()));
+
+    // Includes of itself nest too deeply.
+    fs::write(
+        worktree.join("shared.inc"),
+        "[include]\n\tpath = shared.inc\n",
+    )?;
+    assert!(GitConfig::from_file(&path, ConfigScope::Local, Some(&repo), true).is_err());
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
+
+#[test]
+fn test_layers() -> Result<(), Box<dyn Error>> {
+    let repo = setup_repo(
+        "gitrs_test_config_layers",
+        "[test]\n\tlayer = local\n\tlocal = true\n[extensions]\n\tworktreeConfig = true\n",
+    )?;
+    let worktree = repo.worktree().unwrap();
+    let global = worktree.join("global");
+    fs::write(
+        &global,
+        "[test]\n\tlayer = global\n\tglobal = 2k\n\tword = maybe\n",
+    )?;
+    fs::write(
+        repo.gitpath().join("config.worktree"),
+        "[test]\n\tlayer = worktree\n",
+    )?;
+
+    let mut vars: HashMap<String, String> = HashMap::new();
+    vars.insert("GIT_CONFIG_NOSYSTEM".to_string(), "1".to_string());
+    vars.insert(
+        "GIT_CONFIG_GLOBAL".to_string(),
+        global.display().to_string(),
+    );
+    let config = GitConfig::load_with_env(Some(&repo), &|name| vars.get(name).cloned())?;
+    let layers: Vec<_> = config
+        .get_all("test.layer")?
+        .iter()
+        .map(|value| (value.scope(), value.value().unwrap().to_string()))
+        .collect();
+    assert_eq!(
+        layers,
+        vec![
+            (ConfigScope::Global, "global".to_string()),
+            (ConfigScope::Local, "local".to_string()),
+            (ConfigScope::Worktree, "worktree".to_string()),
+        ]
+    );
+    assert_eq!(config.get_int("test.global")?, Some(2048));
+    assert!(config.get_bool("test.local", false)?);
+    assert!(config.get_bool("test.word", false).is_err());
+
+    vars.insert("GIT_CONFIG_COUNT".to_string(), "1".to_string());
+    vars.insert("GIT_CONFIG_KEY_0".to_string(), "Test.Layer".to_string());
+    vars.insert("GIT_CONFIG_VALUE_0".to_string(), "env".to_string());
+    let parameters = layers::add_config_parameter(String::new(), "test.quoted=it's")?;
+    let parameters = layers::add_config_parameter(parameters, "test.implicit")?;
+    assert!(layers::add_config_parameter(String::new(), "novalue").is_err());
+    vars.insert("GIT_CONFIG_PARAMETERS".to_string(), parameters);
+    let config = GitConfig::load_with_env(Some(&repo), &|name| vars.get(name).cloned())?;
+    let layer = config.get_entry("test.layer")?.unwrap();
+    assert_eq!(layer.value(), Some("env"));
+    assert_eq!(layer.origin(), &ConfigOrigin::CommandLine);
+    assert_eq!(config.get("test.quoted")?, Some("it's".to_string()));
+    assert_eq!(config.get("test.implicit")?, Some("true".to_string()));
+
+    vars.insert("GIT_CONFIG_COUNT".to_string(), "x".to_string());
+    assert!(GitConfig::load_with_env(Some(&repo), &|name| vars.get(name).cloned()).is_err());
+
+    fs::remove_dir_all(worktree)?;
+    Ok(())
+}
+
+#[test]
+fn test_value_types() -> Result<(), Box<dyn Error>> {
+    assert_eq!(ValueType::Bool.normalize(None).as_deref(), Some("true"));
+    assert_eq!(
+        ValueType::Bool.normalize(Some("Off")).as_deref(),
+        Some("false")
+    );
+    assert_eq!(ValueType::Bool.normalize(Some("maybe")), None);
+    assert_eq!(
+        ValueType::Int.normalize(Some("1k")).as_deref(),
+        Some("1024")
+    );
+    assert_eq!(ValueType::Int.normalize(None), None);
+    assert_eq!(
+        ValueType::Color.normalize(Some("green")).as_deref(),
+        Some("\x1b[32m")
+    );
+    assert_eq!("color".parse::<ValueType>()?, ValueType::Color);
+    assert!("date".parse::<ValueType>().is_err());
+    Ok(())
+}
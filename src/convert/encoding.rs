@@ -0,0 +1,155 @@
+use std::convert::TryFrom;
+
+/// Returns true if `encoding` names UTF-8, which never needs re-encoding.
+pub fn is_utf8(encoding: &str) -> bool {
+    let normalized = normalize(encoding);
+    normalized == "UTF8"
+}
+
+/// Returns true if the encoding must not have a byte order mark in the worktree.
+pub fn prohibits_bom(encoding: &str) -> bool {
+    matches!(
+        normalize(encoding).as_str(),
+        "UTF16LE" | "UTF16BE" | "UTF32LE" | "UTF32BE"
+    )
+}
+
+/// Returns true if the encoding must have a byte order mark in the worktree.
+pub fn requires_bom(encoding: &str) -> bool {
+    matches!(normalize(encoding).as_str(), "UTF16" | "UTF32")
+}
+
+/// Returns true if `data` starts with a UTF-16 or UTF-32 byte order mark.
+pub fn has_bom(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xFE])
+        || data.starts_with(&[0xFE, 0xFF])
+        || data.starts_with(&[0x00, 0x00, 0xFE, 0xFF])
+}
+
+fn normalize(encoding: &str) -> String {
+    encoding
+        .chars()
+        .filter(|ch| *ch != '-' && *ch != '_')
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+/// Decodes `data` in `encoding` to UTF-8.
+///
+/// Returns [None] if the encoding is unknown or `data` is not valid in it.
+pub fn decode(encoding: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let text = match normalize(encoding).as_str() {
+        "UTF8" => String::from_utf8(data.to_vec()).ok()?,
+        "UTF16" => match data {
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, false)?,
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, true)?,
+            _ => decode_utf16(data, false)?,
+        },
+        "UTF16LEBOM" => decode_utf16(data.strip_prefix(&[0xFF, 0xFE])?, true)?,
+        "UTF16LE" => decode_utf16(data, true)?,
+        "UTF16BE" => decode_utf16(data, false)?,
+        "UTF32" => match data {
+            [0x00, 0x00, 0xFE, 0xFF, rest @ ..] => decode_utf32(rest, false)?,
+            [0xFF, 0xFE, 0x00, 0x00, rest @ ..] => decode_utf32(rest, true)?,
+            _ => decode_utf32(data, false)?,
+        },
+        "UTF32LE" => decode_utf32(data, true)?,
+        "UTF32BE" => decode_utf32(data, false)?,
+        "ISO88591" | "LATIN1" => data.iter().map(|&b| b as char).collect(),
+        "ASCII" | "USASCII" => {
+            if !data.is_ascii() {
+                return None;
+            }
+            String::from_utf8(data.to_vec()).ok()?
+        }
+        _ => return None,
+    };
+    Some(text.into_bytes())
+}
+
+/// Encodes UTF-8 `data` into `encoding`.
+///
+/// Returns [None] if the encoding is unknown, `data` is not valid UTF-8 or it contains characters
+/// that can not be represented in the encoding.
+pub fn encode(encoding: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let text = std::str::from_utf8(data).ok()?;
+    let encoded = match normalize(encoding).as_str() {
+        "UTF8" => data.to_vec(),
+        "UTF16" => [&[0xFE, 0xFF][..], &encode_utf16(text, false)].concat(),
+        "UTF16LEBOM" => [&[0xFF, 0xFE][..], &encode_utf16(text, true)].concat(),
+        "UTF16LE" => encode_utf16(text, true),
+        "UTF16BE" => encode_utf16(text, false),
+        "UTF32" => [&[0x00, 0x00, 0xFE, 0xFF][..], &encode_utf32(text, false)].concat(),
+        "UTF32LE" => encode_utf32(text, true),
+        "UTF32BE" => encode_utf32(text, false),
+        "ISO88591" | "LATIN1" => text
+            .chars()
+            .map(|ch| u8::try_from(ch as u32).ok())
+            .collect::<Option<Vec<u8>>>()?,
+        "ASCII" | "USASCII" => {
+            if !text.is_ascii() {
+                return None;
+            }
+            data.to_vec()
+        }
+        _ => return None,
+    };
+    Some(encoded)
+}
+
+fn decode_utf16(data: &[u8], little_endian: bool) -> Option<String> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = data.chunks(2).map(|pair| {
+        if little_endian {
+            u16::from_le_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_be_bytes([pair[0], pair[1]])
+        }
+    });
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+fn encode_utf16(text: &str, little_endian: bool) -> Vec<u8> {
+    text.encode_utf16()
+        .flat_map(|unit| {
+            if little_endian {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            }
+        })
+        .collect()
+}
+
+fn decode_utf32(data: &[u8], little_endian: bool) -> Option<String> {
+    if !data.len().is_multiple_of(4) {
+        return None;
+    }
+    data.chunks(4)
+        .map(|quad| {
+            let bytes = [quad[0], quad[1], quad[2], quad[3]];
+            let code = if little_endian {
+                u32::from_le_bytes(bytes)
+            } else {
+                u32::from_be_bytes(bytes)
+            };
+            char::from_u32(code)
+        })
+        .collect()
+}
+
+fn encode_utf32(text: &str, little_endian: bool) -> Vec<u8> {
+    text.chars()
+        .flat_map(|ch| {
+            if little_endian {
+                (ch as u32).to_le_bytes()
+            } else {
+                (ch as u32).to_be_bytes()
+            }
+        })
+        .collect()
+}
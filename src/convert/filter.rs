@@ -0,0 +1,269 @@
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+
+use crate::config::GitConfig;
+use crate::utils::pktline;
+use crate::GitResult;
+
+/// Which way a file is being filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the worktree to the repository.
+    Clean,
+    /// From the repository to the worktree.
+    Smudge,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Clean => "clean",
+            Direction::Smudge => "smudge",
+        }
+    }
+}
+
+/// A filter driver configured with `filter.<name>.clean`, `smudge`, `process` and `required`.
+#[derive(Debug, Clone)]
+pub struct FilterDriver {
+    pub clean: Option<String>,
+    pub smudge: Option<String>,
+    pub process: Option<String>,
+    pub required: bool,
+}
+
+impl FilterDriver {
+    /// Reads the driver called `name` from `config`.
+    pub fn load(config: &GitConfig, name: &str) -> GitResult<Self> {
+        let key = |key: &str| format!("filter.{}.{}", name, key);
+        Ok(Self {
+            clean: config.get(&key("clean"))?,
+            smudge: config.get(&key("smudge"))?,
+            process: config.get(&key("process"))?,
+            required: config.get_bool(&key("required"), false)?,
+        })
+    }
+
+    /// Returns the single-file command for `direction`, if configured.
+    pub fn command(&self, direction: Direction) -> Option<&str> {
+        match direction {
+            Direction::Clean => self.clean.as_deref(),
+            Direction::Smudge => self.smudge.as_deref(),
+        }
+    }
+}
+
+/// Quotes `s` for the shell with single quotes.
+fn sq_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Expands `%f` (the quoted path) and `%%` in a filter command.
+fn expand_command(cmd: &str, path: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = cmd.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('%', Some('f')) => {
+                chars.next();
+                expanded.push_str(&sq_quote(path));
+            }
+            ('%', Some('%')) => {
+                chars.next();
+                expanded.push('%');
+            }
+            _ => expanded.push(ch),
+        }
+    }
+    expanded
+}
+
+fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd);
+    command
+}
+
+/// Runs a single-file filter command with `data` on its stdin and returns its stdout.
+pub fn run_command(cmd: &str, path: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut child = shell(&expand_command(cmd, path))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Feed stdin from another thread so a filter producing output early can not deadlock.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = data.to_vec();
+    let writer = thread::spawn(move || {
+        // The filter may legitimately stop reading early.
+        match stdin.write_all(&input) {
+            Err(error) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    });
+
+    let mut output = Vec::new();
+    child.stdout.take().unwrap().read_to_end(&mut output)?;
+    let status = child.wait()?;
+    writer.join().unwrap()?;
+
+    if status.success() {
+        Ok(output)
+    } else {
+        Err(io::Error::other(format!(
+            "external filter '{}' failed",
+            cmd
+        )))
+    }
+}
+
+/// The response of a long-running filter process for one file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProcessStatus {
+    Success(Vec<u8>),
+    Delayed,
+    Error,
+    Abort,
+}
+
+/// A long-running filter process speaking git's filter protocol (version 2) over pkt-lines.
+#[derive(Debug)]
+pub struct ProcessFilter {
+    child: Child,
+    stdin: Option<BufWriter<ChildStdin>>,
+    stdout: BufReader<ChildStdout>,
+    capabilities: Vec<String>,
+    aborted: Vec<String>,
+}
+
+impl ProcessFilter {
+    /// Starts `cmd` and performs the version and capability handshake.
+    pub fn start(cmd: &str) -> io::Result<Self> {
+        let mut child = shell(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = Some(BufWriter::new(child.stdin.take().unwrap()));
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut filter = Self {
+            child,
+            stdin,
+            stdout,
+            capabilities: Vec::new(),
+            aborted: Vec::new(),
+        };
+
+        pktline::write_text(filter.stdin(), "git-filter-client")?;
+        pktline::write_text(filter.stdin(), "version=2")?;
+        pktline::write_flush(filter.stdin())?;
+        let welcome = pktline::read_text_list(&mut filter.stdout)?;
+        if welcome.first().map(String::as_str) != Some("git-filter-server")
+            || !welcome.iter().any(|line| line == "version=2")
+        {
+            return Err(invalid_data(format!(
+                "unexpected filter process handshake from '{}'",
+                cmd
+            )));
+        }
+
+        for capability in &["clean", "smudge", "delay"] {
+            pktline::write_text(filter.stdin(), &format!("capability={}", capability))?;
+        }
+        pktline::write_flush(filter.stdin())?;
+        filter.capabilities = pktline::read_text_list(&mut filter.stdout)?
+            .into_iter()
+            .filter_map(|line| line.strip_prefix("capability=").map(String::from))
+            .collect();
+
+        Ok(filter)
+    }
+
+    /// Returns true if the process announced `capability` and has not aborted it.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+            && !self.aborted.iter().any(|c| c == capability)
+    }
+
+    /// Sends a file through the process and reads its response.
+    pub fn filter(
+        &mut self,
+        direction: Direction,
+        path: &str,
+        data: &[u8],
+        can_delay: bool,
+    ) -> io::Result<ProcessStatus> {
+        pktline::write_text(self.stdin(), &format!("command={}", direction.name()))?;
+        pktline::write_text(self.stdin(), &format!("pathname={}", path))?;
+        if can_delay && self.supports("delay") {
+            pktline::write_text(self.stdin(), "can-delay=1")?;
+        }
+        pktline::write_flush(self.stdin())?;
+        pktline::write_data(self.stdin(), data)?;
+
+        let status = self.read_status(None)?;
+        let status = match status.as_str() {
+            "success" => {
+                let output = pktline::read_data(&mut self.stdout)?;
+                // The process may revoke its success after sending the contents.
+                match self.read_status(Some("success"))?.as_str() {
+                    "success" => ProcessStatus::Success(output),
+                    "abort" => ProcessStatus::Abort,
+                    _ => ProcessStatus::Error,
+                }
+            }
+            "delayed" if can_delay => ProcessStatus::Delayed,
+            "abort" => ProcessStatus::Abort,
+            _ => ProcessStatus::Error,
+        };
+        if status == ProcessStatus::Abort {
+            self.aborted.push(direction.name().to_string());
+        }
+        Ok(status)
+    }
+
+    /// Asks the process which delayed paths are ready to be fetched.
+    pub fn list_available_blobs(&mut self) -> io::Result<Vec<String>> {
+        pktline::write_text(self.stdin(), "command=list_available_blobs")?;
+        pktline::write_flush(self.stdin())?;
+        let paths = pktline::read_text_list(&mut self.stdout)?
+            .into_iter()
+            .filter_map(|line| line.strip_prefix("pathname=").map(String::from))
+            .collect();
+        match self.read_status(None)?.as_str() {
+            "success" => Ok(paths),
+            status => Err(invalid_data(format!(
+                "unexpected status '{}' when listing delayed blobs",
+                status
+            ))),
+        }
+    }
+
+    fn stdin(&mut self) -> &mut BufWriter<ChildStdin> {
+        self.stdin.as_mut().unwrap()
+    }
+
+    /// Reads a key/value list and returns its `status` (or `default` if the list is empty).
+    fn read_status(&mut self, default: Option<&str>) -> io::Result<String> {
+        let lines = pktline::read_text_list(&mut self.stdout)?;
+        let status = lines
+            .iter()
+            .rev()
+            .find_map(|line| line.strip_prefix("status="))
+            .or(default)
+            .ok_or_else(|| invalid_data("filter process did not send a status".to_string()))?;
+        Ok(status.to_string())
+    }
+}
+
+impl Drop for ProcessFilter {
+    fn drop(&mut self) {
+        // Closing stdin tells the process to exit.
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
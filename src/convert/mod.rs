@@ -0,0 +1,609 @@
+mod encoding;
+mod filter;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub(crate) use self::encoding::decode;
+use self::filter::{Direction, FilterDriver, ProcessFilter, ProcessStatus};
+
+use crate::config::{self, GitConfig};
+use crate::{AttrValue, GitAttributes, GitError, GitRepo, GitResult};
+
+/// The value of `core.autocrlf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoCrlf {
+    False,
+    True,
+    Input,
+}
+
+/// A line ending from `core.eol` or the `eol` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+    Native,
+}
+
+/// The value of `core.safecrlf`: what to do when a conversion is irreversible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SafeCrlf {
+    False,
+    Warn,
+    Fail,
+}
+
+/// The line ending conversion chosen for a single path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CrlfAction {
+    Undefined,
+    Binary,
+    Text,
+    TextInput,
+    TextCrlf,
+    Auto,
+    AutoInput,
+    AutoCrlf,
+}
+
+/// The result of running a filter driver.
+#[derive(Debug)]
+enum Filtered {
+    /// There is no filter, or an optional one failed.
+    Unchanged,
+    Output(Vec<u8>),
+    Delayed,
+}
+
+/// Character statistics used to decide whether a buffer is text.
+#[derive(Debug, Default, Clone, Copy)]
+struct TextStat {
+    nul: usize,
+    lonecr: usize,
+    lonelf: usize,
+    crlf: usize,
+    printable: usize,
+    nonprintable: usize,
+}
+
+impl TextStat {
+    fn gather(data: &[u8]) -> Self {
+        let mut stats = Self::default();
+        let mut i = 0;
+        while i < data.len() {
+            match data[i] {
+                b'\r' => {
+                    if data.get(i + 1) == Some(&b'\n') {
+                        stats.crlf += 1;
+                        i += 1;
+                    } else {
+                        stats.lonecr += 1;
+                    }
+                }
+                b'\n' => stats.lonelf += 1,
+                127 => stats.nonprintable += 1,
+                0x08 | b'\t' | 0x1b | 0x0c => stats.printable += 1,
+                0 => {
+                    stats.nul += 1;
+                    stats.nonprintable += 1;
+                }
+                ch if ch < 32 => stats.nonprintable += 1,
+                _ => stats.printable += 1,
+            }
+            i += 1;
+        }
+        // A trailing EOF character is not counted as non-printable.
+        if data.last() == Some(&0x1a) {
+            stats.nonprintable -= 1;
+        }
+        stats
+    }
+
+    fn is_binary(&self) -> bool {
+        self.lonecr > 0 || self.nul > 0 || (self.printable >> 7) < self.nonprintable
+    }
+}
+
+/// Returns true if git would treat `data` as binary.
+pub fn is_binary(data: &[u8]) -> bool {
+    TextStat::gather(data).is_binary()
+}
+
+/// Converts file contents between their worktree and repository representations.
+///
+/// The "clean" direction ([GitConverter::to_git]) is used when hashing or adding a file and
+/// the "smudge" direction ([GitConverter::to_worktree]) when checking one out. The conversions
+/// are driven by `core.autocrlf`, `core.eol`, `core.safecrlf` and the `text`, `eol`, `crlf` and
+/// `working-tree-encoding` attributes.
+///
+/// Paths with a `filter=<name>` attribute are also passed through the `filter.<name>.clean` and
+/// `filter.<name>.smudge` commands, or through a long-running `filter.<name>.process` which is
+/// kept alive for the lifetime of the converter.
+#[derive(Debug)]
+pub struct GitConverter {
+    attributes: GitAttributes,
+    auto_crlf: AutoCrlf,
+    core_eol: Eol,
+    safe_crlf: SafeCrlf,
+    config: GitConfig,
+    drivers: HashMap<String, FilterDriver>,
+    processes: HashMap<String, Option<ProcessFilter>>,
+    delayed: Vec<(String, String, Vec<u8>)>,
+    warnings: Vec<String>,
+}
+
+impl GitConverter {
+    /// Creates a converter using the config and attributes of `repo`.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading the config or the attributes files.
+    pub fn new(repo: &GitRepo) -> GitResult<Self> {
+        let config = GitConfig::load(Some(repo))?;
+        let auto_crlf = match config.get("core.autocrlf")? {
+            Some(value) if value.eq_ignore_ascii_case("input") => AutoCrlf::Input,
+            Some(value) if config::is_true(&value) => AutoCrlf::True,
+            _ => AutoCrlf::False,
+        };
+        let core_eol = match config.get("core.eol")? {
+            Some(value) if value.eq_ignore_ascii_case("lf") => Eol::Lf,
+            Some(value) if value.eq_ignore_ascii_case("crlf") => Eol::Crlf,
+            _ => Eol::Native,
+        };
+        let safe_crlf = match config.get("core.safecrlf")? {
+            Some(value) if value.eq_ignore_ascii_case("warn") => SafeCrlf::Warn,
+            Some(value) if config::is_true(&value) => SafeCrlf::Fail,
+            Some(_) => SafeCrlf::False,
+            None => SafeCrlf::Warn,
+        };
+
+        Ok(Self {
+            attributes: GitAttributes::new(repo)?,
+            auto_crlf,
+            core_eol,
+            safe_crlf,
+            config,
+            drivers: HashMap::new(),
+            processes: HashMap::new(),
+            delayed: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Overrides `core.safecrlf`.
+    pub fn set_safe_crlf(&mut self, safe_crlf: SafeCrlf) {
+        self.safe_crlf = safe_crlf;
+    }
+
+    /// Returns the warnings about attributes files, the failures of optional filters and the
+    /// `core.safecrlf` warnings since the last call, as printed by git.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        let mut warnings = self.attributes.take_warnings();
+        warnings.append(&mut self.warnings);
+        warnings
+    }
+
+    /// Returns the attributes used by this converter.
+    pub fn attributes(&mut self) -> &mut GitAttributes {
+        &mut self.attributes
+    }
+
+    /// Converts worktree contents of `path` into the contents stored in the repository.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConversionError]: The working tree encoding is invalid for the contents or
+    ///   `core.safecrlf` is true and the line ending conversion is irreversible
+    /// * [GitError::FilterError]: A required clean filter failed
+    pub fn to_git(&mut self, path: &str, data: &[u8]) -> GitResult<Vec<u8>> {
+        let filtered = match self.apply_filter(path, data, Direction::Clean, false)? {
+            Filtered::Output(output) => Some(output),
+            _ => None,
+        };
+        let data = filtered.as_deref().unwrap_or(data);
+        let encoding = self.working_tree_encoding(path)?;
+        let data = match encoding {
+            Some(encoding) => encode_to_git(path, data, &encoding)?,
+            None => data.to_vec(),
+        };
+        let action = self.crlf_action(path)?;
+        self.crlf_to_git(path, data, action)
+    }
+
+    /// Converts the repository contents of `path` into the contents written to the worktree.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConversionError]: The contents can not be represented in the working tree
+    ///   encoding
+    /// * [GitError::FilterError]: A required smudge filter failed
+    pub fn to_worktree(&mut self, path: &str, data: &[u8]) -> GitResult<Vec<u8>> {
+        let data = self.worktree_without_filter(path, data)?;
+        match self.apply_filter(path, &data, Direction::Smudge, false)? {
+            Filtered::Output(output) => Ok(output),
+            _ => Ok(data),
+        }
+    }
+
+    /// Like [GitConverter::to_worktree], but allows a long-running filter process to delay the
+    /// file.
+    ///
+    /// Returns [None] if the file was delayed; its contents are then returned by
+    /// [GitConverter::finish_delayed].
+    ///
+    /// # Errors
+    ///
+    /// Same as [GitConverter::to_worktree].
+    pub fn to_worktree_delayed(&mut self, path: &str, data: &[u8]) -> GitResult<Option<Vec<u8>>> {
+        let data = self.worktree_without_filter(path, data)?;
+        match self.apply_filter(path, &data, Direction::Smudge, true)? {
+            Filtered::Output(output) => Ok(Some(output)),
+            Filtered::Unchanged => Ok(Some(data)),
+            Filtered::Delayed => Ok(None),
+        }
+    }
+
+    /// Waits for every file delayed by [GitConverter::to_worktree_delayed] and returns the paths
+    /// with their worktree contents.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::FilterError]: A filter process failed or reported unknown paths
+    pub fn finish_delayed(&mut self) -> GitResult<Vec<(String, Vec<u8>)>> {
+        let mut finished = Vec::new();
+        while let Some((name, _, _)) = self.delayed.first().cloned() {
+            let process = match self.processes.get_mut(&name).and_then(Option::as_mut) {
+                Some(process) => process,
+                None => return Err(self.delayed_error(&name, "is not available anymore")),
+            };
+            let available = process
+                .list_available_blobs()
+                .map_err(|error| filter_error(&name, "", error.to_string()))?;
+            if available.is_empty() {
+                return Err(self.delayed_error(
+                    &name,
+                    "is not available anymore although not all paths have been filtered",
+                ));
+            }
+
+            for path in available {
+                let i = match self
+                    .delayed
+                    .iter()
+                    .position(|(n, p, _)| *n == name && *p == path)
+                {
+                    Some(i) => i,
+                    None => {
+                        let message = format!(
+                            "external filter '{}' signaled that '{}' is now available although it has not been delayed earlier",
+                            name, path
+                        );
+                        return Err(filter_error(&name, &path, message));
+                    }
+                };
+                let (_, path, data) = self.delayed.remove(i);
+                let process = self.processes.get_mut(&name).unwrap().as_mut().unwrap();
+                let contents = match process.filter(Direction::Smudge, &path, &[], false) {
+                    Ok(ProcessStatus::Success(output)) => output,
+                    _ => self.filter_failed(&name, &path, Direction::Smudge, data)?,
+                };
+                finished.push((path, contents));
+            }
+        }
+        Ok(finished)
+    }
+
+    fn worktree_without_filter(&mut self, path: &str, data: &[u8]) -> GitResult<Vec<u8>> {
+        let action = self.crlf_action(path)?;
+        let data = self.crlf_to_worktree(data, action);
+        match self.working_tree_encoding(path)? {
+            Some(encoding) => match encoding::encode(&encoding, &data) {
+                Some(encoded) => Ok(encoded),
+                None => Err(conversion_error(
+                    path,
+                    format!("failed to encode '{}' from UTF-8 to {}", path, encoding),
+                )),
+            },
+            None => Ok(data),
+        }
+    }
+
+    /// Runs the filter driver of `path` in `direction`.
+    ///
+    /// Delayed files are recorded in `self.delayed`.
+    fn apply_filter(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        direction: Direction,
+        can_delay: bool,
+    ) -> GitResult<Filtered> {
+        let name = match self.attributes.get(path, "filter")? {
+            AttrValue::Value(name) => name,
+            _ => return Ok(Filtered::Unchanged),
+        };
+        if !self.drivers.contains_key(&name) {
+            let driver = FilterDriver::load(&self.config, &name)?;
+            self.drivers.insert(name.clone(), driver);
+        }
+        let driver = &self.drivers[&name];
+
+        if let Some(cmd) = driver.process.clone() {
+            let warnings = &mut self.warnings;
+            let process = self.processes.entry(name.clone()).or_insert_with(|| {
+                match ProcessFilter::start(&cmd) {
+                    Ok(process) => Some(process),
+                    Err(error) => {
+                        warnings.push(format!(
+                            "error: cannot start external filter '{}': {}",
+                            cmd, error
+                        ));
+                        None
+                    }
+                }
+            });
+            if let Some(process) = process.as_mut().filter(|p| p.supports(direction.name())) {
+                match process.filter(direction, path, data, can_delay) {
+                    Ok(ProcessStatus::Success(output)) => return Ok(Filtered::Output(output)),
+                    Ok(ProcessStatus::Delayed) => {
+                        self.delayed.push((name, path.to_string(), data.to_vec()));
+                        return Ok(Filtered::Delayed);
+                    }
+                    Ok(_) => self
+                        .warnings
+                        .push(format!("error: external filter '{}' failed", cmd)),
+                    Err(error) => {
+                        self.warnings.push(format!(
+                            "error: external filter '{}' failed: {}",
+                            cmd, error
+                        ));
+                        self.processes.insert(name.clone(), None);
+                    }
+                }
+            }
+        } else if let Some(cmd) = driver.command(direction) {
+            match filter::run_command(cmd, path, data) {
+                Ok(output) => return Ok(Filtered::Output(output)),
+                Err(error) => self.warnings.push(format!("error: {}", error)),
+            }
+        }
+
+        self.filter_failed(&name, path, direction, Vec::new())
+            .map(|_| Filtered::Unchanged)
+    }
+
+    /// Returns the unfiltered `data` for a failed optional filter, or an error for a required one.
+    fn filter_failed(
+        &self,
+        name: &str,
+        path: &str,
+        direction: Direction,
+        data: Vec<u8>,
+    ) -> GitResult<Vec<u8>> {
+        if self.drivers.get(name).is_some_and(|driver| driver.required) {
+            let message = format!("{}: {} filter '{}' failed", path, direction.name(), name);
+            Err(filter_error(name, path, message))
+        } else {
+            Ok(data)
+        }
+    }
+
+    fn delayed_error(&self, name: &str, message: &str) -> GitError {
+        let message = format!("external filter '{}' {}", name, message);
+        filter_error(name, "", message)
+    }
+
+    fn working_tree_encoding(&mut self, path: &str) -> GitResult<Option<String>> {
+        match self.attributes.get(path, "working-tree-encoding")? {
+            AttrValue::Value(encoding) if !encoding::is_utf8(&encoding) => Ok(Some(encoding)),
+            AttrValue::Set | AttrValue::Unset => Err(conversion_error(
+                path,
+                "true/false are no valid working-tree-encodings".to_string(),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    fn crlf_action(&mut self, path: &str) -> GitResult<CrlfAction> {
+        let attrs = self.attributes.check(path, &["text", "crlf", "eol"])?;
+        let mut action = match &attrs[0].1 {
+            AttrValue::Set => CrlfAction::Text,
+            AttrValue::Unset => CrlfAction::Binary,
+            AttrValue::Value(value) if value == "auto" => CrlfAction::Auto,
+            _ => CrlfAction::Undefined,
+        };
+        if action == CrlfAction::Undefined {
+            action = match &attrs[1].1 {
+                AttrValue::Set => CrlfAction::Text,
+                AttrValue::Unset => CrlfAction::Binary,
+                AttrValue::Value(value) if value == "input" => CrlfAction::TextInput,
+                _ => CrlfAction::Undefined,
+            };
+        }
+        if action != CrlfAction::Binary {
+            let eol = match attrs[2].1.value() {
+                Some("lf") => Some(Eol::Lf),
+                Some("crlf") => Some(Eol::Crlf),
+                _ => None,
+            };
+            action = match (action, eol) {
+                (CrlfAction::Auto, Some(Eol::Lf)) => CrlfAction::AutoInput,
+                (CrlfAction::Auto, Some(Eol::Crlf)) => CrlfAction::AutoCrlf,
+                (_, Some(Eol::Lf)) => CrlfAction::TextInput,
+                (_, Some(Eol::Crlf)) => CrlfAction::TextCrlf,
+                (action, _) => action,
+            };
+        }
+
+        Ok(match (action, self.auto_crlf) {
+            (CrlfAction::Text, _) if self.text_eol_is_crlf() => CrlfAction::TextCrlf,
+            (CrlfAction::Text, _) => CrlfAction::TextInput,
+            (CrlfAction::Undefined, AutoCrlf::False) => CrlfAction::Binary,
+            (CrlfAction::Undefined, AutoCrlf::True) => CrlfAction::AutoCrlf,
+            (CrlfAction::Undefined, AutoCrlf::Input) => CrlfAction::AutoInput,
+            (action, _) => action,
+        })
+    }
+
+    fn text_eol_is_crlf(&self) -> bool {
+        match self.auto_crlf {
+            AutoCrlf::True => true,
+            AutoCrlf::Input => false,
+            AutoCrlf::False => {
+                self.core_eol == Eol::Crlf || (self.core_eol == Eol::Native && cfg!(windows))
+            }
+        }
+    }
+
+    fn output_eol(&self, action: CrlfAction) -> Option<Eol> {
+        match action {
+            CrlfAction::Binary => None,
+            CrlfAction::TextCrlf | CrlfAction::Undefined | CrlfAction::AutoCrlf => Some(Eol::Crlf),
+            CrlfAction::TextInput | CrlfAction::AutoInput => Some(Eol::Lf),
+            CrlfAction::Text | CrlfAction::Auto => {
+                if self.text_eol_is_crlf() {
+                    Some(Eol::Crlf)
+                } else {
+                    Some(Eol::Lf)
+                }
+            }
+        }
+    }
+
+    fn will_convert_lf_to_crlf(&self, stats: &TextStat, action: CrlfAction) -> bool {
+        if self.output_eol(action) != Some(Eol::Crlf) || stats.lonelf == 0 {
+            return false;
+        }
+        if is_auto(action) && (stats.lonecr > 0 || stats.crlf > 0 || stats.is_binary()) {
+            return false;
+        }
+        true
+    }
+
+    fn crlf_to_git(&mut self, path: &str, data: Vec<u8>, action: CrlfAction) -> GitResult<Vec<u8>> {
+        if action == CrlfAction::Binary || data.is_empty() {
+            return Ok(data);
+        }
+        let stats = TextStat::gather(&data);
+        if is_auto(action) && stats.is_binary() {
+            return Ok(data);
+        }
+
+        // Simulate a round trip through the repository to see if it is reversible.
+        let mut new_stats = stats;
+        new_stats.lonelf += new_stats.crlf;
+        new_stats.crlf = 0;
+        if self.will_convert_lf_to_crlf(&new_stats, action) {
+            new_stats.crlf += new_stats.lonelf;
+            new_stats.lonelf = 0;
+        }
+        self.check_safe_crlf(path, &stats, &new_stats)?;
+
+        if stats.crlf == 0 {
+            return Ok(data);
+        }
+        let mut converted = Vec::with_capacity(data.len() - stats.crlf);
+        for (i, &ch) in data.iter().enumerate() {
+            if ch == b'\r' && data.get(i + 1) == Some(&b'\n') {
+                continue;
+            }
+            converted.push(ch);
+        }
+        Ok(converted)
+    }
+
+    fn crlf_to_worktree(&self, data: &[u8], action: CrlfAction) -> Vec<u8> {
+        let stats = TextStat::gather(data);
+        if !self.will_convert_lf_to_crlf(&stats, action) {
+            return data.to_vec();
+        }
+        let mut converted = Vec::with_capacity(data.len() + stats.lonelf);
+        for (i, &ch) in data.iter().enumerate() {
+            if ch == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+                converted.push(b'\r');
+            }
+            converted.push(ch);
+        }
+        converted
+    }
+
+    fn check_safe_crlf(&mut self, path: &str, old: &TextStat, new: &TextStat) -> GitResult<()> {
+        let (fatal, warning) = if old.crlf > 0 && new.crlf == 0 {
+            (
+                format!("CRLF would be replaced by LF in {}", path),
+                format!(
+                    "in the working copy of '{}', CRLF will be replaced by LF the next time Git touches it",
+                    path
+                ),
+            )
+        } else if old.lonelf > 0 && new.lonelf == 0 {
+            (
+                format!("LF would be replaced by CRLF in {}", path),
+                format!(
+                    "in the working copy of '{}', LF will be replaced by CRLF the next time Git touches it",
+                    path
+                ),
+            )
+        } else {
+            return Ok(());
+        };
+
+        match self.safe_crlf {
+            SafeCrlf::Fail => Err(conversion_error(path, fatal)),
+            SafeCrlf::Warn => {
+                self.warnings.push(format!("warning: {}", warning));
+                Ok(())
+            }
+            SafeCrlf::False => Ok(()),
+        }
+    }
+}
+
+fn is_auto(action: CrlfAction) -> bool {
+    matches!(
+        action,
+        CrlfAction::Auto | CrlfAction::AutoInput | CrlfAction::AutoCrlf
+    )
+}
+
+fn encode_to_git(path: &str, data: &[u8], encoding: &str) -> GitResult<Vec<u8>> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    if encoding::prohibits_bom(encoding) && encoding::has_bom(data) {
+        return Err(conversion_error(
+            path,
+            format!("BOM is prohibited in '{}' if encoded as {}", path, encoding),
+        ));
+    }
+    if encoding::requires_bom(encoding) && !encoding::has_bom(data) {
+        return Err(conversion_error(
+            path,
+            format!("BOM is required in '{}' if encoded as {}", path, encoding),
+        ));
+    }
+    encoding::decode(encoding, data).ok_or_else(|| {
+        conversion_error(
+            path,
+            format!("failed to encode '{}' from {} to UTF-8", path, encoding),
+        )
+    })
+}
+
+fn filter_error(filter: &str, path: &str, message: String) -> GitError {
+    GitError::FilterError {
+        filter: filter.to_string(),
+        path: PathBuf::from(path),
+        message,
+    }
+}
+
+fn conversion_error(path: &str, message: String) -> GitError {
+    GitError::ConversionError {
+        path: PathBuf::from(path),
+        message,
+    }
+}
@@ -0,0 +1,195 @@
+use super::*;
+use crate::test_utils;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn setup(name: &str, config: &str, attributes: &str) -> Result<GitRepo, Box<dyn Error>> {
+    let repo = test_utils::setup_with_config(name, config)?;
+    fs::write(repo.worktree().unwrap().join(".gitattributes"), attributes)?;
+    Ok(repo)
+}
+
+#[test]
+fn test_text_stat() {
+    assert!(!is_binary(b"hello\r\nworld\n"));
+    assert!(is_binary(b"hello\0world"));
+    assert!(is_binary(b"lone\rcr"));
+}
+
+#[test]
+fn test_autocrlf() -> Result<(), Box<dyn Error>> {
+    let repo = setup(
+        "gitrs_test_autocrlf",
+        "[core]\n\tautocrlf = true\n\tsafecrlf = false\n",
+        "*.png binary\n",
+    )?;
+    let mut converter = GitConverter::new(&repo)?;
+
+    assert_eq!(converter.to_git("a.txt", b"a\r\nb\r\n")?, b"a\nb\n");
+    assert_eq!(converter.to_worktree("a.txt", b"a\nb\n")?, b"a\r\nb\r\n");
+    // Binary files and files with mixed line endings are left alone.
+    assert_eq!(converter.to_git("a.png", b"a\r\nb")?, b"a\r\nb");
+    assert_eq!(converter.to_worktree("a.bin", b"a\n\0")?, b"a\n\0");
+    assert_eq!(converter.to_worktree("m.txt", b"a\r\nb\n")?, b"a\r\nb\n");
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_eol_attributes() -> Result<(), Box<dyn Error>> {
+    let repo = setup(
+        "gitrs_test_eol_attributes",
+        "[core]\n\tsafecrlf = true\n",
+        "*.txt text\n*.bat text eol=crlf\n*.sh eol=lf\n",
+    )?;
+    let mut converter = GitConverter::new(&repo)?;
+
+    // Without autocrlf only files marked as text are converted.
+    assert_eq!(converter.to_git("a.c", b"a\r\n")?, b"a\r\n");
+    assert_eq!(converter.to_worktree("a.txt", b"a\n")?, b"a\n");
+    assert_eq!(converter.to_worktree("a.bat", b"a\nb\n")?, b"a\r\nb\r\n");
+    assert_eq!(converter.to_git("a.bat", b"a\r\nb\r\n")?, b"a\nb\n");
+    assert_eq!(converter.to_git("a.sh", b"a\n")?, b"a\n");
+
+    // core.safecrlf = true refuses irreversible conversions.
+    converter
+        .to_git("a.sh", b"a\r\n")
+        .expect_err("GitConverter::to_git should fail when CRLF would not be restored on checkout");
+    // With core.safecrlf = warn they are converted with a warning.
+    converter.set_safe_crlf(SafeCrlf::Warn);
+    assert_eq!(converter.to_git("a.sh", b"a\r\n")?, b"a\n");
+    assert_eq!(
+        converter.take_warnings(),
+        ["warning: in the working copy of 'a.sh', CRLF will be replaced by LF the next time Git touches it"]
+    );
+    assert!(converter.take_warnings().is_empty());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_working_tree_encoding() -> Result<(), Box<dyn Error>> {
+    let repo = setup(
+        "gitrs_test_working_tree_encoding",
+        "[core]\n",
+        "*.utf16 text working-tree-encoding=UTF-16LE\n*.bom working-tree-encoding=UTF-16\n",
+    )?;
+    let mut converter = GitConverter::new(&repo)?;
+
+    let utf16 = [b'h', 0, b'i', 0, b'\n', 0];
+    assert_eq!(converter.to_git("a.utf16", &utf16)?, b"hi\n");
+    assert_eq!(converter.to_worktree("a.utf16", b"hi\n")?, utf16);
+
+    let with_bom = [0xFF, 0xFE, b'h', 0];
+    converter
+        .to_git("a.utf16", &with_bom)
+        .expect_err("GitConverter::to_git should reject a BOM for UTF-16LE");
+    assert_eq!(converter.to_git("a.bom", &with_bom)?, b"h");
+    converter
+        .to_git("a.bom", &utf16)
+        .expect_err("GitConverter::to_git should require a BOM for UTF-16");
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_filter_commands() -> Result<(), Box<dyn Error>> {
+    let repo = setup(
+        "gitrs_test_filter_commands",
+        "[filter \"upper\"]\n\tclean = tr a-z A-Z\n\tsmudge = tr A-Z a-z\n\
+         [filter \"broken\"]\n\tclean = false\n\
+         [filter \"strict\"]\n\tclean = false\n\trequired = true\n",
+        "*.up filter=upper\n*.broken filter=broken\n*.strict filter=strict\n",
+    )?;
+    let mut converter = GitConverter::new(&repo)?;
+
+    assert_eq!(converter.to_git("a.up", b"hello")?, b"HELLO");
+    assert_eq!(converter.to_worktree("a.up", b"HELLO")?, b"hello");
+    // Optional filters fall back to the unfiltered contents.
+    assert_eq!(converter.to_git("a.broken", b"hello")?, b"hello");
+    assert_eq!(
+        converter.take_warnings(),
+        ["error: external filter 'false' failed"]
+    );
+    converter
+        .to_git("a.strict", b"hello")
+        .expect_err("GitConverter::to_git should fail when a required filter fails");
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_process_filter() -> Result<(), Box<dyn Error>> {
+    // A minimal long-running filter that upper cases (clean) or lower cases (smudge) contents.
+    // It delays delay.txt when it may, and sends its contents when they are asked for again.
+    let script = r#"
+rp() { l=$(head -c 4); [ -z "$l" ] && exit 0; [ "$l" = "0000" ] && return 1; p=$(head -c $((0x$l - 4))); return 0; }
+wp() { printf '%04x%s\n' $((${#1} + 5)) "$1"; }
+while rp; do :; done
+wp git-filter-server; wp version=2; printf 0000
+while rp; do :; done
+wp capability=clean; wp capability=smudge; wp capability=delay; printf 0000
+delayed=
+while true; do
+    cmd= path= can_delay=
+    while rp; do
+        case "$p" in
+        command=*) cmd=${p#command=};;
+        pathname=*) path=${p#pathname=};;
+        can-delay=1) can_delay=1;;
+        esac
+    done
+    if [ "$cmd" = list_available_blobs ]; then
+        [ -n "$delayed" ] && wp "pathname=$delayed"
+        printf 0000; wp status=success; printf 0000
+        delayed=
+        continue
+    fi
+    data=
+    while rp; do data="$data$p"; done
+    if [ "$cmd" = clean ]; then out=$(printf %s "$data" | tr a-z A-Z); else out=$(printf %s "$data" | tr A-Z a-z); fi
+    if [ "$path" = delay.txt ] && [ -n "$can_delay" ]; then
+        delayed=$path; stored=$out
+        wp status=delayed; printf 0000
+        continue
+    fi
+    # Delayed contents are asked for again without contents.
+    [ "$path" = delay.txt ] && [ -z "$data" ] && out=$stored
+    wp status=success; printf 0000
+    printf '%04x%s' $((${#out} + 4)) "$out"; printf 0000; printf 0000
+done
+"#;
+    let script_path = env::temp_dir().join("gitrs_test_process_filter/filter.sh");
+    let config = format!(
+        "[filter \"proc\"]\n\tprocess = sh {}\n\trequired = true\n",
+        script_path.display()
+    );
+    let repo = setup("gitrs_test_process_filter", &config, "*.txt filter=proc\n")?;
+    fs::write(&script_path, script)?;
+    let mut converter = GitConverter::new(&repo)?;
+
+    assert_eq!(converter.to_git("a.txt", b"hello")?, b"HELLO");
+    assert_eq!(converter.to_git("b.txt", b"world")?, b"WORLD");
+    assert_eq!(converter.to_worktree("a.txt", b"HELLO")?, b"hello");
+    assert_eq!(
+        converter.to_worktree_delayed("b.txt", b"WORLD")?,
+        Some(b"world".to_vec())
+    );
+    assert_eq!(converter.to_worktree_delayed("delay.txt", b"LATER")?, None);
+    assert_eq!(
+        converter.finish_delayed()?,
+        [("delay.txt".to_string(), b"later".to_vec())]
+    );
+    assert!(converter.finish_delayed()?.is_empty());
+    // Without being allowed to delay, the filter answers right away.
+    assert_eq!(converter.to_worktree("delay.txt", b"NOW")?, b"now");
+
+    drop(converter);
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
@@ -0,0 +1,629 @@
+//! Finding the files that differ between trees, the index and the worktree.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use super::output::DiffColors;
+use super::rename::{RenameOptions, MAX_SCORE};
+use crate::index::{GitIndex, IndexEntry};
+use crate::objects::{self, ObjectType};
+use crate::refs::NULL_SHA;
+use crate::utils;
+use crate::{
+    self as gitrs, AttrValue, GitConverter, GitError, GitFileMode, GitOdb, GitRepo, GitResult,
+    GitTree, GitTreeEntry, Pathspec, SafeCrlf,
+};
+
+/// Files with a NUL byte among their first bytes are binary.
+const FIRST_FEW_BYTES: usize = 8000;
+
+/// How a file differs between the two sides of a diff, as shown by `--name-status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    /// A regular file became a symbolic link or a submodule, or the other way around.
+    TypeChanged,
+    /// The file was moved to another path, possibly with changes.
+    Renamed,
+    /// The file is a copy of another, possibly with changes.
+    Copied,
+    /// The path has conflicts in the index.
+    Unmerged,
+}
+
+impl ChangeStatus {
+    /// The letter git shows for the status.
+    pub fn letter(self) -> char {
+        match self {
+            ChangeStatus::Added => 'A',
+            ChangeStatus::Deleted => 'D',
+            ChangeStatus::Modified => 'M',
+            ChangeStatus::TypeChanged => 'T',
+            ChangeStatus::Renamed => 'R',
+            ChangeStatus::Copied => 'C',
+            ChangeStatus::Unmerged => 'U',
+        }
+    }
+}
+
+/// How [GitDiff::diff_trees] handles subtrees, like the `-r` and `-t` options of
+/// `git diff-tree`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TreeRecursion {
+    /// Subtrees are compared as single entries without looking inside.
+    Shallow,
+    /// The files in subtrees are compared, but not the subtrees themselves.
+    #[default]
+    Files,
+    /// Both the subtrees and the files in them are compared, each subtree before its files.
+    Trees,
+}
+
+/// One side of a changed file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    path: String,
+    mode: GitFileMode,
+    sha: String,
+    in_worktree: bool,
+    /// The name was computed from worktree contents that do not match the index.
+    hashed: bool,
+}
+
+impl DiffFile {
+    fn new(path: &str, mode: GitFileMode, sha: &str, in_worktree: bool) -> Self {
+        Self {
+            path: path.to_string(),
+            mode,
+            sha: sha.to_string(),
+            in_worktree,
+            hashed: false,
+        }
+    }
+
+    /// The path of the file relative to the top of the worktree.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The mode of the file.
+    pub fn mode(&self) -> GitFileMode {
+        self.mode
+    }
+
+    /// The name of the blob, or of the commit for submodules.
+    pub fn sha(&self) -> &str {
+        &self.sha
+    }
+
+    /// Returns true if the contents are read from the worktree rather than the object database.
+    pub fn is_in_worktree(&self) -> bool {
+        self.in_worktree
+    }
+
+    /// The name shown by raw output, where like in git worktree files that do not match the
+    /// index have the all zero name.
+    pub(super) fn raw_sha(&self) -> &str {
+        if self.hashed {
+            NULL_SHA
+        } else {
+            &self.sha
+        }
+    }
+}
+
+/// A file that differs between the two sides of a diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    status: ChangeStatus,
+    path: String,
+    old: Option<DiffFile>,
+    new: Option<DiffFile>,
+    /// How similar the sides of a rename or copy are, out of [MAX_SCORE].
+    score: u32,
+}
+
+impl FileChange {
+    /// Creates the change between `old` and `new`, where [None] stands for a missing file.
+    fn new(path: String, old: Option<DiffFile>, new: Option<DiffFile>) -> Self {
+        let status = match (&old, &new) {
+            (None, _) => ChangeStatus::Added,
+            (_, None) => ChangeStatus::Deleted,
+            (Some(old), Some(new)) if old.mode.file_type() != new.mode.file_type() => {
+                ChangeStatus::TypeChanged
+            }
+            _ => ChangeStatus::Modified,
+        };
+        Self {
+            status,
+            path,
+            old,
+            new,
+            score: 0,
+        }
+    }
+
+    fn unmerged(path: String) -> Self {
+        Self {
+            status: ChangeStatus::Unmerged,
+            path,
+            old: None,
+            new: None,
+            score: 0,
+        }
+    }
+
+    /// Creates the rename or copy of `old` to `new`, whose similarity is `score`.
+    pub(super) fn paired(status: ChangeStatus, old: DiffFile, new: DiffFile, score: u32) -> Self {
+        Self {
+            status,
+            path: new.path.clone(),
+            old: Some(old),
+            new: Some(new),
+            score,
+        }
+    }
+
+    /// How the file changed.
+    pub fn status(&self) -> ChangeStatus {
+        self.status
+    }
+
+    /// The path of the file, the new one for renames and copies.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The similarity index of a renamed or copied file in percent, [None] for other changes.
+    pub fn similarity(&self) -> Option<u32> {
+        match self.status {
+            ChangeStatus::Renamed | ChangeStatus::Copied => Some(self.score * 100 / MAX_SCORE),
+            _ => None,
+        }
+    }
+
+    /// The file before the change, [None] if it was added or is unmerged.
+    pub fn old_file(&self) -> Option<&DiffFile> {
+        self.old.as_ref()
+    }
+
+    /// The file after the change, [None] if it was deleted or is unmerged.
+    pub fn new_file(&self) -> Option<&DiffFile> {
+        self.new.as_ref()
+    }
+}
+
+/// Compares the trees, index and worktree of a repository and shows their differences, like
+/// `git diff`.
+#[derive(Debug)]
+pub struct GitDiff {
+    odb: GitOdb,
+    worktree: Option<PathBuf>,
+    index: GitIndex,
+    converter: GitConverter,
+    trust_filemode: bool,
+    symlinks: bool,
+    recursion: TreeRecursion,
+    refresh: bool,
+    pub(super) colors: DiffColors,
+    pub(super) renames: Option<RenameOptions>,
+    pub(super) warnings: Vec<String>,
+}
+
+impl GitDiff {
+    /// Prepares diffs in `repo`, reading its index and settings.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::DiffError]: A `color.diff.<slot>` value is invalid
+    /// * [GitError::IndexError]: The index is corrupt
+    /// * Errors obtained when reading the object database, config or attributes
+    pub fn new(repo: &GitRepo) -> GitResult<Self> {
+        let config = repo.config()?;
+        let mut converter = GitConverter::new(repo)?;
+        // Hashing worktree files to compare them never warns about their line endings.
+        converter.set_safe_crlf(SafeCrlf::False);
+        Ok(Self {
+            odb: GitOdb::open(repo)?,
+            worktree: repo.worktree().map(Path::to_path_buf),
+            index: GitIndex::read(repo)?,
+            converter,
+            trust_filemode: config.get_bool("core.filemode", true)?,
+            symlinks: config.get_bool("core.symlinks", true)?,
+            recursion: TreeRecursion::default(),
+            refresh: true,
+            colors: DiffColors::load(&config)?,
+            renames: None,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Sets how [GitDiff::diff_trees] handles subtrees. The default compares the files in
+    /// them.
+    pub fn set_recursion(&mut self, recursion: TreeRecursion) {
+        self.recursion = recursion;
+    }
+
+    /// Sets whether worktree files whose stat data differ from their index entry are hashed
+    /// to find out if their contents changed, which is the default. Otherwise they are shown
+    /// as changed with the all zero name, like git's plumbing commands do.
+    pub fn set_refresh(&mut self, refresh: bool) {
+        self.refresh = refresh;
+    }
+
+    /// Returns what the rename detection and the conversion of worktree files warned about
+    /// since the last call, as printed by git.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        let mut warnings = std::mem::take(&mut self.warnings);
+        warnings.extend(self.converter.take_warnings());
+        warnings
+    }
+
+    /// The object database the contents are read from.
+    pub fn odb(&self) -> &GitOdb {
+        &self.odb
+    }
+
+    /// Compares the tree `old` with the tree `new`, where [None] stands for the empty tree.
+    /// Subtrees are handled as set with [GitDiff::set_recursion].
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: A tree or blob is missing or corrupt
+    pub fn diff_trees(
+        &mut self,
+        old: Option<&str>,
+        new: Option<&str>,
+        pathspec: &Pathspec,
+    ) -> GitResult<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        self.tree_changes(old, new, "", pathspec, &mut changes)?;
+        let mut unchanged = Vec::new();
+        if let Some(old) = old.filter(|_| self.wants_unchanged()) {
+            let changed: HashSet<&str> = changes.iter().map(FileChange::path).collect();
+            for (path, entry) in GitTree::flatten(&self.odb, old)? {
+                if pathspec.matches(&path) && !changed.contains(path.as_str()) {
+                    unchanged.push(DiffFile::new(&path, entry.mode(), entry.sha(), false));
+                }
+            }
+        }
+        self.find_renames(changes, unchanged)
+    }
+
+    /// Compares the tree `tree` (or the empty tree) with the worktree, or with the index if
+    /// `cached` is set.
+    ///
+    /// Only files in the index are looked up in the worktree, so untracked files are not shown.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::DiffError]: The worktree is compared in a bare repository
+    /// * [GitError::ObjectError]: A tree is missing or corrupt
+    /// * Errors obtained when reading worktree files
+    pub fn diff_index(
+        &mut self,
+        tree: Option<&str>,
+        cached: bool,
+        pathspec: &Pathspec,
+    ) -> GitResult<Vec<FileChange>> {
+        let mut sides: BTreeMap<String, (Option<DiffFile>, Option<DiffFile>)> = BTreeMap::new();
+        if let Some(tree) = tree {
+            for (path, entry) in GitTree::flatten(&self.odb, tree)? {
+                if pathspec.matches(&path) {
+                    let old = DiffFile::new(&path, entry.mode(), entry.sha(), false);
+                    sides.insert(path, (Some(old), None));
+                }
+            }
+        }
+
+        let mut unmerged = Vec::new();
+        for entry in self.index_entries(pathspec) {
+            let path = entry.path().to_string();
+            if entry.stage() != 0 {
+                if unmerged.last() != Some(&path) {
+                    sides.remove(&path);
+                    unmerged.push(path);
+                }
+                continue;
+            }
+            let new = if cached {
+                // Files that are only intended to be added are not in the index yet.
+                if entry.intent_to_add() {
+                    continue;
+                }
+                Some(DiffFile::new(&path, entry.mode(), entry.sha(), false))
+            } else {
+                self.worktree_file(&path, Some(&entry))?
+            };
+            match sides.get_mut(&path) {
+                Some(side) => side.1 = new,
+                None if new.is_some() => {
+                    sides.insert(path, (None, new));
+                }
+                None => {}
+            }
+        }
+        let (changes, unchanged) = collect_changes(sides, unmerged);
+        self.find_renames(changes, unchanged)
+    }
+
+    /// Compares the index with the worktree.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::DiffError]: The repository is bare
+    /// * Errors obtained when reading worktree files
+    pub fn diff_files(&mut self, pathspec: &Pathspec) -> GitResult<Vec<FileChange>> {
+        let mut sides = BTreeMap::new();
+        let mut unmerged: Vec<String> = Vec::new();
+        for entry in self.index_entries(pathspec) {
+            let path = entry.path().to_string();
+            if entry.stage() != 0 {
+                if unmerged.last() != Some(&path) {
+                    unmerged.push(path);
+                }
+                continue;
+            }
+            let old = if entry.intent_to_add() {
+                None
+            } else {
+                Some(DiffFile::new(&path, entry.mode(), entry.sha(), false))
+            };
+            let new = self.worktree_file(&path, Some(&entry))?;
+            if old.is_some() || new.is_some() {
+                sides.insert(path, (old, new));
+            }
+        }
+        let (changes, unchanged) = collect_changes(sides, unmerged);
+        self.find_renames(changes, unchanged)
+    }
+
+    /// Returns the contents of `file` as stored in the repository. Submodules read as the line
+    /// `Subproject commit <sha>`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: The blob is missing or corrupt
+    /// * Errors obtained when reading or converting a worktree file
+    pub fn read(&mut self, file: &DiffFile) -> GitResult<Vec<u8>> {
+        if file.mode.is_gitlink() {
+            return Ok(format!("Subproject commit {}\n", file.sha).into_bytes());
+        }
+        if file.in_worktree {
+            return self.read_worktree(&file.path, file.mode);
+        }
+        Ok(self.odb.read(&file.sha)?.1)
+    }
+
+    /// Returns true if the contents `data` of `file` are shown as binary: the `diff` attribute
+    /// is unset for the path, or the contents have a NUL byte near the start.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitattributes` files.
+    pub(super) fn is_binary(&mut self, file: &DiffFile, data: &[u8]) -> GitResult<bool> {
+        if file.mode.is_gitlink() {
+            return Ok(false);
+        }
+        match self.converter.attributes().get(&file.path, "diff")? {
+            AttrValue::Unset => Ok(true),
+            AttrValue::Set => Ok(false),
+            _ => Ok(data[..data.len().min(FIRST_FEW_BYTES)].contains(&0)),
+        }
+    }
+
+    /// Adds the changed non-tree entries between the trees `old` and `new` below `prefix` to
+    /// `changes`, in tree order.
+    fn tree_changes(
+        &self,
+        old: Option<&str>,
+        new: Option<&str>,
+        prefix: &str,
+        pathspec: &Pathspec,
+        changes: &mut Vec<FileChange>,
+    ) -> GitResult<()> {
+        // Subtrees sort as if their name ended with '/', which also keeps a file and a
+        // directory of the same name apart.
+        type Sides = (Option<GitTreeEntry>, Option<GitTreeEntry>);
+        let mut entries: BTreeMap<Vec<u8>, Sides> = BTreeMap::new();
+        for (sha, is_new) in [(old, false), (new, true)] {
+            let sha = match sha {
+                Some(sha) => sha,
+                None => continue,
+            };
+            for entry in self.odb.read_as::<GitTree>(sha)?.entries() {
+                let mut key = entry.name().as_bytes().to_vec();
+                if entry.mode().is_tree() {
+                    key.push(b'/');
+                }
+                let sides = entries.entry(key).or_default();
+                if is_new {
+                    sides.1 = Some(entry.clone());
+                } else {
+                    sides.0 = Some(entry.clone());
+                }
+            }
+        }
+
+        for (old, new) in entries.into_values() {
+            if old.is_some() && old == new {
+                continue;
+            }
+            let name = old.as_ref().or(new.as_ref()).unwrap().name();
+            let path = format!("{}{}", prefix, name);
+            let file = |entry: &Option<GitTreeEntry>| {
+                entry
+                    .as_ref()
+                    .map(|entry| DiffFile::new(&path, entry.mode(), entry.sha(), false))
+            };
+            let is_tree = old.as_ref().or(new.as_ref()).unwrap().mode().is_tree();
+            if !is_tree {
+                if pathspec.matches(&path) {
+                    changes.push(FileChange::new(path.clone(), file(&old), file(&new)));
+                }
+                continue;
+            }
+            if self.recursion != TreeRecursion::Files && pathspec.may_match_below(&path) {
+                changes.push(FileChange::new(path.clone(), file(&old), file(&new)));
+            }
+            if self.recursion != TreeRecursion::Shallow {
+                let sha =
+                    |entry: &Option<GitTreeEntry>| entry.as_ref().map(|e| e.sha().to_string());
+                let (old, new) = (sha(&old), sha(&new));
+                let prefix = format!("{}/", path);
+                self.tree_changes(old.as_deref(), new.as_deref(), &prefix, pathspec, changes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the index entries selected by `pathspec`.
+    fn index_entries(&self, pathspec: &Pathspec) -> Vec<IndexEntry> {
+        self.index
+            .entries()
+            .iter()
+            .filter(|entry| pathspec.matches(entry.path()))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the worktree file at `path` as a side of a diff, or [None] if it is missing.
+    ///
+    /// The stat data of `entry`, the index entry of the path, avoids hashing unchanged files.
+    /// Without refreshing, files whose stat data differ are not hashed at all.
+    fn worktree_file(
+        &mut self,
+        path: &str,
+        entry: Option<&IndexEntry>,
+    ) -> GitResult<Option<DiffFile>> {
+        let worktree = match &self.worktree {
+            Some(worktree) => worktree,
+            None => {
+                return Err(GitError::DiffError {
+                    message: "this operation must be run in a work tree".to_string(),
+                })
+            }
+        };
+        if let Some(entry) = entry.filter(|entry| entry.assume_valid() || entry.skip_worktree()) {
+            return Ok(Some(DiffFile::new(path, entry.mode(), entry.sha(), false)));
+        }
+        let full = worktree.join(path);
+        let metadata = match fs::symlink_metadata(&full) {
+            Ok(metadata) => metadata,
+            Err(error)
+                if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) =>
+            {
+                return Ok(None)
+            }
+            Err(error) => return Err(GitError::IOError { error, path: full }),
+        };
+
+        let index_mode = entry.map(IndexEntry::mode);
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            // A directory is a checked out submodule, or else the file is gone.
+            return Ok(entry
+                .filter(|entry| entry.mode().is_gitlink())
+                .map(|entry| DiffFile::new(path, entry.mode(), entry.sha(), false)));
+        }
+        let mode = match index_mode {
+            Some(mode) if !file_type.is_symlink() && !self.symlinks && mode.is_symlink() => mode,
+            Some(mode) if file_type.is_file() && !self.trust_filemode && mode.is_file() => mode,
+            _ => GitFileMode::from_metadata(&metadata, self.trust_filemode),
+        };
+
+        if let Some(entry) = entry {
+            let clean =
+                entry.mode() == mode && !entry.intent_to_add() && entry.is_stat_clean(&metadata);
+            if clean && !self.index.is_racy(entry) {
+                return Ok(Some(DiffFile::new(path, mode, entry.sha(), true)));
+            }
+            // Racily clean files may have changed without their stat data showing it, so
+            // only those are hashed.
+            if !clean && !self.refresh {
+                return Ok(Some(DiffFile::new(path, mode, NULL_SHA, true)));
+            }
+        }
+        let data = self.read_worktree(path, mode)?;
+        let sha = objects::hash_object(ObjectType::Blob, &data);
+        let matches_index = entry.is_some_and(|entry| {
+            entry.mode() == mode && entry.sha() == sha && !entry.intent_to_add()
+        });
+        if matches_index {
+            return Ok(Some(DiffFile::new(path, mode, &sha, true)));
+        }
+        if !self.refresh {
+            return Ok(Some(DiffFile::new(path, mode, NULL_SHA, true)));
+        }
+        let mut file = DiffFile::new(path, mode, &sha, true);
+        file.hashed = true;
+        Ok(Some(file))
+    }
+
+    /// Returns the name of the contents of `file`, hashing worktree files that were not
+    /// hashed when comparing them, or the all zero name for a missing side.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading or converting a worktree file.
+    pub(super) fn object_name(&mut self, file: Option<&DiffFile>) -> GitResult<String> {
+        match file {
+            Some(file) if file.in_worktree && file.sha == NULL_SHA => {
+                let data = self.read(file)?;
+                Ok(objects::hash_object(ObjectType::Blob, &data))
+            }
+            file => Ok(sha_of(file).to_string()),
+        }
+    }
+
+    /// Reads the worktree file at `path` with mode `mode` and converts it to its repository
+    /// representation. Symbolic links read as their target.
+    fn read_worktree(&mut self, path: &str, mode: GitFileMode) -> GitResult<Vec<u8>> {
+        let full = match &self.worktree {
+            Some(worktree) => worktree.join(path),
+            None => return Ok(Vec::new()),
+        };
+        let is_link = gitrs::to_git_result(fs::symlink_metadata(&full), &full)?
+            .file_type()
+            .is_symlink();
+        if is_link {
+            let target = gitrs::to_git_result(fs::read_link(&full), &full)?;
+            return Ok(target.to_string_lossy().into_owned().into_bytes());
+        }
+        let data = utils::read_bytes(&full)?;
+        if mode.is_symlink() {
+            return Ok(data);
+        }
+        self.converter.to_git(path, &data)
+    }
+}
+
+/// Turns the two sides of each path into changes with the `unmerged` paths in their place,
+/// and returns them with the files of the unchanged paths.
+fn collect_changes(
+    sides: BTreeMap<String, (Option<DiffFile>, Option<DiffFile>)>,
+    unmerged: Vec<String>,
+) -> (Vec<FileChange>, Vec<DiffFile>) {
+    let mut changes = Vec::new();
+    let mut unchanged = Vec::new();
+    for (path, (old, new)) in sides {
+        match (old, new) {
+            (Some(old), Some(new)) if old.mode == new.mode && old.sha == new.sha => {
+                unchanged.push(old)
+            }
+            (old, new) => changes.push(FileChange::new(path, old, new)),
+        }
+    }
+    changes.extend(unmerged.into_iter().map(FileChange::unmerged));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    (changes, unchanged)
+}
+
+/// Returns `sha`, or the all zero name for a missing side.
+fn sha_of(file: Option<&DiffFile>) -> &str {
+    file.map_or(NULL_SHA, DiffFile::sha)
+}
@@ -0,0 +1,225 @@
+//! The histogram diff: the longest run of equal lines built around the lines occurring least
+//! often in the old range splits the problem, and both sides of it are diffed the same way.
+//! Where all the common lines are too frequent, the Myers algorithm takes over.
+
+use std::collections::HashMap;
+
+use super::myers;
+
+/// Lines occurring more often than this in the old range are not used to split.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// Marks the lines removed from `old` and added to `new`, given as their classes.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    let mut histogram = Histogram {
+        old,
+        new,
+        old_changed,
+        new_changed,
+    };
+    histogram.diff(1, old.len(), 1, new.len());
+}
+
+/// A run of equal lines, with the first and last line on each side counting from 1.
+#[derive(Debug, Default, Clone, Copy)]
+struct Region {
+    begin1: usize,
+    end1: usize,
+    begin2: usize,
+    end2: usize,
+}
+
+/// How often and where a line occurs in the old range.
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    /// The first occurrence.
+    first: usize,
+    count: usize,
+}
+
+/// The occurrences of the lines of an old range, used to find the best region to split at.
+struct Index {
+    records: HashMap<usize, Record>,
+    /// The next occurrence of the line at each position of the range, or 0.
+    next: Vec<usize>,
+    start: usize,
+    /// The lowest occurrence count of the lines in the best region so far.
+    count: usize,
+    has_common: bool,
+}
+
+impl Index {
+    fn next(&self, line: usize) -> usize {
+        self.next[line - self.start]
+    }
+}
+
+struct Histogram<'a> {
+    old: &'a [usize],
+    new: &'a [usize],
+    old_changed: &'a mut [bool],
+    new_changed: &'a mut [bool],
+}
+
+impl Histogram<'_> {
+    /// Diffs `count1` old lines from `line1` with `count2` new lines from `line2`, where lines
+    /// count from 1.
+    fn diff(&mut self, mut line1: usize, mut count1: usize, mut line2: usize, mut count2: usize) {
+        loop {
+            if count1 == 0 || count2 == 0 {
+                self.mark(line1, count1, line2, count2);
+                return;
+            }
+            let lcs = match self.find_lcs(line1, count1, line2, count2) {
+                Some(lcs) => lcs,
+                None => {
+                    let (start1, start2) = (line1 - 1, line2 - 1);
+                    myers::diff(
+                        &self.old[start1..start1 + count1],
+                        &self.new[start2..start2 + count2],
+                        false,
+                        &mut self.old_changed[start1..start1 + count1],
+                        &mut self.new_changed[start2..start2 + count2],
+                    );
+                    return;
+                }
+            };
+            if lcs.begin1 == 0 && lcs.begin2 == 0 {
+                self.mark(line1, count1, line2, count2);
+                return;
+            }
+
+            self.diff(line1, lcs.begin1 - line1, line2, lcs.begin2 - line2);
+            count1 = line1 + count1 - 1 - lcs.end1;
+            line1 = lcs.end1 + 1;
+            count2 = line2 + count2 - 1 - lcs.end2;
+            line2 = lcs.end2 + 1;
+        }
+    }
+
+    /// Returns the region to split at, empty if there are no common lines, or `None` if all
+    /// the common lines are too frequent.
+    fn find_lcs(&self, line1: usize, count1: usize, line2: usize, count2: usize) -> Option<Region> {
+        let mut index = Index {
+            records: HashMap::new(),
+            next: vec![0; count1],
+            start: line1,
+            count: MAX_CHAIN_LENGTH + 1,
+            has_common: false,
+        };
+        for line in (line1..line1 + count1).rev() {
+            let class = self.old[line - 1];
+            match index.records.get_mut(&class) {
+                Some(record) => {
+                    index.next[line - line1] = record.first;
+                    record.first = line;
+                    record.count += 1;
+                }
+                None => {
+                    index.records.insert(
+                        class,
+                        Record {
+                            first: line,
+                            count: 1,
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut lcs = Region::default();
+        let mut line = line2;
+        while line < line2 + count2 {
+            line = self.try_lcs(&mut index, &mut lcs, line, line1, count1, line2, count2);
+        }
+        if index.has_common && index.count > MAX_CHAIN_LENGTH {
+            None
+        } else {
+            Some(lcs)
+        }
+    }
+
+    /// Grows runs of equal lines around each occurrence of the new line `b_ptr` in the old
+    /// range, keeping the best in `lcs`, and returns the next new line to try.
+    #[allow(clippy::too_many_arguments)]
+    fn try_lcs(
+        &self,
+        index: &mut Index,
+        lcs: &mut Region,
+        b_ptr: usize,
+        line1: usize,
+        count1: usize,
+        line2: usize,
+        count2: usize,
+    ) -> usize {
+        let (last1, last2) = (line1 + count1 - 1, line2 + count2 - 1);
+        let mut b_next = b_ptr + 1;
+        let record = match index.records.get(&self.new[b_ptr - 1]) {
+            Some(&record) => record,
+            None => return b_next,
+        };
+        index.has_common = true;
+        if record.count > index.count {
+            return b_next;
+        }
+        let records = &index.records;
+        let count_of = |line: usize| records[&self.old[line - 1]].count;
+
+        let mut a_ptr = record.first;
+        loop {
+            let mut np = index.next(a_ptr);
+            let (mut as_, mut bs, mut ae, mut be) = (a_ptr, b_ptr, a_ptr, b_ptr);
+            let mut rc = record.count;
+            while line1 < as_ && line2 < bs && self.old[as_ - 2] == self.new[bs - 2] {
+                as_ -= 1;
+                bs -= 1;
+                if rc > 1 {
+                    rc = rc.min(count_of(as_));
+                }
+            }
+            while ae < last1 && be < last2 && self.old[ae] == self.new[be] {
+                ae += 1;
+                be += 1;
+                if rc > 1 {
+                    rc = rc.min(count_of(ae));
+                }
+            }
+
+            if b_next <= be {
+                b_next = be + 1;
+            }
+            if lcs.end1 - lcs.begin1 < ae - as_ || rc < index.count {
+                *lcs = Region {
+                    begin1: as_,
+                    end1: ae,
+                    begin2: bs,
+                    end2: be,
+                };
+                index.count = rc;
+            }
+
+            // Occurrences inside the region just found are skipped.
+            while np != 0 && np <= ae {
+                np = index.next(np);
+            }
+            if np == 0 {
+                return b_next;
+            }
+            a_ptr = np;
+        }
+    }
+
+    fn mark(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        for changed in &mut self.old_changed[line1 - 1..line1 - 1 + count1] {
+            *changed = true;
+        }
+        for changed in &mut self.new_changed[line2 - 1..line2 - 1 + count2] {
+            *changed = true;
+        }
+    }
+}
@@ -0,0 +1,486 @@
+//! Line diffs between two versions of a file, computed like git's xdiff: a diff algorithm
+//! marks the removed and added lines, groups of changes are slid to where they read best, and
+//! nearby changes are gathered into hunks with some unchanged lines around them.
+
+mod changes;
+mod histogram;
+mod moved;
+mod myers;
+mod output;
+mod patience;
+mod rename;
+mod slider;
+#[cfg(test)]
+mod tests;
+mod words;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::{GitError, GitResult};
+
+pub use self::changes::{ChangeStatus, DiffFile, FileChange, GitDiff, TreeRecursion};
+pub use self::moved::ColorMoved;
+pub use self::output::DiffFormat;
+pub use self::rename::RenameOptions;
+pub use self::words::WordDiff;
+
+/// Function names longer than this are cut in hunk headers.
+const FUNCTION_MAX_LEN: usize = 80;
+/// Hunk headers are cut to this length, including their newline.
+const HEADER_MAX_LEN: usize = 128;
+
+/// The algorithm finding the changed lines, like `--diff-algorithm=<algorithm>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffAlgorithm {
+    /// The Myers algorithm, which settles for a larger diff where finding the smallest one
+    /// gets expensive.
+    #[default]
+    Myers,
+    /// The Myers algorithm spending the time needed to find the smallest diff.
+    Minimal,
+    /// Matches the lines that occur once on both sides first, then diffs between them.
+    Patience,
+    /// Patience extended to lines that occur rarely instead of once.
+    Histogram,
+}
+
+impl FromStr for DiffAlgorithm {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "myers" | "default" => Ok(DiffAlgorithm::Myers),
+            "minimal" => Ok(DiffAlgorithm::Minimal),
+            "patience" => Ok(DiffAlgorithm::Patience),
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            _ => Err(GitError::DiffError {
+                message: "option diff-algorithm accepts \"myers\", \"minimal\", \"patience\" \
+                          and \"histogram\""
+                    .to_string(),
+            }),
+        }
+    }
+}
+
+/// How two files are compared and their differences grouped into hunks.
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pub algorithm: DiffAlgorithm,
+    /// The number of unchanged lines shown around the changes, 3 by default.
+    pub context: usize,
+    /// The number of unchanged lines that may separate two hunks in addition to their context
+    /// before they are merged, like `--inter-hunk-context`.
+    pub inter_hunk_context: usize,
+    /// Ignore whitespace when comparing lines, like `-w`.
+    pub ignore_all_space: bool,
+    /// Ignore changes in the amount of whitespace, like `-b`.
+    pub ignore_space_change: bool,
+    /// Ignore changes in whitespace at the end of lines, like `--ignore-space-at-eol`.
+    pub ignore_space_at_eol: bool,
+    /// Ignore carriage returns at the end of lines, like `--ignore-cr-at-eol`.
+    pub ignore_cr_at_eol: bool,
+    /// Ignore changes whose lines are all blank, like `--ignore-blank-lines`.
+    pub ignore_blank_lines: bool,
+    /// Slide changes to where the indentation suggests they start and end, like
+    /// `--indent-heuristic`. On by default.
+    pub indent_heuristic: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: DiffAlgorithm::default(),
+            context: 3,
+            inter_hunk_context: 0,
+            ignore_all_space: false,
+            ignore_space_change: false,
+            ignore_space_at_eol: false,
+            ignore_cr_at_eol: false,
+            ignore_blank_lines: false,
+            indent_heuristic: true,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Returns true if some whitespace is ignored when comparing lines.
+    pub fn ignores_whitespace(&self) -> bool {
+        self.ignore_all_space
+            || self.ignore_space_change
+            || self.ignore_space_at_eol
+            || self.ignore_cr_at_eol
+    }
+}
+
+/// Whether a line of a hunk is unchanged, removed or added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+/// A line of a hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HunkLine {
+    pub kind: LineKind,
+    /// The content of the line, including its newline unless it is the incomplete last line
+    /// of its file. Unchanged lines are taken from the new file.
+    pub text: Vec<u8>,
+}
+
+impl HunkLine {
+    /// Returns false for the last line of a file that does not end with a newline.
+    pub fn has_newline(&self) -> bool {
+        self.text.ends_with(b"\n")
+    }
+}
+
+/// A group of nearby changes, with the unchanged lines around them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The first line of the hunk in the old file, counting from 1, or the line before it if
+    /// the hunk has no lines in the old file.
+    pub old_start: usize,
+    pub old_lines: usize,
+    /// The first line of the hunk in the new file, counting like `old_start`.
+    pub new_start: usize,
+    pub new_lines: usize,
+    /// The closest line above the hunk in the old file that looks like the start of a
+    /// function, without trailing whitespace, or empty if there is none.
+    pub function: Vec<u8>,
+    pub lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// Returns the `@@ -<old> +<new> @@ <function>` header of the hunk, without a newline.
+    pub fn header(&self) -> Vec<u8> {
+        let range = |start: usize, lines: usize| match lines {
+            1 => start.to_string(),
+            _ => format!("{},{}", start, lines),
+        };
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_lines),
+            range(self.new_start, self.new_lines)
+        )
+        .into_bytes();
+        if !self.function.is_empty() {
+            header.push(b' ');
+            let room = HEADER_MAX_LEN.saturating_sub(header.len() + 1);
+            header.extend_from_slice(&self.function[..self.function.len().min(room)]);
+        }
+        header
+    }
+}
+
+/// Compares the lines of `old` and `new` and returns the hunks of their differences, in
+/// order. Identical files have no hunks.
+pub fn diff_lines(old: &[u8], new: &[u8], options: &DiffOptions) -> Vec<Hunk> {
+    let (mut old, mut new) = prepare(old, new, options);
+    match options.algorithm {
+        DiffAlgorithm::Myers | DiffAlgorithm::Minimal => myers::diff(
+            &old.classes,
+            &new.classes,
+            options.algorithm == DiffAlgorithm::Minimal,
+            &mut old.changed,
+            &mut new.changed,
+        ),
+        DiffAlgorithm::Patience => patience::diff(
+            &old.classes,
+            &new.classes,
+            &mut old.changed,
+            &mut new.changed,
+        ),
+        DiffAlgorithm::Histogram => histogram::diff(
+            &old.classes,
+            &new.classes,
+            &mut old.changed,
+            &mut new.changed,
+        ),
+    }
+    slider::compact(&mut old, &new, options.indent_heuristic);
+    slider::compact(&mut new, &old, options.indent_heuristic);
+
+    let mut changes = changes(&old, &new);
+    if options.ignore_blank_lines {
+        for change in &mut changes {
+            change.ignore = old.lines[change.old..change.old_end()]
+                .iter()
+                .chain(&new.lines[change.new..change.new_end()])
+                .all(|line| is_blank(line, options));
+        }
+    }
+    hunks(&old, &new, &changes, options)
+}
+
+/// One of the files being compared.
+struct Side<'a> {
+    lines: Vec<&'a [u8]>,
+    /// The equivalence class of each line: lines are equal for the diff if their classes are.
+    classes: Vec<usize>,
+    /// Whether each line is removed from the old file, or added to the new one.
+    changed: Vec<bool>,
+}
+
+impl Side<'_> {
+    fn len(&self) -> isize {
+        self.lines.len() as isize
+    }
+
+    /// Returns true if line `i` is changed. Positions outside the file are unchanged.
+    fn is_changed(&self, i: isize) -> bool {
+        0 <= i && i < self.len() && self.changed[i as usize]
+    }
+}
+
+/// Splits both files into lines and sorts the lines into classes, numbered in the order they
+/// first appear.
+fn prepare<'a>(old: &'a [u8], new: &'a [u8], options: &DiffOptions) -> (Side<'a>, Side<'a>) {
+    let mut classes: HashMap<Cow<[u8]>, usize> = HashMap::new();
+    let mut side = |data: &'a [u8]| {
+        let lines: Vec<&[u8]> = data.split_inclusive(|&c| c == b'\n').collect();
+        let line_classes = lines
+            .iter()
+            .map(|line| {
+                let count = classes.len();
+                *classes.entry(normalize(line, options)).or_insert(count)
+            })
+            .collect();
+        Side {
+            changed: vec![false; lines.len()],
+            lines,
+            classes: line_classes,
+        }
+    };
+    let old = side(old);
+    let new = side(new);
+    (old, new)
+}
+
+/// Returns the part of `line` that matters when comparing it, given the ignored whitespace.
+fn normalize<'a>(line: &'a [u8], options: &DiffOptions) -> Cow<'a, [u8]> {
+    if options.ignore_all_space {
+        Cow::Owned(line.iter().copied().filter(|&c| !is_space(c)).collect())
+    } else if options.ignore_space_change {
+        let mut normalized = Vec::with_capacity(line.len());
+        let mut pending_space = false;
+        for &c in line {
+            if is_space(c) {
+                pending_space = true;
+                continue;
+            }
+            if pending_space {
+                normalized.push(b' ');
+                pending_space = false;
+            }
+            normalized.push(c);
+        }
+        Cow::Owned(normalized)
+    } else if options.ignore_space_at_eol {
+        let end = line
+            .iter()
+            .rposition(|&c| !is_space(c))
+            .map_or(0, |i| i + 1);
+        Cow::Borrowed(&line[..end])
+    } else if options.ignore_cr_at_eol {
+        // The carriage return of an incomplete last line is kept.
+        match line.strip_suffix(b"\n") {
+            Some(line) => Cow::Borrowed(line.strip_suffix(b"\r").unwrap_or(line)),
+            None => Cow::Borrowed(line),
+        }
+    } else {
+        Cow::Borrowed(line)
+    }
+}
+
+/// The whitespace characters for comparing lines and measuring indentation.
+fn is_space(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+/// Returns true if `line` counts as blank for `--ignore-blank-lines`. Like in git, a lone
+/// character on the incomplete last line does too unless whitespace is ignored.
+fn is_blank(line: &[u8], options: &DiffOptions) -> bool {
+    if options.ignores_whitespace() {
+        line.iter().all(|&c| is_space(c))
+    } else {
+        line.len() <= 1
+    }
+}
+
+/// Lines removed from the old file and added to the new one, at 0-based positions.
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    old: usize,
+    new: usize,
+    old_len: usize,
+    new_len: usize,
+    /// Only blank lines change, and blank lines are ignored.
+    ignore: bool,
+}
+
+impl Change {
+    fn old_end(&self) -> usize {
+        self.old + self.old_len
+    }
+
+    fn new_end(&self) -> usize {
+        self.new + self.new_len
+    }
+}
+
+/// Collects the groups of changed lines into the edit script.
+fn changes(old: &Side, new: &Side) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let (mut i1, mut i2) = (old.len(), new.len());
+    while i1 >= 0 || i2 >= 0 {
+        if old.is_changed(i1 - 1) || new.is_changed(i2 - 1) {
+            let (end1, end2) = (i1, i2);
+            while old.is_changed(i1 - 1) {
+                i1 -= 1;
+            }
+            while new.is_changed(i2 - 1) {
+                i2 -= 1;
+            }
+            changes.push(Change {
+                old: i1 as usize,
+                new: i2 as usize,
+                old_len: (end1 - i1) as usize,
+                new_len: (end2 - i2) as usize,
+                ignore: false,
+            });
+        }
+        i1 -= 1;
+        i2 -= 1;
+    }
+    changes.reverse();
+    changes
+}
+
+/// Groups the changes into hunks: changes whose context would touch share a hunk, and
+/// ignored changes only end up in hunks along with changes close to them.
+fn hunks(old: &Side, new: &Side, changes: &[Change], options: &DiffOptions) -> Vec<Hunk> {
+    let context = options.context;
+    let max_common = 2 * context + options.inter_hunk_context;
+    let max_ignorable = context;
+    let gap = |before: &Change, after: &Change| after.old - before.old_end();
+
+    let mut hunks = Vec::new();
+    let mut function = Vec::new();
+    let mut function_limit = -1;
+    let mut next = 0;
+    while next < changes.len() {
+        // Ignored changes far enough from the next change are dropped.
+        let mut first = next;
+        let mut i = next;
+        while i < changes.len() && changes[i].ignore {
+            if i + 1 == changes.len() || gap(&changes[i], &changes[i + 1]) >= max_ignorable {
+                first = i + 1;
+            }
+            i += 1;
+        }
+        if first == changes.len() {
+            break;
+        }
+
+        let mut last = first;
+        let mut ignored = 0;
+        for i in first + 1..changes.len() {
+            let (previous, change) = (&changes[i - 1], &changes[i]);
+            let distance = gap(previous, change);
+            if distance > max_common {
+                break;
+            }
+            if distance < max_ignorable && (!change.ignore || last == i - 1) {
+                last = i;
+                ignored = 0;
+            } else if distance < max_ignorable {
+                ignored += change.new_len;
+            } else if last != i - 1 && change.old + ignored - changes[last].old_end() > max_common {
+                break;
+            } else if !change.ignore {
+                last = i;
+                ignored = 0;
+            } else {
+                ignored += change.new_len;
+            }
+        }
+
+        let (start, end) = (&changes[first], &changes[last]);
+        let s1 = start.old.saturating_sub(context);
+        let s2 = start.new.saturating_sub(context);
+        let post_context = context
+            .min(old.lines.len() - end.old_end())
+            .min(new.lines.len() - end.new_end());
+        let e1 = end.old_end() + post_context;
+        let e2 = end.new_end() + post_context;
+
+        if let Some(line) = function_line(old, s1 as isize - 1, function_limit) {
+            function = line;
+        }
+        function_limit = s1 as isize - 1;
+
+        let mut lines = Vec::new();
+        let line = |kind, text: &[u8]| HunkLine {
+            kind,
+            text: text.to_vec(),
+        };
+        for text in &new.lines[s2..start.new] {
+            lines.push(line(LineKind::Context, text));
+        }
+        let (mut i1, mut i2) = (start.old, start.new);
+        for change in &changes[first..=last] {
+            while i1 < change.old && i2 < change.new {
+                lines.push(line(LineKind::Context, new.lines[i2]));
+                i1 += 1;
+                i2 += 1;
+            }
+            for text in &old.lines[change.old..change.old_end()] {
+                lines.push(line(LineKind::Removed, text));
+            }
+            for text in &new.lines[change.new..change.new_end()] {
+                lines.push(line(LineKind::Added, text));
+            }
+            i1 = change.old_end();
+            i2 = change.new_end();
+        }
+        for text in &new.lines[i2..e2] {
+            lines.push(line(LineKind::Context, text));
+        }
+
+        let start_line = |start: usize, len: usize| if len > 0 { start + 1 } else { start };
+        hunks.push(Hunk {
+            old_start: start_line(s1, e1 - s1),
+            old_lines: e1 - s1,
+            new_start: start_line(s2, e2 - s2),
+            new_lines: e2 - s2,
+            function: function.clone(),
+            lines,
+        });
+        next = last + 1;
+    }
+    hunks
+}
+
+/// Searches the old file from line `start` up to, but not including, line `limit` for a line
+/// starting with a letter, `_` or `$`, and returns it cut for the hunk header.
+fn function_line(old: &Side, start: isize, limit: isize) -> Option<Vec<u8>> {
+    let step = if start > limit { -1 } else { 1 };
+    let mut i = start;
+    while i != limit && 0 <= i && i < old.len() {
+        let line = old.lines[i as usize];
+        if matches!(line.first(), Some(c) if c.is_ascii_alphabetic() || *c == b'_' || *c == b'$') {
+            let line = &line[..line.len().min(FUNCTION_MAX_LEN)];
+            let end = line
+                .iter()
+                .rposition(|&c| !is_space(c))
+                .map_or(0, |i| i + 1);
+            return Some(line[..end].to_vec());
+        }
+        i += step;
+    }
+    None
+}
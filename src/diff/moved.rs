@@ -0,0 +1,228 @@
+//! Finding the blocks of lines moved within a diff, like `git diff --color-moved`.
+//!
+//! A removed line is moved if the same line is added elsewhere in the diff, and the other way
+//! around. Except in [ColorMoved::Plain] mode, moved lines are gathered into blocks of lines
+//! that were moved together, and blocks with too little text are not shown as moved.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use super::{HunkLine, LineKind};
+use crate::{GitError, GitResult};
+
+/// Moved blocks with fewer alphanumeric characters than this are not shown as moved.
+const MIN_ALNUM_COUNT: usize = 20;
+
+/// How moved lines are found and colored, like `--color-moved=<mode>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMoved {
+    /// Every line added or removed elsewhere is moved.
+    Plain,
+    /// Blocks of moved lines with enough text are moved.
+    Blocks,
+    /// Like blocks, with adjacent blocks told apart by alternating colors.
+    #[default]
+    Zebra,
+    /// Like zebra, with the lines inside blocks dimmed to bring out their edges.
+    DimmedZebra,
+}
+
+impl FromStr for ColorMoved {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        match s {
+            "default" | "zebra" => Ok(ColorMoved::Zebra),
+            "plain" => Ok(ColorMoved::Plain),
+            "blocks" => Ok(ColorMoved::Blocks),
+            "dimmed-zebra" | "dimmed_zebra" => Ok(ColorMoved::DimmedZebra),
+            _ => Err(GitError::DiffError {
+                message: "color moved setting must be one of 'no', 'default', 'blocks', \
+                          'zebra', 'dimmed-zebra', 'plain'"
+                    .to_string(),
+            }),
+        }
+    }
+}
+
+/// How a line is colored by `--color-moved`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct MovedMark {
+    pub(super) moved: bool,
+    /// The line is in a block right after another block moved the same way.
+    pub(super) alternative: bool,
+    /// The line is dimmed by [ColorMoved::DimmedZebra].
+    pub(super) dimmed: bool,
+}
+
+/// Returns the marks of `lines`, the lines of all the hunks of a diff in order. The lines
+/// moved between files count too; unchanged lines, and empty unchanged lines standing for
+/// the headers between hunks, end blocks.
+pub(super) fn mark_moved(lines: &[HunkLine], mode: ColorMoved) -> Vec<MovedMark> {
+    // Like in patches, the incomplete last line of a file is the same as a full line.
+    let mut ids = HashMap::new();
+    let id: Vec<usize> = lines
+        .iter()
+        .map(|line| {
+            let next = ids.len();
+            let text = line.text.strip_suffix(b"\n").unwrap_or(&line.text);
+            *ids.entry(text).or_insert(next)
+        })
+        .collect();
+
+    // The lines of each kind by id, and the line following each line in its run of removed
+    // or added lines.
+    let mut removed: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut added: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut next_line = vec![None; lines.len()];
+    let mut prev: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        let by_id = match line.kind {
+            LineKind::Context => {
+                prev = None;
+                continue;
+            }
+            LineKind::Removed => &mut removed,
+            LineKind::Added => &mut added,
+        };
+        by_id.entry(id[i]).or_default().push(i);
+        if let Some(prev) = prev.filter(|&prev| lines[prev].kind == line.kind) {
+            next_line[prev] = Some(i);
+        }
+        prev = Some(i);
+    }
+
+    let mut marks = vec![MovedMark::default(); lines.len()];
+    // The last lines of the blocks elsewhere that the current block may have moved from.
+    let mut candidates: Vec<usize> = Vec::new();
+    let mut block_length = 0;
+    let mut block_kind = None;
+    let mut flipped = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let kind = lines[i].kind;
+        let mut matches = match kind {
+            LineKind::Removed => added.get(&id[i]),
+            LineKind::Added => removed.get(&id[i]),
+            LineKind::Context => {
+                flipped = false;
+                None
+            }
+        };
+        if !candidates.is_empty() && (matches.is_none() || block_kind != Some(kind)) {
+            // A block too short to be moved is looked at again from its second line, which
+            // may start a block of its own.
+            if !end_block(lines, &mut marks, i, block_length, mode) && block_length > 1 {
+                matches = None;
+                i -= block_length;
+            }
+            candidates.clear();
+            block_length = 0;
+            flipped = false;
+        }
+        let matches = match matches {
+            Some(matches) => matches,
+            None => {
+                block_kind = None;
+                i += 1;
+                continue;
+            }
+        };
+        if mode == ColorMoved::Plain {
+            marks[i].moved = true;
+            i += 1;
+            continue;
+        }
+
+        candidates = candidates
+            .iter()
+            .filter_map(|&candidate| next_line[candidate])
+            .filter(|&candidate| id[candidate] == id[i])
+            .collect();
+        if candidates.is_empty() {
+            let contiguous = end_block(lines, &mut marks, i, block_length, mode);
+            if !contiguous && block_length > 1 {
+                // Starting over from the second line of the block moves past this line.
+                i -= block_length;
+            } else {
+                candidates = matches.clone();
+            }
+            flipped = contiguous && !candidates.is_empty() && block_kind == Some(kind) && !flipped;
+            block_kind = Some(kind).filter(|_| !candidates.is_empty());
+            block_length = 0;
+        }
+        if !candidates.is_empty() {
+            block_length += 1;
+            marks[i].moved = true;
+            marks[i].alternative = flipped && mode != ColorMoved::Blocks;
+        }
+        i += 1;
+    }
+    end_block(lines, &mut marks, lines.len(), block_length, mode);
+
+    if mode == ColorMoved::DimmedZebra {
+        dim_moved_lines(lines, &mut marks);
+    }
+    marks
+}
+
+/// Ends the block of the `length` lines before `end`, unmarking them if they have too little
+/// text to be moved. Returns true if the block stays.
+fn end_block(
+    lines: &[HunkLine],
+    marks: &mut [MovedMark],
+    end: usize,
+    length: usize,
+    mode: ColorMoved,
+) -> bool {
+    if mode == ColorMoved::Plain {
+        return length > 0;
+    }
+    let alnum: usize = lines[end - length..end]
+        .iter()
+        .map(|line| {
+            line.text
+                .iter()
+                .filter(|ch| ch.is_ascii_alphanumeric())
+                .count()
+        })
+        .sum();
+    if alnum >= MIN_ALNUM_COUNT {
+        return true;
+    }
+    for mark in &mut marks[end - length..end] {
+        mark.moved = false;
+        mark.alternative = false;
+    }
+    false
+}
+
+/// Dims the moved lines that are neither the first nor the last line of their block, unless
+/// the block next to them is moved too.
+fn dim_moved_lines(lines: &[HunkLine], marks: &mut [MovedMark]) {
+    // The neighbours of a line that are removed or added lines.
+    let changed = |i: Option<usize>| {
+        i.filter(|&i| i < lines.len() && lines[i].kind != LineKind::Context)
+            .map(|i| marks[i])
+    };
+    let mut dimmed = vec![false; lines.len()];
+    for (i, mark) in marks.iter().enumerate() {
+        if !mark.moved {
+            continue;
+        }
+        let (prev, next) = (changed(i.checked_sub(1)), changed(Some(i + 1)));
+        let same_block = |other: Option<MovedMark>| {
+            other.is_some_and(|other| other.moved && other.alternative == mark.alternative)
+        };
+        let other_block = |other: Option<MovedMark>| {
+            other.is_some_and(|other| other.moved && other.alternative != mark.alternative)
+        };
+        // Lines inside a block and at its edges next to unmoved lines are dimmed, while the
+        // edges next to another block stay.
+        dimmed[i] =
+            (same_block(prev) && same_block(next)) || !(other_block(prev) || other_block(next));
+    }
+    for (mark, dimmed) in marks.iter_mut().zip(dimmed) {
+        mark.dimmed = dimmed;
+    }
+}
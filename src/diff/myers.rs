@@ -0,0 +1,395 @@
+//! The Myers algorithm as xdiff implements it: lines that can not match are dropped first,
+//! then the problem is split at the middle snake over and over. Unless a minimal diff is
+//! requested, heuristics stop the search for the middle snake where it gets expensive.
+
+use std::collections::HashMap;
+
+/// A snake longer than this counts as good progress for the heuristics.
+const SNAKE_CNT: isize = 20;
+/// The edit cost from which the heuristics look for good progress.
+const HEUR_MIN_COST: isize = 256;
+/// The lowest edit cost after which the furthest reaching path is taken.
+const MAX_COST_MIN: isize = 256;
+/// How far beyond the edit cost a path has to reach for the heuristics to take it.
+const K_HEUR: isize = 4;
+/// The most times a line may occur on the other side before it counts as frequent.
+const MAX_EQLIMIT: usize = 1024;
+/// How far the scan around a frequent line looks for lines without a match.
+const SIMSCAN_WINDOW: isize = 100;
+/// A frequent line is dropped if fewer than one in this many lines around it have matches.
+const KPDIS_RUN: isize = 4;
+
+/// Marks the lines removed from `old` and added to `new`, given as their classes.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    minimal: bool,
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    let (n1, n2) = (old.len(), new.len());
+    let common = n1.min(n2);
+    let mut start = 0;
+    while start < common && old[start] == new[start] {
+        start += 1;
+    }
+    let mut end = 0;
+    while end < common - start && old[n1 - 1 - end] == new[n2 - 1 - end] {
+        end += 1;
+    }
+
+    let mut counts: HashMap<usize, (usize, usize)> = HashMap::new();
+    for &class in old {
+        counts.entry(class).or_default().0 += 1;
+    }
+    for &class in new {
+        counts.entry(class).or_default().1 += 1;
+    }
+    let (index1, classes1) = reduce(old, start..n1 - end, |class| counts[&class].1, old_changed);
+    let (index2, classes2) = reduce(new, start..n2 - end, |class| counts[&class].0, new_changed);
+
+    let diagonals = classes1.len() + classes2.len() + 3;
+    let mut search = Search {
+        ha1: &classes1,
+        ha2: &classes2,
+        kvdf: vec![0; diagonals],
+        kvdb: vec![0; diagonals],
+        offset: classes2.len() as isize + 1,
+        max_cost: (bogosqrt(diagonals) as isize).max(MAX_COST_MIN),
+    };
+
+    // Each box is shrunk to its changed lines, then split in two at the middle snake.
+    let mut boxes = vec![(0, classes1.len(), 0, classes2.len(), minimal)];
+    while let Some((mut off1, mut lim1, mut off2, mut lim2, need_min)) = boxes.pop() {
+        while off1 < lim1 && off2 < lim2 && classes1[off1] == classes2[off2] {
+            off1 += 1;
+            off2 += 1;
+        }
+        while off1 < lim1 && off2 < lim2 && classes1[lim1 - 1] == classes2[lim2 - 1] {
+            lim1 -= 1;
+            lim2 -= 1;
+        }
+        if off1 == lim1 {
+            for &i in &index2[off2..lim2] {
+                new_changed[i] = true;
+            }
+        } else if off2 == lim2 {
+            for &i in &index1[off1..lim1] {
+                old_changed[i] = true;
+            }
+        } else {
+            let split = search.split(off1, lim1, off2, lim2, need_min);
+            boxes.push((split.i1, lim1, split.i2, lim2, split.min_hi));
+            boxes.push((off1, split.i1, off2, split.i2, split.min_lo));
+        }
+    }
+}
+
+/// Returns roughly the square root of `n`, as a power of two.
+fn bogosqrt(mut n: usize) -> usize {
+    let mut root = 1;
+    while n > 0 {
+        root <<= 1;
+        n >>= 2;
+    }
+    root
+}
+
+/// Drops the lines in `range` of `lines` that can not match or only make the search
+/// expensive, marking them changed, and returns the positions and classes of the others.
+/// `other_count` tells how often a class occurs on the other side. Like in git, this happens
+/// for minimal diffs too.
+fn reduce<F: Fn(usize) -> usize>(
+    lines: &[usize],
+    range: std::ops::Range<usize>,
+    other_count: F,
+    changed: &mut [bool],
+) -> (Vec<usize>, Vec<usize>) {
+    let limit = bogosqrt(lines.len()).min(MAX_EQLIMIT);
+    // 0 for lines without a match, 2 for frequent lines and 1 for the others.
+    let mut discard = vec![0u8; lines.len()];
+    for i in range.clone() {
+        discard[i] = match other_count(lines[i]) {
+            0 => 0,
+            count if count >= limit => 2,
+            _ => 1,
+        };
+    }
+
+    let (mut index, mut classes) = (Vec::new(), Vec::new());
+    if range.is_empty() {
+        return (index, classes);
+    }
+    let (first, last) = (range.start as isize, range.end as isize - 1);
+    for i in range {
+        if discard[i] == 1 || (discard[i] == 2 && !is_lonely(&discard, i as isize, first, last)) {
+            index.push(i);
+            classes.push(lines[i]);
+        } else {
+            changed[i] = true;
+        }
+    }
+    (index, classes)
+}
+
+/// Returns true if the frequent line `i` sits in a run of lines that mostly have no match,
+/// so that it most likely does not match either.
+fn is_lonely(discard: &[u8], i: isize, first: isize, last: isize) -> bool {
+    let first = first.max(i - SIMSCAN_WINDOW);
+    let last = last.min(i + SIMSCAN_WINDOW);
+    let run = |lines: &mut dyn Iterator<Item = isize>| {
+        let (mut unmatched, mut frequent) = (0, 1);
+        for j in lines {
+            match discard[j as usize] {
+                0 => unmatched += 1,
+                2 => frequent += 1,
+                _ => break,
+            }
+        }
+        (unmatched, frequent)
+    };
+
+    let (unmatched_before, frequent_before) = run(&mut (first..i).rev());
+    if unmatched_before == 0 {
+        return false;
+    }
+    let (unmatched_after, frequent_after) = run(&mut (i + 1..=last));
+    if unmatched_after == 0 {
+        return false;
+    }
+    let unmatched = unmatched_before + unmatched_after;
+    let frequent = frequent_before + frequent_after;
+    frequent * KPDIS_RUN < frequent + unmatched
+}
+
+/// Where a box is split, and whether its halves need a minimal diff.
+struct Split {
+    i1: usize,
+    i2: usize,
+    min_lo: bool,
+    min_hi: bool,
+}
+
+/// The state of the search for middle snakes, with the furthest reaching paths on each
+/// diagonal going forward and backward.
+struct Search<'a> {
+    ha1: &'a [usize],
+    ha2: &'a [usize],
+    kvdf: Vec<isize>,
+    kvdb: Vec<isize>,
+    /// The position of diagonal 0 in the vectors, since diagonals can be negative.
+    offset: isize,
+    max_cost: isize,
+}
+
+impl Search<'_> {
+    /// Finds where to split the box between `off1`, `lim1` in the old lines and `off2`,
+    /// `lim2` in the new ones: on the middle snake of the shortest edit script, or where the
+    /// heuristics say so.
+    fn split(
+        &mut self,
+        off1: usize,
+        lim1: usize,
+        off2: usize,
+        lim2: usize,
+        need_min: bool,
+    ) -> Split {
+        let (off1, lim1, off2, lim2) = (off1 as isize, lim1 as isize, off2 as isize, lim2 as isize);
+        let (ha1, ha2, o) = (self.ha1, self.ha2, self.offset);
+        let matches = |i1: isize, i2: isize| ha1[i1 as usize] == ha2[i2 as usize];
+        let kvdf = &mut self.kvdf;
+        let kvdb = &mut self.kvdb;
+        let at = |d: isize| (d + o) as usize;
+        let split = |i1: isize, i2: isize, min_lo, min_hi| Split {
+            i1: i1 as usize,
+            i2: i2 as usize,
+            min_lo,
+            min_hi,
+        };
+
+        let (dmin, dmax) = (off1 - lim2, lim1 - off2);
+        let (fmid, bmid) = (off1 - off2, lim1 - lim2);
+        let odd = (fmid - bmid) & 1 != 0;
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+        kvdf[at(fmid)] = off1;
+        kvdb[at(bmid)] = lim1;
+
+        let mut ec = 1;
+        loop {
+            let mut got_snake = false;
+
+            // Extend the forward diagonals by one, guarding the new ends.
+            if fmin > dmin {
+                fmin -= 1;
+                kvdf[at(fmin - 1)] = -1;
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                kvdf[at(fmax + 1)] = -1;
+            } else {
+                fmax -= 1;
+            }
+            let mut d = fmax;
+            while d >= fmin {
+                let mut i1 = if kvdf[at(d - 1)] >= kvdf[at(d + 1)] {
+                    kvdf[at(d - 1)] + 1
+                } else {
+                    kvdf[at(d + 1)]
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 < lim1 && i2 < lim2 && matches(i1, i2) {
+                    i1 += 1;
+                    i2 += 1;
+                }
+                if i1 - prev1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                kvdf[at(d)] = i1;
+                if odd && bmin <= d && d <= bmax && kvdb[at(d)] <= i1 {
+                    return split(i1, i2, true, true);
+                }
+                d -= 2;
+            }
+
+            // Extend the backward diagonals by one, guarding the new ends.
+            if bmin > dmin {
+                bmin -= 1;
+                kvdb[at(bmin - 1)] = isize::MAX;
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                kvdb[at(bmax + 1)] = isize::MAX;
+            } else {
+                bmax -= 1;
+            }
+            let mut d = bmax;
+            while d >= bmin {
+                let mut i1 = if kvdb[at(d - 1)] < kvdb[at(d + 1)] {
+                    kvdb[at(d - 1)]
+                } else {
+                    kvdb[at(d + 1)] - 1
+                };
+                let prev1 = i1;
+                let mut i2 = i1 - d;
+                while i1 > off1 && i2 > off2 && matches(i1 - 1, i2 - 1) {
+                    i1 -= 1;
+                    i2 -= 1;
+                }
+                if prev1 - i1 > SNAKE_CNT {
+                    got_snake = true;
+                }
+                kvdb[at(d)] = i1;
+                if !odd && fmin <= d && d <= fmax && i1 <= kvdf[at(d)] {
+                    return split(i1, i2, true, true);
+                }
+                d -= 2;
+            }
+
+            if need_min {
+                ec += 1;
+                continue;
+            }
+
+            // Past the trigger cost, a path that got far with a good snake at its end is
+            // taken: how far it got, less its distance from the middle diagonal, has to be
+            // well above the cost.
+            if got_snake && ec > HEUR_MIN_COST {
+                let mut best = 0;
+                let mut found = (0, 0);
+                let mut d = fmax;
+                while d >= fmin {
+                    let dd = (d - fmid).abs();
+                    let i1 = kvdf[at(d)];
+                    let i2 = i1 - d;
+                    let v = (i1 - off1) + (i2 - off2) - dd;
+                    if v > K_HEUR * ec
+                        && v > best
+                        && off1 + SNAKE_CNT <= i1
+                        && i1 < lim1
+                        && off2 + SNAKE_CNT <= i2
+                        && i2 < lim2
+                        && (1..=SNAKE_CNT).all(|k| matches(i1 - k, i2 - k))
+                    {
+                        best = v;
+                        found = (i1, i2);
+                    }
+                    d -= 2;
+                }
+                if best > 0 {
+                    return split(found.0, found.1, true, false);
+                }
+
+                let mut d = bmax;
+                while d >= bmin {
+                    let dd = (d - bmid).abs();
+                    let i1 = kvdb[at(d)];
+                    let i2 = i1 - d;
+                    let v = (lim1 - i1) + (lim2 - i2) - dd;
+                    if v > K_HEUR * ec
+                        && v > best
+                        && off1 < i1
+                        && i1 <= lim1 - SNAKE_CNT
+                        && off2 < i2
+                        && i2 <= lim2 - SNAKE_CNT
+                        && (0..SNAKE_CNT).all(|k| matches(i1 + k, i2 + k))
+                    {
+                        best = v;
+                        found = (i1, i2);
+                    }
+                    d -= 2;
+                }
+                if best > 0 {
+                    return split(found.0, found.1, false, true);
+                }
+            }
+
+            // Enough is enough: take the path reaching furthest in either direction.
+            if ec >= self.max_cost {
+                let (mut fbest, mut fbest1) = (-1, -1);
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut i1 = kvdf[at(d)].min(lim1);
+                    let mut i2 = i1 - d;
+                    if lim2 < i2 {
+                        i1 = lim2 + d;
+                        i2 = lim2;
+                    }
+                    if fbest < i1 + i2 {
+                        fbest = i1 + i2;
+                        fbest1 = i1;
+                    }
+                    d -= 2;
+                }
+
+                let (mut bbest, mut bbest1) = (isize::MAX, isize::MAX);
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut i1 = kvdb[at(d)].max(off1);
+                    let mut i2 = i1 - d;
+                    if i2 < off2 {
+                        i1 = off2 + d;
+                        i2 = off2;
+                    }
+                    if i1 + i2 < bbest {
+                        bbest = i1 + i2;
+                        bbest1 = i1;
+                    }
+                    d -= 2;
+                }
+
+                return if (lim1 + lim2) - bbest < fbest - (off1 + off2) {
+                    split(fbest1, fbest - fbest1, true, false)
+                } else {
+                    split(bbest1, bbest - bbest1, false, true)
+                };
+            }
+            ec += 1;
+        }
+    }
+}
@@ -0,0 +1,1129 @@
+//! Showing the changes of a diff as patches, diffstats or lists of names.
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use regex::bytes::Regex;
+
+use super::changes::{ChangeStatus, DiffFile, FileChange, GitDiff};
+use super::moved::{self, ColorMoved, MovedMark};
+use super::words::{self, WordDiff};
+use super::{diff_lines, DiffOptions, Hunk, HunkLine, LineKind};
+use crate::refs::NULL_SHA;
+use crate::revision::{self, DEFAULT_ABBREV};
+use crate::utils::{self, COLOR_RESET};
+use crate::{self as gitrs, GitConfig, GitError, GitResult};
+
+/// What is shown for the changes of a diff, like the output options of `git diff`.
+///
+/// The names or raw output are listed first, then the numbers of changed lines and finally
+/// the patch.
+#[derive(Debug, Clone, Default)]
+pub struct DiffFormat {
+    /// A unified patch for each file, like `--patch`.
+    pub patch: bool,
+    /// A histogram of the added and removed lines of each file, like `--stat`.
+    pub stat: bool,
+    /// The numbers of added and removed lines of each file, like `--numstat`.
+    pub numstat: bool,
+    /// Only the totals of `--stat`, like `--shortstat`.
+    pub shortstat: bool,
+    /// A line for each created, deleted, renamed or copied file and each changed mode, like
+    /// `--summary`.
+    pub summary: bool,
+    /// The names of the changed files, like `--name-only`.
+    pub name_only: bool,
+    /// The names of the changed files with their status, like `--name-status`.
+    pub name_status: bool,
+    /// The modes, object names and status of the changed files, like `--raw`. The lists of
+    /// names replace it.
+    pub raw: bool,
+    /// The number of hex digits of the object names in raw output, or [None] for the full
+    /// names.
+    pub abbrev: Option<usize>,
+    /// Names are not quoted and end with NUL bytes in raw output and the lists of names,
+    /// like `-z`.
+    pub null_terminated: bool,
+    /// The width of `--stat` lines, or [None] for the width of the terminal.
+    pub stat_width: Option<usize>,
+    /// The widest the file names of `--stat` lines get.
+    pub stat_name_width: Option<usize>,
+    pub color: bool,
+    /// Patches show the changed words within the changed lines, like `--word-diff=<mode>`.
+    pub word_diff: Option<WordDiff>,
+    /// The words of `word_diff`, runs of non-whitespace if [None], like `--word-diff-regex`.
+    pub word_regex: Option<Regex>,
+    /// Lines moved within the diff get their own colors, like `--color-moved=<mode>`. Only
+    /// used with `color`.
+    pub color_moved: Option<ColorMoved>,
+    /// Patches of binary files hold their contents, so that they can be applied, like
+    /// `--binary`.
+    pub binary: bool,
+}
+
+/// The colors of the parts of a diff, set with `color.diff.<slot>`.
+#[derive(Debug, Clone, Default)]
+pub(super) struct DiffColors {
+    meta: String,
+    frag: String,
+    func: String,
+    context: String,
+    old: String,
+    new: String,
+    whitespace: String,
+    /// The colors of moved lines, by whether they are added, in an alternative block and
+    /// dimmed.
+    moved: [[[String; 2]; 2]; 2],
+    reset: String,
+}
+
+impl DiffColors {
+    /// Reads the colors from `config`, falling back to git's defaults.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::DiffError]: A color is invalid
+    pub(super) fn load(config: &GitConfig) -> GitResult<Self> {
+        let color = |slots: &[&str], default: &str| -> GitResult<String> {
+            let mut value = None;
+            for slot in slots {
+                if let Some(color) = config.get(&format!("color.diff.{}", slot))? {
+                    value = Some(color);
+                }
+            }
+            match value {
+                Some(value) => utils::parse_color(&value).ok_or_else(|| GitError::DiffError {
+                    message: format!("invalid color value: {}", value),
+                }),
+                None => Ok(default.to_string()),
+            }
+        };
+        Ok(Self {
+            meta: color(&["meta"], "\x1b[1m")?,
+            frag: color(&["frag"], "\x1b[36m")?,
+            func: color(&["func"], "")?,
+            context: color(&["plain", "context"], "")?,
+            old: color(&["old"], "\x1b[31m")?,
+            new: color(&["new"], "\x1b[32m")?,
+            whitespace: color(&["whitespace"], "\x1b[41m")?,
+            moved: [
+                [
+                    [
+                        color(&["oldMoved"], "\x1b[1;35m")?,
+                        color(&["oldMovedDimmed"], "\x1b[2m")?,
+                    ],
+                    [
+                        color(&["oldMovedAlternative"], "\x1b[1;34m")?,
+                        color(&["oldMovedAlternativeDimmed"], "\x1b[2;3m")?,
+                    ],
+                ],
+                [
+                    [
+                        color(&["newMoved"], "\x1b[1;36m")?,
+                        color(&["newMovedDimmed"], "\x1b[2m")?,
+                    ],
+                    [
+                        color(&["newMovedAlternative"], "\x1b[1;33m")?,
+                        color(&["newMovedAlternativeDimmed"], "\x1b[2;3m")?,
+                    ],
+                ],
+            ],
+            reset: COLOR_RESET.to_string(),
+        })
+    }
+
+    /// Returns the color of a removed or added line marked by `--color-moved`.
+    fn line(&self, kind: LineKind, mark: MovedMark) -> &str {
+        let added = kind == LineKind::Added;
+        match (mark.moved, added) {
+            (false, false) => &self.old,
+            (false, true) => &self.new,
+            (true, _) => {
+                &self.moved[added as usize][mark.alternative as usize][mark.dimmed as usize]
+            }
+        }
+    }
+}
+
+/// How the hunks of the patches of one diff are written.
+struct PatchStyle<'a> {
+    colors: DiffColors,
+    /// The mode and regex of `--word-diff`.
+    words: Option<(WordDiff, Option<&'a Regex>)>,
+    /// The marks of the removed and added lines still to be written, for `--color-moved`.
+    moved: VecDeque<MovedMark>,
+    /// Binary files get a `GIT binary patch` instead of a line saying they differ.
+    binary: bool,
+}
+
+/// The numbers of a file shown by `--stat`, `--numstat` and `--shortstat`.
+#[derive(Debug)]
+struct FileStat {
+    name: String,
+    /// The old path if the file was renamed or copied, and the path.
+    paths: (Option<String>, String),
+    added: usize,
+    deleted: usize,
+    /// For binary files the numbers are the sizes in bytes.
+    binary: bool,
+    unmerged: bool,
+}
+
+impl GitDiff {
+    /// Writes `changes` to `out` as described by `format`, comparing the lines of the files
+    /// as described by `options`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The output can not be written
+    /// * Errors obtained when reading the contents of the files
+    pub fn write<W: Write>(
+        &mut self,
+        changes: &[FileChange],
+        options: &DiffOptions,
+        format: &DiffFormat,
+        out: &mut W,
+    ) -> GitResult<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let colors = if format.color {
+            self.colors.clone()
+        } else {
+            DiffColors::default()
+        };
+
+        let mut output = Vec::new();
+        if format.name_only || format.name_status || format.raw {
+            for change in changes {
+                self.write_name(change, format, &mut output)?;
+            }
+        }
+        let stats = format.stat || format.numstat || format.shortstat;
+        if stats {
+            let mut files = Vec::new();
+            for change in changes {
+                files.extend(self.file_stat(change, options)?);
+            }
+            if format.numstat {
+                write_numstat(&files, format.null_terminated, &mut output);
+            }
+            if format.stat {
+                write_stat(&files, format, &colors, &mut output);
+            }
+            if format.shortstat {
+                write_totals(&files, &mut output);
+            }
+        }
+        if format.summary {
+            for change in changes {
+                write_summary(change, &mut output);
+            }
+        }
+        gitrs::to_git_result(out.write_all(&output), "<stdout>")?;
+
+        if format.patch {
+            if stats || format.summary || format.raw {
+                gitrs::to_git_result(out.write_all(b"\n"), "<stdout>")?;
+            }
+            let moved = match format.color_moved {
+                Some(mode) if format.color && format.word_diff.is_none() => {
+                    self.moved_marks(changes, options, mode)?
+                }
+                _ => VecDeque::new(),
+            };
+            let mut style = PatchStyle {
+                colors,
+                words: format
+                    .word_diff
+                    .map(|mode| (mode, format.word_regex.as_ref())),
+                moved,
+                binary: format.binary,
+            };
+            for change in changes {
+                let mut patch = Vec::new();
+                if change.status() == ChangeStatus::Unmerged {
+                    patch.extend(format!("* Unmerged path {}\n", change.path()).as_bytes());
+                }
+                let pairing = match change.status() {
+                    ChangeStatus::Renamed => Some(("rename", change.similarity().unwrap())),
+                    ChangeStatus::Copied => Some(("copy", change.similarity().unwrap())),
+                    _ => None,
+                };
+                for (old, new) in patch_sides(change) {
+                    self.write_patch(old, new, pairing, options, &mut style, &mut patch)?;
+                }
+                gitrs::to_git_result(out.write_all(&patch), "<stdout>")?;
+            }
+        }
+        gitrs::to_git_result(out.flush(), "<stdout>")
+    }
+
+    /// Returns the `--color-moved` marks of the removed and added lines of the patches of
+    /// `changes`, in order.
+    ///
+    /// # Errors
+    ///
+    /// * Errors obtained when reading the contents of the files
+    fn moved_marks(
+        &mut self,
+        changes: &[FileChange],
+        options: &DiffOptions,
+        mode: ColorMoved,
+    ) -> GitResult<VecDeque<MovedMark>> {
+        // The headers of files and hunks, and the marker of an incomplete last line, end
+        // blocks like unchanged lines.
+        let boundary = || HunkLine {
+            kind: LineKind::Context,
+            text: Vec::new(),
+        };
+        let mut lines = Vec::new();
+        for change in changes {
+            for (old, new) in patch_sides(change) {
+                let (old_data, new_data) = (self.contents(old)?, self.contents(new)?);
+                if self.is_binary_side(old, &old_data)? || self.is_binary_side(new, &new_data)? {
+                    continue;
+                }
+                lines.push(boundary());
+                for hunk in diff_lines(&old_data, &new_data, options) {
+                    lines.push(boundary());
+                    for line in hunk.lines {
+                        let incomplete = !line.has_newline();
+                        lines.push(line);
+                        if incomplete {
+                            lines.push(boundary());
+                        }
+                    }
+                }
+            }
+        }
+        let marks = moved::mark_moved(&lines, mode);
+        Ok(lines
+            .iter()
+            .zip(marks)
+            .filter(|(line, _)| line.kind != LineKind::Context)
+            .map(|(_, mark)| mark)
+            .collect())
+    }
+
+    /// Appends the line of `change` in the lists of names or the raw output to `out`, like
+    /// `:100644 100644 <old sha> <new sha> M\tpath`.
+    fn write_name(
+        &mut self,
+        change: &FileChange,
+        format: &DiffFormat,
+        out: &mut Vec<u8>,
+    ) -> GitResult<()> {
+        let (separator, end) = if format.null_terminated {
+            (b'\0', b'\0')
+        } else {
+            (b'\t', b'\n')
+        };
+        let write_path = |path: &str, out: &mut Vec<u8>| {
+            if format.null_terminated {
+                out.extend(path.as_bytes());
+            } else {
+                out.extend(utils::quote_c_style(path).as_bytes());
+            }
+        };
+        let names = format.name_only || format.name_status;
+        if format.raw && !names {
+            let (old, new) = (change.old_file(), change.new_file());
+            let mode = |file: Option<&DiffFile>| {
+                file.map_or("000000".to_string(), |file| file.mode().to_string())
+            };
+            out.extend(format!(":{} {}", mode(old), mode(new)).as_bytes());
+            for file in [old, new] {
+                let sha = file.map_or(NULL_SHA, DiffFile::raw_sha);
+                let sha = match format.abbrev {
+                    None => sha.to_string(),
+                    Some(abbrev) if sha == NULL_SHA => {
+                        NULL_SHA[..abbrev.min(sha.len())].to_string()
+                    }
+                    Some(abbrev) => revision::abbreviate(self.odb(), sha, abbrev)?,
+                };
+                out.extend(format!(" {}", sha).as_bytes());
+            }
+            out.push(b' ');
+        }
+        if !format.name_only {
+            out.push(change.status().letter() as u8);
+            if let Some(similarity) = change.similarity() {
+                out.extend(format!("{:03}", similarity).as_bytes());
+            }
+            out.push(separator);
+            if let Some(old) = change.old_file().filter(|old| old.path() != change.path()) {
+                write_path(old.path(), out);
+                out.push(separator);
+            }
+        }
+        write_path(change.path(), out);
+        out.push(end);
+        Ok(())
+    }
+
+    /// Returns the numbers of added and removed lines of `change`, or [None] if the contents
+    /// only differ in ways the options ignore.
+    fn file_stat(
+        &mut self,
+        change: &FileChange,
+        options: &DiffOptions,
+    ) -> GitResult<Option<FileStat>> {
+        let name = match change.old_file() {
+            Some(old) if old.path() != change.path() => rename_name(old.path(), change.path()),
+            _ => utils::quote_c_style(change.path()),
+        };
+        let mut stat = FileStat {
+            name,
+            paths: (
+                change.old_file().map(|old| old.path().to_string()),
+                change.path().to_string(),
+            ),
+            added: 0,
+            deleted: 0,
+            binary: false,
+            unmerged: change.status() == ChangeStatus::Unmerged,
+        };
+        if stat.unmerged {
+            return Ok(Some(stat));
+        }
+        let (old, new) = (change.old_file(), change.new_file());
+        let same_contents = self.object_name(old)? == self.object_name(new)?;
+        let old_data = self.contents(old)?;
+        let new_data = self.contents(new)?;
+        stat.binary =
+            self.is_binary_side(old, &old_data)? || self.is_binary_side(new, &new_data)?;
+        if same_contents {
+            return Ok(Some(stat));
+        }
+        if stat.binary {
+            stat.added = new_data.len();
+            stat.deleted = old_data.len();
+            return Ok(Some(stat));
+        }
+
+        for hunk in diff_lines(&old_data, &new_data, options) {
+            for line in &hunk.lines {
+                match line.kind {
+                    LineKind::Added => stat.added += 1,
+                    LineKind::Removed => stat.deleted += 1,
+                    LineKind::Context => {}
+                }
+            }
+        }
+        let is_gitlink =
+            |file: Option<&DiffFile>| file.is_some_and(|file| file.mode().is_gitlink());
+        if let (Some(old), Some(new)) = (old, new) {
+            if stat.added == 0
+                && old.path() == new.path()
+                && stat.deleted == 0
+                && !is_gitlink(Some(old))
+                && !is_gitlink(Some(new))
+            {
+                return Ok(None);
+            }
+        }
+        Ok(Some(stat))
+    }
+
+    /// Appends the patch turning `old` into `new` to `out`, where [None] stands for a missing
+    /// file. `pairing` is the kind (`rename` or `copy`) and similarity index of a renamed or
+    /// copied file.
+    fn write_patch(
+        &mut self,
+        old: Option<&DiffFile>,
+        new: Option<&DiffFile>,
+        pairing: Option<(&str, u32)>,
+        options: &DiffOptions,
+        style: &mut PatchStyle,
+        out: &mut Vec<u8>,
+    ) -> GitResult<()> {
+        let colors = &style.colors;
+        let (meta, reset) = (&colors.meta, &colors.reset);
+        let name_a = old.or(new).unwrap().path();
+        let name_b = new.or(old).unwrap().path();
+        let a_name = quote_path("a/", name_a);
+        let b_name = quote_path("b/", name_b);
+        let labels = [
+            if old.is_some() {
+                a_name.as_str()
+            } else {
+                "/dev/null"
+            },
+            if new.is_some() {
+                b_name.as_str()
+            } else {
+                "/dev/null"
+            },
+        ];
+
+        let mut header = format!("{}diff --git {} {}{}\n", meta, a_name, b_name, reset);
+        let mut must_show_header = true;
+        match (old, new) {
+            (None, Some(new)) => {
+                header.push_str(&format!("{}new file mode {}{}\n", meta, new.mode(), reset));
+            }
+            (Some(old), None) => {
+                header.push_str(&format!(
+                    "{}deleted file mode {}{}\n",
+                    meta,
+                    old.mode(),
+                    reset
+                ));
+            }
+            (Some(old), Some(new)) if old.mode() != new.mode() => {
+                header.push_str(&format!("{}old mode {}{}\n", meta, old.mode(), reset));
+                header.push_str(&format!("{}new mode {}{}\n", meta, new.mode(), reset));
+            }
+            _ => must_show_header = false,
+        }
+        if let Some((kind, similarity)) = pairing {
+            header.push_str(&format!(
+                "{}similarity index {}%{}\n",
+                meta, similarity, reset
+            ));
+            for (side, name) in [("from", name_a), ("to", name_b)] {
+                header.push_str(&format!(
+                    "{}{} {} {}{}\n",
+                    meta,
+                    kind,
+                    side,
+                    utils::quote_c_style(name),
+                    reset
+                ));
+            }
+            must_show_header = true;
+        }
+        let (old_sha, new_sha) = (self.object_name(old)?, self.object_name(new)?);
+        if old_sha == new_sha {
+            if must_show_header {
+                out.extend(header.as_bytes());
+            }
+            return Ok(());
+        }
+        let old_data = self.contents(old)?;
+        let new_data = self.contents(new)?;
+        let binary = self.is_binary_side(old, &old_data)? || self.is_binary_side(new, &new_data)?;
+        // Binary patches name the blobs in full, so that they can be checked before applying.
+        let abbrev = |sha: &str| match binary && style.binary {
+            true => Ok(sha.to_string()),
+            false => revision::abbreviate(self.odb(), sha, DEFAULT_ABBREV),
+        };
+        header.push_str(&format!(
+            "{}index {}..{}",
+            meta,
+            abbrev(&old_sha)?,
+            abbrev(&new_sha)?
+        ));
+        if let (Some(old), Some(new)) = (old, new) {
+            if old.mode() == new.mode() {
+                header.push_str(&format!(" {}", old.mode()));
+            }
+        }
+        header.push_str(&format!("{}\n", reset));
+
+        if binary {
+            if old_data != new_data || must_show_header {
+                out.extend(header.as_bytes());
+            }
+            if old_data != new_data && style.binary {
+                out.extend(b"GIT binary patch\n");
+                write_binary_hunk(&new_data, out)?;
+                write_binary_hunk(&old_data, out)?;
+            } else if old_data != new_data {
+                let message = format!("Binary files {} and {} differ\n", labels[0], labels[1]);
+                out.extend(message.as_bytes());
+            }
+            return Ok(());
+        }
+
+        let hunks = diff_lines(&old_data, &new_data, options);
+        if hunks.is_empty() {
+            if must_show_header {
+                out.extend(header.as_bytes());
+            }
+            return Ok(());
+        }
+        out.extend(header.as_bytes());
+        for (sign, label) in ["---", "+++"].iter().zip(&labels) {
+            let tab = if label.contains(' ') { "\t" } else { "" };
+            out.extend(format!("{}{} {}{}{}\n", meta, sign, label, tab, reset).as_bytes());
+        }
+
+        // Blank lines added at the end of the file are whitespace errors.
+        let blank_at_eof = if colors.whitespace.is_empty() {
+            None
+        } else {
+            blank_at_eof(&old_data, &new_data)
+        };
+        for hunk in &hunks {
+            write_hunk(hunk, style, blank_at_eof, out);
+        }
+        Ok(())
+    }
+
+    /// Returns the contents of `file`, empty for a missing side.
+    fn contents(&mut self, file: Option<&DiffFile>) -> GitResult<Vec<u8>> {
+        match file {
+            Some(file) => self.read(file),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn is_binary_side(&mut self, file: Option<&DiffFile>, data: &[u8]) -> GitResult<bool> {
+        match file {
+            Some(file) => self.is_binary(file, data),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Returns the files compared by the patches of `change`, where [None] stands for a missing
+/// file. A file that changes its type is shown as deleted and added again, and unmerged files
+/// have no patch.
+fn patch_sides(change: &FileChange) -> Vec<(Option<&DiffFile>, Option<&DiffFile>)> {
+    let (old, new) = (change.old_file(), change.new_file());
+    match change.status() {
+        ChangeStatus::Unmerged => Vec::new(),
+        ChangeStatus::TypeChanged => vec![(old, None), (None, new)],
+        _ => vec![(old, new)],
+    }
+}
+
+/// Appends `hunk` to `out`, highlighting whitespace errors in added lines. `blank_at_eof`
+/// holds the first old and new line numbers of the blank lines at the end of the new file.
+fn write_hunk(
+    hunk: &Hunk,
+    style: &mut PatchStyle,
+    blank_at_eof: Option<(usize, usize)>,
+    out: &mut Vec<u8>,
+) {
+    let colors = &style.colors;
+    let reset = colors.reset.as_str();
+    let header = hunk.header();
+    // The function name follows the second "@@".
+    let end = 2 + header[2..].windows(2).position(|w| w == b"@@").unwrap() + 2;
+    out.extend(colors.frag.as_bytes());
+    out.extend(&header[..end]);
+    out.extend(reset.as_bytes());
+    let function = &header[end..];
+    let name = function
+        .iter()
+        .position(|&ch| ch != b' ' && ch != b'\t')
+        .unwrap_or(function.len());
+    if name > 0 {
+        out.extend(colors.context.as_bytes());
+        out.extend(&function[..name]);
+        out.extend(reset.as_bytes());
+    }
+    if name < function.len() {
+        out.extend(colors.func.as_bytes());
+        out.extend(&function[name..]);
+        out.extend(reset.as_bytes());
+    }
+    out.push(b'\n');
+
+    if let Some((mode, regex)) = style.words {
+        write_word_lines(hunk, mode, regex, colors, out);
+        return;
+    }
+
+    // Like git, the line numbers are those of the line after the one just shown.
+    let (mut old_line, mut new_line) = (hunk.old_start, hunk.new_start);
+    for line in &hunk.lines {
+        match line.kind {
+            LineKind::Context => {
+                old_line += 1;
+                new_line += 1;
+                write_line(&colors.context, reset, b" ", &line.text, out);
+            }
+            LineKind::Removed => {
+                old_line += 1;
+                let set = colors.line(line.kind, style.moved.pop_front().unwrap_or_default());
+                write_line(set, reset, b"-", &line.text, out);
+            }
+            LineKind::Added => {
+                new_line += 1;
+                let set = colors.line(line.kind, style.moved.pop_front().unwrap_or_default());
+                let at_eof = blank_at_eof.is_some_and(|(old_blank, new_blank)| {
+                    old_blank <= old_line && new_blank <= new_line && is_blank(&line.text)
+                });
+                if colors.whitespace.is_empty() {
+                    write_line(set, reset, b"+", &line.text, out);
+                } else if at_eof {
+                    write_line(&colors.whitespace, reset, b"+", &line.text, out);
+                } else {
+                    write_line(set, reset, b"+", b"", out);
+                    write_whitespace_errors(&line.text, set, colors, out);
+                }
+            }
+        }
+        if !line.has_newline() {
+            out.push(b'\n');
+            write_line(
+                &colors.context,
+                reset,
+                b"\\",
+                b" No newline at end of file\n",
+                out,
+            );
+        }
+    }
+}
+
+/// Appends the lines of `hunk` with the words changed by each run of removed and added lines
+/// marked as set by `mode`.
+fn write_word_lines(
+    hunk: &Hunk,
+    mode: WordDiff,
+    regex: Option<&Regex>,
+    colors: &DiffColors,
+    out: &mut Vec<u8>,
+) {
+    let style = mode.style(&colors.context, &colors.old, &colors.new, &colors.reset);
+    let (mut minus, mut plus) = (Vec::new(), Vec::new());
+    for line in &hunk.lines {
+        // Like in git, an incomplete last line is shown as if it had a newline.
+        let mut text = line.text.clone();
+        if !line.has_newline() {
+            text.push(b'\n');
+        }
+        match line.kind {
+            LineKind::Removed => minus.extend(text),
+            LineKind::Added => plus.extend(text),
+            LineKind::Context => {
+                words::write_words(&minus, &plus, regex, &style, out);
+                minus.clear();
+                plus.clear();
+                if mode == WordDiff::Porcelain {
+                    write_line(&colors.context, &colors.reset, b" ", &text, out);
+                    out.extend(b"~\n");
+                } else {
+                    write_line(&colors.context, &colors.reset, b"", &text, out);
+                }
+            }
+        }
+    }
+    words::write_words(&minus, &plus, regex, &style, out);
+}
+
+/// Appends `sign` and `text` in the color `set`, keeping a line ending out of the color. An
+/// empty line without a sign is not colored.
+fn write_line(set: &str, reset: &str, sign: &[u8], text: &[u8], out: &mut Vec<u8>) {
+    let (text, ending) = split_ending(text);
+    if !sign.is_empty() || !text.is_empty() {
+        out.extend(set.as_bytes());
+        out.extend(sign);
+        out.extend(text);
+        out.extend(reset.as_bytes());
+    }
+    out.extend(ending);
+}
+
+/// Appends the added line `text`, highlighting whitespace at its end and spaces before tabs
+/// in its indent.
+fn write_whitespace_errors(text: &[u8], set: &str, colors: &DiffColors, out: &mut Vec<u8>) {
+    let (reset, ws) = (&colors.reset, &colors.whitespace);
+    let (text, newline) = match text.strip_suffix(b"\n") {
+        Some(text) => (text, &b"\n"[..]),
+        None => (text, &b""[..]),
+    };
+    let trailing = text.len() - text.iter().rev().take_while(|&&ch| is_space(ch)).count();
+
+    let mut written = 0;
+    for i in 0..trailing {
+        match text[i] {
+            b' ' => continue,
+            b'\t' => {}
+            _ => break,
+        }
+        if written < i {
+            out.extend(ws.as_bytes());
+            out.extend(&text[written..i]);
+            out.extend(reset.as_bytes());
+            out.push(b'\t');
+        } else {
+            out.extend(&text[written..=i]);
+        }
+        written = i + 1;
+    }
+    if trailing > written {
+        out.extend(set.as_bytes());
+        out.extend(&text[written..trailing]);
+        out.extend(reset.as_bytes());
+    }
+    if trailing < text.len() {
+        out.extend(ws.as_bytes());
+        out.extend(&text[trailing..]);
+        out.extend(reset.as_bytes());
+    }
+    out.extend(newline);
+}
+
+/// Splits a trailing newline, and a carriage return before it, off `text`.
+fn split_ending(text: &[u8]) -> (&[u8], &[u8]) {
+    let mut len = text.len();
+    if text[..len].ends_with(b"\n") {
+        len -= 1;
+    }
+    if text[..len].ends_with(b"\r") {
+        len -= 1;
+    }
+    text.split_at(len)
+}
+
+pub(super) fn is_space(ch: u8) -> bool {
+    matches!(ch, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+fn is_blank(text: &[u8]) -> bool {
+    text.iter().all(|&ch| is_space(ch))
+}
+
+/// Returns the old and new line numbers from which on added blank lines are at the end of the
+/// file, or [None] if the new file does not end in more blank lines than the old one.
+fn blank_at_eof(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    let (old_blank, new_blank) = (trailing_blank_lines(old), trailing_blank_lines(new));
+    if new_blank <= old_blank {
+        return None;
+    }
+    Some((
+        count_lines(old) - old_blank + 1,
+        count_lines(new) - new_blank + 1,
+    ))
+}
+
+/// Counts the blank lines at the end of `data`. Like git, blank lines at the very start of
+/// the file are not always counted.
+fn trailing_blank_lines(data: &[u8]) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    let mut end = data.len() as isize - 1;
+    if data[end as usize] == b'\n' {
+        end -= 1;
+    }
+    let mut count = 0;
+    while end > 0 {
+        let mut newline = end;
+        while newline >= 0 && data[newline as usize] != b'\n' {
+            newline -= 1;
+        }
+        if !is_blank(&data[(newline + 1) as usize..=end as usize]) {
+            break;
+        }
+        count += 1;
+        end = newline - 1;
+    }
+    count
+}
+
+/// Counts the lines of `data`, including a last one without a newline.
+fn count_lines(data: &[u8]) -> usize {
+    let newlines = data.iter().filter(|&&ch| ch == b'\n').count();
+    match data.last() {
+        None | Some(b'\n') => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+/// Prefixes `path` with `prefix`, quoting both together if the path needs quoting.
+fn quote_path(prefix: &str, path: &str) -> String {
+    let quoted = utils::quote_c_style(path);
+    if quoted == path {
+        format!("{}{}", prefix, path)
+    } else {
+        format!("\"{}{}", prefix, &quoted[1..])
+    }
+}
+
+/// Returns the name of a file renamed from `old` to `new` in diffstats, like `a => b`, with
+/// the common leading and trailing directories outside of braces: `dir/{a => b}/file`.
+fn rename_name(old: &str, new: &str) -> String {
+    let (quoted_old, quoted_new) = (utils::quote_c_style(old), utils::quote_c_style(new));
+    if quoted_old != old || quoted_new != new {
+        return format!("{} => {}", quoted_old, quoted_new);
+    }
+    let (a, b) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        if x != y {
+            break;
+        }
+        if *x == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // The suffix starts with a slash, which may be the one ending the prefix.
+    let mut suffix = 0;
+    let limit = prefix.saturating_sub(1);
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > limit && j > limit && a[i - 1] == b[j - 1] {
+        if a[i - 1] == b'/' {
+            suffix = a.len() - (i - 1);
+        }
+        i -= 1;
+        j -= 1;
+    }
+    let middle =
+        |name: &str| name[prefix..name.len() - suffix.min(name.len() - prefix)].to_string();
+    if prefix + suffix == 0 {
+        return format!("{} => {}", old, new);
+    }
+    format!(
+        "{}{{{} => {}}}{}",
+        &old[..prefix],
+        middle(old),
+        middle(new),
+        &old[old.len() - suffix..]
+    )
+}
+
+/// Writes the `--summary` lines of `change`, like ` create mode 100644 path` or
+/// ` rename a => b (90%)`.
+fn write_summary(change: &FileChange, out: &mut Vec<u8>) {
+    let (old, new) = (change.old_file(), change.new_file());
+    let mode_change = |show_name: bool, out: &mut Vec<u8>| {
+        if let (Some(old), Some(new)) = (old, new) {
+            if old.mode() != new.mode() {
+                out.extend(format!(" mode change {} => {}", old.mode(), new.mode()).as_bytes());
+                if show_name {
+                    out.extend(format!(" {}", utils::quote_c_style(new.path())).as_bytes());
+                }
+                out.push(b'\n');
+            }
+        }
+    };
+    match change.status() {
+        ChangeStatus::Added | ChangeStatus::Deleted => {
+            let (kind, file) = match new {
+                Some(new) => ("create", new),
+                None => ("delete", old.unwrap()),
+            };
+            let name = utils::quote_c_style(file.path());
+            out.extend(format!(" {} mode {} {}\n", kind, file.mode(), name).as_bytes());
+        }
+        ChangeStatus::Renamed | ChangeStatus::Copied => {
+            let kind = match change.status() {
+                ChangeStatus::Renamed => "rename",
+                _ => "copy",
+            };
+            let name = rename_name(old.unwrap().path(), change.path());
+            let similarity = change.similarity().unwrap_or(0);
+            out.extend(format!(" {} {} ({}%)\n", kind, name, similarity).as_bytes());
+            mode_change(false, out);
+        }
+        ChangeStatus::Unmerged => {}
+        ChangeStatus::Modified | ChangeStatus::TypeChanged => mode_change(true, out),
+    }
+}
+
+/// Appends a `literal` hunk of a binary patch holding `data` to `out`: the deflated data in
+/// base85, at most 52 bytes to a line, led by the length as a letter.
+///
+/// Unlike git, which sends a delta against the other side when it is smaller, this always
+/// sends the whole contents.
+fn write_binary_hunk(data: &[u8], out: &mut Vec<u8>) -> GitResult<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    gitrs::to_git_result(encoder.write_all(data), "<stdout>")?;
+    let deflated = gitrs::to_git_result(encoder.finish(), "<stdout>")?;
+    out.extend(format!("literal {}\n", data.len()).as_bytes());
+    for chunk in deflated.chunks(52) {
+        let len = match chunk.len() {
+            len @ 1..=26 => b'A' + len as u8 - 1,
+            len => b'a' + len as u8 - 27,
+        };
+        out.push(len);
+        out.extend(utils::encode_base85(chunk).as_bytes());
+        out.push(b'\n');
+    }
+    out.push(b'\n');
+    Ok(())
+}
+
+/// Writes the `--numstat` lines of `files`. With `null_terminated` the names are not quoted
+/// and renames show both names, like `1\t2\t\0old\0new\0`.
+fn write_numstat(files: &[FileStat], null_terminated: bool, out: &mut Vec<u8>) {
+    for file in files {
+        let numbers = if file.binary {
+            "-\t-\t".to_string()
+        } else {
+            format!("{}\t{}\t", file.added, file.deleted)
+        };
+        out.extend(numbers.as_bytes());
+        match &file.paths {
+            _ if !null_terminated => out.extend(format!("{}\n", file.name).as_bytes()),
+            (Some(old), new) if old != new => {
+                out.extend(format!("\0{}\0{}\0", old, new).as_bytes());
+            }
+            (_, path) => out.extend(format!("{}\0", path).as_bytes()),
+        }
+    }
+}
+
+/// Writes the `--stat` lines of `files`, fitting the names and histograms into the width of
+/// the output like git does.
+fn write_stat(files: &[FileStat], format: &DiffFormat, colors: &DiffColors, out: &mut Vec<u8>) {
+    if files.is_empty() {
+        return;
+    }
+    let (mut max_len, mut max_change) = (0, 0);
+    let (mut number_width, mut bin_width) = (0, 0);
+    for file in files {
+        max_len = max_len.max(file.name.len());
+        if file.unmerged {
+            bin_width = bin_width.max(8);
+        } else if file.binary {
+            bin_width = bin_width.max(14 + decimal_width(file.added) + decimal_width(file.deleted));
+            number_width = 3;
+        } else {
+            max_change = max_change.max(file.added + file.deleted);
+        }
+    }
+
+    let mut width = match format.stat_width {
+        Some(0) => 80,
+        Some(width) => width,
+        None => env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse::<usize>().ok())
+            .filter(|&columns| columns > 0)
+            .unwrap_or(80),
+    };
+    number_width = number_width.max(decimal_width(max_change));
+    width = width.max(16 + 6 + number_width);
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    let mut name_width = match format.stat_name_width {
+        Some(name_width) if name_width > 0 && name_width < max_len => name_width,
+        _ => max_len,
+    };
+    if name_width + number_width + 6 + graph_width > width {
+        let limit = (width * 3 / 8).saturating_sub(number_width + 6);
+        if graph_width > limit {
+            graph_width = limit.max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let (add_c, del_c, reset) = (&colors.new, &colors.old, &colors.reset);
+    for file in files {
+        // Names that are too long lose their beginning, up to a directory if possible.
+        let mut name = file.name.as_str();
+        let mut prefix = "";
+        let mut len = name_width;
+        if name_width < name.len() {
+            prefix = "...";
+            len = len.saturating_sub(3);
+            name = &name[name.len() - len..];
+            if let Some(slash) = name.find('/') {
+                name = &name[slash..];
+            }
+        }
+        let padding = len.saturating_sub(name.len());
+        let mut line = format!(" {}{}{} | ", prefix, name, " ".repeat(padding));
+
+        if file.binary || file.unmerged {
+            let word = if file.binary { "Bin" } else { "Unmerged" };
+            line.push_str(&format!("{:>1$}", word, number_width));
+            if file.binary && (file.added > 0 || file.deleted > 0) {
+                line.push_str(&format!(
+                    " {}{}{} -> {}{}{} bytes",
+                    del_c, file.deleted, reset, add_c, file.added, reset
+                ));
+            }
+            line.push('\n');
+            out.extend(line.as_bytes());
+            continue;
+        }
+
+        let (mut add, mut del) = (file.added, file.deleted);
+        if graph_width <= max_change {
+            let mut total = scale_linear(add + del, graph_width, max_change);
+            if total < 2 && add > 0 && del > 0 {
+                total = 2;
+            }
+            if add < del {
+                add = scale_linear(add, graph_width, max_change);
+                del = total - add;
+            } else {
+                del = scale_linear(del, graph_width, max_change);
+                add = total - del;
+            }
+        }
+        let total = file.added + file.deleted;
+        line.push_str(&format!("{:>1$}", total, number_width));
+        if total > 0 {
+            line.push(' ');
+        }
+        for (count, ch, color) in [(add, '+', add_c), (del, '-', del_c)] {
+            if count > 0 {
+                line.push_str(color);
+                line.push_str(&ch.to_string().repeat(count));
+                line.push_str(reset);
+            }
+        }
+        line.push('\n');
+        out.extend(line.as_bytes());
+    }
+    write_totals(files, out);
+}
+
+/// Writes the numbers of changed files, insertions and deletions.
+fn write_totals(files: &[FileStat], out: &mut Vec<u8>) {
+    if files.is_empty() {
+        return;
+    }
+    let (mut count, mut insertions, mut deletions) = (0, 0, 0);
+    for file in files.iter().filter(|file| !file.unmerged) {
+        count += 1;
+        if !file.binary {
+            insertions += file.added;
+            deletions += file.deleted;
+        }
+    }
+    if count == 0 {
+        out.extend(b" 0 files changed\n");
+        return;
+    }
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    let mut line = format!(" {}", plural(count, "file changed", "files changed"));
+    if insertions > 0 || deletions == 0 {
+        line.push_str(&format!(
+            ", {}",
+            plural(insertions, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deletions > 0 || insertions == 0 {
+        line.push_str(&format!(
+            ", {}",
+            plural(deletions, "deletion(-)", "deletions(-)")
+        ));
+    }
+    line.push('\n');
+    out.extend(line.as_bytes());
+}
+
+/// Scales `it` from at most `max_change` to at most `width`, keeping it at least 1 if it is
+/// not 0.
+fn scale_linear(it: usize, width: usize, max_change: usize) -> usize {
+    if it == 0 {
+        return 0;
+    }
+    1 + it * (width - 1) / max_change
+}
+
+fn decimal_width(n: usize) -> usize {
+    n.to_string().len()
+}
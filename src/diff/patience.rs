@@ -0,0 +1,179 @@
+//! The patience diff: the lines occurring exactly once on both sides are matched up along
+//! their longest common subsequence, and the gaps between them are diffed the same way.
+//! Where no such lines are left, the Myers algorithm takes over.
+
+use std::collections::HashMap;
+
+use super::myers;
+
+/// Marks the lines removed from `old` and added to `new`, given as their classes.
+pub(super) fn diff(
+    old: &[usize],
+    new: &[usize],
+    old_changed: &mut [bool],
+    new_changed: &mut [bool],
+) {
+    let mut patience = Patience {
+        old,
+        new,
+        old_changed,
+        new_changed,
+    };
+    patience.diff(0, old.len(), 0, new.len());
+}
+
+/// Where the line of a class in the old range occurs in the new range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Occurrence {
+    Missing,
+    Unique(usize),
+    /// The line is not unique on one of the sides.
+    Repeated,
+}
+
+struct Patience<'a> {
+    old: &'a [usize],
+    new: &'a [usize],
+    old_changed: &'a mut [bool],
+    new_changed: &'a mut [bool],
+}
+
+impl Patience<'_> {
+    /// Diffs `count1` old lines from `line1` with `count2` new lines from `line2`.
+    fn diff(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        if count1 == 0 || count2 == 0 {
+            self.mark(line1, count1, line2, count2);
+            return;
+        }
+
+        // The distinct lines of the old range, in order, with where they occur in the new.
+        let mut entries: Vec<(usize, Occurrence)> = Vec::new();
+        let mut positions: HashMap<usize, usize> = HashMap::new();
+        for i in line1..line1 + count1 {
+            match positions.get(&self.old[i]) {
+                Some(&entry) => entries[entry].1 = Occurrence::Repeated,
+                None => {
+                    positions.insert(self.old[i], entries.len());
+                    entries.push((i, Occurrence::Missing));
+                }
+            }
+        }
+        let mut has_matches = false;
+        for j in line2..line2 + count2 {
+            if let Some(&entry) = positions.get(&self.new[j]) {
+                has_matches = true;
+                let occurrence = &mut entries[entry].1;
+                *occurrence = match occurrence {
+                    Occurrence::Missing => Occurrence::Unique(j),
+                    _ => Occurrence::Repeated,
+                };
+            }
+        }
+        if !has_matches {
+            self.mark(line1, count1, line2, count2);
+            return;
+        }
+
+        let sequence = longest_common_sequence(&entries);
+        if sequence.is_empty() {
+            myers::diff(
+                &self.old[line1..line1 + count1],
+                &self.new[line2..line2 + count2],
+                false,
+                &mut self.old_changed[line1..line1 + count1],
+                &mut self.new_changed[line2..line2 + count2],
+            );
+        } else {
+            self.walk(&sequence, line1, count1, line2, count2);
+        }
+    }
+
+    /// Diffs the gaps between the matched lines of `sequence`, after growing the matches by
+    /// the equal lines around them.
+    fn walk(
+        &mut self,
+        sequence: &[(usize, usize)],
+        mut line1: usize,
+        count1: usize,
+        mut line2: usize,
+        count2: usize,
+    ) {
+        let (end1, end2) = (line1 + count1, line2 + count2);
+        let mut k = 0;
+        loop {
+            let (mut next1, mut next2) = match sequence.get(k) {
+                Some(&(next1, next2)) => (next1, next2),
+                None => (end1, end2),
+            };
+            if k < sequence.len() {
+                while next1 > line1 && next2 > line2 && self.old[next1 - 1] == self.new[next2 - 1] {
+                    next1 -= 1;
+                    next2 -= 1;
+                }
+            }
+            while line1 < next1 && line2 < next2 && self.old[line1] == self.new[line2] {
+                line1 += 1;
+                line2 += 1;
+            }
+            if next1 > line1 || next2 > line2 {
+                self.diff(line1, next1 - line1, line2, next2 - line2);
+            }
+            if k == sequence.len() {
+                return;
+            }
+
+            while k + 1 < sequence.len()
+                && sequence[k + 1] == (sequence[k].0 + 1, sequence[k].1 + 1)
+            {
+                k += 1;
+            }
+            line1 = sequence[k].0 + 1;
+            line2 = sequence[k].1 + 1;
+            k += 1;
+        }
+    }
+
+    fn mark(&mut self, line1: usize, count1: usize, line2: usize, count2: usize) {
+        for changed in &mut self.old_changed[line1..line1 + count1] {
+            *changed = true;
+        }
+        for changed in &mut self.new_changed[line2..line2 + count2] {
+            *changed = true;
+        }
+    }
+}
+
+/// Returns the longest sequence of lines unique on both sides that appear in the same order
+/// on both, as pairs of old and new positions.
+fn longest_common_sequence(entries: &[(usize, Occurrence)]) -> Vec<(usize, usize)> {
+    // The last entry of the best sequence found for each length, and the entry before each
+    // entry in its sequence.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; entries.len()];
+    let line2 = |entry: usize| match entries[entry].1 {
+        Occurrence::Unique(line2) => line2,
+        _ => unreachable!(),
+    };
+    for (entry, &(_, occurrence)) in entries.iter().enumerate() {
+        let position = match occurrence {
+            Occurrence::Unique(position) => position,
+            _ => continue,
+        };
+        let length = tails.partition_point(|&tail| line2(tail) < position);
+        previous[entry] = length.checked_sub(1).map(|i| tails[i]);
+        if length == tails.len() {
+            tails.push(entry);
+        } else {
+            tails[length] = entry;
+        }
+    }
+
+    let mut sequence = Vec::new();
+    let mut entry = tails.last().copied();
+    while let Some(i) = entry {
+        sequence.push((entries[i].0, line2(i)));
+        entry = previous[i];
+    }
+    sequence.reverse();
+    sequence
+}
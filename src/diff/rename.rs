@@ -0,0 +1,558 @@
+//! Finding renamed and copied files among the changes of a diff, like git's diffcore-rename:
+//! identical files are paired first, then similar files of the same name in another directory
+//! and finally the most similar files, comparing fingerprints of their contents.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use super::changes::{ChangeStatus, DiffFile, FileChange, GitDiff};
+use crate::{GitError, GitResult};
+
+/// Similarity scores range up to this, which identical files get.
+pub(super) const MAX_SCORE: u32 = 60000;
+/// Files must be at least half similar by default, like `-M50%`.
+const DEFAULT_SCORE: u32 = MAX_SCORE / 2;
+/// The number of best sources kept for each destination.
+const CANDIDATES: usize = 4;
+/// The number of identical sources considered for a destination.
+const MAX_IDENTICAL: usize = 100;
+/// Spans of contents are hashed modulo this prime.
+const HASH_BASE: u32 = 107927;
+
+/// How renamed and copied files are found, like `-M`, `-C` and `--find-copies-harder`.
+#[derive(Debug, Clone)]
+pub struct RenameOptions {
+    /// Also find copies of modified files, like `-C`.
+    pub copies: bool,
+    /// Also find copies of unmodified files, like `--find-copies-harder`. Implies `copies`.
+    pub find_copies_harder: bool,
+    /// How similar files must be to be paired, as returned by [RenameOptions::parse_score]:
+    /// identical files score 60000. Half of that by default.
+    pub min_score: u32,
+    /// Similar files are only searched for if there are at most this many sources times
+    /// this many destinations, like `diff.renameLimit`. 0 means no limit.
+    pub limit: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        Self {
+            copies: false,
+            find_copies_harder: false,
+            min_score: DEFAULT_SCORE,
+            limit: 1000,
+        }
+    }
+}
+
+impl RenameOptions {
+    /// Parses the similarity given to `-M<n>` or `-C<n>`: the digits of a fraction, so that
+    /// `5` is 50% and `05` is 5%, or a percentage ending in `%`. Empty or zero gives the
+    /// default of 50%.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::DiffError]: The similarity is not a number
+    pub fn parse_score(s: &str) -> GitResult<u32> {
+        let (mut num, mut scale, mut dot) = (0u64, 1u64, false);
+        let mut end = s.len();
+        for (i, ch) in s.char_indices() {
+            match ch {
+                '.' if !dot => {
+                    scale = 1;
+                    dot = true;
+                }
+                '%' => {
+                    scale = if dot { scale * 100 } else { 100 };
+                    end = i + 1;
+                    break;
+                }
+                '0'..='9' => {
+                    if scale < 100000 {
+                        scale *= 10;
+                        num = num * 10 + u64::from(ch as u8 - b'0');
+                    }
+                }
+                _ => {
+                    end = i;
+                    break;
+                }
+            }
+        }
+        if end != s.len() {
+            return Err(GitError::DiffError {
+                message: format!("invalid similarity: {}", s),
+            });
+        }
+        Ok(match num {
+            0 => DEFAULT_SCORE,
+            num if num >= scale => MAX_SCORE,
+            num => (u64::from(MAX_SCORE) * num / scale) as u32,
+        })
+    }
+}
+
+/// The sizes of the spans of some contents by their hashes, where a span ends after a newline
+/// or 64 bytes. Like in git, an unfinished span at the end is left out, and so are carriage
+/// returns before newlines in text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Fingerprint {
+    size: usize,
+    /// Sorted by hash.
+    spans: Vec<(u32, usize)>,
+}
+
+impl Fingerprint {
+    pub(super) fn new(data: &[u8], is_text: bool) -> Self {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        let hash =
+            |accum1: u32, accum2: u32| accum1.wrapping_add(accum2.wrapping_mul(0x61)) % HASH_BASE;
+        let (mut accum1, mut accum2, mut length) = (0u32, 0u32, 0);
+        for (i, &ch) in data.iter().enumerate() {
+            if is_text && ch == b'\r' && data.get(i + 1) == Some(&b'\n') {
+                continue;
+            }
+            let old = accum1;
+            accum1 = (accum1 << 7) ^ (accum2 >> 25);
+            accum2 = (accum2 << 7) ^ (old >> 25);
+            accum1 = accum1.wrapping_add(u32::from(ch));
+            length += 1;
+            if length < 64 && ch != b'\n' {
+                continue;
+            }
+            *counts.entry(hash(accum1, accum2)).or_default() += length;
+            accum1 = 0;
+            accum2 = 0;
+            length = 0;
+        }
+        let mut spans: Vec<(u32, usize)> = counts.into_iter().collect();
+        spans.sort_unstable();
+        Self {
+            size: data.len(),
+            spans,
+        }
+    }
+
+    /// Returns the number of bytes of `other` found in these contents.
+    pub(super) fn copied_to(&self, other: &Fingerprint) -> usize {
+        let mut copied = 0;
+        let mut theirs = other.spans.iter().peekable();
+        for &(hash, count) in &self.spans {
+            while theirs
+                .next_if(|&&(other_hash, _)| other_hash < hash)
+                .is_some()
+            {}
+            if let Some((_, other_count)) = theirs.next_if(|&&(other_hash, _)| other_hash == hash) {
+                copied += count.min(*other_count);
+            }
+        }
+        copied
+    }
+
+    /// Returns how similar a file with these contents is to a file with `other`, out of
+    /// [MAX_SCORE], or 0 if their sizes are too far apart to reach `min_score`.
+    pub(super) fn similarity(&self, other: &Fingerprint, min_score: u32) -> u32 {
+        let max_size = self.size.max(other.size) as u64;
+        let delta = max_size - self.size.min(other.size) as u64;
+        if max_size * u64::from(MAX_SCORE - min_score) < delta * u64::from(MAX_SCORE)
+            || other.size == 0
+        {
+            return 0;
+        }
+        (self.copied_to(other) as u64 * u64::from(MAX_SCORE) / max_size) as u32
+    }
+}
+
+/// A file the added files may have been renamed or copied from.
+struct Source {
+    file: DiffFile,
+    /// The number of added files paired with this one, plus one if the file stays.
+    used: usize,
+    unchanged: bool,
+    fingerprint: Option<Fingerprint>,
+}
+
+/// An added file, and the source and score it is paired with.
+struct Destination {
+    change: usize,
+    fingerprint: Option<Fingerprint>,
+    pair: Option<(usize, u32)>,
+}
+
+/// A possible pairing of a destination with a source.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    score: u32,
+    same_name: bool,
+    dst: usize,
+    src: usize,
+}
+
+impl Candidate {
+    /// Returns true if `self` is a worse pairing than `other`.
+    fn is_worse(&self, other: &Candidate) -> bool {
+        (self.score, self.same_name) < (other.score, other.same_name)
+    }
+}
+
+impl GitDiff {
+    /// Sets how renamed and copied files are found, or [None] to show them as added and
+    /// deleted, which is the default.
+    pub fn set_renames(&mut self, renames: Option<RenameOptions>) {
+        self.renames = renames;
+    }
+
+    /// Returns true if copies of unchanged files are searched for, which need to be passed to
+    /// [GitDiff::find_renames].
+    pub(super) fn wants_unchanged(&self) -> bool {
+        self.renames
+            .as_ref()
+            .is_some_and(|renames| renames.find_copies_harder)
+    }
+
+    /// Replaces the added files among `changes` that are renamed or copied from other files
+    /// by renames or copies, as set with [GitDiff::set_renames]. The deleted files renamed
+    /// away are left out.
+    ///
+    /// `unchanged` are the files that are the same on both sides, sorted by path.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading the contents of files.
+    pub(super) fn find_renames(
+        &mut self,
+        changes: Vec<FileChange>,
+        unchanged: Vec<DiffFile>,
+    ) -> GitResult<Vec<FileChange>> {
+        let options = match &self.renames {
+            Some(options) => options.clone(),
+            None => return Ok(changes),
+        };
+        let copies = options.copies || options.find_copies_harder;
+
+        let mut sources: Vec<Source> = Vec::new();
+        let mut destinations: Vec<Destination> = Vec::new();
+        for (i, change) in changes.iter().enumerate() {
+            let stays = match change.status() {
+                ChangeStatus::Added => {
+                    destinations.push(Destination {
+                        change: i,
+                        fingerprint: None,
+                        pair: None,
+                    });
+                    continue;
+                }
+                ChangeStatus::Deleted => false,
+                ChangeStatus::Modified | ChangeStatus::TypeChanged if copies => true,
+                _ => continue,
+            };
+            sources.push(Source {
+                file: change.old_file().unwrap().clone(),
+                used: usize::from(stays),
+                unchanged: false,
+                fingerprint: None,
+            });
+        }
+        if options.find_copies_harder {
+            sources.extend(unchanged.into_iter().map(|file| Source {
+                file,
+                used: 1,
+                unchanged: true,
+                fingerprint: None,
+            }));
+            sources.sort_by(|a, b| a.file.path().cmp(b.file.path()));
+        }
+        if destinations.is_empty() || sources.is_empty() {
+            return Ok(changes);
+        }
+
+        self.find_identical(&mut sources, &mut destinations, &changes, copies)?;
+        if options.min_score < MAX_SCORE {
+            if !copies {
+                let min_score = options.min_score + (MAX_SCORE - options.min_score) / 2;
+                self.find_same_names(&mut sources, &mut destinations, &changes, min_score)?;
+            }
+            let warnings =
+                self.find_similar(&mut sources, &mut destinations, &changes, &options)?;
+            self.warnings.extend(warnings);
+        }
+
+        // The pairs take the place of the added files, and the deleted files renamed away
+        // go. The last pairing of a deleted file is a rename, all others are copies.
+        let gone: HashSet<String> = sources
+            .iter()
+            .filter(|source| source.used > 0)
+            .map(|source| source.file.path().to_string())
+            .collect();
+        let pairs: HashMap<usize, (usize, u32)> = destinations
+            .iter()
+            .filter_map(|dst| dst.pair.map(|pair| (dst.change, pair)))
+            .collect();
+        let mut result = Vec::new();
+        for (i, change) in changes.into_iter().enumerate() {
+            if let Some(&(src, score)) = pairs.get(&i) {
+                let source = &mut sources[src];
+                source.used -= 1;
+                let status = if source.used > 0 {
+                    ChangeStatus::Copied
+                } else {
+                    ChangeStatus::Renamed
+                };
+                let new = change.new_file().unwrap().clone();
+                result.push(FileChange::paired(status, source.file.clone(), new, score));
+            } else if change.status() != ChangeStatus::Deleted || !gone.contains(change.path()) {
+                result.push(change);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Pairs the destinations with identical sources, preferring sources that are not used
+    /// yet and have the same name.
+    fn find_identical(
+        &mut self,
+        sources: &mut [Source],
+        destinations: &mut [Destination],
+        changes: &[FileChange],
+        copies: bool,
+    ) -> GitResult<()> {
+        let mut by_sha: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, source) in sources.iter().enumerate() {
+            by_sha
+                .entry(self.object_name(Some(&source.file))?)
+                .or_default()
+                .push(i);
+        }
+        for dst in destinations.iter_mut() {
+            let target = changes[dst.change].new_file().unwrap();
+            let candidates = match by_sha.get(&self.object_name(Some(target))?) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+            let mut best: Option<(usize, usize)> = None;
+            let mut tries = MAX_IDENTICAL;
+            for &src in candidates {
+                let source = &sources[src].file;
+                // Anything but regular files needs the same mode.
+                if (!source.mode().is_file() || !target.mode().is_file())
+                    && source.mode() != target.mode()
+                {
+                    continue;
+                }
+                if sources[src].used > 0 && !copies {
+                    continue;
+                }
+                let score = usize::from(sources[src].used == 0)
+                    + usize::from(base_name(source.path()) == base_name(target.path()));
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some((src, score));
+                    if score == 2 {
+                        break;
+                    }
+                }
+                tries -= 1;
+                if tries == 0 {
+                    break;
+                }
+            }
+            if let Some((src, _)) = best {
+                sources[src].used += 1;
+                dst.pair = Some((src, MAX_SCORE));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pairs the destinations with the similar sources of the same base name, where both the
+    /// name is unique among the sources and destinations left.
+    fn find_same_names(
+        &mut self,
+        sources: &mut [Source],
+        destinations: &mut [Destination],
+        changes: &[FileChange],
+        min_score: u32,
+    ) -> GitResult<()> {
+        // The index of the file with each name, or None if several have it.
+        let mut source_names: HashMap<&str, Option<usize>> = HashMap::new();
+        for (i, source) in sources.iter().enumerate() {
+            // Sources renamed already are out of the running.
+            if source.used > 0 {
+                continue;
+            }
+            source_names
+                .entry(base_name(source.file.path()))
+                .and_modify(|src| *src = None)
+                .or_insert(Some(i));
+        }
+        let mut destination_names: HashMap<&str, Option<usize>> = HashMap::new();
+        for (i, dst) in destinations.iter().enumerate() {
+            if dst.pair.is_none() {
+                destination_names
+                    .entry(base_name(changes[dst.change].path()))
+                    .and_modify(|dst| *dst = None)
+                    .or_insert(Some(i));
+            }
+        }
+
+        let mut pairs = Vec::new();
+        for (name, &src) in &source_names {
+            if let (Some(src), Some(Some(dst))) = (src, destination_names.get(name)) {
+                pairs.push((src, *dst));
+            }
+        }
+        pairs.sort_unstable();
+        for (src, dst) in pairs {
+            let score = self.similarity(sources, destinations, changes, src, dst, min_score)?;
+            if score >= min_score {
+                sources[src].used += 1;
+                destinations[dst].pair = Some((src, score));
+            }
+        }
+        Ok(())
+    }
+
+    /// Pairs the destinations left with their most similar sources, first with sources not
+    /// used yet and then, for copies, with any source.
+    ///
+    /// Returns the warnings about exceeding `diff.renameLimit`.
+    fn find_similar(
+        &mut self,
+        sources: &mut [Source],
+        destinations: &mut [Destination],
+        changes: &[FileChange],
+        options: &RenameOptions,
+    ) -> GitResult<Vec<String>> {
+        let copies = options.copies || options.find_copies_harder;
+        // Without copies, sources renamed already are out of the running.
+        let candidates: Vec<usize> = (0..sources.len())
+            .filter(|&src| copies || sources[src].used == 0)
+            .collect();
+        let left = destinations.iter().filter(|dst| dst.pair.is_none()).count();
+        if left == 0 || candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut warnings = Vec::new();
+        let mut skip_unchanged = false;
+        let limit = options.limit * options.limit;
+        if options.limit > 0 && left * candidates.len() > limit {
+            let needed = left.max(candidates.len());
+            let changed = candidates
+                .iter()
+                .filter(|&&src| !sources[src].unchanged)
+                .count();
+            if options.find_copies_harder && left * changed <= limit {
+                warnings.push(
+                    "warning: only found copies from modified paths due to too many files."
+                        .to_string(),
+                );
+                skip_unchanged = true;
+            } else {
+                warnings.push(
+                    "warning: exhaustive rename detection was skipped due to too many files."
+                        .to_string(),
+                );
+            }
+            warnings.push(format!(
+                "warning: you may want to set your diff.renameLimit variable to at least {} and retry the command.",
+                needed
+            ));
+            if !skip_unchanged {
+                return Ok(warnings);
+            }
+        }
+
+        let mut matrix = Vec::new();
+        for dst in 0..destinations.len() {
+            if destinations[dst].pair.is_some() {
+                continue;
+            }
+            let mut best: Vec<Candidate> = Vec::with_capacity(CANDIDATES);
+            for &src in &candidates {
+                if skip_unchanged && sources[src].unchanged {
+                    continue;
+                }
+                let score =
+                    self.similarity(sources, destinations, changes, src, dst, options.min_score)?;
+                let target = changes[destinations[dst].change].path();
+                let candidate = Candidate {
+                    score,
+                    same_name: base_name(sources[src].file.path()) == base_name(target),
+                    dst,
+                    src,
+                };
+                if best.len() < CANDIDATES {
+                    best.push(candidate);
+                    continue;
+                }
+                // Replace the first of the worst candidates if this one is better.
+                let mut worst = 0;
+                for i in 1..CANDIDATES {
+                    if best[i].is_worse(&best[worst]) {
+                        worst = i;
+                    }
+                }
+                if best[worst].is_worse(&candidate) {
+                    best[worst] = candidate;
+                }
+            }
+            destinations[dst].fingerprint = None;
+            matrix.extend(best);
+        }
+        matrix.sort_by_key(|candidate| Reverse((candidate.score, candidate.same_name)));
+
+        let passes: &[bool] = if copies { &[false, true] } else { &[false] };
+        for &copies in passes {
+            for candidate in &matrix {
+                if candidate.score < options.min_score {
+                    break;
+                }
+                let dst = &mut destinations[candidate.dst];
+                if dst.pair.is_some() || (!copies && sources[candidate.src].used > 0) {
+                    continue;
+                }
+                dst.pair = Some((candidate.src, candidate.score));
+                sources[candidate.src].used += 1;
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Returns how similar the contents of source `src` and destination `dst` are, out of
+    /// [MAX_SCORE]. Only regular files are compared.
+    fn similarity(
+        &mut self,
+        sources: &mut [Source],
+        destinations: &mut [Destination],
+        changes: &[FileChange],
+        src: usize,
+        dst: usize,
+        min_score: u32,
+    ) -> GitResult<u32> {
+        let target = changes[destinations[dst].change].new_file().unwrap();
+        if !sources[src].file.mode().is_file() || !target.mode().is_file() {
+            return Ok(0);
+        }
+        if sources[src].fingerprint.is_none() {
+            sources[src].fingerprint = Some(self.fingerprint(&sources[src].file)?);
+        }
+        if destinations[dst].fingerprint.is_none() {
+            destinations[dst].fingerprint = Some(self.fingerprint(target)?);
+        }
+        let (source, target) = (&sources[src].fingerprint, &destinations[dst].fingerprint);
+        Ok(source
+            .as_ref()
+            .unwrap()
+            .similarity(target.as_ref().unwrap(), min_score))
+    }
+
+    fn fingerprint(&mut self, file: &DiffFile) -> GitResult<Fingerprint> {
+        let data = self.read(file)?;
+        let is_text = !self.is_binary(file, &data)?;
+        Ok(Fingerprint::new(&data, is_text))
+    }
+}
+
+/// Returns the part of `path` after its last slash.
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap()
+}
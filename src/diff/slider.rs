@@ -0,0 +1,333 @@
+//! Sliding groups of changed lines, like xdiff's `xdl_change_compact()`. A group whose first
+//! line equals the line after it can move down, one whose last line equals the line before it
+//! can move up. Groups are lined up with changes on the other side where possible, and
+//! otherwise placed where the indentation of the lines around them reads best.
+
+use super::{is_space, Side};
+
+/// Indentation beyond this many columns is not measured.
+const MAX_INDENT: i32 = 200;
+/// Blank lines beyond this many in a row are not counted.
+const MAX_BLANKS: i32 = 20;
+/// The furthest a group is slid for the indent heuristic.
+const INDENT_HEURISTIC_MAX_SLIDING: isize = 100;
+
+// The weights of the indent heuristic, determined empirically by git on a corpus of diffs.
+// Smaller penalties are better.
+const START_OF_FILE_PENALTY: i32 = 1;
+const END_OF_FILE_PENALTY: i32 = 21;
+const TOTAL_BLANK_WEIGHT: i32 = -30;
+const POST_BLANK_WEIGHT: i32 = 6;
+const RELATIVE_INDENT_PENALTY: i32 = -4;
+const RELATIVE_INDENT_WITH_BLANK_PENALTY: i32 = 10;
+const RELATIVE_OUTDENT_PENALTY: i32 = 24;
+const RELATIVE_OUTDENT_WITH_BLANK_PENALTY: i32 = 17;
+const RELATIVE_DEDENT_PENALTY: i32 = 23;
+const RELATIVE_DEDENT_WITH_BLANK_PENALTY: i32 = 17;
+const INDENT_WEIGHT: i32 = 60;
+
+/// Slides the groups of changed lines of `side` as far as they go, merging the groups they
+/// bump into, then back to line up with a change in `other`, or to the best place for the
+/// indent heuristic.
+pub(super) fn compact(side: &mut Side, other: &Side, indent_heuristic: bool) {
+    let mut g = Group::first(side);
+    let mut go = Group::first(other);
+    loop {
+        if g.end != g.start {
+            let mut earliest_end;
+            let mut end_matching_other;
+            loop {
+                let size = g.end - g.start;
+                end_matching_other = -1;
+
+                while g.slide_up(side) {
+                    assert!(go.previous(other), "group sync broken sliding up");
+                }
+                earliest_end = g.end;
+                if go.end > go.start {
+                    end_matching_other = g.end;
+                }
+
+                while g.slide_down(side) {
+                    assert!(go.next(other), "group sync broken sliding down");
+                    if go.end > go.start {
+                        end_matching_other = g.end;
+                    }
+                }
+                if size == g.end - g.start {
+                    break;
+                }
+            }
+
+            // The group is now as far down as it goes, so it can only move back up.
+            if g.end == earliest_end {
+                // It can not move at all.
+            } else if end_matching_other != -1 {
+                while go.end == go.start {
+                    assert!(g.slide_up(side), "match disappeared");
+                    assert!(go.previous(other), "group sync broken sliding to match");
+                }
+            } else if indent_heuristic {
+                let size = g.end - g.start;
+                let mut shift = earliest_end
+                    .max(g.end - size - 1)
+                    .max(g.end - INDENT_HEURISTIC_MAX_SLIDING);
+                let mut best: Option<(isize, Score)> = None;
+                while shift <= g.end {
+                    let mut score = Score::default();
+                    score.add_split(&Split::measure(side, shift));
+                    score.add_split(&Split::measure(side, shift - size));
+                    if best.is_none_or(|(_, best)| score.compare(&best) <= 0) {
+                        best = Some((shift, score));
+                    }
+                    shift += 1;
+                }
+                let best_shift = best.map_or(g.end, |(shift, _)| shift);
+                while g.end > best_shift {
+                    assert!(g.slide_up(side), "best shift unreached");
+                    assert!(
+                        go.previous(other),
+                        "group sync broken sliding to blank line"
+                    );
+                }
+            }
+        }
+
+        if !g.next(side) {
+            break;
+        }
+        assert!(go.next(other), "group sync broken moving to next group");
+    }
+}
+
+/// A group of changed lines, possibly empty. Every file has a group at its start, at its end
+/// and between any two unchanged lines.
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    /// The first changed line, or for an empty group the unchanged line it is above.
+    start: isize,
+    /// The first unchanged line after the group.
+    end: isize,
+}
+
+impl Group {
+    fn first(side: &Side) -> Self {
+        let mut end = 0;
+        while side.is_changed(end) {
+            end += 1;
+        }
+        Self { start: 0, end }
+    }
+
+    /// Moves to the next group, returning false at the end of the file.
+    fn next(&mut self, side: &Side) -> bool {
+        if self.end == side.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        while side.is_changed(self.end) {
+            self.end += 1;
+        }
+        true
+    }
+
+    /// Moves to the previous group, returning false at the start of the file.
+    fn previous(&mut self, side: &Side) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        while side.is_changed(self.start - 1) {
+            self.start -= 1;
+        }
+        true
+    }
+
+    /// Slides the group down by a line if possible, merging it with the group it reaches.
+    fn slide_down(&mut self, side: &mut Side) -> bool {
+        if self.end < side.len()
+            && side.classes[self.start as usize] == side.classes[self.end as usize]
+        {
+            side.changed[self.start as usize] = false;
+            side.changed[self.end as usize] = true;
+            self.start += 1;
+            self.end += 1;
+            while side.is_changed(self.end) {
+                self.end += 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Slides the group up by a line if possible, merging it with the group it reaches.
+    fn slide_up(&mut self, side: &mut Side) -> bool {
+        if self.start > 0
+            && side.classes[self.start as usize - 1] == side.classes[self.end as usize - 1]
+        {
+            self.start -= 1;
+            self.end -= 1;
+            side.changed[self.start as usize] = true;
+            side.changed[self.end as usize] = false;
+            while side.is_changed(self.start - 1) {
+                self.start -= 1;
+            }
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Returns the indentation of `line` with tabs counting up to the next multiple of 8, or -1
+/// if it is blank.
+fn indent(line: &[u8]) -> i32 {
+    let mut indent = 0;
+    for &c in line {
+        if !is_space(c) {
+            return indent;
+        } else if c == b' ' {
+            indent += 1;
+        } else if c == b'\t' {
+            indent += 8 - indent % 8;
+        }
+        if indent >= MAX_INDENT {
+            return MAX_INDENT;
+        }
+    }
+    -1
+}
+
+/// What the lines around a possible split between changed and unchanged lines look like.
+struct Split {
+    end_of_file: bool,
+    /// The indentation of the line after the split, -1 if it is blank.
+    indent: i32,
+    /// The number of blank lines right above the split.
+    pre_blank: i32,
+    /// The indentation of the closest non-blank line above the split, -1 if there is none.
+    pre_indent: i32,
+    /// The number of blank lines after the line after the split.
+    post_blank: i32,
+    /// The indentation of the closest non-blank line after the line after the split, -1 if
+    /// there is none.
+    post_indent: i32,
+}
+
+impl Split {
+    /// Measures the split above line `split`.
+    fn measure(side: &Side, split: isize) -> Self {
+        let line = |i: isize| side.lines[i as usize];
+        let (end_of_file, line_indent) = if split >= side.len() {
+            (true, -1)
+        } else {
+            (false, indent(line(split)))
+        };
+
+        let (mut pre_blank, mut pre_indent) = (0, -1);
+        let mut i = split - 1;
+        while i >= 0 {
+            pre_indent = indent(line(i));
+            if pre_indent != -1 {
+                break;
+            }
+            pre_blank += 1;
+            if pre_blank == MAX_BLANKS {
+                pre_indent = 0;
+                break;
+            }
+            i -= 1;
+        }
+
+        let (mut post_blank, mut post_indent) = (0, -1);
+        let mut i = split + 1;
+        while i < side.len() {
+            post_indent = indent(line(i));
+            if post_indent != -1 {
+                break;
+            }
+            post_blank += 1;
+            if post_blank == MAX_BLANKS {
+                post_indent = 0;
+                break;
+            }
+            i += 1;
+        }
+
+        Self {
+            end_of_file,
+            indent: line_indent,
+            pre_blank,
+            pre_indent,
+            post_blank,
+            post_indent,
+        }
+    }
+}
+
+/// How bad the splits around a position of a group are.
+#[derive(Debug, Default, Clone, Copy)]
+struct Score {
+    effective_indent: i32,
+    penalty: i32,
+}
+
+impl Score {
+    fn add_split(&mut self, m: &Split) {
+        if m.pre_indent == -1 && m.pre_blank == 0 {
+            self.penalty += START_OF_FILE_PENALTY;
+        }
+        if m.end_of_file {
+            self.penalty += END_OF_FILE_PENALTY;
+        }
+
+        // The blank lines after the split, including the line right after it.
+        let post_blank = if m.indent == -1 { 1 + m.post_blank } else { 0 };
+        let total_blank = m.pre_blank + post_blank;
+        self.penalty += TOTAL_BLANK_WEIGHT * total_blank;
+        self.penalty += POST_BLANK_WEIGHT * post_blank;
+
+        let indent = if m.indent != -1 {
+            m.indent
+        } else {
+            m.post_indent
+        };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+
+        if indent == -1 || m.pre_indent == -1 || indent == m.pre_indent {
+            // No adjustment.
+        } else if indent > m.pre_indent {
+            self.penalty += if any_blanks {
+                RELATIVE_INDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_INDENT_PENALTY
+            };
+        } else if m.post_indent != -1 && m.post_indent > indent {
+            // Indented less than the line before but more than the line after: probably
+            // the start of a block.
+            self.penalty += if any_blanks {
+                RELATIVE_OUTDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_OUTDENT_PENALTY
+            };
+        } else {
+            // Probably the end of a block.
+            self.penalty += if any_blanks {
+                RELATIVE_DEDENT_WITH_BLANK_PENALTY
+            } else {
+                RELATIVE_DEDENT_PENALTY
+            };
+        }
+    }
+
+    /// Compares two scores, negative if this one is better.
+    fn compare(&self, other: &Score) -> i32 {
+        let indents = (self.effective_indent > other.effective_indent) as i32
+            - (self.effective_indent < other.effective_indent) as i32;
+        INDENT_WEIGHT * indents + (self.penalty - other.penalty)
+    }
+}
@@ -0,0 +1,755 @@
+use super::*;
+use crate::diff::rename;
+use crate::objects;
+use crate::refs::NULL_SHA;
+use crate::test_utils::{self, tree};
+use crate::{GitCheckout, GitFileMode, GitOdb, ObjectType, Pathspec};
+use std::error::Error;
+use std::fs;
+
+/// Returns the hunks of the diff of `old` and `new` as in a patch.
+fn unified(old: &str, new: &str, options: &DiffOptions) -> String {
+    let mut patch = Vec::new();
+    for hunk in diff_lines(old.as_bytes(), new.as_bytes(), options) {
+        patch.extend(hunk.header());
+        patch.push(b'\n');
+        for line in hunk.lines {
+            patch.push(match line.kind {
+                LineKind::Context => b' ',
+                LineKind::Removed => b'-',
+                LineKind::Added => b'+',
+            });
+            patch.extend(&line.text);
+            if !line.has_newline() {
+                patch.extend(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    String::from_utf8(patch).unwrap()
+}
+
+#[test]
+fn test_diff_lines() {
+    let old: String = (1..=12)
+        .map(|i| format!("    let a{} = {};\n", i, i))
+        .collect();
+    let old = format!("fn one() {{\n{}}}\n", old);
+    let new = old
+        .replace("a2 = 2", "a2 = 20")
+        .replace("a10 = 10", "a10 = 100");
+    let new = new.trim_end();
+    let mut options = DiffOptions::default();
+    assert_eq!(unified(&old, &old, &options), "");
+    assert_eq!(
+        unified(&old, new, &options),
+        "@@ -1,6 +1,6 @@\n \
+         fn one() {\n     \
+         let a1 = 1;\n\
+         -    let a2 = 2;\n\
+         +    let a2 = 20;\n     \
+         let a3 = 3;\n     \
+         let a4 = 4;\n     \
+         let a5 = 5;\n\
+         @@ -8,7 +8,7 @@ fn one() {\n     \
+         let a7 = 7;\n     \
+         let a8 = 8;\n     \
+         let a9 = 9;\n\
+         -    let a10 = 10;\n\
+         +    let a10 = 100;\n     \
+         let a11 = 11;\n     \
+         let a12 = 12;\n\
+         -}\n\
+         +}\n\
+         \\ No newline at end of file\n"
+    );
+
+    options.inter_hunk_context = 1;
+    let hunks = diff_lines(old.as_bytes(), new.as_bytes(), &options);
+    assert_eq!(hunks.len(), 1);
+    assert_eq!(hunks[0].header(), b"@@ -1,14 +1,14 @@");
+
+    options.inter_hunk_context = 0;
+    options.context = 1;
+    let hunks = diff_lines(old.as_bytes(), new.as_bytes(), &options);
+    let headers: Vec<Vec<u8>> = hunks.iter().map(Hunk::header).collect();
+    assert_eq!(
+        headers,
+        [
+            &b"@@ -2,3 +2,3 @@ fn one() {"[..],
+            b"@@ -10,5 +10,5 @@ fn one() {"
+        ]
+    );
+
+    options.context = 0;
+    let hunks = diff_lines(b"a\nb\n", b"a\nc\nb\n", &options);
+    assert_eq!(hunks[0].header(), b"@@ -1,0 +2 @@ a");
+    let hunks = diff_lines(b"a\n", b"", &options);
+    assert_eq!(hunks[0].header(), b"@@ -1 +0,0 @@");
+}
+
+#[test]
+fn test_diff_algorithms() {
+    let (old, new) = ("d\ng\ne\nf\n", "b\nf\nf\ng\n");
+    let diff = |algorithm: &str| {
+        let options = DiffOptions {
+            algorithm: algorithm.parse().unwrap(),
+            ..DiffOptions::default()
+        };
+        unified(old, new, &options)
+    };
+    assert_eq!(
+        diff("myers"),
+        "@@ -1,4 +1,4 @@\n-d\n-g\n-e\n+b\n+f\n f\n+g\n"
+    );
+    assert_eq!(diff("default"), diff("myers"));
+    assert_eq!(diff("Minimal"), diff("myers"));
+    assert_eq!(
+        diff("patience"),
+        "@@ -1,4 +1,4 @@\n-d\n+b\n+f\n+f\n g\n-e\n-f\n"
+    );
+    assert_eq!(
+        diff("histogram"),
+        "@@ -1,4 +1,4 @@\n-d\n-g\n-e\n+b\n f\n+f\n+g\n"
+    );
+    assert!("fastest".parse::<DiffAlgorithm>().is_err());
+
+    // Past a certain cost, Myers settles for a larger diff than the minimal one.
+    let old: String = (0..1000).map(|i| format!("{}\n", i * i % 211)).collect();
+    let new: String = (0..1000)
+        .map(|i| format!("{}\n", i * i * i % 223))
+        .collect();
+    let changed_lines = |algorithm| {
+        let options = DiffOptions {
+            algorithm,
+            ..DiffOptions::default()
+        };
+        diff_lines(old.as_bytes(), new.as_bytes(), &options)
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|line| line.kind != LineKind::Context)
+            .count()
+    };
+    assert_eq!(changed_lines(DiffAlgorithm::Myers), 1772);
+    assert_eq!(changed_lines(DiffAlgorithm::Minimal), 1770);
+}
+
+#[test]
+fn test_diff_whitespace() {
+    let old = "if a {\n    x( 1 );\n    y();  \n}\nz();\r\n";
+    let new = "if a {\n\n    x(1);\n    y();\n}\n\nz();\n";
+    let diff = |set: fn(&mut DiffOptions)| {
+        let mut options = DiffOptions::default();
+        set(&mut options);
+        unified(old, new, &options)
+    };
+    assert_eq!(
+        diff(|_| {}),
+        "@@ -1,5 +1,7 @@\n if a {\n-    x( 1 );\n-    y();  \n+\n+    x(1);\n+    y();\n }\n\
+         -z();\r\n+\n+z();\n"
+    );
+    assert_eq!(
+        diff(|options| options.ignore_all_space = true),
+        "@@ -1,5 +1,7 @@\n if a {\n+\n     x(1);\n     y();\n }\n+\n z();\n"
+    );
+    let expected =
+        "@@ -1,5 +1,7 @@\n if a {\n-    x( 1 );\n+\n+    x(1);\n     y();\n }\n+\n z();\n";
+    assert_eq!(diff(|options| options.ignore_space_change = true), expected);
+    assert_eq!(diff(|options| options.ignore_space_at_eol = true), expected);
+    assert_eq!(
+        diff(|options| options.ignore_cr_at_eol = true),
+        "@@ -1,5 +1,7 @@\n if a {\n-    x( 1 );\n-    y();  \n+\n+    x(1);\n+    y();\n }\n+\n z();\n"
+    );
+    assert_eq!(
+        diff(|options| {
+            options.ignore_blank_lines = true;
+            options.ignore_space_change = true;
+        }),
+        expected
+    );
+    assert_eq!(
+        diff(|options| {
+            options.ignore_blank_lines = true;
+            options.ignore_all_space = true;
+        }),
+        ""
+    );
+}
+
+#[test]
+fn test_indent_heuristic() {
+    let (old, new) = ("}\n    }\n}\n", "}\n}\n}\n    }\n}\n");
+    let mut options = DiffOptions::default();
+    assert_eq!(
+        unified(old, new, &options),
+        "@@ -1,3 +1,5 @@\n+}\n+}\n }\n     }\n }\n"
+    );
+    options.indent_heuristic = false;
+    assert_eq!(
+        unified(old, new, &options),
+        "@@ -1,3 +1,5 @@\n }\n+}\n+}\n     }\n }\n"
+    );
+}
+
+/// Returns two trees changing a file's contents, a file's mode and a file's type, and
+/// deleting and adding a file.
+fn two_trees(odb: &GitOdb) -> GitResult<(String, String)> {
+    let one = tree(
+        odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "a\nb\nc\n"),
+            ("dir/run.sh", GitFileMode::regular(), "#!/bin/sh\n"),
+            ("gone.txt", GitFileMode::regular(), "gone\n"),
+            ("link", GitFileMode::symlink(), "a.txt"),
+        ],
+    )?;
+    let two = tree(
+        odb,
+        &[
+            ("a.txt", GitFileMode::regular(), "a\nB\nc\n"),
+            ("dir/run.sh", GitFileMode::executable(), "#!/bin/sh\n"),
+            ("link", GitFileMode::regular(), "text\n"),
+            ("new.txt", GitFileMode::regular(), "new\n"),
+        ],
+    )?;
+    Ok((one, two))
+}
+
+fn output(diff: &mut GitDiff, changes: &[FileChange], format: &DiffFormat) -> String {
+    let mut out = Vec::new();
+    diff.write(changes, &DiffOptions::default(), format, &mut out)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_diff_trees() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_diff_trees")?;
+    let mut diff = GitDiff::new(&repo)?;
+    let (one, two) = two_trees(diff.odb())?;
+    let changes = diff.diff_trees(Some(&one), Some(&two), &Pathspec::new::<&str>(&[]))?;
+    let statuses: Vec<(ChangeStatus, &str)> = changes
+        .iter()
+        .map(|change| (change.status(), change.path()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            (ChangeStatus::Modified, "a.txt"),
+            (ChangeStatus::Modified, "dir/run.sh"),
+            (ChangeStatus::Deleted, "gone.txt"),
+            (ChangeStatus::TypeChanged, "link"),
+            (ChangeStatus::Added, "new.txt"),
+        ]
+    );
+    assert!(diff
+        .diff_trees(Some(&one), Some(&one), &Pathspec::new::<&str>(&[]))?
+        .is_empty());
+    let in_dir = diff.diff_trees(Some(&one), Some(&two), &Pathspec::new(&["dir"]))?;
+    assert_eq!(in_dir.len(), 1);
+    assert_eq!(in_dir[0].path(), "dir/run.sh");
+
+    let patch = DiffFormat {
+        patch: true,
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &patch),
+        "diff --git a/a.txt b/a.txt\n\
+         index de98044..7be73ce 100644\n\
+         --- a/a.txt\n\
+         +++ b/a.txt\n\
+         @@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n\
+         diff --git a/dir/run.sh b/dir/run.sh\n\
+         old mode 100644\n\
+         new mode 100755\n\
+         diff --git a/gone.txt b/gone.txt\n\
+         deleted file mode 100644\n\
+         index 286c5f5..0000000\n\
+         --- a/gone.txt\n\
+         +++ /dev/null\n\
+         @@ -1 +0,0 @@\n-gone\n\
+         diff --git a/link b/link\n\
+         deleted file mode 120000\n\
+         index 8d14cbf..0000000\n\
+         --- a/link\n\
+         +++ /dev/null\n\
+         @@ -1 +0,0 @@\n-a.txt\n\\ No newline at end of file\n\
+         diff --git a/link b/link\n\
+         new file mode 100644\n\
+         index 0000000..8e27be7\n\
+         --- /dev/null\n\
+         +++ b/link\n\
+         @@ -0,0 +1 @@\n+text\n\
+         diff --git a/new.txt b/new.txt\n\
+         new file mode 100644\n\
+         index 0000000..3e75765\n\
+         --- /dev/null\n\
+         +++ b/new.txt\n\
+         @@ -0,0 +1 @@\n+new\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diff_stats() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_diff_stats")?;
+    let mut diff = GitDiff::new(&repo)?;
+    let (one, two) = two_trees(diff.odb())?;
+    let changes = diff.diff_trees(Some(&one), Some(&two), &Pathspec::new::<&str>(&[]))?;
+
+    let stats = DiffFormat {
+        stat: true,
+        numstat: true,
+        shortstat: true,
+        stat_width: Some(40),
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &stats),
+        "1\t1\ta.txt\n0\t0\tdir/run.sh\n0\t1\tgone.txt\n1\t1\tlink\n1\t0\tnew.txt\n \
+         a.txt      | 2 +-\n \
+         dir/run.sh | 0\n \
+         gone.txt   | 1 -\n \
+         link       | 2 +-\n \
+         new.txt    | 1 +\n \
+         5 files changed, 3 insertions(+), 3 deletions(-)\n \
+         5 files changed, 3 insertions(+), 3 deletions(-)\n"
+    );
+
+    let names = DiffFormat {
+        name_status: true,
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "M\ta.txt\nM\tdir/run.sh\nD\tgone.txt\nT\tlink\nA\tnew.txt\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diff_worktree() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_diff_worktree")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    let (one, _) = two_trees(&GitOdb::open(&repo)?)?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(None, &one)?;
+    checkout.write_index()?;
+
+    let everything = Pathspec::new::<&str>(&[]);
+    let mut diff = GitDiff::new(&repo)?;
+    assert!(diff.diff_files(&everything)?.is_empty());
+    assert!(diff.diff_index(Some(&one), true, &everything)?.is_empty());
+
+    fs::write(worktree.join("a.txt"), "a\nb\n")?;
+    fs::remove_file(worktree.join("gone.txt"))?;
+    let mut diff = GitDiff::new(&repo)?;
+    let changes = diff.diff_files(&everything)?;
+    let statuses: Vec<(ChangeStatus, &str)> = changes
+        .iter()
+        .map(|change| (change.status(), change.path()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            (ChangeStatus::Modified, "a.txt"),
+            (ChangeStatus::Deleted, "gone.txt"),
+        ]
+    );
+    assert!(changes[0].new_file().unwrap().is_in_worktree());
+    let numstat = DiffFormat {
+        numstat: true,
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &numstat),
+        "0\t1\ta.txt\n0\t1\tgone.txt\n"
+    );
+    // The index still matches the tree.
+    assert_eq!(diff.diff_index(Some(&one), false, &everything)?.len(), 2);
+    assert!(diff.diff_index(Some(&one), true, &everything)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_raw_output() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_raw_output")?;
+    let mut diff = GitDiff::new(&repo)?;
+    let (one, two) = two_trees(diff.odb())?;
+    let everything = Pathspec::new::<&str>(&[]);
+    let names = DiffFormat {
+        name_status: true,
+        null_terminated: true,
+        ..DiffFormat::default()
+    };
+    diff.set_recursion(TreeRecursion::Shallow);
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "M\0a.txt\0M\0dir\0D\0gone.txt\0T\0link\0A\0new.txt\0"
+    );
+    diff.set_recursion(TreeRecursion::Trees);
+    let changes = diff.diff_trees(Some(&one), Some(&two), &Pathspec::new(&["dir/run.sh"]))?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "M\0dir\0M\0dir/run.sh\0"
+    );
+
+    diff.set_recursion(TreeRecursion::Files);
+    let pathspec = Pathspec::new(&["a.txt", "dir", "gone.txt"]);
+    let changes = diff.diff_trees(Some(&one), Some(&two), &pathspec)?;
+    let blob = |data: &str| objects::hash_object(ObjectType::Blob, data.as_bytes());
+    let raw = DiffFormat {
+        raw: true,
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &raw),
+        format!(
+            ":100644 100644 {} {} M\ta.txt\n\
+             :100644 100755 {} {} M\tdir/run.sh\n\
+             :100644 000000 {} {} D\tgone.txt\n",
+            blob("a\nb\nc\n"),
+            blob("a\nB\nc\n"),
+            blob("#!/bin/sh\n"),
+            blob("#!/bin/sh\n"),
+            blob("gone\n"),
+            NULL_SHA
+        )
+    );
+    let abbreviated = DiffFormat {
+        raw: true,
+        abbrev: Some(7),
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes[2..], &abbreviated),
+        format!(
+            ":100644 000000 {} 0000000 D\tgone.txt\n",
+            &blob("gone\n")[..7]
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diff_files_refresh() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_diff_files_refresh")?;
+    let worktree = repo.worktree().unwrap().to_path_buf();
+    let (one, _) = two_trees(&GitOdb::open(&repo)?)?;
+    let mut checkout = GitCheckout::new(&repo)?;
+    checkout.switch_trees(None, &one)?;
+    checkout.write_index()?;
+
+    // Rewriting a file changes its stat data but not its contents.
+    fs::write(worktree.join("a.txt"), "a\nb\nc\n")?;
+    fs::write(worktree.join("gone.txt"), "changed\n")?;
+    let everything = Pathspec::new::<&str>(&[]);
+    let mut diff = GitDiff::new(&repo)?;
+    let changes = diff.diff_files(&everything)?;
+    assert_eq!(changes.len(), 1);
+    let raw = DiffFormat {
+        raw: true,
+        abbrev: Some(7),
+        ..DiffFormat::default()
+    };
+    let gone = objects::hash_object(ObjectType::Blob, b"gone\n");
+    assert_eq!(
+        output(&mut diff, &changes, &raw),
+        format!(":100644 100644 {} 0000000 M\tgone.txt\n", &gone[..7])
+    );
+
+    diff.set_refresh(false);
+    let changes = diff.diff_files(&everything)?;
+    let paths: Vec<&str> = changes.iter().map(FileChange::path).collect();
+    assert_eq!(paths, ["a.txt", "gone.txt"]);
+    assert_eq!(changes[0].new_file().unwrap().sha(), NULL_SHA);
+    // Patches still compare the contents.
+    let patch = DiffFormat {
+        patch: true,
+        ..DiffFormat::default()
+    };
+    assert_eq!(output(&mut diff, &changes[..1], &patch), "");
+    assert!(output(&mut diff, &changes[1..], &patch).contains(&format!(
+        "index {}..{} 100644\n",
+        &gone[..7],
+        &objects::hash_object(ObjectType::Blob, b"changed\n")[..7]
+    )));
+    Ok(())
+}
+
+#[test]
+fn test_rename_score() -> GitResult<()> {
+    assert_eq!(RenameOptions::parse_score("")?, 30000);
+    assert_eq!(RenameOptions::parse_score("5")?, 30000);
+    assert_eq!(RenameOptions::parse_score("05")?, 3000);
+    assert_eq!(RenameOptions::parse_score("90%")?, 54000);
+    assert_eq!(RenameOptions::parse_score("5.5%")?, 3300);
+    assert_eq!(RenameOptions::parse_score("100%")?, 60000);
+    assert_eq!(RenameOptions::parse_score("150%")?, 60000);
+    assert!(RenameOptions::parse_score("5x").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_fingerprint() {
+    let lines: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+    let one = rename::Fingerprint::new(lines.as_bytes(), true);
+    let changed = rename::Fingerprint::new(lines.replacen("5\n", "five\n", 1).as_bytes(), true);
+    assert_eq!(one.copied_to(&changed), 49);
+    assert_eq!(one.similarity(&changed, 30000), 49 * 60000 / 54);
+    // Line endings do not matter in text.
+    let crlf = rename::Fingerprint::new(lines.replace('\n', "\r\n").as_bytes(), true);
+    assert_eq!(one.copied_to(&crlf), 51);
+    let binary = rename::Fingerprint::new(lines.replace('\n', "\r\n").as_bytes(), false);
+    assert_eq!(one.copied_to(&binary), 0);
+    // Files of too different sizes are not compared.
+    let half = rename::Fingerprint::new(&lines.as_bytes()[..18], true);
+    assert_eq!(one.similarity(&half, 30000), 0);
+    assert_eq!(one.similarity(&half, 6000), 18 * 60000 / 51);
+}
+
+#[test]
+fn test_find_renames() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_find_renames")?;
+    let mut diff = GitDiff::new(&repo)?;
+    let lines = |range: std::ops::RangeInclusive<u32>| -> String {
+        range.map(|i| format!("{}\n", i)).collect()
+    };
+    let (a, b) = (lines(1..=20), lines(21..=40));
+    let one = tree(
+        diff.odb(),
+        &[
+            ("a.txt", GitFileMode::regular(), &a),
+            ("b.txt", GitFileMode::regular(), &b),
+            ("c.txt", GitFileMode::regular(), "c\n"),
+        ],
+    )?;
+    let two = tree(
+        diff.odb(),
+        &[
+            ("b.txt", GitFileMode::regular(), &format!("{}41\n", b)),
+            ("b2.txt", GitFileMode::regular(), &b),
+            ("c2.txt", GitFileMode::regular(), "c\n"),
+            (
+                "dir/a.txt",
+                GitFileMode::regular(),
+                &a.replacen("5\n", "five\n", 1),
+            ),
+        ],
+    )?;
+    let everything = Pathspec::new::<&str>(&[]);
+    let names = DiffFormat {
+        name_status: true,
+        ..DiffFormat::default()
+    };
+
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(changes.len(), 6);
+    diff.set_renames(Some(RenameOptions::default()));
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "M\tb.txt\nA\tb2.txt\nR100\tc.txt\tc2.txt\nR090\ta.txt\tdir/a.txt\n"
+    );
+    assert_eq!(changes[3].status(), ChangeStatus::Renamed);
+    assert_eq!(changes[3].old_file().unwrap().path(), "a.txt");
+    assert_eq!(changes[3].similarity(), Some(90));
+
+    diff.set_renames(Some(RenameOptions {
+        copies: true,
+        ..RenameOptions::default()
+    }));
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "M\tb.txt\nC100\tb.txt\tb2.txt\nR100\tc.txt\tc2.txt\nR090\ta.txt\tdir/a.txt\n"
+    );
+    let stat = DiffFormat {
+        stat: true,
+        stat_width: Some(80),
+        ..DiffFormat::default()
+    };
+    assert_eq!(
+        output(&mut diff, &changes, &stat),
+        " b.txt              | 1 +\n \
+         b.txt => b2.txt    | 0\n \
+         c.txt => c2.txt    | 0\n \
+         a.txt => dir/a.txt | 2 +-\n \
+         4 files changed, 2 insertions(+), 1 deletion(-)\n"
+    );
+    let patch = DiffFormat {
+        patch: true,
+        ..DiffFormat::default()
+    };
+    assert!(output(&mut diff, &changes[1..], &patch).starts_with(
+        "diff --git a/b.txt b/b2.txt\n\
+         similarity index 100%\n\
+         copy from b.txt\n\
+         copy to b2.txt\n\
+         diff --git a/c.txt b/c2.txt\n\
+         similarity index 100%\n\
+         rename from c.txt\n\
+         rename to c2.txt\n\
+         diff --git a/a.txt b/dir/a.txt\n\
+         similarity index 90%\n\
+         rename from a.txt\n\
+         rename to dir/a.txt\n\
+         index 0ff3bbb..fb3ced1 100644\n\
+         --- a/a.txt\n\
+         +++ b/dir/a.txt\n"
+    ));
+
+    diff.set_renames(Some(RenameOptions {
+        min_score: RenameOptions::parse_score("95")?,
+        ..RenameOptions::default()
+    }));
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "D\ta.txt\nM\tb.txt\nA\tb2.txt\nR100\tc.txt\tc2.txt\nA\tdir/a.txt\n"
+    );
+    assert!(diff.take_warnings().is_empty());
+
+    // Past the rename limit similar files are not searched for, with a warning.
+    let one = tree(
+        diff.odb(),
+        &[
+            ("x.txt", GitFileMode::regular(), &a),
+            ("y.txt", GitFileMode::regular(), &b),
+        ],
+    )?;
+    let two = tree(
+        diff.odb(),
+        &[
+            ("x2.txt", GitFileMode::regular(), &format!("{}x\n", a)),
+            ("y2.txt", GitFileMode::regular(), &format!("{}y\n", b)),
+        ],
+    )?;
+    diff.set_renames(Some(RenameOptions {
+        limit: 1,
+        ..RenameOptions::default()
+    }));
+    let changes = diff.diff_trees(Some(&one), Some(&two), &everything)?;
+    assert_eq!(
+        output(&mut diff, &changes, &names),
+        "D\tx.txt\nA\tx2.txt\nD\ty.txt\nA\ty2.txt\n"
+    );
+    assert_eq!(
+        diff.take_warnings(),
+        [
+            "warning: exhaustive rename detection was skipped due to too many files.",
+            "warning: you may want to set your diff.renameLimit variable to at least 2 and retry the command."
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_word_diff() -> Result<(), Box<dyn Error>> {
+    let words = |minus: &str, plus: &str, mode: WordDiff, regex: Option<&str>| {
+        let style = mode.style("", "", "", "");
+        let regex = regex.map(|regex| regex::bytes::Regex::new(regex).unwrap());
+        let mut out = Vec::new();
+        words::write_words(
+            minus.as_bytes(),
+            plus.as_bytes(),
+            regex.as_ref(),
+            &style,
+            &mut out,
+        );
+        String::from_utf8(out).unwrap()
+    };
+    let (minus, plus) = ("one two three foo bar\n", "one 2 three four\n");
+    assert_eq!(
+        words(minus, plus, WordDiff::Plain, None),
+        "one [-two-]{+2+} three [-foo bar-]{+four+}\n"
+    );
+    assert_eq!(
+        words(minus, plus, WordDiff::Porcelain, None),
+        " one \n-two\n+2\n  three \n-foo bar\n+four\n~\n"
+    );
+    assert_eq!(
+        words("ab\n", "aXb\n", WordDiff::Plain, Some(".")),
+        "a{+X+}b\n"
+    );
+    // Only removed lines are shown whole.
+    assert_eq!(
+        words("a b\nc\n", "", WordDiff::Plain, None),
+        "[-a b-]\n[-c-]\n"
+    );
+    assert!("none".parse::<WordDiff>().is_err());
+
+    let repo = test_utils::setup("gitrs_test_word_diff")?;
+    let mut diff = GitDiff::new(&repo)?;
+    let one = tree(
+        diff.odb(),
+        &[("doc.txt", GitFileMode::regular(), "a b\nc d\ne f\ng\n")],
+    )?;
+    let two = tree(
+        diff.odb(),
+        &[("doc.txt", GitFileMode::regular(), "a b\ne F\nx y\nz\ng")],
+    )?;
+    let changes = diff.diff_trees(Some(&one), Some(&two), &Pathspec::new::<&str>(&[]))?;
+    let mut hunks = |mode| {
+        let format = DiffFormat {
+            patch: true,
+            word_diff: Some(mode),
+            ..DiffFormat::default()
+        };
+        let patch = output(&mut diff, &changes, &format);
+        patch[patch.find("@@").unwrap()..].to_string()
+    };
+    // The missing newline at the end is not shown.
+    assert_eq!(
+        hunks(WordDiff::Plain),
+        "@@ -1,4 +1,5 @@\na b\n[-c d-]e [-f-]{+F+}\n{+x y+}\n{+z+}\ng\n"
+    );
+    assert_eq!(
+        hunks(WordDiff::Porcelain),
+        "@@ -1,4 +1,5 @@\n a b\n~\n-c d\n e \n-f\n+F\n~\n+x y\n~\n+z\n~\n g\n~\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_color_moved() {
+    let line = |kind, text: &str| HunkLine {
+        kind,
+        text: text.as_bytes().to_vec(),
+    };
+    let (removed, added, boundary) = (LineKind::Removed, LineKind::Added, LineKind::Context);
+    let lines = [
+        line(removed, "the first line moving down\n"),
+        line(removed, "the second line moving down\n"),
+        line(boundary, ""),
+        line(removed, "another line moving on its own\n"),
+        line(removed, "x\n"),
+        line(boundary, ""),
+        line(added, "another line moving on its own\n"),
+        line(added, "the first line moving down\n"),
+        line(added, "the second line moving down\n"),
+        line(added, "x\n"),
+    ];
+    let marked = |mode, flag: fn(&moved::MovedMark) -> bool| -> Vec<usize> {
+        let marks = moved::mark_moved(&lines, mode);
+        (0..lines.len()).filter(|&i| flag(&marks[i])).collect()
+    };
+    // Blocks with too little text are not moved.
+    assert_eq!(
+        marked(ColorMoved::Plain, |mark| mark.moved),
+        [0, 1, 3, 4, 6, 7, 8, 9]
+    );
+    assert_eq!(
+        marked(ColorMoved::Blocks, |mark| mark.moved),
+        [0, 1, 3, 6, 7, 8]
+    );
+    assert!(marked(ColorMoved::Blocks, |mark| mark.alternative).is_empty());
+    // Adjacent blocks moved from different places alternate.
+    assert_eq!(marked(ColorMoved::Zebra, |mark| mark.alternative), [7, 8]);
+    assert_eq!(
+        marked(ColorMoved::DimmedZebra, |mark| mark.dimmed),
+        [0, 1, 3, 8]
+    );
+    assert_eq!("default".parse::<ColorMoved>().unwrap(), ColorMoved::Zebra);
+}
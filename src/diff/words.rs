@@ -0,0 +1,217 @@
+//! Showing the changed words of the removed and added lines of a hunk, like
+//! `git diff --word-diff`.
+//!
+//! The text of a run of removed lines and of the added lines after it is split into words,
+//! the two lists of words are compared like lines, and the added text is shown with the
+//! removed and added words marked.
+
+use std::str::FromStr;
+
+use regex::bytes::Regex;
+
+use super::output::is_space;
+use super::{diff_lines, DiffOptions};
+use crate::{GitError, GitResult};
+
+/// How the changed words are marked, like `--word-diff=<mode>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WordDiff {
+    /// Removed words as `[-words-]` and added words as `{+words+}`.
+    #[default]
+    Plain,
+    /// Removed and added words only in their colors.
+    Color,
+    /// A line for each run of unchanged, removed or added words, starting with a space, `-`
+    /// or `+`, and a `~` line for each newline, meant for scripts.
+    Porcelain,
+}
+
+impl FromStr for WordDiff {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        match s {
+            "plain" => Ok(WordDiff::Plain),
+            "color" => Ok(WordDiff::Color),
+            "porcelain" => Ok(WordDiff::Porcelain),
+            _ => Err(GitError::DiffError {
+                message: format!("bad --word-diff argument: {}", s),
+            }),
+        }
+    }
+}
+
+/// How a piece of text is written: its color and the marks around it.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct WordMarkup<'a> {
+    pub(super) color: &'a str,
+    pub(super) prefix: &'a str,
+    pub(super) suffix: &'a str,
+}
+
+/// How the unchanged, removed and added words of a word diff are written.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct WordStyle<'a> {
+    pub(super) context: WordMarkup<'a>,
+    pub(super) old: WordMarkup<'a>,
+    pub(super) new: WordMarkup<'a>,
+    /// What a newline in the text is written as.
+    pub(super) newline: &'a str,
+    pub(super) reset: &'a str,
+}
+
+impl WordDiff {
+    /// Returns the markup of `self` with the colors of unchanged, removed and added words,
+    /// which are empty without colors.
+    pub(super) fn style<'a>(
+        self,
+        context: &'a str,
+        old: &'a str,
+        new: &'a str,
+        reset: &'a str,
+    ) -> WordStyle<'a> {
+        let markup = |color, prefix, suffix| WordMarkup {
+            color,
+            prefix,
+            suffix,
+        };
+        match self {
+            WordDiff::Plain => WordStyle {
+                context: markup(context, "", ""),
+                old: markup(old, "[-", "-]"),
+                new: markup(new, "{+", "+}"),
+                newline: "\n",
+                reset,
+            },
+            WordDiff::Color => WordStyle {
+                context: markup(context, "", ""),
+                old: markup(old, "", ""),
+                new: markup(new, "", ""),
+                newline: "\n",
+                reset,
+            },
+            WordDiff::Porcelain => WordStyle {
+                context: markup(context, " ", "\n"),
+                old: markup(old, "-", "\n"),
+                new: markup(new, "+", "\n"),
+                newline: "~\n",
+                reset,
+            },
+        }
+    }
+}
+
+/// Appends the text `plus` to `out` with the words changed from `minus` marked as described
+/// by `style`. Words are runs of non-whitespace, or the matches of `regex`.
+pub(super) fn write_words(
+    minus: &[u8],
+    plus: &[u8],
+    regex: Option<&Regex>,
+    style: &WordStyle,
+    out: &mut Vec<u8>,
+) {
+    if plus.is_empty() {
+        write_text(minus, &style.old, style, out);
+        return;
+    }
+    let minus_words = split_words(minus, regex);
+    let plus_words = split_words(plus, regex);
+    let options = DiffOptions {
+        context: 0,
+        indent_heuristic: false,
+        ..Default::default()
+    };
+    let hunks = diff_lines(
+        &word_lines(minus, &minus_words),
+        &word_lines(plus, &plus_words),
+        &options,
+    );
+
+    // Like the line numbers of hunks, the first word counts from 1, and a side without
+    // words starts after the word before it. Index 0 is an empty word at the start.
+    let span = |words: &[(usize, usize)], start: usize, len: usize| {
+        if len == 0 {
+            (words[start].1, words[start].1)
+        } else {
+            (words[start].0, words[start + len - 1].1)
+        }
+    };
+    let mut current = 0;
+    for hunk in hunks {
+        let (minus_begin, minus_end) = span(&minus_words, hunk.old_start, hunk.old_lines);
+        let (plus_begin, plus_end) = span(&plus_words, hunk.new_start, hunk.new_lines);
+        write_text(&plus[current..plus_begin], &style.context, style, out);
+        write_text(&minus[minus_begin..minus_end], &style.old, style, out);
+        write_text(&plus[plus_begin..plus_end], &style.new, style, out);
+        current = plus_end;
+    }
+    write_text(&plus[current..], &style.context, style, out);
+}
+
+/// Returns the start and end of the words of `text`, after an empty word at its start.
+fn split_words(text: &[u8], regex: Option<&Regex>) -> Vec<(usize, usize)> {
+    let mut words = vec![(0, 0)];
+    let mut pos = 0;
+    while pos < text.len() {
+        let (begin, end) = match regex {
+            Some(regex) => match regex.find_at(text, pos) {
+                // Words do not span lines.
+                Some(word) => {
+                    let end = text[word.start()..word.end()]
+                        .iter()
+                        .position(|&ch| ch == b'\n')
+                        .map_or(word.end(), |newline| word.start() + newline);
+                    (word.start(), end)
+                }
+                None => break,
+            },
+            None => {
+                let begin = match text[pos..].iter().position(|&ch| !is_space(ch)) {
+                    Some(offset) => pos + offset,
+                    None => break,
+                };
+                let len = text[begin..]
+                    .iter()
+                    .position(|&ch| is_space(ch))
+                    .unwrap_or(text.len() - begin);
+                (begin, begin + len)
+            }
+        };
+        if begin == end {
+            pos = begin + 1;
+            continue;
+        }
+        words.push((begin, end));
+        pos = end;
+    }
+    words
+}
+
+/// Returns the words of `text`, without the empty word at the start, as one line each.
+fn word_lines(text: &[u8], words: &[(usize, usize)]) -> Vec<u8> {
+    let mut lines = Vec::new();
+    for &(begin, end) in &words[1..] {
+        lines.extend(&text[begin..end]);
+        lines.push(b'\n');
+    }
+    lines
+}
+
+/// Appends `text` marked up as `markup`, with its newlines written as set by `style`.
+fn write_text(text: &[u8], markup: &WordMarkup, style: &WordStyle, out: &mut Vec<u8>) {
+    for (i, part) in text.split(|&ch| ch == b'\n').enumerate() {
+        if i > 0 {
+            out.extend(style.newline.as_bytes());
+        }
+        if part.is_empty() {
+            continue;
+        }
+        out.extend(markup.color.as_bytes());
+        out.extend(markup.prefix.as_bytes());
+        out.extend(part);
+        out.extend(markup.suffix.as_bytes());
+        if !markup.color.is_empty() {
+            out.extend(style.reset.as_bytes());
+        }
+    }
+}
@@ -28,6 +28,47 @@ pub enum GitError {
     VarInvalidUnicode { var: OsString, data: OsString },
     /// Any error coming from [io::Error].
     IOError { error: io::Error, path: PathBuf },
+    /// A file could not be converted between its worktree and repository representations.
+    ConversionError { path: PathBuf, message: String },
+    /// A required filter driver failed or is not configured.
+    FilterError {
+        filter: String,
+        path: PathBuf,
+        message: String,
+    },
+    /// An object is missing, has an unexpected type or can not be parsed.
+    ObjectError { sha: String, message: String },
+    /// A revision could not be resolved to an object.
+    RevisionError { rev: String, message: String },
+    /// The index file is corrupt or uses an unsupported feature.
+    IndexError { path: PathBuf, message: String },
+    /// A file mode does not fit the six octal digits of a git file mode.
+    FileModeError { mode: u32 },
+    /// A ref could not be read or updated.
+    RefError { name: String, message: String },
+    /// No name or email is configured for the author or committer of a new object.
+    IdentityError { role: String },
+    /// A lock file already exists, so another git process may be running.
+    LockError { path: PathBuf },
+    /// Checking out would lose local changes, or the arguments do not make sense.
+    CheckoutError { message: String },
+    /// A config file can not be parsed, or a config key or value is invalid.
+    ConfigError { path: PathBuf, message: String },
+    /// A branch could not be created, renamed or configured.
+    BranchError { name: String, message: String },
+    /// A repository could not be created, found or opened.
+    RepositoryError { path: PathBuf, message: String },
+    /// The options of a history listing are invalid.
+    LogError { message: String },
+    /// A commit-graph file is corrupt, or a commit-graph can not be written.
+    CommitGraphError { path: PathBuf, message: String },
+    /// The options of a diff are invalid, or the files to compare can not be read.
+    DiffError { message: String },
+    /// A patch is corrupt or does not apply.
+    ApplyError { message: String },
+    /// A mail can not be read, or a series of patches from mails can not be started or
+    /// resumed.
+    MailError { message: String },
 }
 
 impl Display for GitError {
@@ -39,6 +80,52 @@ impl Display for GitError {
                 var.to_str().unwrap(),
                 data.to_string_lossy()
             ),
+            GitError::ConversionError { path: _, message } => write!(f, "fatal: {}", message),
+            GitError::FilterError {
+                filter: _,
+                path: _,
+                message,
+            } => write!(f, "fatal: {}", message),
+            GitError::ObjectError { sha: _, message } => write!(f, "fatal: {}", message),
+            GitError::RevisionError { rev: _, message } => write!(f, "fatal: {}", message),
+            GitError::IndexError { path: _, message } => write!(f, "fatal: {}", message),
+            GitError::FileModeError { mode } => write!(f, "fatal: invalid file mode {:o}", mode),
+            GitError::RefError { name: _, message } => write!(f, "fatal: {}", message),
+            GitError::CheckoutError { message } => write!(f, "{}", message),
+            GitError::ConfigError { path: _, message } => write!(f, "{}", message),
+            GitError::BranchError { name: _, message } => write!(f, "fatal: {}", message),
+            GitError::RepositoryError { path: _, message } => write!(f, "fatal: {}", message),
+            GitError::LogError { message } => write!(f, "fatal: {}", message),
+            GitError::CommitGraphError { path: _, message } => write!(f, "fatal: {}", message),
+            GitError::DiffError { message } => write!(f, "fatal: {}", message),
+            GitError::ApplyError { message } => write!(f, "{}", message),
+            GitError::MailError { message } => write!(f, "{}", message),
+            GitError::IdentityError { role } => {
+                let mut role = role.clone();
+                role[..1].make_ascii_uppercase();
+                write!(
+                    f,
+                    "{} identity unknown\n\n\
+                     *** Please tell me who you are.\n\n\
+                     Run\n\n  \
+                     git config --global user.email \"you@example.com\"\n  \
+                     git config --global user.name \"Your Name\"\n\n\
+                     to set your account's default identity.\n\
+                     Omit --global to set the identity only in this repository.\n\n\
+                     fatal: unable to auto-detect email address",
+                    role
+                )
+            }
+            GitError::LockError { path } => write!(
+                f,
+                "fatal: Unable to create '{}': File exists.\n\n\
+                 Another git process seems to be running in this repository, e.g.\n\
+                 an editor opened by 'git commit'. Please make sure all processes\n\
+                 are terminated then try again. If it still fails, a git process\n\
+                 may have crashed in this repository earlier:\n\
+                 remove the file manually to continue.",
+                path.display()
+            ),
             GitError::IOError { error, path } => {
                 // TODO: Make path absolute for console output without using fs::canonicalize.
                 let path = path.to_str().unwrap();
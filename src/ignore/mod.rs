@@ -0,0 +1,306 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::GitConfig;
+use crate::utils::{self, WM_CASEFOLD, WM_PATHNAME};
+use crate::{GitRepo, GitResult};
+
+/// A single pattern read from a `.gitignore`, `info/exclude` or `core.excludesFile`.
+#[derive(Debug, Clone)]
+pub struct IgnorePattern {
+    glob: String,
+    pattern: String,
+    base: String,
+    source: Option<PathBuf>,
+    line: usize,
+    negative: bool,
+    must_be_dir: bool,
+    no_dir: bool,
+}
+
+impl IgnorePattern {
+    /// Parses a line of an ignore file.
+    ///
+    /// `base` is the directory (relative to the worktree, without a trailing slash) that the
+    /// pattern is relative to. Returns [None] for blank lines and comments.
+    pub fn parse(line: &str, base: &str, source: Option<PathBuf>, line_nr: usize) -> Option<Self> {
+        let line = trim_trailing_spaces(line.trim_end_matches('\r'));
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let original = line.to_string();
+
+        let (negative, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (must_be_dir, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+        let no_dir = !line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line).to_string();
+        let base = if base.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", base.trim_end_matches('/'))
+        };
+
+        Some(Self {
+            glob,
+            pattern: original,
+            base,
+            source,
+            line: line_nr,
+            negative,
+            must_be_dir,
+            no_dir,
+        })
+    }
+
+    /// Returns true if this pattern matches `path`.
+    ///
+    /// `path` is relative to the worktree and uses '/' as a separator. Negation is not taken into
+    /// account here; see [IgnorePattern::is_negative].
+    pub fn matches(&self, path: &str, is_dir: bool, ignore_case: bool) -> bool {
+        if self.must_be_dir && !is_dir {
+            return false;
+        }
+        let flags = if ignore_case { WM_CASEFOLD } else { 0 };
+        if self.no_dir {
+            let basename = path.rsplit('/').next().unwrap_or(path);
+            return utils::wildmatch(&self.glob, basename, flags);
+        }
+
+        let rest = if self.base.is_empty() {
+            path
+        } else if path.len() > self.base.len()
+            && path.is_char_boundary(self.base.len())
+            && (path[..self.base.len()] == self.base
+                || (ignore_case && path[..self.base.len()].eq_ignore_ascii_case(&self.base)))
+        {
+            &path[self.base.len()..]
+        } else {
+            return false;
+        };
+        utils::wildmatch(&self.glob, rest, flags | WM_PATHNAME)
+    }
+
+    /// Returns true if this pattern re-includes paths (starts with '!').
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Returns the pattern as it was written in its source.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns the file this pattern was read from, if any.
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// Returns the line number of this pattern in its source.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+/// Removes trailing spaces that are not escaped with a backslash.
+fn trim_trailing_spaces(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let mut last_space = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' => {
+                if last_space.is_none() {
+                    last_space = Some(i);
+                }
+            }
+            b'\\' => {
+                i += 1;
+                last_space = None;
+            }
+            _ => last_space = None,
+        }
+        i += 1;
+    }
+    match last_space {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+/// Parses every pattern in the contents of an ignore file.
+pub fn parse_patterns(data: &str, base: &str, source: Option<PathBuf>) -> Vec<IgnorePattern> {
+    data.lines()
+        .enumerate()
+        .filter_map(|(i, line)| IgnorePattern::parse(line, base, source.clone(), i + 1))
+        .collect()
+}
+
+/// Decides whether worktree paths are ignored.
+///
+/// Patterns are consulted in git's order of precedence: `.gitignore` files from the deepest
+/// directory up to the worktree root, then `$GIT_DIR/info/exclude`, then `core.excludesFile`.
+/// Within a single source the last matching pattern wins. Paths inside an ignored directory are
+/// always ignored.
+#[derive(Debug)]
+pub struct GitIgnore {
+    worktree: PathBuf,
+    ignore_case: bool,
+    info_exclude: Vec<IgnorePattern>,
+    excludes_file: Vec<IgnorePattern>,
+    per_dir: HashMap<String, Vec<IgnorePattern>>,
+}
+
+impl GitIgnore {
+    /// Creates a matcher for the worktree of `repo`.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading the config or the exclude files.
+    pub fn new(repo: &GitRepo) -> GitResult<Self> {
+        let worktree = repo
+            .worktree()
+            .unwrap_or_else(|| Path::new("."))
+            .to_path_buf();
+        let config = GitConfig::load(Some(repo))?;
+        let ignore_case = config.get_bool("core.ignorecase", false)?;
+
+        let info_path = repo.gitpath().join("info").join("exclude");
+        let info_exclude = read_patterns(&info_path, display_path(&worktree, &info_path))?;
+
+        let excludes_path = config
+            .get_path("core.excludesfile")?
+            .or_else(|| utils::xdg_config_path("ignore"));
+        let excludes_file = match excludes_path {
+            Some(path) => read_patterns(&path, path.clone())?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            worktree,
+            ignore_case,
+            info_exclude,
+            excludes_file,
+            per_dir: HashMap::new(),
+        })
+    }
+
+    /// Returns the pattern that decides whether `path` is ignored, if any.
+    ///
+    /// `path` is relative to the worktree and uses '/' as a separator. The returned pattern may
+    /// be negative, in which case the path is explicitly not ignored.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitignore` files.
+    pub fn matching_pattern(
+        &mut self,
+        path: &str,
+        is_dir: bool,
+    ) -> GitResult<Option<IgnorePattern>> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            return Ok(None);
+        }
+
+        // A path inside an excluded directory can not be re-included.
+        for (i, _) in path.match_indices('/') {
+            if let Some(pattern) = self.last_match(&path[..i], true)? {
+                if !pattern.is_negative() {
+                    return Ok(Some(pattern));
+                }
+            }
+        }
+        self.last_match(path, is_dir)
+    }
+
+    /// Returns true if `path` is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Can return errors obtained when reading `.gitignore` files.
+    pub fn is_ignored(&mut self, path: &str, is_dir: bool) -> GitResult<bool> {
+        Ok(self
+            .matching_pattern(path, is_dir)?
+            .is_some_and(|pattern| !pattern.is_negative()))
+    }
+
+    fn last_match(&mut self, path: &str, is_dir: bool) -> GitResult<Option<IgnorePattern>> {
+        let mut dir = match path.rfind('/') {
+            Some(i) => Some(&path[..i]),
+            None => Some(""),
+        };
+        while let Some(current) = dir {
+            let ignore_case = self.ignore_case;
+            let patterns = self.dir_patterns(current)?;
+            if let Some(pattern) = last_matching(patterns, path, is_dir, ignore_case) {
+                return Ok(Some(pattern.clone()));
+            }
+            dir = if current.is_empty() {
+                None
+            } else {
+                Some(current.rfind('/').map_or("", |i| &current[..i]))
+            };
+        }
+
+        for patterns in [&self.info_exclude, &self.excludes_file].iter() {
+            if let Some(pattern) = last_matching(patterns, path, is_dir, self.ignore_case) {
+                return Ok(Some(pattern.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn dir_patterns(&mut self, dir: &str) -> GitResult<&Vec<IgnorePattern>> {
+        if !self.per_dir.contains_key(dir) {
+            let path = self.worktree.join(dir).join(".gitignore");
+            let source = if dir.is_empty() {
+                PathBuf::from(".gitignore")
+            } else {
+                PathBuf::from(dir).join(".gitignore")
+            };
+            let patterns = if path.is_file() {
+                parse_patterns(&utils::read_file(&path)?, dir, Some(source))
+            } else {
+                Vec::new()
+            };
+            self.per_dir.insert(dir.to_string(), patterns);
+        }
+        Ok(&self.per_dir[dir])
+    }
+}
+
+fn last_matching<'a>(
+    patterns: &'a [IgnorePattern],
+    path: &str,
+    is_dir: bool,
+    ignore_case: bool,
+) -> Option<&'a IgnorePattern> {
+    patterns
+        .iter()
+        .rev()
+        .find(|pattern| pattern.matches(path, is_dir, ignore_case))
+}
+
+fn read_patterns(path: &Path, source: PathBuf) -> GitResult<Vec<IgnorePattern>> {
+    if path.is_file() {
+        Ok(parse_patterns(&utils::read_file(path)?, "", Some(source)))
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn display_path(worktree: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(worktree).unwrap_or(path).to_path_buf()
+}
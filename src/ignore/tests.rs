@@ -0,0 +1,80 @@
+use super::*;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+#[test]
+fn test_parse_pattern() {
+    assert!(IgnorePattern::parse("", "", None, 1).is_none());
+    assert!(IgnorePattern::parse("# comment", "", None, 1).is_none());
+    assert!(IgnorePattern::parse("\\#file", "", None, 1).is_some());
+
+    let pattern = IgnorePattern::parse("!build/  ", "", None, 3).unwrap();
+    assert!(pattern.is_negative());
+    assert_eq!(pattern.pattern(), "!build/");
+    assert_eq!(pattern.line(), 3);
+
+    let pattern = IgnorePattern::parse("trailing\\ ", "", None, 1).unwrap();
+    assert!(pattern.matches("trailing ", false, false));
+}
+
+#[test]
+fn test_pattern_matches() {
+    // Patterns without a slash match in any directory.
+    let pattern = IgnorePattern::parse("*.o", "", None, 1).unwrap();
+    assert!(pattern.matches("main.o", false, false));
+    assert!(pattern.matches("src/main.o", false, false));
+
+    // Patterns with a slash are anchored to their directory.
+    let pattern = IgnorePattern::parse("/build", "", None, 1).unwrap();
+    assert!(pattern.matches("build", true, false));
+    assert!(!pattern.matches("src/build", true, false));
+    let pattern = IgnorePattern::parse("doc/*.txt", "sub", None, 1).unwrap();
+    assert!(pattern.matches("sub/doc/a.txt", false, false));
+    assert!(!pattern.matches("doc/a.txt", false, false));
+    assert!(!pattern.matches("sub/doc/x/a.txt", false, false));
+
+    // Directory only patterns.
+    let pattern = IgnorePattern::parse("target/", "", None, 1).unwrap();
+    assert!(pattern.matches("target", true, false));
+    assert!(!pattern.matches("target", false, false));
+}
+
+#[test]
+fn test_git_ignore() -> Result<(), Box<dyn Error>> {
+    let worktree = env::temp_dir().join("gitrs_test_git_ignore");
+    let gitpath = worktree.join(".git");
+    fs::create_dir_all(gitpath.join("info"))?;
+    fs::create_dir_all(worktree.join("sub"))?;
+    fs::write(
+        gitpath.join("config"),
+        "[core]\n\texcludesfile = /nonexistent\n",
+    )?;
+    fs::write(gitpath.join("info").join("exclude"), "*.tmp\n")?;
+    fs::write(worktree.join(".gitignore"), "*.log\n!keep.log\nbuild/\n")?;
+    fs::write(
+        worktree.join("sub").join(".gitignore"),
+        "!*.tmp\n/local.log\n",
+    )?;
+
+    let repo = GitRepo::new(worktree.clone(), gitpath);
+    let mut ignore = GitIgnore::new(&repo)?;
+
+    assert!(ignore.is_ignored("debug.log", false)?);
+    assert!(!ignore.is_ignored("keep.log", false)?);
+    assert!(ignore.is_ignored("a.tmp", false)?);
+    // Deeper .gitignore files take precedence over info/exclude.
+    assert!(!ignore.is_ignored("sub/a.tmp", false)?);
+    assert!(ignore.is_ignored("sub/local.log", false)?);
+    // Files in an ignored directory can not be re-included.
+    assert!(ignore.is_ignored("build/keep.log", false)?);
+    assert!(!ignore.is_ignored("src/main.rs", false)?);
+
+    let pattern = ignore.matching_pattern("sub/local.log", false)?.unwrap();
+    assert_eq!(pattern.source(), Some(Path::new("sub/.gitignore")));
+    assert_eq!(pattern.line(), 2);
+
+    fs::remove_dir_all(worktree)?;
+
+    Ok(())
+}
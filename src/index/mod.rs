@@ -0,0 +1,487 @@
+//! Reading and writing of the index (the staging area) in `.git/index`.
+
+#[cfg(test)]
+mod tests;
+
+use std::convert::TryInto;
+use std::fs::{self, Metadata};
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+
+use crate::utils::LockFile;
+use crate::{GitError, GitFileMode, GitOdb, GitRepo, GitResult, GitTree, GitTreeEntry};
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXT_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXT_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// A single file tracked by the index, together with the stat data of its worktree copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    ctime: (u32, u32),
+    mtime: (u32, u32),
+    dev: u32,
+    ino: u32,
+    mode: GitFileMode,
+    uid: u32,
+    gid: u32,
+    size: u32,
+    sha: String,
+    flags: u16,
+    extended_flags: u16,
+    path: String,
+}
+
+impl IndexEntry {
+    /// Creates a stage 0 entry without any stat data, so it is never considered up to date.
+    pub fn new(path: &str, mode: GitFileMode, sha: &str) -> Self {
+        Self {
+            ctime: (0, 0),
+            mtime: (0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            sha: sha.to_string(),
+            flags: 0,
+            extended_flags: 0,
+            path: path.to_string(),
+        }
+    }
+
+    /// Creates a stage 0 entry with the stat data of the worktree file described by `metadata`.
+    pub fn from_metadata(path: &str, mode: GitFileMode, sha: &str, metadata: &Metadata) -> Self {
+        let mut entry = Self::new(path, mode, sha);
+        entry.set_stat(metadata);
+        entry
+    }
+
+    /// Records the stat data of the worktree file.
+    pub fn set_stat(&mut self, metadata: &Metadata) {
+        self.ctime = (metadata.ctime() as u32, metadata.ctime_nsec() as u32);
+        self.mtime = (metadata.mtime() as u32, metadata.mtime_nsec() as u32);
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.size() as u32;
+    }
+
+    /// Returns true if the stat data matches `metadata`, meaning the file is unchanged unless the
+    /// entry is racily clean (see [GitIndex::is_racy]).
+    pub fn is_stat_clean(&self, metadata: &Metadata) -> bool {
+        self.mtime == (metadata.mtime() as u32, metadata.mtime_nsec() as u32)
+            && self.ctime == (metadata.ctime() as u32, metadata.ctime_nsec() as u32)
+            && self.ino == metadata.ino() as u32
+            && self.uid == metadata.uid()
+            && self.gid == metadata.gid()
+            && self.size == metadata.size() as u32
+    }
+
+    /// The path of the file relative to the worktree.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The mode of the file.
+    pub fn mode(&self) -> GitFileMode {
+        self.mode
+    }
+
+    /// The Sha1 hash of the blob (or commit for gitlinks).
+    pub fn sha(&self) -> &str {
+        &self.sha
+    }
+
+    /// The merge stage: 0 for normal entries, 1 to 3 for the sides of a conflict.
+    pub fn stage(&self) -> u8 {
+        ((self.flags & FLAG_STAGE_MASK) >> 12) as u8
+    }
+
+    /// Sets the merge stage.
+    pub fn set_stage(&mut self, stage: u8) {
+        self.flags = (self.flags & !FLAG_STAGE_MASK) | (((stage & 3) as u16) << 12);
+    }
+
+    /// The size of the worktree file when it was last staged or checked out.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The modification time of the worktree file as seconds and nanoseconds.
+    pub fn mtime(&self) -> (u32, u32) {
+        self.mtime
+    }
+
+    /// Returns true if the `assume-unchanged` bit is set.
+    pub fn assume_valid(&self) -> bool {
+        self.flags & FLAG_ASSUME_VALID != 0
+    }
+
+    /// Returns true if the `skip-worktree` bit is set.
+    pub fn skip_worktree(&self) -> bool {
+        self.extended_flags & EXT_FLAG_SKIP_WORKTREE != 0
+    }
+
+    /// Returns true if the entry was added with `git add -N`.
+    pub fn intent_to_add(&self) -> bool {
+        self.extended_flags & EXT_FLAG_INTENT_TO_ADD != 0
+    }
+
+    fn sort_key(&self) -> (&[u8], u8) {
+        (self.path.as_bytes(), self.stage())
+    }
+}
+
+/// The index of a repository.
+#[derive(Debug)]
+pub struct GitIndex {
+    path: PathBuf,
+    version: u32,
+    entries: Vec<IndexEntry>,
+    timestamp: Option<(u32, u32)>,
+}
+
+impl GitIndex {
+    /// Reads the index of `repo`. A missing index file is an empty index.
+    ///
+    /// # Errors
+    ///
+    /// See [GitIndex::from_file].
+    pub fn read(repo: &GitRepo) -> GitResult<Self> {
+        Self::from_file(repo.gitpath().join("index"))
+    }
+
+    /// Reads an index file in version 2, 3 or 4.
+    ///
+    /// Optional extensions (like the cached tree) are skipped and dropped when writing.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The file can not be read
+    /// * [GitError::IndexError]: The file is corrupt or uses a required extension that is not
+    ///   supported
+    pub fn from_file<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                return Err(GitError::IOError { error, path })
+            }
+            Err(_) => {
+                return Ok(Self {
+                    path,
+                    version: 2,
+                    entries: Vec::new(),
+                    timestamp: None,
+                })
+            }
+        };
+        let data = crate::utils::read_bytes(&path)?;
+        let corrupt = |message: &str| GitError::IndexError {
+            path: path.clone(),
+            message: format!("index file corrupt: {}", message),
+        };
+
+        if data.len() < 12 + 20 || &data[..4] != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (content, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(content).as_slice() != checksum {
+            return Err(corrupt("bad index file sha1 signature"));
+        }
+        let version = be32(&data[4..]);
+        if !(2..=4).contains(&version) {
+            return Err(corrupt(&format!("bad index version {}", version)));
+        }
+        let count = be32(&data[8..]) as usize;
+
+        let mut pos = 12;
+        let mut entries = Vec::with_capacity(count);
+        let mut previous_path = String::new();
+        for _ in 0..count {
+            if content.len() < pos + 62 {
+                return Err(corrupt("truncated entry"));
+            }
+            let field = |i: usize| be32(&content[pos + i * 4..]);
+            let mut entry = IndexEntry {
+                ctime: (field(0), field(1)),
+                mtime: (field(2), field(3)),
+                dev: field(4),
+                ino: field(5),
+                mode: GitFileMode::from_octal(field(6)).map_err(|_| corrupt("bad file mode"))?,
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+                sha: hex::encode(&content[pos + 40..pos + 60]),
+                flags: u16::from_be_bytes([content[pos + 60], content[pos + 61]]),
+                extended_flags: 0,
+                path: String::new(),
+            };
+            let start = pos;
+            pos += 62;
+            if entry.flags & FLAG_EXTENDED != 0 {
+                if version < 3 || content.len() < pos + 2 {
+                    return Err(corrupt("unexpected extended flags"));
+                }
+                entry.extended_flags = u16::from_be_bytes([content[pos], content[pos + 1]]);
+                pos += 2;
+            }
+
+            if version == 4 {
+                // The path is stored as the number of bytes to strip from the previous path,
+                // followed by the new suffix.
+                let mut byte = *content.get(pos).ok_or_else(|| corrupt("truncated path"))?;
+                pos += 1;
+                let mut strip = (byte & 0x7f) as usize;
+                while byte & 0x80 != 0 {
+                    byte = *content.get(pos).ok_or_else(|| corrupt("truncated path"))?;
+                    pos += 1;
+                    strip = ((strip + 1) << 7) | (byte & 0x7f) as usize;
+                }
+                let nul = content[pos..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| corrupt("unterminated path"))?;
+                if strip > previous_path.len() {
+                    return Err(corrupt("invalid path compression"));
+                }
+                let mut path = previous_path.as_bytes()[..previous_path.len() - strip].to_vec();
+                path.extend_from_slice(&content[pos..pos + nul]);
+                entry.path = String::from_utf8_lossy(&path).into_owned();
+                pos += nul + 1;
+            } else {
+                let nul = content[pos..]
+                    .iter()
+                    .position(|&b| b == 0)
+                    .ok_or_else(|| corrupt("unterminated path"))?;
+                entry.path = String::from_utf8_lossy(&content[pos..pos + nul]).into_owned();
+                // Entries are padded with 1 to 8 NUL bytes to a multiple of 8 bytes.
+                pos = start + (pos + nul - start + 8) / 8 * 8;
+            }
+            previous_path = entry.path.clone();
+            entries.push(entry);
+        }
+
+        while pos + 8 <= content.len() {
+            let signature = &content[pos..pos + 4];
+            let size = be32(&content[pos + 4..]) as usize;
+            if !signature[0].is_ascii_uppercase() {
+                return Err(GitError::IndexError {
+                    path: path.clone(),
+                    message: format!(
+                        "index uses {} extension, which we do not understand",
+                        String::from_utf8_lossy(signature)
+                    ),
+                });
+            }
+            pos += 8 + size;
+        }
+        if pos != content.len() {
+            return Err(corrupt("truncated extension"));
+        }
+
+        Ok(Self {
+            path,
+            version,
+            entries,
+            timestamp: Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32)),
+        })
+    }
+
+    /// Writes the index back to its file through `index.lock`.
+    ///
+    /// Version 3 is written if any entry has extended flags, version 2 otherwise.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::LockError]: The index is locked by another process
+    /// * [GitError::IOError]: The index can not be written
+    pub fn write(&mut self) -> GitResult<()> {
+        self.entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        let version: u32 = if self.entries.iter().any(|e| e.extended_flags != 0) {
+            3
+        } else {
+            2
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            let start = data.len();
+            for field in &[
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode.to_octal(),
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+            data.extend_from_slice(&hex::decode(&entry.sha).unwrap_or_else(|_| vec![0; 20]));
+
+            let mut flags = entry.flags & (FLAG_ASSUME_VALID | FLAG_STAGE_MASK);
+            flags |= entry.path.len().min(FLAG_NAME_MASK as usize) as u16;
+            if entry.extended_flags != 0 {
+                flags |= FLAG_EXTENDED;
+            }
+            data.extend_from_slice(&flags.to_be_bytes());
+            if entry.extended_flags != 0 {
+                data.extend_from_slice(&entry.extended_flags.to_be_bytes());
+            }
+            data.extend_from_slice(entry.path.as_bytes());
+            let len = data.len() - start;
+            data.resize(start + (len + 8) / 8 * 8, 0);
+        }
+        let checksum = Sha1::digest(&data);
+        data.extend_from_slice(&checksum);
+
+        let mut lock = LockFile::acquire(&self.path)?;
+        lock.write_all(&data)?;
+        lock.commit()?;
+
+        self.version = version;
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            self.timestamp = Some((metadata.mtime() as u32, metadata.mtime_nsec() as u32));
+        }
+        Ok(())
+    }
+
+    /// Writes the stage 0 entries to `odb` as a tree, with a subtree for each directory, and
+    /// returns its name. Entries only marked as intended to be added are left out.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IndexError]: A path is unmerged
+    /// * Errors obtained when writing the objects
+    pub fn write_tree(&self, odb: &GitOdb) -> GitResult<String> {
+        if let Some(entry) = self.entries.iter().find(|entry| entry.stage() != 0) {
+            return Err(GitError::IndexError {
+                path: self.path.clone(),
+                message: format!("{}: unmerged ({})", entry.path, entry.sha),
+            });
+        }
+        let entries: Vec<&IndexEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.intent_to_add())
+            .collect();
+        write_subtree(odb, &entries, 0)
+    }
+
+    /// The version of the index file that was read.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// All entries, sorted by path and stage.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the stage 0 entry for `path`.
+    pub fn entry(&self, path: &str) -> Option<&IndexEntry> {
+        self.find(path, 0).ok().map(|i| &self.entries[i])
+    }
+
+    /// Returns the stage 0 entry for `path` for updating its stat data.
+    pub fn entry_mut(&mut self, path: &str) -> Option<&mut IndexEntry> {
+        match self.find(path, 0) {
+            Ok(i) => Some(&mut self.entries[i]),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns true if any path has unmerged (stage 1 to 3) entries.
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.iter().any(|entry| entry.stage() != 0)
+    }
+
+    /// Returns true if `path` is tracked in any stage.
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.iter().any(|entry| entry.path == path)
+    }
+
+    /// Adds or replaces the entry for its path and stage. Adding a stage 0 entry resolves any
+    /// conflict on the path.
+    pub fn add(&mut self, entry: IndexEntry) {
+        if entry.stage() == 0 {
+            let path = entry.path.clone();
+            self.entries
+                .retain(|existing| existing.path != path || existing.stage() == 0);
+        }
+        match self.find(&entry.path, entry.stage()) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Removes all stages of `path`. Returns true if anything was removed.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.len() != len
+    }
+
+    /// Returns true if `entry` was modified in the same second as (or after) the index was
+    /// written, in which case matching stat data does not prove the file is unchanged.
+    pub fn is_racy(&self, entry: &IndexEntry) -> bool {
+        match self.timestamp {
+            Some(timestamp) => entry.mtime >= timestamp,
+            None => true,
+        }
+    }
+
+    fn find(&self, path: &str, stage: u8) -> Result<usize, usize> {
+        self.entries
+            .binary_search_by(|entry| entry.sort_key().cmp(&(path.as_bytes(), stage)))
+    }
+}
+
+fn be32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+/// Writes the tree of `entries`, which all share the first `prefix` bytes of their paths and
+/// are sorted, and returns its name.
+fn write_subtree(odb: &GitOdb, entries: &[&IndexEntry], prefix: usize) -> GitResult<String> {
+    let mut tree_entries = Vec::new();
+    let mut i = 0;
+    while i < entries.len() {
+        let rest = &entries[i].path[prefix..];
+        match rest.find('/') {
+            Some(slash) => {
+                // The paths below a directory are next to each other in the sorted index.
+                let dir = &rest[..=slash];
+                let len = entries[i..]
+                    .iter()
+                    .take_while(|entry| entry.path[prefix..].starts_with(dir))
+                    .count();
+                let sha = write_subtree(odb, &entries[i..i + len], prefix + dir.len())?;
+                tree_entries.push(GitTreeEntry::new(GitFileMode::tree(), &rest[..slash], &sha));
+                i += len;
+            }
+            None => {
+                tree_entries.push(GitTreeEntry::new(entries[i].mode, rest, &entries[i].sha));
+                i += 1;
+            }
+        }
+    }
+    odb.write_object(&GitTree::new(tree_entries))
+}
@@ -0,0 +1,101 @@
+use super::*;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+const SHA: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+#[test]
+fn test_index_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_index_roundtrip");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("index");
+    let _ = fs::remove_file(&path);
+
+    let mut index = GitIndex::from_file(&path)?;
+    assert!(index.entries().is_empty());
+    index.add(IndexEntry::new("b.txt", GitFileMode::regular(), SHA));
+    index.add(IndexEntry::new("a/c.sh", GitFileMode::executable(), SHA));
+    index.add(IndexEntry::new("a.txt", GitFileMode::symlink(), SHA));
+    index.write()?;
+
+    let mut index = GitIndex::from_file(&path)?;
+    assert_eq!(index.version(), 2);
+    // Entries are sorted by path bytes, so "a.txt" comes before "a/c.sh".
+    let paths: Vec<&str> = index.entries().iter().map(IndexEntry::path).collect();
+    assert_eq!(paths, ["a.txt", "a/c.sh", "b.txt"]);
+    assert!(index.entry("a/c.sh").unwrap().mode().is_executable());
+    assert!(index.remove("b.txt"));
+    assert!(!index.remove("b.txt"));
+    index.write()?;
+    assert_eq!(GitIndex::from_file(&path)?.entries().len(), 2);
+
+    fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_index_stages() -> Result<(), Box<dyn Error>> {
+    let mut index = GitIndex::from_file(env::temp_dir().join("gitrs_test_index_stages"))?;
+    for stage in 1..=3 {
+        let mut entry = IndexEntry::new("file", GitFileMode::regular(), SHA);
+        entry.set_stage(stage);
+        index.add(entry);
+    }
+    assert!(index.has_conflicts());
+    assert!(index.entry("file").is_none());
+
+    // Resolving a conflict replaces all of its stages.
+    index.add(IndexEntry::new("file", GitFileMode::regular(), SHA));
+    assert!(!index.has_conflicts());
+    assert_eq!(index.entries().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_index_checksum() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_index_checksum");
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("index");
+    let _ = fs::remove_file(&path);
+
+    let mut index = GitIndex::from_file(&path)?;
+    index.add(IndexEntry::new("file", GitFileMode::regular(), SHA));
+    index.write()?;
+    let mut data = fs::read(&path)?;
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(&path, data)?;
+    assert!(matches!(
+        GitIndex::from_file(&path),
+        Err(GitError::IndexError { .. })
+    ));
+
+    fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_write_tree() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_write_tree");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    let odb = GitOdb::from_dir(&dir)?;
+
+    let mut index = GitIndex::from_file(dir.join("index"))?;
+    index.add(IndexEntry::new("b.txt", GitFileMode::regular(), SHA));
+    index.add(IndexEntry::new("a/c.sh", GitFileMode::executable(), SHA));
+    index.add(IndexEntry::new("a.txt", GitFileMode::regular(), SHA));
+    // The same tree as `git write-tree` makes.
+    let tree = index.write_tree(&odb)?;
+    assert_eq!(tree, "893d007e76f1af00c6b540cc5673f97b74ca32e9");
+    assert!(odb.contains(&tree));
+
+    let mut entry = IndexEntry::new("b.txt", GitFileMode::regular(), SHA);
+    entry.set_stage(2);
+    index.add(entry);
+    assert!(index.write_tree(&odb).is_err());
+
+    fs::remove_dir_all(dir)?;
+    Ok(())
+}
@@ -0,0 +1,440 @@
+#[cfg(test)]
+mod tests;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ConfigFile, GitConfig};
+use crate::refs;
+use crate::{self as gitrs, utils, GitError, GitRepo, GitResult};
+
+const DEFAULT_DESCRIPTION: &[u8] =
+    b"Unnamed repository; edit this file 'description' to name the repository.\n";
+
+const DEFAULT_EXCLUDE: &[u8] = b"# git ls-files --others --exclude-from=.git/info/exclude
+# Lines that start with '#' are comments.
+# For a project mostly in C, the following would be a good set of
+# exclude patterns (uncomment them if you want to use them):
+# *.[oa]
+# *~
+";
+
+/// How `git init` sets up a repository.
+///
+/// Unlike git, nothing is taken from the environment: `git init` fills in the fields standing
+/// for `$GIT_DIR`, `$GIT_WORK_TREE` and `$GIT_TEMPLATE_DIR`.
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// The directory to create the repository in, the current directory by default.
+    pub directory: Option<PathBuf>,
+    pub bare: bool,
+    /// The git directory, relative to `directory`, instead of `.git` or `directory` itself for
+    /// a bare repository, like `$GIT_DIR`.
+    pub git_dir: Option<PathBuf>,
+    /// The worktree, if it is not `directory`, like `$GIT_WORK_TREE`. It is recorded as
+    /// `core.worktree` unless it holds the git directory.
+    pub work_tree: Option<PathBuf>,
+    /// A template directory to copy into the new git directory, `init.templateDir` or the
+    /// built-in files by default; an empty path copies nothing.
+    pub template: Option<PathBuf>,
+    /// The branch `HEAD` points to, `init.defaultBranch` or `master` by default.
+    pub initial_branch: Option<String>,
+    /// The `--shared` permission mode.
+    pub shared: Option<SharedMode>,
+    /// Put the git directory here and link to it with a `.git` file.
+    pub separate_git_dir: Option<PathBuf>,
+}
+
+/// What [GitRepo::init_with_report] did, for `git init` to tell.
+#[derive(Debug, Clone)]
+pub struct InitReport {
+    pub repo: GitRepo,
+    /// The repository existed already.
+    pub reinitialized: bool,
+    /// No initial branch was given or configured, so the repository starts on `master`.
+    pub default_branch: bool,
+    /// Problems that did not stop the repository from being set up, each a `warning: ` line.
+    pub warnings: Vec<String>,
+}
+
+/// Who may access the files of a shared repository, like `core.sharedRepository`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedMode {
+    /// Use the permissions from the umask.
+    Umask,
+    /// Make the repository group-writable.
+    Group,
+    /// Make the repository group-writable and readable by everybody.
+    Everybody,
+    /// Use exactly these permission bits for files.
+    Mode(u32),
+}
+
+impl SharedMode {
+    /// Parses a `--shared` or `core.sharedRepository` value, where [None] means `group`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ConfigError]: The value is neither a mode nor a boolean, or the mode does
+    ///   not let the owner read and write
+    pub fn parse(value: Option<&str>) -> GitResult<Self> {
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(SharedMode::Group),
+        };
+        match value {
+            "umask" => return Ok(SharedMode::Umask),
+            "group" => return Ok(SharedMode::Group),
+            "all" | "world" | "everybody" => return Ok(SharedMode::Everybody),
+            _ => {}
+        }
+        let mode = match u32::from_str_radix(value, 8) {
+            Ok(mode) => mode,
+            Err(_) => {
+                return match crate::config::parse_bool(Some(value)) {
+                    Some(true) => Ok(SharedMode::Group),
+                    Some(false) => Ok(SharedMode::Umask),
+                    None => Err(GitError::ConfigError {
+                        path: PathBuf::new(),
+                        message: format!("fatal: bad boolean config value '{}' for 'arg'", value),
+                    }),
+                }
+            }
+        };
+        match mode {
+            0 => Ok(SharedMode::Umask),
+            1 => Ok(SharedMode::Group),
+            2 => Ok(SharedMode::Everybody),
+            mode if mode & 0o600 != 0o600 => Err(GitError::ConfigError {
+                path: PathBuf::new(),
+                message: format!(
+                    "fatal: problem with core.sharedRepository filemode value (0{:03o}).\n\
+                     The owner of files must always have read and write permissions.",
+                    mode
+                ),
+            }),
+            // Nobody else can be given write access.
+            mode => Ok(SharedMode::Mode(mode & 0o666)),
+        }
+    }
+
+    /// Returns the permissions of a file or directory with `mode` in a shared repository.
+    pub fn adjust(self, mode: u32, dir: bool) -> u32 {
+        let mut mode = match self {
+            SharedMode::Umask => return mode,
+            SharedMode::Group => mode | 0o660,
+            SharedMode::Everybody => mode | 0o664,
+            SharedMode::Mode(bits) => (mode & !0o777) | bits,
+        };
+        if dir {
+            // Directories can be entered by whoever can read them, and keep their group.
+            mode |= (mode & 0o444) >> 2;
+            mode |= 0o2000;
+        }
+        mode
+    }
+
+    /// The value written to `core.sharedRepository`.
+    fn config_value(self) -> Option<String> {
+        match self {
+            SharedMode::Umask => None,
+            SharedMode::Group => Some("1".to_string()),
+            SharedMode::Everybody => Some("2".to_string()),
+            SharedMode::Mode(bits) => Some(format!("0{:o}", bits)),
+        }
+    }
+}
+
+/// Initializes a git repository, or reinitializes an existing one without touching its
+/// contents, and tells how it went.
+///
+/// # Errors
+///
+/// * [GitError::RepositoryError]: The initial branch name is invalid
+/// * [GitError::ConfigError]: A config file can not be parsed
+/// * [GitError::IOError]: A directory or file can not be created, copied or moved
+pub fn init(options: &InitOptions) -> GitResult<InitReport> {
+    let directory = options
+        .directory
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    utils::create_dir_all_if_new(&directory)?;
+
+    // Like git, GIT_DIR is relative to the directory being initialized.
+    let git_dir = options.git_dir.as_ref().map(|dir| directory.join(dir));
+    let gitpath = if options.bare {
+        git_dir.unwrap_or_else(|| directory.clone())
+    } else if let Some(separate) = &options.separate_git_dir {
+        link_git_dir(&directory.join(".git"), separate)?
+    } else if let Some(gitpath) = git_dir {
+        gitpath
+    } else {
+        let dot_git = directory.join(".git");
+        read_gitfile(&dot_git)?.unwrap_or(dot_git)
+    };
+
+    let head_path = gitpath.join("HEAD");
+    let reinit = head_path.is_file();
+    let mut warnings = Vec::new();
+    utils::create_dir_all_if_new(&gitpath)?;
+    warnings.extend(copy_template(&gitpath, options.template.as_deref())?);
+    let mut default_branch = false;
+    let branch = if reinit {
+        if let Some(branch) = &options.initial_branch {
+            warnings.push(format!(
+                "warning: re-init: ignored --initial-branch={}",
+                branch
+            ));
+        }
+        None
+    } else {
+        let (branch, default) = initial_branch(options)?;
+        default_branch = default;
+        Some(branch)
+    };
+    utils::create_dir_all_if_new(gitpath.join("objects").join("info"))?;
+    utils::create_dir_if_new(gitpath.join("objects").join("pack"))?;
+    let refs_dir = gitpath.join("refs");
+    utils::create_dir_all_if_new(refs_dir.join("heads"))?;
+    utils::create_dir_if_new(refs_dir.join("tags"))?;
+
+    if let Some(branch) = branch {
+        utils::write_if_new(
+            &head_path,
+            format!("ref: refs/heads/{}\n", branch).as_bytes(),
+        )?;
+    }
+    write_config(&gitpath, options, reinit)?;
+
+    let shared = options.shared.unwrap_or(SharedMode::Umask);
+    if shared != SharedMode::Umask {
+        adjust_shared_permissions(&gitpath, shared)?;
+    }
+
+    let worktree = match &options.work_tree {
+        _ if options.bare => None,
+        Some(worktree) => Some(worktree.clone()),
+        None => Some(directory),
+    };
+    Ok(InitReport {
+        repo: GitRepo::with_worktree(worktree, gitpath),
+        reinitialized: reinit,
+        default_branch,
+        warnings,
+    })
+}
+
+/// Returns the branch a new repository starts on, checking that it is a valid name, and
+/// whether it is the default `master` because none is configured.
+fn initial_branch(options: &InitOptions) -> GitResult<(String, bool)> {
+    let (branch, default) = match &options.initial_branch {
+        Some(branch) => (branch.clone(), false),
+        None => match GitConfig::load(None)?.get("init.defaultBranch")? {
+            Some(branch) => (branch, false),
+            None => ("master".to_string(), true),
+        },
+    };
+    if !refs::is_valid_branch_name(&branch) {
+        return Err(GitError::RepositoryError {
+            path: PathBuf::new(),
+            message: format!("invalid initial branch name: '{}'", branch),
+        });
+    }
+    Ok((branch, default))
+}
+
+/// Copies the template directory into `gitpath` without overwriting existing files, and
+/// returns a warning if it does not exist.
+///
+/// Without an explicit template, `init.templateDir` is used, and failing that the built-in
+/// description, hooks and exclude file.
+fn copy_template(gitpath: &Path, template: Option<&Path>) -> GitResult<Option<String>> {
+    let template = match template {
+        Some(template) => Some(template.to_path_buf()),
+        None => GitConfig::load(None)?.get_path("init.templateDir")?,
+    };
+    match template {
+        Some(template) if template.as_os_str().is_empty() => {}
+        Some(template) if !template.is_dir() => {
+            return Ok(Some(format!(
+                "warning: templates not found in {}",
+                template.display()
+            )));
+        }
+        Some(template) => copy_dir(&template, gitpath)?,
+        None => {
+            utils::write_if_new(gitpath.join("description"), DEFAULT_DESCRIPTION)?;
+            utils::create_dir_if_new(gitpath.join("hooks"))?;
+            utils::create_dir_if_new(gitpath.join("info"))?;
+            utils::write_if_new(gitpath.join("info").join("exclude"), DEFAULT_EXCLUDE)?;
+        }
+    }
+    Ok(None)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> GitResult<()> {
+    utils::create_dir_if_new(to)?;
+    for entry in gitrs::to_git_result(fs::read_dir(from), from)? {
+        let entry = gitrs::to_git_result(entry, from)?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let file_type = gitrs::to_git_result(entry.file_type(), &source)?;
+        if file_type.is_dir() {
+            copy_dir(&source, &target)?;
+        } else if target.symlink_metadata().is_err() {
+            if file_type.is_symlink() {
+                let link = gitrs::to_git_result(fs::read_link(&source), &source)?;
+                gitrs::to_git_result(std::os::unix::fs::symlink(link, &target), &target)?;
+            } else {
+                gitrs::to_git_result(fs::copy(&source, &target), &target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the core settings to the config, keeping anything a template or an earlier init
+/// put there.
+fn write_config(gitpath: &Path, options: &InitOptions, reinit: bool) -> GitResult<()> {
+    let mut config = ConfigFile::open(gitpath.join("config"))?;
+    config.set("core.repositoryformatversion", "0")?;
+    let filemode = probe_filemode(gitpath, reinit)?;
+    config.set("core.filemode", if filemode { "true" } else { "false" })?;
+    if options.bare {
+        config.set("core.bare", "true")?;
+    } else {
+        config.set("core.bare", "false")?;
+        if config.get("core.logallrefupdates")?.is_none() {
+            config.set("core.logallrefupdates", "true")?;
+        }
+        // A worktree given with --work-tree is recorded unless it is the default one.
+        if let Some(worktree) = &options.work_tree {
+            utils::create_dir_all_if_new(worktree)?;
+            let worktree = gitrs::to_git_result(worktree.canonicalize(), worktree)?;
+            let abs_gitpath = gitrs::to_git_result(gitpath.canonicalize(), gitpath)?;
+            if abs_gitpath.parent() != Some(worktree.as_path()) {
+                config.set("core.worktree", &worktree.to_string_lossy())?;
+            }
+        }
+    }
+    // Like git, only settings that differ from the defaults are written.
+    if !reinit {
+        if !probe_symlinks(gitpath)? {
+            config.set("core.symlinks", "false")?;
+        }
+        if probe_ignorecase(gitpath) {
+            config.set("core.ignorecase", "true")?;
+        }
+        let precomposed = probe_precomposed_unicode(gitpath)?;
+        if precomposed || cfg!(target_os = "macos") {
+            let value = if precomposed { "true" } else { "false" };
+            config.set("core.precomposeunicode", value)?;
+        }
+    }
+    if let Some(value) = options.shared.and_then(SharedMode::config_value) {
+        config.set("core.sharedrepository", &value)?;
+        config.set("receive.denyNonFastforwards", "true")?;
+    }
+    config.write()
+}
+
+/// Returns true if the filesystem keeps the executable bit: flipping it on a new file must
+/// show up in its metadata, and the file must not start out executable.
+fn probe_filemode(gitpath: &Path, reinit: bool) -> GitResult<bool> {
+    let path = gitpath.join("config.probe");
+    gitrs::to_git_result(fs::write(&path, b""), &path)?;
+    let trusted = (|| -> std::io::Result<bool> {
+        let mode = fs::symlink_metadata(&path)?.permissions().mode();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode ^ 0o100))?;
+        let flipped = fs::symlink_metadata(&path)?.permissions().mode();
+        Ok(flipped != mode && (reinit || mode & 0o100 == 0))
+    })()
+    .unwrap_or(false);
+    gitrs::to_git_result(fs::remove_file(&path), &path)?;
+    Ok(trusted)
+}
+
+/// Returns true if symbolic links can be created in the git directory.
+fn probe_symlinks(gitpath: &Path) -> GitResult<bool> {
+    let path = gitpath.join("symlink.probe");
+    // A probe left behind by an interrupted init would make the link fail.
+    let _ = fs::remove_file(&path);
+    let supported = std::os::unix::fs::symlink("testing", &path).is_ok()
+        && fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink());
+    if supported {
+        gitrs::to_git_result(fs::remove_file(&path), &path)?;
+    }
+    Ok(supported)
+}
+
+/// Returns true if the filesystem ignores case, so `HEAD`, which a new repository always
+/// has by now, can be found as `hEaD`.
+fn probe_ignorecase(gitpath: &Path) -> bool {
+    gitpath.join("hEaD").exists()
+}
+
+/// Returns true if a file created with a precomposed unicode name can be found by its
+/// decomposed name, as on HFS+.
+fn probe_precomposed_unicode(gitpath: &Path) -> GitResult<bool> {
+    let precomposed = gitpath.join("\u{e4}");
+    gitrs::to_git_result(fs::write(&precomposed, b""), &precomposed)?;
+    let found = gitpath.join("a\u{308}").exists();
+    gitrs::to_git_result(fs::remove_file(&precomposed), &precomposed)?;
+    Ok(found)
+}
+
+/// Applies the shared permissions to everything in `gitpath`.
+fn adjust_shared_permissions(path: &Path, shared: SharedMode) -> GitResult<()> {
+    let metadata = gitrs::to_git_result(fs::symlink_metadata(path), path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mode = metadata.permissions().mode();
+    let adjusted = shared.adjust(mode, metadata.is_dir());
+    if adjusted != mode {
+        let permissions = fs::Permissions::from_mode(adjusted);
+        gitrs::to_git_result(fs::set_permissions(path, permissions), path)?;
+    }
+    if metadata.is_dir() {
+        for entry in gitrs::to_git_result(fs::read_dir(path), path)? {
+            adjust_shared_permissions(&gitrs::to_git_result(entry, path)?.path(), shared)?;
+        }
+    }
+    Ok(())
+}
+
+/// Points the `.git` file at `dot_git` to `separate`, moving an existing git directory there
+/// first, and returns the git directory.
+fn link_git_dir(dot_git: &Path, separate: &Path) -> GitResult<PathBuf> {
+    let existing = if dot_git.is_dir() {
+        Some(dot_git.to_path_buf())
+    } else {
+        read_gitfile(dot_git)?
+    };
+    if let Some(existing) = existing {
+        if existing != separate {
+            gitrs::to_git_result(fs::rename(&existing, separate), separate)?;
+        }
+    }
+    utils::create_dir_all_if_new(separate)?;
+    let abs = gitrs::to_git_result(separate.canonicalize(), separate)?;
+    let link = format!("gitdir: {}\n", abs.display());
+    gitrs::to_git_result(fs::write(dot_git, link), dot_git)?;
+    Ok(separate.to_path_buf())
+}
+
+/// Returns the git directory a `.git` file points to, or [None] if `path` is not a file.
+fn read_gitfile(path: &Path) -> GitResult<Option<PathBuf>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let data = utils::read_file(path)?;
+    match data.trim_end().strip_prefix("gitdir: ") {
+        Some(dir) => Ok(Some(path.parent().unwrap_or(Path::new(".")).join(dir))),
+        None => Err(GitError::RepositoryError {
+            path: path.to_path_buf(),
+            message: format!("invalid gitfile format: {}", path.display()),
+        }),
+    }
+}
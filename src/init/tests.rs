@@ -0,0 +1,251 @@
+use super::*;
+use crate::ObjectType;
+use std::env;
+use std::error::Error;
+
+#[test]
+fn test_init_and_open() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_init_and_open");
+    let _ = fs::remove_dir_all(&dir);
+
+    let options = InitOptions {
+        directory: Some(dir.join("work")),
+        initial_branch: Some("main".to_string()),
+        ..Default::default()
+    };
+    let repo = GitRepo::init(&options)?;
+    assert_eq!(repo.gitpath(), dir.join("work/.git"));
+    assert_eq!(repo.worktree(), Some(dir.join("work").as_path()));
+
+    let repo = GitRepo::open(dir.join("work"))?;
+    assert!(!repo.is_bare());
+    assert_eq!(
+        repo.refs().head_branch()?.as_deref(),
+        Some("refs/heads/main")
+    );
+    assert!(repo.index()?.entries().is_empty());
+    let sha = repo.odb()?.write(ObjectType::Blob, b"hello\n")?;
+    assert_eq!(sha, "ce013625030ba8dba906f756967f9e9ca394464a");
+    assert_eq!(repo.config()?.get("core.bare")?.as_deref(), Some("false"));
+
+    let options = InitOptions {
+        directory: Some(dir.join("bare.git")),
+        bare: true,
+        ..options
+    };
+    GitRepo::init(&options)?;
+    assert!(GitRepo::open(dir.join("bare.git"))?.is_bare());
+    assert!(GitRepo::open(&dir).is_err());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_template() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_init_template");
+    let _ = fs::remove_dir_all(&dir);
+    let template = dir.join("template");
+    fs::create_dir_all(template.join("hooks"))?;
+    fs::write(template.join("hooks/pre-commit"), "#!/bin/sh\n")?;
+    fs::write(template.join("description"), "From the template\n")?;
+
+    let options = InitOptions {
+        directory: Some(dir.join("work")),
+        template: Some(template.clone()),
+        initial_branch: Some("main".to_string()),
+        ..Default::default()
+    };
+    let repo = GitRepo::init(&options)?;
+    let gitpath = repo.gitpath();
+    assert_eq!(
+        fs::read_to_string(gitpath.join("hooks/pre-commit"))?,
+        "#!/bin/sh\n"
+    );
+    assert!(!gitpath.join("info/exclude").exists());
+
+    // Reinitializing keeps files changed since.
+    fs::write(gitpath.join("description"), "Changed\n")?;
+    GitRepo::init(&options)?;
+    assert_eq!(
+        fs::read_to_string(gitpath.join("description"))?,
+        "Changed\n"
+    );
+
+    // An empty template copies nothing.
+    let options = InitOptions {
+        directory: Some(dir.join("empty")),
+        template: Some(PathBuf::new()),
+        ..options
+    };
+    let repo = GitRepo::init(&options)?;
+    assert!(!repo.gitpath().join("description").exists());
+    assert!(repo.gitpath().join("HEAD").is_file());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_shared() -> Result<(), Box<dyn Error>> {
+    assert_eq!(SharedMode::parse(None)?, SharedMode::Group);
+    assert_eq!(SharedMode::parse(Some("all"))?, SharedMode::Everybody);
+    assert_eq!(SharedMode::parse(Some("false"))?, SharedMode::Umask);
+    assert_eq!(SharedMode::parse(Some("0640"))?, SharedMode::Mode(0o640));
+    assert!(SharedMode::parse(Some("0440")).is_err());
+    assert!(SharedMode::parse(Some("sometimes")).is_err());
+
+    let dir = env::temp_dir().join("gitrs_test_init_shared");
+    let _ = fs::remove_dir_all(&dir);
+    let options = InitOptions {
+        directory: Some(dir.clone()),
+        template: Some(PathBuf::new()),
+        initial_branch: Some("main".to_string()),
+        shared: Some(SharedMode::Group),
+        ..Default::default()
+    };
+    let repo = GitRepo::init(&options)?;
+    let mode = |path: &str| -> std::io::Result<u32> {
+        Ok(fs::metadata(repo.gitpath().join(path))?
+            .permissions()
+            .mode())
+    };
+    assert_eq!(mode("HEAD")? & 0o660, 0o660);
+    assert_eq!(mode("objects")? & 0o2770, 0o2770);
+    let config = ConfigFile::open(repo.gitpath().join("config"))?;
+    assert_eq!(
+        config.get("core.sharedRepository")?,
+        Some(Some("1".to_string()))
+    );
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_separate_git_dir() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_init_separate_git_dir");
+    let _ = fs::remove_dir_all(&dir);
+    let options = InitOptions {
+        directory: Some(dir.join("work")),
+        template: Some(PathBuf::new()),
+        initial_branch: Some("main".to_string()),
+        separate_git_dir: Some(dir.join("separate")),
+        ..Default::default()
+    };
+    let repo = GitRepo::init(&options)?;
+    assert_eq!(repo.gitpath(), dir.join("separate"));
+    assert!(dir.join("separate/HEAD").is_file());
+    assert_eq!(
+        fs::read_to_string(dir.join("work/.git"))?,
+        format!(
+            "gitdir: {}\n",
+            dir.join("separate").canonicalize()?.display()
+        )
+    );
+    let repo = GitRepo::open(dir.join("work"))?;
+    assert_eq!(
+        repo.gitpath().canonicalize()?,
+        dir.join("separate").canonicalize()?
+    );
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_invalid_branch() {
+    let dir = env::temp_dir().join("gitrs_test_init_invalid_branch");
+    let _ = fs::remove_dir_all(&dir);
+    let options = InitOptions {
+        directory: Some(dir.clone()),
+        template: Some(PathBuf::new()),
+        initial_branch: Some("bad..name".to_string()),
+        ..Default::default()
+    };
+    match GitRepo::init(&options) {
+        Err(GitError::RepositoryError { message, .. }) => {
+            assert_eq!(message, "invalid initial branch name: 'bad..name'")
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(!dir.join(".git/HEAD").exists());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_init_probes() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_init_probes");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("Case.probe"), "")?;
+    let ignorecase = dir.join("cASE.PROBE").exists();
+
+    let options = InitOptions {
+        directory: Some(dir.join("work")),
+        template: Some(PathBuf::new()),
+        initial_branch: Some("main".to_string()),
+        ..Default::default()
+    };
+    let repo = GitRepo::init(&options)?;
+    let config = ConfigFile::open(repo.gitpath().join("config"))?;
+    let expected = if ignorecase {
+        Some(Some("true".to_string()))
+    } else {
+        None
+    };
+    assert_eq!(config.get("core.ignorecase")?, expected);
+    assert_eq!(config.get("core.symlinks")?, None);
+    assert!(config.get("core.filemode")?.is_some());
+    assert!(!repo.gitpath().join("config.probe").exists());
+    assert!(!repo.gitpath().join("symlink.probe").exists());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_init_report() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("gitrs_test_init_report");
+    let _ = fs::remove_dir_all(&dir);
+    let options = InitOptions {
+        directory: Some(dir.join("work")),
+        git_dir: Some(PathBuf::from("custom.git")),
+        work_tree: Some(dir.join("tree")),
+        template: Some(dir.join("missing")),
+        initial_branch: Some("main".to_string()),
+        ..Default::default()
+    };
+    let report = GitRepo::init_with_report(&options)?;
+    assert!(!report.reinitialized);
+    assert!(!report.default_branch);
+    assert_eq!(
+        report.warnings,
+        vec![format!(
+            "warning: templates not found in {}",
+            dir.join("missing").display()
+        )]
+    );
+    assert_eq!(report.repo.gitpath(), dir.join("work/custom.git"));
+    assert_eq!(report.repo.worktree(), Some(dir.join("tree").as_path()));
+    let config = ConfigFile::open(dir.join("work/custom.git/config"))?;
+    assert_eq!(
+        config.get("core.worktree")?,
+        Some(Some(dir.join("tree").canonicalize()?.display().to_string()))
+    );
+
+    let options = InitOptions {
+        template: Some(PathBuf::new()),
+        initial_branch: Some("other".to_string()),
+        ..options
+    };
+    let report = GitRepo::init_with_report(&options)?;
+    assert!(report.reinitialized);
+    assert_eq!(
+        report.warnings,
+        vec!["warning: re-init: ignored --initial-branch=other".to_string()]
+    );
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
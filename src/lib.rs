@@ -2,12 +2,61 @@
 //!
 //! `gitrs` is a re-implementation of some git features in native rust with no [libgit2](https://libgit2.org)  bindings.
 
+mod apply;
+mod attributes;
+mod checkout;
+mod commit_graph;
 mod config;
+mod convert;
+mod diff;
 mod error;
+mod ignore;
+mod index;
+mod init;
+mod log;
+mod mail;
 mod objects;
-mod subcommands;
+mod pathspec;
+mod refs;
+mod revision;
+pub mod subcommands;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
+pub use crate::apply::{
+    parse_patch, ApplyOptions, ApplyReport, BinaryHunk, BinaryPatch, FilePatch, GitApply,
+};
+pub use crate::attributes::{AttrValue, GitAttributes};
+pub use crate::checkout::GitCheckout;
+pub use crate::commit_graph::{
+    write_commit_graph, BloomFilter, CommitGraphOptions, GitCommitGraph, GraphCommit, SplitStrategy,
+};
+pub use crate::config::{
+    add_config_parameter, parse_bool, ConfigEntry, ConfigFile, ConfigOrigin, ConfigScope,
+    ConfigValue, GitConfig, ValuePattern, ValueType,
+};
+pub use crate::convert::{is_binary, AutoCrlf, Eol, GitConverter, SafeCrlf};
+pub use crate::diff::{
+    diff_lines, ChangeStatus, ColorMoved, DiffAlgorithm, DiffFile, DiffFormat, DiffOptions,
+    FileChange, GitDiff, Hunk, HunkLine, LineKind, RenameOptions, TreeRecursion, WordDiff,
+};
 pub use crate::error::{to_git_result, GitError, GitResult};
-pub use crate::objects::{GitBlob, GitFileMode, GitObject, GitRepo};
-pub use crate::subcommands::{hash_object, init};
+pub use crate::ignore::{GitIgnore, IgnorePattern};
+pub use crate::index::{GitIndex, IndexEntry};
+pub use crate::init::{InitOptions, InitReport, SharedMode};
+pub use crate::log::{write_log, Decorate, LogOptions, PrettyFormat};
+pub use crate::mail::{
+    format_patches, parse_mail, split_mbox, AmMessage, AmOptions, AmReport, AmStop,
+    FormatPatchOptions, GitAm, MailInfo, PatchMail,
+};
+pub use crate::objects::{
+    GitBlob, GitCommit, GitFileMode, GitObject, GitOdb, GitRepo, GitSignature, GitTag, GitTree,
+    GitTreeEntry, ObjectType,
+};
+pub use crate::pathspec::Pathspec;
+pub use crate::refs::{GitRefs, RefTarget, ReflogEntry};
+pub use crate::revision::{
+    merge_bases, rev_parse, RevSort, RevWalk, WalkOptions, WalkResult, WalkedCommit, DEFAULT_ABBREV,
+};
+pub use crate::utils::{approxidate, now, DateFormat};
@@ -0,0 +1,572 @@
+//! Drawing the history next to the log, like `git log --graph`.
+//!
+//! This is a port of git's `graph.c`, so that the same history is drawn the same way.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Lines between commits, leaving every branch line unchanged.
+    Padding,
+    /// A `...` line, for a commit whose output was not finished.
+    Skip,
+    /// Lines making room for an octopus merge.
+    PreCommit,
+    /// The line with the commit itself.
+    Commit,
+    /// The line fanning out to the parents of a merge.
+    PostMerge,
+    /// Lines moving branch lines to the left until they are in their columns.
+    Collapsing,
+}
+
+const MERGE_CHARS: [char; 3] = ['/', '|', '\\'];
+
+/// The state of the graph drawn left of the commits of a log.
+///
+/// Each commit is added with [Graph::update], then its lines are taken with
+/// [Graph::next_line] until the commit is finished.
+#[derive(Debug)]
+pub struct Graph {
+    commit: String,
+    /// The parents of the commit that are shown.
+    parents: Vec<String>,
+    width: usize,
+    expansion_row: usize,
+    state: State,
+    prev_state: State,
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// How the edges to the parents of a merge leave the commit: 0 when the first parent is to
+    /// the left, 1 otherwise. -1 until the layout is chosen.
+    merge_layout: isize,
+    edges_added: isize,
+    prev_edges_added: isize,
+    /// The commit each branch line leads to before this commit.
+    columns: Vec<String>,
+    /// The commit each branch line leads to after this commit.
+    new_columns: Vec<String>,
+    /// For each screen column, the column of `new_columns` the branch line there ends in, or
+    /// -1.
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+    mapping_size: usize,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Graph {
+    /// Creates an empty graph.
+    pub fn new() -> Self {
+        Self {
+            commit: String::new(),
+            parents: Vec::new(),
+            width: 0,
+            expansion_row: 0,
+            state: State::Padding,
+            prev_state: State::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: 0,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: Vec::new(),
+            new_columns: Vec::new(),
+            mapping: Vec::new(),
+            old_mapping: Vec::new(),
+            mapping_size: 0,
+        }
+    }
+
+    /// Moves on to the commit `sha`, whose shown parents are `parents`.
+    pub fn update(&mut self, sha: &str, parents: Vec<String>) {
+        self.commit = sha.to_string();
+        self.parents = parents;
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+
+        // The state is set without recording the previous one, as no line was shown for it.
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    /// Returns true once all lines up to and including the commit line were taken, and the
+    /// branch lines are back in their columns.
+    pub fn is_commit_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    /// Returns the next line of the graph, and whether it is the line of the commit.
+    pub fn next_line(&mut self) -> (String, bool) {
+        let mut line = String::new();
+        let commit_line = match self.state {
+            State::Padding => {
+                self.padding(&mut line);
+                false
+            }
+            State::Skip => {
+                self.skip_line(&mut line);
+                false
+            }
+            State::PreCommit => {
+                self.pre_commit_line(&mut line);
+                false
+            }
+            State::Commit => {
+                self.commit_line(&mut line);
+                true
+            }
+            State::PostMerge => {
+                self.post_merge_line(&mut line);
+                false
+            }
+            State::Collapsing => {
+                self.collapsing_line(&mut line);
+                false
+            }
+        };
+        self.pad(&mut line);
+        (line, commit_line)
+    }
+
+    /// Returns a line that leaves all branch lines unchanged, for output between the lines of
+    /// the graph.
+    pub fn padding_line(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+        let mut line = String::new();
+        for column in &self.columns {
+            line.push('|');
+            if *column == self.commit && self.parents.len() > 2 {
+                line.push_str(&" ".repeat((self.parents.len() - 2) * 2));
+            } else {
+                line.push(' ');
+            }
+        }
+        self.pad(&mut line);
+        self.prev_state = State::Padding;
+        line
+    }
+
+    /// Appends the lines of the graph up to the commit line, which is left unterminated for
+    /// the commit to follow.
+    pub fn show_commit(&mut self, out: &mut String) {
+        if self.is_commit_finished() {
+            out.push_str(&self.padding_line());
+            return;
+        }
+        loop {
+            let (line, commit_line) = self.next_line();
+            out.push_str(&line);
+            if commit_line {
+                break;
+            }
+            out.push('\n');
+            if self.is_commit_finished() {
+                break;
+            }
+        }
+    }
+
+    /// Appends `text`, prefixing each line but the first with the graph, then the lines left
+    /// to finish the commit.
+    pub fn show_message(&mut self, out: &mut String, text: &str) {
+        let mut lines = text.split_inclusive('\n').peekable();
+        while let Some(line) = lines.next() {
+            out.push_str(line);
+            if line.ends_with('\n') && lines.peek().is_some() {
+                out.push_str(&self.next_line().0);
+            }
+        }
+        if !self.is_commit_finished() {
+            let terminated = text.ends_with('\n');
+            if !terminated {
+                out.push('\n');
+            }
+            self.show_remainder(out);
+            if terminated {
+                out.push('\n');
+            }
+        }
+    }
+
+    /// Appends the line after the commit line.
+    pub fn show_oneline(&mut self, out: &mut String) {
+        out.push_str(&self.next_line().0);
+    }
+
+    /// Appends the lines left to finish the commit, separated by newlines.
+    pub fn show_remainder(&mut self, out: &mut String) {
+        while !self.is_commit_finished() {
+            out.push_str(&self.next_line().0);
+            if !self.is_commit_finished() {
+                out.push('\n');
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    fn find_new_column(&self, sha: &str) -> Option<usize> {
+        self.new_columns.iter().position(|column| column == sha)
+    }
+
+    fn insert_into_new_columns(&mut self, sha: &str, index: Option<usize>) {
+        let i = match self.find_new_column(sha) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(sha.to_string());
+                self.new_columns.len() - 1
+            }
+        };
+
+        let mapping_index;
+        if let Some(index) = index.filter(|_| self.parents.len() > 1 && self.merge_layout == -1) {
+            // The first parent of a merge chooses the layout of the merge line, depending on
+            // whether it is left of the merge.
+            let dist = index as isize - i as isize;
+            let shift = if dist > 1 { 2 * dist - 3 } else { 1 };
+            self.merge_layout = if dist > 0 { 0 } else { 1 };
+            self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+            mapping_index = (self.width as isize + (self.merge_layout - 1) * shift) as usize;
+            self.width += 2 * self.merge_layout as usize;
+        } else if self.edges_added > 0 && self.mapping[self.width - 2] == i as isize {
+            // The commit is in the last existing column, so the two edges join immediately.
+            mapping_index = self.width - 2;
+            self.edges_added = -1;
+        } else {
+            mapping_index = self.width;
+            self.width += 2;
+        }
+        self.mapping[mapping_index] = i as isize;
+    }
+
+    fn update_columns(&mut self) {
+        // The new columns of the previous commit are the columns of this one.
+        std::mem::swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+
+        let max_new_columns = self.columns.len() + self.parents.len();
+        self.mapping_size = 2 * max_new_columns;
+        self.mapping = vec![-1; self.mapping_size];
+        if self.old_mapping.len() < self.mapping_size {
+            self.old_mapping.resize(self.mapping_size, -1);
+        }
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                self.commit.clone()
+            } else {
+                self.columns[i].clone()
+            };
+
+            if column == self.commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone() {
+                    self.insert_into_new_columns(&parent, Some(i));
+                }
+                // The commit always takes up at least 2 spaces.
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(&column, None);
+            }
+        }
+
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+
+    fn num_dashed_parents(&self) -> isize {
+        self.parents.len() as isize + self.merge_layout - 3
+    }
+
+    fn num_expansion_rows(&self) -> isize {
+        self.num_dashed_parents() * 2
+    }
+
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && (self.expansion_row as isize) < self.num_expansion_rows()
+    }
+
+    fn is_mapping_correct(&self) -> bool {
+        // An entry one right of its target is drawn as '/', so it is right on the next line.
+        self.mapping[..self.mapping_size]
+            .iter()
+            .enumerate()
+            .all(|(i, &target)| target < 0 || target == (i / 2) as isize)
+    }
+
+    fn pad(&self, line: &mut String) {
+        // All lines of a commit have the same width, to keep the text right of them aligned.
+        let width = line.chars().count();
+        if width < self.width {
+            line.push_str(&" ".repeat(self.width - width));
+        }
+    }
+
+    fn padding(&self, line: &mut String) {
+        for _ in &self.new_columns {
+            line.push_str("| ");
+        }
+    }
+
+    fn skip_line(&mut self, line: &mut String) {
+        line.push_str("...");
+        if self.needs_pre_commit_line() {
+            self.set_state(State::PreCommit);
+        } else {
+            self.set_state(State::Commit);
+        }
+    }
+
+    fn pre_commit_line(&mut self, line: &mut String) {
+        // Each parent of an octopus merge over 2 needs 2 more rows of room.
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if *column == self.commit {
+                seen_this = true;
+                line.push('|');
+                line.push_str(&" ".repeat(self.expansion_row));
+            } else if seen_this && self.expansion_row == 0 {
+                // Lines right of a previous merge continue as they were drawn.
+                if self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    line.push('\\');
+                } else {
+                    line.push('|');
+                }
+            } else if seen_this {
+                line.push('\\');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.set_state(State::Commit);
+        }
+    }
+
+    fn octopus_merge(&self, line: &mut String) {
+        let dashed_parents = self.num_dashed_parents();
+        for i in 0..dashed_parents {
+            line.push('-');
+            line.push(if i == dashed_parents - 1 { '.' } else { '-' });
+        }
+    }
+
+    fn commit_line(&mut self, line: &mut String) {
+        // The commit may not be in any column yet, if none of its children were shown.
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let is_commit = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                true
+            } else {
+                self.columns[i] == self.commit
+            };
+
+            if is_commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push('\\');
+            } else if seen_this && self.edges_added == 1 {
+                // A line coming into this commit as '\' from a previous merge stays one.
+                if self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    line.push('\\');
+                } else {
+                    line.push('|');
+                }
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping[2 * i + 1] == i as isize
+                && self.mapping[2 * i] < i as isize
+            {
+                line.push('/');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+
+        if self.parents.len() > 1 {
+            self.set_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        } else {
+            self.set_state(State::Collapsing);
+        }
+    }
+
+    fn post_merge_line(&mut self, line: &mut String) {
+        let first_parent = self.parents[0].clone();
+        let mut seen_parent = false;
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = if i == self.columns.len() {
+                if seen_this {
+                    break;
+                }
+                self.commit.clone()
+            } else {
+                self.columns[i].clone()
+            };
+
+            if column == self.commit {
+                // The edges to the parents lead to their columns after the merge.
+                seen_this = true;
+                let mut idx = self.merge_layout as usize;
+                for j in 0..self.parents.len() {
+                    line.push(MERGE_CHARS[idx]);
+                    if idx == 2 {
+                        if self.edges_added > 0 || j + 1 < self.parents.len() {
+                            line.push(' ');
+                        }
+                    } else {
+                        idx += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                line.push(if self.edges_added > 0 { '\\' } else { '|' });
+                line.push(' ');
+            } else {
+                line.push('|');
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    line.push(if seen_parent { '_' } else { ' ' });
+                }
+            }
+
+            if column == first_parent {
+                seen_parent = true;
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        } else {
+            self.set_state(State::Collapsing);
+        }
+    }
+
+    fn collapsing_line(&mut self, line: &mut String) {
+        let mut used_horizontal = false;
+        let mut horizontal_edge: isize = -1;
+        let mut horizontal_edge_target: isize = -1;
+
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        if self.mapping.len() < self.mapping_size {
+            self.mapping.resize(self.mapping_size, -1);
+        }
+        for target in &mut self.mapping[..self.mapping_size] {
+            *target = -1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // Branch lines only ever move to the left, so that crossing lines are legible.
+            let ii = i as isize;
+            if target * 2 == ii {
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                // Nothing is to the left, so move left by one.
+                self.mapping[i - 1] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge = ii;
+                    horizontal_edge_target = target;
+                    let mut j = target * 2 + 3;
+                    while j < ii - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // The line to the left leads to the same commit, so the two combine.
+            } else {
+                // Cross over the line to the left, which leads elsewhere.
+                self.mapping[i - 2] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge_target = target;
+                    horizontal_edge = ii - 1;
+                    let mut j = target * 2 + 3;
+                    while j < ii - 2 {
+                        self.mapping[j as usize] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+
+        let size = self.mapping_size;
+        self.old_mapping[..size].copy_from_slice(&self.mapping[..size]);
+        if self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            let ii = i as isize;
+            if target < 0 {
+                line.push(' ');
+            } else if target * 2 == ii {
+                line.push('|');
+            } else if target == horizontal_edge_target && ii != horizontal_edge - 1 {
+                // Only the first segment of a horizontal edge continues on the next line.
+                if ii != target * 2 + 3 {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                line.push('_');
+            } else {
+                if used_horizontal && ii < horizontal_edge {
+                    self.mapping[i] = -1;
+                }
+                line.push('/');
+            }
+        }
+
+        if self.is_mapping_correct() {
+            self.set_state(State::Padding);
+        }
+    }
+}
@@ -0,0 +1,592 @@
+//! Showing the history of commits, like `git log`.
+
+mod graph;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+use self::graph::Graph;
+
+use crate::refs::{GitRefs, RefTarget};
+use crate::revision::{self, DEFAULT_ABBREV};
+use crate::utils::{self, DateFormat};
+use crate::{
+    self as gitrs, GitError, GitOdb, GitResult, GitSignature, GitTag, ObjectType, WalkResult,
+    WalkedCommit,
+};
+
+/// How each commit is shown, like `--pretty=<format>`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PrettyFormat {
+    /// The abbreviated name and the subject on one line.
+    Oneline,
+    /// The name, author and subject.
+    Short,
+    /// The name, author, date and message.
+    #[default]
+    Medium,
+    /// The name, author, committer and message.
+    Full,
+    /// The name, author and committer with their dates, and the message.
+    Fuller,
+    /// A format string with `%` placeholders. With `terminator`, each commit ends with a
+    /// newline (`tformat:`), otherwise commits are separated by one (`format:`).
+    Format { format: String, terminator: bool },
+}
+
+impl FromStr for PrettyFormat {
+    type Err = GitError;
+
+    /// Parses a format name, `format:<string>`, `tformat:<string>` or a string containing
+    /// placeholders, which is read as `tformat:`.
+    fn from_str(s: &str) -> GitResult<Self> {
+        if let Some(format) = s.strip_prefix("format:") {
+            return Ok(PrettyFormat::Format {
+                format: format.to_string(),
+                terminator: false,
+            });
+        }
+        if let Some(format) = s.strip_prefix("tformat:") {
+            return Ok(PrettyFormat::Format {
+                format: format.to_string(),
+                terminator: true,
+            });
+        }
+        match s {
+            "oneline" => Ok(PrettyFormat::Oneline),
+            "short" => Ok(PrettyFormat::Short),
+            "" | "medium" => Ok(PrettyFormat::Medium),
+            "full" => Ok(PrettyFormat::Full),
+            "fuller" => Ok(PrettyFormat::Fuller),
+            _ if s.contains('%') => Ok(PrettyFormat::Format {
+                format: s.to_string(),
+                terminator: true,
+            }),
+            _ => Err(GitError::LogError {
+                message: format!("invalid --pretty format: {}", s),
+            }),
+        }
+    }
+}
+
+/// Which ref names are shown next to the commits they point to, like `--decorate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decorate {
+    #[default]
+    No,
+    /// Names without the `refs/heads/`, `refs/tags/` and `refs/remotes/` prefixes.
+    Short,
+    Full,
+}
+
+impl FromStr for Decorate {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        match s {
+            "no" => Ok(Decorate::No),
+            "short" => Ok(Decorate::Short),
+            "full" => Ok(Decorate::Full),
+            _ => Err(GitError::LogError {
+                message: format!("invalid --decorate option: {}", s),
+            }),
+        }
+    }
+}
+
+/// How a log is shown.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub format: PrettyFormat,
+    /// Show abbreviated commit names in the header of each commit.
+    pub abbrev_commit: bool,
+    pub date: DateFormat,
+    pub decorate: Decorate,
+    /// Draw the history left of the commits. The walk should sort the commits topologically
+    /// and rewrite their parents.
+    pub graph: bool,
+    /// Only draw the edges to the first parents, for walks following only them.
+    pub first_parent: bool,
+}
+
+/// Writes the commits of `walk` to `out` as described by `options`.
+///
+/// # Errors
+///
+/// * [GitError::LogError]: A color in the format is invalid
+/// * [GitError::IOError]: The output can not be written
+/// * Errors obtained when reading refs or objects
+pub fn write_log<W: Write>(
+    odb: &GitOdb,
+    refs: &GitRefs,
+    walk: &WalkResult,
+    options: &LogOptions,
+    out: &mut W,
+) -> GitResult<()> {
+    let formatter = Formatter::new(odb, refs, options)?;
+    let mut graph = if options.graph {
+        Some(Graph::new())
+    } else {
+        None
+    };
+    let use_terminator = matches!(
+        options.format,
+        PrettyFormat::Oneline
+            | PrettyFormat::Format {
+                terminator: true,
+                ..
+            }
+    );
+    let is_empty =
+        matches!(&options.format, PrettyFormat::Format { format, .. } if format.is_empty());
+
+    let mut shown_one = false;
+    let mut missing_newline = false;
+    for walked in walk.commits() {
+        let mut entry = String::new();
+        if let Some(graph) = graph.as_mut() {
+            let parents = walked
+                .parents()
+                .iter()
+                .take(if options.first_parent { 1 } else { usize::MAX })
+                .filter(|parent| walk.is_shown(parent))
+                .cloned()
+                .collect();
+            graph.update(walked.sha(), parents);
+        }
+
+        // Without terminators, commits are separated by newlines, keeping the graph going.
+        if shown_one && !use_terminator {
+            if let Some(graph) = graph.as_mut().filter(|_| !missing_newline) {
+                entry.push_str(&graph.padding_line());
+            }
+            entry.push('\n');
+        }
+        shown_one = true;
+        if let Some(graph) = graph.as_mut() {
+            graph.show_commit(&mut entry);
+        }
+
+        let sha = formatter.commit_name(walked.sha())?;
+        match options.format {
+            PrettyFormat::Format { .. } => {}
+            PrettyFormat::Oneline => {
+                entry.push_str(&sha);
+                entry.push_str(&formatter.decorations(walked.sha(), " (", ")"));
+                entry.push(' ');
+            }
+            _ => {
+                entry.push_str("commit ");
+                entry.push_str(&sha);
+                entry.push_str(&formatter.decorations(walked.sha(), " (", ")"));
+                entry.push('\n');
+                if let Some(graph) = graph.as_mut() {
+                    graph.show_oneline(&mut entry);
+                }
+            }
+        }
+
+        let message = formatter.message(walked)?;
+        missing_newline = !message.ends_with('\n');
+        match graph.as_mut() {
+            Some(graph) => graph.show_message(&mut entry, &message),
+            None => entry.push_str(&message),
+        }
+        if use_terminator && !is_empty {
+            if let Some(graph) = graph.as_mut().filter(|_| !missing_newline) {
+                entry.push_str(&graph.padding_line());
+            }
+            entry.push('\n');
+        }
+        gitrs::to_git_result(out.write_all(entry.as_bytes()), "<stdout>")?;
+    }
+    gitrs::to_git_result(out.flush(), "<stdout>")
+}
+
+/// A ref name pointing to a commit.
+#[derive(Debug)]
+struct Decoration {
+    name: String,
+    is_tag: bool,
+}
+
+/// Formats the parts of the commits of a log.
+struct Formatter<'a> {
+    odb: &'a GitOdb,
+    options: &'a LogOptions,
+    /// The ref names pointing to each commit, `HEAD` first and then in reverse order.
+    decorations: HashMap<String, Vec<Decoration>>,
+    head_branch: Option<String>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(odb: &'a GitOdb, refs: &GitRefs, options: &'a LogOptions) -> GitResult<Self> {
+        let mut formatter = Self {
+            odb,
+            options,
+            decorations: HashMap::new(),
+            head_branch: None,
+        };
+        let wants_decorations = match &options.format {
+            PrettyFormat::Format { format, .. } => ["%", "%+", "%-", "% "].iter().any(|prefix| {
+                format.contains(&format!("{}d", prefix)) || format.contains(&format!("{}D", prefix))
+            }),
+            _ => false,
+        };
+        if options.decorate == Decorate::No && !wants_decorations {
+            return Ok(formatter);
+        }
+
+        let mut names = refs.list("refs/")?;
+        if let Some(sha) = refs.resolve("HEAD")? {
+            names.push(("HEAD".to_string(), sha));
+        }
+        for (name, sha) in names {
+            let is_tag = name.starts_with("refs/tags/");
+            // Tags decorate the objects they point to as well.
+            let mut sha = sha;
+            while odb.contains(&sha) && odb.read_type(&sha)? == ObjectType::Tag {
+                formatter.decorate(&sha, &name, is_tag);
+                sha = odb.read_as::<GitTag>(&sha)?.object().to_string();
+            }
+            formatter.decorate(&sha, &name, is_tag);
+        }
+        if let Some(RefTarget::Symbolic(branch)) = refs.read("HEAD")? {
+            formatter.head_branch = Some(branch);
+        }
+        Ok(formatter)
+    }
+
+    fn decorate(&mut self, sha: &str, name: &str, is_tag: bool) {
+        let decorations = self.decorations.entry(sha.to_string()).or_default();
+        decorations.insert(
+            0,
+            Decoration {
+                name: name.to_string(),
+                is_tag,
+            },
+        );
+    }
+
+    /// Returns the ref names pointing to `sha` between `prefix` and `suffix`, or an empty
+    /// string if there are none.
+    fn decorations(&self, sha: &str, prefix: &str, suffix: &str) -> String {
+        let decorations = match self.decorations.get(sha) {
+            Some(decorations) => decorations,
+            None => return String::new(),
+        };
+        // The branch HEAD points to is shown as `HEAD -> <branch>`.
+        let current = self.head_branch.as_ref().filter(|branch| {
+            decorations
+                .iter()
+                .any(|decoration| decoration.name == "HEAD")
+                && decorations
+                    .iter()
+                    .any(|decoration| decoration.name == **branch)
+        });
+
+        let mut names = Vec::new();
+        for decoration in decorations {
+            if Some(&decoration.name) == current {
+                continue;
+            }
+            let mut name = String::new();
+            if decoration.is_tag {
+                name.push_str("tag: ");
+            }
+            name.push_str(self.ref_name(&decoration.name));
+            if let Some(current) = current.filter(|_| decoration.name == "HEAD") {
+                name.push_str(" -> ");
+                name.push_str(self.ref_name(current));
+            }
+            names.push(name);
+        }
+        format!("{}{}{}", prefix, names.join(", "), suffix)
+    }
+
+    fn ref_name<'n>(&self, name: &'n str) -> &'n str {
+        if self.options.decorate == Decorate::Full {
+            return name;
+        }
+        ["refs/heads/", "refs/tags/", "refs/remotes/"]
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(name)
+    }
+
+    fn abbrev(&self, sha: &str) -> GitResult<String> {
+        revision::abbreviate(self.odb, sha, DEFAULT_ABBREV)
+    }
+
+    /// The name of a commit in its header line.
+    fn commit_name(&self, sha: &str) -> GitResult<String> {
+        if self.options.abbrev_commit {
+            self.abbrev(sha)
+        } else {
+            Ok(sha.to_string())
+        }
+    }
+
+    /// Returns what is shown of `walked` after its header line.
+    fn message(&self, walked: &WalkedCommit) -> GitResult<String> {
+        let format = match &self.options.format {
+            PrettyFormat::Format { format, .. } => return self.expand(walked, format),
+            PrettyFormat::Oneline => return Ok(walked.commit().summary().trim_end().to_string()),
+            format => format,
+        };
+
+        let commit = walked.commit();
+        let mut out = String::new();
+        if walked.parents().len() > 1 {
+            out.push_str("Merge:");
+            for parent in walked.parents() {
+                out.push(' ');
+                out.push_str(&self.abbrev(parent)?);
+            }
+            out.push('\n');
+        }
+        let date = |signature: &GitSignature| {
+            utils::format_date(signature.time(), signature.offset(), &self.options.date)
+        };
+        let user =
+            |signature: &GitSignature| format!("{} <{}>", signature.name(), signature.email());
+        let (author, committer) = (commit.author(), commit.committer());
+        match format {
+            PrettyFormat::Short => out.push_str(&format!("Author: {}\n", user(author))),
+            PrettyFormat::Medium => out.push_str(&format!(
+                "Author: {}\nDate:   {}\n",
+                user(author),
+                date(author)
+            )),
+            PrettyFormat::Full => out.push_str(&format!(
+                "Author: {}\nCommit: {}\n",
+                user(author),
+                user(committer)
+            )),
+            _ => out.push_str(&format!(
+                "Author:     {}\nAuthorDate: {}\nCommit:     {}\nCommitDate: {}\n",
+                user(author),
+                date(author),
+                user(committer),
+                date(committer)
+            )),
+        }
+        out.push('\n');
+
+        // Leading blank lines are skipped, and the short format stops after the subject.
+        let mut first = true;
+        for line in commit.message().lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                if first {
+                    continue;
+                }
+                if *format == PrettyFormat::Short {
+                    break;
+                }
+            }
+            first = false;
+            out.push_str("    ");
+            out.push_str(&expand_tabs(line));
+            out.push('\n');
+        }
+        let len = out.trim_end().len();
+        out.truncate(len);
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// Expands the placeholders in `format` for the commit `walked`.
+    fn expand(&self, walked: &WalkedCommit, format: &str) -> GitResult<String> {
+        let mut out = String::new();
+        let mut rest = format;
+        while let Some(percent) = rest.find('%') {
+            out.push_str(&rest[..percent]);
+            rest = &rest[percent + 1..];
+
+            // `%+x` adds a newline before a non-empty expansion, `% x` a space, and `%-x`
+            // removes the newlines before an empty one.
+            let magic = rest
+                .chars()
+                .next()
+                .filter(|ch| matches!(ch, '+' | '-' | ' '));
+            let spec = if magic.is_some() { &rest[1..] } else { rest };
+            let start = out.len();
+            let consumed = self.placeholder(walked, spec, &mut out)?;
+            if consumed == 0 {
+                out.push('%');
+                continue;
+            }
+            match magic {
+                Some('-') if out.len() == start => {
+                    let len = out.trim_end_matches('\n').len();
+                    out.truncate(len);
+                }
+                Some('+') if out.len() > start => out.insert(start, '\n'),
+                Some(' ') if out.len() > start => out.insert(start, ' '),
+                _ => {}
+            }
+            rest = &spec[consumed..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Appends the expansion of the placeholder at the start of `spec`, returning its length,
+    /// or 0 if it is unknown.
+    fn placeholder(&self, walked: &WalkedCommit, spec: &str, out: &mut String) -> GitResult<usize> {
+        let commit = walked.commit();
+        let mut chars = spec.chars();
+        let first = match chars.next() {
+            Some(first) => first,
+            None => return Ok(0),
+        };
+        match first {
+            'H' => out.push_str(walked.sha()),
+            'h' => out.push_str(&self.abbrev(walked.sha())?),
+            'T' => out.push_str(commit.tree()),
+            't' => out.push_str(&self.abbrev(commit.tree())?),
+            'P' => out.push_str(&walked.parents().join(" ")),
+            'p' => {
+                let parents = walked
+                    .parents()
+                    .iter()
+                    .map(|parent| self.abbrev(parent))
+                    .collect::<GitResult<Vec<_>>>()?;
+                out.push_str(&parents.join(" "));
+            }
+            'a' | 'c' => {
+                let signature = if first == 'a' {
+                    commit.author()
+                } else {
+                    commit.committer()
+                };
+                let kind = chars.next().unwrap_or('\0');
+                match person(signature, kind, &self.options.date) {
+                    Some(value) => out.push_str(&value),
+                    None => return Ok(0),
+                }
+                return Ok(2);
+            }
+            'd' => out.push_str(&self.decorations(walked.sha(), " (", ")")),
+            'D' => out.push_str(&self.decorations(walked.sha(), "", "")),
+            's' => out.push_str(commit.summary().trim_end()),
+            'f' => out.push_str(&sanitize_subject(&commit.summary())),
+            'b' => out.push_str(commit.body()),
+            'B' => out.push_str(commit.message()),
+            'e' => out.push_str(commit.encoding().unwrap_or("")),
+            'N' => {}
+            'm' => out.push('>'),
+            'n' => out.push('\n'),
+            '%' => out.push('%'),
+            'x' => {
+                let byte = spec
+                    .get(1..3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match byte {
+                    Some(byte) => out.push(byte as char),
+                    None => return Ok(0),
+                }
+                return Ok(3);
+            }
+            'C' => return color(spec, out),
+            _ => return Ok(0),
+        }
+        Ok(1)
+    }
+}
+
+/// Expands the placeholder `%a<kind>` or `%c<kind>` for `signature`.
+fn person(signature: &GitSignature, kind: char, date: &DateFormat) -> Option<String> {
+    let (time, offset) = (signature.time(), signature.offset());
+    let value = match kind {
+        'n' | 'N' => signature.name().to_string(),
+        'e' | 'E' => signature.email().to_string(),
+        'l' | 'L' => {
+            let email = signature.email();
+            email[..email.find('@').unwrap_or(email.len())].to_string()
+        }
+        'd' => utils::format_date(time, offset, date),
+        'D' => utils::format_date(time, offset, &DateFormat::Rfc),
+        'r' => utils::format_date(time, offset, &DateFormat::Relative),
+        't' => utils::format_date(time, offset, &DateFormat::Unix),
+        'i' => utils::format_date(time, offset, &DateFormat::Iso),
+        'I' => utils::format_date(time, offset, &DateFormat::IsoStrict),
+        's' => utils::format_date(time, offset, &DateFormat::Short),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Expands `%C(<color>)`, `%Cred`, `%Cgreen`, `%Cblue` or `%Creset` at the start of `spec`.
+///
+/// Colors are only shown when asked for with `%C(always,<color>)`.
+fn color(spec: &str, out: &mut String) -> GitResult<usize> {
+    if let Some(inner) = spec.strip_prefix("C(") {
+        let close = match inner.find(')') {
+            Some(close) => close,
+            None => return Ok(0),
+        };
+        if let Some(value) = inner[..close].strip_prefix("always,") {
+            match utils::parse_color(value) {
+                Some(color) => out.push_str(&color),
+                None => {
+                    return Err(GitError::LogError {
+                        message: "unable to parse --pretty format".to_string(),
+                    })
+                }
+            }
+        }
+        return Ok(close + 3);
+    }
+    Ok(["red", "green", "blue", "reset"]
+        .iter()
+        .find(|name| spec[1..].starts_with(*name))
+        .map_or(1, |name| name.len() + 1))
+}
+
+/// Turns a subject into something usable as a file name, like `%f`.
+fn sanitize_subject(subject: &str) -> String {
+    let mut out = String::new();
+    let mut space = false;
+    let mut chars = subject.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' {
+            if space && !out.is_empty() {
+                out.push('-');
+            }
+            space = false;
+            out.push(ch);
+            // Runs of dots are collapsed, so that the name never contains `..`.
+            while ch == '.' && chars.peek() == Some(&'.') {
+                chars.next();
+            }
+        } else {
+            space = true;
+        }
+    }
+    out.trim_end_matches(['.', '-']).to_string()
+}
+
+/// Expands tabs to the next multiple of 8 columns.
+fn expand_tabs(line: &str) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut out = String::new();
+    let mut width = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = 8 - width % 8;
+            out.push_str(&" ".repeat(spaces));
+            width += spaces;
+        } else {
+            out.push(ch);
+            width += 1;
+        }
+    }
+    out
+}
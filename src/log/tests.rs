@@ -0,0 +1,152 @@
+use super::*;
+use crate::revision::{RevSort, RevWalk, WalkOptions};
+use crate::test_utils::{self, tree};
+use crate::GitFileMode;
+use std::error::Error;
+use std::fs;
+
+fn commit(odb: &GitOdb, message: &str, time: i64, parents: &[&str]) -> GitResult<String> {
+    let tree = tree(odb, &[("file.txt", GitFileMode::regular(), message)])?;
+    let author = GitSignature::new("A U Thor", "author@example.com", time, 120);
+    let committer = GitSignature::new("C O Mitter", "committer@example.com", time, 120);
+    test_utils::commit(odb, &tree, parents, author, committer, message)
+}
+
+fn log(
+    odb: &GitOdb,
+    refs: &GitRefs,
+    walk_options: WalkOptions,
+    options: &LogOptions,
+) -> Result<String, Box<dyn Error>> {
+    let mut walk = RevWalk::new(odb, walk_options);
+    walk.push(&refs.resolve("HEAD")?.unwrap());
+    let mut out = Vec::new();
+    write_log(odb, refs, &walk.walk()?, options, &mut out)?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[test]
+fn test_pretty_formats() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_pretty_formats")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first\n", 1_700_000_000, &[])?;
+    let second = commit(
+        &odb,
+        "second\n\nbody\n\tindented\n",
+        1_700_003_600,
+        &[&first],
+    )?;
+    refs.update_head(&second, "commit")?;
+    refs.update("refs/tags/v1", &first, "tag")?;
+
+    let options = LogOptions {
+        format: PrettyFormat::Oneline,
+        abbrev_commit: true,
+        decorate: Decorate::Short,
+        ..LogOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, WalkOptions::default(), &options)?,
+        format!(
+            "{} (HEAD -> main) second\n{} (tag: v1) first\n",
+            &second[..7],
+            &first[..7]
+        )
+    );
+
+    let options = LogOptions {
+        decorate: Decorate::Full,
+        ..LogOptions::default()
+    };
+    let limited = WalkOptions {
+        max_count: Some(1),
+        ..WalkOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, limited.clone(), &options)?,
+        format!(
+            "commit {} (HEAD -> refs/heads/main)\nAuthor: A U Thor <author@example.com>\n\
+             Date:   Wed Nov 15 01:13:20 2023 +0200\n\n    second\n    \n    body\n            indented\n",
+            second
+        )
+    );
+
+    let options = LogOptions {
+        format: "%h|%an|%ae|%cn|%s|%b|%D|%ad%n".parse()?,
+        date: DateFormat::Short,
+        ..LogOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, limited, &options)?,
+        format!(
+            "{}|A U Thor|author@example.com|C O Mitter|second|body\n\tindented\n|HEAD -> main|2023-11-15\n\n",
+            &second[..7]
+        )
+    );
+
+    let options = LogOptions {
+        format: "format:%s%+b%-d".parse()?,
+        ..LogOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, WalkOptions::default(), &options)?,
+        "second\nbody\n\tindented\n (HEAD -> main)\nfirst (tag: v1)"
+    );
+    assert!("bogus".parse::<PrettyFormat>().is_err());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_graph() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_graph")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first\n", 1_700_000_000, &[])?;
+    let second = commit(&odb, "second\n", 1_700_000_100, &[&first])?;
+    let side = commit(&odb, "side\n", 1_700_000_200, &[&first])?;
+    let merge = commit(&odb, "merge\n", 1_700_000_300, &[&second, &side])?;
+    refs.update_head(&merge, "commit")?;
+
+    let walk_options = WalkOptions {
+        sort: RevSort::Topo,
+        rewrite_parents: true,
+        ..WalkOptions::default()
+    };
+    let options = LogOptions {
+        format: "%s".parse()?,
+        graph: true,
+        ..LogOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, walk_options.clone(), &options)?,
+        "*   merge\n|\\  \n| * side\n* | second\n|/  \n* first\n"
+    );
+
+    let options = LogOptions {
+        format: PrettyFormat::Short,
+        abbrev_commit: true,
+        graph: true,
+        ..LogOptions::default()
+    };
+    assert_eq!(
+        log(&odb, &refs, walk_options, &options)?,
+        format!(
+            "*   commit {}\n|\\  Merge: {} {}\n| | Author: A U Thor <author@example.com>\n| | \n\
+             | |     merge\n| | \n| * commit {}\n| | Author: A U Thor <author@example.com>\n\
+             | | \n| |     side\n| | \n* | commit {}\n|/  Author: A U Thor <author@example.com>\n\
+             |   \n|       second\n| \n* commit {}\n  Author: A U Thor <author@example.com>\n  \n      first\n",
+            &merge[..7],
+            &second[..7],
+            &side[..7],
+            &side[..7],
+            &second[..7],
+            &first[..7]
+        )
+    );
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
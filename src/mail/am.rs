@@ -0,0 +1,464 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::parse::{parse_mail, MailInfo};
+use crate::utils;
+use crate::{
+    self as gitrs, parse_patch, ApplyOptions, GitApply, GitCheckout, GitCommit, GitDiff, GitError,
+    GitIndex, GitOdb, GitRefs, GitRepo, GitResult, GitSignature, GitTree, Pathspec,
+};
+
+/// How the patches of mails are committed, like the options of `git am`.
+#[derive(Debug, Clone, Default)]
+pub struct AmOptions {
+    /// Merge patches that do not apply with the blobs they were made from, leaving conflicts
+    /// to resolve, like `--3way`.
+    pub three_way: bool,
+    /// Add a `Signed-off-by` trailer for the committer to the messages, like `--signoff`.
+    pub signoff: bool,
+    /// Do not report each patch as it is applied, like `--quiet`.
+    pub quiet: bool,
+}
+
+/// Something to report while working through a series, in the order `git am` prints it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmMessage {
+    /// The patch with this title is about to be applied. Left out with [AmOptions::quiet].
+    Applying(String),
+    /// A line of progress or an error from applying the current patch.
+    Apply(String),
+}
+
+/// Why a series stopped at a patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmStop {
+    /// The mail has no patch.
+    EmptyPatch,
+    /// The patch, the `number`th of the series, does not apply or has conflicts.
+    PatchFailed { number: usize, title: String },
+    /// The index has conflicts left when resuming.
+    Unmerged,
+    /// The index does not change `HEAD` when resuming.
+    NoChanges,
+}
+
+/// What a step of a series did.
+#[derive(Debug, Clone, Default)]
+pub struct AmReport {
+    /// The patches applied and what applying them reported, in order.
+    pub messages: Vec<AmMessage>,
+    /// Why the series stopped, or [None] if every patch is committed.
+    pub stopped: Option<AmStop>,
+}
+
+/// Commits the patches of a series of mails on top of `HEAD`, like `git am`.
+///
+/// The mails and the progress through them are kept in `.git/rebase-apply`. When a patch does
+/// not apply the series stops, to be resumed once the patch is applied by hand, continued with
+/// the next patch or aborted. The progress is returned in an [AmReport].
+#[derive(Debug)]
+pub struct GitAm {
+    repo: GitRepo,
+    dir: PathBuf,
+}
+
+impl GitAm {
+    /// Prepares to apply mails in `repo`.
+    pub fn new(repo: &GitRepo) -> Self {
+        Self {
+            repo: repo.clone(),
+            dir: repo.gitpath().join("rebase-apply"),
+        }
+    }
+
+    /// Returns true if a series was started and is neither done nor aborted.
+    pub fn in_progress(&self) -> bool {
+        self.dir.is_dir()
+    }
+
+    /// Starts committing the patches of `mails`, as split by [super::split_mbox], in order.
+    ///
+    /// If a patch is empty or does not apply, the series stops and waits for
+    /// [GitAm::resolve], [GitAm::skip] or [GitAm::abort].
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::MailError]: A series is in progress already, the index does not match
+    ///   `HEAD` or a mail has no author email
+    /// * [GitError::IdentityError]: The committer is unknown
+    /// * Errors obtained when reading or writing objects, refs, the index or the worktree
+    pub fn start(&self, mails: &[Vec<u8>], options: &AmOptions) -> GitResult<AmReport> {
+        if self.in_progress() {
+            return Err(mail_error(format!(
+                "fatal: previous rebase directory {} still exists but mbox given.",
+                self.dir.display()
+            )));
+        }
+        let refs = GitRefs::new(&self.repo);
+        let head = refs.resolve("HEAD")?;
+        self.check_index(head.as_deref())?;
+
+        utils::create_dir_all_if_new(&self.dir)?;
+        for (i, mail) in mails.iter().enumerate() {
+            self.write(&format!("{:04}", i + 1), mail)?;
+        }
+        self.write("next", b"1\n")?;
+        self.write("last", format!("{}\n", mails.len()).as_bytes())?;
+        for (name, value) in [
+            ("threeway", options.three_way),
+            ("sign", options.signoff),
+            ("quiet", options.quiet),
+        ] {
+            self.write(name, if value { b"t\n" } else { b"f\n" })?;
+        }
+        match &head {
+            Some(head) => refs.update("ORIG_HEAD", head, "am")?,
+            None if refs.exists("ORIG_HEAD")? => refs.delete("ORIG_HEAD")?,
+            None => (),
+        }
+        self.write_abort_safety(head.as_deref())?;
+        self.run(AmReport::default())
+    }
+
+    /// Commits the patch the series stopped at, once it is applied and added to the index by
+    /// hand, and goes on with the next ones, like `git am --continue`.
+    ///
+    /// The series stops again if the index still has conflicts or does not change anything,
+    /// or if a later patch does not apply.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::MailError]: No series is in progress
+    /// * Errors obtained as for [GitAm::start]
+    pub fn resolve(&self) -> GitResult<AmReport> {
+        self.check_in_progress()?;
+        let message = self.read_string("final-commit")?;
+        let mut report = AmReport::default();
+        if !self.read_flag("quiet")? {
+            let title = title(&message).to_string();
+            report.messages.push(AmMessage::Applying(title));
+        }
+        let index = GitIndex::read(&self.repo)?;
+        if index.has_conflicts() {
+            report.stopped = Some(AmStop::Unmerged);
+            return Ok(report);
+        }
+        let odb = GitOdb::open(&self.repo)?;
+        let head_tree = match GitRefs::new(&self.repo).resolve("HEAD")? {
+            Some(head) => Some(odb.read_as::<GitCommit>(&head)?.tree().to_string()),
+            None => None,
+        };
+        let tree = index.write_tree(&odb)?;
+        if Some(&tree) == head_tree.as_ref() {
+            report.stopped = Some(AmStop::NoChanges);
+            return Ok(report);
+        }
+        self.commit(&tree, &message)?;
+        self.advance()?;
+        self.run(report)
+    }
+
+    /// Drops the patch the series stopped at, resetting the index and the worktree to `HEAD`,
+    /// and goes on with the next ones, like `git am --skip`.
+    ///
+    /// The series stops again if a later patch does not apply.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::MailError]: No series is in progress
+    /// * Errors obtained as for [GitAm::start]
+    pub fn skip(&self) -> GitResult<AmReport> {
+        self.check_in_progress()?;
+        let head = GitRefs::new(&self.repo).resolve("HEAD")?;
+        self.reset(head.as_deref())?;
+        self.advance()?;
+        self.run(AmReport::default())
+    }
+
+    /// Stops the series and moves back to the commit it started from, discarding the changes
+    /// in the index and the worktree, like `git am --abort`.
+    ///
+    /// If `HEAD` was moved since the series stopped, it is left alone and false is returned.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::MailError]: No series is in progress
+    /// * Errors obtained when reading objects or updating refs, the index or the worktree
+    pub fn abort(&self) -> GitResult<bool> {
+        self.check_in_progress()?;
+        let refs = GitRefs::new(&self.repo);
+        let head = refs.resolve("HEAD")?;
+        let safety = self.read_string("abort-safety")?;
+        let rewind = head.as_deref().unwrap_or("") == safety.trim();
+        if rewind {
+            let orig_head = refs.resolve("ORIG_HEAD")?;
+            self.reset(orig_head.as_deref())?;
+            match orig_head {
+                Some(orig_head) => refs.update_head(&orig_head, "am --abort")?,
+                None => {
+                    if let Some(branch) = refs.head_branch()? {
+                        if refs.exists(&branch)? {
+                            refs.delete(&branch)?;
+                        }
+                    }
+                }
+            }
+        }
+        gitrs::to_git_result(fs::remove_dir_all(&self.dir), &self.dir)?;
+        Ok(rewind)
+    }
+
+    /// Returns the patch the series stopped at: the whole mail, or only its diff with `diff`,
+    /// like `git am --show-current-patch`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::MailError]: No series is in progress
+    /// * [GitError::IOError]: The patch can not be read
+    pub fn current_patch(&self, diff: bool) -> GitResult<Vec<u8>> {
+        self.check_in_progress()?;
+        if diff {
+            return self.read("patch");
+        }
+        let next = self.read_number("next")?;
+        self.read(&format!("{:04}", next))
+    }
+
+    /// Applies and commits the patches from the next one on, until one of them does not apply,
+    /// adding to `report`. The directory of the series is removed once all are committed.
+    fn run(&self, mut report: AmReport) -> GitResult<AmReport> {
+        let last = self.read_number("last")?;
+        let three_way = self.read_flag("threeway")?;
+        let signoff = self.read_flag("sign")?;
+        let quiet = self.read_flag("quiet")?;
+        loop {
+            let next = self.read_number("next")?;
+            if next > last {
+                break;
+            }
+            let msgnum = format!("{:04}", next);
+            let info = parse_mail(&self.read(&msgnum)?);
+            let mut message = info.message();
+            if signoff {
+                let signer = GitSignature::from_env(&self.repo, "COMMITTER")?;
+                message = super::append_signoff(&message, &signer);
+            }
+            self.write_patch(&info, &message)?;
+            if info.author_email.is_empty() {
+                return Err(mail_error(
+                    "fatal: Patch does not have a valid e-mail address.".to_string(),
+                ));
+            }
+            if info.patch.is_empty() {
+                report.stopped = Some(AmStop::EmptyPatch);
+                return Ok(report);
+            }
+
+            let title = title(&message).to_string();
+            if !quiet {
+                report.messages.push(AmMessage::Applying(title.clone()));
+            }
+            if !self.apply(&info.patch, three_way, &mut report.messages)? {
+                report.stopped = Some(AmStop::PatchFailed {
+                    number: next,
+                    title,
+                });
+                return Ok(report);
+            }
+            let tree = GitIndex::read(&self.repo)?.write_tree(&GitOdb::open(&self.repo)?)?;
+            self.commit(&tree, &message)?;
+            self.advance()?;
+        }
+        gitrs::to_git_result(fs::remove_dir_all(&self.dir), &self.dir)?;
+        Ok(report)
+    }
+
+    /// Applies `patch` to the index and the worktree, adding what happened to `messages`.
+    /// Returns false if it does not apply, or applies with conflicts.
+    fn apply(
+        &self,
+        patch: &[u8],
+        three_way: bool,
+        messages: &mut Vec<AmMessage>,
+    ) -> GitResult<bool> {
+        let options = ApplyOptions {
+            index: true,
+            three_way,
+            ..ApplyOptions::default()
+        };
+        let result = parse_patch(patch, 1)
+            .and_then(|patches| GitApply::new(&self.repo, options)?.apply(&patches));
+        match result {
+            Ok(report) => {
+                let clean = report.is_clean();
+                messages.extend(report.messages.into_iter().map(AmMessage::Apply));
+                let conflicts = report.conflicts.iter();
+                messages.extend(conflicts.map(|path| AmMessage::Apply(format!("U {}", path))));
+                Ok(clean)
+            }
+            Err(GitError::ApplyError { message }) => {
+                messages.push(AmMessage::Apply(message));
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Commits `tree` on top of `HEAD` with `message` and the author of the current patch.
+    fn commit(&self, tree: &str, message: &str) -> GitResult<()> {
+        let (name, email, date) = self.read_author_script()?;
+        let (time, offset) = date
+            .as_deref()
+            .and_then(utils::parse_date)
+            .unwrap_or((utils::now(), 0));
+        let author = GitSignature::new(&name, &email, time, offset);
+        let committer = GitSignature::from_env(&self.repo, "COMMITTER")?;
+
+        let refs = GitRefs::new(&self.repo);
+        let parents: Vec<String> = refs.resolve("HEAD")?.into_iter().collect();
+        let commit = GitCommit::new(tree, &parents, author, committer, message);
+        let sha = GitOdb::open(&self.repo)?.write_object(&commit)?;
+        refs.update_head(&sha, &format!("am: {}", title(message)))?;
+        self.write_abort_safety(Some(&sha))
+    }
+
+    /// Resets the index and the worktree to the tree of `commit`, or to an empty tree.
+    fn reset(&self, commit: Option<&str>) -> GitResult<()> {
+        let mut checkout = GitCheckout::new(&self.repo)?;
+        let tree = match commit {
+            Some(commit) => checkout
+                .odb()
+                .read_as::<GitCommit>(commit)?
+                .tree()
+                .to_string(),
+            None => checkout.odb().write_object(&GitTree::new(Vec::new()))?,
+        };
+        checkout.restore(Some(&tree), &Pathspec::default(), true, true)?;
+        checkout.write_index()
+    }
+
+    /// Refuses to start while the index has changes from `head`, which would be committed
+    /// with the first patch.
+    fn check_index(&self, head: Option<&str>) -> GitResult<()> {
+        let mut diff = GitDiff::new(&self.repo)?;
+        let tree = match head {
+            Some(head) => Some(diff.odb().read_as::<GitCommit>(head)?.tree().to_string()),
+            None => None,
+        };
+        let changes = diff.diff_index(tree.as_deref(), true, &Pathspec::default())?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut paths: Vec<&str> = changes.iter().map(|change| change.path()).collect();
+        paths.dedup();
+        Err(mail_error(format!(
+            "fatal: Dirty index: cannot apply patches (dirty: {})",
+            paths.join(" ")
+        )))
+    }
+
+    fn check_in_progress(&self) -> GitResult<()> {
+        if self.in_progress() {
+            Ok(())
+        } else {
+            Err(mail_error(
+                "fatal: Resolve operation not in progress, we are not resuming.".to_string(),
+            ))
+        }
+    }
+
+    /// Moves on to the next patch.
+    fn advance(&self) -> GitResult<()> {
+        let next = self.read_number("next")?;
+        self.write("next", format!("{}\n", next + 1).as_bytes())
+    }
+
+    /// Records the commit `HEAD` is at, or [None] if unborn, after the last step of the series.
+    /// Aborting only moves `HEAD` back if it still is there.
+    fn write_abort_safety(&self, sha: Option<&str>) -> GitResult<()> {
+        let contents = sha.map_or(String::new(), |sha| format!("{}\n", sha));
+        self.write("abort-safety", contents.as_bytes())
+    }
+
+    /// Writes the parts of the current mail like git: the patch, the message in
+    /// `final-commit` and the author in `author-script`, which is a shell script setting
+    /// `GIT_AUTHOR_NAME`, `GIT_AUTHOR_EMAIL` and `GIT_AUTHOR_DATE`.
+    fn write_patch(&self, info: &MailInfo, message: &str) -> GitResult<()> {
+        self.write("patch", &info.patch)?;
+        self.write("final-commit", message.as_bytes())?;
+        let script = format!(
+            "GIT_AUTHOR_NAME={}\nGIT_AUTHOR_EMAIL={}\nGIT_AUTHOR_DATE={}\n",
+            shell_quote(&info.author_name),
+            shell_quote(&info.author_email),
+            shell_quote(info.date.as_deref().unwrap_or(""))
+        );
+        self.write("author-script", script.as_bytes())
+    }
+
+    /// Reads the name, email and date of the author of the current patch from
+    /// `author-script`.
+    fn read_author_script(&self) -> GitResult<(String, String, Option<String>)> {
+        let script = self.read_string("author-script")?;
+        let value = |name: &str| {
+            script
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+                .map(shell_unquote)
+                .unwrap_or_default()
+        };
+        let date = Some(value("GIT_AUTHOR_DATE")).filter(|date| !date.is_empty());
+        Ok((value("GIT_AUTHOR_NAME"), value("GIT_AUTHOR_EMAIL"), date))
+    }
+
+    fn read_flag(&self, name: &str) -> GitResult<bool> {
+        Ok(self.read_string(name)?.trim() == "t")
+    }
+
+    fn read_number(&self, name: &str) -> GitResult<usize> {
+        let value = self.read_string(name)?;
+        value.trim().parse().map_err(|_| {
+            mail_error(format!(
+                "fatal: invalid number '{}' in {}",
+                value.trim(),
+                self.dir.join(name).display()
+            ))
+        })
+    }
+
+    fn read_string(&self, name: &str) -> GitResult<String> {
+        Ok(String::from_utf8_lossy(&self.read(name)?).into_owned())
+    }
+
+    fn read(&self, name: &str) -> GitResult<Vec<u8>> {
+        let path = self.dir.join(name);
+        gitrs::to_git_result(fs::read(&path), &path)
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> GitResult<()> {
+        let path = self.dir.join(name);
+        gitrs::to_git_result(fs::write(&path, contents), &path)
+    }
+}
+
+fn mail_error(message: String) -> GitError {
+    GitError::MailError { message }
+}
+
+/// Returns the first line of a commit message.
+fn title(message: &str) -> &str {
+    message.lines().next().unwrap_or("")
+}
+
+/// Quotes `value` for a shell in single quotes, like git's `sq_quote`.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Reverses [shell_quote].
+fn shell_unquote(value: &str) -> String {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .unwrap_or(value)
+        .replace("'\\''", "'")
+}
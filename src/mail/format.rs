@@ -0,0 +1,437 @@
+use std::collections::BTreeMap;
+
+use crate::utils::{self, DateFormat};
+use crate::{
+    DiffFormat, DiffOptions, GitCommit, GitDiff, GitOdb, GitRepo, GitResult, GitSignature,
+    Pathspec, RenameOptions,
+};
+
+/// The date of the `From ` line that starts each mail, which is always the same so that the
+/// line is easy to recognize.
+const MAGIC_DATE: &str = "Mon Sep 17 00:00:00 2001";
+
+/// How wide the lines of headers get before they are folded.
+const MAX_HEADER_WIDTH: usize = 78;
+
+/// How wide encoded words may get, per RFC 2047.
+const MAX_ENCODED_WIDTH: usize = 76;
+
+/// How wide the diffstat and the shortlog of the cover letter get.
+const MAIL_WRAP: usize = 72;
+
+/// How long file names get, counting the `.patch` suffix and one more character.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The file name of the cover letter.
+pub(crate) const COVER_LETTER_NAME: &str = "0000-cover-letter.patch";
+
+/// The headers marking mails whose body is not plain ASCII.
+const MIME_HEADERS: &str = "MIME-Version: 1.0\n\
+                            Content-Type: text/plain; charset=UTF-8\n\
+                            Content-Transfer-Encoding: 8bit\n";
+
+/// How commits are turned into mails, like the options of `git format-patch`.
+#[derive(Debug, Clone)]
+pub struct FormatPatchOptions {
+    /// Number the subjects like `[PATCH 2/3]`: always with `Some(true)`, never with
+    /// `Some(false)`, and otherwise if there are several mails, like `--numbered` and
+    /// `--no-numbered`.
+    pub numbered: Option<bool>,
+    /// Add a mail describing the series, to be filled in before sending it, like
+    /// `--cover-letter`.
+    pub cover_letter: bool,
+    /// Add a `Signed-off-by` trailer for the committer, like `--signoff`.
+    pub signoff: bool,
+    /// What the subjects start with in brackets, like `--subject-prefix`.
+    pub subject_prefix: String,
+    /// The number of the first patch, like `--start-number`.
+    pub start_number: usize,
+    /// How renamed and copied files are found, or [None] to show them as deleted and added.
+    pub renames: Option<RenameOptions>,
+}
+
+impl Default for FormatPatchOptions {
+    fn default() -> Self {
+        Self {
+            numbered: None,
+            cover_letter: false,
+            signoff: false,
+            subject_prefix: "PATCH".to_string(),
+            start_number: 1,
+            renames: Some(RenameOptions::default()),
+        }
+    }
+}
+
+/// A mail made by [format_patches].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchMail {
+    /// The file name for the mail, like `0001-Fix-the-frotz.patch`.
+    pub name: String,
+    /// The mail in the mbox format.
+    pub contents: Vec<u8>,
+}
+
+/// Turns `commits`, oldest first, into mails holding their messages and patches, like
+/// `git format-patch`. The patches have the changes of each commit to its first parent and
+/// include binary files.
+///
+/// # Errors
+///
+/// * [crate::GitError::IdentityError]: The committer is unknown and `signoff` or
+///   `cover_letter` is set
+/// * Errors obtained when reading objects or the config
+pub fn format_patches(
+    repo: &GitRepo,
+    commits: &[String],
+    options: &FormatPatchOptions,
+) -> GitResult<Vec<PatchMail>> {
+    if commits.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut diff = GitDiff::new(repo)?;
+    diff.set_renames(options.renames.clone());
+    let odb = GitOdb::open(repo)?;
+    let commits = commits
+        .iter()
+        .map(|sha| Ok((sha.as_str(), odb.read_as::<GitCommit>(sha)?)))
+        .collect::<GitResult<Vec<_>>>()?;
+    let signer = if options.signoff {
+        Some(GitSignature::from_env(repo, "COMMITTER")?)
+    } else {
+        None
+    };
+
+    let last = options.start_number + commits.len() - 1;
+    let numbered = options
+        .numbered
+        .unwrap_or(commits.len() > 1 || options.cover_letter);
+    let subject_prefix = |number: usize| {
+        let prefix = &options.subject_prefix;
+        if numbered {
+            let separator = if prefix.is_empty() { "" } else { " " };
+            let width = last.to_string().len();
+            format!(
+                "[{}{}{:0width$}/{}] ",
+                prefix,
+                separator,
+                number,
+                last,
+                width = width
+            )
+        } else if !prefix.is_empty() {
+            format!("[{}] ", prefix)
+        } else {
+            String::new()
+        }
+    };
+
+    let mut mails = Vec::new();
+    if options.cover_letter {
+        let committer = GitSignature::from_env(repo, "COMMITTER")?;
+        let contents = cover_letter(&mut diff, &commits, &committer, &subject_prefix(0), &odb)?;
+        mails.push(PatchMail {
+            name: COVER_LETTER_NAME.to_string(),
+            contents,
+        });
+    }
+    for (i, (sha, commit)) in commits.iter().enumerate() {
+        let number = options.start_number + i;
+        let message = match &signer {
+            Some(signer) => super::append_signoff(commit.message(), signer),
+            None => commit.message().to_string(),
+        };
+        let (subject, body) = split_message(&message);
+
+        let mut mail = format!("From {} {}\n", sha, MAGIC_DATE);
+        write_headers(
+            &mut mail,
+            commit.author(),
+            &subject_prefix(number),
+            &subject,
+        );
+        if !body.is_ascii() || !subject.is_ascii() {
+            mail.push_str(MIME_HEADERS);
+        }
+        mail.push('\n');
+        for line in body.lines() {
+            mail.push_str(line);
+            mail.push('\n');
+        }
+        mail.push_str("---\n");
+        let mut contents = mail.into_bytes();
+
+        let parent = match commit.parents().first() {
+            Some(parent) => Some(odb.read_as::<GitCommit>(parent)?.tree().to_string()),
+            None => None,
+        };
+        let changes =
+            diff.diff_trees(parent.as_deref(), Some(commit.tree()), &Pathspec::default())?;
+        let format = DiffFormat {
+            patch: true,
+            stat: true,
+            summary: true,
+            binary: true,
+            stat_width: Some(MAIL_WRAP),
+            ..DiffFormat::default()
+        };
+        diff.write(&changes, &DiffOptions::default(), &format, &mut contents)?;
+        contents.extend(signature().as_bytes());
+        mails.push(PatchMail {
+            name: file_name(number, &subject),
+            contents,
+        });
+    }
+    Ok(mails)
+}
+
+/// Returns the cover letter for `commits`: a template for the description of the series,
+/// followed by their subjects by author and the diffstat of all of them.
+fn cover_letter(
+    diff: &mut GitDiff,
+    commits: &[(&str, GitCommit)],
+    committer: &GitSignature,
+    subject_prefix: &str,
+    odb: &GitOdb,
+) -> GitResult<Vec<u8>> {
+    let (last_sha, last) = &commits[commits.len() - 1];
+    let mut mail = format!("From {} {}\n", last_sha, MAGIC_DATE);
+    write_headers(&mut mail, committer, subject_prefix, "*** SUBJECT HERE ***");
+    let non_ascii = commits.iter().any(|(_, commit)| {
+        !commit.message().is_ascii()
+            || !commit.author().to_string().is_ascii()
+            || !commit.committer().to_string().is_ascii()
+    });
+    if non_ascii {
+        mail.push_str(MIME_HEADERS);
+    }
+    mail.push_str("\n*** BLURB HERE ***\n\n");
+
+    let mut authors: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (_, commit) in commits {
+        authors
+            .entry(commit.author().name())
+            .or_default()
+            .push(commit.summary());
+    }
+    for (author, subjects) in authors {
+        mail.push_str(&format!("{} ({}):\n", author, subjects.len()));
+        for subject in subjects {
+            mail.push_str(&wrap_text(&subject, 2, 4, MAIL_WRAP));
+            mail.push('\n');
+        }
+        mail.push('\n');
+    }
+    let mut contents = mail.into_bytes();
+
+    let first = &commits[0].1;
+    let base = match first.parents().first() {
+        Some(parent) => Some(odb.read_as::<GitCommit>(parent)?.tree().to_string()),
+        None => None,
+    };
+    let changes = diff.diff_trees(base.as_deref(), Some(last.tree()), &Pathspec::default())?;
+    let format = DiffFormat {
+        stat: true,
+        summary: true,
+        stat_width: Some(MAIL_WRAP),
+        ..DiffFormat::default()
+    };
+    diff.write(&changes, &DiffOptions::default(), &format, &mut contents)?;
+    contents.push(b'\n');
+    contents.extend(signature().as_bytes());
+    Ok(contents)
+}
+
+/// Writes the `From`, `Date` and `Subject` headers, encoding and folding them like git.
+fn write_headers(mail: &mut String, person: &GitSignature, subject_prefix: &str, subject: &str) {
+    mail.push_str("From: ");
+    let name = person.name();
+    let mut max_width = MAX_HEADER_WIDTH;
+    if needs_rfc2047(name) {
+        add_rfc2047(mail, name, true);
+        max_width = MAX_ENCODED_WIDTH;
+    } else if name.contains(|ch| "()<>[]:;@,.\"\\".contains(ch)) {
+        let quoted = format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        mail.push_str(&wrap_text(&quoted, -6, 1, max_width));
+    } else {
+        mail.push_str(&wrap_text(name, -6, 1, max_width));
+    }
+    if max_width < last_line_width(mail) + person.email().len() + 3 {
+        mail.push('\n');
+    }
+    mail.push_str(&format!(" <{}>\n", person.email()));
+    mail.push_str(&format!(
+        "Date: {}\n",
+        utils::format_date(person.time(), person.offset(), &DateFormat::Rfc)
+    ));
+
+    mail.push_str("Subject: ");
+    mail.push_str(subject_prefix);
+    if needs_rfc2047(subject) {
+        add_rfc2047(mail, subject, false);
+    } else {
+        let indent = last_line_width(mail) as isize;
+        mail.push_str(&wrap_text(subject, -indent, 1, MAX_HEADER_WIDTH));
+    }
+    mail.push('\n');
+}
+
+/// Returns the subject of `message`, its first paragraph joined into one line, and the rest.
+fn split_message(message: &str) -> (String, &str) {
+    let message = message.trim_start_matches('\n');
+    let (subject, body) = match message.find("\n\n") {
+        Some(i) => (&message[..i], message[i..].trim_start_matches('\n')),
+        None => (message, ""),
+    };
+    let subject = subject.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+    (subject, body)
+}
+
+/// Returns the file name of the mail for a commit: its number and the letters, digits,
+/// dots and underscores of its subject, with dashes for everything else.
+fn file_name(number: usize, subject: &str) -> String {
+    let mut name = format!("{:04}-", number);
+    let mut separate = false;
+    let mut last = '\0';
+    for ch in subject.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' {
+            if ch == '.' && last == '.' {
+                continue;
+            }
+            if separate && name.len() > 5 {
+                name.push('-');
+            }
+            separate = false;
+            name.push(ch);
+        } else {
+            separate = true;
+        }
+        last = ch;
+    }
+    let trimmed = name.trim_end_matches(['.', '-']).len();
+    name.truncate(trimmed.max(5));
+    name.truncate(MAX_NAME_LENGTH - ".patch".len() - 1);
+    name.push_str(".patch");
+    name
+}
+
+/// The signature ending each mail, with the version of the program that wrote it.
+fn signature() -> String {
+    format!("-- \n{}\n\n", env!("CARGO_PKG_VERSION"))
+}
+
+/// Returns the number of characters after the last newline of `text`.
+fn last_line_width(text: &str) -> usize {
+    let start = text.rfind('\n').map_or(0, |i| i + 1);
+    text[start..].chars().count()
+}
+
+/// Returns true if `text` must be written as encoded words in a header.
+fn needs_rfc2047(text: &str) -> bool {
+    text.bytes()
+        .any(|b| !b.is_ascii() || b == b'\n' || b == 0x1b)
+        || text.contains("=?")
+}
+
+/// Writes `text` as quoted-printable encoded words of UTF-8, folding the header line before
+/// an encoded word would get too wide. Address headers encode more characters.
+fn add_rfc2047(out: &mut String, text: &str, address: bool) {
+    const START: &str = "=?UTF-8?q?";
+    let mut width = last_line_width(out) + START.len();
+    out.push_str(START);
+    for ch in text.chars() {
+        let mut buf = [0; 4];
+        let bytes = ch.encode_utf8(&mut buf).as_bytes();
+        let special = bytes.len() > 1 || {
+            let b = bytes[0];
+            !b.is_ascii_graphic()
+                || b == b'='
+                || b == b'?'
+                || b == b'_'
+                || (address && !b.is_ascii_alphanumeric() && !b"!*+-/".contains(&b))
+        };
+        let encoded = if special {
+            bytes.iter().map(|b| format!("={:02X}", b)).collect()
+        } else {
+            ch.to_string()
+        };
+        if width + encoded.len() + 2 > MAX_ENCODED_WIDTH {
+            out.push_str("?=\n ");
+            out.push_str(START);
+            width = START.len() + 1;
+        }
+        width += encoded.len();
+        out.push_str(&encoded);
+    }
+    out.push_str("?=");
+}
+
+/// Wraps the words of `text` into lines of at most `width` characters like git does. The
+/// first line is indented by `indent1`, or continues a line of that many characters if it is
+/// negative, and the others are indented by `indent2`. A newline followed by a letter or
+/// digit is taken as a space, and other newlines are kept.
+pub(super) fn wrap_text(text: &str, indent1: isize, indent2: usize, width: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut indent = indent1.max(0) as usize;
+    let mut line_width = indent1.unsigned_abs();
+    // Where the current line starts, and the whitespace before the word being measured.
+    let mut bol = 0;
+    let mut space = if indent1 < 0 { Some(0) } else { None };
+    let mut i = 0;
+    loop {
+        let ch = chars.get(i).copied();
+        if ch.is_some_and(|ch| !ch.is_ascii_whitespace()) {
+            line_width += 1;
+            i += 1;
+            continue;
+        }
+        let mut new_line = line_width > width && space.is_some();
+        if !new_line {
+            if ch.is_none() && i == bol {
+                return out;
+            }
+            let start = match space {
+                Some(start) => start,
+                None => {
+                    out.extend(std::iter::repeat_n(' ', indent));
+                    bol
+                }
+            };
+            out.extend(&chars[start..i]);
+            let ch = match ch {
+                Some(ch) => ch,
+                None => return out,
+            };
+            space = Some(i);
+            if ch == '\t' {
+                line_width |= 7;
+            } else if ch == '\n' {
+                space = Some(i + 1);
+                match chars.get(i + 1) {
+                    Some('\n') => {
+                        out.push('\n');
+                        new_line = true;
+                    }
+                    Some(next) if next.is_ascii_alphanumeric() => out.push(' '),
+                    _ => new_line = true,
+                }
+            }
+            if !new_line {
+                line_width += 1;
+                i += 1;
+                continue;
+            }
+        }
+        out.push('\n');
+        let start = space.unwrap();
+        i = start
+            + chars
+                .get(start)
+                .map_or(0, |ch| ch.is_ascii_whitespace() as usize);
+        bol = i;
+        space = None;
+        line_width = indent2;
+        indent = indent2;
+    }
+}
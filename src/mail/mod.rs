@@ -0,0 +1,80 @@
+//! Sending commits as patches in mails and committing the patches of mails, like
+//! `git format-patch` and `git am`.
+//!
+//! The mails are plain text in the mbox format, each starting with a `From ` line that holds
+//! the name of the commit.
+
+mod am;
+mod format;
+mod parse;
+#[cfg(test)]
+mod tests;
+
+pub use self::am::{AmMessage, AmOptions, AmReport, AmStop, GitAm};
+pub(crate) use self::format::COVER_LETTER_NAME;
+pub use self::format::{format_patches, FormatPatchOptions, PatchMail};
+pub use self::parse::{parse_mail, split_mbox, MailInfo};
+
+use crate::GitSignature;
+
+/// The trailers git adds itself, which make a paragraph count as trailers even if it has a
+/// few other lines.
+const GIT_TRAILERS: [&str; 2] = ["Signed-off-by: ", "(cherry picked from commit "];
+
+/// Adds a `Signed-off-by` trailer for `signer` to `message`, unless it is its last line
+/// already. The trailer starts a new paragraph unless the message ends with trailers.
+fn append_signoff(message: &str, signer: &GitSignature) -> String {
+    let signoff = format!("Signed-off-by: {} <{}>", signer.name(), signer.email());
+    let mut message = message.trim_end_matches('\n').to_string();
+    if message.lines().last() == Some(signoff.as_str()) {
+        message.push('\n');
+        return message;
+    }
+    if !message.is_empty() && !ends_with_trailers(&message) {
+        message.push('\n');
+    }
+    message.push('\n');
+    message.push_str(&signoff);
+    message.push('\n');
+    message
+}
+
+/// Returns true if the last paragraph of `message`, not counting the subject, is a block of
+/// `Token: value` trailers. Git's own trailers allow up to three other lines for each trailer.
+fn ends_with_trailers(message: &str) -> bool {
+    let lines: Vec<&str> = message.trim_start_matches('\n').lines().collect();
+    let start = match lines.iter().rposition(|line| line.trim().is_empty()) {
+        Some(blank) => blank + 1,
+        None => return false,
+    };
+    let (mut trailers, mut others, mut recognized) = (0, 0, false);
+    for line in &lines[start..] {
+        if line.starts_with([' ', '\t']) {
+            // A continuation of the line before.
+            continue;
+        }
+        if GIT_TRAILERS.iter().any(|prefix| line.starts_with(prefix)) {
+            recognized = true;
+            trailers += 1;
+        } else if is_trailer(line) {
+            trailers += 1;
+        } else {
+            others += 1;
+        }
+    }
+    trailers > 0 && (others == 0 || (recognized && trailers * 3 >= others))
+}
+
+/// Returns true if `line` is a `Token: value` trailer, the token made of letters, digits and
+/// dashes.
+fn is_trailer(line: &str) -> bool {
+    let colon = match line.find(':') {
+        Some(colon) if colon > 0 => colon,
+        _ => return false,
+    };
+    let token = line[..colon].trim_end_matches([' ', '\t']);
+    !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
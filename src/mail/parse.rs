@@ -0,0 +1,449 @@
+use crate::convert;
+
+/// What `git am` needs from a mail: the author and message of the commit, and the patch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MailInfo {
+    pub author_name: String,
+    pub author_email: String,
+    /// The date the mail was sent, like `Thu, 7 Apr 2005 15:13:13 -0700`.
+    pub date: Option<String>,
+    /// The subject without `Re:` and bracketed prefixes like `[PATCH 1/2]`.
+    pub subject: String,
+    /// The message below the subject, up to the patch.
+    pub body: String,
+    /// The patch, from the `---`, `diff -` or `Index: ` line it starts with to the end of
+    /// the mail.
+    pub patch: Vec<u8>,
+}
+
+impl MailInfo {
+    /// Returns the commit message: the subject and the body separated by a blank line, without
+    /// trailing whitespace and repeated blank lines.
+    pub fn message(&self) -> String {
+        let mut message = String::new();
+        let mut blank = false;
+        let text = format!("{}\n\n{}", self.subject, self.body);
+        for line in text.lines().map(str::trim_end) {
+            if line.is_empty() {
+                blank = !message.is_empty();
+                continue;
+            }
+            if blank {
+                message.push('\n');
+                blank = false;
+            }
+            message.push_str(line);
+            message.push('\n');
+        }
+        message
+    }
+
+    /// Takes the value of the `From`, `Subject` or `Date` header.
+    fn set_header(&mut self, name: &str, value: &str) {
+        match name {
+            "from" => {
+                let (name, email) = parse_address(value);
+                self.author_name = name;
+                self.author_email = email;
+            }
+            "subject" => self.subject = clean_subject(value),
+            "date" => self.date = Some(value.to_string()),
+            _ => (),
+        }
+    }
+}
+
+/// Splits a mailbox into its mails, like `git mailsplit`. Each mail starts with a `From ` line
+/// that ends with a date; input not starting with one is a single mail. Lines end with a bare
+/// newline in the mails.
+pub fn split_mbox(data: &[u8]) -> Vec<Vec<u8>> {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let mut lines = data[start..].split_inclusive(|&b| b == b'\n').peekable();
+    let bare = !lines.peek().is_some_and(|line| is_from_line(line));
+
+    let mut mails: Vec<Vec<u8>> = Vec::new();
+    for line in lines {
+        if mails.is_empty() || (!bare && is_from_line(line)) {
+            mails.push(Vec::new());
+        }
+        let mail = mails.last_mut().unwrap();
+        match line.strip_suffix(b"\r\n") {
+            Some(line) => {
+                mail.extend_from_slice(line);
+                mail.push(b'\n');
+            }
+            None => mail.extend_from_slice(line),
+        }
+    }
+    mails
+}
+
+/// Returns true if `line` starts a mail in a mailbox: `From `, the sender and a date.
+fn is_from_line(line: &[u8]) -> bool {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    if line.len() < 19 || !line.starts_with(b"From ") {
+        return false;
+    }
+    let colon = match line[5..line.len() - 1].iter().rposition(|&b| b == b':') {
+        Some(colon) => colon + 5,
+        None => return false,
+    };
+    let digit = |i: usize| line.get(i).is_some_and(u8::is_ascii_digit);
+    if colon < 4
+        || ![colon - 4, colon - 2, colon - 1, colon + 1, colon + 2]
+            .iter()
+            .all(|&i| digit(i))
+    {
+        return false;
+    }
+    // The year follows the time.
+    let year: String = line[colon + 3..]
+        .iter()
+        .map(|&b| b as char)
+        .skip_while(|ch| ch.is_ascii_whitespace())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    year.parse::<u32>().is_ok_and(|year| year > 90)
+}
+
+/// Reads the author, subject, date, message and patch of a mail, like `git mailinfo`.
+///
+/// Headers are decoded from RFC 2047 encoded words, and the body from quoted-printable or
+/// base64 and its charset. `From:`, `Subject:` and `Date:` lines at the start of the body
+/// replace the headers of the mail.
+pub fn parse_mail(mail: &[u8]) -> MailInfo {
+    let mut info = MailInfo::default();
+    let mut lines = mail.split_inclusive(|&b| b == b'\n').peekable();
+
+    let mut charset = None;
+    let mut transfer_encoding = String::new();
+    let mut body = Vec::new();
+    while let Some(line) = lines.next() {
+        if line == b"\n" || !is_header(line) {
+            if line != b"\n" {
+                body.extend_from_slice(line);
+            }
+            break;
+        }
+        let mut header = strip_newline(line).to_vec();
+        while let Some(next) =
+            lines.next_if(|next| next.starts_with(b" ") || next.starts_with(b"\t"))
+        {
+            header.extend_from_slice(strip_newline(next));
+        }
+        let header = String::from_utf8_lossy(&header);
+        let (name, value) = match header.split_once(':') {
+            Some((name, value)) => (name.to_ascii_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "content-type" => charset = header_parameter(value, "charset"),
+            "content-transfer-encoding" => transfer_encoding = value.to_ascii_lowercase(),
+            _ => {
+                info.set_header(&name, &decode_header(value));
+            }
+        }
+    }
+    for line in lines {
+        body.extend_from_slice(line);
+    }
+    let body = match transfer_encoding.as_str() {
+        "quoted-printable" => decode_quoted_printable(&body),
+        "base64" => decode_base64(&body),
+        _ => body,
+    };
+
+    // Lines before the first text may replace the headers, up to a blank line.
+    let mut message = Vec::new();
+    let mut in_body_headers = true;
+    let mut found_headers = false;
+    let mut lines = body.split_inclusive(|&b| b == b'\n');
+    for line in &mut lines {
+        if in_body_headers {
+            if strip_newline(line).is_empty() {
+                in_body_headers = !found_headers;
+                continue;
+            }
+            let text = String::from_utf8_lossy(strip_newline(line));
+            if let Some((name, value)) = text.split_once(':') {
+                let name = name.to_ascii_lowercase();
+                if ["from", "subject", "date"].contains(&name.as_str()) {
+                    info.set_header(&name, &decode_header(value.trim()));
+                    found_headers = true;
+                    continue;
+                }
+            }
+            in_body_headers = false;
+        }
+        if is_patch_break(line) {
+            info.patch = line.to_vec();
+            break;
+        }
+        message.extend_from_slice(line);
+    }
+    for line in lines {
+        info.patch.extend_from_slice(line);
+    }
+    info.body = decode_text(charset.as_deref(), &message);
+    info
+}
+
+fn strip_newline(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\n").unwrap_or(line)
+}
+
+/// Returns true if `line` looks like a header, `Name: value`, or is a mailbox `From ` line.
+fn is_header(line: &[u8]) -> bool {
+    if line.starts_with(b"From ") || line.starts_with(b">From ") {
+        return true;
+    }
+    for &b in line {
+        match b {
+            b':' => return true,
+            33..=57 | 59..=126 => continue,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Returns true if `line` starts the patch: a `diff -` or `Index: ` line, `--- <name>` or a
+/// `---` separator.
+fn is_patch_break(line: &[u8]) -> bool {
+    if line.starts_with(b"diff -") || line.starts_with(b"Index: ") {
+        return true;
+    }
+    if line.len() < 4 || !line.starts_with(b"---") {
+        return false;
+    }
+    if line[3] == b' ' && !line[4].is_ascii_whitespace() {
+        return true;
+    }
+    line[3..].iter().all(u8::is_ascii_whitespace) && line.ends_with(b"\n")
+}
+
+/// Returns the value of `parameter` in a header like `text/plain; charset=UTF-8`.
+fn header_parameter(value: &str, parameter: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|part| {
+        let (name, value) = part.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case(parameter) {
+            Some(value.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Splits an address like `A U Thor <author@example.com>` or `author@example.com (A U Thor)`
+/// into the name and the email. Without a usable name the email is the name.
+fn parse_address(address: &str) -> (String, String) {
+    let address = unquote(address);
+    let at = match address.find('@') {
+        Some(at) => at,
+        None => {
+            let name = address.trim().to_string();
+            return (name, String::new());
+        }
+    };
+    let mut start = at;
+    let mut bracketed = false;
+    for (i, ch) in address[..at].char_indices().rev() {
+        if ch.is_whitespace() {
+            break;
+        }
+        if ch == '<' {
+            bracketed = true;
+            break;
+        }
+        start = i;
+    }
+    let end = address[at..]
+        .find(|ch: char| ch.is_whitespace() || ch == '>')
+        .map_or(address.len(), |end| at + end);
+    let email = address[start..end].to_string();
+    let name_start = if bracketed { start - 1 } else { start };
+    let name_end = if address[end..].starts_with('>') {
+        end + 1
+    } else {
+        end
+    };
+    let name = format!("{}{}", &address[..name_start], &address[name_end..]);
+
+    let mut name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.starts_with('(') && name.ends_with(')') {
+        name = name[1..name.len() - 1].to_string();
+    }
+    if name.is_empty() || name.contains(|ch| "@<>".contains(ch)) {
+        name = email.clone();
+    }
+    (name, email)
+}
+
+/// Removes the quotes around quoted strings and the backslashes escaping characters in them.
+fn unquote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '"' {
+            out.push(ch);
+            continue;
+        }
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => break,
+                '\\' => out.extend(chars.next()),
+                _ => out.push(ch),
+            }
+        }
+    }
+    out
+}
+
+/// Removes the `Re:` and bracketed prefixes like `[PATCH 1/2]` from the start of a subject,
+/// and collapses its whitespace.
+fn clean_subject(subject: &str) -> String {
+    let mut subject = subject;
+    loop {
+        let bytes = subject.as_bytes();
+        subject = match bytes.first() {
+            Some(b' ') | Some(b'\t') | Some(b':') => &subject[1..],
+            Some(b'r') | Some(b'R')
+                if bytes.len() > 3 && bytes[1].eq_ignore_ascii_case(&b'e') && bytes[2] == b':' =>
+            {
+                &subject[3..]
+            }
+            Some(b'[') => match subject.find(']') {
+                Some(end) => &subject[end + 1..],
+                None => break,
+            },
+            _ => break,
+        };
+    }
+    subject
+        .split_ascii_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decodes the RFC 2047 encoded words in a header, like `=?UTF-8?q?J=C3=B6rg?=`. Whitespace
+/// between encoded words is dropped.
+fn decode_header(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = decode_word(&rest[start..]);
+        let (text, len) = match decoded {
+            Some(decoded) => decoded,
+            None => {
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+                continue;
+            }
+        };
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&text);
+        rest = &rest[start + len..];
+        after_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes the encoded word `=?charset?encoding?text?=` at the start of `word`, returning
+/// the text and the length of the word.
+fn decode_word(word: &str) -> Option<(String, usize)> {
+    let mut parts = word[2..].splitn(3, '?');
+    let charset = parts.next()?;
+    let encoding = parts.next()?;
+    let rest = parts.next()?;
+    let end = rest.find("?=")?;
+    let text = &rest.as_bytes()[..end];
+    let data = match encoding {
+        "q" | "Q" => {
+            let text: Vec<u8> = text
+                .iter()
+                .map(|&b| if b == b'_' { b' ' } else { b })
+                .collect();
+            decode_quoted_printable(&text)
+        }
+        "b" | "B" => decode_base64(text),
+        _ => return None,
+    };
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+    Some((decode_text(Some(charset), &data), len))
+}
+
+/// Converts text in `charset` to UTF-8, replacing what can not be converted.
+fn decode_text(charset: Option<&str>, data: &[u8]) -> String {
+    let converted = charset.and_then(|charset| convert::decode(charset, data));
+    String::from_utf8_lossy(converted.as_deref().unwrap_or(data)).into_owned()
+}
+
+/// Decodes quoted-printable text: `=XX` stands for a byte and `=` ends soft line breaks.
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'=' {
+            out.push(data[i]);
+            i += 1;
+            continue;
+        }
+        let hex = data.get(i + 1..i + 3).and_then(|hex| {
+            let hex = std::str::from_utf8(hex).ok()?;
+            u8::from_str_radix(hex, 16).ok()
+        });
+        match hex {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None if data[i + 1..].starts_with(b"\n") => i += 2,
+            None if data[i + 1..].starts_with(b"\r\n") => i += 3,
+            None => {
+                out.push(b'=');
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Decodes base64, ignoring whitespace and anything after the padding.
+fn decode_base64(data: &[u8]) -> Vec<u8> {
+    let digit = |b: u8| -> Option<u32> {
+        match b {
+            b'A'..=b'Z' => Some((b - b'A') as u32),
+            b'a'..=b'z' => Some((b - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((b - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    };
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    let (mut value, mut bits) = (0u32, 0);
+    for &b in data {
+        if b == b'=' {
+            break;
+        }
+        if let Some(digit) = digit(b) {
+            value = (value << 6) | digit;
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                out.push((value >> bits) as u8);
+                value &= (1 << bits) - 1;
+            }
+        }
+    }
+    out
+}
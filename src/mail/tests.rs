@@ -0,0 +1,212 @@
+use super::*;
+use crate::refs::GitRefs;
+use crate::test_utils::{self, tree};
+use crate::{GitCommit, GitFileMode, GitOdb, GitResult};
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn commit(odb: &GitOdb, content: &str, message: &str, parents: &[&str]) -> GitResult<String> {
+    let tree = tree(odb, &[("file.txt", GitFileMode::regular(), content)])?;
+    let author = GitSignature::new("A U Thör", "author@example.com", 1112911993, -420);
+    let committer = GitSignature::new("C O Mitter", "committer@example.com", 1112912053, -420);
+    test_utils::commit(odb, &tree, parents, author, committer, message)
+}
+
+const MAIL: &str = "\
+From 1234567890123456789012345678901234567890 Mon Sep 17 00:00:00 2001
+From: =?UTF-8?q?A=20U=20Th=C3=B6r?= <author@example.com>
+Date: Thu, 7 Apr 2005 15:13:13 -0700
+Subject: Re: [PATCH 2/3] Change the
+ file
+
+From: Other Author <other@example.com>
+
+Some details.
+---
+ file.txt | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+
+diff --git a/file.txt b/file.txt
+";
+
+#[test]
+fn test_parse_mail() {
+    let mails = split_mbox(format!("{}{}", MAIL, MAIL).as_bytes());
+    assert_eq!(mails.len(), 2);
+    assert_eq!(mails[0], MAIL.as_bytes());
+
+    let info = parse_mail(&mails[0]);
+    assert_eq!(info.author_name, "Other Author");
+    assert_eq!(info.author_email, "other@example.com");
+    assert_eq!(info.date.as_deref(), Some("Thu, 7 Apr 2005 15:13:13 -0700"));
+    assert_eq!(info.subject, "Change the file");
+    assert_eq!(info.message(), "Change the file\n\nSome details.\n");
+    assert!(info.patch.starts_with(b"---\n file.txt"));
+
+    let info = parse_mail(b"From: A U Thor <author@example.com>\nSubject: [RFC] x\n\nbody\n");
+    assert_eq!(info.author_name, "A U Thor");
+    assert_eq!(info.message(), "x\n\nbody\n");
+    assert!(info.patch.is_empty());
+
+    // Short names are kept; only a missing name falls back to the email.
+    let info = parse_mail(b"From: A <a@example.com>\n\nbody\n");
+    assert_eq!(info.author_name, "A");
+    let info = parse_mail(b"From: ab@example.com (Jo)\n\nbody\n");
+    assert_eq!(info.author_name, "Jo");
+    let info = parse_mail(b"From: <ab@example.com>\n\nbody\n");
+    assert_eq!(info.author_name, "ab@example.com");
+}
+
+#[test]
+fn test_append_signoff() {
+    let signer = GitSignature::new("C O Mitter", "committer@example.com", 0, 0);
+    let signoff = "Signed-off-by: C O Mitter <committer@example.com>\n";
+    assert_eq!(
+        append_signoff("title\n", &signer),
+        format!("title\n\n{}", signoff)
+    );
+    assert_eq!(
+        append_signoff("title\n\nbody\n", &signer),
+        format!("title\n\nbody\n\n{}", signoff)
+    );
+    let trailers = "title\n\nAcked-by: Someone <someone@example.com>\n";
+    assert_eq!(
+        append_signoff(trailers, &signer),
+        format!("{}{}", trailers, signoff)
+    );
+    let signed = format!("title\n\n{}", signoff);
+    assert_eq!(append_signoff(&signed, &signer), signed);
+    // A title alone does not count as trailers.
+    assert_eq!(
+        append_signoff("fix: title\n", &signer),
+        format!("fix: title\n\n{}", signoff)
+    );
+}
+
+#[test]
+fn test_format_patches() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("test_mail_format_patches")?;
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, "one\n", "Add the file\n", &[])?;
+    let second = commit(
+        &odb,
+        "two\n",
+        "Change the file\n\nIt says two now.\n",
+        &[&first],
+    )?;
+
+    let options = FormatPatchOptions::default();
+    let mails = format_patches(&repo, &[first.clone(), second.clone()], &options)?;
+    assert_eq!(mails.len(), 2);
+    assert_eq!(mails[0].name, "0001-Add-the-file.patch");
+    assert_eq!(mails[1].name, "0002-Change-the-file.patch");
+
+    let contents = String::from_utf8(mails[1].contents.clone())?;
+    let expected = format!(
+        "\
+From {} Mon Sep 17 00:00:00 2001
+From: =?UTF-8?q?A=20U=20Th=C3=B6r?= <author@example.com>
+Date: Thu, 7 Apr 2005 15:13:13 -0700
+Subject: [PATCH 2/2] Change the file
+
+It says two now.
+---
+ file.txt | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+
+diff --git a/file.txt b/file.txt
+index 5626abf..f719efd 100644
+--- a/file.txt
++++ b/file.txt
+@@ -1 +1 @@
+-one
++two
+--\x20
+{}
+
+",
+        second,
+        env!("CARGO_PKG_VERSION")
+    );
+    assert_eq!(contents, expected);
+
+    let options = FormatPatchOptions {
+        numbered: Some(false),
+        subject_prefix: String::new(),
+        ..FormatPatchOptions::default()
+    };
+    let mails = format_patches(&repo, &[second], &options)?;
+    assert!(String::from_utf8(mails[0].contents.clone())?.contains("\nSubject: Change the file\n"));
+    Ok(())
+}
+
+#[test]
+fn test_am() -> Result<(), Box<dyn Error>> {
+    let source = test_utils::setup("test_mail_am_source")?;
+    let odb = GitOdb::open(&source)?;
+    let first = commit(&odb, "one\n", "Add the file\n", &[])?;
+    let second = commit(
+        &odb,
+        "two\n",
+        "Change the file\n\nIt says two now.\n",
+        &[&first],
+    )?;
+    let mails = format_patches(
+        &source,
+        &[first, second.clone()],
+        &FormatPatchOptions::default(),
+    )?;
+    let mbox: Vec<u8> = mails.into_iter().flat_map(|mail| mail.contents).collect();
+
+    let repo = test_utils::setup("test_mail_am")?;
+    let target = GitOdb::open(&repo)?;
+    fs::write(
+        repo.gitpath().join("config"),
+        "[user]\n\tname = C O Mitter\n\temail = committer@example.com\n",
+    )?;
+    let am = GitAm::new(&repo);
+    let options = AmOptions {
+        quiet: true,
+        ..AmOptions::default()
+    };
+    let report = am.start(&split_mbox(&mbox), &options)?;
+    assert!(report.stopped.is_none());
+    assert!(report.messages.is_empty());
+    assert!(!am.in_progress());
+
+    let head = GitRefs::new(&repo).resolve("HEAD")?.unwrap();
+    let commit = target.read_as::<GitCommit>(&head)?;
+    let original = odb.read_as::<GitCommit>(&second)?;
+    assert_eq!(commit.tree(), original.tree());
+    assert_eq!(commit.author(), original.author());
+    assert_eq!(commit.message(), "Change the file\n\nIt says two now.\n");
+    assert_eq!(
+        fs::read_to_string(repo.worktree().unwrap().join("file.txt"))?,
+        "two\n"
+    );
+
+    // The same patches no longer apply.
+    let options = AmOptions::default();
+    let report = am.start(&split_mbox(&mbox), &options)?;
+    assert_eq!(
+        report.messages,
+        [
+            AmMessage::Applying("Add the file".to_string()),
+            AmMessage::Apply("error: file.txt: already exists in index".to_string()),
+        ]
+    );
+    assert_eq!(
+        report.stopped,
+        Some(AmStop::PatchFailed {
+            number: 1,
+            title: "Add the file".to_string()
+        })
+    );
+    assert!(am.in_progress());
+    assert!(String::from_utf8(am.current_patch(false)?)?.contains("Add the file"));
+    assert!(am.abort()?);
+    assert!(!am.in_progress());
+    assert_eq!(GitRefs::new(&repo).resolve("HEAD")?, Some(head));
+    Ok(())
+}
@@ -1,4 +1,9 @@
-use clap::{App, Arg, SubCommand};
+mod commands;
+
+use std::env;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 
 fn main() {
     // Get command line arguments.
@@ -6,6 +11,43 @@ fn main() {
         .version(env!("CARGO_PKG_VERSION"))
         .author("Ryan Cohen <rcohenprogramming@gmail.com>")
         .about("A rust implementation of some git features.")
+        .arg(
+            Arg::with_name("config")
+                .help("Pass a configuration parameter to the command, as <name>=<value>.")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("C")
+                .help("Run as if gitrs was started in <path> instead of the current directory.")
+                .short("C")
+                .takes_value(true)
+                .value_name("path")
+                .multiple(true)
+                .number_of_values(1)
+                .empty_values(true),
+        )
+        .arg(
+            Arg::with_name("git-dir")
+                .help("Set the path to the repository (\".git\" directory).")
+                .long("git-dir")
+                .takes_value(true)
+                .value_name("path"),
+        )
+        .arg(
+            Arg::with_name("work-tree")
+                .help("Set the path to the working tree.")
+                .long("work-tree")
+                .takes_value(true)
+                .value_name("path"),
+        )
+        .arg(
+            Arg::with_name("bare")
+                .help("Treat the repository as a bare repository.")
+                .long("bare"),
+        )
         .subcommand(
             SubCommand::with_name("init")
                 .arg(
@@ -20,20 +62,1342 @@ fn main() {
                         .long("quiet")
                         .takes_value(false),
                 )
+                .arg(
+                    Arg::with_name("bare")
+                        .help("Create a bare repository.")
+                        .long("bare"),
+                )
+                .arg(
+                    Arg::with_name("template")
+                        .help("Copy the files of this template directory into the new repository.")
+                        .long("template")
+                        .takes_value(true)
+                        .value_name("template-directory")
+                        .empty_values(true),
+                )
+                .arg(
+                    Arg::with_name("initial-branch")
+                        .help("Use this name for the initial branch.")
+                        .short("b")
+                        .long("initial-branch")
+                        .takes_value(true)
+                        .value_name("branch-name"),
+                )
+                .arg(
+                    Arg::with_name("shared")
+                        .help("Make the repository shared among several users (umask, group, all or an octal mode).")
+                        .long("shared")
+                        .takes_value(true)
+                        .value_name("permissions")
+                        .min_values(0)
+                        .max_values(1)
+                        .require_equals(true),
+                )
+                .arg(
+                    Arg::with_name("separate-git-dir")
+                        .help("Create the git directory here and link to it from the working tree.")
+                        .long("separate-git-dir")
+                        .takes_value(true)
+                        .value_name("git-dir")
+                        .conflicts_with("bare"),
+                )
                 .version("0.0.1")
         )
-        .subcommand(SubCommand::with_name("hash-object").arg(Arg::with_name("file").index(1)))
+        .subcommand(
+            SubCommand::with_name("hash-object")
+                .arg(Arg::with_name("file").index(1).required(true))
+                .arg(
+                    Arg::with_name("path")
+                        .help("Hash object as it were located at the given path.")
+                        .long("path")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("no-filters")
+                        .help("Hash the contents as is, ignoring any input filter.")
+                        .long("no-filters")
+                        .conflicts_with("path"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-ignore")
+                .about("Debug gitignore / exclude files.")
+                .arg(
+                    Arg::with_name("pathname")
+                        .help("The paths to check.")
+                        .multiple(true)
+                        .required_unless("stdin"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .help("Output details about the matching pattern (if any) for each given pathname.")
+                        .short("v")
+                        .long("verbose"),
+                )
+                .arg(
+                    Arg::with_name("non-matching")
+                        .help("Show given paths which don't match any pattern.")
+                        .short("n")
+                        .long("non-matching")
+                        .requires("verbose"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Don't output anything, just set exit status.")
+                        .short("q")
+                        .long("quiet"),
+                )
+                .arg(
+                    Arg::with_name("stdin")
+                        .help("Read pathnames from the standard input, one per line.")
+                        .long("stdin"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check-attr")
+                .about("Display gitattributes information.")
+                .arg(
+                    Arg::with_name("attr")
+                        .help("The attributes to show, followed by paths if '--' is not given.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("pathname")
+                        .help("The paths to show attributes of.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .help("List all attributes that are associated with the specified paths.")
+                        .short("a")
+                        .long("all"),
+                )
+                .arg(
+                    Arg::with_name("stdin")
+                        .help("Read pathnames from the standard input, one per line.")
+                        .long("stdin"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("branch")
+                .about("List, create, or delete branches.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("Branch names, a start point, or patterns to list.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("delete")
+                        .help("Delete a fully merged branch.")
+                        .short("d")
+                        .long("delete"),
+                )
+                .arg(
+                    Arg::with_name("D")
+                        .help("Delete a branch even if it is not merged.")
+                        .short("D"),
+                )
+                .arg(
+                    Arg::with_name("move")
+                        .help("Move or rename a branch and its reflog.")
+                        .short("m")
+                        .long("move"),
+                )
+                .arg(
+                    Arg::with_name("M")
+                        .help("Move or rename a branch even if the new name exists.")
+                        .short("M"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .help("Reset an existing branch, or force deleting and renaming.")
+                        .short("f")
+                        .long("force"),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .help("List branches, optionally matching the given patterns.")
+                        .short("l")
+                        .long("list"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .help("Show the tip of each branch; twice to also show its upstream.")
+                        .short("v")
+                        .long("verbose")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .help("List both local and remote-tracking branches.")
+                        .short("a")
+                        .long("all"),
+                )
+                .arg(
+                    Arg::with_name("remotes")
+                        .help("List or delete remote-tracking branches.")
+                        .short("r")
+                        .long("remotes"),
+                )
+                .arg(
+                    Arg::with_name("contains")
+                        .help("Only list branches which contain the commit.")
+                        .long("contains")
+                        .takes_value(true)
+                        .value_name("commit")
+                        .min_values(0)
+                        .max_values(1),
+                )
+                .arg(
+                    Arg::with_name("merged")
+                        .help("Only list branches merged into the commit.")
+                        .long("merged")
+                        .takes_value(true)
+                        .value_name("commit")
+                        .min_values(0)
+                        .max_values(1),
+                )
+                .arg(
+                    Arg::with_name("no-merged")
+                        .help("Only list branches not merged into the commit.")
+                        .long("no-merged")
+                        .takes_value(true)
+                        .value_name("commit")
+                        .min_values(0)
+                        .max_values(1),
+                )
+                .arg(
+                    Arg::with_name("set-upstream-to")
+                        .help("Set up the branch's upstream.")
+                        .short("u")
+                        .long("set-upstream-to")
+                        .takes_value(true)
+                        .value_name("upstream"),
+                )
+                .arg(
+                    Arg::with_name("unset-upstream")
+                        .help("Remove the upstream information of the branch.")
+                        .long("unset-upstream"),
+                )
+                .arg(
+                    Arg::with_name("show-current")
+                        .help("Print the name of the current branch.")
+                        .long("show-current"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Suppress non-error messages.")
+                        .short("q")
+                        .long("quiet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("checkout")
+                .about("Switch branches or restore working tree files.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The branch or commit to switch to, or a tree-ish and paths to restore.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("The paths to restore.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("b")
+                        .help("Create a new branch and switch to it.")
+                        .short("b")
+                        .takes_value(true)
+                        .value_name("new-branch")
+                        .conflicts_with("B"),
+                )
+                .arg(
+                    Arg::with_name("B")
+                        .help("Create or reset a branch and switch to it.")
+                        .short("B")
+                        .takes_value(true)
+                        .value_name("new-branch"),
+                )
+                .arg(
+                    Arg::with_name("detach")
+                        .help("Check out a commit for inspection and discardable experiments.")
+                        .long("detach"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .help("Throw away local changes when switching branches.")
+                        .short("f")
+                        .long("force"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Suppress feedback messages.")
+                        .short("q")
+                        .long("quiet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("switch")
+                .about("Switch branches.")
+                .arg(Arg::with_name("branch").help("The branch to switch to, or the start point of a new branch."))
+                .arg(
+                    Arg::with_name("create")
+                        .help("Create a new branch and switch to it.")
+                        .short("c")
+                        .long("create")
+                        .takes_value(true)
+                        .value_name("new-branch")
+                        .conflicts_with("force-create"),
+                )
+                .arg(
+                    Arg::with_name("force-create")
+                        .help("Create or reset a branch and switch to it.")
+                        .short("C")
+                        .long("force-create")
+                        .takes_value(true)
+                        .value_name("new-branch"),
+                )
+                .arg(
+                    Arg::with_name("detach")
+                        .help("Switch to a commit for inspection and discardable experiments.")
+                        .short("d")
+                        .long("detach"),
+                )
+                .arg(
+                    Arg::with_name("discard-changes")
+                        .help("Throw away local changes.")
+                        .short("f")
+                        .long("discard-changes")
+                        .alias("force"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Suppress feedback messages.")
+                        .short("q")
+                        .long("quiet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Restore working tree files.")
+                .arg(
+                    Arg::with_name("pathspec")
+                        .help("The paths to restore.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("source")
+                        .help("Restore the contents from the given tree-ish.")
+                        .short("s")
+                        .long("source")
+                        .takes_value(true)
+                        .value_name("tree"),
+                )
+                .arg(
+                    Arg::with_name("staged")
+                        .help("Restore the index.")
+                        .short("S")
+                        .long("staged"),
+                )
+                .arg(
+                    Arg::with_name("worktree")
+                        .help("Restore the working tree (the default).")
+                        .short("W")
+                        .long("worktree"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Suppress feedback messages.")
+                        .short("q")
+                        .long("quiet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Get and set repository or global options.")
+                // Like git, options end at the name, so values may start with '-'.
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("args")
+                        .help("The name, value and value pattern, depending on the action.")
+                        .multiple(true)
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::with_name("global")
+                        .help("Use the global config file.")
+                        .long("global"),
+                )
+                .arg(
+                    Arg::with_name("system")
+                        .help("Use the system config file.")
+                        .long("system"),
+                )
+                .arg(
+                    Arg::with_name("local")
+                        .help("Use the repository config file.")
+                        .long("local"),
+                )
+                .arg(
+                    Arg::with_name("worktree")
+                        .help("Use the per-worktree config file.")
+                        .long("worktree"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .help("Use the given config file.")
+                        .short("f")
+                        .long("file")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("location")
+                        .args(&["global", "system", "local", "worktree", "file"]),
+                )
+                .arg(
+                    Arg::with_name("get")
+                        .help("Get the value of a name, optionally matching a value pattern.")
+                        .long("get"),
+                )
+                .arg(
+                    Arg::with_name("get-all")
+                        .help("Get all values of a name, optionally matching a value pattern.")
+                        .long("get-all"),
+                )
+                .arg(
+                    Arg::with_name("get-regexp")
+                        .help("Get the names and values of names matching a regex.")
+                        .long("get-regexp"),
+                )
+                .arg(
+                    Arg::with_name("replace-all")
+                        .help("Replace all values of a name, optionally only those matching a value pattern.")
+                        .long("replace-all"),
+                )
+                .arg(
+                    Arg::with_name("add")
+                        .help("Add a new value without altering existing ones.")
+                        .long("add"),
+                )
+                .arg(
+                    Arg::with_name("unset")
+                        .help("Remove the value of a name.")
+                        .long("unset"),
+                )
+                .arg(
+                    Arg::with_name("unset-all")
+                        .help("Remove all values of a name, optionally only those matching a value pattern.")
+                        .long("unset-all"),
+                )
+                .arg(
+                    Arg::with_name("rename-section")
+                        .help("Rename a section.")
+                        .long("rename-section"),
+                )
+                .arg(
+                    Arg::with_name("remove-section")
+                        .help("Remove a section.")
+                        .long("remove-section"),
+                )
+                .arg(
+                    Arg::with_name("list")
+                        .help("List all variables with their values.")
+                        .short("l")
+                        .long("list"),
+                )
+                .group(ArgGroup::with_name("action").args(&[
+                    "get",
+                    "get-all",
+                    "get-regexp",
+                    "replace-all",
+                    "add",
+                    "unset",
+                    "unset-all",
+                    "rename-section",
+                    "remove-section",
+                    "list",
+                ]))
+                .arg(
+                    Arg::with_name("type")
+                        .help("Check and canonicalize values as the given type.")
+                        .short("t")
+                        .long("type")
+                        .takes_value(true)
+                        .possible_values(&["bool", "int", "path", "color"]),
+                )
+                .arg(
+                    Arg::with_name("bool")
+                        .help("Values are \"true\" or \"false\", like --type=bool.")
+                        .long("bool"),
+                )
+                .arg(
+                    Arg::with_name("int")
+                        .help("Values are decimal numbers, like --type=int.")
+                        .long("int"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .help("Values are paths, like --type=path.")
+                        .long("path"),
+                )
+                .group(ArgGroup::with_name("types").args(&["type", "bool", "int", "path"]))
+                .arg(
+                    Arg::with_name("fixed-value")
+                        .help("Compare values to the value pattern for equality.")
+                        .long("fixed-value"),
+                )
+                .arg(
+                    Arg::with_name("includes")
+                        .help("Respect include directives when reading a single file.")
+                        .long("includes"),
+                )
+                .arg(
+                    Arg::with_name("no-includes")
+                        .help("Do not respect include directives.")
+                        .long("no-includes")
+                        .conflicts_with("includes"),
+                )
+                .arg(
+                    Arg::with_name("show-origin")
+                        .help("Show the file or command line each value comes from.")
+                        .long("show-origin"),
+                )
+                .arg(
+                    Arg::with_name("show-scope")
+                        .help("Show the scope of each value.")
+                        .long("show-scope"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("log")
+                .about("Show commit logs.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The revisions to show the history of, and the paths to limit it to.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only show commits changing these paths.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .args(&walk_args())
+                .arg(
+                    Arg::with_name("oneline")
+                        .help("Show each commit on one line, like --pretty=oneline --abbrev-commit.")
+                        .long("oneline"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .help("Pretty-print the commits in a format (oneline, short, medium, full, fuller, format:<string>).")
+                        .long("pretty")
+                        .takes_value(true)
+                        .value_name("format")
+                        .min_values(0)
+                        .max_values(1)
+                        .require_equals(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .help("Pretty-print the commits in a format, like --pretty.")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("format")
+                        .empty_values(true),
+                )
+                .arg(
+                    Arg::with_name("abbrev-commit")
+                        .help("Show abbreviated commit names.")
+                        .long("abbrev-commit"),
+                )
+                .arg(
+                    Arg::with_name("no-abbrev-commit")
+                        .help("Show full commit names.")
+                        .long("no-abbrev-commit")
+                        .overrides_with("abbrev-commit"),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .help("Show dates in a format (relative, iso, iso-strict, rfc, short, raw, unix, format:<strftime>).")
+                        .long("date")
+                        .takes_value(true)
+                        .value_name("format"),
+                )
+                .arg(
+                    Arg::with_name("decorate")
+                        .help("Show the ref names of the commits (short, full, auto or no).")
+                        .long("decorate")
+                        .takes_value(true)
+                        .value_name("style")
+                        .min_values(0)
+                        .max_values(1)
+                        .require_equals(true),
+                )
+                .arg(
+                    Arg::with_name("no-decorate")
+                        .help("Do not show the ref names of the commits.")
+                        .long("no-decorate")
+                        .overrides_with("decorate"),
+                )
+                .arg(
+                    Arg::with_name("graph")
+                        .help("Draw a text-based graph of the commit history.")
+                        .long("graph"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rev-list")
+                .about("Lists commit objects in reverse chronological order.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The revisions to list the history of (^<rev> or <rev>..<rev> to exclude, <rev>...<rev> for either but not both), and the paths to limit it to.")
+                        .multiple(true)
+                        .required_unless("all"),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only list commits changing these paths.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .args(&walk_args())
+                .arg(
+                    Arg::with_name("count")
+                        .help("Print the number of commits that would be listed.")
+                        .long("count"),
+                )
+                .arg(
+                    Arg::with_name("objects")
+                        .help("Also list the trees and blobs of the listed commits, with their paths.")
+                        .long("objects"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("commit-graph")
+                .about("Writes and verifies the commit-graph file.")
+                .subcommand(
+                    SubCommand::with_name("write")
+                        .about("Writes a commit-graph of the commits in all packfiles and their ancestors.")
+                        .arg(
+                            Arg::with_name("reachable")
+                                .help("Write the commits reachable from all refs instead.")
+                                .long("reachable"),
+                        )
+                        .arg(
+                            Arg::with_name("stdin-commits")
+                                .help("Write the commits listed on stdin instead.")
+                                .long("stdin-commits")
+                                .conflicts_with("reachable"),
+                        )
+                        .arg(
+                            Arg::with_name("append")
+                                .help("Also keep the commits of the existing commit-graph.")
+                                .long("append"),
+                        )
+                        .arg(
+                            Arg::with_name("split")
+                                .help("Write a layer of a commit-graph chain, merging layers (no-merge or replace).")
+                                .long("split")
+                                .takes_value(true)
+                                .value_name("strategy")
+                                .min_values(0)
+                                .max_values(1)
+                                .require_equals(true),
+                        )
+                        .arg(
+                            Arg::with_name("changed-paths")
+                                .help("Store changed-path Bloom filters.")
+                                .long("changed-paths"),
+                        )
+                        .arg(
+                            Arg::with_name("no-changed-paths")
+                                .help("Do not store changed-path Bloom filters, even if the existing commit-graph does.")
+                                .long("no-changed-paths")
+                                .overrides_with("changed-paths"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Checks the commit-graph against the object database.")
+                        .arg(
+                            Arg::with_name("shallow")
+                                .help("Only check the top layer of a commit-graph chain.")
+                                .long("shallow"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge-base")
+                .about("Finds the best common ancestors of commits for a merge.")
+                .arg(
+                    Arg::with_name("commits")
+                        .help("The commits, or the ref and commit for --fork-point.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .help("Print all best common ancestors instead of one.")
+                        .short("a")
+                        .long("all"),
+                )
+                .arg(
+                    Arg::with_name("octopus")
+                        .help("Find the best common ancestors of all commits together.")
+                        .long("octopus"),
+                )
+                .arg(
+                    Arg::with_name("independent")
+                        .help("List the commits not reachable from any other.")
+                        .long("independent")
+                        .conflicts_with_all(&["octopus", "all"]),
+                )
+                .arg(
+                    Arg::with_name("is-ancestor")
+                        .help("Check whether the first commit is an ancestor of the second.")
+                        .long("is-ancestor")
+                        .conflicts_with_all(&["octopus", "independent", "all"]),
+                )
+                .arg(
+                    Arg::with_name("fork-point")
+                        .help("Find where the commit (HEAD by default) forked from the ref, using its reflog.")
+                        .long("fork-point")
+                        .conflicts_with_all(&["octopus", "independent", "is-ancestor"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show changes between the worktree, the index and commits.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The commits or trees to compare, and the paths to limit the diff to.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only show changes of these paths.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("cached")
+                        .help("Compare the index with HEAD or the given commit.")
+                        .long("cached")
+                        .alias("staged"),
+                )
+                .args(&diff_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("diff-tree")
+                .about("Show changes between two trees, or a commit and its parent.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The two trees, or the commit, to compare, and the paths to limit the diff to.")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only show changes of these paths.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .help("Compare the files in subtrees instead of the subtrees.")
+                        .short("r"),
+                )
+                .arg(
+                    Arg::with_name("trees")
+                        .help("Show the subtrees as well as the files in them.")
+                        .short("t"),
+                )
+                .arg(
+                    Arg::with_name("root")
+                        .help("Show a root commit as adding all of its files.")
+                        .long("root"),
+                )
+                .arg(
+                    Arg::with_name("no-commit-id")
+                        .help("Do not show the name of the commit before its changes.")
+                        .long("no-commit-id"),
+                )
+                .args(&diff_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("diff-index")
+                .about("Show changes between a tree and the worktree or index.")
+                .arg(
+                    Arg::with_name("args")
+                        .help("The tree to compare, and the paths to limit the diff to.")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only show changes of these paths.")
+                        .multiple(true)
+                        .last(true),
+                )
+                .arg(
+                    Arg::with_name("cached")
+                        .help("Compare the tree with the index instead of the worktree.")
+                        .long("cached"),
+                )
+                .args(&diff_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("diff-files")
+                .about("Show changes between the index and the worktree.")
+                .arg(
+                    Arg::with_name("paths")
+                        .help("Only show changes of these paths.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("no-deleted")
+                        .help("Do not show files missing from the worktree.")
+                        .short("q"),
+                )
+                .args(&diff_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Apply a patch to files and/or to the index.")
+                .arg(
+                    Arg::with_name("patches")
+                        .help("The files to read the patches from, or - for stdin.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .help("Only check that the patches apply, without changing anything.")
+                        .long("check"),
+                )
+                .arg(
+                    Arg::with_name("index")
+                        .help("Apply to both the index and the worktree.")
+                        .long("index"),
+                )
+                .arg(
+                    Arg::with_name("cached")
+                        .help("Apply to the index only, without touching the worktree.")
+                        .long("cached"),
+                )
+                .arg(
+                    Arg::with_name("reverse")
+                        .help("Apply the patches in reverse.")
+                        .short("R")
+                        .long("reverse"),
+                )
+                .arg(
+                    Arg::with_name("3way")
+                        .help("Merge with the blobs the patches were made from, leaving conflicts in the index.")
+                        .short("3")
+                        .long("3way"),
+                )
+                .arg(
+                    Arg::with_name("p")
+                        .help("Remove <n> leading components from the paths of the patches, 1 by default.")
+                        .short("p")
+                        .takes_value(true)
+                        .value_name("n"),
+                )
+                .arg(
+                    Arg::with_name("C")
+                        .help("Only require <n> lines of context around each change to match.")
+                        .short("C")
+                        .takes_value(true)
+                        .value_name("n"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .help("Report each patch and where its hunks apply.")
+                        .short("v")
+                        .long("verbose"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("format-patch")
+                .about("Prepare each commit with its patch in a mail, for sending or applying with am.")
+                .arg(
+                    Arg::with_name("revisions")
+                        .help("The commits since <since> up to HEAD, a range like A..B, or with -<n> the last commits up to a revision.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("max-count")
+                        .help("Prepare the last <n> commits, written as -<n>.")
+                        .long("max-count")
+                        .takes_value(true)
+                        .value_name("n")
+                        .hidden(true),
+                )
+                .arg(
+                    Arg::with_name("root")
+                        .help("Prepare all commits up to the revision, from the root commit on.")
+                        .long("root"),
+                )
+                .arg(
+                    Arg::with_name("output-directory")
+                        .help("Write the mails to files in this directory instead of the current one.")
+                        .short("o")
+                        .long("output-directory")
+                        .takes_value(true)
+                        .value_name("dir"),
+                )
+                .arg(
+                    Arg::with_name("stdout")
+                        .help("Print all mails to the standard output in mbox format instead of writing files.")
+                        .long("stdout")
+                        .conflicts_with("output-directory"),
+                )
+                .arg(
+                    Arg::with_name("cover-letter")
+                        .help("Add a cover letter describing the series, to be filled in.")
+                        .long("cover-letter"),
+                )
+                .arg(
+                    Arg::with_name("numbered")
+                        .help("Number the subjects like [PATCH n/m], even for a single patch.")
+                        .short("n")
+                        .long("numbered"),
+                )
+                .arg(
+                    Arg::with_name("no-numbered")
+                        .help("Do not number the subjects, just [PATCH].")
+                        .short("N")
+                        .long("no-numbered")
+                        .overrides_with("numbered"),
+                )
+                .arg(
+                    Arg::with_name("signoff")
+                        .help("Add a Signed-off-by trailer for the committer.")
+                        .short("s")
+                        .long("signoff"),
+                )
+                .arg(
+                    Arg::with_name("subject-prefix")
+                        .help("Put this in the brackets of the subjects instead of PATCH.")
+                        .long("subject-prefix")
+                        .takes_value(true)
+                        .value_name("prefix"),
+                )
+                .arg(
+                    Arg::with_name("start-number")
+                        .help("Start numbering the patches at <n> instead of 1.")
+                        .long("start-number")
+                        .takes_value(true)
+                        .value_name("n"),
+                )
+                .arg(
+                    Arg::with_name("no-renames")
+                        .help("Show renamed files as deleted and added.")
+                        .long("no-renames"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Do not print the names of the files written.")
+                        .short("q")
+                        .long("quiet"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("am")
+                .about("Apply the patches of a series of mails, committing each of them.")
+                .arg(
+                    Arg::with_name("mbox")
+                        .help("The mailboxes to read the mails from, or stdin if none.")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("3way")
+                        .help("Merge patches that do not apply with the blobs they were made from.")
+                        .short("3")
+                        .long("3way"),
+                )
+                .arg(
+                    Arg::with_name("signoff")
+                        .help("Add a Signed-off-by trailer for the committer.")
+                        .short("s")
+                        .long("signoff"),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .help("Only print errors.")
+                        .short("q")
+                        .long("quiet"),
+                )
+                .arg(
+                    Arg::with_name("continue")
+                        .help("Commit the patch that did not apply after resolving it, and go on.")
+                        .long("continue")
+                        .visible_alias("resolved")
+                        .short("r"),
+                )
+                .arg(
+                    Arg::with_name("skip")
+                        .help("Skip the patch that did not apply, and go on.")
+                        .long("skip"),
+                )
+                .arg(
+                    Arg::with_name("abort")
+                        .help("Stop and restore the branch to where it was before am started.")
+                        .long("abort"),
+                )
+                .arg(
+                    Arg::with_name("show-current-patch")
+                        .help("Show the mail (raw) or the patch (diff) am stopped at.")
+                        .long("show-current-patch")
+                        .takes_value(true)
+                        .value_name("raw|diff")
+                        .possible_values(&["raw", "diff"])
+                        .min_values(0)
+                        .max_values(1)
+                        .require_equals(true),
+                )
+                .group(ArgGroup::with_name("operation").args(&[
+                    "continue",
+                    "skip",
+                    "abort",
+                    "show-current-patch",
+                ])),
+        )
         .subcommand(SubCommand::with_name("cat-file").arg(Arg::with_name("file").index(1)))
-        .get_matches();
+        .get_matches_from(commands::expand_short_values(env::args()));
+
+    if let Err(error) = commands::setup_environment(&matches) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
 
     // Run subcommand from args.
     let result = match matches.subcommand() {
-        ("init", _) => gitrs::init(&matches),
-        ("hash-object", _) => gitrs::hash_object(&matches),
+        ("init", _) => commands::init(&matches),
+        ("hash-object", _) => commands::hash_object(&matches),
+        ("check-attr", _) => commands::check_attr(&matches),
+        ("checkout", _) => commands::checkout(&matches),
+        ("switch", _) => commands::switch(&matches),
+        ("restore", _) => commands::restore(&matches),
+        ("log", _) => commands::log(&matches),
+        ("rev-list", _) => commands::rev_list(&matches),
+        ("commit-graph", _) => commands::commit_graph(&matches).map(|valid| {
+            if !valid {
+                process::exit(1);
+            }
+        }),
+        ("diff", _) => commands::diff(&matches).map(|same| {
+            if !same {
+                process::exit(1);
+            }
+        }),
+        ("diff-tree", _) => commands::diff_tree(&matches).map(|same| {
+            if !same {
+                process::exit(1);
+            }
+        }),
+        ("apply", _) => commands::apply(&matches).map(|clean| {
+            if !clean {
+                process::exit(1);
+            }
+        }),
+        ("format-patch", _) => commands::format_patch(&matches),
+        ("am", _) => commands::am(&matches).map(|done| {
+            if !done {
+                process::exit(1);
+            }
+        }),
+        ("diff-index", _) => commands::diff_index(&matches).map(|same| {
+            if !same {
+                process::exit(1);
+            }
+        }),
+        ("diff-files", _) => commands::diff_files(&matches).map(|same| {
+            if !same {
+                process::exit(1);
+            }
+        }),
+        ("merge-base", _) => commands::merge_base(&matches).map(|found| {
+            if !found {
+                process::exit(1);
+            }
+        }),
+        ("branch", _) => commands::branch(&matches).map(|success| {
+            if !success {
+                process::exit(1);
+            }
+        }),
+        ("config", _) => match commands::config(&matches) {
+            Ok(commands::ConfigStatus::Done) => Ok(()),
+            Ok(status) => process::exit(status.exit_code()),
+            Err(failure) => {
+                eprintln!("{}", failure.error);
+                process::exit(failure.status.exit_code());
+            }
+        },
+        ("check-ignore", _) => commands::check_ignore(&matches).map(|ignored| {
+            if !ignored {
+                process::exit(1);
+            }
+        }),
         _ => Ok(()),
     };
 
     if let Err(error) = result {
         eprintln!("{}", error);
+        process::exit(1);
     }
 }
+
+/// The options limiting and ordering the history walked by `log` and `rev-list`.
+fn walk_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("max-count")
+            .help("Limit the number of commits to output.")
+            .short("n")
+            .long("max-count")
+            .takes_value(true)
+            .value_name("number"),
+        Arg::with_name("all")
+            .help("Show the history of all refs, as if they were listed as revisions.")
+            .long("all"),
+        Arg::with_name("topo-order")
+            .help(
+                "Show no parents before all of their children, and avoid mixing lines of history.",
+            )
+            .long("topo-order"),
+        Arg::with_name("date-order")
+            .help("Show no parents before all of their children, otherwise in commit date order.")
+            .long("date-order")
+            .overrides_with("topo-order"),
+        Arg::with_name("reverse")
+            .help("Output the commits in reverse order.")
+            .long("reverse"),
+        Arg::with_name("ancestry-path")
+            .help("Only show commits that are descendants of the excluded commits.")
+            .long("ancestry-path"),
+        Arg::with_name("first-parent")
+            .help("Only follow the first parent of merge commits.")
+            .long("first-parent"),
+        Arg::with_name("since")
+            .help("Show commits more recent than a date, like '2 weeks ago' or '2024-10-17'.")
+            .long("since")
+            .alias("after")
+            .takes_value(true)
+            .value_name("date"),
+        Arg::with_name("until")
+            .help("Show commits older than a date.")
+            .long("until")
+            .alias("before")
+            .takes_value(true)
+            .value_name("date"),
+        Arg::with_name("author")
+            .help("Only show commits whose author matches the regular expression.")
+            .long("author")
+            .takes_value(true)
+            .value_name("pattern")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("grep")
+            .help("Only show commits whose message matches the regular expression.")
+            .long("grep")
+            .takes_value(true)
+            .value_name("pattern")
+            .multiple(true)
+            .number_of_values(1),
+        Arg::with_name("regexp-ignore-case")
+            .help("Match the --author and --grep patterns regardless of case.")
+            .short("i")
+            .long("regexp-ignore-case"),
+    ]
+}
+
+/// The options of `diff` and the `diff-*` plumbing commands choosing the output and how lines
+/// and files are compared.
+fn diff_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("patch")
+            .help("Show a patch, which is the default.")
+            .short("p")
+            .long("patch")
+            .alias("u"),
+        Arg::with_name("unified")
+            .help("Show patches with <n> lines of context instead of three.")
+            .short("U")
+            .long("unified")
+            .takes_value(true)
+            .value_name("n"),
+        Arg::with_name("stat")
+            .help("Show a histogram of the changed lines of each file, <width> columns wide with at most <name-width> for the names.")
+            .long("stat")
+            .takes_value(true)
+            .value_name("width[,name-width]")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("numstat")
+            .help("Show the numbers of added and removed lines of each file.")
+            .long("numstat"),
+        Arg::with_name("shortstat")
+            .help("Only show the totals of --stat.")
+            .long("shortstat"),
+        Arg::with_name("summary")
+            .help("Show created, deleted, renamed and copied files and changed modes.")
+            .long("summary"),
+        Arg::with_name("binary")
+            .help("Show patches of binary files that can be applied.")
+            .long("binary"),
+        Arg::with_name("name-only")
+            .help("Only show the names of the changed files.")
+            .long("name-only"),
+        Arg::with_name("name-status")
+            .help("Only show the names and statuses of the changed files.")
+            .long("name-status")
+            .conflicts_with("name-only"),
+        Arg::with_name("raw")
+            .help("Show the modes, object names and status of the changed files.")
+            .long("raw"),
+        Arg::with_name("z")
+            .help("Do not quote names, and end them with NUL bytes instead of newlines.")
+            .short("z"),
+        Arg::with_name("abbrev")
+            .help("Shorten the object names of the raw output to <n> hex digits or more.")
+            .long("abbrev")
+            .takes_value(true)
+            .value_name("n")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("no-abbrev")
+            .help("Show the full object names in the raw output.")
+            .long("no-abbrev")
+            .overrides_with("abbrev"),
+        Arg::with_name("color")
+            .help("Color the output: always, never or auto for a terminal.")
+            .long("color")
+            .takes_value(true)
+            .value_name("when")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("no-color")
+            .help("Do not color the output.")
+            .long("no-color")
+            .overrides_with("color"),
+        Arg::with_name("word-diff")
+            .help("Show the changed words instead of lines: plain, color, porcelain or none.")
+            .long("word-diff")
+            .takes_value(true)
+            .value_name("mode")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("word-diff-regex")
+            .help("Take the matches of <regex> as words, with --word-diff=plain by default.")
+            .long("word-diff-regex")
+            .takes_value(true)
+            .value_name("regex"),
+        Arg::with_name("color-words")
+            .help("Show the changed words in color, like --word-diff=color --word-diff-regex=<regex>.")
+            .long("color-words")
+            .takes_value(true)
+            .value_name("regex")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("color-moved")
+            .help("Color moved lines: no, default, plain, blocks, zebra or dimmed-zebra.")
+            .long("color-moved")
+            .takes_value(true)
+            .value_name("mode")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("no-color-moved")
+            .help("Do not color moved lines.")
+            .long("no-color-moved")
+            .overrides_with("color-moved"),
+        Arg::with_name("ignore-all-space")
+            .help("Ignore whitespace when comparing lines.")
+            .short("w")
+            .long("ignore-all-space"),
+        Arg::with_name("ignore-space-change")
+            .help("Ignore changes in the amount of whitespace.")
+            .short("b")
+            .long("ignore-space-change"),
+        Arg::with_name("ignore-space-at-eol")
+            .help("Ignore changes in whitespace at the end of lines.")
+            .long("ignore-space-at-eol"),
+        Arg::with_name("ignore-cr-at-eol")
+            .help("Ignore carriage returns at the end of lines.")
+            .long("ignore-cr-at-eol"),
+        Arg::with_name("ignore-blank-lines")
+            .help("Ignore changes whose lines are all blank.")
+            .long("ignore-blank-lines"),
+        Arg::with_name("diff-algorithm")
+            .help("Choose a diff algorithm (myers, minimal, patience or histogram).")
+            .long("diff-algorithm")
+            .takes_value(true)
+            .value_name("algorithm"),
+        Arg::with_name("minimal")
+            .help("Spend extra time to find the smallest diff.")
+            .long("minimal"),
+        Arg::with_name("patience")
+            .help("Use the patience diff algorithm.")
+            .long("patience"),
+        Arg::with_name("histogram")
+            .help("Use the histogram diff algorithm.")
+            .long("histogram"),
+        Arg::with_name("inter-hunk-context")
+            .help("Merge hunks separated by up to <n> unchanged lines.")
+            .long("inter-hunk-context")
+            .takes_value(true)
+            .value_name("n"),
+        Arg::with_name("indent-heuristic")
+            .help("Slide changes to where the indentation suggests they start and end.")
+            .long("indent-heuristic"),
+        Arg::with_name("no-indent-heuristic")
+            .help("Do not use the indent heuristic.")
+            .long("no-indent-heuristic")
+            .overrides_with("indent-heuristic"),
+        Arg::with_name("find-renames")
+            .help("Show files similar to deleted ones as renamed, at least <n> similar (50% by default).")
+            .short("M")
+            .long("find-renames")
+            .takes_value(true)
+            .value_name("n")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true),
+        Arg::with_name("find-copies")
+            .help("Also show files similar to modified ones as copied; given twice like --find-copies-harder.")
+            .short("C")
+            .long("find-copies")
+            .takes_value(true)
+            .value_name("n")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true)
+            .multiple(true),
+        Arg::with_name("find-copies-harder")
+            .help("Also show files similar to unmodified ones as copied.")
+            .long("find-copies-harder"),
+        Arg::with_name("no-renames")
+            .help("Do not look for renamed files, even if diff.renames is set.")
+            .long("no-renames"),
+        Arg::with_name("rename-limit")
+            .help("Only look for similar files among up to <n> added and deleted files, instead of diff.renameLimit.")
+            .short("l")
+            .takes_value(true)
+            .value_name("n"),
+        Arg::with_name("exit-code")
+            .help("Exit with 1 if there are changes and 0 otherwise.")
+            .long("exit-code"),
+        Arg::with_name("quiet")
+            .help("Show nothing, and exit like --exit-code.")
+            .long("quiet"),
+    ]
+}
@@ -1,59 +1,63 @@
 use std::ffi::OsString;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::str::FromStr;
 
-use flate2::read::ZlibDecoder;
-use sha1::{Digest, Sha1};
-
+use super::ObjectType;
 use crate::{self as gitrs, GitFileMode, GitObject, GitResult};
 
 /// A git blob object.
 #[derive(Debug)]
 pub struct GitBlob {
-    data: String,
+    data: Vec<u8>,
     size: usize,
     filename: Option<OsString>,
     filemode: Option<GitFileMode>,
 }
 
+impl GitBlob {
+    /// Returns the name of the file this blob was read from, if any.
+    pub fn filename(&self) -> Option<&OsString> {
+        self.filename.as_ref()
+    }
+
+    /// Returns the file mode of the file this blob was read from, if any.
+    pub fn filemode(&self) -> Option<&GitFileMode> {
+        self.filemode.as_ref()
+    }
+}
+
 impl GitObject for GitBlob {
-    fn data(&self) -> &str {
-        self.data.as_str()
+    fn object_type() -> ObjectType {
+        ObjectType::Blob
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
     }
 
     fn fmt(&self) -> &'static str {
         "blob"
     }
 
-    fn from_data(data: &str) -> Self
+    fn from_data(data: &[u8]) -> GitResult<Self>
     where
         Self: Sized,
     {
-        Self {
-            data: data.to_string(),
+        Ok(Self {
+            data: data.to_vec(),
             size: data.len(),
             filename: None,
             filemode: None,
-        }
+        })
     }
 
     fn from_object_file<P: AsRef<Path>>(path: P) -> GitResult<Self> {
-        let file = gitrs::to_git_result(File::open(&path), &path)?;
-        let mut data = String::new();
-        ZlibDecoder::new(&file).read_to_string(&mut data).unwrap();
-
-        // Find delimiters
-        let d1 = data.find(' ').unwrap();
-        let d2 = data[d1 + 1..].find('\x00').unwrap();
-
-        // Remove header from data
-        let data = data[d2 + 1..].to_string();
+        let data = super::read_typed_object_file(&path, ObjectType::Blob)?;
 
         // Read file mode
-        let mode = gitrs::to_git_result(file.metadata(), &path)?
+        let mode = gitrs::to_git_result(fs::metadata(&path), &path)?
             .permissions()
             .mode();
 
@@ -66,8 +70,8 @@ impl GitObject for GitBlob {
         })
     }
 
-    fn serialize(&self) -> String {
-        format!("{} {}\x00{}", self.fmt(), self.size, self.data)
+    fn serialize(&self) -> Vec<u8> {
+        super::serialize_object(ObjectType::Blob, &self.data)
     }
 
     fn size(&self) -> usize {
@@ -75,6 +79,6 @@ impl GitObject for GitBlob {
     }
 
     fn to_sha1(&self) -> String {
-        hex::encode(Sha1::digest(self.serialize().as_bytes()))
+        super::hash_object(ObjectType::Blob, &self.data)
     }
 }
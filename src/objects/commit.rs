@@ -0,0 +1,184 @@
+use std::path::Path;
+
+use super::ObjectType;
+use crate::{GitObject, GitResult, GitSignature};
+
+/// A git commit object.
+#[derive(Debug, Clone)]
+pub struct GitCommit {
+    tree: String,
+    parents: Vec<String>,
+    author: GitSignature,
+    committer: GitSignature,
+    encoding: Option<String>,
+    extra_headers: Vec<(String, String)>,
+    message: String,
+    data: Vec<u8>,
+}
+
+impl GitCommit {
+    /// Creates a commit of `tree` with the given parents, identities and message.
+    pub fn new(
+        tree: &str,
+        parents: &[String],
+        author: GitSignature,
+        committer: GitSignature,
+        message: &str,
+    ) -> Self {
+        let mut data = format!("tree {}\n", tree);
+        for parent in parents {
+            data.push_str(&format!("parent {}\n", parent));
+        }
+        data.push_str(&format!(
+            "author {}\ncommitter {}\n\n{}",
+            author, committer, message
+        ));
+
+        Self {
+            tree: tree.to_string(),
+            parents: parents.to_vec(),
+            author,
+            committer,
+            encoding: None,
+            extra_headers: Vec::new(),
+            message: message.to_string(),
+            data: data.into_bytes(),
+        }
+    }
+
+    /// The Sha1 hash of the commit's tree.
+    pub fn tree(&self) -> &str {
+        &self.tree
+    }
+
+    /// The Sha1 hashes of the commit's parents.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    /// The author of the commit.
+    pub fn author(&self) -> &GitSignature {
+        &self.author
+    }
+
+    /// The committer of the commit.
+    pub fn committer(&self) -> &GitSignature {
+        &self.committer
+    }
+
+    /// The encoding of the message, if it is not UTF-8.
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    /// Headers other than tree, parent, author, committer and encoding, e.g. `gpgsig`.
+    pub fn extra_headers(&self) -> &[(String, String)] {
+        &self.extra_headers
+    }
+
+    /// The full commit message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The subject of the message: its first paragraph joined into a single line.
+    pub fn summary(&self) -> String {
+        let message = self.message.trim_start_matches('\n');
+        message
+            .split("\n\n")
+            .next()
+            .unwrap_or("")
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The message without its subject paragraph.
+    pub fn body(&self) -> &str {
+        let message = self.message.trim_start_matches('\n');
+        match message.find("\n\n") {
+            Some(i) => message[i..].trim_start_matches('\n'),
+            None => "",
+        }
+    }
+}
+
+impl GitObject for GitCommit {
+    fn object_type() -> ObjectType {
+        ObjectType::Commit
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn fmt(&self) -> &'static str {
+        "commit"
+    }
+
+    fn from_data(data: &[u8]) -> GitResult<Self> {
+        let text = String::from_utf8_lossy(data);
+        let (headers, message) = match text.find("\n\n") {
+            Some(i) => (&text[..i], &text[i + 2..]),
+            None => (text.trim_end_matches('\n'), ""),
+        };
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut encoding = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
+        for line in headers.lines() {
+            // Continuation lines of multi-line headers start with a space.
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = extra_headers.last_mut() {
+                    value.push('\n');
+                    value.push_str(continuation);
+                }
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => (line, ""),
+            };
+            match key {
+                "tree" => tree = Some(value.to_string()),
+                "parent" => parents.push(value.to_string()),
+                "author" => author = GitSignature::parse(value),
+                "committer" => committer = GitSignature::parse(value),
+                "encoding" => encoding = Some(value.to_string()),
+                _ => extra_headers.push((key.to_string(), value.to_string())),
+            }
+        }
+
+        let error = |message| super::parse_error(ObjectType::Commit, message);
+        Ok(Self {
+            tree: tree.ok_or_else(|| error("missing tree"))?,
+            parents,
+            author: author.ok_or_else(|| error("missing author"))?,
+            committer: committer.ok_or_else(|| error("missing committer"))?,
+            encoding,
+            extra_headers,
+            message: message.to_string(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn from_object_file<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        Self::from_data(&super::read_typed_object_file(path, ObjectType::Commit)?)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        super::serialize_object(ObjectType::Commit, &self.data)
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn to_sha1(&self) -> String {
+        super::hash_object(ObjectType::Commit, &self.data)
+    }
+}
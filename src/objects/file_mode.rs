@@ -1,5 +1,11 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs::Metadata;
+use std::os::unix::fs::PermissionsExt;
+
+use crate::{GitError, GitResult};
+
 /// A representation of file permissions.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct GitFileMode {
     file_type: u8,
     owner_mode: u8,
@@ -27,6 +33,110 @@ impl GitFileMode {
     pub fn other_mode(&self) -> u8 {
         self.other_mode
     }
+
+    /// The mode of a regular, non-executable file (`100644`).
+    pub fn regular() -> Self {
+        Self::from("100644")
+    }
+
+    /// The mode of an executable file (`100755`).
+    pub fn executable() -> Self {
+        Self::from("100755")
+    }
+
+    /// The mode of a symbolic link (`120000`).
+    pub fn symlink() -> Self {
+        Self::from("120000")
+    }
+
+    /// The mode of a submodule commit (`160000`).
+    pub fn gitlink() -> Self {
+        Self::from("160000")
+    }
+
+    /// The mode of a subtree (`040000`).
+    pub fn tree() -> Self {
+        Self::from("040000")
+    }
+
+    /// Returns the mode git records for a file in the worktree.
+    ///
+    /// Without `trust_executable_bit` (`core.filemode = false`) every regular file is recorded as
+    /// non-executable.
+    pub fn from_metadata(metadata: &Metadata, trust_executable_bit: bool) -> Self {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            Self::symlink()
+        } else if file_type.is_dir() {
+            Self::tree()
+        } else if trust_executable_bit && metadata.permissions().mode() & 0o100 != 0 {
+            Self::executable()
+        } else {
+            Self::regular()
+        }
+    }
+
+    /// Returns the mode as the octal number git stores in the index, e.g. `0o100644`.
+    pub fn to_octal(self) -> u32 {
+        u32::from_str_radix(&self.to_string(), 8).unwrap()
+    }
+
+    /// Returns the mode from the octal number git stores in the index.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::FileModeError]: The mode has more than six octal digits
+    pub fn from_octal(mode: u32) -> GitResult<Self> {
+        if mode > 0o177777 {
+            return Err(GitError::FileModeError { mode });
+        }
+        Ok(Self::from(format!("{:06o}", mode).as_str()))
+    }
+
+    /// Returns true for regular files, executable or not.
+    pub fn is_file(&self) -> bool {
+        self.file_type == 10
+    }
+
+    /// Returns true for executable regular files.
+    pub fn is_executable(&self) -> bool {
+        self.is_file() && self.owner_mode & 1 != 0
+    }
+
+    /// Returns true for symbolic links.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == 12
+    }
+
+    /// Returns true for submodule commits.
+    pub fn is_gitlink(&self) -> bool {
+        self.file_type == 16
+    }
+
+    /// Returns true for subtrees.
+    pub fn is_tree(&self) -> bool {
+        self.file_type == 4
+    }
+
+    /// Returns the permission bits a checked out file with this mode is created with, before the
+    /// umask is applied.
+    pub fn permissions(&self) -> u32 {
+        if self.is_executable() {
+            0o777
+        } else {
+            0o666
+        }
+    }
+}
+
+impl Display for GitFileMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}{:02}{}{}",
+            self.file_type, self.owner_mode, self.group_mode, self.other_mode
+        )
+    }
 }
 
 impl From<u32> for GitFileMode {
@@ -45,13 +155,13 @@ impl From<u32> for GitFileMode {
     }
 }
 
-impl Into<u32> for GitFileMode {
-    fn into(self) -> u32 {
+impl From<GitFileMode> for u32 {
+    fn from(mode: GitFileMode) -> u32 {
         let (t, own, grp, oth) = (
-            self.file_type as u32,
-            self.owner_mode as u32,
-            self.group_mode as u32,
-            self.other_mode as u32,
+            mode.file_type as u32,
+            mode.owner_mode as u32,
+            mode.group_mode as u32,
+            mode.other_mode as u32,
         );
         (t << 14) | (own << 5) | (grp << 2) | oth
     }
@@ -59,6 +169,9 @@ impl Into<u32> for GitFileMode {
 
 impl From<&str> for GitFileMode {
     fn from(filemode: &str) -> Self {
+        // Trees are written as "40000" in tree objects.
+        let filemode = format!("{:0>6}", filemode);
+        let filemode = filemode.as_str();
         assert_eq!(filemode.len(), 6);
         let file_type = filemode[..2].parse::<u8>().unwrap();
         let owner_mode = filemode[2..4].parse::<u8>().unwrap();
@@ -1,27 +1,102 @@
 mod blob;
+mod commit;
 mod file_mode;
+mod odb;
+mod pack;
 mod repo;
+mod signature;
+mod tag;
 #[cfg(test)]
 mod tests;
+mod tree;
 
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 
+use flate2::read::ZlibDecoder;
+
 pub use blob::GitBlob;
+pub use commit::GitCommit;
 pub use file_mode::GitFileMode;
+pub use odb::{is_full_sha, GitOdb};
+pub(crate) use pack::apply_delta;
 pub use repo::GitRepo;
+pub use signature::GitSignature;
+pub use tag::GitTag;
+pub use tree::{GitTree, GitTreeEntry};
+
+use crate::{self as gitrs, GitError, GitResult};
+
+/// The four types of objects stored in a git object database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectType {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectType {
+    /// Returns the name used in object headers, e.g. `blob`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectType::Commit => "commit",
+            ObjectType::Tree => "tree",
+            ObjectType::Blob => "blob",
+            ObjectType::Tag => "tag",
+        }
+    }
+
+    /// Returns the type called `name` in object headers.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "commit" => Some(ObjectType::Commit),
+            "tree" => Some(ObjectType::Tree),
+            "blob" => Some(ObjectType::Blob),
+            "tag" => Some(ObjectType::Tag),
+            _ => None,
+        }
+    }
+
+    /// Returns the type with the number used in packfiles.
+    fn from_pack_type(kind: u8) -> Option<Self> {
+        match kind {
+            1 => Some(ObjectType::Commit),
+            2 => Some(ObjectType::Tree),
+            3 => Some(ObjectType::Blob),
+            4 => Some(ObjectType::Tag),
+            _ => None,
+        }
+    }
+}
 
-use crate::GitResult;
+impl Display for ObjectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
 /// A data interface used to serialize and deserialize different types of git objects.
 pub trait GitObject {
+    /// Returns the type of object this implementation represents.
+    fn object_type() -> ObjectType
+    where
+        Self: Sized;
+
     /// Returns the data contained in this object without the header.
-    fn data(&self) -> &str;
+    fn data(&self) -> &[u8];
 
     /// Returns the type of object.
     fn fmt(&self) -> &'static str;
 
     /// Returns an object created from data (without the header).
-    fn from_data(data: &str) -> Self
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: The data can not be parsed as this type of object
+    fn from_data(data: &[u8]) -> GitResult<Self>
     where
         Self: Sized;
 
@@ -35,7 +110,7 @@ pub trait GitObject {
         Self: Sized;
 
     /// Returns the data contained in this object including the header.
-    fn serialize(&self) -> String;
+    fn serialize(&self) -> Vec<u8>;
 
     /// Returns the size of this object.
     fn size(&self) -> usize;
@@ -43,3 +118,72 @@ pub trait GitObject {
     /// Returns the Sha1 hash for this object.
     fn to_sha1(&self) -> String;
 }
+
+/// Reads and inflates a loose object file, returning its type and data without the header.
+///
+/// # Errors
+///
+/// * [GitError::IOError]: The file can not be read
+/// * [GitError::ObjectError]: The file is not a valid loose object
+pub fn read_object_file<P: AsRef<Path>>(path: P) -> GitResult<(ObjectType, Vec<u8>)> {
+    let file = gitrs::to_git_result(File::open(&path), &path)?;
+    let mut data = Vec::new();
+    gitrs::to_git_result(ZlibDecoder::new(file).read_to_end(&mut data), &path)?;
+
+    let corrupt = || GitError::ObjectError {
+        sha: String::new(),
+        message: format!("loose object {} is corrupt", path.as_ref().display()),
+    };
+    let space = data.iter().position(|&b| b == b' ').ok_or_else(corrupt)?;
+    let nul = data.iter().position(|&b| b == 0).ok_or_else(corrupt)?;
+    if nul < space {
+        return Err(corrupt());
+    }
+    let kind = std::str::from_utf8(&data[..space])
+        .ok()
+        .and_then(ObjectType::from_name)
+        .ok_or_else(corrupt)?;
+    let size = std::str::from_utf8(&data[space + 1..nul])
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .ok_or_else(corrupt)?;
+    if size != data.len() - nul - 1 {
+        return Err(corrupt());
+    }
+
+    Ok((kind, data.split_off(nul + 1)))
+}
+
+/// Reads a loose object file that is expected to contain an object of type `kind`.
+fn read_typed_object_file<P: AsRef<Path>>(path: P, kind: ObjectType) -> GitResult<Vec<u8>> {
+    let (found, data) = read_object_file(&path)?;
+    if found != kind {
+        return Err(GitError::ObjectError {
+            sha: String::new(),
+            message: format!("{} is a {}, not a {}", path.as_ref().display(), found, kind),
+        });
+    }
+    Ok(data)
+}
+
+/// Returns the header and data of an object as it is hashed and stored.
+fn serialize_object(kind: ObjectType, data: &[u8]) -> Vec<u8> {
+    let mut serialized = format!("{} {}\x00", kind, data.len()).into_bytes();
+    serialized.extend_from_slice(data);
+    serialized
+}
+
+/// Returns the Sha1 hash of an object with type `kind` and contents `data`.
+pub fn hash_object(kind: ObjectType, data: &[u8]) -> String {
+    use sha1::{Digest, Sha1};
+
+    hex::encode(Sha1::digest(&serialize_object(kind, data)))
+}
+
+/// Returns an [GitError::ObjectError] for an object that could not be parsed.
+fn parse_error(kind: ObjectType, message: &str) -> GitError {
+    GitError::ObjectError {
+        sha: String::new(),
+        message: format!("invalid {} object: {}", kind, message),
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::pack::Pack;
+use super::ObjectType;
+use crate::{self as gitrs, utils, GitError, GitObject, GitRepo, GitResult};
+
+/// The object database of a repository: loose objects and packfiles in `.git/objects`.
+#[derive(Debug)]
+pub struct GitOdb {
+    objects_dir: PathBuf,
+    packs: Vec<Pack>,
+}
+
+impl GitOdb {
+    /// Opens the object database of `repo` and reads the indexes of its packfiles.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The pack directory or a pack index can not be read
+    /// * [GitError::ObjectError]: A pack index is corrupt
+    pub fn open(repo: &GitRepo) -> GitResult<Self> {
+        Self::from_dir(repo.gitpath().join("objects"))
+    }
+
+    /// Opens the object database stored in `objects_dir`.
+    ///
+    /// # Errors
+    ///
+    /// See [GitOdb::open].
+    pub fn from_dir<P: AsRef<Path>>(objects_dir: P) -> GitResult<Self> {
+        let objects_dir = objects_dir.as_ref().to_path_buf();
+        let pack_dir = objects_dir.join("pack");
+        let mut packs = Vec::new();
+        if pack_dir.is_dir() {
+            let mut idx_paths = Vec::new();
+            for entry in gitrs::to_git_result(fs::read_dir(&pack_dir), &pack_dir)? {
+                let path = gitrs::to_git_result(entry, &pack_dir)?.path();
+                if path.extension().is_some_and(|ext| ext == "idx") {
+                    idx_paths.push(path);
+                }
+            }
+            idx_paths.sort();
+            for path in idx_paths {
+                packs.push(Pack::open(&path)?);
+            }
+        }
+
+        Ok(Self { objects_dir, packs })
+    }
+
+    /// Returns the directory the loose objects are stored in.
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    /// Returns true if the object `sha` exists.
+    pub fn contains(&self, sha: &str) -> bool {
+        if !is_full_sha(sha) {
+            return false;
+        }
+        utils::get_object_path(&self.objects_dir, sha).is_file() || self.find_packed(sha).is_some()
+    }
+
+    fn find_packed(&self, sha: &str) -> Option<(&Pack, u64)> {
+        let mut raw = [0u8; 20];
+        hex::decode_to_slice(sha, &mut raw).ok()?;
+        self.packs
+            .iter()
+            .find_map(|pack| pack.find(&raw).map(|offset| (pack, offset)))
+    }
+
+    /// Reads the object `sha`, returning its type and data.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: The object does not exist or is corrupt
+    /// * [GitError::IOError]: The object can not be read
+    pub fn read(&self, sha: &str) -> GitResult<(ObjectType, Vec<u8>)> {
+        if !is_full_sha(sha) {
+            return Err(missing(sha));
+        }
+        let path = utils::get_object_path(&self.objects_dir, sha);
+        if path.is_file() {
+            return super::read_object_file(path);
+        }
+        match self.find_packed(sha) {
+            Some((pack, offset)) => pack.read_at(offset, self),
+            None => Err(missing(sha)),
+        }
+    }
+
+    /// Returns only the type of the object `sha`.
+    ///
+    /// # Errors
+    ///
+    /// See [GitOdb::read].
+    pub fn read_type(&self, sha: &str) -> GitResult<ObjectType> {
+        Ok(self.read(sha)?.0)
+    }
+
+    /// Reads and parses the object `sha` as a `T`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: The object does not exist, has another type or can not be parsed
+    /// * [GitError::IOError]: The object can not be read
+    pub fn read_as<T: GitObject>(&self, sha: &str) -> GitResult<T> {
+        let (kind, data) = self.read(sha)?;
+        if kind != T::object_type() {
+            return Err(GitError::ObjectError {
+                sha: sha.to_string(),
+                message: format!("object {} is a {}, not a {}", sha, kind, T::object_type()),
+            });
+        }
+        T::from_data(&data).map_err(|error| match error {
+            GitError::ObjectError { sha: _, message } => GitError::ObjectError {
+                sha: sha.to_string(),
+                message: format!("{} ({})", message, sha),
+            },
+            error => error,
+        })
+    }
+
+    /// Writes an object as a loose object and returns its Sha1 hash.
+    ///
+    /// Objects that already exist are not written again.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The object file can not be written
+    pub fn write(&self, kind: ObjectType, data: &[u8]) -> GitResult<String> {
+        let sha = super::hash_object(kind, data);
+        if self.contains(&sha) {
+            return Ok(sha);
+        }
+
+        let path = utils::get_object_path(&self.objects_dir, &sha);
+        let dir = path.parent().unwrap();
+        utils::create_dir_all_if_new(dir)?;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        gitrs::to_git_result(
+            encoder.write_all(&super::serialize_object(kind, data)),
+            &path,
+        )?;
+        let compressed = gitrs::to_git_result(encoder.finish(), &path)?;
+
+        // Write to a temporary file first so readers never see a partial object.
+        let tmp_path = dir.join(format!("tmp_obj_{}", std::process::id()));
+        let result = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .and_then(|mut file| file.write_all(&compressed));
+        gitrs::to_git_result(result, &tmp_path)?;
+        let mut permissions =
+            gitrs::to_git_result(fs::metadata(&tmp_path), &tmp_path)?.permissions();
+        permissions.set_readonly(true);
+        gitrs::to_git_result(fs::set_permissions(&tmp_path, permissions), &tmp_path)?;
+        match fs::rename(&tmp_path, &path) {
+            Err(error) if error.kind() != ErrorKind::AlreadyExists => {
+                let _ = fs::remove_file(&tmp_path);
+                Err(GitError::IOError { error, path })
+            }
+            _ => Ok(sha),
+        }
+    }
+
+    /// Writes `object` to the database and returns its Sha1 hash.
+    ///
+    /// # Errors
+    ///
+    /// See [GitOdb::write].
+    pub fn write_object<T: GitObject>(&self, object: &T) -> GitResult<String> {
+        self.write(T::object_type(), object.data())
+    }
+
+    /// Returns every object whose name starts with the hex string `prefix`, sorted.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: A loose object directory can not be read
+    pub fn find_prefix(&self, prefix: &str) -> GitResult<Vec<String>> {
+        let prefix = prefix.to_ascii_lowercase();
+        if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(Vec::new());
+        }
+
+        let mut found = Vec::new();
+        let dirs: Vec<String> = if prefix.len() >= 2 {
+            vec![prefix[..2].to_string()]
+        } else {
+            (0..=255u8)
+                .map(|b| format!("{:02x}", b))
+                .filter(|dir| dir.starts_with(&prefix))
+                .collect()
+        };
+        for dir in dirs {
+            let path = self.objects_dir.join(&dir);
+            if !path.is_dir() {
+                continue;
+            }
+            for entry in gitrs::to_git_result(fs::read_dir(&path), &path)? {
+                let entry = gitrs::to_git_result(entry, &path)?;
+                let sha = format!("{}{}", dir, entry.file_name().to_string_lossy());
+                if is_full_sha(&sha) && sha.starts_with(&prefix) {
+                    found.push(sha);
+                }
+            }
+        }
+        for pack in &self.packs {
+            found.extend(pack.find_prefix(&prefix));
+        }
+
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+
+    /// Returns the names of all objects in the database, sorted.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: A loose object directory can not be read
+    pub fn all_objects(&self) -> GitResult<Vec<String>> {
+        let mut found = Vec::new();
+        for b in 0..=255u8 {
+            found.extend(self.find_prefix(&format!("{:02x}", b))?);
+        }
+        Ok(found)
+    }
+
+    /// Returns the names of all objects stored in packfiles, sorted.
+    pub fn packed_objects(&self) -> Vec<String> {
+        let mut found: Vec<String> = self.packs.iter().flat_map(Pack::objects).collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+}
+
+/// Returns true if `sha` is a full, lowercase hex object name.
+pub fn is_full_sha(sha: &str) -> bool {
+    sha.len() == 40 && sha.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn missing(sha: &str) -> GitError {
+    GitError::ObjectError {
+        sha: sha.to_string(),
+        message: format!("unable to read {}", sha),
+    }
+}
@@ -0,0 +1,275 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+
+use crate::objects::{GitOdb, ObjectType};
+use crate::{self as gitrs, utils, GitError, GitResult};
+
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// A packfile and its version 2 index.
+#[derive(Debug)]
+pub struct Pack {
+    pack_path: PathBuf,
+    fanout: Vec<u32>,
+    shas: Vec<[u8; 20]>,
+    offsets: Vec<u64>,
+}
+
+impl Pack {
+    /// Reads the index at `idx_path`; the pack is expected next to it with a `.pack` extension.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The index can not be read
+    /// * [GitError::ObjectError]: The index is corrupt or not version 2
+    pub fn open(idx_path: &Path) -> GitResult<Self> {
+        let data = utils::read_bytes(idx_path)?;
+        let corrupt = || GitError::ObjectError {
+            sha: String::new(),
+            message: format!("index file {} is corrupt", idx_path.display()),
+        };
+        if data.len() < 8 + 256 * 4
+            || data[..4] != [0xff, b't', b'O', b'c']
+            || be32(&data[4..]) != 2
+        {
+            return Err(corrupt());
+        }
+
+        let fanout: Vec<u32> = (0..256).map(|i| be32(&data[8 + i * 4..])).collect();
+        let count = fanout[255] as usize;
+        let shas_start = 8 + 256 * 4;
+        let offsets_start = shas_start + count * 20 + count * 4;
+        let large_start = offsets_start + count * 4;
+        if data.len() < large_start + 40 {
+            return Err(corrupt());
+        }
+
+        let shas = (0..count)
+            .map(|i| {
+                data[shas_start + i * 20..shas_start + (i + 1) * 20]
+                    .try_into()
+                    .unwrap()
+            })
+            .collect();
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = be32(&data[offsets_start + i * 4..]);
+            if offset & 0x8000_0000 != 0 {
+                let index = large_start + (offset & 0x7fff_ffff) as usize * 8;
+                if data.len() < index + 8 {
+                    return Err(corrupt());
+                }
+                offsets.push(u64::from_be_bytes(
+                    data[index..index + 8].try_into().unwrap(),
+                ));
+            } else {
+                offsets.push(offset as u64);
+            }
+        }
+
+        Ok(Self {
+            pack_path: idx_path.with_extension("pack"),
+            fanout,
+            shas,
+            offsets,
+        })
+    }
+
+    /// Returns the offset of an object in the pack.
+    pub fn find(&self, sha: &[u8; 20]) -> Option<u64> {
+        let (start, end) = self.fanout_range(sha[0]);
+        self.shas[start..end]
+            .binary_search(sha)
+            .ok()
+            .map(|i| self.offsets[start + i])
+    }
+
+    /// Returns every object name in the pack starting with the hex `prefix`.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        let first = match u8::from_str_radix(&format!("{:0<2}", &prefix[..prefix.len().min(2)]), 16)
+        {
+            Ok(byte) => byte,
+            Err(_) => return Vec::new(),
+        };
+        let (start, end) = if prefix.len() >= 2 {
+            self.fanout_range(first)
+        } else {
+            (0, self.shas.len())
+        };
+        self.shas[start..end]
+            .iter()
+            .map(hex::encode)
+            .filter(|sha| sha.starts_with(prefix))
+            .collect()
+    }
+
+    /// Returns the names of all objects in the pack, sorted.
+    pub fn objects(&self) -> Vec<String> {
+        self.shas.iter().map(hex::encode).collect()
+    }
+
+    fn fanout_range(&self, first: u8) -> (usize, usize) {
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first as usize - 1] as usize
+        };
+        (start, self.fanout[first as usize] as usize)
+    }
+
+    /// Reads and resolves the object at `offset`.
+    ///
+    /// Deltas against objects outside of the pack are resolved through `odb`.
+    pub fn read_at(&self, offset: u64, odb: &GitOdb) -> GitResult<(ObjectType, Vec<u8>)> {
+        let file = gitrs::to_git_result(File::open(&self.pack_path), &self.pack_path)?;
+        let mut reader = BufReader::new(file);
+        self.read_with(&mut reader, offset, odb, 0)
+    }
+
+    fn read_with(
+        &self,
+        reader: &mut BufReader<File>,
+        offset: u64,
+        odb: &GitOdb,
+        depth: usize,
+    ) -> GitResult<(ObjectType, Vec<u8>)> {
+        let path = &self.pack_path;
+        let corrupt = |message: &str| GitError::ObjectError {
+            sha: String::new(),
+            message: format!("packfile {} is corrupt: {}", path.display(), message),
+        };
+        if depth > 10_000 {
+            return Err(corrupt("delta chain too long"));
+        }
+
+        gitrs::to_git_result(reader.seek(SeekFrom::Start(offset)), path)?;
+        let mut byte = read_byte(reader, path)?;
+        let kind = (byte >> 4) & 7;
+        let mut size = (byte & 15) as u64;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = read_byte(reader, path)?;
+            size |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+
+        match kind {
+            OBJ_OFS_DELTA => {
+                let mut byte = read_byte(reader, path)?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = read_byte(reader, path)?;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                let delta = inflate(reader, size, path)?;
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| corrupt("invalid delta base offset"))?;
+                let (kind, base) = self.read_with(reader, base_offset, odb, depth + 1)?;
+                let data = apply_delta(&base, &delta).ok_or_else(|| corrupt("invalid delta"))?;
+                Ok((kind, data))
+            }
+            OBJ_REF_DELTA => {
+                let mut base_sha = [0u8; 20];
+                gitrs::to_git_result(reader.read_exact(&mut base_sha), path)?;
+                let delta = inflate(reader, size, path)?;
+                let (kind, base) = match self.find(&base_sha) {
+                    Some(base_offset) => self.read_with(reader, base_offset, odb, depth + 1)?,
+                    None => odb.read(&hex::encode(base_sha))?,
+                };
+                let data = apply_delta(&base, &delta).ok_or_else(|| corrupt("invalid delta"))?;
+                Ok((kind, data))
+            }
+            _ => {
+                let kind = ObjectType::from_pack_type(kind)
+                    .ok_or_else(|| corrupt("unknown object type"))?;
+                Ok((kind, inflate(reader, size, path)?))
+            }
+        }
+    }
+}
+
+fn be32(data: &[u8]) -> u32 {
+    u32::from_be_bytes(data[..4].try_into().unwrap())
+}
+
+fn read_byte(reader: &mut BufReader<File>, path: &Path) -> GitResult<u8> {
+    let mut byte = [0u8];
+    gitrs::to_git_result(reader.read_exact(&mut byte), path)?;
+    Ok(byte[0])
+}
+
+fn inflate(reader: &mut BufReader<File>, size: u64, path: &Path) -> GitResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(size as usize);
+    let decoder = ZlibDecoder::new(reader);
+    gitrs::to_git_result(decoder.take(size).read_to_end(&mut data), path)?;
+    Ok(data)
+}
+
+/// Reads a little endian base 128 size from a delta header.
+fn delta_size(delta: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos)?;
+        *pos += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(size);
+        }
+    }
+}
+
+/// Applies a git delta to `base`. Returns [None] if the delta is malformed.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = delta_size(delta, &mut pos)?;
+    let result_size = delta_size(delta, &mut pos)?;
+    if base_size != base.len() {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset.checked_add(size)?)?);
+        } else if op != 0 {
+            let size = op as usize;
+            result.extend_from_slice(delta.get(pos..pos + size)?);
+            pos += size;
+        } else {
+            return None;
+        }
+    }
+
+    if result.len() == result_size {
+        Some(result)
+    } else {
+        None
+    }
+}
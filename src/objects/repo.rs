@@ -1,67 +1,197 @@
-use std::env::{self, VarError};
+use std::env;
 use std::ffi::OsString;
-use std::path::{Path, PathBuf};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
 
-use clap::ArgMatches;
+use crate::config::{self, ConfigFile, GitConfig};
+use crate::init::{self, InitOptions, InitReport};
+use crate::{self as gitrs, utils, GitError, GitIndex, GitOdb, GitRefs, GitResult};
 
-use crate::{GitError, GitResult};
+/// Repository extensions that are understood in any repository format version.
+const EXTENSIONS_V0: [&str; 4] = ["noop", "preciousobjects", "partialclone", "worktreeconfig"];
+
+/// Repository extensions that are only valid with `core.repositoryformatversion = 1`.
+const EXTENSIONS_V1: [&str; 1] = ["objectformat"];
+
+/// Looks up an environment variable, like [env::var_os].
+pub(crate) type Env<'a> = dyn Fn(&str) -> Option<OsString> + 'a;
+
+const NOT_FOUND: &str = "not a git repository (or any of the parent directories): .git";
 
 /// A container for all information about a git repository.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GitRepo {
     worktree: Option<PathBuf>,
     gitpath: PathBuf,
+    prefix: PathBuf,
 }
 
 impl GitRepo {
     /// Creates a container from existing info about the repository.
     pub fn new(worktree: PathBuf, gitpath: PathBuf) -> Self {
         let worktree = Some(worktree);
-        Self { worktree, gitpath }
+        Self {
+            worktree,
+            gitpath,
+            prefix: PathBuf::new(),
+        }
+    }
+
+    /// Creates a container for a repository with or without a worktree.
+    pub(crate) fn with_worktree(worktree: Option<PathBuf>, gitpath: PathBuf) -> Self {
+        Self {
+            worktree,
+            gitpath,
+            prefix: PathBuf::new(),
+        }
     }
 
-    /// Returns the current git repository from command line arguments.
+    /// Opens the repository at `path`, which is either a worktree with a `.git` directory or
+    /// file, or a git directory.
+    ///
+    /// Unlike [GitRepo::discover], parent directories and the environment are not looked at.
     ///
     /// # Errors
     ///
-    /// * [GitError::VarInvalidUnicode]: GIT_DIR environment variable is invalid unicode
-    /// * [GitError::IOError]: Arguments contain a path to an invalid directory
-    pub fn from_args(matches: &ArgMatches) -> GitResult<GitRepo> {
-        // Get GIT_DIR environment variable
-        let gitpath = match env::var("GIT_DIR") {
-            Ok(dir) => dir,
-            Err(VarError::NotPresent) => ".git".to_string(),
-            Err(VarError::NotUnicode(dir)) => {
-                return Err(GitError::VarInvalidUnicode {
-                    var: OsString::from("GIT_DIR"),
-                    data: dir,
-                });
+    /// * [GitError::RepositoryError]: `path` is not a repository, a `.git` file is invalid, or
+    ///   the repository format is not supported
+    /// * [GitError::IOError]: `path` can not be resolved
+    pub fn open<P: AsRef<Path>>(path: P) -> GitResult<GitRepo> {
+        let path = path.as_ref();
+        let abs_dir = gitrs::to_git_result(path.canonicalize(), path)?;
+        match find_at(path, None)? {
+            Some((worktree, gitpath)) => Self::with_prefix(worktree, gitpath, &abs_dir),
+            None => Err(not_a_repository(&format!(
+                "not a git repository: '{}'",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Creates a repository, or reinitializes an existing one, as described by `options`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RepositoryError]: The initial branch name is invalid
+    /// * [GitError::ConfigError]: A config file can not be parsed
+    /// * [GitError::IOError]: A directory or file can not be created, copied or moved
+    pub fn init(options: &InitOptions) -> GitResult<GitRepo> {
+        Ok(init::init(options)?.repo)
+    }
+
+    /// Creates or reinitializes a repository like [GitRepo::init], and also tells whether it
+    /// existed and what to warn about.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRepo::init].
+    pub fn init_with_report(options: &InitOptions) -> GitResult<InitReport> {
+        init::init(options)
+    }
+
+    /// Finds the repository containing `dir` like git does: through `$GIT_DIR`, or by looking
+    /// for a `.git` directory or file, or a bare repository, in `dir` and its parents.
+    ///
+    /// The search stops below the directories in `$GIT_CEILING_DIRECTORIES`, and at
+    /// filesystem boundaries unless `$GIT_DISCOVERY_ACROSS_FILESYSTEM` is set. The worktree
+    /// can be overridden with `$GIT_WORK_TREE` or `core.worktree`. Returned paths are relative
+    /// to `dir` if it is relative.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RepositoryError]: No repository was found, a `.git` file is invalid, or the
+    ///   repository format is not supported
+    /// * [GitError::VarInvalidUnicode]: An environment variable is invalid unicode
+    /// * [GitError::IOError]: `dir` can not be resolved
+    pub fn discover(dir: &Path) -> GitResult<GitRepo> {
+        match Self::discover_gently(dir)? {
+            Some(repo) => Ok(repo),
+            None => Err(not_found(dir)?),
+        }
+    }
+
+    /// Finds the repository containing `dir` like [GitRepo::discover], returning [None] if
+    /// there is none or `$GIT_DIR` is not a repository.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRepo::discover].
+    pub fn discover_gently(dir: &Path) -> GitResult<Option<GitRepo>> {
+        Self::discover_with_env(dir, &process_env)
+    }
+
+    /// Finds the repository containing `dir` like [GitRepo::discover_gently], taking the
+    /// environment variables from `env`.
+    pub(crate) fn discover_with_env(dir: &Path, env: &Env) -> GitResult<Option<GitRepo>> {
+        let abs_dir = gitrs::to_git_result(dir.canonicalize(), dir)?;
+        let work_tree_env = env_path(env, "GIT_WORK_TREE")?.map(|path| join(dir, path));
+
+        if let Some(gitpath) = env_path(env, "GIT_DIR")? {
+            let gitpath = join(dir, gitpath);
+            if !is_git_directory(&gitpath) {
+                return Ok(None);
             }
-        };
+            check_repository_format(&gitpath)?;
+            // Without any worktree setting, the current directory is the top of the worktree.
+            let default = Some(dir.to_path_buf());
+            let worktree = configured_worktree(&gitpath, work_tree_env, default)?;
+            return Ok(Some(Self::with_prefix(worktree, gitpath, &abs_dir)?));
+        }
 
-        // TODO: Add -git-dir to command line args and parse directly here
+        let ceilings = ceiling_directories(env)?;
+        let across_filesystems = env("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+            .is_some_and(|value| config::is_true(&value.to_string_lossy()));
+        let device = gitrs::to_git_result(fs::metadata(&abs_dir), &abs_dir)?.dev();
 
-        // Get worktree from 'git init' args.
-        let worktree = ".";
-        let worktree = if let Some(sub_m) = matches.subcommand_matches("init") {
-            if let Some(dir) = sub_m.value_of("directory") {
-                dir
-            } else {
-                worktree
+        let mut current = abs_dir.clone();
+        let mut relative = dir.to_path_buf();
+        loop {
+            if let Some((worktree, gitpath)) = find_at(&relative, work_tree_env.clone())? {
+                return Ok(Some(Self::with_prefix(worktree, gitpath, &abs_dir)?));
             }
-        } else {
-            worktree
-        };
-        let worktree = PathBuf::from(worktree);
 
-        let gitpath = PathBuf::from(gitpath);
-        let gitpath = if !gitpath.is_absolute() {
-            worktree.join(gitpath)
-        } else {
-            gitpath
-        };
+            let parent = match current.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => return Ok(None),
+            };
+            // Ceiling directories themselves are never searched.
+            if ceilings.contains(&parent) {
+                return Ok(None);
+            }
+            if !across_filesystems {
+                let parent_device = gitrs::to_git_result(fs::metadata(&parent), &parent)?.dev();
+                if parent_device != device {
+                    return Err(not_a_repository(&format!(
+                        "not a git repository (or any parent up to mount point {})\n\
+                         Stopping at filesystem boundary (GIT_DISCOVERY_ACROSS_FILESYSTEM not set).",
+                        current.display()
+                    )));
+                }
+            }
+            current = parent;
+            relative = join(&relative, "..");
+        }
+    }
 
-        Ok(GitRepo::new(worktree, gitpath))
+    /// Creates the repository for a search that started in `abs_dir`, computing the prefix of
+    /// `abs_dir` in the worktree.
+    fn with_prefix(worktree: Option<PathBuf>, gitpath: PathBuf, abs_dir: &Path) -> GitResult<Self> {
+        let prefix = match &worktree {
+            Some(worktree) => {
+                let abs_worktree = gitrs::to_git_result(worktree.canonicalize(), worktree)?;
+                abs_dir
+                    .strip_prefix(&abs_worktree)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default()
+            }
+            None => PathBuf::new(),
+        };
+        Ok(Self {
+            worktree,
+            gitpath,
+            prefix,
+        })
     }
 
     /// Returns a [Path] to the git directory of this repository.
@@ -73,4 +203,350 @@ impl GitRepo {
     pub fn worktree(&self) -> Option<&Path> {
         self.worktree.as_deref()
     }
+
+    /// Opens the object database of this repository.
+    ///
+    /// # Errors
+    ///
+    /// See [GitOdb::open].
+    pub fn odb(&self) -> GitResult<GitOdb> {
+        GitOdb::open(self)
+    }
+
+    /// Returns the references of this repository.
+    pub fn refs(&self) -> GitRefs {
+        GitRefs::new(self)
+    }
+
+    /// Reads the index of this repository.
+    ///
+    /// # Errors
+    ///
+    /// See [GitIndex::read].
+    pub fn index(&self) -> GitResult<GitIndex> {
+        GitIndex::read(self)
+    }
+
+    /// Loads the configuration of this repository from every config layer.
+    ///
+    /// # Errors
+    ///
+    /// See [GitConfig::load].
+    pub fn config(&self) -> GitResult<GitConfig> {
+        GitConfig::load(Some(self))
+    }
+
+    /// Makes the repository bare, like `--bare` on the command line.
+    pub fn set_bare(&mut self) {
+        self.worktree = None;
+        self.prefix = PathBuf::new();
+    }
+
+    /// Returns true if the repository has no worktree.
+    pub fn is_bare(&self) -> bool {
+        self.worktree.is_none()
+    }
+
+    /// Returns the directory the repository was discovered from, relative to the top of the
+    /// worktree. It is empty at the top.
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Converts `path`, relative to the directory the repository was discovered from, to a
+    /// path relative to the top of the worktree, like git does for paths on the command line.
+    ///
+    /// Returns `.` for the top itself. A trailing slash is kept.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RepositoryError]: The path is outside the worktree
+    pub fn worktree_path(&self, path: &str) -> GitResult<String> {
+        let outside = || {
+            let top = self
+                .worktree
+                .as_ref()
+                .and_then(|worktree| worktree.canonicalize().ok())
+                .unwrap_or_default();
+            GitError::RepositoryError {
+                path: PathBuf::from(path),
+                message: format!(
+                    "{}: '{}' is outside repository at '{}'",
+                    path,
+                    path,
+                    top.display()
+                ),
+            }
+        };
+
+        let mut parts: Vec<String> = Vec::new();
+        let relative = if Path::new(path).is_absolute() {
+            let top = self
+                .worktree
+                .as_ref()
+                .and_then(|worktree| worktree.canonicalize().ok())
+                .ok_or_else(outside)?;
+            Path::new(path)
+                .strip_prefix(&top)
+                .map_err(|_| outside())?
+                .to_path_buf()
+        } else {
+            self.prefix.join(path)
+        };
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => parts.push(name.to_string_lossy().into_owned()),
+                Component::ParentDir => {
+                    parts.pop().ok_or_else(outside)?;
+                }
+                _ => {}
+            }
+        }
+
+        let mut normalized = parts.join("/");
+        if normalized.is_empty() {
+            normalized.push('.');
+        } else if path.ends_with('/') {
+            normalized.push('/');
+        }
+        Ok(normalized)
+    }
+}
+
+/// Returns the worktree and git directory of the repository at `dir`, without looking at
+/// its parents.
+fn find_at(
+    dir: &Path,
+    work_tree_env: Option<PathBuf>,
+) -> GitResult<Option<(Option<PathBuf>, PathBuf)>> {
+    let dot_git = join(dir, ".git");
+    let gitpath = match read_gitfile(&dot_git)? {
+        Some(gitpath) => Some(gitpath),
+        None if is_git_directory(&dot_git) => Some(dot_git),
+        None => None,
+    };
+    if let Some(gitpath) = gitpath {
+        check_repository_format(&gitpath)?;
+        let worktree = configured_worktree(&gitpath, work_tree_env, Some(dir.to_path_buf()))?;
+        return Ok(Some((worktree, gitpath)));
+    }
+    if is_git_directory(dir) {
+        // A bare repository, or the inside of a `.git` directory.
+        check_repository_format(dir)?;
+        let worktree = configured_worktree(dir, work_tree_env, None)?;
+        return Ok(Some((worktree, dir.to_path_buf())));
+    }
+    Ok(None)
+}
+
+/// Returns true if `path` looks like a git directory: it has a valid `HEAD`, an object
+/// database and a `refs` directory.
+fn is_git_directory(path: &Path) -> bool {
+    let head = match fs::read_to_string(path.join("HEAD")) {
+        Ok(head) => head,
+        Err(_) => return false,
+    };
+    let head = head.trim_end();
+    let valid_head = head.starts_with("ref: refs/")
+        || (head.len() == 40 && head.chars().all(|ch| ch.is_ascii_hexdigit()));
+    let objects = match env::var_os("GIT_OBJECT_DIRECTORY") {
+        Some(dir) => PathBuf::from(dir),
+        None => path.join("objects"),
+    };
+    valid_head && objects.is_dir() && path.join("refs").is_dir()
+}
+
+/// Returns the git directory a `.git` file at `path` points to, or [None] if `path` is not a
+/// file.
+fn read_gitfile(path: &Path) -> GitResult<Option<PathBuf>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let data = utils::read_file(path)?;
+    let target = match data.trim_end().strip_prefix("gitdir: ") {
+        Some(target) => target,
+        None => {
+            let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            return Err(not_a_repository(&format!(
+                "invalid gitfile format: {}",
+                path.display()
+            )));
+        }
+    };
+    let gitpath = path.parent().unwrap_or(Path::new(".")).join(target);
+    if !is_git_directory(&gitpath) {
+        return Err(not_a_repository(&format!(
+            "not a git repository: {}",
+            target
+        )));
+    }
+    Ok(Some(gitpath))
+}
+
+/// Returns the worktree of the repository at `gitpath`: `$GIT_WORK_TREE`, else
+/// `core.worktree`, else none if `core.bare` is set, else `default`.
+fn configured_worktree(
+    gitpath: &Path,
+    work_tree_env: Option<PathBuf>,
+    default: Option<PathBuf>,
+) -> GitResult<Option<PathBuf>> {
+    if work_tree_env.is_some() {
+        return Ok(work_tree_env);
+    }
+    let config = ConfigFile::open(gitpath.join("config"))?;
+    if let Some(Some(worktree)) = config.get("core.worktree")? {
+        return Ok(Some(gitpath.join(worktree)));
+    }
+    let bare = config.get("core.bare")?;
+    if bare.is_some_and(|value| config::parse_bool(value.as_deref()) == Some(true)) {
+        return Ok(None);
+    }
+    Ok(default)
+}
+
+/// Checks that the repository at `gitpath` has a format version and extensions we support.
+///
+/// # Errors
+///
+/// * [GitError::RepositoryError]: The version is too new or an extension is not supported
+/// * [GitError::ConfigError]: The config can not be parsed
+fn check_repository_format(gitpath: &Path) -> GitResult<()> {
+    let path = gitpath.join("config");
+    let config = ConfigFile::open(&path)?;
+    let version = match config.get("core.repositoryformatversion")? {
+        Some(value) => {
+            let value = value.unwrap_or_default();
+            config::parse_int(&value).ok_or_else(|| GitError::ConfigError {
+                path: path.clone(),
+                message: format!(
+                    "fatal: bad numeric config value '{}' for 'core.repositoryformatversion' \
+                     in file {}: invalid unit",
+                    value,
+                    path.display()
+                ),
+            })?
+        }
+        None => 0,
+    };
+    if version > 1 {
+        return Err(not_a_repository(&format!(
+            "Expected git repo version <= 1, found {}",
+            version
+        )));
+    }
+
+    let entries = config.entries();
+    let mut unknown = Vec::new();
+    let mut v1_only = Vec::new();
+    for entry in &entries {
+        let extension = match entry.name().strip_prefix("extensions.") {
+            Some(extension) => extension,
+            None => continue,
+        };
+        if extension == "objectformat" {
+            let format = entry.value().unwrap_or_default();
+            if format.eq_ignore_ascii_case("sha256") {
+                return Err(not_a_repository(&format!(
+                    "unsupported object format '{}' in extensions.objectformat",
+                    format
+                )));
+            }
+            if !format.eq_ignore_ascii_case("sha1") {
+                return Err(GitError::ConfigError {
+                    path,
+                    message: format!(
+                        "error: invalid value for 'extensions.objectformat': '{}'",
+                        format
+                    ),
+                });
+            }
+        }
+        if EXTENSIONS_V1.contains(&extension) {
+            v1_only.push(extension);
+        } else if !EXTENSIONS_V0.contains(&extension) {
+            unknown.push(extension);
+        }
+    }
+    // Unknown extensions only matter from version 1 on.
+    if version == 1 && !unknown.is_empty() {
+        return Err(not_a_repository(&format!(
+            "unknown repository extension found:\n\t{}",
+            unknown.join("\n\t")
+        )));
+    }
+    if version == 0 && !v1_only.is_empty() {
+        return Err(not_a_repository(&format!(
+            "repo version is 0, but v1-only extension found:\n\t{}",
+            v1_only.join("\n\t")
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the absolute directories listed in `$GIT_CEILING_DIRECTORIES`.
+fn ceiling_directories(env: &Env) -> GitResult<Vec<PathBuf>> {
+    let value = match env_path(env, "GIT_CEILING_DIRECTORIES")? {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+    // After an empty entry, the directories are used as given instead of being resolved.
+    let mut resolve = true;
+    let mut ceilings = Vec::new();
+    for entry in env::split_paths(&value) {
+        if entry.as_os_str().is_empty() {
+            resolve = false;
+        } else if entry.is_absolute() {
+            let entry = if resolve {
+                entry.canonicalize().unwrap_or(entry)
+            } else {
+                entry
+            };
+            ceilings.push(entry);
+        }
+    }
+    Ok(ceilings)
+}
+
+/// Reads an environment variable of this process.
+fn process_env(name: &str) -> Option<OsString> {
+    env::var_os(name)
+}
+
+fn env_path(env: &Env, name: &str) -> GitResult<Option<PathBuf>> {
+    match env(name).map(OsString::into_string) {
+        Some(Ok(value)) if value.is_empty() => Ok(None),
+        Some(Ok(value)) => Ok(Some(PathBuf::from(value))),
+        None => Ok(None),
+        Some(Err(data)) => Err(GitError::VarInvalidUnicode {
+            var: OsString::from(name),
+            data,
+        }),
+    }
+}
+
+/// Joins `path` to `dir` without a leading `./`.
+fn join<P: AsRef<Path>>(dir: &Path, path: P) -> PathBuf {
+    if dir == Path::new(".") {
+        path.as_ref().to_path_buf()
+    } else {
+        dir.join(path)
+    }
+}
+
+/// Returns the error for a failed search from `dir`.
+fn not_found(dir: &Path) -> GitResult<GitError> {
+    match env_path(&process_env, "GIT_DIR")? {
+        Some(gitpath) => Ok(not_a_repository(&format!(
+            "not a git repository: '{}'",
+            join(dir, gitpath).display()
+        ))),
+        None => Ok(not_a_repository(NOT_FOUND)),
+    }
+}
+
+fn not_a_repository(message: &str) -> GitError {
+    GitError::RepositoryError {
+        path: PathBuf::new(),
+        message: message.to_string(),
+    }
 }
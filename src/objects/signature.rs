@@ -0,0 +1,168 @@
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+
+use crate::config;
+use crate::utils::{format_offset, now};
+use crate::{GitError, GitRepo, GitResult};
+
+/// The name, email and time recorded for the author or committer of a commit, a tagger or a
+/// reflog entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSignature {
+    name: String,
+    email: String,
+    time: i64,
+    offset: i32,
+}
+
+impl GitSignature {
+    /// Creates a signature; `offset` is the timezone offset from UTC in minutes.
+    pub fn new(name: &str, email: &str, time: i64, offset: i32) -> Self {
+        Self {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+            offset,
+        }
+    }
+
+    /// Parses a signature in the form `Name <email> 1234567890 +0100`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let open = line.find('<')?;
+        let close = open + line[open..].find('>')?;
+        let name = line[..open].trim_end();
+        let email = &line[open + 1..close];
+
+        let mut parts = line[close + 1..].split_whitespace();
+        let time = parts.next().and_then(|time| time.parse().ok()).unwrap_or(0);
+        let offset = parts.next().and_then(parse_offset).unwrap_or(0);
+        Some(Self::new(name, email, time, offset))
+    }
+
+    /// Returns the identity for `role` (`AUTHOR` or `COMMITTER`) at the current time.
+    ///
+    /// The name, email and date come from `GIT_<role>_NAME`, `GIT_<role>_EMAIL` and
+    /// `GIT_<role>_DATE`, falling back to `user.name` and `user.email`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IdentityError]: No name or email is configured
+    /// * [GitError::IOError]: A config file can not be read
+    pub fn from_env(repo: &GitRepo, role: &str) -> GitResult<Self> {
+        let var = |key: &str| env::var(format!("GIT_{}_{}", role, key)).ok();
+        let name = match var("NAME") {
+            Some(name) => Some(name),
+            None => config::get(repo, "user.name")?,
+        };
+        let email = match var("EMAIL") {
+            Some(email) => Some(email),
+            None => match config::get(repo, "user.email")? {
+                Some(email) => Some(email),
+                None => env::var("EMAIL").ok(),
+            },
+        };
+        let (name, email) = match (name, email) {
+            (Some(name), Some(email)) if !name.is_empty() => (name, email),
+            _ => {
+                return Err(GitError::IdentityError {
+                    role: role.to_lowercase(),
+                })
+            }
+        };
+
+        let (time, offset) = match var("DATE").and_then(|date| parse_date(&date)) {
+            Some(date) => date,
+            None => (now(), 0),
+        };
+        Ok(Self::new(&name, &email, time, offset))
+    }
+
+    /// Returns the committer identity like [GitSignature::from_env], but falls back to the login
+    /// name and host instead of failing. This is what reflog entries are written with.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: A config file can not be read
+    pub fn committer_or_default(repo: &GitRepo) -> GitResult<Self> {
+        match Self::from_env(repo, "COMMITTER") {
+            Err(GitError::IdentityError { role: _ }) => {
+                let user = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+                let host = fs::read_to_string("/etc/hostname")
+                    .map(|host| host.trim().to_string())
+                    .unwrap_or_else(|_| "(none)".to_string());
+                let email = format!("{}@{}", user, host);
+                let (time, offset) = env::var("GIT_COMMITTER_DATE")
+                    .ok()
+                    .and_then(|date| parse_date(&date))
+                    .unwrap_or((now(), 0));
+                Ok(Self::new(&user, &email, time, offset))
+            }
+            result => result,
+        }
+    }
+
+    /// The name of the person.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The email address of the person.
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// Seconds since the unix epoch.
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    /// The timezone offset from UTC in minutes.
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    /// Returns the timezone offset formatted as `+hhmm`.
+    pub fn offset_string(&self) -> String {
+        format_offset(self.offset)
+    }
+}
+
+impl Display for GitSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time,
+            self.offset_string()
+        )
+    }
+}
+
+/// Parses a timezone offset in the form `+hhmm`, returning minutes.
+pub fn parse_offset(offset: &str) -> Option<i32> {
+    let (sign, digits) = match offset.as_bytes().first()? {
+        b'+' => (1, &offset[1..]),
+        b'-' => (-1, &offset[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Parses a date in git's internal format, `<seconds> <offset>` or `@<seconds> <offset>`.
+fn parse_date(date: &str) -> Option<(i64, i32)> {
+    let mut parts = date.split_whitespace();
+    let time = parts.next()?.trim_start_matches('@').parse().ok()?;
+    let offset = match parts.next() {
+        Some(offset) => parse_offset(offset)?,
+        None => 0,
+    };
+    Some((time, offset))
+}
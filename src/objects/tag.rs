@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use super::ObjectType;
+use crate::{GitObject, GitResult, GitSignature};
+
+/// An annotated git tag object.
+#[derive(Debug, Clone)]
+pub struct GitTag {
+    object: String,
+    target_type: ObjectType,
+    name: String,
+    tagger: Option<GitSignature>,
+    message: String,
+    data: Vec<u8>,
+}
+
+impl GitTag {
+    /// Creates a tag called `name` pointing at `object` of type `target_type`.
+    pub fn new(
+        object: &str,
+        target_type: ObjectType,
+        name: &str,
+        tagger: GitSignature,
+        message: &str,
+    ) -> Self {
+        let data = format!(
+            "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
+            object, target_type, name, tagger, message
+        );
+        Self {
+            object: object.to_string(),
+            target_type,
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message: message.to_string(),
+            data: data.into_bytes(),
+        }
+    }
+
+    /// The Sha1 hash of the tagged object.
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    /// The type of the tagged object.
+    pub fn target_type(&self) -> ObjectType {
+        self.target_type
+    }
+
+    /// The name of the tag.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The person who created the tag; very old tags have none.
+    pub fn tagger(&self) -> Option<&GitSignature> {
+        self.tagger.as_ref()
+    }
+
+    /// The tag message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl GitObject for GitTag {
+    fn object_type() -> ObjectType {
+        ObjectType::Tag
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn fmt(&self) -> &'static str {
+        "tag"
+    }
+
+    fn from_data(data: &[u8]) -> GitResult<Self> {
+        let text = String::from_utf8_lossy(data);
+        let (headers, message) = match text.find("\n\n") {
+            Some(i) => (&text[..i], &text[i + 2..]),
+            None => (text.trim_end_matches('\n'), ""),
+        };
+
+        let mut object = None;
+        let mut target_type = None;
+        let mut name = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            let (key, value) = match line.find(' ') {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => (line, ""),
+            };
+            match key {
+                "object" => object = Some(value.to_string()),
+                "type" => target_type = ObjectType::from_name(value),
+                "tag" => name = Some(value.to_string()),
+                "tagger" => tagger = GitSignature::parse(value),
+                _ => {}
+            }
+        }
+
+        let error = |message| super::parse_error(ObjectType::Tag, message);
+        Ok(Self {
+            object: object.ok_or_else(|| error("missing object"))?,
+            target_type: target_type.ok_or_else(|| error("missing type"))?,
+            name: name.ok_or_else(|| error("missing tag name"))?,
+            tagger,
+            message: message.to_string(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn from_object_file<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        Self::from_data(&super::read_typed_object_file(path, ObjectType::Tag)?)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        super::serialize_object(ObjectType::Tag, &self.data)
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn to_sha1(&self) -> String {
+        super::hash_object(ObjectType::Tag, &self.data)
+    }
+}
@@ -7,10 +7,7 @@ fn test_file_mode() -> Result<(), Box<dyn Error>> {
     use std::os::unix::fs::PermissionsExt;
 
     let path = "/tmp/test.txt";
-    let file = OpenOptions::new()
-        .create_new(true)
-        .write(true)
-        .open(&path)?;
+    let file = OpenOptions::new().create_new(true).write(true).open(path)?;
     file.set_permissions(Permissions::from_mode(0o644))?;
 
     let real_mode = file.metadata()?.permissions().mode();
@@ -19,5 +16,432 @@ fn test_file_mode() -> Result<(), Box<dyn Error>> {
 
     assert_eq!(real_mode, mode.into());
 
+    assert_eq!(
+        GitFileMode::from_octal(0o100755)?,
+        GitFileMode::executable()
+    );
+    assert!(GitFileMode::from_octal(0o1100755).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_discover() -> Result<(), Box<dyn Error>> {
+    use std::env;
+    use std::fs;
+
+    let dir = env::temp_dir().join("gitrs_test_discover");
+    let _ = fs::remove_dir_all(&dir);
+    let gitpath = dir.join("repo/.git");
+    fs::create_dir_all(gitpath.join("objects"))?;
+    fs::create_dir_all(gitpath.join("refs"))?;
+    fs::create_dir_all(dir.join("repo/a/b"))?;
+    fs::write(gitpath.join("HEAD"), "ref: refs/heads/master\n")?;
+    fs::write(
+        gitpath.join("config"),
+        "[core]\n\trepositoryformatversion = 0\n",
+    )?;
+
+    let repo = GitRepo::discover(&dir.join("repo/a/b"))?;
+    assert_eq!(repo.gitpath().canonicalize()?, gitpath.canonicalize()?);
+    assert_eq!(repo.prefix(), Path::new("a/b"));
+    assert_eq!(repo.worktree_path("c")?, "a/b/c");
+    assert_eq!(repo.worktree_path("../../")?, ".");
+    assert_eq!(repo.worktree_path("./../c/")?, "a/c/");
+    assert!(repo.worktree_path("../../../c").is_err());
+
+    // Inside the git directory, the repository is bare.
+    let repo = GitRepo::discover(&gitpath.join("refs"))?;
+    assert!(repo.is_bare());
+
+    let ceiling = dir.join("repo/a").into_os_string();
+    let env = |name: &str| match name {
+        "GIT_CEILING_DIRECTORIES" => Some(ceiling.clone()),
+        _ => None,
+    };
+    assert!(GitRepo::discover_with_env(&dir.join("repo/a/b"), &env)?.is_none());
+    assert!(GitRepo::discover_with_env(&dir.join("repo/a"), &env)?.is_some());
+
+    let config = "[core]\n\trepositoryformatversion = 1\n[extensions]\n\tunknown = true\n";
+    fs::write(gitpath.join("config"), config)?;
+    assert!(GitRepo::discover(&dir.join("repo")).is_err());
+    let config = "[core]\n\trepositoryformatversion = 2\n";
+    fs::write(gitpath.join("config"), config)?;
+    assert!(GitRepo::discover(&dir.join("repo")).is_err());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Encodes `size` as the little endian base 128 number used in delta headers.
+fn delta_size_bytes(mut size: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Encodes a delta instruction copying `size` bytes at `offset` of the base.
+fn copy_op(offset: usize, size: usize) -> Vec<u8> {
+    let mut op = 0x80;
+    let mut args = Vec::new();
+    for i in 0..4 {
+        let byte = (offset >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 1 << i;
+            args.push(byte);
+        }
+    }
+    for i in 0..3 {
+        let byte = (size >> (8 * i)) as u8;
+        if byte != 0 {
+            op |= 0x10 << i;
+            args.push(byte);
+        }
+    }
+    let mut bytes = vec![op];
+    bytes.extend(args);
+    bytes
+}
+
+/// Encodes a delta instruction inserting `data`, which must be shorter than 128 bytes.
+fn insert_op(data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn make_delta(base: &[u8], result: &[u8], ops: &[Vec<u8>]) -> Vec<u8> {
+    let mut delta = delta_size_bytes(base.len());
+    delta.extend(delta_size_bytes(result.len()));
+    for op in ops {
+        delta.extend_from_slice(op);
+    }
+    delta
+}
+
+#[test]
+fn test_apply_delta() {
+    let base = b"0123456789";
+    let delta = make_delta(base, b"23456abcd", &[vec![0x91, 2, 5], insert_op(b"abcd")]);
+    assert_eq!(apply_delta(base, &delta), Some(b"23456abcd".to_vec()));
+
+    // A copy without size bytes copies 0x10000 bytes.
+    let large: Vec<u8> = (0..0x10000).map(|i| (i % 251) as u8).collect();
+    let delta = make_delta(&large, &large, &[vec![0x80]]);
+    assert_eq!(apply_delta(&large, &delta), Some(large.clone()));
+
+    // The base has the wrong size.
+    let delta = make_delta(b"012345678", b"0", &[copy_op(0, 1)]);
+    assert_eq!(apply_delta(base, &delta), None);
+    // Opcode 0 is reserved.
+    let delta = make_delta(base, b"", &[vec![0]]);
+    assert_eq!(apply_delta(base, &delta), None);
+    // The copy reaches past the end of the base.
+    let delta = make_delta(base, b"0123456789a", &[copy_op(0, 11)]);
+    assert_eq!(apply_delta(base, &delta), None);
+    // The insert is truncated.
+    let delta = make_delta(base, b"abcd", &[vec![4, b'a', b'b']]);
+    assert_eq!(apply_delta(base, &delta), None);
+    // The result is shorter than announced.
+    let delta = make_delta(base, b"0123", &[copy_op(0, 2)]);
+    assert_eq!(apply_delta(base, &delta), None);
+}
+
+/// Encodes the type and size header of a pack entry.
+fn pack_entry_header(kind: u8, mut size: usize) -> Vec<u8> {
+    let mut byte = (kind << 4) | (size & 15) as u8;
+    size >>= 4;
+    let mut bytes = Vec::new();
+    while size != 0 {
+        bytes.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    bytes.push(byte);
+    bytes
+}
+
+/// Encodes the distance to the base of an `OFS_DELTA` entry.
+fn ofs_distance(mut distance: usize) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.insert(0, 0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[test]
+fn test_pack() -> Result<(), Box<dyn Error>> {
+    use std::env;
+    use std::fs;
+
+    let objects_dir = env::temp_dir().join("gitrs_test_pack");
+    let _ = fs::remove_dir_all(&objects_dir);
+    fs::create_dir_all(objects_dir.join("pack"))?;
+
+    let loose = b"a loose base\n".to_vec();
+    let loose_sha = GitOdb::from_dir(&objects_dir)?.write(ObjectType::Blob, &loose)?;
+
+    // Large enough that the distance of the OFS_DELTA takes more than one byte.
+    let base: Vec<u8> = (0..1000)
+        .map(|i| format!("line {}\n", i))
+        .collect::<String>()
+        .into_bytes();
+    let base_sha = hash_object(ObjectType::Blob, &base);
+
+    let mut pack = b"PACK\0\0\0\x02\0\0\0\x04".to_vec();
+    let mut entries = Vec::new();
+
+    let offset = pack.len();
+    pack.extend(pack_entry_header(3, base.len()));
+    pack.extend(deflate(&base)?);
+    entries.push((base_sha.clone(), offset, base.clone()));
+    let base_offset = offset;
+
+    let ofs = [&base[..20], b"changed\n"].concat();
+    let delta = make_delta(&base, &ofs, &[copy_op(0, 20), insert_op(b"changed\n")]);
+    let offset = pack.len();
+    pack.extend(pack_entry_header(6, delta.len()));
+    pack.extend(ofs_distance(offset - base_offset));
+    pack.extend(deflate(&delta)?);
+    entries.push((hash_object(ObjectType::Blob, &ofs), offset, ofs));
+
+    let in_pack = [&base[..], b"more\n"].concat();
+    let delta = make_delta(
+        &base,
+        &in_pack,
+        &[copy_op(0, base.len()), insert_op(b"more\n")],
+    );
+    let offset = pack.len();
+    pack.extend(pack_entry_header(7, delta.len()));
+    pack.extend(hex::decode(&base_sha)?);
+    pack.extend(deflate(&delta)?);
+    entries.push((hash_object(ObjectType::Blob, &in_pack), offset, in_pack));
+
+    let outside = b"base!\n".to_vec();
+    let delta = make_delta(&loose, &outside, &[copy_op(8, 4), insert_op(b"!\n")]);
+    let offset = pack.len();
+    pack.extend(pack_entry_header(7, delta.len()));
+    pack.extend(hex::decode(&loose_sha)?);
+    pack.extend(deflate(&delta)?);
+    entries.push((hash_object(ObjectType::Blob, &outside), offset, outside));
+    pack.extend([0u8; 20]);
+
+    entries.sort();
+    let mut idx = b"\xfftOc\0\0\0\x02".to_vec();
+    for i in 0..256 {
+        let count = entries
+            .iter()
+            .filter(|(sha, _, _)| (hex::decode(&sha[..2]).unwrap()[0] as usize) <= i)
+            .count() as u32;
+        idx.extend(&count.to_be_bytes());
+    }
+    for (sha, _, _) in &entries {
+        idx.extend(hex::decode(sha)?);
+    }
+    idx.extend(vec![0u8; entries.len() * 4]);
+    // The first entry is stored in the table of large offsets.
+    idx.extend(&0x8000_0000u32.to_be_bytes());
+    for (_, offset, _) in &entries[1..] {
+        idx.extend(&(*offset as u32).to_be_bytes());
+    }
+    idx.extend(&(entries[0].1 as u64).to_be_bytes());
+    idx.extend([0u8; 40]);
+
+    fs::write(objects_dir.join("pack/pack-test.pack"), &pack)?;
+    fs::write(objects_dir.join("pack/pack-test.idx"), &idx)?;
+
+    let odb = GitOdb::from_dir(&objects_dir)?;
+    for (sha, _, data) in &entries {
+        assert!(odb.contains(sha));
+        assert_eq!(odb.read(sha)?, (ObjectType::Blob, data.clone()));
+    }
+    let mut packed: Vec<String> = entries.iter().map(|(sha, _, _)| sha.clone()).collect();
+    assert_eq!(odb.packed_objects(), packed);
+    packed.push(loose_sha.clone());
+    packed.sort();
+    assert_eq!(odb.all_objects()?, packed);
+    assert_eq!(odb.find_prefix(&base_sha[..7])?, vec![base_sha.clone()]);
+    assert!(odb.read(&"0".repeat(40)).is_err());
+
+    // Only version 2 indexes are supported.
+    idx[7] = 3;
+    fs::write(objects_dir.join("pack/pack-test.idx"), &idx)?;
+    assert!(GitOdb::from_dir(&objects_dir).is_err());
+
+    fs::remove_dir_all(&objects_dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_signature() {
+    let signature = GitSignature::parse("A U Thor <author@example.com> 1112911993 -0130").unwrap();
+    assert_eq!(signature.name(), "A U Thor");
+    assert_eq!(signature.email(), "author@example.com");
+    assert_eq!(signature.time(), 1112911993);
+    assert_eq!(signature.offset(), -90);
+    assert_eq!(
+        signature.to_string(),
+        "A U Thor <author@example.com> 1112911993 -0130"
+    );
+
+    let signature = GitSignature::parse("<nobody@example.com> 0 +0545").unwrap();
+    assert_eq!(signature.name(), "");
+    assert_eq!(signature.offset(), 345);
+    assert_eq!(signature.offset_string(), "+0545");
+
+    // A missing or malformed date reads as the epoch in UTC.
+    let signature = GitSignature::parse("Name <email> 12 +5").unwrap();
+    assert_eq!((signature.time(), signature.offset()), (12, 0));
+    let signature = GitSignature::parse("Name <email>").unwrap();
+    assert_eq!((signature.time(), signature.offset()), (0, 0));
+    assert!(GitSignature::parse("Name email").is_none());
+
+    assert_eq!(signature::parse_offset("+1400"), Some(840));
+    assert_eq!(signature::parse_offset("-0000"), Some(0));
+    assert_eq!(signature::parse_offset("0100"), None);
+}
+
+#[test]
+fn test_commit() -> Result<(), Box<dyn Error>> {
+    let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                parent 1111111111111111111111111111111111111111\n\
+                parent 2222222222222222222222222222222222222222\n\
+                author A U Thor <author@example.com> 1112911993 -0130\n\
+                committer C O Mitter <committer@example.com> 1112912053 +0545\n\
+                encoding ISO-8859-1\n\
+                gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n -----END PGP SIGNATURE-----\n\
+                \n\
+                Merge branches\n\
+                \n\
+                With a body.\n";
+    let commit = GitCommit::from_data(data.as_bytes())?;
+    assert_eq!(commit.tree(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    assert_eq!(
+        commit.parents(),
+        &[
+            "1111111111111111111111111111111111111111".to_string(),
+            "2222222222222222222222222222222222222222".to_string()
+        ]
+    );
+    assert_eq!(commit.author().offset(), -90);
+    assert_eq!(commit.committer().name(), "C O Mitter");
+    assert_eq!(commit.committer().offset(), 345);
+    assert_eq!(commit.encoding(), Some("ISO-8859-1"));
+    assert_eq!(
+        commit.extra_headers(),
+        &[(
+            "gpgsig".to_string(),
+            "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"
+                .to_string()
+        )]
+    );
+    assert_eq!(commit.message(), "Merge branches\n\nWith a body.\n");
+    assert_eq!(commit.summary(), "Merge branches");
+    assert_eq!(commit.body(), "With a body.\n");
+    // The data is kept verbatim, so the commit hashes like it does in git.
+    assert_eq!(commit.data(), data.as_bytes());
+    assert_eq!(commit.to_sha1(), "9bcd1af31a352e8773260f7b32fcef50d548556f");
+
+    let author = GitSignature::new("A U Thor", "author@example.com", 1112911993, -90);
+    let committer = GitSignature::new("C O Mitter", "committer@example.com", 1112912053, 345);
+    let parents = commit.parents().to_vec();
+    let new = GitCommit::new(
+        commit.tree(),
+        &parents,
+        author.clone(),
+        committer.clone(),
+        "Subject\n",
+    );
+    let parsed = GitCommit::from_data(new.data())?;
+    assert_eq!(parsed.tree(), commit.tree());
+    assert_eq!(parsed.parents(), &parents[..]);
+    assert_eq!(parsed.author(), &author);
+    assert_eq!(parsed.committer(), &committer);
+    assert_eq!(parsed.encoding(), None);
+    assert!(parsed.extra_headers().is_empty());
+    assert_eq!(parsed.message(), "Subject\n");
+    assert_eq!(parsed.to_sha1(), new.to_sha1());
+
+    let root = GitCommit::new(commit.tree(), &[], author.clone(), committer, "");
+    assert!(GitCommit::from_data(root.data())?.parents().is_empty());
+    assert!(GitCommit::from_data(b"author A <a> 0 +0000\n\nNo tree\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_tag() -> Result<(), Box<dyn Error>> {
+    let tagger = GitSignature::new("C O Mitter", "committer@example.com", 1112912053, -480);
+    let tag = GitTag::new(
+        "1111111111111111111111111111111111111111",
+        ObjectType::Commit,
+        "v1.0",
+        tagger.clone(),
+        "Version 1.0\n-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----\n",
+    );
+    let parsed = GitTag::from_data(tag.data())?;
+    assert_eq!(parsed.object(), tag.object());
+    assert_eq!(parsed.target_type(), ObjectType::Commit);
+    assert_eq!(parsed.name(), "v1.0");
+    assert_eq!(parsed.tagger(), Some(&tagger));
+    assert_eq!(parsed.message(), tag.message());
+    assert_eq!(parsed.to_sha1(), tag.to_sha1());
+
+    // Very old tags have no tagger.
+    let data = "object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                type tree\n\
+                tag v0.1\n\
+                \n\
+                An old tag without a tagger.\n";
+    let tag = GitTag::from_data(data.as_bytes())?;
+    assert_eq!(tag.target_type(), ObjectType::Tree);
+    assert_eq!(tag.tagger(), None);
+    assert_eq!(tag.message(), "An old tag without a tagger.\n");
+    assert_eq!(tag.to_sha1(), "ac38dfdde1ad560ef73406aba920f65a8bc2606c");
+    Ok(())
+}
+
+#[test]
+fn test_tree() -> Result<(), Box<dyn Error>> {
+    let sha = "e69de29bb2d1d6434b8b29ae4cf6bd9d4cc7a8d1";
+    let tree = GitTree::new(vec![
+        GitTreeEntry::new(GitFileMode::tree(), "a", sha),
+        GitTreeEntry::new(GitFileMode::executable(), "a.sh", sha),
+        GitTreeEntry::new(GitFileMode::regular(), "a-b", sha),
+    ]);
+    // Trees sort as if their names ended with a slash.
+    let names: Vec<&str> = tree.entries().iter().map(GitTreeEntry::name).collect();
+    assert_eq!(names, ["a-b", "a.sh", "a"]);
+    assert!(tree.data().starts_with(b"100644 a-b\0"));
+
+    let parsed = GitTree::from_data(tree.data())?;
+    assert_eq!(parsed.entries().len(), 3);
+    assert!(parsed.entry("a").unwrap().mode().is_tree());
+    assert!(parsed.entry("a.sh").unwrap().mode().is_executable());
+    assert_eq!(parsed.entry("a-b").unwrap().sha(), sha);
+    assert_eq!(parsed.to_sha1(), tree.to_sha1());
+
+    assert!(GitTree::from_data(b"1006440 a\0aaaaaaaaaaaaaaaaaaaa").is_err());
+    assert!(GitTree::from_data(b"100644 a\0short").is_err());
     Ok(())
 }
@@ -0,0 +1,210 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::ObjectType;
+use crate::{GitFileMode, GitObject, GitOdb, GitResult};
+
+/// A single entry of a tree: a blob, symlink, gitlink or subtree with its name and mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitTreeEntry {
+    mode: GitFileMode,
+    name: String,
+    sha: String,
+}
+
+impl GitTreeEntry {
+    /// Creates an entry; `name` may also be a full path when entries are flattened.
+    pub fn new(mode: GitFileMode, name: &str, sha: &str) -> Self {
+        Self {
+            mode,
+            name: name.to_string(),
+            sha: sha.to_string(),
+        }
+    }
+
+    /// The mode of the entry.
+    pub fn mode(&self) -> GitFileMode {
+        self.mode
+    }
+
+    /// The name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The Sha1 hash of the object the entry points to.
+    pub fn sha(&self) -> &str {
+        &self.sha
+    }
+
+    /// Compares entries the way git sorts trees: subtrees sort as if their name ended with `/`.
+    fn cmp_git(&self, other: &Self) -> Ordering {
+        let key = |entry: &Self| {
+            let mut key = entry.name.as_bytes().to_vec();
+            if entry.mode.is_tree() {
+                key.push(b'/');
+            }
+            key
+        };
+        key(self).cmp(&key(other))
+    }
+}
+
+/// A git tree object.
+#[derive(Debug, Clone)]
+pub struct GitTree {
+    entries: Vec<GitTreeEntry>,
+    data: Vec<u8>,
+}
+
+impl GitTree {
+    /// Creates a tree from its entries, sorting them in git order.
+    pub fn new(mut entries: Vec<GitTreeEntry>) -> Self {
+        entries.sort_by(|a, b| a.cmp_git(b));
+        let mut data = Vec::new();
+        for entry in &entries {
+            // Trees are stored without the leading zero of their mode.
+            let mode = entry.mode.to_string();
+            data.extend_from_slice(mode.trim_start_matches('0').as_bytes());
+            data.push(b' ');
+            data.extend_from_slice(entry.name.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&hex::decode(&entry.sha).unwrap_or_else(|_| vec![0; 20]));
+        }
+        Self { entries, data }
+    }
+
+    /// The entries of the tree in git order.
+    pub fn entries(&self) -> &[GitTreeEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry called `name`.
+    pub fn entry(&self, name: &str) -> Option<&GitTreeEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Reads the tree `sha` and all of its subtrees, returning every non-tree entry keyed by its
+    /// full path. The names of the returned entries are full paths as well.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: A tree is missing or corrupt
+    pub fn flatten(odb: &GitOdb, sha: &str) -> GitResult<BTreeMap<String, GitTreeEntry>> {
+        let mut entries = BTreeMap::new();
+        flatten_into(odb, sha, "", &mut entries)?;
+        Ok(entries)
+    }
+
+    /// Looks up `path` (separated by `/`) starting at the tree `sha`.
+    ///
+    /// Returns [None] if no such entry exists.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: A tree on the way is missing or corrupt
+    pub fn find_path(odb: &GitOdb, sha: &str, path: &str) -> GitResult<Option<GitTreeEntry>> {
+        let mut current = GitTreeEntry::new(GitFileMode::tree(), "", sha);
+        for component in Path::new(path).iter() {
+            let component = component.to_string_lossy();
+            if component == "." {
+                continue;
+            }
+            if !current.mode.is_tree() {
+                return Ok(None);
+            }
+            let tree = odb.read_as::<GitTree>(&current.sha)?;
+            current = match tree.entry(&component) {
+                Some(entry) => entry.clone(),
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(current))
+    }
+}
+
+fn flatten_into(
+    odb: &GitOdb,
+    sha: &str,
+    prefix: &str,
+    entries: &mut BTreeMap<String, GitTreeEntry>,
+) -> GitResult<()> {
+    let tree = odb.read_as::<GitTree>(sha)?;
+    for entry in tree.entries {
+        let path = format!("{}{}", prefix, entry.name);
+        if entry.mode.is_tree() {
+            flatten_into(odb, &entry.sha, &format!("{}/", path), entries)?;
+        } else {
+            entries.insert(
+                path.clone(),
+                GitTreeEntry::new(entry.mode, &path, &entry.sha),
+            );
+        }
+    }
+    Ok(())
+}
+
+impl GitObject for GitTree {
+    fn object_type() -> ObjectType {
+        ObjectType::Tree
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn fmt(&self) -> &'static str {
+        "tree"
+    }
+
+    fn from_data(data: &[u8]) -> GitResult<Self> {
+        let mut entries = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| super::parse_error(ObjectType::Tree, "missing mode"))?;
+            let nul = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| super::parse_error(ObjectType::Tree, "missing name"))?;
+            if nul < space || rest.len() < nul + 21 {
+                return Err(super::parse_error(ObjectType::Tree, "truncated entry"));
+            }
+            let mode = std::str::from_utf8(&rest[..space])
+                .ok()
+                .filter(|mode| {
+                    (5..=6).contains(&mode.len())
+                        && mode.bytes().all(|b| (b'0'..=b'7').contains(&b))
+                })
+                .ok_or_else(|| super::parse_error(ObjectType::Tree, "invalid mode"))?;
+            let name = String::from_utf8_lossy(&rest[space + 1..nul]);
+            let sha = hex::encode(&rest[nul + 1..nul + 21]);
+            entries.push(GitTreeEntry::new(GitFileMode::from(mode), &name, &sha));
+            rest = &rest[nul + 21..];
+        }
+
+        Ok(Self {
+            entries,
+            data: data.to_vec(),
+        })
+    }
+
+    fn from_object_file<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        Self::from_data(&super::read_typed_object_file(path, ObjectType::Tree)?)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        super::serialize_object(ObjectType::Tree, &self.data)
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn to_sha1(&self) -> String {
+        super::hash_object(ObjectType::Tree, &self.data)
+    }
+}
@@ -0,0 +1,73 @@
+use crate::utils;
+
+/// A list of paths or glob patterns selecting files, as given on the command line.
+///
+/// An item matches a path if it is equal to it, names one of its leading directories or matches
+/// it as a glob (where `*` also matches `/`). An empty pathspec and `.` match everything.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    items: Vec<String>,
+}
+
+impl Pathspec {
+    /// Creates a pathspec from paths relative to the top of the worktree.
+    pub fn new<S: AsRef<str>>(items: &[S]) -> Self {
+        let items = items
+            .iter()
+            .map(|item| {
+                let item = item.as_ref();
+                let item = item.strip_prefix("./").unwrap_or(item);
+                let item = if item == "." { "" } else { item };
+                item.trim_end_matches('/').to_string()
+            })
+            .collect();
+        Self { items }
+    }
+
+    /// Returns true if no items were given.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The normalized items of the pathspec.
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    /// Returns true if `path` is selected by the pathspec.
+    pub fn matches(&self, path: &str) -> bool {
+        self.is_empty() || (0..self.items.len()).any(|i| self.item_matches(i, path))
+    }
+
+    /// Returns true if the pathspec may select `dir` or files below it, as with globs it can
+    /// not tell without looking.
+    pub fn may_match_below(&self, dir: &str) -> bool {
+        self.matches(dir)
+            || self.items.iter().any(|item| {
+                has_glob(item)
+                    || (item.len() > dir.len()
+                        && item.starts_with(dir)
+                        && item.as_bytes()[dir.len()] == b'/')
+            })
+    }
+
+    /// Returns true if the `i`th item selects `path`.
+    pub fn item_matches(&self, i: usize, path: &str) -> bool {
+        let item = &self.items[i];
+        if item.is_empty() || path == item {
+            return true;
+        }
+        if path.len() > item.len()
+            && path.starts_with(item.as_str())
+            && path.as_bytes()[item.len()] == b'/'
+        {
+            return true;
+        }
+        has_glob(item) && utils::wildmatch(item, path, 0)
+    }
+}
+
+/// Returns true if `item` contains glob special characters.
+pub(crate) fn has_glob(item: &str) -> bool {
+    item.contains(['*', '?', '[', '\\'])
+}
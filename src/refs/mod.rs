@@ -0,0 +1,608 @@
+//! Reading and updating branches, tags and other refs, and their reflogs.
+
+#[cfg(test)]
+mod tests;
+
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::utils::{self, LockFile};
+use crate::{self as gitrs, GitError, GitRepo, GitResult, GitSignature};
+
+/// The object name used in reflogs for a ref that did not exist.
+pub const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+/// Symbolic refs are followed at most this many times.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The value of a ref: an object name, or the name of another ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefTarget {
+    Object(String),
+    Symbolic(String),
+}
+
+/// A single line of a reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    old: String,
+    new: String,
+    signature: GitSignature,
+    message: String,
+}
+
+impl ReflogEntry {
+    /// The value before the update, [NULL_SHA] if the ref was created.
+    pub fn old_sha(&self) -> &str {
+        &self.old
+    }
+
+    /// The value after the update.
+    pub fn new_sha(&self) -> &str {
+        &self.new
+    }
+
+    /// Who made the update and when.
+    pub fn signature(&self) -> &GitSignature {
+        &self.signature
+    }
+
+    /// Why the ref was updated, e.g. `checkout: moving from main to topic`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let (head, message) = match line.find('\t') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        let old = head.get(..40)?;
+        let new = head.get(41..81)?;
+        let signature = GitSignature::parse(head.get(82..)?)?;
+        Some(Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            signature,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// The refs of a repository, stored as loose files and in `packed-refs`.
+#[derive(Debug)]
+pub struct GitRefs {
+    repo: GitRepo,
+}
+
+impl GitRefs {
+    /// Returns the refs of `repo`.
+    pub fn new(repo: &GitRepo) -> Self {
+        Self { repo: repo.clone() }
+    }
+
+    fn gitpath(&self) -> &Path {
+        self.repo.gitpath()
+    }
+
+    fn ref_path(&self, name: &str) -> PathBuf {
+        self.gitpath().join(name)
+    }
+
+    /// Reads `name` without following symbolic refs.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RefError]: The ref file is corrupt
+    /// * [GitError::IOError]: The ref can not be read
+    pub fn read(&self, name: &str) -> GitResult<Option<RefTarget>> {
+        let path = self.ref_path(name);
+        if path.is_file() {
+            let data = utils::read_file(&path)?;
+            let data = data.trim_end();
+            if let Some(target) = data.strip_prefix("ref:") {
+                return Ok(Some(RefTarget::Symbolic(target.trim().to_string())));
+            }
+            if data.len() >= 40 && data[..40].bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Ok(Some(RefTarget::Object(data[..40].to_ascii_lowercase())));
+            }
+            return Err(GitError::RefError {
+                name: name.to_string(),
+                message: format!("bad ref {}", name),
+            });
+        }
+        if path.is_dir() {
+            return Ok(None);
+        }
+
+        Ok(self
+            .packed_refs()?
+            .into_iter()
+            .find(|(packed, _)| packed == name)
+            .map(|(_, sha)| RefTarget::Object(sha)))
+    }
+
+    /// Returns true if `name` exists, as a loose or packed ref.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::read].
+    pub fn exists(&self, name: &str) -> GitResult<bool> {
+        Ok(self.read(name)?.is_some())
+    }
+
+    /// Follows symbolic refs starting at `name` and returns the name of the last ref, which may
+    /// not exist yet (like the branch of an unborn `HEAD`).
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RefError]: The symbolic refs form a loop
+    /// * [GitError::IOError]: A ref can not be read
+    pub fn resolve_name(&self, name: &str) -> GitResult<String> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMREF_DEPTH {
+            match self.read(&name)? {
+                Some(RefTarget::Symbolic(target)) => name = target,
+                _ => return Ok(name),
+            }
+        }
+        Err(GitError::RefError {
+            message: format!("symbolic ref {} is nested too deeply", name),
+            name,
+        })
+    }
+
+    /// Returns the object name `name` points to after following symbolic refs, or [None] if the
+    /// ref (or the ref it points to) does not exist.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::resolve_name].
+    pub fn resolve(&self, name: &str) -> GitResult<Option<String>> {
+        let name = self.resolve_name(name)?;
+        match self.read(&name)? {
+            Some(RefTarget::Object(sha)) => Ok(Some(sha)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the full name of the branch `HEAD` points to, or [None] if `HEAD` is detached.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::read].
+    pub fn head_branch(&self) -> GitResult<Option<String>> {
+        match self.read("HEAD")? {
+            Some(RefTarget::Symbolic(target)) => Ok(Some(target)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Expands a short name like `main` or `v1.0` to the full name of an existing ref, using the
+    /// same rules as git: the name itself, then `refs/`, `refs/tags/`, `refs/heads/`,
+    /// `refs/remotes/` and `refs/remotes/<name>/HEAD`.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::read].
+    pub fn dwim(&self, short: &str) -> GitResult<Option<String>> {
+        if !is_valid_ref_name(short) && short != "HEAD" {
+            return Ok(None);
+        }
+        let candidates = [
+            short.to_string(),
+            format!("refs/{}", short),
+            format!("refs/tags/{}", short),
+            format!("refs/heads/{}", short),
+            format!("refs/remotes/{}", short),
+            format!("refs/remotes/{}/HEAD", short),
+        ];
+        for candidate in candidates.iter() {
+            // Only HEAD-like names are looked up directly in the git directory.
+            if candidate == short
+                && !short.starts_with("refs/")
+                && !short.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+            {
+                continue;
+            }
+            if self.resolve(candidate)?.is_some() {
+                return Ok(Some(candidate.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns every ref below `prefix` (e.g. `refs/heads/`) with the object it points to, sorted
+    /// by name. Symbolic refs are resolved; dangling ones are skipped.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: A ref or ref directory can not be read
+    pub fn list(&self, prefix: &str) -> GitResult<Vec<(String, String)>> {
+        let mut names = Vec::new();
+        collect_loose(&self.gitpath().join("refs"), "refs/", &mut names)?;
+        let mut refs = Vec::new();
+        for name in names {
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(sha) = self.resolve(&name)? {
+                refs.push((name, sha));
+            }
+        }
+        for (name, sha) in self.packed_refs()? {
+            if name.starts_with(prefix) && !refs.iter().any(|(loose, _)| *loose == name) {
+                refs.push((name, sha));
+            }
+        }
+        refs.sort();
+        Ok(refs)
+    }
+
+    /// Points `name` at the object `sha` without following symbolic refs, and records the update
+    /// with `message` in the reflog.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RefError]: The name is invalid or conflicts with an existing ref
+    /// * [GitError::LockError]: The ref is locked by another process
+    /// * [GitError::IOError]: The ref can not be written
+    pub fn update(&self, name: &str, sha: &str, message: &str) -> GitResult<()> {
+        let old = self.resolve(name)?;
+        self.write_ref(name, &format!("{}\n", sha))?;
+        self.log_update(name, old.as_deref(), sha, message)
+    }
+
+    /// Points `HEAD`, or the branch it is on, at the object `sha`.
+    ///
+    /// The update is recorded in the reflogs of both `HEAD` and the branch.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::update].
+    pub fn update_head(&self, sha: &str, message: &str) -> GitResult<()> {
+        let target = self.resolve_name("HEAD")?;
+        if target == "HEAD" {
+            return self.update("HEAD", sha, message);
+        }
+        let old = self.resolve(&target)?;
+        self.update(&target, sha, message)?;
+        self.log_update("HEAD", old.as_deref(), sha, message)
+    }
+
+    /// Makes `name` a symbolic ref pointing to `target`, logging the change of the object `name`
+    /// resolves to.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::update].
+    pub fn set_symbolic(&self, name: &str, target: &str, message: &str) -> GitResult<()> {
+        let old = self.resolve(name)?;
+        self.write_ref(name, &format!("ref: {}\n", target))?;
+        if let Some(new) = self.resolve(target)? {
+            self.log_update(name, old.as_deref(), &new, message)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes `name` from the loose refs and `packed-refs`, along with its reflog.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RefError]: The ref does not exist
+    /// * [GitError::LockError]: The ref or `packed-refs` is locked by another process
+    /// * [GitError::IOError]: The ref can not be removed
+    pub fn delete(&self, name: &str) -> GitResult<()> {
+        if !self.exists(name)? {
+            return Err(GitError::RefError {
+                name: name.to_string(),
+                message: format!("cannot delete ref {}: it does not exist", name),
+            });
+        }
+
+        let path = self.ref_path(name);
+        if path.is_file() {
+            // Hold the lock while removing so concurrent writers notice.
+            let lock = LockFile::acquire(&path)?;
+            gitrs::to_git_result(fs::remove_file(&path), &path)?;
+            drop(lock);
+            self.remove_empty_dirs(&path, "refs");
+        }
+
+        let packed = self.packed_refs_raw()?;
+        if packed
+            .iter()
+            .any(|line| packed_line_name(line) == Some(name))
+        {
+            let mut data = String::new();
+            let mut skip_peeled = false;
+            for line in packed {
+                if line.starts_with('^') && skip_peeled {
+                    continue;
+                }
+                skip_peeled = packed_line_name(&line) == Some(name);
+                if !skip_peeled {
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+            }
+            utils::write_locked(self.gitpath().join("packed-refs"), data.as_bytes())?;
+        }
+
+        let log_path = self.gitpath().join("logs").join(name);
+        if log_path.is_file() {
+            gitrs::to_git_result(fs::remove_file(&log_path), &log_path)?;
+            self.remove_empty_dirs(&log_path, "logs");
+        }
+        Ok(())
+    }
+
+    /// Renames the ref `old` to `new`, moving its reflog along and recording the rename with
+    /// `message`. `HEAD` is updated if it points to `old`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::RefError]: `old` does not exist, or `new` is invalid or conflicts with an
+    ///   existing ref
+    /// * [GitError::LockError]: A ref is locked by another process
+    /// * [GitError::IOError]: A ref or reflog can not be moved
+    pub fn rename(&self, old: &str, new: &str, message: &str) -> GitResult<()> {
+        let sha = match self.read(old)? {
+            Some(RefTarget::Object(sha)) => sha,
+            _ => {
+                return Err(GitError::RefError {
+                    name: old.to_string(),
+                    message: format!("refname {} not found", old),
+                })
+            }
+        };
+        if self.exists(new)? {
+            return Err(GitError::RefError {
+                name: new.to_string(),
+                message: format!("'{}' already exists", new),
+            });
+        }
+
+        let old_log = self.gitpath().join("logs").join(old);
+        let log = if old_log.is_file() {
+            Some(gitrs::to_git_result(fs::read(&old_log), &old_log)?)
+        } else {
+            None
+        };
+        // Deleting first lets `a` be renamed to `a/b` and back.
+        self.delete(old)?;
+        if let Err(error) = self.write_ref(new, &format!("{}\n", sha)) {
+            self.write_ref(old, &format!("{}\n", sha))?;
+            if let Some(log) = &log {
+                utils::create_dir_all_if_new(old_log.parent().unwrap())?;
+                gitrs::to_git_result(fs::write(&old_log, log), &old_log)?;
+            }
+            return Err(error);
+        }
+        if let Some(log) = log {
+            let new_log = self.gitpath().join("logs").join(new);
+            utils::create_dir_all_if_new(new_log.parent().unwrap())?;
+            gitrs::to_git_result(fs::write(&new_log, log), &new_log)?;
+        }
+        self.log_update(new, Some(&sha), &sha, message)?;
+
+        if self.read("HEAD")? == Some(RefTarget::Symbolic(old.to_string())) {
+            self.write_ref("HEAD", &format!("ref: {}\n", new))?;
+            self.log_update("HEAD", Some(&sha), &sha, message)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the reflog of `name`, oldest entry first. A missing reflog is empty.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The reflog can not be read
+    pub fn reflog(&self, name: &str) -> GitResult<Vec<ReflogEntry>> {
+        let path = self.gitpath().join("logs").join(name);
+        if !path.is_file() {
+            return Ok(Vec::new());
+        }
+        Ok(utils::read_file(&path)?
+            .lines()
+            .filter_map(ReflogEntry::parse)
+            .collect())
+    }
+
+    /// Returns the name of the branch (or commit) checked out `n` switches ago, read from the
+    /// `checkout: moving from <old> to <new>` messages of the `HEAD` reflog.
+    ///
+    /// # Errors
+    ///
+    /// See [GitRefs::reflog].
+    pub fn previous_checkout(&self, n: usize) -> GitResult<Option<String>> {
+        let n = match n.checked_sub(1) {
+            Some(n) => n,
+            None => return Ok(None),
+        };
+        let previous = self
+            .reflog("HEAD")?
+            .into_iter()
+            .rev()
+            .filter_map(|entry| {
+                let moved = entry.message.strip_prefix("checkout: moving from ")?;
+                let to = moved.rfind(" to ")?;
+                Some(moved[..to].to_string())
+            })
+            .nth(n);
+        Ok(previous)
+    }
+
+    fn write_ref(&self, name: &str, contents: &str) -> GitResult<()> {
+        if name != "HEAD" && !is_valid_ref_name(name) {
+            return Err(GitError::RefError {
+                name: name.to_string(),
+                message: format!("'{}' is not a valid ref name", name),
+            });
+        }
+        let path = self.ref_path(name);
+
+        // A ref can not be created where a directory of refs (or a ref for a parent) exists.
+        let conflict = if path.is_dir() {
+            Some(name.to_string())
+        } else {
+            name.match_indices('/')
+                .map(|(i, _)| &name[..i])
+                .find(|parent| self.ref_path(parent).is_file())
+                .map(String::from)
+        };
+        if let Some(conflict) = conflict {
+            return Err(GitError::RefError {
+                name: name.to_string(),
+                message: format!(
+                    "cannot lock ref '{}': '{}' exists; cannot create '{}'",
+                    name, conflict, name
+                ),
+            });
+        }
+
+        utils::create_dir_all_if_new(path.parent().unwrap())?;
+        utils::write_locked(&path, contents.as_bytes())
+    }
+
+    /// Returns true if updates of `name` are written to its reflog.
+    fn should_log(&self, name: &str) -> GitResult<bool> {
+        if self.gitpath().join("logs").join(name).is_file() {
+            return Ok(true);
+        }
+        let setting = config::get(&self.repo, "core.logAllRefUpdates")?;
+        match setting.as_deref() {
+            Some(value) if value.eq_ignore_ascii_case("always") => Ok(true),
+            Some(value) if !config::is_true(value) => Ok(false),
+            _ => {
+                if setting.is_none() && config::get_bool(&self.repo, "core.bare", false)? {
+                    return Ok(false);
+                }
+                Ok(name == "HEAD"
+                    || name.starts_with("refs/heads/")
+                    || name.starts_with("refs/remotes/")
+                    || name.starts_with("refs/notes/"))
+            }
+        }
+    }
+
+    fn log_update(&self, name: &str, old: Option<&str>, new: &str, message: &str) -> GitResult<()> {
+        if !self.should_log(name)? {
+            return Ok(());
+        }
+        let signature = GitSignature::committer_or_default(&self.repo)?;
+        let message = message.replace('\n', " ");
+        let line = format!(
+            "{} {} {}\t{}\n",
+            old.unwrap_or(NULL_SHA),
+            new,
+            signature,
+            message.trim()
+        );
+
+        let path = self.gitpath().join("logs").join(name);
+        utils::create_dir_all_if_new(path.parent().unwrap())?;
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        gitrs::to_git_result(result, &path)
+    }
+
+    /// Returns the lines of `packed-refs`.
+    fn packed_refs_raw(&self) -> GitResult<Vec<String>> {
+        let path = self.gitpath().join("packed-refs");
+        match fs::read_to_string(&path) {
+            Ok(data) => Ok(data.lines().map(String::from).collect()),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(error) => Err(GitError::IOError { error, path }),
+        }
+    }
+
+    /// Returns the refs in `packed-refs` as (name, object) pairs.
+    fn packed_refs(&self) -> GitResult<Vec<(String, String)>> {
+        Ok(self
+            .packed_refs_raw()?
+            .iter()
+            .filter_map(|line| {
+                let name = packed_line_name(line)?;
+                Some((name.to_string(), line[..40].to_string()))
+            })
+            .collect())
+    }
+
+    fn remove_empty_dirs(&self, path: &Path, stop: &str) {
+        let stop = self.gitpath().join(stop);
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if current == stop || !current.starts_with(&stop) || fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
+
+/// Returns the ref name of a `packed-refs` line, or [None] for comments and peeled lines.
+fn packed_line_name(line: &str) -> Option<&str> {
+    if line.starts_with('#') || line.starts_with('^') || line.len() < 42 {
+        return None;
+    }
+    Some(&line[41..])
+}
+
+fn collect_loose(dir: &Path, prefix: &str, names: &mut Vec<String>) -> GitResult<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in gitrs::to_git_result(fs::read_dir(dir), dir)? {
+        let entry = gitrs::to_git_result(entry, dir)?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        let path = entry.path();
+        if path.is_dir() {
+            collect_loose(&path, &format!("{}/", name), names)?;
+        } else if !name.ends_with(".lock") {
+            names.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if `name` is a valid ref name according to the rules of `git check-ref-format`.
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('/')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("//")
+        || name.contains("@{")
+    {
+        return false;
+    }
+    let bad_char = |ch: char| {
+        ch.is_ascii_control() || matches!(ch, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+    };
+    if name.chars().any(bad_char) {
+        return false;
+    }
+    name.split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// Returns true if `name` can be used as a branch name.
+pub fn is_valid_branch_name(name: &str) -> bool {
+    !name.starts_with('-') && name != "HEAD" && is_valid_ref_name(&format!("refs/heads/{}", name))
+}
+
+/// Shortens a full ref name for display, e.g. `refs/heads/main` to `main`.
+pub fn shorten(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
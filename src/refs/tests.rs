@@ -0,0 +1,159 @@
+use super::*;
+use crate::test_utils;
+use std::error::Error;
+use std::fs;
+
+const SHA1: &str = "1111111111111111111111111111111111111111";
+const SHA2: &str = "2222222222222222222222222222222222222222";
+
+/// The config of the repositories the tests update refs in, which keeps reflogs.
+const CONFIG: &str =
+    "[core]\n\tlogallrefupdates = true\n[user]\n\tname = A U Thor\n\temail = author@example.com\n";
+
+#[test]
+fn test_ref_names() {
+    assert!(is_valid_ref_name("refs/heads/main"));
+    assert!(is_valid_ref_name("refs/heads/feature/x-1"));
+    assert!(!is_valid_ref_name("refs/heads/a..b"));
+    assert!(!is_valid_ref_name("refs/heads/a.lock"));
+    assert!(!is_valid_ref_name("refs/heads/.hidden"));
+    assert!(!is_valid_ref_name("refs/heads/a b"));
+    assert!(!is_valid_ref_name("refs/heads/a@{1}"));
+    assert!(!is_valid_ref_name("refs/heads/"));
+    assert!(is_valid_branch_name("topic"));
+    assert!(!is_valid_branch_name("-topic"));
+    assert!(!is_valid_branch_name("HEAD"));
+    assert_eq!(shorten("refs/heads/main"), "main");
+    assert_eq!(shorten("refs/remotes/origin/main"), "origin/main");
+}
+
+#[test]
+fn test_update_and_resolve() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup_with_config("gitrs_test_refs_update", CONFIG)?;
+    let refs = GitRefs::new(&repo);
+
+    assert_eq!(refs.resolve("HEAD")?, None);
+    assert_eq!(refs.head_branch()?.as_deref(), Some("refs/heads/main"));
+    refs.update_head(SHA1, "commit (initial): first")?;
+    refs.update_head(SHA2, "commit: second")?;
+    assert_eq!(refs.resolve("HEAD")?.as_deref(), Some(SHA2));
+    assert_eq!(refs.resolve("refs/heads/main")?.as_deref(), Some(SHA2));
+
+    let log = refs.reflog("refs/heads/main")?;
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[0].old_sha(), NULL_SHA);
+    assert_eq!(log[1].old_sha(), SHA1);
+    assert_eq!(log[1].message(), "commit: second");
+    assert_eq!(log[1].signature().name(), "A U Thor");
+    assert_eq!(refs.reflog("HEAD")?.len(), 2);
+
+    // A ref can not be created below an existing one.
+    assert!(refs
+        .update("refs/heads/main/topic", SHA1, "branch")
+        .is_err());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_packed_refs() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup_with_config("gitrs_test_refs_packed", CONFIG)?;
+    fs::write(
+        repo.gitpath().join("packed-refs"),
+        format!(
+            "# pack-refs with: peeled fully-peeled sorted \n{} refs/heads/main\n{} refs/tags/v1.0\n^{}\n",
+            SHA1, SHA2, SHA1
+        ),
+    )?;
+    let refs = GitRefs::new(&repo);
+
+    assert_eq!(refs.dwim("v1.0")?.as_deref(), Some("refs/tags/v1.0"));
+    assert_eq!(refs.dwim("main")?.as_deref(), Some("refs/heads/main"));
+    assert_eq!(refs.dwim("missing")?, None);
+
+    // Loose refs take precedence over packed ones.
+    refs.update("refs/heads/main", SHA2, "reset")?;
+    assert_eq!(refs.resolve("main")?, None);
+    assert_eq!(refs.resolve("refs/heads/main")?.as_deref(), Some(SHA2));
+    assert_eq!(
+        refs.list("refs/")?,
+        [
+            ("refs/heads/main".to_string(), SHA2.to_string()),
+            ("refs/tags/v1.0".to_string(), SHA2.to_string())
+        ]
+    );
+
+    refs.delete("refs/heads/main")?;
+    assert!(!refs.exists("refs/heads/main")?);
+    let packed = fs::read_to_string(repo.gitpath().join("packed-refs"))?;
+    assert!(!packed.contains("refs/heads/main"));
+    assert!(packed.contains("refs/tags/v1.0\n^"));
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_previous_checkout() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup_with_config("gitrs_test_refs_previous", CONFIG)?;
+    let refs = GitRefs::new(&repo);
+    refs.update("refs/heads/main", SHA1, "branch: Created from HEAD")?;
+    refs.update("refs/heads/topic", SHA1, "branch: Created from HEAD")?;
+    refs.set_symbolic(
+        "HEAD",
+        "refs/heads/topic",
+        "checkout: moving from main to topic",
+    )?;
+    refs.set_symbolic(
+        "HEAD",
+        "refs/heads/main",
+        "checkout: moving from topic to main",
+    )?;
+
+    assert_eq!(refs.previous_checkout(1)?.as_deref(), Some("topic"));
+    assert_eq!(refs.previous_checkout(2)?.as_deref(), Some("main"));
+    assert_eq!(refs.previous_checkout(3)?, None);
+    assert_eq!(refs.previous_checkout(0)?, None);
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_rename() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup_with_config("gitrs_test_refs_rename", CONFIG)?;
+    let refs = GitRefs::new(&repo);
+    refs.update_head(SHA1, "commit (initial): first")?;
+
+    refs.rename(
+        "refs/heads/main",
+        "refs/heads/main/nested",
+        "Branch: renamed",
+    )?;
+    assert!(!refs.exists("refs/heads/main")?);
+    assert_eq!(refs.resolve("HEAD")?.as_deref(), Some(SHA1));
+    assert_eq!(
+        refs.head_branch()?.as_deref(),
+        Some("refs/heads/main/nested")
+    );
+    let log = refs.reflog("refs/heads/main/nested")?;
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[1].old_sha(), SHA1);
+    assert_eq!(refs.reflog("HEAD")?.last().unwrap().old_sha(), SHA1);
+
+    refs.update("refs/heads/other", SHA2, "branch")?;
+    assert!(refs
+        .rename(
+            "refs/heads/main/nested",
+            "refs/heads/other",
+            "Branch: renamed"
+        )
+        .is_err());
+    assert!(refs
+        .rename("refs/heads/missing", "refs/heads/x", "")
+        .is_err());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::refs::{GitRefs, NULL_SHA};
+use crate::{GitCommit, GitCommitGraph, GitOdb, GitResult};
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+/// The generation of commits missing from the commit-graph.
+const GENERATION_INFINITY: u64 = u64::MAX;
+
+/// Returns the best common ancestors of `one` and all of `others`, newest first, like
+/// `git merge-base --all`. None of them is an ancestor of another.
+///
+/// # Errors
+///
+/// * [GitError::ObjectError]: A commit on the way is missing or corrupt
+///
+/// [GitError::ObjectError]: crate::GitError::ObjectError
+pub fn merge_bases(odb: &GitOdb, one: &str, others: &[String]) -> GitResult<Vec<String>> {
+    Ancestry::new(odb).merge_bases(one, others)
+}
+
+/// Returns the best common ancestors of all `commits` together, like
+/// `git merge-base --octopus --all`.
+///
+/// # Errors
+///
+/// See [merge_bases].
+pub fn octopus_merge_bases(odb: &GitOdb, commits: &[String]) -> GitResult<Vec<String>> {
+    let ancestry = Ancestry::new(odb);
+    let mut bases: Vec<String> = commits.iter().take(1).cloned().collect();
+    for commit in commits.iter().skip(1) {
+        let mut next = Vec::new();
+        for base in &bases {
+            next.extend(ancestry.merge_bases(commit, std::slice::from_ref(base))?);
+        }
+        bases = next;
+    }
+    ancestry.independent(&bases)
+}
+
+/// Returns the `commits` that are not reachable from any of the others, in their order, like
+/// `git merge-base --independent`.
+///
+/// # Errors
+///
+/// See [merge_bases].
+pub fn independent_commits(odb: &GitOdb, commits: &[String]) -> GitResult<Vec<String>> {
+    Ancestry::new(odb).independent(commits)
+}
+
+/// Returns true if the commit `ancestor` is reachable from the commit `commit` (or is the same
+/// commit).
+///
+/// # Errors
+///
+/// See [merge_bases].
+pub fn is_ancestor(odb: &GitOdb, ancestor: &str, commit: &str) -> GitResult<bool> {
+    let ancestry = Ancestry::new(odb);
+    let generation = ancestry.info(ancestor)?.generation;
+    if generation > ancestry.info(commit)?.generation {
+        return Ok(false);
+    }
+    let (_, flags) = ancestry.paint_down_to_common(ancestor, &[commit.to_string()], generation)?;
+    Ok(flags[ancestor] & PARENT2 != 0)
+}
+
+/// Returns the commit `commit` forked from the ref `name`, like `git merge-base --fork-point`:
+/// the common ancestor of `commit` and the values `name` had according to its reflog, if it
+/// is one of these values. Returns `None` if there is no such commit.
+///
+/// # Errors
+///
+/// * [GitError::IOError]: The reflog can not be read
+/// * [GitError::ObjectError]: A commit on the way is missing or corrupt
+///
+/// [GitError::IOError]: crate::GitError::IOError
+/// [GitError::ObjectError]: crate::GitError::ObjectError
+pub fn fork_point(
+    odb: &GitOdb,
+    refs: &GitRefs,
+    name: &str,
+    commit: &str,
+) -> GitResult<Option<String>> {
+    let log = refs.reflog(name)?;
+    let mut values: Vec<&str> = log.iter().take(1).map(|entry| entry.old_sha()).collect();
+    values.extend(log.iter().map(|entry| entry.new_sha()));
+    let current = refs.resolve(name)?;
+    if log.is_empty() {
+        values.extend(current.as_deref());
+    }
+
+    // Values that are not (or no longer) commits are skipped.
+    let mut candidates: Vec<String> = Vec::new();
+    for sha in values {
+        if sha != NULL_SHA
+            && !candidates.iter().any(|candidate| candidate == sha)
+            && odb.read_as::<GitCommit>(sha).is_ok()
+        {
+            candidates.push(sha.to_string());
+        }
+    }
+
+    let bases = merge_bases(odb, commit, &candidates)?;
+    Ok(match bases.as_slice() {
+        [base] if candidates.contains(base) => Some(base.clone()),
+        _ => None,
+    })
+}
+
+/// The parts of a commit needed to compare it with others.
+#[derive(Debug, Clone)]
+struct CommitInfo {
+    parents: Vec<String>,
+    time: i64,
+    generation: u64,
+}
+
+/// Finds common ancestors like git does, painting down from the commits until the paths
+/// meet. With a commit-graph, the walk goes by generation number and stops early where it
+/// can; otherwise it goes by commit date.
+struct Ancestry<'a> {
+    odb: &'a GitOdb,
+    graph: Option<GitCommitGraph>,
+    infos: RefCell<HashMap<String, CommitInfo>>,
+}
+
+impl<'a> Ancestry<'a> {
+    fn new(odb: &'a GitOdb) -> Self {
+        Self {
+            odb,
+            graph: GitCommitGraph::open(odb.objects_dir()).unwrap_or(None),
+            infos: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn info(&self, sha: &str) -> GitResult<CommitInfo> {
+        if let Some(info) = self.infos.borrow().get(sha) {
+            return Ok(info.clone());
+        }
+        let info = match self.graph.as_ref().and_then(|graph| graph.lookup(sha)) {
+            Some(commit) => CommitInfo {
+                parents: commit.parents().to_vec(),
+                time: commit.time(),
+                generation: commit.generation(),
+            },
+            None => {
+                let commit = self.odb.read_as::<GitCommit>(sha)?;
+                CommitInfo {
+                    parents: commit.parents().to_vec(),
+                    time: commit.committer().time(),
+                    generation: GENERATION_INFINITY,
+                }
+            }
+        };
+        self.infos
+            .borrow_mut()
+            .insert(sha.to_string(), info.clone());
+        Ok(info)
+    }
+
+    fn merge_bases(&self, one: &str, others: &[String]) -> GitResult<Vec<String>> {
+        if others.iter().any(|other| other == one) {
+            return Ok(vec![one.to_string()]);
+        }
+        let (found, flags) = self.paint_down_to_common(one, others, 0)?;
+        let mut bases = Vec::new();
+        for sha in found {
+            if flags[&sha] & STALE == 0 {
+                self.insert_by_date(&mut bases, sha)?;
+            }
+        }
+        if bases.len() <= 1 {
+            return Ok(bases);
+        }
+
+        // A base found before its descendant was can still be an ancestor of another base.
+        let mut sorted = Vec::new();
+        for sha in self.independent(&bases)? {
+            self.insert_by_date(&mut sorted, sha)?;
+        }
+        Ok(sorted)
+    }
+
+    fn independent(&self, commits: &[String]) -> GitResult<Vec<String>> {
+        let mut unique: Vec<&String> = Vec::new();
+        for commit in commits {
+            if !unique.contains(&commit) {
+                unique.push(commit);
+            }
+        }
+
+        let mut redundant = vec![false; unique.len()];
+        for i in 0..unique.len() {
+            if redundant[i] {
+                continue;
+            }
+            let others: Vec<usize> = (0..unique.len())
+                .filter(|&j| j != i && !redundant[j])
+                .collect();
+            let work: Vec<String> = others.iter().map(|&j| unique[j].clone()).collect();
+            // Nothing below the lowest generation can reach one of the commits.
+            let mut min_generation = self.info(unique[i])?.generation;
+            for sha in &work {
+                min_generation = min_generation.min(self.info(sha)?.generation);
+            }
+            let (_, flags) = self.paint_down_to_common(unique[i], &work, min_generation)?;
+            if flags[unique[i]] & PARENT2 != 0 {
+                redundant[i] = true;
+            }
+            for &j in &others {
+                if flags[unique[j]] & PARENT1 != 0 {
+                    redundant[j] = true;
+                }
+            }
+        }
+        Ok(unique
+            .into_iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(sha, _)| sha.clone())
+            .collect())
+    }
+
+    /// Walks down from `one` and `others`, marking commits reachable from `one` with PARENT1
+    /// and from the others with PARENT2, and returns the commits reached from both sides first
+    /// along with the marks. The ancestors of these commits are marked STALE; the walk stops
+    /// when only stale commits are left, or at commits below `min_generation`.
+    fn paint_down_to_common(
+        &self,
+        one: &str,
+        others: &[String],
+        min_generation: u64,
+    ) -> GitResult<(Vec<String>, HashMap<String, u8>)> {
+        let mut flags: HashMap<String, u8> = HashMap::new();
+        flags.insert(one.to_string(), PARENT1);
+        if others.is_empty() {
+            return Ok((vec![one.to_string()], flags));
+        }
+
+        // Without a cutoff, topological levels order the walk worse than commit dates do.
+        let by_generation = min_generation > 0
+            || self
+                .graph
+                .as_ref()
+                .is_some_and(|graph| graph.has_generation_data());
+        let mut queue = BinaryHeap::new();
+        let mut counter = 0;
+        let mut push = |queue: &mut BinaryHeap<_>, sha: &str| -> GitResult<()> {
+            let info = self.info(sha)?;
+            let generation = if by_generation { info.generation } else { 0 };
+            queue.push((generation, info.time, Reverse(counter), sha.to_string()));
+            counter += 1;
+            Ok(())
+        };
+        push(&mut queue, one)?;
+        for other in others {
+            *flags.entry(other.clone()).or_default() |= PARENT2;
+            push(&mut queue, other)?;
+        }
+
+        let mut found = Vec::new();
+        while queue.iter().any(|(.., sha)| flags[sha] & STALE == 0) {
+            let (generation, _, _, sha) = queue.pop().unwrap();
+            if generation < min_generation {
+                break;
+            }
+            let mut marks = flags[&sha] & (PARENT1 | PARENT2 | STALE);
+            if marks == PARENT1 | PARENT2 {
+                if flags[&sha] & RESULT == 0 {
+                    *flags.get_mut(&sha).unwrap() |= RESULT;
+                    self.insert_by_date(&mut found, sha.clone())?;
+                }
+                marks |= STALE;
+            }
+            for parent in self.info(&sha)?.parents {
+                let parent_flags = flags.entry(parent.clone()).or_default();
+                if *parent_flags & marks == marks {
+                    continue;
+                }
+                *parent_flags |= marks;
+                push(&mut queue, &parent)?;
+            }
+        }
+        Ok((found, flags))
+    }
+
+    /// Inserts `sha` into `list` before the first older commit.
+    fn insert_by_date(&self, list: &mut Vec<String>, sha: String) -> GitResult<()> {
+        let time = self.info(&sha)?.time;
+        let mut position = list.len();
+        for (i, other) in list.iter().enumerate() {
+            if self.info(other)?.time < time {
+                position = i;
+                break;
+            }
+        }
+        list.insert(position, sha);
+        Ok(())
+    }
+}
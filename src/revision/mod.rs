@@ -0,0 +1,287 @@
+//! Resolving revision expressions like `main~2`, `v1.0^{tree}` or `HEAD:src/lib.rs` to objects.
+
+mod merge_base;
+#[cfg(test)]
+mod tests;
+mod walk;
+
+use std::collections::HashSet;
+
+use crate::objects::ObjectType;
+use crate::refs::GitRefs;
+use crate::{GitCommit, GitError, GitOdb, GitResult, GitTag, GitTree};
+
+pub use self::merge_base::{
+    fork_point, independent_commits, is_ancestor, merge_bases, octopus_merge_bases,
+};
+pub use self::walk::{RevSort, RevWalk, WalkOptions, WalkResult, WalkedCommit};
+
+/// Object names are abbreviated to at least this many hex digits.
+pub const DEFAULT_ABBREV: usize = 7;
+
+/// Resolves the revision expression `rev` to an object name.
+///
+/// Supported are full and abbreviated object names, ref names (with the same lookup rules as
+/// git), `@` for `HEAD`, `<ref>@{<n>}` reflog entries, `@{-<n>}` for the n-th previously checked
+/// out branch, the suffixes `^`, `^<n>`, `~`, `~<n>`, `^{}` and `^{<type>}`, and `<rev>:<path>`.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: The expression is invalid, unknown or ambiguous
+/// * [GitError::ObjectError]: An object on the way is missing or corrupt
+pub fn rev_parse(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<String> {
+    if let Some(colon) = rev.find(':') {
+        let (base, path) = (&rev[..colon], &rev[colon + 1..]);
+        if base.is_empty() {
+            return Err(error(rev, "looking up paths in the index is not supported"));
+        }
+        let tree = peel(odb, &rev_parse(odb, refs, base)?, ObjectType::Tree)
+            .map_err(|_| error(rev, &format!("'{}' is not a tree-ish", base)))?;
+        let path = path.trim_start_matches("./").trim_end_matches('/');
+        if path.is_empty() {
+            return Ok(tree);
+        }
+        return match GitTree::find_path(odb, &tree, path)? {
+            Some(entry) => Ok(entry.sha().to_string()),
+            None => Err(error(
+                rev,
+                &format!("path '{}' does not exist in '{}'", path, base),
+            )),
+        };
+    }
+
+    // Ref names can not contain '^' or '~', so the first of them ends the base revision.
+    let split = rev.find(['^', '~']).unwrap_or(rev.len());
+    let (base, mut suffix) = rev.split_at(split);
+    let mut sha = resolve_base(odb, refs, base, rev)?;
+
+    while !suffix.is_empty() {
+        let op = suffix.as_bytes()[0];
+        suffix = &suffix[1..];
+        if op == b'^' && suffix.starts_with('{') {
+            let close = suffix
+                .find('}')
+                .ok_or_else(|| error(rev, "missing '}' in revision"))?;
+            let kind = &suffix[1..close];
+            suffix = &suffix[close + 1..];
+            sha = match kind {
+                "" => peel_tags(odb, &sha)?,
+                "object" => sha,
+                kind => match ObjectType::from_name(kind) {
+                    Some(kind) => peel(odb, &sha, kind)?,
+                    None => return Err(error(rev, &format!("invalid object type '{}'", kind))),
+                },
+            };
+            continue;
+        }
+
+        let digits = suffix.bytes().take_while(u8::is_ascii_digit).count();
+        let n = if digits == 0 {
+            1
+        } else {
+            suffix[..digits]
+                .parse::<usize>()
+                .map_err(|_| error(rev, "invalid number in revision"))?
+        };
+        suffix = &suffix[digits..];
+
+        let commit_sha = peel(odb, &sha, ObjectType::Commit)?;
+        if op == b'^' {
+            if n == 0 {
+                sha = commit_sha;
+                continue;
+            }
+            let commit = odb.read_as::<GitCommit>(&commit_sha)?;
+            sha = commit
+                .parents()
+                .get(n - 1)
+                .cloned()
+                .ok_or_else(|| unknown(rev))?;
+        } else {
+            sha = commit_sha;
+            for _ in 0..n {
+                let commit = odb.read_as::<GitCommit>(&sha)?;
+                sha = commit
+                    .parents()
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| unknown(rev))?;
+            }
+        }
+    }
+
+    Ok(sha)
+}
+
+/// Resolves `rev` like [rev_parse] and peels the result to a commit.
+///
+/// # Errors
+///
+/// See [rev_parse] and [peel].
+pub fn resolve_commit(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<String> {
+    let sha = rev_parse(odb, refs, rev)?;
+    peel(odb, &sha, ObjectType::Commit)
+}
+
+fn resolve_base(odb: &GitOdb, refs: &GitRefs, base: &str, rev: &str) -> GitResult<String> {
+    if base.is_empty() {
+        return Err(unknown(rev));
+    }
+    if base == "@" {
+        return refs.resolve("HEAD")?.ok_or_else(|| unknown(rev));
+    }
+
+    if let Some(at) = base.find("@{") {
+        let inner = base[at + 2..]
+            .strip_suffix('}')
+            .ok_or_else(|| error(rev, "missing '}' in revision"))?;
+        if let Some(n) = inner.strip_prefix('-') {
+            let n = n.parse::<usize>().map_err(|_| unknown(rev))?;
+            if at != 0 {
+                return Err(unknown(rev));
+            }
+            let previous = refs.previous_checkout(n)?.ok_or_else(|| {
+                error(
+                    rev,
+                    &format!("ambiguous argument '{}': no {} previous branch", rev, n),
+                )
+            })?;
+            return resolve_base(odb, refs, &previous, rev);
+        }
+
+        let n = inner.parse::<usize>().map_err(|_| unknown(rev))?;
+        let name = match &base[..at] {
+            "" => refs.resolve_name("HEAD")?,
+            name => refs.dwim(name)?.ok_or_else(|| unknown(rev))?,
+        };
+        let log = refs.reflog(&name)?;
+        return match log.len().checked_sub(n + 1) {
+            Some(i) => Ok(log[i].new_sha().to_string()),
+            None => Err(error(
+                rev,
+                &format!("log for '{}' only has {} entries", base, log.len()),
+            )),
+        };
+    }
+
+    if base.len() == 40 && base.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(base.to_ascii_lowercase());
+    }
+    if let Some(name) = refs.dwim(base)? {
+        if let Some(sha) = refs.resolve(&name)? {
+            return Ok(sha);
+        }
+    }
+    if base.len() >= 4 && base.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let found = odb.find_prefix(base)?;
+        match found.len() {
+            0 => {}
+            1 => return Ok(found[0].clone()),
+            _ => return Err(error(rev, &format!("short SHA1 {} is ambiguous", base))),
+        }
+    }
+    Err(unknown(rev))
+}
+
+/// Follows tags (and from commits to their tree) until an object of type `kind` is reached.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: The object can not be peeled to `kind`
+/// * [GitError::ObjectError]: An object is missing or corrupt
+pub fn peel(odb: &GitOdb, sha: &str, kind: ObjectType) -> GitResult<String> {
+    let mut sha = sha.to_string();
+    loop {
+        let found = odb.read_type(&sha)?;
+        if found == kind {
+            return Ok(sha);
+        }
+        match found {
+            ObjectType::Tag => sha = odb.read_as::<GitTag>(&sha)?.object().to_string(),
+            ObjectType::Commit if kind == ObjectType::Tree => {
+                sha = odb.read_as::<GitCommit>(&sha)?.tree().to_string()
+            }
+            _ => {
+                return Err(GitError::RevisionError {
+                    message: format!("{} is a {}, not a {}", sha, found, kind),
+                    rev: sha,
+                })
+            }
+        }
+    }
+}
+
+/// Follows tags until a non-tag object is reached.
+///
+/// # Errors
+///
+/// * [GitError::ObjectError]: An object is missing or corrupt
+pub fn peel_tags(odb: &GitOdb, sha: &str) -> GitResult<String> {
+    let mut sha = sha.to_string();
+    while odb.read_type(&sha)? == ObjectType::Tag {
+        sha = odb.read_as::<GitTag>(&sha)?.object().to_string();
+    }
+    Ok(sha)
+}
+
+/// Returns how many commits are reachable from `commit` but not from `base`, and the other way
+/// around.
+///
+/// # Errors
+///
+/// * [GitError::ObjectError]: A commit on the way is missing or corrupt
+pub fn ahead_behind(odb: &GitOdb, commit: &str, base: &str) -> GitResult<(usize, usize)> {
+    let ours = reachable(odb, commit)?;
+    let theirs = reachable(odb, base)?;
+    Ok((
+        ours.difference(&theirs).count(),
+        theirs.difference(&ours).count(),
+    ))
+}
+
+fn reachable(odb: &GitOdb, commit: &str) -> GitResult<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![commit.to_string()];
+    while let Some(sha) = stack.pop() {
+        if seen.insert(sha.clone()) {
+            stack.extend(odb.read_as::<GitCommit>(&sha)?.parents().iter().cloned());
+        }
+    }
+    Ok(seen)
+}
+
+/// Returns the shortest prefix of `sha` of at least `min_len` digits that names no other
+/// object.
+///
+/// # Errors
+///
+/// * [GitError::IOError]: The object database can not be read
+pub fn abbreviate(odb: &GitOdb, sha: &str, min_len: usize) -> GitResult<String> {
+    let candidates = odb.find_prefix(&sha[..min_len.min(sha.len())])?;
+    let mut len = min_len.min(sha.len());
+    while len < sha.len()
+        && candidates
+            .iter()
+            .any(|other| other != sha && other.starts_with(&sha[..len]))
+    {
+        len += 1;
+    }
+    Ok(sha[..len].to_string())
+}
+
+fn error(rev: &str, message: &str) -> GitError {
+    GitError::RevisionError {
+        rev: rev.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn unknown(rev: &str) -> GitError {
+    error(
+        rev,
+        &format!(
+            "ambiguous argument '{}': unknown revision or path not in the working tree.",
+            rev
+        ),
+    )
+}
@@ -0,0 +1,366 @@
+use super::*;
+use crate::objects::ObjectType;
+use crate::test_utils::{self, tree};
+use crate::{GitFileMode, GitSignature, Pathspec};
+use regex::Regex;
+use std::error::Error;
+use std::fs;
+
+fn commit(odb: &GitOdb, content: &str, parents: &[&str]) -> GitResult<String> {
+    let tree = tree(odb, &[("src/file.txt", GitFileMode::regular(), content)])?;
+    let signature = GitSignature::new("A U Thor", "author@example.com", 1_000_000_000, 0);
+    let message = format!("{}\n", content);
+    test_utils::commit(odb, &tree, parents, signature.clone(), signature, &message)
+}
+
+#[test]
+fn test_rev_parse() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_rev_parse")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first", &[])?;
+    let second = commit(&odb, "second", &[&first])?;
+    let side = commit(&odb, "side", &[&first])?;
+    let merge = commit(&odb, "merge", &[&second, &side])?;
+    refs.update_head(&merge, "commit")?;
+
+    assert_eq!(rev_parse(&odb, &refs, "HEAD")?, merge);
+    assert_eq!(rev_parse(&odb, &refs, "@")?, merge);
+    assert_eq!(rev_parse(&odb, &refs, "main")?, merge);
+    assert_eq!(rev_parse(&odb, &refs, "main^")?, second);
+    assert_eq!(rev_parse(&odb, &refs, "main^2")?, side);
+    assert_eq!(rev_parse(&odb, &refs, "HEAD~2")?, first);
+    assert_eq!(rev_parse(&odb, &refs, "HEAD^2~1")?, first);
+    assert_eq!(rev_parse(&odb, &refs, &merge[..8])?, merge);
+    assert!(rev_parse(&odb, &refs, "HEAD~3").is_err());
+    assert!(rev_parse(&odb, &refs, "HEAD^3").is_err());
+    assert!(rev_parse(&odb, &refs, "nothing").is_err());
+
+    let tree = odb.read_as::<GitCommit>(&merge)?.tree().to_string();
+    assert_eq!(rev_parse(&odb, &refs, "HEAD^{tree}")?, tree);
+    assert_eq!(rev_parse(&odb, &refs, "HEAD:")?, tree);
+    let blob = rev_parse(&odb, &refs, "HEAD:src/file.txt")?;
+    assert_eq!(odb.read(&blob)?, (ObjectType::Blob, b"merge".to_vec()));
+    assert!(rev_parse(&odb, &refs, "HEAD:missing").is_err());
+    assert!(rev_parse(&odb, &refs, "HEAD^{tree}^{commit}").is_err());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_reflog_revisions() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_rev_parse_reflog")?;
+    let odb = GitOdb::open(&repo)?;
+    fs::write(
+        repo.gitpath().join("config"),
+        "[core]\n\tlogallrefupdates = true\n",
+    )?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first", &[])?;
+    let second = commit(&odb, "second", &[&first])?;
+    refs.update_head(&first, "commit (initial)")?;
+    refs.update_head(&second, "commit")?;
+
+    assert_eq!(rev_parse(&odb, &refs, "main@{0}")?, second);
+    assert_eq!(rev_parse(&odb, &refs, "main@{1}")?, first);
+    assert_eq!(rev_parse(&odb, &refs, "@{1}")?, first);
+    assert!(rev_parse(&odb, &refs, "main@{2}").is_err());
+
+    refs.update("refs/heads/topic", &first, "branch")?;
+    refs.set_symbolic(
+        "HEAD",
+        "refs/heads/topic",
+        "checkout: moving from main to topic",
+    )?;
+    assert_eq!(rev_parse(&odb, &refs, "@{-1}")?, second);
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_abbreviate() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_abbreviate")?;
+    let odb = GitOdb::open(&repo)?;
+    let sha = commit(&odb, "first", &[])?;
+
+    assert_eq!(abbreviate(&odb, &sha, DEFAULT_ABBREV)?, sha[..7]);
+    assert_eq!(abbreviate(&odb, &sha, 40)?, sha);
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_ancestry() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_ancestry")?;
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, "first", &[])?;
+    let second = commit(&odb, "second", &[&first])?;
+    let side = commit(&odb, "side", &[&first])?;
+    let merge = commit(&odb, "merge", &[&second, &side])?;
+
+    assert!(is_ancestor(&odb, &first, &merge)?);
+    assert!(is_ancestor(&odb, &side, &merge)?);
+    assert!(is_ancestor(&odb, &merge, &merge)?);
+    assert!(!is_ancestor(&odb, &side, &second)?);
+    assert_eq!(ahead_behind(&odb, &second, &side)?, (1, 1));
+    assert_eq!(ahead_behind(&odb, &merge, &first)?, (3, 0));
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_merge_bases() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_merge_bases")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first", &[])?;
+    let left = commit(&odb, "left", &[&first])?;
+    let right = commit(&odb, "right", &[&first])?;
+    // Merging both ways makes both sides best common ancestors.
+    let cross_left = commit(&odb, "cross left", &[&left, &right])?;
+    let cross_right = commit(&odb, "cross right", &[&right, &left])?;
+    let other = commit(&odb, "other", &[&left])?;
+
+    let mut bases = merge_bases(&odb, &cross_left, std::slice::from_ref(&cross_right))?;
+    bases.sort();
+    let mut expected = vec![left.clone(), right.clone()];
+    expected.sort();
+    assert_eq!(bases, expected);
+    assert_eq!(
+        merge_bases(&odb, &other, std::slice::from_ref(&right))?,
+        vec![first.clone()]
+    );
+    assert_eq!(
+        merge_bases(&odb, &other, std::slice::from_ref(&cross_right))?,
+        vec![left.clone()]
+    );
+    assert_eq!(
+        merge_bases(&odb, &other, &[right.clone(), cross_right.clone()])?,
+        vec![left.clone()]
+    );
+    assert_eq!(
+        octopus_merge_bases(&odb, &[other.clone(), cross_left.clone(), right.clone()])?,
+        vec![first]
+    );
+    assert_eq!(
+        independent_commits(
+            &odb,
+            &[
+                left.clone(),
+                cross_left.clone(),
+                other.clone(),
+                left.clone()
+            ]
+        )?,
+        [cross_left.clone(), other.clone()]
+    );
+
+    // The fork point is found even after the branch was rewound.
+    refs.update("refs/heads/upstream", &left, "branch: Created")?;
+    refs.update("refs/heads/upstream", &right, "reset")?;
+    assert_eq!(
+        fork_point(&odb, &refs, "refs/heads/upstream", &other)?,
+        Some(left)
+    );
+    assert_eq!(
+        fork_point(&odb, &refs, "refs/heads/upstream", &cross_right)?,
+        None
+    );
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+fn walk(
+    odb: &GitOdb,
+    tips: &[&str],
+    hidden: &[&str],
+    options: WalkOptions,
+) -> GitResult<Vec<String>> {
+    let mut walk = RevWalk::new(odb, options);
+    for tip in tips {
+        walk.push(tip);
+    }
+    for sha in hidden {
+        walk.hide(sha);
+    }
+    let result = walk.walk()?;
+    Ok(result
+        .commits()
+        .iter()
+        .map(|commit| commit.commit().message().trim_end().to_string())
+        .collect())
+}
+
+#[test]
+fn test_walk() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_walk")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = commit(&odb, "first", &[])?;
+    let second = commit(&odb, "second", &[&first])?;
+    let side = commit(&odb, "side", &[&first])?;
+    let third = commit(&odb, "third", &[&second])?;
+    let merge = commit(&odb, "merge", &[&third, &side])?;
+    refs.update_head(&merge, "commit")?;
+
+    // With equal dates, commits are visited in the order they were found.
+    let all = walk(&odb, &[&merge], &[], WalkOptions::default())?;
+    assert_eq!(all, ["merge", "third", "side", "second", "first"]);
+    let topo = WalkOptions {
+        sort: RevSort::Topo,
+        ..WalkOptions::default()
+    };
+    assert_eq!(
+        walk(&odb, &[&merge], &[], topo)?,
+        ["merge", "side", "third", "second", "first"]
+    );
+    assert_eq!(
+        walk(&odb, &[&merge], &[&second], WalkOptions::default())?,
+        ["merge", "third", "side"]
+    );
+    let limited = WalkOptions {
+        max_count: Some(2),
+        ..WalkOptions::default()
+    };
+    assert_eq!(walk(&odb, &[&merge], &[], limited)?, ["merge", "third"]);
+
+    let mut range = RevWalk::new(&odb, WalkOptions::default());
+    range.push_rev(&refs, "HEAD~1..")?;
+    range.push_rev(&refs, "^HEAD^2")?;
+    let result = range.walk()?;
+    assert_eq!(result.commits().len(), 1);
+    assert_eq!(result.commits()[0].sha(), merge);
+    assert!(range.push_rev(&refs, "nosuch..HEAD").is_err());
+
+    let mut symmetric = RevWalk::new(&odb, WalkOptions::default());
+    symmetric.push_rev(&refs, "HEAD^2...HEAD~1")?;
+    let result = symmetric.walk()?;
+    let shas: Vec<&str> = result.commits().iter().map(|commit| commit.sha()).collect();
+    assert_eq!(shas, [side.as_str(), third.as_str(), second.as_str()]);
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_walk_filters() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_walk_filters")?;
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, "first", &[])?;
+    let second = commit(&odb, "second", &[&first])?;
+    let side = commit(&odb, "side", &[&first])?;
+    let third = commit(&odb, "third", &[&second])?;
+    let merge = commit(&odb, "merge", &[&third, &side])?;
+
+    let options = |f: fn(&mut WalkOptions)| {
+        let mut options = WalkOptions::default();
+        f(&mut options);
+        options
+    };
+    assert_eq!(
+        walk(&odb, &[&merge], &[], options(|o| o.reverse = true))?,
+        ["first", "second", "side", "third", "merge"]
+    );
+    assert_eq!(
+        walk(&odb, &[&merge], &[], options(|o| o.first_parent = true))?,
+        ["merge", "third", "second", "first"]
+    );
+    // The other parents are not followed, but still shown.
+    let mut first_parent = RevWalk::new(&odb, options(|o| o.first_parent = true));
+    first_parent.push(&merge);
+    assert_eq!(
+        first_parent.walk()?.commits()[0].parents(),
+        [third.clone(), side.clone()]
+    );
+    let ancestry = options(|o| o.ancestry_path = true);
+    assert_eq!(
+        walk(&odb, &[&merge], &[&second], ancestry.clone())?,
+        ["merge", "third"]
+    );
+    assert_eq!(walk(&odb, &[&merge], &[&side], ancestry)?, ["merge"]);
+    assert_eq!(
+        walk(
+            &odb,
+            &[&merge],
+            &[],
+            options(|o| o.grep = vec![Regex::new("^s").unwrap()])
+        )?,
+        ["side", "second"]
+    );
+    let nobody = options(|o| o.authors = vec![Regex::new("nobody").unwrap()]);
+    assert!(walk(&odb, &[&merge], &[], nobody)?.is_empty());
+    let since = options(|o| o.since = Some(1_000_000_001));
+    assert!(walk(&odb, &[&merge], &[], since)?.is_empty());
+    let until = options(|o| o.until = Some(1_000_000_000));
+    assert_eq!(walk(&odb, &[&merge], &[], until)?.len(), 5);
+
+    // Objects of the excluded parents are left out, and each object is listed once.
+    let mut walker = RevWalk::new(&odb, WalkOptions::default());
+    walker.push(&merge);
+    walker.hide(&second);
+    let result = walker.walk()?;
+    let objects = walker.objects(&result)?;
+    let paths: Vec<&str> = objects.iter().map(|(_, path)| path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "",
+            "src",
+            "src/file.txt",
+            "",
+            "src",
+            "src/file.txt",
+            "",
+            "src",
+            "src/file.txt"
+        ]
+    );
+    assert_eq!(objects[0].0, odb.read_as::<GitCommit>(&merge)?.tree());
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_walk_pathspec() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_walk_pathspec")?;
+    let odb = GitOdb::open(&repo)?;
+    let first = commit(&odb, "a", &[])?;
+    let same = commit(&odb, "a", &[&first])?;
+    let changed = commit(&odb, "b", &[&same])?;
+    let side = commit(&odb, "c", &[&first])?;
+    // The merge keeps the content of its first parent, so only that side is followed.
+    let merge = commit(&odb, "b", &[&changed, &side])?;
+
+    let options = WalkOptions {
+        pathspec: Pathspec::new(&["src/file.txt"]),
+        ..WalkOptions::default()
+    };
+    assert_eq!(walk(&odb, &[&merge], &[], options.clone())?, ["b", "a"]);
+    let other = WalkOptions {
+        pathspec: Pathspec::new(&["other"]),
+        ..WalkOptions::default()
+    };
+    assert!(walk(&odb, &[&merge], &[], other)?.is_empty());
+
+    // Parents are rewritten to the nearest commits that are shown.
+    let rewritten = WalkOptions {
+        rewrite_parents: true,
+        ..options
+    };
+    let mut walker = RevWalk::new(&odb, rewritten);
+    walker.push(&merge);
+    let result = walker.walk()?;
+    assert_eq!(result.commits()[0].sha(), changed);
+    assert_eq!(result.commits()[0].parents(), std::slice::from_ref(&first));
+    assert!(result.is_shown(&first));
+    assert!(!result.is_shown(&same));
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
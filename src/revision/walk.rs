@@ -0,0 +1,788 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+use regex::Regex;
+
+use super::{merge_bases, peel, resolve_commit, rev_parse};
+use crate::pathspec::has_glob;
+use crate::refs::GitRefs;
+use crate::{GitCommit, GitCommitGraph, GitOdb, GitResult, GitTag, GitTree, ObjectType, Pathspec};
+
+/// The order a [RevWalk] returns commits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RevSort {
+    /// Newest commit date first, as the history is walked.
+    #[default]
+    Default,
+    /// No parent before all of its children, otherwise by commit date.
+    Date,
+    /// No parent before all of its children, and lines of history kept together.
+    Topo,
+}
+
+/// What a [RevWalk] returns, like the options of `git rev-list`.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub sort: RevSort,
+    /// Only show commits changing these paths, simplifying the history like git does by
+    /// default.
+    pub pathspec: Pathspec,
+    /// Stop after this many commits.
+    pub max_count: Option<usize>,
+    /// Replace the parents of each commit with its nearest ancestors that are shown, like
+    /// `--parents` and `--graph` do.
+    pub rewrite_parents: bool,
+    /// Return the commits in reverse order, after `max_count` is applied.
+    pub reverse: bool,
+    /// Only follow the first parent of merges.
+    pub first_parent: bool,
+    /// Only show commits that are descendants of an excluded commit.
+    pub ancestry_path: bool,
+    /// Only show commits made at or after this time; the walk does not go past older ones.
+    pub since: Option<i64>,
+    /// Only show commits made at or before this time.
+    pub until: Option<i64>,
+    /// Only show commits whose author (`Name <email>`) matches one of these patterns.
+    pub authors: Vec<Regex>,
+    /// Only show commits whose message matches one of these patterns.
+    pub grep: Vec<Regex>,
+}
+
+/// A commit returned by a [RevWalk].
+#[derive(Debug, Clone)]
+pub struct WalkedCommit {
+    sha: String,
+    commit: GitCommit,
+    parents: Vec<String>,
+}
+
+impl WalkedCommit {
+    /// The Sha1 hash of the commit.
+    pub fn sha(&self) -> &str {
+        &self.sha
+    }
+
+    /// The parsed commit.
+    pub fn commit(&self) -> &GitCommit {
+        &self.commit
+    }
+
+    /// The parents after history simplification, or the rewritten parents.
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+}
+
+/// The commits a walk returns, and which commits would be shown without a limit.
+#[derive(Debug, Clone, Default)]
+pub struct WalkResult {
+    commits: Vec<WalkedCommit>,
+    shown: HashSet<String>,
+}
+
+impl WalkResult {
+    /// The commits in the requested order.
+    pub fn commits(&self) -> &[WalkedCommit] {
+        &self.commits
+    }
+
+    /// Returns true if the commit `sha` is part of the history, even if the walk stopped
+    /// before it.
+    pub fn is_shown(&self, sha: &str) -> bool {
+        self.shown.contains(sha)
+    }
+}
+
+/// Walks the history from a set of commits, excluding the history of others, like
+/// `git rev-list`.
+#[derive(Debug)]
+pub struct RevWalk<'a> {
+    odb: &'a GitOdb,
+    options: WalkOptions,
+    tips: Vec<String>,
+    hidden: Vec<String>,
+    tags: Vec<String>,
+    graph: Option<GitCommitGraph>,
+    /// The keys to look up in changed-path Bloom filters for each pathspec item, if every item
+    /// is a literal path.
+    bloom_keys: Option<Vec<Vec<String>>>,
+    commits: RefCell<HashMap<String, GitCommit>>,
+    infos: RefCell<HashMap<String, CommitInfo>>,
+    trees: RefCell<HashMap<String, BTreeMap<String, String>>>,
+}
+
+/// The parts of a commit needed to walk past it, read from the commit-graph if possible.
+#[derive(Debug, Clone)]
+struct CommitInfo {
+    tree: String,
+    parents: Vec<String>,
+    time: i64,
+}
+
+/// A commit in the walk's priority queue, ordered by commit date and then by insertion.
+#[derive(PartialEq, Eq)]
+struct Queued<T> {
+    time: i64,
+    counter: usize,
+    item: T,
+}
+
+impl<T: Eq> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.time
+            .cmp(&other.time)
+            .then_with(|| other.counter.cmp(&self.counter))
+    }
+}
+
+impl<T: Eq> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A commit visited by the walk.
+struct Node {
+    sha: String,
+    parents: Vec<String>,
+    /// The parents the commit keeps in git, shown when parents are not rewritten. With
+    /// `first_parent`, only TREESAME commits lose the others.
+    commit_parents: Vec<String>,
+    treesame: bool,
+    shown: bool,
+    /// False for commits left out of the sorted history, like git does for commits newer than
+    /// `until` when it walks the whole history first.
+    listed: bool,
+}
+
+impl<'a> RevWalk<'a> {
+    /// Creates a walk over the commits in `odb`, using its commit-graph if it has a valid one.
+    pub fn new(odb: &'a GitOdb, options: WalkOptions) -> Self {
+        let graph = GitCommitGraph::open(odb.objects_dir()).unwrap_or(None);
+        let items = options.pathspec.items();
+        let bloom_keys = if items.is_empty()
+            || items.iter().any(|item| item.is_empty() || has_glob(item))
+        {
+            None
+        } else {
+            Some(
+                items
+                    .iter()
+                    .map(|item| {
+                        let mut keys = vec![item.clone()];
+                        keys.extend(item.match_indices('/').map(|(i, _)| item[..i].to_string()));
+                        keys
+                    })
+                    .collect(),
+            )
+        };
+        Self {
+            odb,
+            options,
+            tips: Vec::new(),
+            hidden: Vec::new(),
+            tags: Vec::new(),
+            graph,
+            bloom_keys,
+            commits: RefCell::new(HashMap::new()),
+            infos: RefCell::new(HashMap::new()),
+            trees: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Adds the commit `sha` and its history.
+    pub fn push(&mut self, sha: &str) {
+        self.tips.push(sha.to_string());
+    }
+
+    /// Adds the annotated tag `sha`, which [RevWalk::objects] lists, and the history of the
+    /// commit it points to.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: The tag does not point to a commit
+    pub fn push_tag(&mut self, sha: &str) -> GitResult<()> {
+        let commit = peel(self.odb, sha, ObjectType::Commit)?;
+        self.tags.push(sha.to_string());
+        self.push(&commit);
+        Ok(())
+    }
+
+    /// Excludes the commit `sha` and its history.
+    pub fn hide(&mut self, sha: &str) {
+        self.hidden.push(sha.to_string());
+    }
+
+    /// Returns true if no commits were added.
+    pub fn is_empty(&self) -> bool {
+        self.tips.is_empty()
+    }
+
+    /// Adds a revision argument: `<rev>`, `^<rev>` to exclude, the range `<a>..<b>`, or the
+    /// symmetric difference `<a>...<b>`.
+    ///
+    /// # Errors
+    ///
+    /// See [resolve_commit].
+    pub fn push_rev(&mut self, refs: &GitRefs, arg: &str) -> GitResult<()> {
+        fn side(rev: &str) -> &str {
+            if rev.is_empty() {
+                "HEAD"
+            } else {
+                rev
+            }
+        }
+        if let Some(rev) = arg.strip_prefix('^') {
+            let sha = resolve_commit(self.odb, refs, rev)?;
+            self.hide(&sha);
+        } else if let Some(dots) = arg.find("...") {
+            // The commits reachable from either side but not from both.
+            let (left, right) = (side(&arg[..dots]), side(&arg[dots + 3..]));
+            let one = resolve_commit(self.odb, refs, left)?;
+            let two = resolve_commit(self.odb, refs, right)?;
+            for base in merge_bases(self.odb, &one, &[two])? {
+                self.hide(&base);
+            }
+            self.push_object(refs, left)?;
+            self.push_object(refs, right)?;
+        } else if let Some(dots) = arg.find("..") {
+            let from = resolve_commit(self.odb, refs, side(&arg[..dots]))?;
+            self.hide(&from);
+            self.push_object(refs, side(&arg[dots + 2..]))?;
+        } else {
+            self.push_object(refs, arg)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the commit `rev` names, remembering the annotated tag it was named by.
+    fn push_object(&mut self, refs: &GitRefs, rev: &str) -> GitResult<()> {
+        let sha = resolve_commit(self.odb, refs, rev)?;
+        let object = rev_parse(self.odb, refs, rev)?;
+        if object != sha && self.odb.read_type(&object)? == ObjectType::Tag {
+            self.tags.push(object);
+        }
+        self.push(&sha);
+        Ok(())
+    }
+
+    /// Walks the history and returns the commits to show.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: A commit or tree is missing or corrupt
+    pub fn walk(&self) -> GitResult<WalkResult> {
+        let uninteresting = self.reachable(&self.hidden)?;
+
+        // Visit commits by date like git does, simplifying merges on the way.
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut queued: HashSet<String> = HashSet::new();
+        let mut queue = BinaryHeap::new();
+        let mut counter = 0;
+        let mut expired = Vec::new();
+        // With generation numbers, git sorts the history as it walks it instead of walking all
+        // of it first.
+        let limited = (self.options.sort != RevSort::Default && self.graph.is_none())
+            || !self.hidden.is_empty()
+            || self.options.ancestry_path;
+        for tip in &self.tips {
+            if !uninteresting.contains(tip) && queued.insert(tip.clone()) {
+                let time = self.info(tip)?.time;
+                queue.push(Queued {
+                    time,
+                    counter,
+                    item: tip.clone(),
+                });
+                counter += 1;
+            }
+        }
+        while let Some(Queued { item: sha, .. }) = queue.pop() {
+            let time = self.info(&sha)?.time;
+            if let Some(since) = self.options.since {
+                if time < since {
+                    expired.push(sha);
+                    continue;
+                }
+            }
+            let (parents, treesame) = self.simplify(&sha, &uninteresting)?;
+            for parent in &parents {
+                if !uninteresting.contains(parent) && queued.insert(parent.clone()) {
+                    let time = self.info(parent)?.time;
+                    queue.push(Queued {
+                        time,
+                        counter,
+                        item: parent.clone(),
+                    });
+                    counter += 1;
+                }
+            }
+            index.insert(sha.clone(), nodes.len());
+            let shown = !treesame && self.matches(&sha, time)?;
+            let commit_parents = if self.options.first_parent && !treesame {
+                self.info(&sha)?.parents
+            } else {
+                parents.clone()
+            };
+            nodes.push(Node {
+                sha,
+                parents,
+                commit_parents,
+                treesame,
+                shown,
+                listed: !limited || self.options.until.is_none_or(|until| time <= until),
+            });
+        }
+        if self.options.ancestry_path && !self.hidden.is_empty() {
+            self.limit_to_ancestry(&mut nodes)?;
+        }
+        // When the whole history is walked before showing it, git excludes the history of
+        // commits older than `since` as well, even when it is reached through newer commits.
+        // Sorting the history as it is walked has the same effect, as these commits keep their
+        // parents from ever becoming ready.
+        if (limited || self.options.sort != RevSort::Default) && !expired.is_empty() {
+            let expired = self.reachable(&expired)?;
+            for node in nodes.iter_mut() {
+                if expired.contains(&node.sha) {
+                    node.shown = false;
+                }
+            }
+        }
+
+        let order = match self.options.sort {
+            RevSort::Default => (0..nodes.len()).collect(),
+            sort => topo_sort(&nodes, &index, sort == RevSort::Date, limited, |sha| {
+                self.info(sha).map(|info| info.time)
+            })?,
+        };
+
+        let mut shown: HashSet<String> = nodes
+            .iter()
+            .filter(|node| node.shown)
+            .map(|node| node.sha.clone())
+            .collect();
+        // The other parents of merges are never walked with `first_parent`, but git still
+        // counts them as part of the history when they pass the filters.
+        if self.options.first_parent {
+            for node in nodes.iter().filter(|node| node.shown) {
+                for parent in node.commit_parents.iter().skip(1) {
+                    if index.contains_key(parent) || uninteresting.contains(parent) {
+                        continue;
+                    }
+                    let time = self.info(parent)?.time;
+                    if self.options.since.is_none_or(|since| time >= since)
+                        && self.matches(parent, time)?
+                    {
+                        shown.insert(parent.clone());
+                    }
+                }
+            }
+        }
+        let mut commits = Vec::new();
+        for i in order {
+            let node = &nodes[i];
+            if !node.shown {
+                continue;
+            }
+            if self
+                .options
+                .max_count
+                .is_some_and(|max| commits.len() >= max)
+            {
+                break;
+            }
+            let parents = if self.options.rewrite_parents {
+                rewrite_parents(node, &nodes, &index)
+            } else {
+                node.commit_parents.clone()
+            };
+            commits.push(WalkedCommit {
+                sha: node.sha.clone(),
+                commit: self.commit(&node.sha)?,
+                parents,
+            });
+        }
+        if self.options.reverse {
+            commits.reverse();
+        }
+        Ok(WalkResult { commits, shown })
+    }
+
+    /// Returns the annotated tags that were added, then the trees and blobs of the walked
+    /// commits with their paths, like `git rev-list --objects`. Objects of the excluded
+    /// parents of walked commits are left out.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::ObjectError]: A commit or tree is missing or corrupt
+    pub fn objects(&self, result: &WalkResult) -> GitResult<Vec<(String, String)>> {
+        let uninteresting = self.reachable(&self.hidden)?;
+        let mut seen = HashSet::new();
+        for walked in result.commits() {
+            for parent in walked.commit().parents() {
+                if uninteresting.contains(parent) {
+                    let tree = self.info(parent)?.tree;
+                    self.mark_tree(&tree, &mut seen)?;
+                }
+            }
+        }
+        let mut objects = Vec::new();
+        for tag in &self.tags {
+            if seen.insert(tag.clone()) {
+                let name = self.odb.read_as::<GitTag>(tag)?.name().to_string();
+                objects.push((tag.clone(), name));
+            }
+        }
+        for walked in result.commits() {
+            self.list_tree(walked.commit().tree(), "", &mut seen, &mut objects)?;
+        }
+        Ok(objects)
+    }
+
+    /// Adds the tree `sha` and everything in it to `seen`.
+    fn mark_tree(&self, sha: &str, seen: &mut HashSet<String>) -> GitResult<()> {
+        if !seen.insert(sha.to_string()) {
+            return Ok(());
+        }
+        for entry in self.odb.read_as::<GitTree>(sha)?.entries() {
+            if entry.mode().is_tree() {
+                self.mark_tree(entry.sha(), seen)?;
+            } else if !entry.mode().is_gitlink() {
+                seen.insert(entry.sha().to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the tree `sha` at `path` and then its contents to `objects`, depth first,
+    /// skipping objects in `seen`.
+    fn list_tree(
+        &self,
+        sha: &str,
+        path: &str,
+        seen: &mut HashSet<String>,
+        objects: &mut Vec<(String, String)>,
+    ) -> GitResult<()> {
+        if !seen.insert(sha.to_string()) {
+            return Ok(());
+        }
+        objects.push((sha.to_string(), path.to_string()));
+        for entry in self.odb.read_as::<GitTree>(sha)?.entries() {
+            let entry_path = if path.is_empty() {
+                entry.name().to_string()
+            } else {
+                format!("{}/{}", path, entry.name())
+            };
+            if entry.mode().is_tree() {
+                self.list_tree(entry.sha(), &entry_path, seen, objects)?;
+            } else if !entry.mode().is_gitlink() && seen.insert(entry.sha().to_string()) {
+                objects.push((entry.sha().to_string(), entry_path));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if the commit `sha` made at `time` passes the date, author and message
+    /// filters.
+    fn matches(&self, sha: &str, time: i64) -> GitResult<bool> {
+        let options = &self.options;
+        if options.until.is_some_and(|until| time > until) {
+            return Ok(false);
+        }
+        if options.authors.is_empty() && options.grep.is_empty() {
+            return Ok(true);
+        }
+        let commit = self.commit(sha)?;
+        let author = format!("{} <{}>", commit.author().name(), commit.author().email());
+        Ok(
+            (options.authors.is_empty() || options.authors.iter().any(|re| re.is_match(&author)))
+                && (options.grep.is_empty()
+                    || options.grep.iter().any(|re| re.is_match(commit.message()))),
+        )
+    }
+
+    /// Hides the walked commits that are not descendants of an excluded commit, like
+    /// `--ancestry-path`.
+    fn limit_to_ancestry(&self, nodes: &mut [Node]) -> GitResult<()> {
+        let mut on_path: HashSet<String> = self.hidden.iter().cloned().collect();
+        loop {
+            let mut progress = false;
+            // Parents come after their children, so walking backwards converges quickly.
+            for node in nodes.iter().rev() {
+                if on_path.contains(&node.sha) {
+                    continue;
+                }
+                if self
+                    .info(&node.sha)?
+                    .parents
+                    .iter()
+                    .any(|parent| on_path.contains(parent))
+                {
+                    on_path.insert(node.sha.clone());
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for node in nodes.iter_mut() {
+            if !on_path.contains(&node.sha) {
+                node.shown = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the parents to follow from `sha`, and whether the commit is TREESAME, i.e.
+    /// changes none of the paths in the pathspec.
+    ///
+    /// A merge that is TREESAME to one of its parents only follows that parent, unless the
+    /// full history is kept for `ancestry_path`.
+    fn simplify(
+        &self,
+        sha: &str,
+        uninteresting: &HashSet<String>,
+    ) -> GitResult<(Vec<String>, bool)> {
+        let info = self.info(sha)?;
+        let parents: Vec<String> = if self.options.first_parent {
+            info.parents.iter().take(1).cloned().collect()
+        } else {
+            info.parents
+        };
+        if self.options.pathspec.is_empty() {
+            return Ok((parents, false));
+        }
+
+        if parents.is_empty() {
+            return Ok((parents, self.paths(&info.tree)?.is_empty()));
+        }
+        // Changes from excluded parents only count if no parent is walked.
+        let mut relevant_change = false;
+        let mut irrelevant_change = false;
+        let mut relevant_parents = 0;
+        let mut tree = None;
+        for (i, parent) in parents.iter().enumerate() {
+            let relevant = !uninteresting.contains(parent);
+            if relevant {
+                relevant_parents += 1;
+            }
+            // The Bloom filter compares the commit to its first parent, and can only tell
+            // that the paths are certainly unchanged.
+            let unchanged = if i == 0 && !self.maybe_changed(sha) {
+                true
+            } else {
+                if tree.is_none() {
+                    tree = Some(self.paths(&info.tree)?);
+                }
+                self.paths(&self.info(parent)?.tree)? == *tree.as_ref().unwrap()
+            };
+            if !unchanged {
+                if relevant {
+                    relevant_change = true;
+                } else {
+                    irrelevant_change = true;
+                }
+            } else if relevant && !self.options.ancestry_path {
+                return Ok((vec![parent.clone()], true));
+            }
+        }
+        let changed = relevant_change || (irrelevant_change && relevant_parents == 0);
+        Ok((parents, !changed))
+    }
+
+    /// Returns the files of the tree `sha` selected by the pathspec, with their modes and
+    /// objects.
+    fn paths(&self, sha: &str) -> GitResult<BTreeMap<String, String>> {
+        if let Some(paths) = self.trees.borrow().get(sha) {
+            return Ok(paths.clone());
+        }
+        let paths: BTreeMap<String, String> = GitTree::flatten(self.odb, sha)?
+            .into_iter()
+            .filter(|(path, _)| self.options.pathspec.matches(path))
+            .map(|(path, entry)| (path, format!("{} {}", entry.mode(), entry.sha())))
+            .collect();
+        self.trees
+            .borrow_mut()
+            .insert(sha.to_string(), paths.clone());
+        Ok(paths)
+    }
+
+    /// Returns false if the changed-path Bloom filter of the commit `sha` rules out that it
+    /// changes the pathspec compared to its first parent.
+    fn maybe_changed(&self, sha: &str) -> bool {
+        let (graph, keys) = match (&self.graph, &self.bloom_keys) {
+            (Some(graph), Some(keys)) => (graph, keys),
+            _ => return true,
+        };
+        match graph.changed_paths(sha) {
+            Some(filter) => keys
+                .iter()
+                .any(|keys| keys.iter().all(|key| filter.contains(key))),
+            None => true,
+        }
+    }
+
+    fn info(&self, sha: &str) -> GitResult<CommitInfo> {
+        if let Some(info) = self.infos.borrow().get(sha) {
+            return Ok(info.clone());
+        }
+        let info = match self.graph.as_ref().and_then(|graph| graph.lookup(sha)) {
+            Some(commit) => CommitInfo {
+                tree: commit.tree().to_string(),
+                parents: commit.parents().to_vec(),
+                time: commit.time(),
+            },
+            None => {
+                let commit = self.commit(sha)?;
+                CommitInfo {
+                    tree: commit.tree().to_string(),
+                    parents: commit.parents().to_vec(),
+                    time: commit.committer().time(),
+                }
+            }
+        };
+        self.infos
+            .borrow_mut()
+            .insert(sha.to_string(), info.clone());
+        Ok(info)
+    }
+
+    fn commit(&self, sha: &str) -> GitResult<GitCommit> {
+        if let Some(commit) = self.commits.borrow().get(sha) {
+            return Ok(commit.clone());
+        }
+        let commit = self.odb.read_as::<GitCommit>(sha)?;
+        self.commits
+            .borrow_mut()
+            .insert(sha.to_string(), commit.clone());
+        Ok(commit)
+    }
+
+    fn reachable(&self, tips: &[String]) -> GitResult<HashSet<String>> {
+        let mut seen = HashSet::new();
+        let mut stack = tips.to_vec();
+        while let Some(sha) = stack.pop() {
+            if seen.insert(sha.clone()) {
+                stack.extend(self.info(&sha)?.parents);
+            }
+        }
+        Ok(seen)
+    }
+}
+
+/// Sorts the walked commits so that no parent comes before its children, like git's
+/// `sort_in_topological_order`. With `by_date`, ready commits are taken newest first;
+/// otherwise the most recently readied one is, which keeps lines of history together.
+///
+/// Like git, a `limited` walk waits for all the parents a commit keeps, while the walk
+/// sorting the history as it goes only waits for the followed ones.
+fn topo_sort<F>(
+    nodes: &[Node],
+    index: &HashMap<String, usize>,
+    by_date: bool,
+    limited: bool,
+    time: F,
+) -> GitResult<Vec<usize>>
+where
+    F: Fn(&str) -> GitResult<i64>,
+{
+    // Listed commits start with an in-degree of 1, so that 0 can mean emitted or unlisted.
+    let mut indegree: Vec<usize> = nodes.iter().map(|node| node.listed as usize).collect();
+    let listed = |sha: &String| index.get(sha).copied().filter(|&i| nodes[i].listed);
+    let parents = |i: usize| {
+        if limited {
+            &nodes[i].commit_parents
+        } else {
+            &nodes[i].parents
+        }
+    };
+    for i in (0..nodes.len()).filter(|&i| nodes[i].listed) {
+        for parent in parents(i) {
+            if let Some(i) = listed(parent) {
+                indegree[i] += 1;
+            }
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..nodes.len()).filter(|&i| indegree[i] == 1).collect();
+    let mut heap = BinaryHeap::new();
+    let mut counter = 0;
+    if by_date {
+        for &i in &ready {
+            heap.push(Queued {
+                time: time(&nodes[i].sha)?,
+                counter,
+                item: i,
+            });
+            counter += 1;
+        }
+        ready.clear();
+    } else {
+        // The tips are shown in the order they were walked.
+        ready.reverse();
+    }
+
+    let mut order = Vec::new();
+    loop {
+        let i = if by_date {
+            match heap.pop() {
+                Some(queued) => queued.item,
+                None => break,
+            }
+        } else {
+            match ready.pop() {
+                Some(i) => i,
+                None => break,
+            }
+        };
+        for parent in parents(i) {
+            let p = match listed(parent) {
+                Some(p) => p,
+                None => continue,
+            };
+            if indegree[p] == 0 {
+                continue;
+            }
+            indegree[p] -= 1;
+            if indegree[p] == 1 {
+                if by_date {
+                    heap.push(Queued {
+                        time: time(parent)?,
+                        counter,
+                        item: p,
+                    });
+                    counter += 1;
+                } else {
+                    ready.push(p);
+                }
+            }
+        }
+        indegree[i] = 0;
+        order.push(i);
+    }
+    Ok(order)
+}
+
+/// Replaces each parent of `node` that is TREESAME with its nearest ancestor along the
+/// simplified history that is not, dropping parents whose history has none. Parents outside
+/// the walk are kept, like git does.
+fn rewrite_parents(node: &Node, nodes: &[Node], index: &HashMap<String, usize>) -> Vec<String> {
+    let mut parents: Vec<String> = Vec::new();
+    for parent in &node.commit_parents {
+        let mut current = Some(parent);
+        while let Some(&i) = current.and_then(|sha| index.get(sha)) {
+            if !nodes[i].treesame {
+                break;
+            }
+            current = nodes[i].parents.first();
+        }
+        if let Some(sha) = current {
+            if !parents.contains(sha) {
+                parents.push(sha.clone());
+            }
+        }
+    }
+    parents
+}
@@ -0,0 +1,579 @@
+use crate::config;
+use crate::refs::{self, GitRefs, RefTarget};
+use crate::revision::{self, DEFAULT_ABBREV};
+use crate::utils;
+use crate::{GitCommit, GitError, GitOdb, GitRepo, GitResult};
+
+/// Which branches to list and how to show them.
+#[derive(Debug, Default)]
+pub struct ListOptions {
+    pub patterns: Vec<String>,
+    pub local: bool,
+    pub remotes: bool,
+    /// `-v` shows the tip of each branch, `-vv` also its upstream.
+    pub verbose: u64,
+    pub contains: Option<String>,
+    pub merged: Option<String>,
+    pub no_merged: Option<String>,
+}
+
+/// Prints the branches selected by `options`, marking the current one with `*`.
+///
+/// # Errors
+///
+/// * [GitError::BranchError]: A commit given to filter by is invalid
+/// * Errors obtained when reading refs or objects
+pub fn list(repo: &GitRepo, options: &ListOptions) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let head = refs.head_branch()?;
+    let filter_commit = |rev: &Option<String>| -> GitResult<Option<String>> {
+        match rev {
+            Some(rev) => Ok(Some(resolve_commit(&odb, &refs, rev)?)),
+            None => Ok(None),
+        }
+    };
+    let contains = filter_commit(&options.contains)?;
+    let merged = filter_commit(&options.merged)?;
+    let no_merged = filter_commit(&options.no_merged)?;
+
+    // (display name, full name, tip, symbolic target)
+    let mut branches: Vec<(String, Option<String>, String, Option<String>)> = Vec::new();
+    if head.is_none() && options.local {
+        if let Some(sha) = refs.resolve("HEAD")? {
+            branches.push((detached_description(&odb, &refs, &sha)?, None, sha, None));
+        }
+    }
+    let mut prefixes = Vec::new();
+    if options.local {
+        prefixes.push("refs/heads/");
+    }
+    if options.remotes {
+        prefixes.push("refs/remotes/");
+    }
+    for prefix in prefixes {
+        for (name, sha) in refs.list(prefix)? {
+            let short = if options.local && prefix == "refs/remotes/" {
+                // Remote-tracking branches are told apart from local ones in `--all`.
+                format!("remotes/{}", refs::shorten(&name))
+            } else {
+                refs::shorten(&name).to_string()
+            };
+            if !options.patterns.is_empty()
+                && !options
+                    .patterns
+                    .iter()
+                    .any(|pattern| utils::wildmatch(pattern, &short, 0))
+            {
+                continue;
+            }
+            let target = match refs.read(&name)? {
+                Some(RefTarget::Symbolic(target)) => Some(refs::shorten(&target).to_string()),
+                _ => None,
+            };
+            branches.push((short, Some(name), sha, target));
+        }
+    }
+
+    let mut selected = Vec::new();
+    for branch in branches {
+        let tip = &branch.2;
+        if let Some(commit) = &contains {
+            if !revision::is_ancestor(&odb, commit, tip)? {
+                continue;
+            }
+        }
+        if let Some(commit) = &merged {
+            if !revision::is_ancestor(&odb, tip, commit)? {
+                continue;
+            }
+        }
+        if let Some(commit) = &no_merged {
+            if revision::is_ancestor(&odb, tip, commit)? {
+                continue;
+            }
+        }
+        selected.push(branch);
+    }
+
+    let width = selected
+        .iter()
+        .filter(|(_, _, _, target)| target.is_none())
+        .map(|(short, _, _, _)| short.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (short, full, sha, target) in &selected {
+        let current = full.is_none() || *full == head;
+        let marker = if current { "* " } else { "  " };
+        if let Some(target) = target {
+            println!("{}{} -> {}", marker, short, target);
+            continue;
+        }
+        if options.verbose == 0 {
+            println!("{}{}", marker, short);
+            continue;
+        }
+        let tracking = match full
+            .as_deref()
+            .and_then(|full| full.strip_prefix("refs/heads/"))
+        {
+            Some(branch) => tracking_info(repo, &odb, &refs, branch, sha, options.verbose)?,
+            None => String::new(),
+        };
+        let commit = odb.read_as::<GitCommit>(sha)?;
+        println!(
+            "{}{:<width$} {} {}{}",
+            marker,
+            short,
+            revision::abbreviate(&odb, sha, DEFAULT_ABBREV)?,
+            tracking,
+            commit.summary(),
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// Creates the branch `name` at `start` (or `HEAD`), resetting it if it exists and `force` is
+/// set. A remote-tracking start point becomes the upstream, following `branch.autoSetupMerge`.
+///
+/// # Errors
+///
+/// * [GitError::BranchError]: The name is invalid, the branch exists, or `start` is not a
+///   commit
+/// * Errors obtained when writing the ref or config
+pub fn create(
+    repo: &GitRepo,
+    name: &str,
+    start: Option<&str>,
+    force: bool,
+    quiet: bool,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let full = branch_ref(name)?;
+    let exists = refs.exists(&full)?;
+    if exists && !force {
+        return Err(error(
+            name,
+            &format!("a branch named '{}' already exists", name),
+        ));
+    }
+    if exists && refs.head_branch()?.as_deref() == Some(full.as_str()) {
+        return Err(error(name, "cannot force update the current branch."));
+    }
+
+    let start = start.unwrap_or("HEAD");
+    let commit = resolve_commit(&odb, &refs, start)?;
+    let message = if exists {
+        format!("branch: Reset to {}", start)
+    } else {
+        format!("branch: Created from {}", start)
+    };
+    refs.update(&full, &commit, &message)?;
+
+    let auto_setup = config::get(repo, "branch.autoSetupMerge")?;
+    // Besides true and false, the setting can be "always", "inherit" or "simple".
+    if auto_setup.is_none_or(|value| !value.eq_ignore_ascii_case("false")) {
+        if let Some(upstream) = refs.dwim(start)? {
+            if upstream.starts_with("refs/remotes/") {
+                set_upstream(repo, name, &upstream, quiet)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the branches in `names`, refusing branches that are not merged into their upstream
+/// (or `HEAD`) unless `force` is set. With `remotes` the names are remote-tracking branches.
+///
+/// Errors for single branches are printed and the others are still deleted. Returns false if
+/// any branch could not be deleted.
+///
+/// # Errors
+///
+/// * Errors obtained when reading refs, objects or writing the config
+pub fn delete(
+    repo: &GitRepo,
+    names: &[String],
+    force: bool,
+    remotes: bool,
+    quiet: bool,
+) -> GitResult<bool> {
+    if names.is_empty() {
+        return Err(error("", "branch name required"));
+    }
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let head = refs.head_branch()?;
+    let mut success = true;
+
+    for name in names {
+        let (full, kind) = if remotes {
+            (format!("refs/remotes/{}", name), "remote-tracking branch")
+        } else {
+            (format!("refs/heads/{}", name), "branch")
+        };
+        let sha = match refs.resolve(&full)? {
+            Some(sha) => sha,
+            None => {
+                eprintln!("error: {} '{}' not found", kind, name);
+                success = false;
+                continue;
+            }
+        };
+        if !remotes && head.as_deref() == Some(full.as_str()) {
+            match repo.worktree() {
+                Some(worktree) => eprintln!(
+                    "error: cannot delete branch '{}' used by worktree at '{}'",
+                    name,
+                    std::path::absolute(worktree)
+                        .as_deref()
+                        .unwrap_or(worktree)
+                        .display()
+                ),
+                None => eprintln!("error: cannot delete branch '{}' checked out", name),
+            }
+            success = false;
+            continue;
+        }
+        if !force && !remotes && !is_merged(repo, &odb, &refs, name, &sha)? {
+            eprintln!(
+                "error: the branch '{}' is not fully merged.\n\
+                 If you are sure you want to delete it, run 'git branch -D {}'.",
+                name, name
+            );
+            success = false;
+            continue;
+        }
+
+        refs.delete(&full)?;
+        if !remotes {
+            config::rename_section(
+                repo.gitpath().join("config"),
+                &format!("branch.{}", name),
+                None,
+            )?;
+        }
+        if !quiet {
+            println!(
+                "Deleted {} {} (was {}).",
+                kind,
+                name,
+                revision::abbreviate(&odb, &sha, DEFAULT_ABBREV)?
+            );
+        }
+    }
+    Ok(success)
+}
+
+/// Renames the branch `old` (or the current branch) to `new`, moving its reflog and config.
+///
+/// # Errors
+///
+/// * [GitError::BranchError]: A name is invalid, `old` does not exist, or `new` exists and
+///   `force` is not set
+/// * Errors obtained when writing refs or the config
+pub fn rename(repo: &GitRepo, old: Option<&str>, new: &str, force: bool) -> GitResult<()> {
+    let refs = GitRefs::new(repo);
+    let head = refs.head_branch()?;
+    let old = match old {
+        Some(old) => old.to_string(),
+        None => match &head {
+            Some(head) => refs::shorten(head).to_string(),
+            None => {
+                return Err(error(
+                    new,
+                    "cannot rename the current branch while not on any.",
+                ))
+            }
+        },
+    };
+    let old_full = format!("refs/heads/{}", old);
+    let new_full = branch_ref(new)?;
+    let is_head = head.as_deref() == Some(old_full.as_str());
+    let old_exists = refs.exists(&old_full)?;
+    if !old_exists && !is_head {
+        return Err(error(&old, &format!("no branch named '{}'", old)));
+    }
+    if old_full != new_full && refs.exists(&new_full)? {
+        if !force {
+            return Err(error(
+                new,
+                &format!("a branch named '{}' already exists", new),
+            ));
+        }
+        if head.as_deref() == Some(new_full.as_str()) {
+            return Err(error(new, "cannot force update the current branch."));
+        }
+        refs.delete(&new_full)?;
+    }
+    if old_full == new_full {
+        return Ok(());
+    }
+
+    let message = format!("Branch: renamed {} to {}", old_full, new_full);
+    if old_exists {
+        refs.rename(&old_full, &new_full, &message)?;
+    } else {
+        // Renaming an unborn branch only moves HEAD.
+        refs.set_symbolic("HEAD", &new_full, &message)?;
+    }
+    config::rename_section(
+        repo.gitpath().join("config"),
+        &format!("branch.{}", old),
+        Some(&format!("branch.{}", new)),
+    )
+}
+
+/// Makes `upstream` the upstream of `branch` (or the current branch).
+///
+/// # Errors
+///
+/// * [GitError::BranchError]: The branch or upstream does not exist
+/// * Errors obtained when writing the config
+pub fn set_upstream_to(
+    repo: &GitRepo,
+    upstream: &str,
+    branch: Option<&str>,
+    quiet: bool,
+) -> GitResult<()> {
+    let refs = GitRefs::new(repo);
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => match refs.head_branch()? {
+            Some(head) => refs::shorten(&head).to_string(),
+            None => {
+                return Err(error(
+                    upstream,
+                    &format!(
+                    "could not set upstream of HEAD to {} when it does not point to any branch.",
+                    upstream
+                ),
+                ))
+            }
+        },
+    };
+    if !refs.exists(&format!("refs/heads/{}", branch))? {
+        return Err(error(
+            &branch,
+            &format!("branch '{}' does not exist", branch),
+        ));
+    }
+    let full = match refs.dwim(upstream)? {
+        Some(full) if full.starts_with("refs/heads/") || full.starts_with("refs/remotes/") => full,
+        _ => {
+            return Err(error(
+                upstream,
+                &format!(
+                    "the requested upstream branch '{}' does not exist",
+                    upstream
+                ),
+            ))
+        }
+    };
+    set_upstream(repo, &branch, &full, quiet)
+}
+
+/// Removes the upstream configuration of `branch` (or the current branch).
+///
+/// # Errors
+///
+/// * [GitError::BranchError]: There is no such branch, or it has no upstream
+/// * Errors obtained when writing the config
+pub fn unset_upstream(repo: &GitRepo, branch: Option<&str>) -> GitResult<()> {
+    let refs = GitRefs::new(repo);
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => match refs.head_branch()? {
+            Some(head) => refs::shorten(&head).to_string(),
+            None => return Err(error("HEAD", "HEAD does not point to a branch")),
+        },
+    };
+    let key = format!("branch.{}.merge", branch);
+    if config::read_value(repo.gitpath().join("config"), &key)?.is_none() {
+        return Err(error(
+            &branch,
+            &format!("branch '{}' has no upstream information", branch),
+        ));
+    }
+    let path = repo.gitpath().join("config");
+    config::set_value(&path, &format!("branch.{}.remote", branch), None)?;
+    config::set_value(&path, &key, None)
+}
+
+/// Prints the name of the current branch, or nothing if `HEAD` is detached.
+///
+/// # Errors
+///
+/// See [GitRefs::head_branch].
+pub fn show_current(repo: &GitRepo) -> GitResult<()> {
+    if let Some(head) = GitRefs::new(repo).head_branch()? {
+        println!("{}", refs::shorten(&head));
+    }
+    Ok(())
+}
+
+/// Returns the full name of the upstream of `branch`, as configured by `branch.<name>.remote`
+/// and `branch.<name>.merge`.
+///
+/// # Errors
+///
+/// * Errors obtained when reading the config
+pub fn upstream(repo: &GitRepo, branch: &str) -> GitResult<Option<String>> {
+    let remote = config::get(repo, &format!("branch.{}.remote", branch))?;
+    let merge = config::get(repo, &format!("branch.{}.merge", branch))?;
+    Ok(match (remote, merge) {
+        (Some(remote), Some(merge)) if remote == "." => Some(merge),
+        (Some(remote), Some(merge)) => {
+            let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+            Some(format!("refs/remotes/{}/{}", remote, merge))
+        }
+        _ => None,
+    })
+}
+
+fn set_upstream(repo: &GitRepo, branch: &str, upstream: &str, quiet: bool) -> GitResult<()> {
+    let (remote, merge) = match upstream.strip_prefix("refs/remotes/") {
+        Some(rest) => match rest.find('/') {
+            Some(slash) => (
+                rest[..slash].to_string(),
+                format!("refs/heads/{}", &rest[slash + 1..]),
+            ),
+            None => {
+                return Err(error(
+                    upstream,
+                    &format!("the requested upstream branch '{}' does not exist", rest),
+                ))
+            }
+        },
+        None => (".".to_string(), upstream.to_string()),
+    };
+    let path = repo.gitpath().join("config");
+    config::set_value(&path, &format!("branch.{}.remote", branch), Some(&remote))?;
+    config::set_value(&path, &format!("branch.{}.merge", branch), Some(&merge))?;
+    if !quiet {
+        println!(
+            "branch '{}' set up to track '{}'.",
+            branch,
+            refs::shorten(upstream)
+        );
+    }
+    Ok(())
+}
+
+/// Returns true if the branch tip `sha` is merged into the upstream of `branch`, or into
+/// `HEAD` if it has none, warning when the two disagree like git does.
+fn is_merged(
+    repo: &GitRepo,
+    odb: &GitOdb,
+    refs: &GitRefs,
+    branch: &str,
+    sha: &str,
+) -> GitResult<bool> {
+    let head = refs.resolve("HEAD")?;
+    let merged_to_head = match &head {
+        Some(head) => revision::is_ancestor(odb, sha, head)?,
+        None => false,
+    };
+    let upstream = match upstream(repo, branch)? {
+        Some(upstream) => match refs.resolve(&upstream)? {
+            Some(tip) => (upstream, tip),
+            None => return Ok(merged_to_head),
+        },
+        None => return Ok(merged_to_head),
+    };
+    let merged = revision::is_ancestor(odb, sha, &upstream.1)?;
+    if merged && !merged_to_head {
+        eprintln!(
+            "warning: deleting branch '{}' that has been merged to\n         '{}', but not yet merged to HEAD.",
+            branch, upstream.0
+        );
+    } else if !merged && merged_to_head {
+        eprintln!(
+            "warning: not deleting branch '{}' that is not yet merged to\n         '{}', even though it is merged to HEAD.",
+            branch, upstream.0
+        );
+    }
+    Ok(merged)
+}
+
+/// Describes the upstream of `branch` for `-v` (`[ahead 1]`) and `-vv` (`[origin/main: ahead 1]`).
+fn tracking_info(
+    repo: &GitRepo,
+    odb: &GitOdb,
+    refs: &GitRefs,
+    branch: &str,
+    sha: &str,
+    verbose: u64,
+) -> GitResult<String> {
+    let upstream = match upstream(repo, branch)? {
+        Some(upstream) => upstream,
+        None => return Ok(String::new()),
+    };
+    let name = refs::shorten(&upstream);
+    let counts = match refs.resolve(&upstream)? {
+        Some(tip) => {
+            let (ahead, behind) = revision::ahead_behind(odb, sha, &tip)?;
+            match (ahead, behind) {
+                (0, 0) => String::new(),
+                (ahead, 0) => format!("ahead {}", ahead),
+                (0, behind) => format!("behind {}", behind),
+                (ahead, behind) => format!("ahead {}, behind {}", ahead, behind),
+            }
+        }
+        None => "gone".to_string(),
+    };
+    Ok(match (verbose, counts.is_empty()) {
+        (1, true) => String::new(),
+        (1, false) => format!("[{}] ", counts),
+        (_, true) => format!("[{}] ", name),
+        (_, false) => format!("[{}: {}] ", name, counts),
+    })
+}
+
+/// Describes a detached `HEAD` like `(HEAD detached at v1.0)`, using the name it was checked
+/// out by if the reflog records one.
+fn detached_description(odb: &GitOdb, refs: &GitRefs, sha: &str) -> GitResult<String> {
+    let checkout = refs.reflog("HEAD")?.into_iter().rev().find_map(|entry| {
+        let moved = entry.message().strip_prefix("checkout: moving from ")?;
+        let to = moved.rfind(" to ")?;
+        Some((moved[to + 4..].to_string(), entry.new_sha().to_string()))
+    });
+    let (name, at) = match checkout {
+        Some((name, target)) => {
+            let display = if refs.dwim(&name)?.is_some() {
+                name
+            } else {
+                revision::abbreviate(odb, &target, DEFAULT_ABBREV)?
+            };
+            (display, target == sha)
+        }
+        None => (revision::abbreviate(odb, sha, DEFAULT_ABBREV)?, true),
+    };
+    let preposition = if at { "at" } else { "from" };
+    Ok(format!("(HEAD detached {} {})", preposition, name))
+}
+
+fn branch_ref(name: &str) -> GitResult<String> {
+    if refs::is_valid_branch_name(name) {
+        Ok(format!("refs/heads/{}", name))
+    } else {
+        Err(error(
+            name,
+            &format!("'{}' is not a valid branch name", name),
+        ))
+    }
+}
+
+fn resolve_commit(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<String> {
+    revision::resolve_commit(odb, refs, rev)
+        .map_err(|_| error(rev, &format!("not a valid object name: '{}'", rev)))
+}
+
+fn error(name: &str, message: &str) -> GitError {
+    GitError::BranchError {
+        name: name.to_string(),
+        message: message.to_string(),
+    }
+}
@@ -0,0 +1,356 @@
+use crate::checkout::GitCheckout;
+use crate::config;
+use crate::objects::ObjectType;
+use crate::pathspec::Pathspec;
+use crate::refs::{self, GitRefs};
+use crate::revision::{self, DEFAULT_ABBREV};
+use crate::{GitCommit, GitError, GitOdb, GitRepo, GitResult};
+
+/// Which command is switching, as the two differ in what they accept and print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Checkout,
+    Switch,
+}
+
+/// Options shared by `checkout` and `switch` when moving `HEAD`.
+#[derive(Debug, Default)]
+pub struct SwitchOptions {
+    pub force: bool,
+    pub quiet: bool,
+    pub detach: bool,
+    /// A branch to create (`-b`/`-c`), and whether it may be reset if it exists (`-B`/`-C`).
+    pub new_branch: Option<(String, bool)>,
+}
+
+/// What `HEAD` will point to after switching.
+enum Target {
+    Branch(String),
+    Detached(String),
+}
+
+/// Switches `HEAD` to `target`, updating the index and worktree.
+///
+/// # Errors
+///
+/// * [GitError::CheckoutError]: The target is invalid, or local changes would be overwritten
+/// * Errors obtained when reading objects, refs or writing the worktree
+pub fn switch(
+    repo: &GitRepo,
+    command: Command,
+    target: Option<&str>,
+    options: &SwitchOptions,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let old_commit = refs.resolve("HEAD")?;
+    let old_branch = refs.head_branch()?;
+    let target = match target {
+        Some("-") => Some("@{-1}"),
+        target => target,
+    };
+
+    let (target_name, commit, new_head) = if let Some((name, reset)) = &options.new_branch {
+        if !refs::is_valid_branch_name(name) {
+            return Err(fatal(&format!("'{}' is not a valid branch name", name)));
+        }
+        let full = format!("refs/heads/{}", name);
+        if !reset && refs.exists(&full)? {
+            return Err(fatal(&format!("a branch named '{}' already exists", name)));
+        }
+        let start = target.unwrap_or("HEAD");
+        let commit = revision::resolve_commit(&odb, &refs, start)
+            .map_err(|_| fatal(&format!("invalid reference: {}", start)))?;
+        (name.clone(), commit, Target::Branch(full))
+    } else {
+        let name = match target {
+            Some(name) => name,
+            None if options.detach => "HEAD",
+            None if command == Command::Switch => {
+                return Err(fatal("missing branch or commit argument"))
+            }
+            None => {
+                // A plain `git checkout` only reports on the current state.
+                return Ok(());
+            }
+        };
+        let branch = branch_name(&refs, name)?;
+        match branch {
+            Some(branch) if !options.detach => {
+                let full = format!("refs/heads/{}", branch);
+                let commit = refs.resolve(&full)?.unwrap();
+                (branch, commit, Target::Branch(full))
+            }
+            _ => {
+                let commit = match revision::resolve_commit(&odb, &refs, name) {
+                    Ok(commit) => commit,
+                    Err(_) if command == Command::Checkout => {
+                        return Err(GitError::CheckoutError {
+                            message: format!(
+                                "error: pathspec '{}' did not match any file(s) known to git",
+                                name
+                            ),
+                        })
+                    }
+                    Err(_) => return Err(fatal(&format!("invalid reference: {}", name))),
+                };
+                if command == Command::Switch && !options.detach {
+                    return Err(fatal(&format!(
+                        "a branch is expected, got {}",
+                        describe_non_branch(&refs, name)?
+                    )));
+                }
+                (name.to_string(), commit.clone(), Target::Detached(commit))
+            }
+        }
+    };
+
+    let odb_tree = |commit: &str| revision::peel(&odb, commit, ObjectType::Tree);
+    let old_tree = match &old_commit {
+        Some(commit) => Some(odb_tree(commit)?),
+        None => None,
+    };
+    let new_tree = odb_tree(&commit)?;
+
+    let mut checkout = GitCheckout::new(repo)?;
+    checkout.set_force(options.force);
+    let result = checkout.switch_trees(old_tree.as_deref(), &new_tree);
+    print_warnings(&mut checkout);
+    result?;
+    checkout.write_index()?;
+
+    let old_desc = match (&old_branch, &old_commit) {
+        (Some(branch), _) => refs::shorten(branch).to_string(),
+        (None, Some(commit)) => commit.clone(),
+        (None, None) => "HEAD".to_string(),
+    };
+    let message = format!("checkout: moving from {} to {}", old_desc, target_name);
+    let mut report = Vec::new();
+
+    // Leaving a detached HEAD for another commit.
+    if old_branch.is_none() {
+        if let Some(old) = &old_commit {
+            let leaves_commit = match &new_head {
+                Target::Detached(new) => new != old,
+                Target::Branch(_) => true,
+            };
+            if leaves_commit {
+                report.push(format!(
+                    "Previous HEAD position was {}",
+                    describe_commit(&odb, old)?
+                ));
+            }
+        }
+    }
+
+    match &new_head {
+        Target::Branch(full) => {
+            let short = refs::shorten(full);
+            if let Some((_, reset)) = &options.new_branch {
+                let exists = refs.exists(full)?;
+                let start = target.unwrap_or("HEAD");
+                let log = if exists {
+                    format!("branch: Reset to {}", start)
+                } else {
+                    format!("branch: Created from {}", start)
+                };
+                refs.update(full, &commit, &log)?;
+                report.push(if *reset && exists {
+                    format!("Switched to and reset branch '{}'", short)
+                } else {
+                    format!("Switched to a new branch '{}'", short)
+                });
+            } else if old_branch.as_deref() == Some(full.as_str()) {
+                report.push(format!("Already on '{}'", short));
+            } else {
+                report.push(format!("Switched to branch '{}'", short));
+            }
+            refs.set_symbolic("HEAD", full, &message)?;
+        }
+        Target::Detached(commit) => {
+            refs.update("HEAD", commit, &message)?;
+            if old_branch.is_some()
+                && !options.detach
+                && config::get_bool(repo, "advice.detachedHead", true)?
+            {
+                report.push(detached_advice(&target_name));
+            }
+            report.push(format!("HEAD is now at {}", describe_commit(&odb, commit)?));
+        }
+    }
+
+    if !options.quiet {
+        for line in report {
+            eprintln!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Checks out `paths` from `source` (a tree-ish) or the index, like `git checkout -- <paths>`.
+///
+/// # Errors
+///
+/// * [GitError::CheckoutError]: A path matched nothing or is unmerged
+/// * Errors obtained when reading objects or writing the worktree
+pub fn checkout_paths(
+    repo: &GitRepo,
+    source: Option<&str>,
+    paths: &[String],
+    quiet: bool,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let source = match source {
+        Some(rev) => Some(resolve_tree(&odb, &refs, rev)?),
+        None => None,
+    };
+
+    let mut checkout = GitCheckout::new(repo)?;
+    let count = checkout.checkout_paths(
+        source.as_ref().map(|(tree, _)| tree.as_str()),
+        &Pathspec::new(paths),
+    );
+    print_warnings(&mut checkout);
+    let count = count?;
+    checkout.write_index()?;
+
+    if !quiet {
+        let from = match &source {
+            Some((_, object)) => revision::abbreviate(&odb, object, DEFAULT_ABBREV)?,
+            None => "the index".to_string(),
+        };
+        let noun = if count == 1 { "path" } else { "paths" };
+        eprintln!("Updated {} {} from {}", count, noun, from);
+    }
+    Ok(())
+}
+
+/// Restores `paths` in the index and/or worktree, like `git restore`.
+///
+/// Without `source` the worktree is restored from the index and the index from `HEAD`.
+///
+/// # Errors
+///
+/// * [GitError::CheckoutError]: No paths were given, or a path matched nothing
+/// * Errors obtained when reading objects or writing the worktree
+pub fn restore(
+    repo: &GitRepo,
+    source: Option<&str>,
+    paths: &[String],
+    staged: bool,
+    worktree: bool,
+) -> GitResult<()> {
+    if paths.is_empty() {
+        return Err(fatal("you must specify path(s) to restore"));
+    }
+    let worktree = worktree || !staged;
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let source = match source {
+        Some(rev) => Some(resolve_tree(&odb, &refs, rev)?.0),
+        None if staged => match refs.resolve("HEAD")? {
+            Some(commit) => Some(revision::peel(&odb, &commit, ObjectType::Tree)?),
+            // An unborn branch restores from the empty tree.
+            None => Some(odb.write(ObjectType::Tree, b"")?),
+        },
+        None => None,
+    };
+
+    let mut checkout = GitCheckout::new(repo)?;
+    let result = checkout.restore(source.as_deref(), &Pathspec::new(paths), staged, worktree);
+    print_warnings(&mut checkout);
+    result?;
+    checkout.write_index()
+}
+
+/// Prints what converting files for the worktree warned about.
+fn print_warnings(checkout: &mut GitCheckout) {
+    for warning in checkout.take_warnings() {
+        eprintln!("{}", warning);
+    }
+}
+
+/// Returns true if `rev` resolves to an object, used to tell revisions from paths.
+pub fn is_revision(repo: &GitRepo, rev: &str) -> GitResult<bool> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let rev = if rev == "-" { "@{-1}" } else { rev };
+    Ok(revision::rev_parse(&odb, &refs, rev).is_ok())
+}
+
+/// Returns the branch called `name` (after expanding `@{-<n>}`), if it exists.
+fn branch_name(refs: &GitRefs, name: &str) -> GitResult<Option<String>> {
+    let name = match name.strip_prefix("@{-").and_then(|n| n.strip_suffix('}')) {
+        Some(n) => match n.parse::<usize>().ok() {
+            Some(n) => match refs.previous_checkout(n)? {
+                Some(previous) => previous,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        },
+        None => name.to_string(),
+    };
+    if refs::is_valid_branch_name(&name) && refs.exists(&format!("refs/heads/{}", name))? {
+        Ok(Some(name))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Resolves a tree-ish, returning the tree and the object `rev` named.
+fn resolve_tree(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<(String, String)> {
+    let object = revision::rev_parse(odb, refs, rev)
+        .map_err(|_| fatal(&format!("could not resolve {}", rev)))?;
+    let tree = revision::peel(odb, &object, ObjectType::Tree)
+        .map_err(|_| fatal(&format!("reference is not a tree: {}", rev)))?;
+    Ok((tree, object))
+}
+
+/// Describes what a non-branch revision is for `switch` errors, e.g. `tag 'v1.0'`.
+fn describe_non_branch(refs: &GitRefs, name: &str) -> GitResult<String> {
+    Ok(match refs.dwim(name)? {
+        Some(full) if full.starts_with("refs/tags/") => format!("tag '{}'", name),
+        Some(full) if full.starts_with("refs/remotes/") => format!("remote branch '{}'", name),
+        _ => format!("commit '{}'", name),
+    })
+}
+
+/// Returns `<abbrev> <subject>` for a commit.
+fn describe_commit(odb: &GitOdb, sha: &str) -> GitResult<String> {
+    let commit = odb.read_as::<GitCommit>(sha)?;
+    Ok(format!(
+        "{} {}",
+        revision::abbreviate(odb, sha, DEFAULT_ABBREV)?,
+        commit.summary()
+    ))
+}
+
+fn detached_advice(target: &str) -> String {
+    format!(
+        "Note: switching to '{}'.
+
+You are in 'detached HEAD' state. You can look around, make experimental
+changes and commit them, and you can discard any commits you make in this
+state without impacting any branches by switching back to a branch.
+
+If you want to create a new branch to retain commits you create, you may
+do so (now or later) by using -c with the switch command. Example:
+
+  git switch -c <new-branch-name>
+
+Or undo this operation with:
+
+  git switch -
+
+Turn off this advice by setting config variable advice.detachedHead to false
+",
+        target
+    )
+}
+
+fn fatal(message: &str) -> GitError {
+    GitError::CheckoutError {
+        message: format!("fatal: {}", message),
+    }
+}
@@ -0,0 +1,465 @@
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::config::{self, ConfigFile, ConfigScope, ConfigValue, GitConfig, ValueType};
+use crate::{GitError, GitRepo, GitResult, ValuePattern};
+
+/// How `git config` ends. Like git, each way of failing has an exit status of its own, so
+/// that scripts can tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigStatus {
+    /// The command did what was asked.
+    Done,
+    /// No value matches the variable or pattern to read.
+    NotFound,
+    /// Any error git reports with `error:` and without a status of its own.
+    Error,
+    /// An error git reports with `fatal:`, such as an invalid value or a missing section.
+    Fatal,
+    /// A variable to change has no section or name.
+    InvalidKey,
+    /// The config file to change can not be parsed.
+    InvalidFile,
+    /// There is nothing to unset, or several values match where only one may.
+    NothingSet,
+    /// A name or value pattern is not a valid regular expression.
+    InvalidPattern,
+    /// The number of arguments is wrong.
+    Usage,
+}
+
+impl ConfigStatus {
+    /// Returns the exit status git uses.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ConfigStatus::Done => 0,
+            ConfigStatus::NotFound | ConfigStatus::Error => 1,
+            ConfigStatus::InvalidKey => 2,
+            ConfigStatus::InvalidFile => 3,
+            ConfigStatus::NothingSet => 5,
+            ConfigStatus::InvalidPattern => 6,
+            ConfigStatus::Fatal => 128,
+            ConfigStatus::Usage => 129,
+        }
+    }
+}
+
+/// An error of `git config` and the status it ends with.
+#[derive(Debug)]
+pub struct ConfigFailure {
+    pub status: ConfigStatus,
+    pub error: GitError,
+}
+
+impl From<GitError> for ConfigFailure {
+    fn from(error: GitError) -> Self {
+        let status = if error.to_string().starts_with("fatal:") {
+            ConfigStatus::Fatal
+        } else {
+            ConfigStatus::Error
+        };
+        Self { status, error }
+    }
+}
+
+pub type ConfigResult<T> = Result<T, ConfigFailure>;
+
+/// Where `git config` reads and writes, and how values are shown.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOptions {
+    /// A single file to use (`--file`, `--global`, ...) and the scope it stands for. Without
+    /// it, values are read from every layer and written to the repository config.
+    pub source: Option<(PathBuf, ConfigScope)>,
+    pub value_type: Option<ValueType>,
+    /// Compare values to value patterns for equality instead of as regular expressions.
+    pub fixed_value: bool,
+    /// Follow includes when reading a single file.
+    pub includes: bool,
+    pub show_origin: bool,
+    pub show_scope: bool,
+}
+
+/// Returns the file `git config --<scope>` uses.
+///
+/// # Errors
+///
+/// * [GitError::ConfigError]: `--global` is used without `$HOME`
+pub fn scope_path(repo: Option<&GitRepo>, scope: ConfigScope) -> GitResult<PathBuf> {
+    let gitpath = || {
+        repo.map(GitRepo::gitpath).ok_or_else(|| {
+            error(&format!(
+                "fatal: --{} can only be used inside a git repository",
+                scope
+            ))
+        })
+    };
+    match scope {
+        ConfigScope::System => Ok(config::system_path()),
+        ConfigScope::Global => config::global_path().ok_or_else(|| error("fatal: $HOME not set")),
+        ConfigScope::Worktree => {
+            let local = gitpath()?.join("config");
+            let extension = config::read_value(&local, "extensions.worktreeConfig")?;
+            if extension.is_some_and(|value| config::is_true(&value)) {
+                Ok(gitpath()?.join("config.worktree"))
+            } else {
+                Ok(local)
+            }
+        }
+        ConfigScope::Local | ConfigScope::Command => Ok(gitpath()?.join("config")),
+    }
+}
+
+/// Prints the last value of `name` matching `pattern`, or all of them, and returns
+/// [ConfigStatus::NotFound] if there is none.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidPattern]: The pattern is invalid
+/// * Errors obtained when reading the config files, or when the name is invalid or a value
+///   does not have the requested type
+pub fn get(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name: &str,
+    pattern: Option<&str>,
+    all: bool,
+) -> ConfigResult<ConfigStatus> {
+    let config = read(repo, options, true)?;
+    let pattern = value_pattern(options, pattern)?;
+    let mut lines = Vec::new();
+    for value in config.get_all(name)? {
+        if pattern.as_ref().is_none_or(|p| p.matches(value.value())) {
+            lines.push(format_value(options, value, None)?);
+        }
+    }
+    if !all && lines.len() > 1 {
+        lines.drain(..lines.len() - 1);
+    }
+    for line in &lines {
+        println!("{}", line);
+    }
+    Ok(found(!lines.is_empty()))
+}
+
+/// Prints `name value` for every variable whose name matches `name_regex` and whose value
+/// matches `pattern`, and returns [ConfigStatus::NotFound] if there is none.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidPattern]: A pattern is invalid
+/// * Errors obtained when reading the config files, or when a value does not have the
+///   requested type
+pub fn get_regexp(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name_regex: &str,
+    pattern: Option<&str>,
+) -> ConfigResult<ConfigStatus> {
+    // Like git, lower case the section and key parts of the pattern naively.
+    let first = name_regex.find('.').unwrap_or(name_regex.len());
+    let last = name_regex.rfind('.').map_or(0, |dot| dot + 1).max(first);
+    let name_regex = format!(
+        "{}{}{}",
+        name_regex[..first].to_ascii_lowercase(),
+        &name_regex[first..last],
+        name_regex[last..].to_ascii_lowercase()
+    );
+    let regex = Regex::new(&name_regex).map_err(|_| ConfigFailure {
+        status: ConfigStatus::InvalidPattern,
+        error: error(&format!("error: invalid key pattern: {}", name_regex)),
+    })?;
+    let pattern = value_pattern(options, pattern)?;
+
+    let config = read(repo, options, true)?;
+    let mut any = false;
+    for value in config.values() {
+        if regex.is_match(value.name()) && pattern.as_ref().is_none_or(|p| p.matches(value.value()))
+        {
+            println!("{}", format_value(options, value, Some(' '))?);
+            any = true;
+        }
+    }
+    Ok(found(any))
+}
+
+/// Prints every variable as `name=value`.
+///
+/// # Errors
+///
+/// Can return errors obtained when reading the config files.
+pub fn list(repo: Option<&GitRepo>, options: &ConfigOptions) -> GitResult<()> {
+    let options = ConfigOptions {
+        value_type: None,
+        ..options.clone()
+    };
+    for value in read(repo, &options, false)?.values() {
+        println!("{}", format_value(&options, value, Some('='))?);
+    }
+    Ok(())
+}
+
+/// Sets `name` to `value`, replacing the only value matching `pattern` if one is given.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidKey]: The name is invalid
+/// * [ConfigStatus::InvalidFile]: The config file can not be parsed
+/// * [ConfigStatus::NothingSet]: Several values would be replaced
+/// * [ConfigStatus::InvalidPattern]: The pattern is invalid
+/// * Errors obtained when the value is invalid, or when reading or writing the config file
+pub fn set(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name: &str,
+    value: &str,
+    pattern: Option<&str>,
+) -> ConfigResult<()> {
+    check_name(name)?;
+    let value = typed_value(options, name, value)?;
+    let mut file = open(repo, options)?;
+    match value_pattern(options, pattern)? {
+        Some(pattern) => {
+            let matching = file
+                .get_all(name)?
+                .iter()
+                .filter(|value| pattern.matches(value.as_deref()))
+                .count();
+            if matching > 1 {
+                return Err(ConfigFailure {
+                    status: ConfigStatus::NothingSet,
+                    error: error(&format!("warning: {} has multiple values", name)),
+                });
+            }
+            file.replace_all(name, &value, Some(&pattern))?;
+        }
+        // The name is valid, so this fails only if the variable has several values.
+        None => file.set(name, &value).map_err(|error| ConfigFailure {
+            status: ConfigStatus::NothingSet,
+            error,
+        })?,
+    }
+    Ok(file.write()?)
+}
+
+/// Adds another value for `name`.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidKey]: The name is invalid
+/// * [ConfigStatus::InvalidFile]: The config file can not be parsed
+/// * Errors obtained when the value is invalid, or when reading or writing the config file
+pub fn add(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name: &str,
+    value: &str,
+) -> ConfigResult<()> {
+    check_name(name)?;
+    let value = typed_value(options, name, value)?;
+    let mut file = open(repo, options)?;
+    file.add(name, &value)?;
+    Ok(file.write()?)
+}
+
+/// Replaces all values of `name` matching `pattern` (or all values) with `value`.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidKey]: The name is invalid
+/// * [ConfigStatus::InvalidFile]: The config file can not be parsed
+/// * [ConfigStatus::InvalidPattern]: The pattern is invalid
+/// * Errors obtained when the value is invalid, or when reading or writing the config file
+pub fn replace_all(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name: &str,
+    value: &str,
+    pattern: Option<&str>,
+) -> ConfigResult<()> {
+    check_name(name)?;
+    let value = typed_value(options, name, value)?;
+    let pattern = value_pattern(options, pattern)?;
+    let mut file = open(repo, options)?;
+    file.replace_all(name, &value, pattern.as_ref())?;
+    Ok(file.write()?)
+}
+
+/// Removes the only value of `name` matching `pattern`, or all matching values, and returns
+/// [ConfigStatus::NothingSet] if nothing was removed.
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidKey]: The name is invalid
+/// * [ConfigStatus::InvalidFile]: The config file can not be parsed
+/// * [ConfigStatus::NothingSet]: Several values match without `all`
+/// * [ConfigStatus::InvalidPattern]: The pattern is invalid
+/// * Errors obtained when reading or writing the config file
+pub fn unset(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    name: &str,
+    pattern: Option<&str>,
+    all: bool,
+) -> ConfigResult<ConfigStatus> {
+    check_name(name)?;
+    let pattern = value_pattern(options, pattern)?;
+    let mut file = open(repo, options)?;
+    let removed = if all {
+        file.unset_all(name, pattern.as_ref())? > 0
+    } else {
+        // The name is valid, so this fails only if several values match.
+        file.unset(name, pattern.as_ref())
+            .map_err(|error| ConfigFailure {
+                status: ConfigStatus::NothingSet,
+                error,
+            })?
+    };
+    if !removed {
+        return Ok(ConfigStatus::NothingSet);
+    }
+    file.write()?;
+    Ok(ConfigStatus::Done)
+}
+
+/// Renames the section `old` to `new`, or removes it if `new` is [None].
+///
+/// # Errors
+///
+/// * [ConfigStatus::InvalidFile]: The config file can not be parsed
+/// * [GitError::ConfigError]: A section name is invalid, or there is no such section
+/// * Errors obtained when reading or writing the config file
+pub fn rename_section(
+    repo: Option<&GitRepo>,
+    options: &ConfigOptions,
+    old: &str,
+    new: Option<&str>,
+) -> ConfigResult<()> {
+    let mut file = open(repo, options)?;
+    if !file.rename_section(old, new)? {
+        return Err(error(&format!("fatal: no such section: {}", old)).into());
+    }
+    Ok(file.write()?)
+}
+
+/// Reads the selected file, or every config layer. Like git, a missing file only reads as
+/// empty when values are looked up with `lookup`.
+fn read(repo: Option<&GitRepo>, options: &ConfigOptions, lookup: bool) -> GitResult<GitConfig> {
+    match &options.source {
+        Some((path, _)) if !path.is_file() && lookup => Ok(GitConfig::default()),
+        Some((path, _)) if !path.is_file() => Err(error(&format!(
+            "fatal: unable to read config file '{}': No such file or directory",
+            path.display()
+        ))),
+        Some((path, scope)) => GitConfig::from_file(path, *scope, repo, options.includes),
+        None => GitConfig::load(repo),
+    }
+}
+
+/// Opens the file to change: the selected one or the repository config.
+fn open(repo: Option<&GitRepo>, options: &ConfigOptions) -> ConfigResult<ConfigFile> {
+    let path = match (&options.source, repo) {
+        (Some((path, _)), _) => path.clone(),
+        (None, Some(repo)) => repo.gitpath().join("config"),
+        (None, None) => return Err(error("fatal: not in a git directory").into()),
+    };
+    ConfigFile::open(path).map_err(|error| match error {
+        GitError::ConfigError { .. } => ConfigFailure {
+            status: ConfigStatus::InvalidFile,
+            error,
+        },
+        error => error.into(),
+    })
+}
+
+/// Checks that the variable `name` to change has a section and a name.
+fn check_name(name: &str) -> ConfigResult<()> {
+    config::canonical_name(name).map_err(|error| ConfigFailure {
+        status: ConfigStatus::InvalidKey,
+        error,
+    })?;
+    Ok(())
+}
+
+fn value_pattern(
+    options: &ConfigOptions,
+    pattern: Option<&str>,
+) -> ConfigResult<Option<ValuePattern>> {
+    match pattern {
+        Some(pattern) if options.fixed_value => Ok(Some(ValuePattern::fixed(pattern))),
+        Some(pattern) => match ValuePattern::new(pattern) {
+            Ok(pattern) => Ok(Some(pattern)),
+            Err(error) => Err(ConfigFailure {
+                status: ConfigStatus::InvalidPattern,
+                error,
+            }),
+        },
+        None if options.fixed_value => {
+            Err(error("error: --fixed-value only applies with 'value-pattern'").into())
+        }
+        None => Ok(None),
+    }
+}
+
+/// Returns [ConfigStatus::Done] if a value was `found`, or [ConfigStatus::NotFound].
+fn found(found: bool) -> ConfigStatus {
+    if found {
+        ConfigStatus::Done
+    } else {
+        ConfigStatus::NotFound
+    }
+}
+
+/// Checks `value` against the requested type, returning what is written to the file.
+fn typed_value(options: &ConfigOptions, name: &str, value: &str) -> GitResult<String> {
+    let value_type = match options.value_type {
+        Some(value_type) => value_type,
+        None => return Ok(value.to_string()),
+    };
+    match value_type.normalize(Some(value)) {
+        // Paths and colors are stored as written.
+        Some(_) if matches!(value_type, ValueType::Path | ValueType::Color) => {
+            Ok(value.to_string())
+        }
+        Some(normalized) => Ok(normalized),
+        None => Err(value_type.invalid(name, Some(value), "")),
+    }
+}
+
+/// Formats a value for output, prefixed by its scope and origin if requested. With a
+/// `key_delimiter`, the name is shown first.
+fn format_value(
+    options: &ConfigOptions,
+    value: &ConfigValue,
+    key_delimiter: Option<char>,
+) -> GitResult<String> {
+    let mut line = String::new();
+    if options.show_scope {
+        line.push_str(&format!("{}\t", value.scope()));
+    }
+    if options.show_origin {
+        line.push_str(&format!("{}\t", value.origin()));
+    }
+    let shown = match options.value_type {
+        Some(value_type) => Some(value.typed(value_type)?),
+        None => value.value().map(String::from),
+    };
+    match key_delimiter {
+        Some(delimiter) => {
+            line.push_str(value.name());
+            if let Some(shown) = shown {
+                line.push(delimiter);
+                line.push_str(&shown);
+            }
+        }
+        None => line.push_str(shown.as_deref().unwrap_or("")),
+    }
+    Ok(line)
+}
+
+fn error(message: &str) -> GitError {
+    GitError::ConfigError {
+        path: PathBuf::new(),
+        message: message.to_string(),
+    }
+}
@@ -0,0 +1,326 @@
+use std::io::{self, IsTerminal, Write};
+
+use super::log_helper;
+use crate::refs::GitRefs;
+use crate::revision;
+use crate::{
+    self as gitrs, ChangeStatus, DiffFormat, DiffOptions, FileChange, GitCommit, GitConfig,
+    GitDiff, GitError, GitOdb, GitRepo, GitResult, ObjectType, Pathspec, RenameOptions,
+};
+
+/// Shows the changes between the trees, index and worktree selected by `revisions`, like
+/// `git diff`:
+///
+/// * no revision compares the index with the worktree, or HEAD with the index if `cached`
+/// * one revision compares its tree with the worktree, or with the index if `cached`
+/// * two revisions, `A..B` or `A...B` compare two trees, where `A...B` starts from the merge
+///   base of `A` and `B`
+///
+/// Renamed and copied files are found as set by `renames`.
+///
+/// Returns true if nothing changed.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: A revision is invalid
+/// * [GitError::DiffError]: There are too many revisions
+/// * Errors obtained when reading objects, the index or worktree files
+pub fn diff(
+    repo: &GitRepo,
+    revisions: &[String],
+    paths: &[String],
+    cached: bool,
+    renames: Option<RenameOptions>,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> GitResult<bool> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let pathspec = Pathspec::new(paths);
+    let mut diff = GitDiff::new(repo)?;
+    diff.set_renames(renames);
+
+    let changes = match revisions {
+        [] if cached => {
+            let head = match refs.resolve("HEAD")? {
+                Some(head) => Some(tree(&odb, &refs, &head)?),
+                None => None,
+            };
+            diff.diff_index(head.as_deref(), true, &pathspec)?
+        }
+        [] => diff.diff_files(&pathspec)?,
+        [range] if range.contains("..") => {
+            let (old, new) = range_trees(&odb, &refs, range)?;
+            diff.diff_trees(Some(&old), Some(&new), &pathspec)?
+        }
+        [rev] => {
+            let tree = tree(&odb, &refs, rev)?;
+            diff.diff_index(Some(&tree), cached, &pathspec)?
+        }
+        [old, new] => {
+            let (old, new) = (tree(&odb, &refs, old)?, tree(&odb, &refs, new)?);
+            diff.diff_trees(Some(&old), Some(&new), &pathspec)?
+        }
+        _ => {
+            return Err(GitError::DiffError {
+                message: "combined diffs of more than two revisions are not supported".to_string(),
+            })
+        }
+    };
+    show(&mut diff, &changes, options, format)?;
+    Ok(changes.is_empty())
+}
+
+/// Shows the changes between the trees of two revisions, or between a commit and its first
+/// parent, like `git diff-tree`. Subtrees and renames are handled as set up in `diff`.
+///
+/// The changes of a single commit follow its name unless `commit_id` is false. Root commits
+/// are only compared with the empty tree with `root`, and merges show nothing.
+///
+/// Returns true if nothing changed.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: A revision is invalid
+/// * [GitError::DiffError]: There are not one or two revisions
+/// * Errors obtained when reading objects
+#[allow(clippy::too_many_arguments)]
+pub fn diff_tree(
+    repo: &GitRepo,
+    diff: &mut GitDiff,
+    revisions: &[String],
+    paths: &[String],
+    root: bool,
+    commit_id: bool,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> GitResult<bool> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let pathspec = Pathspec::new(paths);
+    let (header, changes) = match revisions {
+        [old, new] => {
+            let (old, new) = (tree(&odb, &refs, old)?, tree(&odb, &refs, new)?);
+            (None, diff.diff_trees(Some(&old), Some(&new), &pathspec)?)
+        }
+        [rev] => {
+            let sha = commit(&odb, &refs, rev)?;
+            let commit = odb.read_as::<GitCommit>(&sha)?;
+            let parent = match commit.parents() {
+                [] if root => None,
+                [parent] => Some(odb.read_as::<GitCommit>(parent)?.tree().to_string()),
+                _ => return Ok(true),
+            };
+            let changes = diff.diff_trees(parent.as_deref(), Some(commit.tree()), &pathspec)?;
+            (Some(sha).filter(|_| commit_id), changes)
+        }
+        _ => {
+            return Err(GitError::DiffError {
+                message: "diff-tree compares one commit or two trees".to_string(),
+            })
+        }
+    };
+    if let Some(sha) = header.filter(|_| !changes.is_empty()) {
+        let end = if format.null_terminated { '\0' } else { '\n' };
+        let stdout = io::stdout();
+        log_helper::ignore_broken_pipe(gitrs::to_git_result(
+            write!(stdout.lock(), "{}{}", sha, end),
+            "<stdout>",
+        ))?;
+    }
+    show(diff, &changes, options, format)?;
+    Ok(changes.is_empty())
+}
+
+/// Shows the changes between the tree of `revisions`, its only item, and the worktree, or the
+/// index if `cached` is set, like `git diff-index`.
+///
+/// Returns true if nothing changed.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: The revision is invalid
+/// * [GitError::DiffError]: There is not exactly one revision, or the worktree is compared in
+///   a bare repository
+/// * Errors obtained when reading objects, the index or worktree files
+pub fn diff_index(
+    repo: &GitRepo,
+    diff: &mut GitDiff,
+    revisions: &[String],
+    paths: &[String],
+    cached: bool,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> GitResult<bool> {
+    let rev = match revisions {
+        [rev] => rev,
+        _ => {
+            return Err(GitError::DiffError {
+                message: "diff-index compares one tree".to_string(),
+            })
+        }
+    };
+    let tree = tree(&GitOdb::open(repo)?, &GitRefs::new(repo), rev)?;
+    let changes = diff.diff_index(Some(&tree), cached, &Pathspec::new(paths))?;
+    show(diff, &changes, options, format)?;
+    Ok(changes.is_empty())
+}
+
+/// Shows the changes between the index and the worktree, like `git diff-files`. With
+/// `no_deleted`, files missing from the worktree are left out.
+///
+/// Returns true if nothing changed.
+///
+/// # Errors
+///
+/// * [GitError::DiffError]: The repository is bare
+/// * Errors obtained when reading the index or worktree files
+pub fn diff_files(
+    diff: &mut GitDiff,
+    paths: &[String],
+    no_deleted: bool,
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> GitResult<bool> {
+    let mut changes = diff.diff_files(&Pathspec::new(paths))?;
+    if no_deleted {
+        changes.retain(|change| change.status() != ChangeStatus::Deleted);
+    }
+    show(diff, &changes, options, format)?;
+    Ok(changes.is_empty())
+}
+
+/// Writes `changes` to stdout, stopping quietly when the reader goes away, then prints what
+/// the diff warned about.
+fn show(
+    diff: &mut GitDiff,
+    changes: &[FileChange],
+    options: &DiffOptions,
+    format: &DiffFormat,
+) -> GitResult<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = log_helper::ignore_broken_pipe(diff.write(changes, options, format, &mut out));
+    for warning in diff.take_warnings() {
+        eprintln!("{}", warning);
+    }
+    result
+}
+
+/// Returns the trees compared by `A..B` or `A...B`, where an empty side stands for HEAD.
+fn range_trees(odb: &GitOdb, refs: &GitRefs, range: &str) -> GitResult<(String, String)> {
+    let (symmetric, sides) = match range.split_once("...") {
+        Some(sides) => (true, sides),
+        None => (false, range.split_once("..").unwrap()),
+    };
+    let (old, new) = (or_head(sides.0), or_head(sides.1));
+    if !symmetric {
+        return Ok((tree(odb, refs, old)?, tree(odb, refs, new)?));
+    }
+    let old = commit(odb, refs, old)?;
+    let new = commit(odb, refs, new)?;
+    let base = match revision::merge_bases(odb, &old, std::slice::from_ref(&new))?.first() {
+        Some(base) => base.clone(),
+        None => {
+            return Err(GitError::DiffError {
+                message: format!("{}: no merge base", range),
+            })
+        }
+    };
+    Ok((tree(odb, refs, &base)?, tree(odb, refs, &new)?))
+}
+
+fn or_head(rev: &str) -> &str {
+    if rev.is_empty() {
+        "HEAD"
+    } else {
+        rev
+    }
+}
+
+fn commit(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<String> {
+    let sha = revision::rev_parse(odb, refs, rev)?;
+    revision::peel(odb, &sha, ObjectType::Commit)
+}
+
+/// Returns the tree of the commit or tree `rev`.
+fn tree(odb: &GitOdb, refs: &GitRefs, rev: &str) -> GitResult<String> {
+    let sha = revision::rev_parse(odb, refs, rev)?;
+    revision::peel(odb, &sha, ObjectType::Tree).map_err(|_| GitError::RevisionError {
+        rev: rev.to_string(),
+        message: format!("unable to read tree object {}", rev),
+    })
+}
+
+/// Reads whether to color the output from `--color[=<when>]`, `--no-color`, `color.diff` or
+/// `color.ui`, where `auto` colors only output to a terminal.
+///
+/// # Errors
+///
+/// * [GitError::DiffError]: The value is invalid
+/// * [GitError::ConfigError]: The config can not be read
+pub fn use_color(config: &GitConfig, option: Option<&str>, no_color: bool) -> GitResult<bool> {
+    if no_color {
+        return Ok(false);
+    }
+    let when = match option {
+        Some(when) => when.to_string(),
+        None => match config.get("color.diff")? {
+            Some(when) => when,
+            None => config
+                .get("color.ui")?
+                .unwrap_or_else(|| "auto".to_string()),
+        },
+    };
+    match when.to_ascii_lowercase().as_str() {
+        "always" | "true" | "yes" | "on" | "1" => Ok(true),
+        "never" | "false" | "no" | "off" | "0" => Ok(false),
+        "auto" => Ok(io::stdout().is_terminal()),
+        _ => Err(GitError::DiffError {
+            message: format!("invalid color value: {}", when),
+        }),
+    }
+}
+
+/// Reads `--stat=<width>[,<name-width>]` into `format`.
+///
+/// # Errors
+///
+/// * [GitError::DiffError]: A width is not a number
+pub fn stat_widths(format: &mut DiffFormat, value: &str) -> GitResult<()> {
+    let mut widths = value.splitn(2, ',').map(|width| {
+        width.parse::<usize>().map_err(|_| GitError::DiffError {
+            message: format!("invalid --stat value: {}", value),
+        })
+    });
+    format.stat_width = widths.next().transpose()?;
+    format.stat_name_width = widths.next().transpose()?;
+    Ok(())
+}
+
+/// Returns the value of the integer option `name`, or of `key` in the config.
+///
+/// # Errors
+///
+/// * [GitError::DiffError]: The option is not a number
+/// * [GitError::ConfigError]: The config value is not a number
+pub fn count_option(
+    config: &GitConfig,
+    value: Option<&str>,
+    name: &str,
+    key: &str,
+    default: usize,
+) -> GitResult<usize> {
+    match value {
+        Some(value) => value.parse().map_err(|_| GitError::DiffError {
+            message: format!("{} expects a non-negative integer value", name),
+        }),
+        None => match config.get_int(key)? {
+            Some(count) if count >= 0 => Ok(count as usize),
+            Some(_) => Err(GitError::DiffError {
+                message: format!("bad config variable '{}'", key),
+            }),
+            None => Ok(default),
+        },
+    }
+}
@@ -0,0 +1,267 @@
+use std::io::{self, ErrorKind, IsTerminal, Write};
+use std::path::Path;
+
+use crate::refs::{self, GitRefs};
+use crate::revision::{self, RevWalk, WalkOptions};
+use crate::{
+    self as gitrs, Decorate, GitError, GitOdb, GitRepo, GitResult, LogOptions, ObjectType,
+};
+
+/// Splits the arguments given before `--` into revisions and paths.
+///
+/// Arguments are revisions until the first one that is not, which with all following ones
+/// must name existing files.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: An argument is neither a revision nor a file, or both
+pub fn split_args(repo: &GitRepo, args: &[String]) -> GitResult<(Vec<String>, Vec<String>)> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let mut revisions = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        let exists = Path::new(arg).symlink_metadata().is_ok();
+        if is_revision(&odb, &refs, arg) {
+            if exists {
+                return Err(ambiguous(arg, "both revision and filename"));
+            }
+            revisions.push(arg.clone());
+            continue;
+        }
+        if !exists {
+            return Err(ambiguous(
+                arg,
+                "unknown revision or path not in the working tree.",
+            ));
+        }
+        let paths = args[i..].to_vec();
+        if let Some(missing) = paths
+            .iter()
+            .find(|path| Path::new(path).symlink_metadata().is_err())
+        {
+            return Err(GitError::RevisionError {
+                rev: missing.clone(),
+                message: format!(
+                    "{}: no such path in the working tree.\nUse 'git <command> -- <path>...' to specify paths that do not exist locally.",
+                    missing
+                ),
+            });
+        }
+        return Ok((revisions, paths));
+    }
+    Ok((revisions, Vec::new()))
+}
+
+/// Returns true if `arg` is a revision, an excluded revision, a range or a symmetric
+/// difference.
+fn is_revision(odb: &GitOdb, refs: &GitRefs, arg: &str) -> bool {
+    let arg = arg.strip_prefix('^').unwrap_or(arg);
+    let sides: Vec<&str> = if arg.contains("...") {
+        arg.splitn(2, "...").collect()
+    } else if arg.contains("..") {
+        arg.splitn(2, "..").collect()
+    } else {
+        vec![arg]
+    };
+    sides.iter().all(|side| {
+        let side = if side.is_empty() && sides.len() > 1 {
+            "HEAD"
+        } else {
+            side
+        };
+        revision::resolve_commit(odb, refs, side).is_ok()
+    })
+}
+
+/// Prints the history of `revisions` (or `HEAD`) limited by `walk_options`, as described by
+/// `options`.
+///
+/// With `all`, the history of all refs is shown as well. With `separated`, the revisions were
+/// given before `--` and must all be valid.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: A revision is invalid
+/// * [GitError::LogError]: The current branch has no commits yet
+/// * Errors obtained when reading refs or objects, or writing the output
+pub fn log(
+    repo: &GitRepo,
+    revisions: &[String],
+    all: bool,
+    separated: bool,
+    walk_options: WalkOptions,
+    options: &LogOptions,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let mut walk = RevWalk::new(&odb, walk_options);
+    if !all && revisions.is_empty() {
+        match refs.resolve("HEAD")? {
+            Some(sha) => walk.push(&sha),
+            None => {
+                let branch = refs.resolve_name("HEAD")?;
+                return Err(GitError::LogError {
+                    message: format!(
+                        "your current branch '{}' does not have any commits yet",
+                        refs::shorten(&branch)
+                    ),
+                });
+            }
+        }
+    }
+    push_revisions(&mut walk, &odb, &refs, revisions, all, separated)?;
+
+    let result = walk.walk()?;
+    let stdout = io::stdout();
+    ignore_broken_pipe(gitrs::write_log(
+        &odb,
+        &refs,
+        &result,
+        options,
+        &mut stdout.lock(),
+    ))
+}
+
+/// Prints the commits reachable from `revisions` (and all refs with `all`) limited by
+/// `walk_options`, one per line, like `git rev-list`.
+///
+/// With `separated`, the revisions were given before `--` and must all be valid. With `count`,
+/// only the number of commits is printed. With `objects`, the trees and blobs of the commits
+/// follow with their paths.
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: A revision is invalid
+/// * Errors obtained when reading refs or objects, or writing the output
+pub fn rev_list(
+    repo: &GitRepo,
+    revisions: &[String],
+    all: bool,
+    separated: bool,
+    walk_options: WalkOptions,
+    count: bool,
+    objects: bool,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let mut walk = RevWalk::new(&odb, walk_options);
+    push_revisions(&mut walk, &odb, &refs, revisions, all, separated)?;
+    let result = walk.walk()?;
+
+    let mut output = String::new();
+    let objects = if objects {
+        walk.objects(&result)?
+    } else {
+        Vec::new()
+    };
+    if count {
+        output.push_str(&format!("{}\n", result.commits().len() + objects.len()));
+    } else {
+        for commit in result.commits() {
+            output.push_str(commit.sha());
+            output.push('\n');
+        }
+        for (sha, path) in objects {
+            output.push_str(&format!("{} {}\n", sha, path));
+        }
+    }
+    let stdout = io::stdout();
+    ignore_broken_pipe(gitrs::to_git_result(
+        stdout.lock().write_all(output.as_bytes()),
+        "stdout",
+    ))
+}
+
+/// Adds `revisions` to `walk`, and with `all` every ref and `HEAD`. With `separated`, errors
+/// name the revisions as bad revisions.
+fn push_revisions(
+    walk: &mut RevWalk,
+    odb: &GitOdb,
+    refs: &GitRefs,
+    revisions: &[String],
+    all: bool,
+    separated: bool,
+) -> GitResult<()> {
+    if all {
+        let mut tips = refs.list("refs/")?;
+        tips.extend(refs.resolve("HEAD")?.map(|sha| ("HEAD".to_string(), sha)));
+        for (_, sha) in tips {
+            // Refs to other objects than commits, like tags of trees, have no history.
+            if let Ok(commit) = revision::peel(odb, &sha, ObjectType::Commit) {
+                if commit == sha {
+                    walk.push(&commit);
+                } else {
+                    walk.push_tag(&sha)?;
+                }
+            }
+        }
+    }
+    for revision in revisions {
+        walk.push_rev(refs, revision).map_err(|error| {
+            if separated {
+                GitError::RevisionError {
+                    rev: revision.clone(),
+                    message: format!("bad revision '{}'", revision),
+                }
+            } else {
+                error
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Treats output cut short by a reader like `head` as success.
+pub fn ignore_broken_pipe(result: GitResult<()>) -> GitResult<()> {
+    match result {
+        Err(GitError::IOError { error, .. }) if error.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Reads the decoration style from `--decorate[=<style>]`, `--no-decorate` or `log.decorate`,
+/// where `auto` decorates only output to a terminal.
+///
+/// # Errors
+///
+/// * [GitError::LogError]: The style is invalid
+/// * [GitError::ConfigError]: `log.decorate` can not be read
+pub fn decorate_style(
+    repo: &GitRepo,
+    option: Option<&str>,
+    no_decorate: bool,
+) -> GitResult<Decorate> {
+    if no_decorate {
+        return Ok(Decorate::No);
+    }
+    let style = match option {
+        Some(style) => style.to_string(),
+        None => match repo.config()?.get("log.decorate")? {
+            Some(value) => match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => "short".to_string(),
+                "false" | "no" | "off" | "0" => "no".to_string(),
+                "" => "no".to_string(),
+                other => other.to_string(),
+            },
+            None => "auto".to_string(),
+        },
+    };
+    if style == "auto" {
+        return Ok(if io::stdout().is_terminal() {
+            Decorate::Short
+        } else {
+            Decorate::No
+        });
+    }
+    style.parse()
+}
+
+fn ambiguous(arg: &str, reason: &str) -> GitError {
+    GitError::RevisionError {
+        rev: arg.to_string(),
+        message: format!(
+            "ambiguous argument '{}': {}\nUse '--' to separate paths from revisions, like this:\n'git <command> [<revision>...] -- [<file>...]'",
+            arg, reason
+        ),
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+use super::log_helper;
+use crate::mail::COVER_LETTER_NAME;
+use crate::refs::GitRefs;
+use crate::revision::{RevWalk, WalkOptions};
+use crate::utils;
+use crate::{
+    self as gitrs, AmMessage, AmReport, AmStop, GitError, GitOdb, GitRepo, GitResult, PatchMail,
+};
+
+/// What `am` prints when it stops at a patch, after the reason.
+const RESOLVE_MESSAGE: &str = "\
+When you have resolved this problem, run \"git am --continue\".
+If you prefer to skip this patch, run \"git am --skip\" instead.
+To restore the original branch and stop patching, run \"git am --abort\".";
+
+/// Returns the commits `format-patch` prepares for `revisions`, oldest first, leaving out
+/// merges:
+///
+/// * a single revision `<since>` selects the commits up to `HEAD` that it does not reach,
+///   unless `max_count` or `root` is given, where it is the last commit
+/// * otherwise the revisions select commits like `git log`, such as `A..B`
+///
+/// # Errors
+///
+/// * [GitError::RevisionError]: A revision is invalid
+/// * [GitError::ObjectError]: A commit is missing or corrupt
+pub fn patch_commits(
+    repo: &GitRepo,
+    revisions: &[String],
+    max_count: Option<usize>,
+    root: bool,
+) -> GitResult<Vec<String>> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let options = WalkOptions {
+        max_count,
+        ..WalkOptions::default()
+    };
+    let mut walk = RevWalk::new(&odb, options);
+    match revisions {
+        [] if max_count.is_some() => walk.push_rev(&refs, "HEAD")?,
+        [since] if max_count.is_none() && !root && !since.contains("..") => {
+            walk.push_rev(&refs, &format!("{}..HEAD", since))?
+        }
+        _ => {
+            for revision in revisions {
+                walk.push_rev(&refs, revision)?;
+            }
+        }
+    }
+    if walk.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut commits: Vec<String> = walk
+        .walk()?
+        .commits()
+        .iter()
+        .filter(|commit| commit.parents().len() < 2)
+        .map(|commit| commit.sha().to_string())
+        .collect();
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Prints `mails` one after the other, or writes them to files in `dir` and prints the names
+/// of the files unless `quiet` is set.
+///
+/// # Errors
+///
+/// * [GitError::IOError]: The directory can not be created, or a file can not be written
+pub fn write_mails(
+    mails: &[PatchMail],
+    dir: Option<&str>,
+    stdout: bool,
+    quiet: bool,
+) -> GitResult<()> {
+    if stdout {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for (i, mail) in mails.iter().enumerate() {
+            // Like git, a blank line separates the patches, but not the cover letter.
+            let separator = i > 0 && mails[i - 1].name != COVER_LETTER_NAME;
+            let result = out
+                .write_all(if separator { b"\n" } else { b"" })
+                .and_then(|_| out.write_all(&mail.contents));
+            log_helper::ignore_broken_pipe(gitrs::to_git_result(result, "<stdout>"))?;
+        }
+        return Ok(());
+    }
+    if let Some(dir) = dir {
+        utils::create_dir_all_if_new(dir)?;
+    }
+    for mail in mails {
+        let path = match dir {
+            Some(dir) => Path::new(dir).join(&mail.name),
+            None => Path::new(&mail.name).to_path_buf(),
+        };
+        gitrs::to_git_result(fs::write(&path, &mail.contents), &path)?;
+        if !quiet {
+            println!("{}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Reads the mailboxes `files` for `am`, or stdin if there are none.
+///
+/// # Errors
+///
+/// * [GitError::MailError]: A file can not be read
+pub fn read_mailboxes(files: &[String]) -> GitResult<Vec<Vec<u8>>> {
+    if files.is_empty() {
+        let mut data = Vec::new();
+        gitrs::to_git_result(io::stdin().lock().read_to_end(&mut data), "<stdin>")?;
+        return Ok(vec![data]);
+    }
+    files
+        .iter()
+        .map(|file| {
+            fs::read(file).map_err(|error| {
+                let reason = match error.kind() {
+                    ErrorKind::NotFound => "No such file or directory".to_string(),
+                    _ => error.to_string(),
+                };
+                GitError::MailError {
+                    message: format!("fatal: could not open '{}' for reading: {}", file, reason),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Prints what a step of `am` did like git: the patches on stdout, what applying them
+/// reported on stderr and, if the series stopped, why and how to go on. Returns false if it
+/// stopped.
+pub fn print_am_report(report: &AmReport) -> bool {
+    for message in &report.messages {
+        match message {
+            AmMessage::Applying(title) => println!("Applying: {}", title),
+            AmMessage::Apply(message) => eprintln!("{}", message),
+        }
+    }
+    match &report.stopped {
+        None => return true,
+        Some(AmStop::EmptyPatch) => println!("Patch is empty."),
+        Some(AmStop::PatchFailed { number, title }) => {
+            println!("Patch failed at {:04} {}", number, title);
+            eprintln!("hint: Use 'git am --show-current-patch=diff' to see the failed patch");
+        }
+        Some(AmStop::Unmerged) => println!(
+            "You still have unmerged paths in your index.\n\
+             You should 'git add' each file with resolved conflicts to mark them as such.\n\
+             You might run `git rm` on a file to accept \"deleted by them\" for it."
+        ),
+        Some(AmStop::NoChanges) => println!(
+            "No changes - did you forget to use 'git add'?\n\
+             If there is nothing left to stage, chances are that something else\n\
+             already introduced the same changes; you might want to skip this patch."
+        ),
+    }
+    println!("{}", RESOLVE_MESSAGE);
+    false
+}
@@ -1,8 +1,250 @@
-use crate::{GitBlob, GitObject /*, GitRepo*/};
+use std::path::Path;
 
-pub fn hash_object(data: &str) -> String {
-    let blob = GitBlob::from_data(data);
-    blob.to_sha1()
+use crate::objects::{self, ObjectType};
+use crate::refs::GitRefs;
+use crate::revision;
+use crate::utils;
+use crate::{
+    CommitGraphOptions, GitAttributes, GitCommitGraph, GitError, GitIgnore, GitOdb, GitRepo,
+    GitResult,
+};
+
+pub fn hash_object(data: &[u8]) -> String {
+    objects::hash_object(ObjectType::Blob, data)
 }
 
 //pub fn cat_file(repo: &GitRepo, object: &str) -> String {}
+
+/// Prints the paths that are ignored, or with `verbose` the pattern deciding each path.
+///
+/// Returns true if any path was ignored.
+pub fn check_ignore(
+    repo: &GitRepo,
+    paths: &[String],
+    verbose: bool,
+    non_matching: bool,
+    quiet: bool,
+) -> GitResult<bool> {
+    let mut ignore = GitIgnore::new(repo)?;
+    let worktree = repo.worktree().unwrap_or_else(|| Path::new("."));
+    let mut any_ignored = false;
+
+    for path in paths {
+        let normalized = repo.worktree_path(path)?;
+        let is_dir = path.ends_with('/') || worktree.join(&normalized).is_dir();
+        let pattern = ignore.matching_pattern(&normalized, is_dir)?;
+        let ignored = pattern.as_ref().is_some_and(|p| !p.is_negative());
+        any_ignored |= ignored;
+        if quiet {
+            continue;
+        }
+
+        match (&pattern, verbose) {
+            (Some(pattern), true) => println!(
+                "{}:{}:{}\t{}",
+                pattern.source().map_or(String::new(), |s| {
+                    utils::quote_c_style(&s.display().to_string())
+                }),
+                pattern.line(),
+                pattern.pattern(),
+                utils::quote_c_style(path)
+            ),
+            (None, true) if non_matching => println!("::\t{}", utils::quote_c_style(path)),
+            _ if ignored => println!("{}", utils::quote_c_style(path)),
+            _ => {}
+        }
+    }
+
+    Ok(any_ignored)
+}
+
+/// Prints the gitattributes of each path, either the ones in `attrs` or every specified one.
+pub fn check_attr(repo: &GitRepo, attrs: &[String], paths: &[String], all: bool) -> GitResult<()> {
+    let mut attributes = GitAttributes::new(repo)?;
+    let names: Vec<&str> = attrs.iter().map(String::as_str).collect();
+
+    for path in paths {
+        let normalized = repo.worktree_path(path)?;
+        let values = if all {
+            attributes.check_all(&normalized)?
+        } else {
+            attributes.check(&normalized, &names)?
+        };
+        for warning in attributes.take_warnings() {
+            eprintln!("{}", warning);
+        }
+        for (name, value) in values {
+            println!("{}: {}: {}", utils::quote_c_style(path), name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Where `commit-graph write` takes the commits to write from, besides their ancestors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphSource {
+    /// The commits in all packfiles.
+    Packs,
+    /// The commits the refs point to.
+    Reachable,
+    /// The given commits, or the commits the given tags point to.
+    Commits(Vec<String>),
+}
+
+/// Writes a commit-graph holding the commits of `source` and their ancestors.
+pub fn commit_graph_write(
+    repo: &GitRepo,
+    source: GraphSource,
+    options: &CommitGraphOptions,
+) -> GitResult<()> {
+    let odb = GitOdb::open(repo)?;
+    let commits = match source {
+        GraphSource::Packs => odb
+            .packed_objects()
+            .into_iter()
+            .filter(|sha| odb.read_type(sha).ok() == Some(ObjectType::Commit))
+            .collect(),
+        // Refs to other objects than commits, like tags of trees, are skipped.
+        GraphSource::Reachable => GitRefs::new(repo)
+            .list("refs/")?
+            .into_iter()
+            .filter_map(|(_, sha)| revision::peel(&odb, &sha, ObjectType::Commit).ok())
+            .collect(),
+        GraphSource::Commits(shas) => shas
+            .iter()
+            .map(|sha| {
+                revision::peel(&odb, sha, ObjectType::Commit).map_err(|_| {
+                    GitError::CommitGraphError {
+                        path: odb.objects_dir().to_path_buf(),
+                        message: format!("invalid commit object id: {}", sha),
+                    }
+                })
+            })
+            .collect::<GitResult<Vec<String>>>()?,
+    };
+    crate::write_commit_graph(&odb, &commits, options)
+}
+
+/// Checks the commit-graph and prints the problems found.
+///
+/// Returns true if the commit-graph is valid or there is none.
+pub fn commit_graph_verify(repo: &GitRepo, shallow: bool) -> GitResult<bool> {
+    let odb = GitOdb::open(repo)?;
+    let graph = match GitCommitGraph::open(odb.objects_dir()) {
+        Ok(Some(graph)) => graph,
+        Ok(None) => return Ok(true),
+        Err(GitError::CommitGraphError { path: _, message }) => {
+            eprintln!("error: {}", message);
+            return Ok(false);
+        }
+        Err(error) => return Err(error),
+    };
+    let errors = graph.verify(&odb, shallow);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+    Ok(errors.is_empty())
+}
+
+/// What `merge-base` computes from its commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeBaseMode {
+    /// The best common ancestors of the first commit and all others.
+    Bases,
+    /// The best common ancestors of all commits together.
+    Octopus,
+    /// The commits not reachable from any other.
+    Independent,
+    /// Whether the first commit is an ancestor of the second.
+    IsAncestor,
+    /// The commit the second commit (or `HEAD`) forked from the ref given first.
+    ForkPoint,
+}
+
+/// Prints the merge bases of `revs` selected by `mode`, all of them with `all` and otherwise
+/// the newest one.
+///
+/// Returns false if there are none, or for [MergeBaseMode::IsAncestor] if the first commit is
+/// not an ancestor of the second.
+pub fn merge_base(
+    repo: &GitRepo,
+    mode: MergeBaseMode,
+    revs: &[String],
+    all: bool,
+) -> GitResult<bool> {
+    let odb = GitOdb::open(repo)?;
+    let refs = GitRefs::new(repo);
+    let commit = |rev: &str| -> GitResult<String> {
+        let invalid = |kind: &str| GitError::RevisionError {
+            rev: rev.to_string(),
+            message: format!("Not a valid {} name {}", kind, rev),
+        };
+        let sha = revision::rev_parse(&odb, &refs, rev).map_err(|_| invalid("object"))?;
+        revision::peel(&odb, &sha, ObjectType::Commit).map_err(|_| invalid("commit"))
+    };
+    let fatal = |message: &str| GitError::RevisionError {
+        rev: String::new(),
+        message: message.to_string(),
+    };
+
+    let bases = match mode {
+        MergeBaseMode::IsAncestor => {
+            if revs.len() != 2 {
+                return Err(fatal("--is-ancestor takes exactly two commits"));
+            }
+            return revision::is_ancestor(&odb, &commit(&revs[0])?, &commit(&revs[1])?);
+        }
+        MergeBaseMode::ForkPoint => {
+            if revs.is_empty() || revs.len() > 2 {
+                return Err(fatal("--fork-point takes a ref and at most one commit"));
+            }
+            let name = refs.dwim(&revs[0])?.ok_or_else(|| GitError::RefError {
+                name: revs[0].clone(),
+                message: format!("No such ref: '{}'", revs[0]),
+            })?;
+            let rev = revs.get(1).map_or("HEAD", String::as_str);
+            let derived =
+                commit(rev).map_err(|_| fatal(&format!("Not a valid object name: '{}'", rev)))?;
+            return match revision::fork_point(&odb, &refs, &name, &derived)? {
+                Some(sha) => {
+                    println!("{}", sha);
+                    Ok(true)
+                }
+                None => Ok(false),
+            };
+        }
+        MergeBaseMode::Bases => {
+            if revs.len() < 2 {
+                return Err(fatal("merge-base needs at least two commits"));
+            }
+            let commits = revs
+                .iter()
+                .map(|rev| commit(rev))
+                .collect::<GitResult<Vec<String>>>()?;
+            revision::merge_bases(&odb, &commits[0], &commits[1..])?
+        }
+        MergeBaseMode::Octopus | MergeBaseMode::Independent => {
+            let commits = revs
+                .iter()
+                .map(|rev| commit(rev))
+                .collect::<GitResult<Vec<String>>>()?;
+            if mode == MergeBaseMode::Octopus {
+                revision::octopus_merge_bases(&odb, &commits)?
+            } else {
+                revision::independent_commits(&odb, &commits)?
+            }
+        }
+    };
+
+    // The independent commits are always all printed.
+    let count = if all || mode == MergeBaseMode::Independent {
+        bases.len()
+    } else {
+        1
+    };
+    for sha in bases.iter().take(count) {
+        println!("{}", sha);
+    }
+    Ok(!bases.is_empty())
+}
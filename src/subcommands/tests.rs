@@ -0,0 +1,148 @@
+use super::branch_helper;
+use super::config_helper::{ConfigFailure, ConfigStatus};
+use crate::config;
+use crate::refs::GitRefs;
+use crate::test_utils;
+use crate::{self as gitrs, GitFileMode, GitOdb, GitResult, GitSignature};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[test]
+fn test_config_status() {
+    let failure = |message: &str| {
+        ConfigFailure::from(gitrs::GitError::ConfigError {
+            path: PathBuf::new(),
+            message: message.to_string(),
+        })
+    };
+
+    let fatal = failure("fatal: bad boolean config value 'maybe' for 'a.b'");
+    assert_eq!(fatal.status, ConfigStatus::Fatal);
+    assert_eq!(fatal.status.exit_code(), 128);
+    let error = failure("error: key does not contain a section: foo");
+    assert_eq!(error.status, ConfigStatus::Error);
+    assert_eq!(error.status.exit_code(), 1);
+    // Errors that are not about the config are fatal too, like in git.
+    let io = ConfigFailure::from(gitrs::GitError::IOError {
+        path: PathBuf::from("config"),
+        error: io::Error::from(io::ErrorKind::PermissionDenied),
+    });
+    assert_eq!(io.status, ConfigStatus::Fatal);
+
+    assert_eq!(ConfigStatus::Done.exit_code(), 0);
+    assert_eq!(ConfigStatus::InvalidFile.exit_code(), 3);
+    assert_eq!(ConfigStatus::Usage.exit_code(), 129);
+}
+
+/// Writes a commit touching a single file and returns its name.
+fn branch_commit(odb: &GitOdb, content: &str, parents: &[&str]) -> GitResult<String> {
+    let tree = test_utils::tree(odb, &[("file.txt", GitFileMode::regular(), content)])?;
+    let signature = GitSignature::new("A U Thor", "author@example.com", 1_000_000_000, 0);
+    test_utils::commit(odb, &tree, parents, signature.clone(), signature, content)
+}
+
+#[test]
+fn test_branch_delete() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_branch_delete")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = branch_commit(&odb, "first", &[])?;
+    let second = branch_commit(&odb, "second", &[&first])?;
+    let other = branch_commit(&odb, "other", &[])?;
+    refs.update_head(&first, "commit")?;
+    let delete = |name: &str, force: bool| {
+        branch_helper::delete(&repo, &[name.to_string()], force, false, true)
+    };
+
+    // Without an upstream, branches have to be merged into HEAD.
+    refs.update("refs/heads/merged", &first, "branch")?;
+    assert!(delete("merged", false)?);
+    assert!(!refs.exists("refs/heads/merged")?);
+    refs.update("refs/heads/topic", &second, "branch")?;
+    assert!(!delete("topic", false)?);
+    assert!(refs.exists("refs/heads/topic")?);
+
+    // With one, they have to be merged into the upstream instead.
+    refs.update("refs/remotes/origin/topic", &second, "fetch")?;
+    branch_helper::set_upstream_to(&repo, "origin/topic", Some("topic"), true)?;
+    assert!(delete("topic", false)?);
+    assert!(!refs.exists("refs/heads/topic")?);
+    let config_path = repo.gitpath().join("config");
+    assert_eq!(
+        config::read_value(&config_path, "branch.topic.merge")?,
+        None
+    );
+
+    refs.update("refs/heads/stale", &first, "branch")?;
+    refs.update("refs/remotes/origin/stale", &other, "fetch")?;
+    branch_helper::set_upstream_to(&repo, "origin/stale", Some("stale"), true)?;
+    assert!(!delete("stale", false)?);
+    assert!(delete("stale", true)?);
+    assert!(!refs.exists("refs/heads/stale")?);
+
+    // The current branch and missing branches are never deleted.
+    assert!(!delete("main", true)?);
+    assert!(!delete("missing", true)?);
+    assert!(refs.exists("refs/heads/main")?);
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
+
+#[test]
+fn test_branch_upstream() -> Result<(), Box<dyn Error>> {
+    let repo = test_utils::setup("gitrs_test_branch_upstream")?;
+    let odb = GitOdb::open(&repo)?;
+    let refs = GitRefs::new(&repo);
+    let first = branch_commit(&odb, "first", &[])?;
+    refs.update_head(&first, "commit")?;
+    refs.update("refs/heads/topic", &first, "branch")?;
+    refs.update("refs/remotes/origin/feature/x", &first, "fetch")?;
+    let config_path = repo.gitpath().join("config");
+    let value = |key: &str| config::read_value(&config_path, key);
+
+    // A remote-tracking branch sets the remote and the branch on it.
+    branch_helper::set_upstream_to(&repo, "origin/feature/x", None, true)?;
+    assert_eq!(value("branch.main.remote")?.as_deref(), Some("origin"));
+    assert_eq!(
+        value("branch.main.merge")?.as_deref(),
+        Some("refs/heads/feature/x")
+    );
+    assert_eq!(
+        branch_helper::upstream(&repo, "main")?.as_deref(),
+        Some("refs/remotes/origin/feature/x")
+    );
+
+    // A local branch is tracked through the remote ".".
+    branch_helper::set_upstream_to(&repo, "main", Some("topic"), true)?;
+    assert_eq!(value("branch.topic.remote")?.as_deref(), Some("."));
+    assert_eq!(
+        value("branch.topic.merge")?.as_deref(),
+        Some("refs/heads/main")
+    );
+    assert_eq!(
+        branch_helper::upstream(&repo, "topic")?.as_deref(),
+        Some("refs/heads/main")
+    );
+
+    assert!(branch_helper::set_upstream_to(&repo, "origin/missing", None, true).is_err());
+    assert!(branch_helper::set_upstream_to(&repo, "main", Some("missing"), true).is_err());
+
+    branch_helper::unset_upstream(&repo, Some("topic"))?;
+    assert_eq!(value("branch.topic.remote")?, None);
+    assert_eq!(value("branch.topic.merge")?, None);
+    assert_eq!(branch_helper::upstream(&repo, "topic")?, None);
+    assert!(branch_helper::unset_upstream(&repo, Some("topic")).is_err());
+
+    // Creating a branch from a remote-tracking branch sets it up as its upstream.
+    branch_helper::create(&repo, "feature", Some("origin/feature/x"), false, true)?;
+    assert_eq!(
+        branch_helper::upstream(&repo, "feature")?.as_deref(),
+        Some("refs/remotes/origin/feature/x")
+    );
+
+    fs::remove_dir_all(repo.worktree().unwrap())?;
+    Ok(())
+}
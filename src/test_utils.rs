@@ -0,0 +1,65 @@
+//! Fixtures shared by the unit tests.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+
+use crate::{
+    GitCommit, GitFileMode, GitOdb, GitRepo, GitResult, GitSignature, GitTree, GitTreeEntry,
+    ObjectType,
+};
+
+/// Makes an empty repository with a worktree named `name` in the temporary directory,
+/// replacing whatever an earlier run left there. `HEAD` is on the unborn branch `main`, and
+/// the config trusts file modes.
+pub(crate) fn setup(name: &str) -> Result<GitRepo, Box<dyn Error>> {
+    setup_with_config(name, "[core]\n\tfilemode = true\n")
+}
+
+/// Makes an empty repository like [setup], with `config` as its config file.
+pub(crate) fn setup_with_config(name: &str, config: &str) -> Result<GitRepo, Box<dyn Error>> {
+    let worktree = env::temp_dir().join(name);
+    let gitpath = worktree.join(".git");
+    let _ = fs::remove_dir_all(&worktree);
+    fs::create_dir_all(gitpath.join("objects"))?;
+    fs::create_dir_all(gitpath.join("refs/heads"))?;
+    fs::write(gitpath.join("HEAD"), "ref: refs/heads/main\n")?;
+    fs::write(gitpath.join("config"), config)?;
+    Ok(GitRepo::new(worktree, gitpath))
+}
+
+/// Writes a tree of `(path, mode, contents)` files and returns its name. Paths have at most
+/// one directory.
+pub(crate) fn tree(odb: &GitOdb, files: &[(&str, GitFileMode, &str)]) -> GitResult<String> {
+    let mut dirs: BTreeMap<String, Vec<GitTreeEntry>> = BTreeMap::new();
+    for (path, mode, contents) in files {
+        let sha = odb.write(ObjectType::Blob, contents.as_bytes())?;
+        let (dir, name) = match path.rfind('/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => ("", *path),
+        };
+        dirs.entry(dir.to_string())
+            .or_default()
+            .push(GitTreeEntry::new(*mode, name, &sha));
+    }
+    let mut root = dirs.remove("").unwrap_or_default();
+    for (dir, entries) in dirs {
+        let sha = odb.write_object(&GitTree::new(entries))?;
+        root.push(GitTreeEntry::new(GitFileMode::tree(), &dir, &sha));
+    }
+    odb.write_object(&GitTree::new(root))
+}
+
+/// Writes a commit of `tree` on top of `parents` and returns its name.
+pub(crate) fn commit(
+    odb: &GitOdb,
+    tree: &str,
+    parents: &[&str],
+    author: GitSignature,
+    committer: GitSignature,
+    message: &str,
+) -> GitResult<String> {
+    let parents: Vec<String> = parents.iter().map(|parent| parent.to_string()).collect();
+    odb.write_object(&GitCommit::new(tree, &parents, author, committer, message))
+}
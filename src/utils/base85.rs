@@ -0,0 +1,39 @@
+/// The digits of git's base85, in order, as used by binary patches.
+const DIGITS: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Encodes `data` as five digits for each four bytes, padding the last group with zeros.
+pub fn encode_base85(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(4) * 5);
+    for group in data.chunks(4) {
+        let mut bytes = [0; 4];
+        bytes[..group.len()].copy_from_slice(group);
+        let mut value = u32::from_be_bytes(bytes);
+        let mut digits = [0; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = DIGITS[(value % 85) as usize];
+            value /= 85;
+        }
+        out.extend(digits.iter().map(|&digit| digit as char));
+    }
+    out
+}
+
+/// Decodes `len` bytes from `digits`, five digits for each four bytes. Returns [None] unless
+/// there are exactly enough groups of valid digits.
+pub fn decode_base85(digits: &[u8], len: usize) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(5) || digits.len() / 5 != len.div_ceil(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(len);
+    for group in digits.chunks(5) {
+        let mut value: u32 = 0;
+        for &digit in group {
+            let digit = DIGITS.iter().position(|&ch| ch == digit)? as u32;
+            value = value.checked_mul(85)?.checked_add(digit)?;
+        }
+        let take = (len - out.len()).min(4);
+        out.extend_from_slice(&value.to_be_bytes()[..take]);
+    }
+    Some(out)
+}
@@ -0,0 +1,125 @@
+/// Resets all colors and attributes.
+pub const COLOR_RESET: &str = "\x1b[m";
+
+const ATTRIBUTES: [(&str, u8, u8); 7] = [
+    ("bold", 1, 22),
+    ("dim", 2, 22),
+    ("italic", 3, 23),
+    ("ul", 4, 24),
+    ("blink", 5, 25),
+    ("reverse", 7, 27),
+    ("strike", 9, 29),
+];
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Unset,
+    Normal,
+    Default,
+    /// One of the 8 basic colors, plus 60 for the bright variants.
+    Ansi(u8),
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn parse(word: &str) -> Option<Self> {
+        let lower = word.to_ascii_lowercase();
+        if lower == "normal" {
+            return Some(Color::Normal);
+        }
+        if lower == "default" {
+            return Some(Color::Default);
+        }
+        if let Some(hex) = word.strip_prefix('#') {
+            if hex.len() == 6 && hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+                return Some(Color::Rgb(byte(0), byte(2), byte(4)));
+            }
+            return None;
+        }
+        let (name, bright) = match lower.strip_prefix("bright") {
+            Some(name) => (name, 60),
+            None => (lower.as_str(), 0),
+        };
+        if let Some(i) = COLOR_NAMES.iter().position(|color| *color == name) {
+            return Some(Color::Ansi(i as u8 + bright));
+        }
+        match word.parse::<i32>().ok()? {
+            -1 => Some(Color::Normal),
+            n @ 0..=7 => Some(Color::Ansi(n as u8)),
+            n @ 8..=15 => Some(Color::Ansi(n as u8 - 8 + 60)),
+            n @ 16..=255 => Some(Color::Ansi256(n as u8)),
+            _ => None,
+        }
+    }
+
+    /// Appends the SGR parameters selecting this color; `base` is 30 for the foreground and
+    /// 40 for the background.
+    fn push_codes(self, base: u8, codes: &mut Vec<String>) {
+        match self {
+            Color::Unset | Color::Normal => {}
+            Color::Default => codes.push((base + 9).to_string()),
+            Color::Ansi(n) => codes.push((base + n).to_string()),
+            Color::Ansi256(n) => codes.push(format!("{};5;{}", base + 8, n)),
+            Color::Rgb(r, g, b) => codes.push(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        }
+    }
+}
+
+/// Parses a color setting like `red bold`, `#ff0000 ul` or `reset` the way git does, and
+/// returns its ANSI escape sequence.
+///
+/// The first color is the foreground and the second the background. Attributes can be
+/// turned off with a `no` or `no-` prefix. Returns [None] if the value is not a valid color.
+pub fn parse_color(value: &str) -> Option<String> {
+    if value.trim().eq_ignore_ascii_case("reset") {
+        return Some(COLOR_RESET.to_string());
+    }
+
+    let mut foreground = Color::Unset;
+    let mut background = Color::Unset;
+    let mut attributes = Vec::new();
+    for word in value.split_ascii_whitespace() {
+        if let Some(color) = Color::parse(word) {
+            if foreground == Color::Unset {
+                foreground = color;
+            } else if background == Color::Unset {
+                background = color;
+            } else {
+                return None;
+            }
+            continue;
+        }
+        let lower = word.to_ascii_lowercase();
+        let (name, negate) = match lower.strip_prefix("no") {
+            Some(name) => (name.strip_prefix('-').unwrap_or(name), true),
+            None => (lower.as_str(), false),
+        };
+        let i = ATTRIBUTES.iter().position(|(attr, _, _)| *attr == name)?;
+        // Like git, attributes are written in table order, with all negations last.
+        let bit = if negate { i + ATTRIBUTES.len() } else { i };
+        if !attributes.contains(&bit) {
+            attributes.push(bit);
+        }
+    }
+    attributes.sort_unstable();
+
+    let mut codes: Vec<String> = attributes
+        .iter()
+        .map(|&bit| match ATTRIBUTES.get(bit) {
+            Some((_, on, _)) => on.to_string(),
+            None => ATTRIBUTES[bit - ATTRIBUTES.len()].2.to_string(),
+        })
+        .collect();
+    foreground.push_codes(30, &mut codes);
+    background.push_codes(40, &mut codes);
+    if codes.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
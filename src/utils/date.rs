@@ -0,0 +1,817 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{GitError, GitResult};
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// How dates are shown, like `--date=<format>`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DateFormat {
+    /// `Thu Oct 17 12:00:00 2024 +0200`
+    #[default]
+    Default,
+    /// `2 hours ago`
+    Relative,
+    /// `2024-10-17 12:00:00 +0200`
+    Iso,
+    /// `2024-10-17T12:00:00+02:00`
+    IsoStrict,
+    /// `Thu, 17 Oct 2024 12:00:00 +0200`
+    Rfc,
+    /// `2024-10-17`
+    Short,
+    /// `1729159200 +0200`
+    Raw,
+    /// `1729159200`
+    Unix,
+    /// A `strftime` format.
+    Format(String),
+}
+
+impl FromStr for DateFormat {
+    type Err = GitError;
+
+    fn from_str(s: &str) -> GitResult<Self> {
+        if let Some(format) = s.strip_prefix("format:") {
+            return Ok(DateFormat::Format(format.to_string()));
+        }
+        match s {
+            "default" => Ok(DateFormat::Default),
+            "relative" => Ok(DateFormat::Relative),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc),
+            "short" => Ok(DateFormat::Short),
+            "raw" => Ok(DateFormat::Raw),
+            "unix" => Ok(DateFormat::Unix),
+            _ => Err(GitError::LogError {
+                message: format!("unknown date format {}", s),
+            }),
+        }
+    }
+}
+
+/// The broken down time of a date in its own timezone.
+struct Tm {
+    year: i64,
+    month: usize,
+    day: u32,
+    weekday: usize,
+    yearday: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl Tm {
+    fn new(time: i64, offset: i32) -> Self {
+        let local = time + i64::from(offset) * 60;
+        let days = local.div_euclid(86400);
+        let seconds = local.rem_euclid(86400) as u32;
+
+        // Converts days since the epoch to a civil date (Howard Hinnant's algorithm).
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as usize;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+        let before = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334][month - 1];
+        let yearday = before + day + if leap && month > 2 { 1 } else { 0 };
+
+        Self {
+            year,
+            month,
+            day,
+            weekday: (days + 4).rem_euclid(7) as usize,
+            yearday,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+}
+
+/// Formats `time` (seconds since the epoch) in the timezone `offset` (minutes east of UTC)
+/// like git does.
+pub fn format_date(time: i64, offset: i32, format: &DateFormat) -> String {
+    let tm = Tm::new(time, offset);
+    let tz = format_offset(offset);
+    match format {
+        DateFormat::Default => format!(
+            "{} {} {} {:02}:{:02}:{:02} {} {}",
+            &WEEKDAYS[tm.weekday][..3],
+            &MONTHS[tm.month - 1][..3],
+            tm.day,
+            tm.hour,
+            tm.minute,
+            tm.second,
+            tm.year,
+            tz
+        ),
+        DateFormat::Relative => relative_date(time, now()),
+        DateFormat::Iso => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            tm.year, tm.month, tm.day, tm.hour, tm.minute, tm.second, tz
+        ),
+        DateFormat::IsoStrict => format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}:{}",
+            tm.year,
+            tm.month,
+            tm.day,
+            tm.hour,
+            tm.minute,
+            tm.second,
+            &tz[..3],
+            &tz[3..]
+        ),
+        DateFormat::Rfc => format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} {}",
+            &WEEKDAYS[tm.weekday][..3],
+            tm.day,
+            &MONTHS[tm.month - 1][..3],
+            tm.year,
+            tm.hour,
+            tm.minute,
+            tm.second,
+            tz
+        ),
+        DateFormat::Short => format!("{:04}-{:02}-{:02}", tm.year, tm.month, tm.day),
+        DateFormat::Raw => format!("{} {}", time, tz),
+        DateFormat::Unix => time.to_string(),
+        DateFormat::Format(format) => strftime(format, &tm, time, offset),
+    }
+}
+
+/// Describes how long before `now` the date `time` was, like `3 days ago`.
+pub fn relative_date(time: i64, now: i64) -> String {
+    if time > now {
+        return "in the future".to_string();
+    }
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("{} {}", n, unit)
+        } else {
+            format!("{} {}s", n, unit)
+        }
+    };
+    let diff = now - time;
+    if diff < 90 {
+        return format!("{} ago", plural(diff, "second"));
+    }
+    let minutes = (diff + 30) / 60;
+    if minutes < 90 {
+        return format!("{} ago", plural(minutes, "minute"));
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return format!("{} ago", plural(hours, "hour"));
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return format!("{} ago", plural(days, "day"));
+    }
+    if days < 70 {
+        return format!("{} ago", plural((days + 3) / 7, "week"));
+    }
+    if days < 365 {
+        return format!("{} ago", plural((days + 15) / 30, "month"));
+    }
+    if days < 1825 {
+        let total_months = (days * 12 * 2 + 365) / (365 * 2);
+        let (years, months) = (total_months / 12, total_months % 12);
+        if months > 0 {
+            return format!("{}, {} ago", plural(years, "year"), plural(months, "month"));
+        }
+        return format!("{} ago", plural(years, "year"));
+    }
+    format!("{} ago", plural((days + 183) / 365, "year"))
+}
+
+/// Returns the current time in seconds since the epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// Formats a timezone offset in minutes as `+hhmm`.
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}{:02}", sign, offset / 60, offset % 60)
+}
+
+/// Parses a date the way git's `approxidate` does for options like `--since`.
+///
+/// Exact dates like `2024-10-17 12:00:00 +0200` or `@1729159200` are tried first; otherwise
+/// the date is approximated from `now` with phrases like `2 weeks ago`, `yesterday`, `noon`,
+/// `last friday` or `Oct 17`. Like git, words that are not understood are ignored. Dates
+/// without a timezone are taken as UTC.
+pub fn approxidate(date: &str, now: i64) -> i64 {
+    if let Some((time, _)) = parse_exact_date(date, now) {
+        return time;
+    }
+
+    let mut tm = DateParts::at(now);
+    let current = DateParts::at(now);
+    tm.year = None;
+    tm.month = None;
+    tm.day = None;
+    let bytes = date.as_bytes();
+    let mut number = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_digit() {
+            tm.pending_number(&mut number);
+            let (value, end) = parse_number(bytes, i);
+            if let Some(len) = match_multi_number(value, bytes, i, end, &mut tm, now) {
+                i += len;
+                continue;
+            }
+            // Zero-padding is only accepted for small numbers, like in `Dec 02`.
+            if bytes[i] != b'0' || end - i <= 2 {
+                number = value;
+            }
+            i = end;
+        } else if bytes[i].is_ascii_alphabetic() {
+            i = approxidate_alpha(bytes, i, &mut tm, &current, &mut number);
+        } else {
+            i += 1;
+        }
+    }
+    tm.pending_number(&mut number);
+    tm.update(&current, 0)
+}
+
+/// The fields of a date being parsed, unset until given; the month counts from 0.
+#[derive(Debug, Clone, Default)]
+struct DateParts {
+    year: Option<i64>,
+    month: Option<i64>,
+    day: Option<i64>,
+    hour: Option<i64>,
+    minute: Option<i64>,
+    second: Option<i64>,
+    weekday: i64,
+}
+
+impl DateParts {
+    fn at(time: i64) -> Self {
+        let tm = Tm::new(time, 0);
+        Self {
+            year: Some(tm.year),
+            month: Some(tm.month as i64 - 1),
+            day: Some(i64::from(tm.day)),
+            hour: Some(i64::from(tm.hour)),
+            minute: Some(i64::from(tm.minute)),
+            second: Some(i64::from(tm.second)),
+            weekday: tm.weekday as i64,
+        }
+    }
+
+    /// Returns the time of a fully given date, normalizing days past the end of the month.
+    fn to_time(&self) -> Option<i64> {
+        let year = self.year.filter(|year| (1970..2100).contains(year))?;
+        let month = self.month.filter(|month| (0..12).contains(month))?;
+        let days = days_from_civil(year, month + 1) + self.day? - 1;
+        Some(days * 86400 + self.hour? * 3600 + self.minute? * 60 + self.second?)
+    }
+
+    /// Fills the unset date fields from `now`, moves the date `seconds` back and sets all
+    /// fields from the result, like git's `update_tm`.
+    fn update(&mut self, now: &DateParts, seconds: i64) -> i64 {
+        let month = *self.month.get_or_insert(now.month.unwrap_or(0));
+        let day = *self.day.get_or_insert(now.day.unwrap_or(1));
+        let year = *self.year.get_or_insert_with(|| {
+            let year = now.year.unwrap_or(1970);
+            if Some(month) > now.month {
+                year - 1
+            } else {
+                year
+            }
+        });
+        let months = year * 12 + month;
+        let days = days_from_civil(months.div_euclid(12), months.rem_euclid(12) + 1) + day - 1;
+        let time = days * 86400
+            + self.hour.unwrap_or(0) * 3600
+            + self.minute.unwrap_or(0) * 60
+            + self.second.unwrap_or(0)
+            - seconds;
+        *self = Self::at(time);
+        time
+    }
+
+    /// Uses a number that no unit followed as the day, month or year, whichever is unset.
+    fn pending_number(&mut self, number: &mut i64) {
+        let n = *number;
+        if n == 0 {
+            return;
+        }
+        *number = 0;
+        if self.day.is_none() && n < 32 {
+            self.day = Some(n);
+        } else if self.month.is_none() && n < 13 {
+            self.month = Some(n - 1);
+        } else if self.year.is_none() {
+            if n > 1969 && n < 2100 {
+                self.year = Some(n);
+            } else if n > 69 && n < 100 {
+                self.year = Some(1900 + n);
+            } else if n < 38 {
+                self.year = Some(2000 + n);
+            }
+        }
+    }
+
+    /// Sets the date if it is valid and, with `refuse_future`, not more than ten days after
+    /// `now`.
+    fn set_date(
+        &mut self,
+        year: Option<i64>,
+        month: i64,
+        day: i64,
+        refuse_future: bool,
+        now: i64,
+    ) -> bool {
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return false;
+        }
+        let year = match year {
+            None if refuse_future => Tm::new(now, 0).year,
+            None => return false,
+            Some(year) if (1970..2100).contains(&year) => year,
+            Some(year) if year > 70 && year < 100 => 1900 + year,
+            Some(year) if year < 38 => 2000 + year,
+            Some(_) => return false,
+        };
+        let check = Self {
+            year: Some(year),
+            month: Some(month - 1),
+            day: Some(day),
+            ..self.clone()
+        };
+        if refuse_future && check.to_time().is_some_and(|time| time > now + 10 * 86400) {
+            return false;
+        }
+        *self = check;
+        true
+    }
+}
+
+/// Returns the days from the epoch to the first of `month` (1 to 12) in `year`.
+fn days_from_civil(year: i64, month: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * ((month + 9) % 12) + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Parses the digits starting at `start`, returning the number and where it ends.
+fn parse_number(bytes: &[u8], start: usize) -> (i64, usize) {
+    let mut end = start;
+    let mut value: i64 = 0;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        value = value
+            .saturating_mul(10)
+            .saturating_add(i64::from(bytes[end] - b'0'));
+        end += 1;
+    }
+    (value, end)
+}
+
+/// Returns how many characters of the word at the start of `date` match the start of `name`,
+/// ignoring case, or 0 if the word goes on where they differ.
+fn match_string(date: &[u8], name: &str) -> usize {
+    let name = name.as_bytes();
+    for (i, &byte) in date.iter().enumerate() {
+        if name.get(i).is_some_and(|ch| ch.eq_ignore_ascii_case(&byte)) {
+            continue;
+        }
+        return if byte.is_ascii_alphanumeric() { 0 } else { i };
+    }
+    date.len()
+}
+
+/// Parses `<num><sep><num>[<sep><num>]` at `start` as a time or a date, where the first
+/// number ends at `end`, returning its length.
+fn match_multi_number(
+    first: i64,
+    bytes: &[u8],
+    start: usize,
+    end: usize,
+    tm: &mut DateParts,
+    now: i64,
+) -> Option<usize> {
+    let separator = *bytes.get(end)?;
+    if !b":-/.".contains(&separator) || !bytes.get(end + 1)?.is_ascii_digit() {
+        return None;
+    }
+    let (second, mut end) = parse_number(bytes, end + 1);
+    let mut third = None;
+    if bytes.get(end) == Some(&separator) && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        let (value, next) = parse_number(bytes, end + 1);
+        third = Some(value);
+        end = next;
+    }
+    if separator == b':' {
+        let seconds = third.unwrap_or(0);
+        if first >= 25 || second >= 60 || seconds > 60 {
+            return None;
+        }
+        tm.hour = Some(first);
+        tm.minute = Some(second);
+        tm.second = Some(seconds);
+        return Some(end - start);
+    }
+    let matched = (first > 70
+        && (tm.set_date(Some(first), second, third.unwrap_or(-1), false, now)
+            || tm.set_date(Some(first), third.unwrap_or(-1), second, false, now)))
+        // `dd.mm.yy` is the norm in parts of Europe, otherwise `mm/dd/yy` comes first.
+        || (separator == b'.' && tm.set_date(third, second, first, true, now))
+        || tm.set_date(third, first, second, true, now)
+        || tm.set_date(third, second, first, true, now);
+    if matched {
+        Some(end - start)
+    } else {
+        None
+    }
+}
+
+const NUMBER_NAMES: [&str; 11] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+];
+
+const UNITS: [(&str, i64); 5] = [
+    ("seconds", 1),
+    ("minutes", 60),
+    ("hours", 3600),
+    ("days", 86400),
+    ("weeks", 7 * 86400),
+];
+
+/// Interprets the word at `start` of an approximate date, returning where it ends.
+fn approxidate_alpha(
+    bytes: &[u8],
+    start: usize,
+    tm: &mut DateParts,
+    now: &DateParts,
+    number: &mut i64,
+) -> usize {
+    let date = &bytes[start..];
+    let end = start
+        + date
+            .iter()
+            .take_while(|byte| byte.is_ascii_alphabetic())
+            .count();
+
+    if MONTHS.iter().any(|month| match_string(date, month) >= 3) {
+        let month = MONTHS
+            .iter()
+            .position(|month| match_string(date, month) >= 3)
+            .unwrap();
+        tm.month = Some(month as i64);
+        return end;
+    }
+
+    let special = [
+        "yesterday",
+        "noon",
+        "midnight",
+        "tea",
+        "pm",
+        "am",
+        "never",
+        "now",
+    ];
+    if let Some(&word) = special
+        .iter()
+        .find(|word| match_string(date, word) == word.len())
+    {
+        let n = *number;
+        *number = 0;
+        let at_hour = |tm: &mut DateParts, hour: i64| {
+            if tm.hour.unwrap_or(0) < hour {
+                tm.update(now, 86400);
+            }
+            tm.hour = Some(hour);
+            tm.minute = Some(0);
+            tm.second = Some(0);
+        };
+        match word {
+            "yesterday" => {
+                tm.update(now, 86400);
+            }
+            "noon" => at_hour(tm, 12),
+            "midnight" => at_hour(tm, 0),
+            "tea" => at_hour(tm, 17),
+            "pm" | "am" => {
+                let mut hour = tm.hour.unwrap_or(0);
+                if n != 0 {
+                    hour = n;
+                    tm.minute = Some(0);
+                    tm.second = Some(0);
+                }
+                tm.hour = Some(hour % 12 + if word == "pm" { 12 } else { 0 });
+            }
+            "never" => *tm = DateParts::at(0),
+            _ => {
+                tm.update(now, 0);
+            }
+        }
+        return end;
+    }
+
+    if *number == 0 {
+        if let Some(n) = (1..NUMBER_NAMES.len())
+            .find(|&n| match_string(date, NUMBER_NAMES[n]) == NUMBER_NAMES[n].len())
+        {
+            *number = n as i64;
+        } else if match_string(date, "last") == 4 {
+            *number = 1;
+        }
+        return end;
+    }
+
+    if let Some(&(_, seconds)) = UNITS
+        .iter()
+        .find(|(unit, _)| match_string(date, unit) >= unit.len() - 1)
+    {
+        tm.update(now, seconds * *number);
+        *number = 0;
+        return end;
+    }
+
+    if let Some(weekday) = WEEKDAYS
+        .iter()
+        .position(|weekday| match_string(date, &format!("{}s", weekday)) >= 3)
+    {
+        let mut weeks = *number - 1;
+        *number = 0;
+        let mut diff = tm.weekday - weekday as i64;
+        if diff <= 0 {
+            weeks += 1;
+        }
+        diff += 7 * weeks;
+        tm.update(now, diff * 86400);
+        return end;
+    }
+
+    if match_string(date, "months") >= 5 {
+        tm.update(now, 0);
+        let months = tm.year.unwrap_or(0) * 12 + tm.month.unwrap_or(0) - *number;
+        *number = 0;
+        tm.year = Some(months.div_euclid(12));
+        tm.month = Some(months.rem_euclid(12));
+    } else if match_string(date, "years") >= 4 {
+        tm.update(now, 0);
+        tm.year = tm.year.map(|year| year - *number);
+        *number = 0;
+    }
+    end
+}
+
+const TIMEZONES: [(&str, i64); 16] = [
+    ("utc", 0),
+    ("gmt", 0),
+    ("ut", 0),
+    ("z", 0),
+    ("cet", 60),
+    ("cest", 120),
+    ("eet", 120),
+    ("eest", 180),
+    ("est", -300),
+    ("edt", -240),
+    ("cst", -360),
+    ("cdt", -300),
+    ("mst", -420),
+    ("mdt", -360),
+    ("pst", -480),
+    ("pdt", -420),
+];
+
+/// Parses a fully specified date like `Thu, 7 Apr 2005 15:13:13 -0700`, as in the `Date`
+/// header of a mail, returning the seconds since the epoch and the timezone offset in
+/// minutes.
+pub fn parse_date(date: &str) -> Option<(i64, i32)> {
+    let (time, offset) = parse_exact_date(date, now())?;
+    Some((time, offset.unwrap_or(0) as i32))
+}
+
+/// Parses a fully specified date like git's `parse_date_basic`, returning `None` unless it
+/// has a year, month, day and time, and the timezone if given.
+fn parse_exact_date(date: &str, now: i64) -> Option<(i64, Option<i64>)> {
+    let bytes = date.as_bytes();
+    let mut tm = DateParts::default();
+    let mut offset = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        let next_is_digit = rest.get(1).is_some_and(u8::is_ascii_digit);
+        let matched = if rest[0].is_ascii_alphabetic() {
+            exact_alpha(rest, &mut tm, &mut offset)
+        } else if rest[0].is_ascii_digit() {
+            let (value, end) = parse_number(bytes, i);
+            let digits = end - i;
+            // More than 8 digits are seconds since the epoch, fewer may be `YYYYMMDD`.
+            if value >= 100_000_000 && tm.year.is_none() && tm.month.is_none() && tm.day.is_none() {
+                return Some((value, None));
+            }
+            if let Some(len) = match_multi_number(value, bytes, i, end, &mut tm, now) {
+                len
+            } else {
+                exact_number(value, digits, &mut tm, &mut offset, now);
+                digits
+            }
+        } else if (rest[0] == b'+' || rest[0] == b'-') && next_is_digit {
+            match_tz(rest, &mut offset)
+        } else {
+            0
+        };
+        i += matched.max(1);
+    }
+    Some((tm.to_time()? - offset.unwrap_or(0) * 60, offset))
+}
+
+/// Interprets a word of an exact date, returning its length.
+fn exact_alpha(date: &[u8], tm: &mut DateParts, offset: &mut Option<i64>) -> usize {
+    let len = date
+        .iter()
+        .take_while(|byte| byte.is_ascii_alphabetic())
+        .count();
+    if let Some(month) = MONTHS
+        .iter()
+        .position(|month| match_string(date, month) >= 3)
+    {
+        tm.month = Some(month as i64);
+    } else if let Some(weekday) = WEEKDAYS
+        .iter()
+        .position(|weekday| match_string(date, weekday) >= 3)
+    {
+        tm.weekday = weekday as i64;
+    } else if let Some(&(_, minutes)) = TIMEZONES
+        .iter()
+        .find(|(name, _)| match_string(date, name) == name.len())
+    {
+        *offset = Some(minutes);
+    } else if match_string(date, "pm") == 2 {
+        tm.hour = Some(tm.hour.unwrap_or(0) % 12 + 12);
+    } else if match_string(date, "am") == 2 {
+        tm.hour = Some(tm.hour.unwrap_or(0) % 12);
+    } else if date[0] == b'T' && date.get(1).is_some_and(u8::is_ascii_digit) {
+        // The time of an ISO 8601 date like `2024-10-17T12:00:00`.
+        tm.minute = Some(0);
+        tm.second = Some(0);
+        return 1;
+    }
+    len
+}
+
+/// Guesses what a number of an exact date means from its value and number of digits.
+fn exact_number(value: i64, digits: usize, tm: &mut DateParts, offset: &mut Option<i64>, now: i64) {
+    match digits {
+        8 => {
+            tm.set_date(
+                Some(value / 10000),
+                value / 100 % 100,
+                value % 100,
+                false,
+                now,
+            );
+        }
+        6 => {
+            let (hour, minute, second) = (value / 10000, value / 100 % 100, value % 100);
+            if hour <= 24 && minute < 60 && second <= 60 {
+                tm.hour = Some(hour);
+                tm.minute = Some(minute);
+                tm.second = Some(second);
+            }
+        }
+        4 => {
+            if value <= 1400 && offset.is_none() {
+                *offset = Some(value / 100 * 60 + value % 100);
+            } else if value > 1900 && value < 2100 {
+                tm.year = Some(value);
+            }
+        }
+        1 | 2 => {
+            if value > 0 && value < 32 && tm.day.is_none() {
+                tm.day = Some(value);
+            } else if digits == 2 && tm.year.is_none() && value < 10 && tm.day.is_some() {
+                tm.year = Some(2000 + value);
+            } else if digits == 2 && tm.year.is_none() && value >= 70 {
+                tm.year = Some(1900 + value);
+            } else if value > 0 && value < 13 && tm.month.is_none() {
+                tm.month = Some(value - 1);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parses a timezone like `+0200`, `-07:30` or `+02`, returning its length.
+fn match_tz(date: &[u8], offset: &mut Option<i64>) -> usize {
+    let (mut hour, mut end) = parse_number(date, 1);
+    let mut minute = 0;
+    match end - 1 {
+        4 => {
+            minute = hour % 100;
+            hour /= 100;
+        }
+        2 if date.get(end) == Some(&b':') => {
+            let (value, next) = parse_number(date, end + 1);
+            minute = if next - 1 == 5 { value } else { 99 };
+            end = next;
+        }
+        2 => {}
+        _ => minute = 99,
+    }
+    if minute < 60 && hour < 24 {
+        let minutes = hour * 60 + minute;
+        *offset = Some(if date[0] == b'-' { -minutes } else { minutes });
+    }
+    end
+}
+
+/// Expands the `strftime` conversions git users rely on; others are kept as written.
+fn strftime(format: &str, tm: &Tm, time: i64, offset: i32) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+        let conversion = match chars.next() {
+            Some(conversion) => conversion,
+            None => {
+                out.push('%');
+                break;
+            }
+        };
+        match conversion {
+            'a' => out.push_str(&WEEKDAYS[tm.weekday][..3]),
+            'A' => out.push_str(WEEKDAYS[tm.weekday]),
+            'b' | 'h' => out.push_str(&MONTHS[tm.month - 1][..3]),
+            'B' => out.push_str(MONTHS[tm.month - 1]),
+            'd' => out.push_str(&format!("{:02}", tm.day)),
+            'e' => out.push_str(&format!("{:2}", tm.day)),
+            'F' => out.push_str(&format!("{:04}-{:02}-{:02}", tm.year, tm.month, tm.day)),
+            'H' => out.push_str(&format!("{:02}", tm.hour)),
+            'I' => out.push_str(&format!("{:02}", (tm.hour + 11) % 12 + 1)),
+            'j' => out.push_str(&format!("{:03}", tm.yearday)),
+            'm' => out.push_str(&format!("{:02}", tm.month)),
+            'M' => out.push_str(&format!("{:02}", tm.minute)),
+            'p' => out.push_str(if tm.hour < 12 { "AM" } else { "PM" }),
+            'R' => out.push_str(&format!("{:02}:{:02}", tm.hour, tm.minute)),
+            's' => out.push_str(&time.to_string()),
+            'S' => out.push_str(&format!("{:02}", tm.second)),
+            'T' => out.push_str(&format!("{:02}:{:02}:{:02}", tm.hour, tm.minute, tm.second)),
+            'u' => out.push_str(&((tm.weekday + 6) % 7 + 1).to_string()),
+            'w' => out.push_str(&tm.weekday.to_string()),
+            'y' => out.push_str(&format!("{:02}", tm.year % 100)),
+            'Y' => out.push_str(&tm.year.to_string()),
+            'z' => out.push_str(&format_offset(offset)),
+            'Z' => {}
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            '%' => out.push('%'),
+            other => {
+                out.push('%');
+                out.push(other);
+            }
+        }
+    }
+    out
+}
@@ -14,7 +14,7 @@ use crate::{self as gitrs, GitResult};
 ///
 /// * NotFound: One of the directory components of the directory path does not exist.
 /// * PermissionDenied: The user lacks permission to get the specified access rights for the
-/// directory.
+///   directory.
 /// * Other: One of the directory components of the specified directory path was not, in fact, a directory.
 #[inline]
 pub fn create_dir_if_new<P: AsRef<Path>>(path: P) -> GitResult<()> {
@@ -34,7 +34,7 @@ pub fn create_dir_if_new<P: AsRef<Path>>(path: P) -> GitResult<()> {
 ///
 /// * NotFound: One of the directory components of the directory path does not exist.
 /// * PermissionDenied: The user lacks permission to get the specified access rights for the
-/// directory.
+///   directory.
 /// * Other: One of the directory components of the specified directory path was not, in fact, a directory.
 #[inline]
 pub fn create_dir_all_if_new<P: AsRef<Path>>(path: P) -> GitResult<()> {
@@ -54,6 +54,18 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> GitResult<String> {
     Ok(data)
 }
 
+/// Shorthand for reading the raw bytes of a file.
+///
+/// # Errors
+///
+/// This functions returns some [io::Error]s from [OpenOptions::open] wrapped in a [GitError::IOError].
+pub fn read_bytes<P: AsRef<Path>>(path: P) -> GitResult<Vec<u8>> {
+    let mut file = gitrs::to_git_result(OpenOptions::new().read(true).open(&path), &path)?;
+    let mut data = Vec::new();
+    gitrs::to_git_result(file.read_to_end(&mut data), path)?;
+    Ok(data)
+}
+
 /// Shorthand for creating a new file and writing a buffer into it.
 ///
 /// Returns [Ok] if the file already exists.
@@ -65,11 +77,11 @@ pub fn read_file<P: AsRef<Path>>(path: P) -> GitResult<String> {
 /// * NotFound: One of the directory components of the file path does not exist.
 /// * PermissionDenied: The user lacks permission to get the specified access rights for the file.
 /// * PermissionDenied: The user lacks permission to open one of the directory components of the
-/// specified path.
+///   specified path.
 /// * Other: One of the directory components of the specified file path was not, in fact, a directory.
 /// * Other: Filesystem-level errors: full disk, write permission requested on a read-only file
-/// system, exceeded disk quota, too many open files, too long filename, too many symbolic links
-/// in the specified path (Unix-like systems only), etc.
+///   system, exceeded disk quota, too many open files, too long filename, too many symbolic links
+///   in the specified path (Unix-like systems only), etc.
 #[inline]
 pub fn write_if_new<P: AsRef<Path>>(path: P, buf: &[u8]) -> GitResult<()> {
     gitrs::to_git_result(consume_already_exists(write_new(&path, buf)), path)
@@ -0,0 +1,93 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{self as gitrs, GitError, GitResult};
+
+/// An exclusive `<path>.lock` file used to replace a file atomically.
+///
+/// The new contents are written to the lock file, which is renamed over the original file by
+/// [LockFile::commit]. Dropping an uncommitted lock removes it and leaves the original untouched.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    /// Creates the lock file for `path`.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::LockError]: The lock file already exists
+    /// * [GitError::IOError]: The lock file can not be created
+    pub fn acquire<P: AsRef<Path>>(path: P) -> GitResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                return Err(GitError::LockError { path: lock_path });
+            }
+            Err(error) => {
+                return Err(GitError::IOError {
+                    error,
+                    path: lock_path,
+                })
+            }
+        };
+
+        Ok(Self {
+            path,
+            lock_path,
+            file: Some(file),
+        })
+    }
+
+    /// Appends `data` to the new contents.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The lock file can not be written
+    pub fn write_all(&mut self, data: &[u8]) -> GitResult<()> {
+        let file = self.file.as_mut().unwrap();
+        gitrs::to_git_result(file.write_all(data), &self.lock_path)
+    }
+
+    /// Replaces the original file with the lock file.
+    ///
+    /// # Errors
+    ///
+    /// * [GitError::IOError]: The lock file can not be renamed
+    pub fn commit(mut self) -> GitResult<()> {
+        drop(self.file.take());
+        gitrs::to_git_result(fs::rename(&self.lock_path, &self.path), &self.path)
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Atomically replaces the contents of `path` with `data` through a lock file.
+///
+/// # Errors
+///
+/// See [LockFile::acquire], [LockFile::write_all] and [LockFile::commit].
+pub fn write_locked<P: AsRef<Path>>(path: P, data: &[u8]) -> GitResult<()> {
+    let mut lock = LockFile::acquire(path)?;
+    lock.write_all(data)?;
+    lock.commit()
+}
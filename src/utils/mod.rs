@@ -1,6 +1,20 @@
+mod base85;
+mod color;
+mod date;
 mod fileio;
+mod lockfile;
 mod paths;
+pub mod pktline;
+mod quote;
 #[cfg(test)]
 mod tests;
+mod wildmatch;
 
-pub use fileio::{create_dir_all_if_new, create_dir_if_new, read_file, write_if_new};
+pub use base85::{decode_base85, encode_base85};
+pub use color::{parse_color, COLOR_RESET};
+pub use date::{approxidate, format_date, format_offset, now, parse_date, DateFormat};
+pub use fileio::{create_dir_all_if_new, create_dir_if_new, read_bytes, read_file, write_if_new};
+pub use lockfile::{write_locked, LockFile};
+pub use paths::{expand_home, get_object_path, xdg_config_path};
+pub use quote::{quote_c_style, unquote_c_style};
+pub use wildmatch::{wildmatch, WM_CASEFOLD, WM_PATHNAME};
@@ -1,12 +1,25 @@
-use std::path::PathBuf;
+use std::env;
+use std::path::{Path, PathBuf};
 
-use crate::GitRepo;
-
-/// Returns the path to an object from it's Sha1 hash.
+/// Returns the path to a loose object in `objects_dir` from it's Sha1 hash.
 #[inline]
-pub fn get_object_path(repo: &GitRepo, sha: &str) -> PathBuf {
-    repo.gitpath()
-        .join("objects")
-        .join(&sha[0..2])
-        .join(&sha[2..])
+pub fn get_object_path(objects_dir: &Path, sha: &str) -> PathBuf {
+    objects_dir.join(&sha[0..2]).join(&sha[2..])
+}
+
+/// Expands a leading `~/` in a path from a config file to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Returns the path of `name` in git's XDG config directory (`$XDG_CONFIG_HOME/git`, defaulting
+/// to `~/.config/git`).
+pub fn xdg_config_path(name: &str) -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("git").join(name)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/git").join(name)),
+    }
 }
@@ -0,0 +1,76 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+/// The largest payload a single pkt-line can carry.
+pub const MAX_PACKET_DATA: usize = 65516;
+
+/// Writes `data` as a single pkt-line.
+pub fn write_packet<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_PACKET_DATA {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "packet exceeds the maximum pkt-line size",
+        ));
+    }
+    write!(writer, "{:04x}", data.len() + 4)?;
+    writer.write_all(data)
+}
+
+/// Writes a text pkt-line, terminated by a newline.
+pub fn write_text<W: Write>(writer: &mut W, line: &str) -> io::Result<()> {
+    write_packet(writer, format!("{}\n", line).as_bytes())
+}
+
+/// Writes a flush packet (`0000`).
+pub fn write_flush<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"0000")?;
+    writer.flush()
+}
+
+/// Writes `data` split over as many pkt-lines as needed, followed by a flush packet.
+pub fn write_data<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(MAX_PACKET_DATA) {
+        write_packet(writer, chunk)?;
+    }
+    write_flush(writer)
+}
+
+/// Reads a single pkt-line. Returns [None] for a flush packet.
+pub fn read_packet<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let len = std::str::from_utf8(&header)
+        .ok()
+        .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "invalid pkt-line header"))?;
+    match len {
+        0 => Ok(None),
+        1..=3 => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "invalid pkt-line length",
+        )),
+        _ => {
+            let mut data = vec![0u8; len - 4];
+            reader.read_exact(&mut data)?;
+            Ok(Some(data))
+        }
+    }
+}
+
+/// Reads text pkt-lines up to the next flush packet, without their trailing newlines.
+pub fn read_text_list<R: Read>(reader: &mut R) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    while let Some(packet) = read_packet(reader)? {
+        let line = String::from_utf8_lossy(&packet);
+        lines.push(line.trim_end_matches('\n').to_string());
+    }
+    Ok(lines)
+}
+
+/// Reads binary pkt-lines up to the next flush packet and concatenates them.
+pub fn read_data<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let Some(packet) = read_packet(reader)? {
+        data.extend_from_slice(&packet);
+    }
+    Ok(data)
+}
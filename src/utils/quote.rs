@@ -0,0 +1,75 @@
+/// Parses a C-style quoted string at the start of `s` (which must begin with '"').
+///
+/// Returns the unquoted string and the number of bytes consumed, or [None] if the string is not
+/// properly terminated.
+pub fn unquote_c_style(s: &str) -> Option<(String, usize)> {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&b'"') {
+        return None;
+    }
+    let mut out = Vec::new();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => return Some((String::from_utf8_lossy(&out).into_owned(), i + 1)),
+            b'\\' => {
+                i += 1;
+                let ch = *bytes.get(i)?;
+                let unescaped = match ch {
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b'f' => 0x0c,
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'v' => 0x0b,
+                    b'\\' | b'"' => ch,
+                    b'0'..=b'3' => {
+                        let digits = bytes.get(i..i + 3)?;
+                        if !digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+                            return None;
+                        }
+                        i += 2;
+                        digits.iter().fold(0u8, |acc, d| (acc << 3) | (d - b'0'))
+                    }
+                    _ => return None,
+                };
+                out.push(unescaped);
+            }
+            ch => out.push(ch),
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Quotes `s` C-style like git does for paths with special characters.
+///
+/// Returns `s` unchanged if it does not need quoting.
+pub fn quote_c_style(s: &str) -> String {
+    let needs_quoting = s
+        .bytes()
+        .any(|ch| ch == b'"' || ch == b'\\' || !(0x20..0x7f).contains(&ch));
+    if !needs_quoting {
+        return s.to_string();
+    }
+
+    let mut out = String::from("\"");
+    for ch in s.bytes() {
+        match ch {
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            0x0c => out.push_str("\\f"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x0b => out.push_str("\\v"),
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            ch if !(0x20..0x7f).contains(&ch) => out.push_str(&format!("\\{:03o}", ch)),
+            ch => out.push(ch as char),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -87,7 +87,7 @@ fn test_read_file() -> Result<(), Box<dyn Error>> {
         .create_new(true)
         .write(true)
         .open(&path)?;
-    file.write(b"test")?;
+    file.write_all(b"test")?;
 
     // Should read 'test' from the new file
     let buf = read_file(&path)?;
@@ -98,3 +98,159 @@ fn test_read_file() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_wildmatch() {
+    // Basic wildcards
+    assert!(wildmatch("foo", "foo", 0));
+    assert!(!wildmatch("foo", "bar", 0));
+    assert!(wildmatch("f?o", "foo", 0));
+    assert!(wildmatch("*.o", "main.o", 0));
+    assert!(!wildmatch("*.o", "main.c", 0));
+
+    // Bracket expressions
+    assert!(wildmatch("[a-c]at", "bat", 0));
+    assert!(!wildmatch("[!a-c]at", "bat", 0));
+    assert!(wildmatch("[^a-c]at", "rat", 0));
+    assert!(wildmatch("[]]", "]", 0));
+    assert!(wildmatch("[[:digit:]]x", "7x", 0));
+    assert!(wildmatch("\\*", "*", 0));
+    assert!(!wildmatch("\\*", "a", 0));
+
+    // Slashes with WM_PATHNAME
+    assert!(wildmatch("*", "a/b", 0));
+    assert!(!wildmatch("*", "a/b", WM_PATHNAME));
+    assert!(!wildmatch("a?b", "a/b", WM_PATHNAME));
+    assert!(wildmatch("a/*/c", "a/b/c", WM_PATHNAME));
+    assert!(!wildmatch("a/*/c", "a/b/x/c", WM_PATHNAME));
+
+    // Double stars
+    assert!(wildmatch("**/foo", "foo", WM_PATHNAME));
+    assert!(wildmatch("**/foo", "a/b/foo", WM_PATHNAME));
+    assert!(wildmatch("a/**/b", "a/b", WM_PATHNAME));
+    assert!(wildmatch("a/**/b", "a/x/y/b", WM_PATHNAME));
+    assert!(wildmatch("a/**", "a/x/y", WM_PATHNAME));
+    assert!(!wildmatch("a/**", "a", WM_PATHNAME));
+    assert!(!wildmatch("a**b", "a/b", WM_PATHNAME));
+
+    // Case folding
+    assert!(!wildmatch("README", "readme", 0));
+    assert!(wildmatch("README", "readme", WM_CASEFOLD));
+    assert!(wildmatch("[A-Z]x", "bx", WM_CASEFOLD));
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("red bold").as_deref(), Some("\x1b[1;31m"));
+    assert_eq!(parse_color("reset").as_deref(), Some("\x1b[m"));
+    assert_eq!(parse_color("normal").as_deref(), Some(""));
+    assert_eq!(
+        parse_color("nobold brightred ul #102030").as_deref(),
+        Some("\x1b[4;22;91;48;2;16;32;48m")
+    );
+    assert_eq!(parse_color("normal blue").as_deref(), Some("\x1b[44m"));
+    assert_eq!(parse_color("12 200").as_deref(), Some("\x1b[94;48;5;200m"));
+    assert_eq!(parse_color("red blue green"), None);
+    assert_eq!(parse_color("nonsense"), None);
+}
+
+#[test]
+fn test_format_date() -> Result<(), Box<dyn Error>> {
+    let date = |format: &str| -> Result<String, Box<dyn Error>> {
+        Ok(format_date(1_500_000_000, -450, &format.parse()?))
+    };
+    assert_eq!(date("default")?, "Thu Jul 13 19:10:00 2017 -0730");
+    assert_eq!(date("iso")?, "2017-07-13 19:10:00 -0730");
+    assert_eq!(date("iso-strict")?, "2017-07-13T19:10:00-07:30");
+    assert_eq!(date("rfc")?, "Thu, 13 Jul 2017 19:10:00 -0730");
+    assert_eq!(date("short")?, "2017-07-13");
+    assert_eq!(date("raw")?, "1500000000 -0730");
+    assert_eq!(date("unix")?, "1500000000");
+    assert_eq!(
+        date("format:%A %B %d %e %j %I %p %y %H:%M:%S %z")?,
+        "Thursday July 13 13 194 07 PM 17 19:10:00 -0730"
+    );
+    assert_eq!(
+        format_date(0, 0, &DateFormat::Iso),
+        "1970-01-01 00:00:00 +0000"
+    );
+    assert!("bogus".parse::<DateFormat>().is_err());
+    assert_eq!(
+        parse_date("Thu, 13 Jul 2017 19:10:00 -0730"),
+        Some((1_500_000_000, -450))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_base85() {
+    assert_eq!(encode_base85(b"x\0y"), "cmR0-");
+    assert_eq!(decode_base85(b"cmR0-", 3), Some(b"x\0y".to_vec()));
+    let data: Vec<u8> = (0..=255).collect();
+    assert_eq!(
+        decode_base85(encode_base85(&data).as_bytes(), 256),
+        Some(data)
+    );
+    assert_eq!(decode_base85(b"cmR0", 3), None);
+    assert_eq!(decode_base85(b"cmR0\"", 3), None);
+}
+
+#[test]
+fn test_relative_date() {
+    let now = 1_000_000_000;
+    let ago = |seconds: i64| date::relative_date(now - seconds, now);
+    assert_eq!(ago(-5), "in the future");
+    assert_eq!(ago(1), "1 second ago");
+    assert_eq!(ago(89), "89 seconds ago");
+    assert_eq!(ago(90), "2 minutes ago");
+    assert_eq!(ago(3600), "60 minutes ago");
+    assert_eq!(ago(3 * 3600), "3 hours ago");
+    assert_eq!(ago(2 * 86400), "2 days ago");
+    assert_eq!(ago(20 * 86400), "3 weeks ago");
+    assert_eq!(ago(100 * 86400), "3 months ago");
+    assert_eq!(ago(400 * 86400), "1 year, 1 month ago");
+    assert_eq!(ago(2 * 365 * 86400), "2 years ago");
+    assert_eq!(ago(10 * 365 * 86400), "10 years ago");
+}
+
+#[test]
+fn test_approxidate() {
+    // Sun Oct 18 22:13:31 2026 UTC
+    let now = 1_792_361_611;
+    let date = |date: &str| approxidate(date, now);
+    assert_eq!(date("2023-01-01 10:00"), 1_672_567_200);
+    assert_eq!(date("2023-01-01T10:00:00+0200"), 1_672_560_000);
+    assert_eq!(date("2023-01-01 10:00 -0730"), 1_672_594_200);
+    assert_eq!(date("Thu Nov 16 2023 12:00:00 +0100"), 1_700_132_400);
+    assert_eq!(date("Jan 5 2020 10:00 PM"), 1_578_261_600);
+    assert_eq!(date("@1700000000"), 1_700_000_000);
+    assert_eq!(date("1700000000"), 1_700_000_000);
+    // Dates without a time keep the current time of day.
+    assert_eq!(date("2023-01-01"), 1_672_611_211);
+    assert_eq!(date("2023-02-30"), 1_677_795_211);
+    assert_eq!(date("01/05/2023"), 1_672_956_811);
+    assert_eq!(date("5.1.2023"), 1_672_956_811);
+    assert_eq!(date("25/12/2023"), 1_703_542_411);
+    assert_eq!(date("Jan 5 2020"), 1_578_262_411);
+    assert_eq!(date("dec 02"), 1_764_713_611);
+    assert_eq!(date("10:00"), 1_792_317_600);
+    assert_eq!(date("2023-01-01 10pm"), 1_672_610_400);
+    assert_eq!(date("10pm yesterday"), 1_792_274_400);
+    assert_eq!(date("noon"), 1_792_324_800);
+    assert_eq!(date("midnight"), 1_792_281_600);
+    assert_eq!(date("yesterday"), now - 86400);
+    assert_eq!(date("3.days"), now - 3 * 86400);
+    assert_eq!(date("2 weeks ago"), now - 14 * 86400);
+    assert_eq!(date("two weeks ago"), now - 14 * 86400);
+    assert_eq!(date("2 days 3 hours ago"), now - 2 * 86400 - 3 * 3600);
+    assert_eq!(date("1 month ago"), now - 30 * 86400);
+    assert_eq!(date("3 months ago"), now - 92 * 86400);
+    assert_eq!(date("1 year ago"), now - 365 * 86400);
+    assert_eq!(date("2 years 3 months ago"), now - 71_020_800);
+    assert_eq!(date("1.5 days ago"), now - 14_688_000);
+    assert_eq!(date("last friday"), now - 2 * 86400);
+    assert_eq!(date("last sunday"), now - 7 * 86400);
+    assert_eq!(date("2 sundays ago"), now - 14 * 86400);
+    assert_eq!(date("never"), 0);
+    assert_eq!(date("bogus"), now);
+}
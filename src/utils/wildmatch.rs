@@ -0,0 +1,236 @@
+/// Match case insensitively.
+pub const WM_CASEFOLD: u32 = 1;
+/// Wildcards do not match '/' unless written as `**` between slashes.
+pub const WM_PATHNAME: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Matched,
+    NoMatch,
+    AbortAll,
+    AbortToStarStar,
+}
+
+/// Returns true if `text` matches the shell glob `pattern` using git's wildmatch semantics.
+///
+/// Supports `?`, `*`, `**`, bracket expressions (including ranges, negation with `!` or `^` and
+/// `[:class:]` names) and backslash escapes.
+pub fn wildmatch(pattern: &str, text: &str, flags: u32) -> bool {
+    dowild(pattern.as_bytes(), text.as_bytes(), flags) == Outcome::Matched
+}
+
+fn fold(ch: u8, flags: u32) -> u8 {
+    if flags & WM_CASEFOLD != 0 {
+        ch.to_ascii_lowercase()
+    } else {
+        ch
+    }
+}
+
+fn dowild(p: &[u8], text: &[u8], flags: u32) -> Outcome {
+    let pathname = flags & WM_PATHNAME != 0;
+    let mut pi = 0;
+    let mut ti = 0;
+
+    while pi < p.len() {
+        let mut p_ch = p[pi];
+        if ti >= text.len() && p_ch != b'*' {
+            return Outcome::AbortAll;
+        }
+        let t_ch = text.get(ti).map_or(0, |&ch| fold(ch, flags));
+
+        match p_ch {
+            b'\\' => {
+                // Literal match with the following character.
+                pi += 1;
+                p_ch = p.get(pi).copied().unwrap_or(0);
+                if fold(p_ch, flags) != t_ch {
+                    return Outcome::NoMatch;
+                }
+            }
+            b'?' => {
+                if pathname && t_ch == b'/' {
+                    return Outcome::NoMatch;
+                }
+            }
+            b'*' => {
+                let star = pi;
+                pi += 1;
+                let match_slash = if p.get(pi) == Some(&b'*') {
+                    while p.get(pi) == Some(&b'*') {
+                        pi += 1;
+                    }
+                    if !pathname {
+                        true
+                    } else if (star == 0 || p[star - 1] == b'/')
+                        && (pi == p.len()
+                            || p[pi] == b'/'
+                            || (p[pi] == b'\\' && p.get(pi + 1) == Some(&b'/')))
+                    {
+                        // "**/" may also match zero directories.
+                        if p.get(pi) == Some(&b'/')
+                            && dowild(&p[pi + 1..], &text[ti..], flags) == Outcome::Matched
+                        {
+                            return Outcome::Matched;
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                } else {
+                    !pathname
+                };
+
+                if pi == p.len() {
+                    // A trailing star matches everything left, unless it would cross a slash.
+                    if !match_slash && text[ti..].contains(&b'/') {
+                        return Outcome::AbortToStarStar;
+                    }
+                    return Outcome::Matched;
+                } else if !match_slash && p[pi] == b'/' {
+                    // Skip to the next slash; both slashes are consumed below.
+                    match text[ti..].iter().position(|&ch| ch == b'/') {
+                        Some(offset) => ti += offset,
+                        None => return Outcome::AbortAll,
+                    }
+                } else {
+                    while ti < text.len() {
+                        let matched = dowild(&p[pi..], &text[ti..], flags);
+                        if matched != Outcome::NoMatch {
+                            if !match_slash || matched != Outcome::AbortToStarStar {
+                                return matched;
+                            }
+                        } else if !match_slash && text[ti] == b'/' {
+                            return Outcome::AbortToStarStar;
+                        }
+                        ti += 1;
+                    }
+                    return Outcome::AbortAll;
+                }
+            }
+            b'[' => {
+                pi += 1;
+                p_ch = p.get(pi).copied().unwrap_or(0);
+                if p_ch == b'^' {
+                    p_ch = b'!';
+                }
+                let negated = p_ch == b'!';
+                if negated {
+                    pi += 1;
+                    p_ch = p.get(pi).copied().unwrap_or(0);
+                }
+                let mut prev_ch = 0;
+                let mut matched = false;
+                loop {
+                    if p_ch == 0 {
+                        return Outcome::AbortAll;
+                    }
+                    if p_ch == b'\\' {
+                        pi += 1;
+                        p_ch = p.get(pi).copied().unwrap_or(0);
+                        if p_ch == 0 {
+                            return Outcome::AbortAll;
+                        }
+                        if t_ch == fold(p_ch, flags) {
+                            matched = true;
+                        }
+                    } else if p_ch == b'-'
+                        && prev_ch != 0
+                        && p.get(pi + 1).is_some_and(|&ch| ch != b']')
+                    {
+                        pi += 1;
+                        p_ch = p[pi];
+                        if p_ch == b'\\' {
+                            pi += 1;
+                            p_ch = p.get(pi).copied().unwrap_or(0);
+                            if p_ch == 0 {
+                                return Outcome::AbortAll;
+                            }
+                        }
+                        let raw = text[ti];
+                        if (prev_ch..=p_ch).contains(&raw)
+                            || (flags & WM_CASEFOLD != 0
+                                && ((prev_ch..=p_ch).contains(&raw.to_ascii_lowercase())
+                                    || (prev_ch..=p_ch).contains(&raw.to_ascii_uppercase())))
+                        {
+                            matched = true;
+                        }
+                        p_ch = 0;
+                    } else if p_ch == b'[' && p.get(pi + 1) == Some(&b':') {
+                        let start = pi + 2;
+                        let end = p[start..]
+                            .windows(2)
+                            .position(|w| w == b":]")
+                            .map(|offset| start + offset);
+                        match end {
+                            Some(end) => {
+                                let class = &p[start..end];
+                                match char_class(class, text[ti], flags) {
+                                    Some(true) => matched = true,
+                                    Some(false) => {}
+                                    None => return Outcome::AbortAll,
+                                }
+                                pi = end + 1;
+                                p_ch = 0;
+                            }
+                            None => {
+                                // Not a class; treat '[' literally.
+                                if t_ch == b'[' {
+                                    matched = true;
+                                }
+                            }
+                        }
+                    } else if t_ch == fold(p_ch, flags) {
+                        matched = true;
+                    }
+                    prev_ch = p_ch;
+                    pi += 1;
+                    p_ch = p.get(pi).copied().unwrap_or(0);
+                    if p_ch == b']' {
+                        break;
+                    }
+                }
+                if matched == negated || (pathname && t_ch == b'/') {
+                    return Outcome::NoMatch;
+                }
+            }
+            _ => {
+                if t_ch != fold(p_ch, flags) {
+                    return Outcome::NoMatch;
+                }
+            }
+        }
+        pi += 1;
+        ti += 1;
+    }
+
+    if ti < text.len() {
+        Outcome::NoMatch
+    } else {
+        Outcome::Matched
+    }
+}
+
+/// Returns whether `ch` belongs to the named character class, or [None] for an unknown class.
+fn char_class(class: &[u8], ch: u8, flags: u32) -> Option<bool> {
+    let matched = match class {
+        b"alnum" => ch.is_ascii_alphanumeric(),
+        b"alpha" => ch.is_ascii_alphabetic(),
+        b"blank" => ch == b' ' || ch == b'\t',
+        b"cntrl" => ch.is_ascii_control(),
+        b"digit" => ch.is_ascii_digit(),
+        b"graph" => ch.is_ascii_graphic(),
+        b"lower" => {
+            ch.is_ascii_lowercase() || (flags & WM_CASEFOLD != 0 && ch.is_ascii_uppercase())
+        }
+        b"print" => ch.is_ascii_graphic() || ch == b' ',
+        b"punct" => ch.is_ascii_punctuation(),
+        b"space" => ch.is_ascii_whitespace() || ch == 0x0b,
+        b"upper" => {
+            ch.is_ascii_uppercase() || (flags & WM_CASEFOLD != 0 && ch.is_ascii_lowercase())
+        }
+        b"xdigit" => ch.is_ascii_hexdigit(),
+        _ => return None,
+    };
+    Some(matched)
+}